@@ -49,11 +49,15 @@ where
     } = stack.build();
 
     info!("Starting advertising and GATT service");
-    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
-        name: "TrouBLE",
-        appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
-    }))
-        .unwrap();
+    let mut storage = ServerStorage::new();
+    let server = Server::new_with_config(
+        GapConfig::Peripheral(PeripheralConfig {
+            name: "TrouBLE",
+            appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
+        }),
+        &mut storage,
+    )
+    .unwrap();
 
     let _ = join(ble_task(runner), async {
         loop {
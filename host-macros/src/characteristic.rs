@@ -65,6 +65,29 @@ pub struct DescriptorArgs {
     pub access: AccessArgs,
 }
 
+/// Characteristic Presentation Format descriptor arguments.
+#[derive(Debug)]
+pub struct PresentationFormatArgs {
+    pub format: syn::Expr,
+    pub exponent: Option<syn::Expr>,
+    pub unit: Option<syn::Expr>,
+    pub namespace: Option<syn::Expr>,
+    pub description: Option<syn::Expr>,
+}
+
+/// Standard descriptors with typed handles, each declared by its own tag on the characteristic field.
+#[derive(Debug, Default)]
+pub struct StandardDescriptors {
+    /// Text of the Characteristic User Description descriptor.
+    pub user_description: Option<syn::Expr>,
+    /// Characteristic Presentation Format descriptors, in declaration order.
+    pub presentation_formats: Vec<PresentationFormatArgs>,
+    /// If true, an Aggregate Format descriptor lists the Presentation Format descriptors.
+    pub aggregate_format: bool,
+    /// Inclusive lower and upper bounds of the Valid Range descriptor.
+    pub valid_range: Option<(syn::Expr, syn::Expr)>,
+}
+
 /// Characteristic attribute arguments
 #[derive(Debug)]
 pub struct CharacteristicArgs {
@@ -78,7 +101,19 @@ pub struct CharacteristicArgs {
     pub descriptors: Vec<DescriptorArgs>,
     /// Any '///' comments on each field, parsed in super::check_for_characteristic.
     pub doc_string: String,
+    /// Standard descriptors, parsed in super::check_for_characteristic.
+    pub standard_descriptors: StandardDescriptors,
     pub access: AccessArgs,
+    /// Handler producing the value before it is read, `fn(&Self) -> T`.
+    pub on_read: Option<syn::Expr>,
+    /// Handler called with the decoded value when it is written, `fn(&Self, &T)`.
+    pub on_write: Option<syn::Expr>,
+    /// If true, the Extended Properties descriptor declares support for the Reliable Write procedure.
+    pub reliable_write: bool,
+    /// If true, the Extended Properties descriptor declares the Characteristic User Description as writable.
+    pub writable_auxiliaries: bool,
+    /// Fixed handle of the characteristic declaration.
+    pub handle: Option<syn::Expr>,
 }
 
 /// Check if this bool type has been specified more than once.
@@ -125,6 +160,11 @@ impl CharacteristicArgs {
         let mut indicate: Option<bool> = None;
         let mut default_value: Option<syn::Expr> = None;
         let mut write_without_response: Option<bool> = None;
+        let mut on_read: Option<syn::Expr> = None;
+        let mut on_write: Option<syn::Expr> = None;
+        let mut reliable_write: Option<bool> = None;
+        let mut writable_auxiliaries: Option<bool> = None;
+        let mut handle: Option<syn::Expr> = None;
         attribute.parse_nested_meta(|meta| {
             match meta.path.get_ident().ok_or(meta.error("no ident"))?.to_string().as_str() {
                 "uuid" => check_multi(&mut uuid, "uuid", &meta, parse_uuid(&meta)?)?,
@@ -133,27 +173,62 @@ impl CharacteristicArgs {
                 "notify" => check_multi(&mut notify, "notify", &meta, true)?,
                 "indicate" => check_multi(&mut indicate, "indicate", &meta, true)?,
                 "write_without_response" => check_multi(&mut write_without_response, "write_without_response", &meta, true)?,
+                "reliable_write" => check_multi(&mut reliable_write, "reliable_write", &meta, true)?,
+                "writable_auxiliaries" => check_multi(&mut writable_auxiliaries, "writable_auxiliaries", &meta, true)?,
                 "value" => {
                     let value = meta
                         .value()
                         .map_err(|_| meta.error("'value' must be followed by '= [data]'.  i.e. value = \"42\""))?;
                     check_multi(&mut default_value, "value", &meta, value.parse()?)?
                 }
+                "on_read" => {
+                    let value = meta.value().map_err(|_| {
+                        meta.error("'on_read' must be followed by '= [handler]'.  i.e. on_read = Self::current_level")
+                    })?;
+                    check_multi(&mut on_read, "on_read", &meta, value.parse()?)?
+                }
+                "on_write" => {
+                    let value = meta.value().map_err(|_| {
+                        meta.error("'on_write' must be followed by '= [handler]'.  i.e. on_write = Self::level_written")
+                    })?;
+                    check_multi(&mut on_write, "on_write", &meta, value.parse()?)?
+                }
+                "handle" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'handle' must be followed by '= [handle]'.  i.e. handle = 0x0110"))?;
+                    check_multi(&mut handle, "handle", &meta, value.parse()?)?
+                }
                 "default_value" => return Err(meta.error("Use 'value' for default value")),
                 "descriptor" => return Err(meta.error("Descriptors are added as separate tags i.e. #[descriptor(uuid = \"1234\", value = 42, read, write, notify, indicate)]")),
                 other => return Err(
                     meta.error(
                         format!(
-                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, notify, indicate, value\n"
+                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, notify, indicate, reliable_write, writable_auxiliaries, value, on_read, on_write, handle\n"
                         ))),
             };
             Ok(())
         })?;
+        if on_read.is_some() && read != Some(true) {
+            return Err(Error::custom("'on_read' requires the 'read' property").into());
+        }
+        if on_write.is_some() && write != Some(true) && write_without_response != Some(true) {
+            return Err(Error::custom("'on_write' requires the 'write' or 'write_without_response' property").into());
+        }
+        if reliable_write.is_some() && write != Some(true) {
+            return Err(Error::custom("'reliable_write' requires the 'write' property").into());
+        }
         Ok(Self {
             uuid: uuid.ok_or(Error::custom("Characteristic must have a UUID"))?,
             doc_string: String::new(),
             descriptors: Vec::new(),
+            standard_descriptors: StandardDescriptors::default(),
             default_value,
+            on_read,
+            on_write,
+            reliable_write: reliable_write.unwrap_or_default(),
+            writable_auxiliaries: writable_auxiliaries.unwrap_or_default(),
+            handle,
             access: AccessArgs {
                 write_without_response: write_without_response.unwrap_or_default(),
                 indicate: indicate.unwrap_or_default(),
@@ -227,3 +302,110 @@ impl DescriptorArgs {
         })
     }
 }
+
+impl PresentationFormatArgs {
+    /// Parse `#[presentation_format(format = .., exponent = .., unit = .., namespace = .., description = ..)]`.
+    pub fn parse(attribute: &syn::Attribute) -> Result<Self> {
+        let mut format: Option<syn::Expr> = None;
+        let mut exponent: Option<syn::Expr> = None;
+        let mut unit: Option<syn::Expr> = None;
+        let mut namespace: Option<syn::Expr> = None;
+        let mut description: Option<syn::Expr> = None;
+        attribute.parse_nested_meta(|meta| {
+            let name = meta.path.get_ident().ok_or(meta.error("no ident"))?.to_string();
+            let arg = match name.as_str() {
+                "format" => &mut format,
+                "exponent" => &mut exponent,
+                "unit" => &mut unit,
+                "namespace" => &mut namespace,
+                "description" => &mut description,
+                other => {
+                    return Err(meta.error(format!(
+                        "Unsupported presentation format property: '{other}'.\nSupported properties are: format, exponent, unit, namespace, description"
+                    )));
+                }
+            };
+            let value = meta
+                .value()
+                .map_err(|_| meta.error(format!("'{name}' must be followed by '= [value]'. i.e. {name} = 0")))?;
+            check_multi(arg, &name, &meta, value.parse()?)
+        })?;
+        Ok(Self {
+            format: format.ok_or(Error::custom("Presentation format must have a format"))?,
+            exponent,
+            unit,
+            namespace,
+            description,
+        })
+    }
+}
+
+impl StandardDescriptors {
+    /// Parse a standard descriptor tag into these descriptors.
+    ///
+    /// Returns `Ok(false)` if the attribute is not a standard descriptor tag.
+    pub fn parse(&mut self, attribute: &syn::Attribute) -> Result<bool> {
+        let Some(ident) = attribute.path().get_ident() else {
+            return Ok(false);
+        };
+        match ident.to_string().as_str() {
+            "user_description" => {
+                if self.user_description.is_some() {
+                    return Err(syn::Error::new(
+                        attribute.span(),
+                        "'user_description' should not be specified more than once",
+                    ));
+                }
+                self.user_description = Some(attribute.parse_args().map_err(|_| {
+                    syn::Error::new(
+                        attribute.span(),
+                        "user_description takes the text of the description. i.e. #[user_description(\"Battery Level\")]",
+                    )
+                })?);
+            }
+            "presentation_format" => self
+                .presentation_formats
+                .push(PresentationFormatArgs::parse(attribute)?),
+            "aggregate_format" => {
+                attribute.meta.require_path_only()?;
+                if self.aggregate_format {
+                    return Err(syn::Error::new(
+                        attribute.span(),
+                        "'aggregate_format' should not be specified more than once",
+                    ));
+                }
+                self.aggregate_format = true;
+            }
+            "valid_range" => {
+                let mut lower: Option<syn::Expr> = None;
+                let mut upper: Option<syn::Expr> = None;
+                attribute.parse_nested_meta(|meta| {
+                    let name = meta.path.get_ident().ok_or(meta.error("no ident"))?.to_string();
+                    let arg = match name.as_str() {
+                        "lower" => &mut lower,
+                        "upper" => &mut upper,
+                        other => {
+                            return Err(meta.error(format!(
+                                "Unsupported valid range property: '{other}'.\nSupported properties are: lower, upper"
+                            )));
+                        }
+                    };
+                    let value = meta.value().map_err(|_| {
+                        meta.error(format!("'{name}' must be followed by '= [value]'. i.e. {name} = 0"))
+                    })?;
+                    check_multi(arg, &name, &meta, value.parse()?)
+                })?;
+                if self.valid_range.is_some() {
+                    return Err(syn::Error::new(
+                        attribute.span(),
+                        "'valid_range' should not be specified more than once",
+                    ));
+                }
+                let missing = || syn::Error::new(attribute.span(), "valid_range requires both 'lower' and 'upper'");
+                self.valid_range = Some((lower.ok_or_else(missing)?, upper.ok_or_else(missing)?));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
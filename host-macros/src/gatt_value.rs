@@ -0,0 +1,142 @@
+//! FixedGattValue derive.
+//!
+//! Generates the conversions between a user type and its GATT bytes, so the type can be used as a characteristic value.
+//! Enums must be fieldless with an integer `repr`, and structs must be `repr(C, packed)` with fields that are
+//! themselves fixed size GATT values. In both cases the in-memory layout of the type is its GATT encoding.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Error, Fields, Ident, Result};
+
+const INTEGER_REPRS: &[&str] = &["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"];
+
+/// Build the `FixedGattValue` implementation for the derive input.
+pub fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "FixedGattValue can not be derived for generic types",
+        ));
+    }
+    let reprs = parse_repr(&input)?;
+    match &input.data {
+        Data::Enum(data) => derive_enum(&input.ident, &reprs, data),
+        Data::Struct(data) => derive_struct(&input.ident, &reprs, data),
+        Data::Union(_) => Err(Error::new(
+            input.ident.span(),
+            "FixedGattValue can only be derived for enums and structs",
+        )),
+    }
+}
+
+/// Collect the idents listed in any `#[repr(..)]` attributes.
+fn parse_repr(input: &DeriveInput) -> Result<Vec<Ident>> {
+    let mut reprs = Vec::new();
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            if let Some(ident) = meta.path.get_ident() {
+                reprs.push(ident.clone());
+            }
+            Ok(())
+        })?;
+    }
+    Ok(reprs)
+}
+
+fn derive_enum(ident: &Ident, reprs: &[Ident], data: &syn::DataEnum) -> Result<TokenStream2> {
+    let Some(repr) = reprs
+        .iter()
+        .find(|repr| INTEGER_REPRS.contains(&repr.to_string().as_str()))
+    else {
+        return Err(Error::new(
+            ident.span(),
+            "FixedGattValue enums must have an integer representation. i.e. #[repr(u8)]",
+        ));
+    };
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|variant| !matches!(variant.fields, Fields::Unit))
+    {
+        return Err(Error::new(
+            variant.span(),
+            "FixedGattValue enums can only have unit variants",
+        ));
+    }
+    let variants: Vec<&Ident> = data.variants.iter().map(|variant| &variant.ident).collect();
+
+    Ok(quote! {
+        impl trouble_host::types::gatt_traits::FixedGattValue for #ident {
+            const SIZE: usize = core::mem::size_of::<#repr>();
+
+            fn from_gatt(data: &[u8]) -> Result<Self, trouble_host::types::gatt_traits::FromGattError> {
+                let value = <#repr as trouble_host::types::gatt_traits::FixedGattValue>::from_gatt(data)?;
+                #(
+                    if value == Self::#variants as #repr {
+                        return Ok(Self::#variants);
+                    }
+                )*
+                Err(trouble_host::types::gatt_traits::FromGattError::InvalidValue)
+            }
+
+            fn as_gatt(&self) -> &[u8] {
+                // SAFETY: a fieldless enum with an integer representation is stored as its discriminant.
+                unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+            }
+        }
+    })
+}
+
+fn derive_struct(ident: &Ident, reprs: &[Ident], data: &syn::DataStruct) -> Result<TokenStream2> {
+    if !(reprs.iter().any(|repr| repr == "C") && reprs.iter().any(|repr| repr == "packed")) {
+        return Err(Error::new(
+            ident.span(),
+            "FixedGattValue structs must be #[repr(C, packed)]",
+        ));
+    }
+    let types: Vec<&syn::Type> = data.fields.iter().map(|field| &field.ty).collect();
+    let bindings: Vec<Ident> = (0..types.len()).map(|index| format_ident!("field_{index}")).collect();
+    let construct = match &data.fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!(Self { #(#names: #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(Self(#(#bindings),*)),
+        Fields::Unit => quote!(Self),
+    };
+
+    Ok(quote! {
+        impl trouble_host::types::gatt_traits::FixedGattValue for #ident {
+            const SIZE: usize = 0 #(+ <#types as trouble_host::types::gatt_traits::FixedGattValue>::SIZE)*;
+
+            fn from_gatt(data: &[u8]) -> Result<Self, trouble_host::types::gatt_traits::FromGattError> {
+                if data.len() != Self::SIZE {
+                    return Err(trouble_host::types::gatt_traits::FromGattError::InvalidLength);
+                }
+                #[allow(unused_mut)]
+                let mut offset = 0;
+                #(
+                    let size = <#types as trouble_host::types::gatt_traits::FixedGattValue>::SIZE;
+                    let #bindings = <#types as trouble_host::types::gatt_traits::FixedGattValue>::from_gatt(
+                        &data[offset..offset + size],
+                    )?;
+                    offset += size;
+                )*
+                let _ = offset;
+                Ok(#construct)
+            }
+
+            fn as_gatt(&self) -> &[u8] {
+                const {
+                    assert!(
+                        core::mem::size_of::<#ident>() == <#ident as trouble_host::types::gatt_traits::FixedGattValue>::SIZE,
+                        "the fields of a FixedGattValue struct must be stored as their GATT bytes"
+                    )
+                };
+                // SAFETY: the struct is packed, so its bytes are the bytes of its fields in order without padding.
+                unsafe { core::slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+            }
+        }
+    })
+}
@@ -7,11 +7,12 @@ extern crate proc_macro;
 
 mod characteristic;
 mod ctxt;
+mod gatt_value;
 mod server;
 mod service;
 mod uuid;
 
-use characteristic::{Characteristic, CharacteristicArgs, DescriptorArgs};
+use characteristic::{Characteristic, CharacteristicArgs, DescriptorArgs, StandardDescriptors};
 use ctxt::Ctxt;
 use proc_macro::TokenStream;
 use server::{ServerArgs, ServerBuilder};
@@ -34,6 +35,31 @@ use syn::{parse_macro_input, Error};
 /// }
 ///
 /// ```
+///
+/// The values of the characteristics and descriptors are kept in a generated `MyGattServerStorage`, which the
+/// server borrows, so that the same server can be built more than once, e.g. for several stacks:
+///
+/// ```rust no_run
+/// let mut storage = MyGattServerStorage::new();
+/// let server = MyGattServer::new_default("TrouBLE", &mut storage).unwrap();
+/// ```
+///
+/// A service can include services declared before it with `#[include(..)]`, which adds an include definition
+/// for each of them to the service:
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+///
+/// #[gatt_server]
+/// struct HidServer {
+///     bas: BatteryService,
+///     #[include(bas)]
+///     hid: HidService,
+/// }
+/// ```
+///
+/// `#[gatt_server(service_changed)]` adds a Service Changed characteristic to the GATT service, so that clients
+/// are told about services added or removed while the server is running.
 #[proc_macro_attribute]
 pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
     let server_args = {
@@ -74,12 +100,57 @@ pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
 ///    #[characteristic(uuid = "2a28", read, write, notify, value = 42.0)]
 ///    /// Can be in any order
 ///    location: f32,
-///    #[characteristic(uuid = "2a39", write)]
+///    /// Handlers run when the server's `dispatch` method is called with a matching event
+///    #[characteristic(uuid = "2a39", write, on_write = Self::control_written)]
 ///    control: u8,
-///    #[characteristic(uuid = "2a63", read, notify)]
+///    #[characteristic(uuid = "2a63", read, notify, on_read = |_| 42)]
 ///    energy_expended: u16,
 /// }
+///
+/// impl HeartRateService {
+///     fn control_written(&self, value: &u8) {
+///         // React to the new control value
+///     }
+/// }
 /// ```
+///
+/// `on_read` handlers have the signature `fn(&Self) -> T` and update the stored value before it is read.
+/// `on_write` handlers have the signature `fn(&Self, &T)` and receive the decoded value that was written.
+///
+/// `reliable_write` and `writable_auxiliaries` add a Characteristic Extended Properties descriptor declaring
+/// those capabilities, and set the extended properties bit in the characteristic declaration.
+///
+/// `#[gatt_service(uuid = .., secondary)]` declares a secondary service, which clients only find through the
+/// services including it (see [macro@gatt_server]).
+///
+/// `handle = ..` on the service or on a characteristic fixes the handle of its declaration, so that it does not
+/// change when other services or characteristics are added before it. Handles must increase in declaration order.
+///
+/// Standard descriptors have their own tags, and each adds a typed descriptor field named after the characteristic:
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+///
+/// #[gatt_service(uuid = "181a")]
+/// struct EnvironmentService {
+///    /// Adds `temperature_user_description`, `temperature_presentation_format` and `temperature_valid_range`
+///    #[characteristic(uuid = "2a6e", read, notify, value = 2150)]
+///    #[user_description("Room temperature")]
+///    #[presentation_format(format = PresentationFormat::FORMAT_SINT16, exponent = -2, unit = 0x272f)]
+///    #[valid_range(lower = -4000, upper = 8500)]
+///    temperature: i16,
+///    /// Several presentation formats are numbered, i.e. `position_presentation_format_0`,
+///    /// and `aggregate_format` adds `position_aggregate_format` listing them
+///    #[characteristic(uuid = "2aae", read, value = [0; 8])]
+///    #[presentation_format(format = PresentationFormat::FORMAT_SINT32, exponent = -7, unit = 0x2763)]
+///    #[presentation_format(format = PresentationFormat::FORMAT_SINT32, exponent = -7, unit = 0x2763)]
+///    #[aggregate_format]
+///    position: [u8; 8],
+/// }
+/// ```
+///
+/// `presentation_format` defaults to an exponent of 0, the unitless unit, the Bluetooth SIG namespace and a
+/// description of 0. `valid_range` bounds have the characteristic's type.
 #[proc_macro_attribute]
 pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
     // Get arguments from the gatt_service macro attribute
@@ -128,6 +199,52 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+/// Derive `FixedGattValue` for a fieldless enum or a packed struct.
+///
+/// Enums must have an integer representation, and are encoded as their discriminant. Decoding bytes which don't
+/// match any variant fails with `FromGattError::InvalidValue`.
+///
+/// Structs must be `#[repr(C, packed)]` and are encoded as their fields in order, so each field must itself
+/// implement `FixedGattValue`.
+///
+/// # Example
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+///
+/// #[derive(FixedGattValue, Clone, Copy, Default)]
+/// #[repr(u8)]
+/// enum Mode {
+///     #[default]
+///     Off = 0,
+///     On = 1,
+///     Auto = 2,
+/// }
+///
+/// #[derive(FixedGattValue, Clone, Copy, Default)]
+/// #[repr(C, packed)]
+/// struct Setpoint {
+///     mode: Mode,
+///     temperature: i16,
+/// }
+///
+/// #[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b0")]
+/// struct ThermostatService {
+///     #[characteristic(uuid = "2a3a", read, write)]
+///     mode: Mode,
+///     #[characteristic(uuid = "2a3b", read, write)]
+///     setpoint: Setpoint,
+/// }
+/// ```
+#[proc_macro_derive(FixedGattValue)]
+pub fn derive_fixed_gatt_value(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    match gatt_value::derive(input) {
+        Ok(result) => result.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
 /// Check if a field has a characteristic attribute and parse it.
 ///
 /// If so also check if that field has descriptors and/or docstrings.
@@ -163,6 +280,7 @@ fn check_for_characteristic(
         return RETAIN; // If the field does not have a characteristic attribute, retain it.
     };
     let mut descriptors = Vec::new();
+    let mut standard_descriptors = StandardDescriptors::default();
     let mut doc_string = String::new();
     let mut characteristic_checked = false;
     for attr in &field.attrs {
@@ -190,6 +308,12 @@ fn check_for_characteristic(
                         return REMOVE; // If there was an error parsing the descriptor, remove the field.
                     }
                 },
+                "user_description" | "presentation_format" | "aggregate_format" | "valid_range" => {
+                    if let Err(e) = standard_descriptors.parse(attr) {
+                        *err = Some(e);
+                        return REMOVE; // If there was an error parsing the descriptor, remove the field.
+                    }
+                }
                 "characteristic" => {
                     // make sure we only have one characteristic meta tag
                     if characteristic_checked {
@@ -212,7 +336,7 @@ fn check_for_characteristic(
                 _ => {
                     *err = Some(Error::new(
                         attr.path().span(),
-                        "only doc (///), descriptor and characteristic tags are supported.",
+                        "only doc (///), descriptor, user_description, presentation_format, aggregate_format, valid_range and characteristic tags are supported.",
                     ));
                     return REMOVE; // If there was an error parsing the descriptor, remove the field.
                 }
@@ -226,8 +350,16 @@ fn check_for_characteristic(
             return REMOVE; // If there was an error parsing the characteristic, remove the field.
         }
     };
+    if standard_descriptors.aggregate_format && standard_descriptors.presentation_formats.is_empty() {
+        *err = Some(Error::new(
+            field.span(),
+            "'aggregate_format' requires at least one presentation_format tag",
+        ));
+        return REMOVE;
+    }
     args.doc_string = doc_string;
     args.descriptors = descriptors;
+    args.standard_descriptors = standard_descriptors;
     characteristics.push(Characteristic::new(field, args));
     REMOVE // Successfully parsed, remove the field from the fields vec.
 }
@@ -5,7 +5,7 @@
 
 use darling::Error;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 use syn::meta::ParseNestedMeta;
 use syn::spanned::Spanned;
 use syn::{parse_quote, Expr, Result};
@@ -17,6 +17,7 @@ pub(crate) struct ServerArgs {
     attribute_table_size: Option<Expr>,
     cccd_table_size: Option<Expr>,
     connections_max: Option<Expr>,
+    service_changed: bool,
 }
 
 impl ServerArgs {
@@ -65,8 +66,11 @@ impl ServerArgs {
                 })?;
                 self.connections_max = Some(buffer.parse()?);
             }
+            "service_changed" => {
+                self.service_changed = true;
+            }
             other => return Err(meta.error(format!(
-                "Unsupported server property: '{other}'.\nSupported properties are: mutex_type, packet_type, attribute_table_size, cccd_table_size, connections_max"
+                "Unsupported server property: '{other}'.\nSupported properties are: mutex_type, packet_type, attribute_table_size, cccd_table_size, connections_max, service_changed"
             ))),
         }
         Ok(())
@@ -102,33 +106,74 @@ impl ServerBuilder {
         let mut code_server_populate = TokenStream2::new();
         let mut code_attribute_summation = TokenStream2::new();
         let mut code_cccd_summation = TokenStream2::new();
+        let mut code_dispatch = TokenStream2::new();
+        let mut code_storage_fields = TokenStream2::new();
+        let mut code_storage_init = TokenStream2::new();
+        let mut declared: Vec<&syn::Ident> = Vec::new();
         for service in &self.properties.fields {
             let vis = &service.vis;
             let service_span = service.span();
             let service_name = service.ident.as_ref().expect("All fields should have names");
             let service_type = &service.ty;
+            let includes = match parse_includes(service, &declared) {
+                Ok(includes) => includes,
+                Err(e) => return e.to_compile_error(),
+            };
+            let include_count = includes.len();
+            declared.push(service_name);
+            let storage_type = match service_storage_type(service_type) {
+                Ok(storage_type) => storage_type,
+                Err(e) => return e.to_compile_error(),
+            };
+
+            code_storage_fields.extend(quote_spanned! {service_span=>
+                #service_name: #storage_type,
+            });
+            code_storage_init.extend(quote_spanned! {service_span=>
+                #service_name: <#storage_type>::new(),
+            });
 
             code_service_definition.extend(quote_spanned! {service_span=>
                 #vis #service_name: #service_type,
             });
 
-            code_service_init.extend(quote_spanned! {service_span=>
-                let #service_name = #service_type::new(&mut table);
-            });
+            if includes.is_empty() {
+                code_service_init.extend(quote_spanned! {service_span=>
+                    let #service_name = #service_type::new(&mut table, &mut storage.#service_name);
+                });
+            } else {
+                code_service_init.extend(quote_spanned! {service_span=>
+                    let #service_name = #service_type::new_including(&mut table, &mut storage.#service_name, &[#(#includes.handle()),*]);
+                });
+            }
 
             code_server_populate.extend(quote_spanned! {service_span=>
                 #service_name,
             });
 
             code_attribute_summation.extend(quote_spanned! {service_span=>
-               + #service_type::ATTRIBUTE_COUNT
+               + #service_type::ATTRIBUTE_COUNT + #include_count
             });
 
             code_cccd_summation.extend(quote_spanned! {service_span=>
                + #service_type::CCCD_COUNT
-            })
+            });
+
+            code_dispatch.extend(quote_spanned! {service_span=>
+                if self.#service_name.dispatch(&self.server, event)? {
+                    return Ok(true);
+                }
+            });
         }
 
+        let gap_build = if self.arguments.service_changed {
+            code_attribute_summation.extend(quote!(+ trouble_host::gap::SERVICE_CHANGED_ATTRIBUTE_COUNT));
+            code_cccd_summation.extend(quote!(+ trouble_host::gap::SERVICE_CHANGED_CCCD_COUNT));
+            quote!(build_with_service_changed)
+        } else {
+            quote!(build)
+        };
+
         let attribute_table_size = if let Some(value) = self.arguments.attribute_table_size {
             value
         } else {
@@ -147,6 +192,9 @@ impl ServerBuilder {
             parse_quote!(1)
         };
 
+        let storage_name = format_ident!("{}Storage", name);
+        let storage_doc = format!("Attribute value storage for the services of a [`{name}`].");
+
         quote! {
             const _ATTRIBUTE_TABLE_SIZE: usize = #attribute_table_size;
             // This pattern causes the assertion to happen at compile time
@@ -162,12 +210,34 @@ impl ServerBuilder {
                 #code_service_definition
             }
 
+            #[doc = #storage_doc]
+            #visibility struct #storage_name {
+                #code_storage_fields
+            }
+
+            impl #storage_name {
+                /// Create zeroed storage.
+                #visibility const fn new() -> Self {
+                    Self {
+                        #code_storage_init
+                    }
+                }
+            }
+
+            impl Default for #storage_name {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
             impl<'values> #name<'values>
             {
                 /// Create a new Gatt Server instance.
                 ///
                 /// Requires you to add your own GAP Service.  Use `new_default(name)` or `new_with_config(name, gap_config)` if you want to add a GAP Service.
-                #visibility fn new(mut table: trouble_host::attribute::AttributeTable<'values, #mutex_type, _ATTRIBUTE_TABLE_SIZE>) -> Self {
+                ///
+                /// The attribute values are kept in `storage`, so each instance of the server needs its own.
+                #visibility fn new(mut table: trouble_host::attribute::AttributeTable<'values, #mutex_type, _ATTRIBUTE_TABLE_SIZE>, storage: &'values mut #storage_name) -> Self {
 
                     #code_service_init
 
@@ -181,10 +251,12 @@ impl ServerBuilder {
                 /// This function will add a Generic GAP Service with the given name.
                 /// The maximum length which the name can be is 22 bytes (limited by the size of the advertising packet).
                 /// If a name longer than this is passed, Err() is returned.
-                #visibility fn new_default(name: &'values str) -> Result<Self, &'static str> {
+                ///
+                /// The attribute values are kept in `storage`, so each instance of the server needs its own.
+                #visibility fn new_default(name: &'values str, storage: &'values mut #storage_name) -> Result<Self, &'static str> {
                     let mut table: trouble_host::attribute::AttributeTable<'_, #mutex_type, _ATTRIBUTE_TABLE_SIZE> = trouble_host::attribute::AttributeTable::new();
 
-                    trouble_host::gap::GapConfig::default(name).build(&mut table)?;
+                    trouble_host::gap::GapConfig::default(name).#gap_build(&mut table)?;
 
                     #code_service_init
 
@@ -199,10 +271,12 @@ impl ServerBuilder {
                 /// This function will add a GAP Service.
                 /// The maximum length which the device name can be is 22 bytes (limited by the size of the advertising packet).
                 /// If a name longer than this is passed, Err() is returned.
-                #visibility fn new_with_config(gap: trouble_host::gap::GapConfig<'values>) -> Result<Self, &'static str> {
+                ///
+                /// The attribute values are kept in `storage`, so each instance of the server needs its own.
+                #visibility fn new_with_config(gap: trouble_host::gap::GapConfig<'values>, storage: &'values mut #storage_name) -> Result<Self, &'static str> {
                     let mut table: trouble_host::attribute::AttributeTable<'_, #mutex_type, _ATTRIBUTE_TABLE_SIZE> = trouble_host::attribute::AttributeTable::new();
 
-                    gap.build(&mut table)?;
+                    gap.#gap_build(&mut table)?;
 
                     #code_service_init
 
@@ -224,6 +298,14 @@ impl ServerBuilder {
                     self.server.get_cccd_table(connection)
                 }
 
+                /// Run the `on_read` / `on_write` handlers registered for the characteristic targeted by this event.
+                ///
+                /// Returns `Ok(true)` if a handler was run. The event still has to be accepted or rejected afterwards.
+                #visibility fn dispatch(&self, event: &trouble_host::gatt::GattEvent<'_, '_, #packet_type>) -> Result<bool, trouble_host::Error> {
+                    #code_dispatch
+                    Ok(false)
+                }
+
                 #visibility fn set_cccd_table(&self, connection: &trouble_host::connection::Connection<'_, #packet_type>, table: trouble_host::prelude::CccdTable<_CCCD_TABLE_SIZE>) {
                     self.server.set_cccd_table(connection, table);
                 }
@@ -241,3 +323,42 @@ impl ServerBuilder {
         }
     }
 }
+
+/// Name the storage struct generated by `#[gatt_service]` for a service type, i.e. `BatteryServiceStorage`.
+fn service_storage_type(service_type: &syn::Type) -> Result<syn::Type> {
+    let syn::Type::Path(path) = service_type else {
+        return Err(syn::Error::new_spanned(
+            service_type,
+            "services must be named by the path of a #[gatt_service] struct",
+        ));
+    };
+    let mut path = path.clone();
+    let last = path
+        .path
+        .segments
+        .last_mut()
+        .ok_or(syn::Error::new_spanned(service_type, "service type path is empty"))?;
+    last.ident = format_ident!("{}Storage", last.ident);
+    Ok(syn::Type::Path(path))
+}
+
+/// Parse the `#[include(..)]` attribute of a service field, listing services declared before it.
+fn parse_includes(service: &syn::Field, declared: &[&syn::Ident]) -> Result<Vec<syn::Ident>> {
+    let mut includes = Vec::new();
+    for attr in service.attrs.iter().filter(|attr| attr.path().is_ident("include")) {
+        attr.parse_nested_meta(|meta| {
+            let include = meta
+                .path
+                .get_ident()
+                .ok_or(meta.error("include takes the names of services. i.e. #[include(bas)]"))?;
+            if !declared.contains(&include) {
+                return Err(meta.error(format!(
+                    "'{include}' must be a service declared before this one in the server"
+                )));
+            }
+            includes.push(include.clone());
+            Ok(())
+        })?;
+    }
+    Ok(includes)
+}
@@ -4,7 +4,6 @@
 //! The struct definition is used to define the characteristics of the service, and the ServiceBuilder is used to
 //! generate the code required to create the service.
 
-use convert_case::{Case, Casing};
 use darling::Error;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, quote_spanned, ToTokens};
@@ -18,11 +17,17 @@ use crate::uuid::parse_arg_uuid;
 #[derive(Debug)]
 pub(crate) struct ServiceArgs {
     pub uuid: TokenStream2,
+    /// If true, the service is declared as a secondary service.
+    pub secondary: bool,
+    /// Fixed handle of the service declaration.
+    pub handle: Option<syn::Expr>,
 }
 
 impl syn::parse::Parse for ServiceArgs {
     fn parse(input: syn::parse::ParseStream) -> Result<Self> {
         let mut uuid: Option<_> = None;
+        let mut secondary = false;
+        let mut handle: Option<syn::Expr> = None;
 
         while !input.is_empty() {
             let meta = input.parse()?;
@@ -44,15 +49,31 @@ impl syn::parse::Parse for ServiceArgs {
                             }
                             uuid = Some(parse_arg_uuid(&name_value.value)?);
                         }
+                        "handle" => {
+                            if handle.is_some() {
+                                return Err(Error::custom("handle cannot be specified more than once")
+                                    .with_span(&name_value.span())
+                                    .into());
+                            }
+                            handle = Some(name_value.value.clone());
+                        }
                         other => {
                             return Err(Error::unknown_field(&format!(
-                                "Unsupported service property: '{other}'.\nSupported properties are: uuid"
+                                "Unsupported service property: '{other}'.\nSupported properties are: uuid, secondary, handle"
                             ))
                             .with_span(&name_value.span())
                             .into())
                         }
                     }
                 }
+                Meta::Path(path) if path.is_ident("secondary") => {
+                    if secondary {
+                        return Err(Error::custom("secondary cannot be specified more than once")
+                            .with_span(&path.span())
+                            .into());
+                    }
+                    secondary = true;
+                }
                 _ => return Err(Error::custom("Unexpected argument").with_span(&meta.span()).into()),
             }
             let _ = input.parse::<Token![,]>();
@@ -62,6 +83,8 @@ impl syn::parse::Parse for ServiceArgs {
             uuid: uuid.ok_or(Error::custom(
                 "Service must have a UUID (i.e. `#[gatt_service(uuid = '1234')]` or `#[gatt_service(uuid = service::BATTERY)]`)",
             ))?,
+            secondary,
+            handle,
         })
     }
 }
@@ -75,6 +98,10 @@ pub(crate) struct ServiceBuilder {
     code_build_chars: TokenStream2,
     code_struct_init: TokenStream2,
     code_fields: TokenStream2,
+    code_dispatch_read: TokenStream2,
+    code_dispatch_write: TokenStream2,
+    code_storage_fields: TokenStream2,
+    code_storage_init: TokenStream2,
 }
 
 impl ServiceBuilder {
@@ -88,8 +115,23 @@ impl ServiceBuilder {
             code_impl: TokenStream2::new(),
             code_fields: TokenStream2::new(),
             code_build_chars: TokenStream2::new(),
+            code_dispatch_read: TokenStream2::new(),
+            code_dispatch_write: TokenStream2::new(),
+            code_storage_fields: TokenStream2::new(),
+            code_storage_init: TokenStream2::new(),
         }
     }
+
+    /// Add a byte array of `len` bytes to the storage struct of the service, returning the expression borrowing it.
+    fn add_storage(&mut self, name: &syn::Ident, len: TokenStream2) -> TokenStream2 {
+        self.code_storage_fields.extend(quote! {
+            #name: [u8; #len],
+        });
+        self.code_storage_init.extend(quote! {
+            #name: [0; #len],
+        });
+        quote!(&mut storage.#name)
+    }
     /// Increment the number of access arguments required for this characteristic
     ///
     /// At least two attributes will be added to the attribute table for each characteristic:
@@ -117,24 +159,80 @@ impl ServiceBuilder {
         let fields = self.code_fields;
         let code_build_chars = self.code_build_chars;
         let uuid = self.args.uuid;
+        let set_handle = self.args.handle.map(|handle| {
+            quote! {
+                table
+                    .set_next_handle(#handle)
+                    .expect("service handles must not overlap the handles before them");
+            }
+        });
+        let add_service = if self.args.secondary {
+            quote!(add_secondary_service)
+        } else {
+            quote!(add_service)
+        };
         let attribute_count = self.attribute_count;
         let cccd_count = self.cccd_count;
+        let code_dispatch_read = self.code_dispatch_read;
+        let code_dispatch_write = self.code_dispatch_write;
+        let code_storage_fields = self.code_storage_fields;
+        let code_storage_init = self.code_storage_init;
+        let storage_name = format_ident!("{}Storage", struct_name);
+        let storage_doc = format!("Attribute value storage for a [`{struct_name}`].");
         quote! {
             #visibility struct #struct_name {
                 #fields
                 handle: u16,
             }
 
+            #[doc = #storage_doc]
+            #visibility struct #storage_name {
+                #code_storage_fields
+            }
+
+            impl #storage_name {
+                /// Create zeroed storage.
+                #visibility const fn new() -> Self {
+                    Self {
+                        #code_storage_init
+                    }
+                }
+            }
+
+            impl Default for #storage_name {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
             #[allow(unused)]
             impl #struct_name {
                 #visibility const ATTRIBUTE_COUNT: usize = #attribute_count;
                 #visibility const CCCD_COUNT: usize = #cccd_count;
 
-                #visibility fn new<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Self
+                /// Add the service to the table, keeping its attribute values in `storage`.
+                #visibility fn new<'d, M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'d, M, MAX_ATTRIBUTES>, storage: &'d mut #storage_name) -> Self
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                {
+                    Self::new_including(table, storage, &[])
+                }
+
+                /// Add the service to the table, including the services declared at the `includes` handles.
+                ///
+                /// The included services must already be in the table, and each include adds one attribute on top of
+                /// `ATTRIBUTE_COUNT`.
+                #visibility fn new_including<'d, M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'d, M, MAX_ATTRIBUTES>, storage: &'d mut #storage_name, includes: &[u16]) -> Self
                 where
                     M: embassy_sync::blocking_mutex::raw::RawMutex,
                 {
-                    let mut service = table.add_service(trouble_host::attribute::Service::new(#uuid));
+                    #set_handle
+                    let mut service = table.#add_service(trouble_host::attribute::Service::new(#uuid));
+                    for include in includes {
+                        service
+                            .include_service(*include)
+                            .expect("included services must be added to the table first");
+                    }
                     #code_build_chars
 
                     Self {
@@ -142,20 +240,71 @@ impl ServiceBuilder {
                         #code_struct_init
                     }
                 }
+
+                /// Handle of the service declaration.
+                #visibility fn handle(&self) -> u16 {
+                    self.handle
+                }
+
+                /// Run the `on_read` / `on_write` handlers registered for the characteristic targeted by this event.
+                ///
+                /// Returns `Ok(true)` if a handler was run. The event still has to be accepted or rejected afterwards.
+                #visibility fn dispatch<M, P, const AT: usize, const CT: usize, const CN: usize>(
+                    &self,
+                    server: &trouble_host::prelude::AttributeServer<'_, M, P, AT, CT, CN>,
+                    event: &trouble_host::gatt::GattEvent<'_, '_, P>,
+                ) -> Result<bool, trouble_host::Error>
+                where
+                    M: embassy_sync::blocking_mutex::raw::RawMutex,
+                    P: trouble_host::PacketPool,
+                {
+                    match event {
+                        trouble_host::gatt::GattEvent::Read(event) => {
+                            #code_dispatch_read
+                        }
+                        trouble_host::gatt::GattEvent::Write(event) => {
+                            #code_dispatch_write
+                        }
+                        _ => {}
+                    }
+                    Ok(false)
+                }
                 #code_impl
             }
         }
     }
 
-    /// Construct instructions for adding a characteristic to the service, with static storage.
-    fn construct_characteristic_static(&mut self, characteristic: Characteristic) {
-        let (code_descriptors, named_descriptors) = self.build_descriptors(&characteristic);
-        let name_screaming = format_ident!("{}", characteristic.name.as_str().to_case(Case::Constant));
+    /// Construct instructions for adding a characteristic to the service, with its value in the service storage.
+    fn construct_characteristic(&mut self, characteristic: Characteristic) {
+        let (mut code_descriptors, mut named_descriptors) = self.build_descriptors(&characteristic);
+        let (code_standard_descriptors, named_standard_descriptors) = self.build_standard_descriptors(&characteristic);
+        code_descriptors.extend(code_standard_descriptors);
+        named_descriptors.extend(named_standard_descriptors);
         let char_name = format_ident!("{}", characteristic.name);
         let ty = characteristic.ty;
+        let store = self.add_storage(
+            &char_name,
+            quote!(<#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE),
+        );
         let access = &characteristic.args.access;
         let properties = set_access_properties(access);
         let uuid = characteristic.args.uuid;
+        let code_extended_props = if characteristic.args.reliable_write || characteristic.args.writable_auxiliaries {
+            let reliable_write = characteristic.args.reliable_write;
+            let writable_auxiliaries = characteristic.args.writable_auxiliaries;
+            quote_spanned! {characteristic.span=>
+                builder.add_extended_properties(trouble_host::attribute::CharacteristicExtendedProps::new(#reliable_write, #writable_auxiliaries));
+            }
+        } else {
+            quote! {}
+        };
+        let code_set_handle = characteristic.args.handle.map(|handle| {
+            quote_spanned! {characteristic.span=>
+                service
+                    .set_next_handle(#handle)
+                    .expect("characteristic handles must not overlap the handles before them");
+            }
+        });
         let default_value = match characteristic.args.default_value {
             Some(val) => quote!(#val),                                       // if set by user
             None => quote_spanned!(characteristic.span => <#ty>::default()), // or default otherwise
@@ -163,10 +312,11 @@ impl ServiceBuilder {
 
         self.code_build_chars.extend(quote_spanned! {characteristic.span=>
             let (#char_name, #(#named_descriptors),*) = {
-                static #name_screaming: static_cell::StaticCell<[u8; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]> = static_cell::StaticCell::new();
-                let store = #name_screaming.init([0; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]);
+                let store = #store;
+                #code_set_handle
                 let mut builder = service
                     .add_characteristic(#uuid, &[#(#properties),*], #default_value, store);
+                #code_extended_props
                 #code_descriptors
 
                 (builder.build(), #(#named_descriptors),*)
@@ -176,10 +326,37 @@ impl ServiceBuilder {
         self.code_struct_init.extend(quote_spanned!(characteristic.span=>
             #char_name,
         ));
+
+        if let Some(on_read) = characteristic.args.on_read {
+            // Only refresh the value on the first chunk so that a long read returns a consistent value.
+            self.code_dispatch_read.extend(quote_spanned! {on_read.span()=>
+                if event.handle() == self.#char_name.handle {
+                    if event.offset() == 0 {
+                        let handler: fn(&Self) -> #ty = #on_read;
+                        self.#char_name.set(server, &handler(self))?;
+                    }
+                    return Ok(true);
+                }
+            });
+        }
+
+        if let Some(on_write) = characteristic.args.on_write {
+            // Only whole values are decoded, not the part of a long write starting at an offset.
+            self.code_dispatch_write.extend(quote_spanned! {on_write.span()=>
+                if event.handle() == self.#char_name.handle {
+                    if event.offset() == 0 {
+                        let value = event.value(&self.#char_name).map_err(|_| trouble_host::Error::InvalidValue)?;
+                        let handler: fn(&Self, &#ty) = #on_write;
+                        handler(self, &value);
+                    }
+                    return Ok(true);
+                }
+            });
+        }
     }
 
     /// Consume the lists of fields and fields marked as characteristics and prepare the code to add them to the service
-    /// by generating the macro blueprints for any methods, fields, and static storage required.
+    /// by generating the macro blueprints for any methods, fields, and storage required.
     pub fn process_characteristics_and_fields(
         mut self,
         mut fields: Vec<syn::Field>,
@@ -213,7 +390,7 @@ impl ServiceBuilder {
 
             self.increment_attributes(&ch.args.access);
 
-            self.construct_characteristic_static(ch);
+            self.construct_characteristic(ch);
         }
         assert_eq!(fields.len(), doc_strings.len());
         // Processing common to all fields
@@ -241,15 +418,18 @@ impl ServiceBuilder {
     /// Generate token stream for any descriptors tagged against this characteristic.
     fn build_descriptors(&mut self, characteristic: &Characteristic) -> (TokenStream2, Vec<TokenStream2>) {
         let mut named_descriptors = Vec::<TokenStream2>::new();
-        (characteristic
+        (
+            characteristic
                 .args
                 .descriptors
                 .iter()
                 .enumerate()
                 .map(|(index, args)| {
-                    let name_screaming =
-                        format_ident!("DESC_{index}_{}", characteristic.name.as_str().to_case(Case::Constant));
-                    let identifier = args.name.as_ref().map(|name| format_ident!("{}_{}_descriptor", characteristic.name.as_str(), name.value()));
+                    let storage_name = format_ident!("{}_descriptor_{index}", characteristic.name.as_str());
+                    let identifier = args
+                        .name
+                        .as_ref()
+                        .map(|name| format_ident!("{}_{}_descriptor", characteristic.name.as_str(), name.value()));
                     let access = &args.access;
                     let properties = set_access_properties(access);
                     let uuid = &args.uuid;
@@ -259,12 +439,12 @@ impl ServiceBuilder {
                     };
                     let capacity = match &args.capacity {
                         Some(cap) => quote!(#cap),
-                        None => quote!(#default_value.len() as usize)
+                        None => quote!(#default_value.len() as usize),
                     };
 
                     let mut identifier_assignment = None;
                     if let Some(name) = &identifier {
-                        self.code_fields.extend(quote_spanned!{ identifier.span() =>
+                        self.code_fields.extend(quote_spanned! { identifier.span() =>
                             #name: trouble_host::attribute::Descriptor<&'static [u8]>,
                         });
                         self.code_struct_init.extend(quote_spanned! { identifier.span() =>
@@ -275,12 +455,12 @@ impl ServiceBuilder {
                     };
 
                     self.attribute_count += 1; // descriptors should always only be one attribute.
+                    let store = self.add_storage(&storage_name, capacity);
 
                     quote_spanned! {characteristic.span=>
                         #identifier_assignment {
                             let value = #default_value;
-                            static #name_screaming: static_cell::StaticCell<[u8; #capacity]> = static_cell::StaticCell::new();
-                            let store = #name_screaming.init([0; #capacity]);
+                            let store = #store;
                             let value = trouble_host::types::gatt_traits::AsGatt::as_gatt(&value);
                             store[..value.len()].copy_from_slice(value);
                             builder.add_descriptor::<&[u8], _>(
@@ -292,7 +472,131 @@ impl ServiceBuilder {
                     }
                 })
                 .collect(),
-            named_descriptors)
+            named_descriptors,
+        )
+    }
+
+    /// Generate token stream for the standard descriptors tagged against this characteristic.
+    ///
+    /// Each of these descriptors is read only and gets a typed field named after the characteristic.
+    fn build_standard_descriptors(&mut self, characteristic: &Characteristic) -> (TokenStream2, Vec<TokenStream2>) {
+        let standard = &characteristic.args.standard_descriptors;
+        let char_name = characteristic.name.as_str();
+        let ty = &characteristic.ty;
+        let read = quote!(trouble_host::attribute::CharacteristicProp::Read);
+        let mut code = TokenStream2::new();
+        let mut named_descriptors = Vec::<TokenStream2>::new();
+        let mut add_field = |this: &mut Self, name: &syn::Ident, value_ty: TokenStream2| {
+            this.code_fields.extend(quote_spanned! {characteristic.span=>
+                #name: trouble_host::attribute::Descriptor<#value_ty>,
+            });
+            this.code_struct_init.extend(quote_spanned! {characteristic.span=>
+                #name,
+            });
+            this.attribute_count += 1;
+            named_descriptors.push(name.to_token_stream());
+        };
+
+        if let Some(text) = &standard.user_description {
+            let name = format_ident!("{char_name}_user_description");
+            add_field(self, &name, quote!(&'static str));
+            code.extend(quote_spanned! {characteristic.span=>
+                let #name = {
+                    let text: &'static str = #text;
+                    builder.add_descriptor_ro::<&'static str, _>(
+                        trouble_host::types::uuid::Uuid::new_short(0x2901),
+                        text.as_bytes(),
+                    )
+                };
+            });
+        }
+
+        let numbered = standard.presentation_formats.len() > 1;
+        let mut format_names = Vec::new();
+        for (index, format) in standard.presentation_formats.iter().enumerate() {
+            let name = if numbered {
+                format_ident!("{char_name}_presentation_format_{index}")
+            } else {
+                format_ident!("{char_name}_presentation_format")
+            };
+            let store = self.add_storage(&name, quote!(7));
+            let value = &format.format;
+            let exponent = format.exponent.as_ref().map_or(quote!(0), |e| quote!(#e));
+            let unit = format.unit.as_ref().map_or(
+                quote!(trouble_host::attribute::PresentationFormat::UNITLESS),
+                |u| quote!(#u),
+            );
+            let namespace = format.namespace.as_ref().map_or(
+                quote!(trouble_host::attribute::PresentationFormat::NAMESPACE_BLUETOOTH_SIG),
+                |n| quote!(#n),
+            );
+            let description = format.description.as_ref().map_or(quote!(0), |d| quote!(#d));
+            add_field(self, &name, quote!(trouble_host::attribute::PresentationFormat));
+            code.extend(quote_spanned! {characteristic.span=>
+                let #name = {
+                    let format = trouble_host::attribute::PresentationFormat::new(
+                        #value,
+                        #exponent,
+                        #unit,
+                        #namespace,
+                        #description,
+                    );
+                    let store = #store;
+                    *store = format.to_bytes();
+                    builder.add_descriptor::<trouble_host::attribute::PresentationFormat, _>(
+                        trouble_host::types::uuid::Uuid::new_short(0x2904),
+                        &[#read],
+                        store,
+                    )
+                };
+            });
+            format_names.push(name);
+        }
+
+        if standard.aggregate_format {
+            let name = format_ident!("{char_name}_aggregate_format");
+            let len = format_names.len() * 2;
+            let store = self.add_storage(&name, quote!(#len));
+            add_field(self, &name, quote!([u8; #len]));
+            code.extend(quote_spanned! {characteristic.span=>
+                let #name = {
+                    let store = #store;
+                    let handles = [#(trouble_host::attribute::AttributeHandle::handle(&#format_names)),*];
+                    for (chunk, handle) in store.chunks_exact_mut(2).zip(handles) {
+                        chunk.copy_from_slice(&handle.to_le_bytes());
+                    }
+                    builder.add_descriptor::<[u8; #len], _>(
+                        trouble_host::types::uuid::Uuid::new_short(0x2905),
+                        &[#read],
+                        store,
+                    )
+                };
+            });
+        }
+
+        if let Some((lower, upper)) = &standard.valid_range {
+            let name = format_ident!("{char_name}_valid_range");
+            let range_ty = quote!(trouble_host::attribute::ValidRange<#ty>);
+            let store = self.add_storage(
+                &name,
+                quote!(<#range_ty as trouble_host::types::gatt_traits::FixedGattValue>::SIZE),
+            );
+            add_field(self, &name, range_ty.clone());
+            code.extend(quote_spanned! {characteristic.span=>
+                let #name = {
+                    let range: #range_ty = trouble_host::attribute::ValidRange::new(#lower, #upper);
+                    let store = #store;
+                    store.copy_from_slice(trouble_host::types::gatt_traits::AsGatt::as_gatt(&range));
+                    builder.add_descriptor::<#range_ty, _>(
+                        trouble_host::types::uuid::Uuid::new_short(0x2906),
+                        &[#read],
+                        store,
+                    )
+                };
+            });
+        }
+
+        (code, named_descriptors)
     }
 }
 
@@ -11,8 +11,12 @@ static CONFIGS: &[(&str, usize)] = &[
     ("L2CAP_TX_QUEUE_SIZE", 8),
     ("DEFAULT_PACKET_POOL_SIZE", 16),
     ("DEFAULT_PACKET_POOL_MTU", 251),
+    ("DEFAULT_PACKET_POOL_SMALL_SIZE", 0),
+    ("DEFAULT_PACKET_POOL_SMALL_MTU", 27),
     ("GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS", 1),
     ("GATT_CLIENT_NOTIFICATION_QUEUE_SIZE", 1),
+    ("GATT_SERVER_PREPARE_QUEUE_SIZE", 0),
+    ("GATT_SERVER_NOTIFICATION_QUEUE_SIZE", 4),
     // END AUTOGENERATED CONFIG FEATURES
 ];
 
@@ -116,6 +120,21 @@ fn main() {
                 panic!("📍 'default-packet-pool-mtu-{{X}}' feature also needs 'default-packet-pool' to be enabled.");
             }
         }
+
+        // Only one of (or none) 'packet-pool-align-{X}' is allowed
+        {
+            let n = 0;
+            #[cfg(feature = "packet-pool-align-4")]
+            let n = n + 1;
+            #[cfg(feature = "packet-pool-align-8")]
+            let n = n + 1;
+            #[cfg(feature = "packet-pool-align-16")]
+            let n = n + 1;
+            #[cfg(feature = "packet-pool-align-32")]
+            let n = n + 1;
+
+            assert!(n <= 1, "📍 More than one 'packet-pool-align-X' feature is enabled.");
+        }
     }
 
     // Rebuild if config envvar changed.
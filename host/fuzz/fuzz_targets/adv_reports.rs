@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    trouble_host::fuzz::adv_data(data);
+    trouble_host::fuzz::adv_reports(data);
+    trouble_host::fuzz::ext_adv_reports(data);
+});
@@ -52,12 +52,24 @@ feature("default_packet_pool_size",
 feature("default_packet_pool_mtu",
         "Controls the packet MTU of the default packet pool, if enabled.",
         default=251, vals = [27, 48, 64, 128, 251, 255, 512, 1024])
+feature("default_packet_pool_small_size",
+        "Controls the number of small packets of the default packet pool, if enabled.",
+        default=0, min=0, max=128, pow2=True)
+feature("default_packet_pool_small_mtu",
+        "Controls the small packet MTU of the default packet pool, if enabled.",
+        default=27, vals = [27, 32, 48, 64, 128])
 feature("gatt_client_notification_max_subscribers",
         "When using the GATT client, this controls how many subscribers can be created.",
         default=1, min=1, max=512, pow2=True)
 feature("gatt_client_notification_queue_size",
         "When using the GATT client, this controls how many notifications can be queued for each subscriber.",
         default=1, min=1, max=512, pow2=True)
+feature("gatt_server_prepare_queue_size",
+        "When using the GATT server, this controls how many bytes of prepared writes can be queued for each client.",
+        default=0, vals = [0, 64, 128, 256, 512, 1024, 2048, 4096])
+feature("gatt_server_notification_queue_size",
+        "When using the GATT server, this controls how many notifications to all clients can be queued for each client.",
+        default=4, min=1, max=64, pow2=True)
 
 # ========= Update Cargo.toml
 
@@ -0,0 +1,316 @@
+//! Accounting of the time spent advertising, scanning and in connection events.
+//!
+//! The controller does not report how long the radio is active, so the host estimates it from the time
+//! each activity was enabled and its parameters: the number of events follows from the interval, and
+//! each event is assumed to keep the radio busy for a fixed airtime.
+use bt_hci::param::AdvHandle;
+#[cfg(feature = "peripheral")]
+use bt_hci::param::{AdvChannelMap, PhyKind};
+use embassy_time::{Duration, Instant};
+
+#[cfg(feature = "peripheral")]
+use crate::advertise::{AdvertisementParameters, RawAdvertisement};
+#[cfg(feature = "scan")]
+use crate::connection::ScanConfig;
+
+/// Maximum number of advertising sets tracked by the host.
+pub const TRACKED_ADV_SETS: usize = 4;
+
+/// Inter frame space between packets of the same event.
+#[cfg(feature = "peripheral")]
+const IFS: Duration = Duration::from_micros(150);
+
+/// Estimated radio usage of an advertising set, a scan or a connection.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RadioActivity {
+    /// Time the activity was enabled.
+    pub enabled: Duration,
+    /// Estimated time the radio was transmitting or receiving.
+    pub radio: Duration,
+    /// Estimated number of advertising, scan or connection events.
+    pub events: u64,
+}
+
+impl RadioActivity {
+    /// Estimated fraction of the enabled time the radio was active, in parts per thousand.
+    pub fn duty_cycle_permille(&self) -> u32 {
+        match self.enabled.as_micros() {
+            0 => 0,
+            enabled => (self.radio.as_micros() * 1000 / enabled) as u32,
+        }
+    }
+
+    fn add(&mut self, other: &RadioActivity) {
+        self.enabled += other.enabled;
+        self.radio += other.radio;
+        self.events += other.events;
+    }
+}
+
+/// Estimated radio usage of the advertising sets and scanning of the host.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RadioUsage {
+    /// Advertising, indexed by advertising handle.
+    pub advertising: [RadioActivity; TRACKED_ADV_SETS],
+    /// Scanning.
+    pub scanning: RadioActivity,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    start: Instant,
+    until: Option<Instant>,
+    interval: Duration,
+    airtime: Duration,
+}
+
+impl Segment {
+    fn activity(&self, now: Instant) -> RadioActivity {
+        let end = self.until.map_or(now, |until| until.min(now));
+        if end <= self.start {
+            return RadioActivity::default();
+        }
+        let enabled = end - self.start;
+        let interval = self.interval.as_ticks().max(1);
+        let full = enabled.as_ticks() / interval;
+        let rest = Duration::from_ticks(enabled.as_ticks() % interval);
+        let airtime = self.airtime.min(self.interval);
+        RadioActivity {
+            enabled,
+            radio: Duration::from_ticks(airtime.as_ticks() * full) + rest.min(airtime),
+            events: full + u64::from(rest.as_ticks() > 0),
+        }
+    }
+}
+
+/// Accumulated radio activity, with the currently running period if any.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ActivityTimer {
+    total: RadioActivity,
+    running: Option<Segment>,
+}
+
+impl ActivityTimer {
+    pub(crate) const fn new() -> Self {
+        Self {
+            total: RadioActivity {
+                enabled: Duration::from_ticks(0),
+                radio: Duration::from_ticks(0),
+                events: 0,
+            },
+            running: None,
+        }
+    }
+
+    /// Start a period with one event of `airtime` every `interval`, ending at `until` at the latest.
+    pub(crate) fn start(&mut self, now: Instant, interval: Duration, airtime: Duration, until: Option<Instant>) {
+        self.stop(now);
+        self.running = Some(Segment {
+            start: now,
+            until,
+            interval,
+            airtime,
+        });
+    }
+
+    /// Continue the running period with a new interval, or start one if none is running.
+    pub(crate) fn set_interval(&mut self, now: Instant, interval: Duration, airtime: Duration) {
+        match self.running {
+            Some(segment) if segment.interval == interval && segment.airtime == airtime => {}
+            Some(segment) => self.start(now, interval, airtime, segment.until),
+            None => self.start(now, interval, airtime, None),
+        }
+    }
+
+    pub(crate) fn stop(&mut self, now: Instant) {
+        if let Some(segment) = self.running.take() {
+            self.total.add(&segment.activity(now));
+        }
+    }
+
+    pub(crate) fn activity(&self, now: Instant) -> RadioActivity {
+        let mut activity = self.total;
+        if let Some(segment) = &self.running {
+            activity.add(&segment.activity(now));
+        }
+        activity
+    }
+}
+
+/// Radio activity of the advertising sets and scanning.
+#[derive(Debug)]
+pub(crate) struct ActivityState {
+    advertising: [ActivityTimer; TRACKED_ADV_SETS],
+    scanning: ActivityTimer,
+}
+
+impl ActivityState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            advertising: [ActivityTimer::new(); TRACKED_ADV_SETS],
+            scanning: ActivityTimer::new(),
+        }
+    }
+
+    #[cfg(feature = "peripheral")]
+    pub(crate) fn advertising_started(
+        &mut self,
+        handle: AdvHandle,
+        params: &AdvertisementParameters,
+        data: &RawAdvertisement<'_>,
+    ) {
+        let timer = self
+            .advertising
+            .iter_mut()
+            .enumerate()
+            .find(|(idx, _)| AdvHandle::new(*idx as u8) == handle);
+        if let Some((_, timer)) = timer {
+            // The controller adds a random delay of 0-10 ms to every advertising event.
+            let interval = (params.interval_min + params.interval_max) / 2 + Duration::from_millis(5);
+            let now = Instant::now();
+            let until = params.timeout.map(|timeout| now + timeout);
+            timer.start(now, interval, advertising_airtime(params, data), until);
+        }
+    }
+
+    pub(crate) fn advertising_stopped(&mut self, handle: Option<AdvHandle>) {
+        let now = Instant::now();
+        for (idx, timer) in self.advertising.iter_mut().enumerate() {
+            if handle.is_none() || handle == Some(AdvHandle::new(idx as u8)) {
+                timer.stop(now);
+            }
+        }
+    }
+
+    #[cfg(feature = "scan")]
+    pub(crate) fn scan_started(&mut self, config: &ScanConfig<'_>) {
+        let now = Instant::now();
+        let until = (config.timeout.as_ticks() > 0).then(|| now + config.timeout);
+        self.scanning.start(now, config.interval, config.window, until);
+    }
+
+    pub(crate) fn scan_stopped(&mut self) {
+        self.scanning.stop(Instant::now());
+    }
+
+    pub(crate) fn usage(&self) -> RadioUsage {
+        let now = Instant::now();
+        let mut usage = RadioUsage {
+            scanning: self.scanning.activity(now),
+            ..Default::default()
+        };
+        for (activity, timer) in usage.advertising.iter_mut().zip(self.advertising.iter()) {
+            *activity = timer.activity(now);
+        }
+        usage
+    }
+
+    pub(crate) fn reset(&mut self) {
+        let now = Instant::now();
+        for timer in self.advertising.iter_mut().chain(core::iter::once(&mut self.scanning)) {
+            timer.total = RadioActivity::default();
+            if let Some(segment) = timer.running.as_mut() {
+                segment.start = now;
+            }
+        }
+    }
+}
+
+/// Time to transmit `len` bytes, including preamble, access address and CRC, on `phy`.
+#[cfg(feature = "peripheral")]
+fn packet_airtime(phy: PhyKind, len: usize) -> Duration {
+    let len = len as u64;
+    Duration::from_micros(match phy {
+        PhyKind::Le1M => 8 * (len + 8),
+        PhyKind::Le2M => 4 * (len + 9),
+        // Assume the S=8 coding of the coded PHY, which is the slowest.
+        _ => 400 + 64 * (len + 3),
+    })
+}
+
+/// Airtime of one advertising event.
+///
+/// Counts the advertising PDUs sent on the primary channels, each followed by the inter frame space, and
+/// for extended advertising the auxiliary PDU carrying the data on the secondary channel.
+#[cfg(feature = "peripheral")]
+fn advertising_airtime(params: &AdvertisementParameters, data: &RawAdvertisement<'_>) -> Duration {
+    let map = params.channel_map.unwrap_or(AdvChannelMap::ALL);
+    let channels = [
+        map.is_channel_37_enabled(),
+        map.is_channel_38_enabled(),
+        map.is_channel_39_enabled(),
+    ]
+    .iter()
+    .filter(|enabled| **enabled)
+    .count() as u32;
+    // Header and advertiser address.
+    let header = 2 + 6;
+    if data.props.legacy_adv() {
+        (packet_airtime(PhyKind::Le1M, header + data.adv_data.len()) + IFS) * channels
+    } else {
+        // ADV_EXT_IND with the extended header pointing to the auxiliary packet.
+        let primary = (packet_airtime(params.primary_phy, header + 6) + IFS) * channels;
+        primary + packet_airtime(params.secondary_phy, header + 6 + data.adv_data.len())
+    }
+}
+
+/// Airtime of a connection event in which both sides send an empty packet on the 1M PHY.
+///
+/// Each empty packet takes 80 µs and is followed by the inter frame space.
+pub(crate) const CONNECTION_EVENT_AIRTIME: Duration = Duration::from_micros(2 * (80 + 150));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periodic_activity() {
+        let start = Instant::from_secs(10);
+        let mut timer = ActivityTimer::new();
+        timer.start(start, Duration::from_millis(100), Duration::from_millis(1), None);
+
+        let activity = timer.activity(start + Duration::from_millis(1050));
+        assert_eq!(activity.enabled, Duration::from_millis(1050));
+        assert_eq!(activity.events, 11);
+        assert_eq!(activity.radio, Duration::from_millis(11));
+        assert_eq!(activity.duty_cycle_permille(), 10);
+
+        timer.stop(start + Duration::from_secs(2));
+        let activity = timer.activity(start + Duration::from_secs(5));
+        assert_eq!(activity.enabled, Duration::from_secs(2));
+        assert_eq!(activity.events, 20);
+    }
+
+    #[test]
+    fn activity_ends_at_timeout() {
+        let start = Instant::from_secs(10);
+        let mut timer = ActivityTimer::new();
+        timer.start(
+            start,
+            Duration::from_millis(100),
+            Duration::from_millis(100),
+            Some(start + Duration::from_secs(1)),
+        );
+        let activity = timer.activity(start + Duration::from_secs(3));
+        assert_eq!(activity.enabled, Duration::from_secs(1));
+        assert_eq!(activity.radio, Duration::from_secs(1));
+        assert_eq!(activity.duty_cycle_permille(), 1000);
+    }
+
+    #[test]
+    fn interval_change_keeps_total() {
+        let start = Instant::from_secs(10);
+        let mut timer = ActivityTimer::new();
+        timer.set_interval(start, Duration::from_millis(50), CONNECTION_EVENT_AIRTIME);
+        timer.set_interval(
+            start + Duration::from_secs(1),
+            Duration::from_millis(100),
+            CONNECTION_EVENT_AIRTIME,
+        );
+        let activity = timer.activity(start + Duration::from_secs(2));
+        assert_eq!(activity.enabled, Duration::from_secs(2));
+        assert_eq!(activity.events, 30);
+    }
+}
@@ -4,6 +4,7 @@ pub use bt_hci::param::{AdvChannelMap, AdvFilterPolicy, AdvHandle, AdvSet, PhyKi
 use embassy_time::Duration;
 
 use crate::cursor::{ReadCursor, WriteCursor};
+use crate::types::appearance::Appearance;
 use crate::types::uuid::Uuid;
 use crate::{bt_hci_duration, codec, Address};
 
@@ -93,6 +94,9 @@ pub struct AdvertisementParameters {
     pub channel_map: Option<AdvChannelMap>,
 
     /// Filtering policy
+    ///
+    /// Restricts scan and/or connection requests to the devices in the filter accept list, see
+    /// [`Peripheral::set_filter_accept_list`](crate::peripheral::Peripheral::set_filter_accept_list).
     pub filter_policy: AdvFilterPolicy,
 
     /// Fragmentation preference
@@ -116,6 +120,26 @@ impl Default for AdvertisementParameters {
     }
 }
 
+/// One payload of a beacon rotation, see [`Peripheral::rotate_adv_data`](crate::peripheral::Peripheral::rotate_adv_data).
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdvRotationEntry<'d> {
+    /// Advertisement data to broadcast.
+    pub data: Advertisement<'d>,
+    /// Number of advertising events this payload is broadcast for before moving to the next one.
+    pub events: u16,
+}
+
+impl AdvRotationEntry<'_> {
+    /// How long this payload stays on air with the given advertising parameters.
+    ///
+    /// The controller adds a pseudo-random delay of up to 10 ms to every advertising event,
+    /// so the average of 5 ms is included.
+    pub fn duration(&self, params: &AdvertisementParameters) -> Duration {
+        (params.interval_max + Duration::from_millis(5)) * self.events as u32
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) struct RawAdvertisement<'d> {
@@ -385,6 +409,9 @@ pub enum AdStructure<'a> {
     /// Sets the shortened device name.
     ShortenedLocalName(&'a [u8]),
 
+    /// External appearance of the device.
+    Appearance(Appearance),
+
     /// Set manufacturer specific data
     ManufacturerSpecificData {
         /// Company identifier.
@@ -412,6 +439,52 @@ impl AdStructure<'_> {
         Ok(w.len())
     }
 
+    /// Encode a slice of advertisement structures followed by the device name.
+    ///
+    /// The name is emitted as a Complete Local Name if it fits in the space left in `adv_dest`,
+    /// otherwise it is truncated (on a character boundary) to a Shortened Local Name. When
+    /// `scan_dest` is given and the name had to be shortened, the complete name is written to the
+    /// scan response data instead.
+    ///
+    /// Returns the number of bytes written to `adv_dest` and `scan_dest` respectively.
+    pub fn encode_slice_with_name(
+        data: &[AdStructure<'_>],
+        name: &str,
+        adv_dest: &mut [u8],
+        scan_dest: Option<&mut [u8]>,
+    ) -> Result<(usize, usize), codec::Error> {
+        let mut w = WriteCursor::new(adv_dest);
+        for item in data.iter() {
+            item.encode(&mut w)?;
+        }
+
+        // Each AD structure has a length and a type byte before the payload.
+        let available = w.available().saturating_sub(2);
+        if name.len() <= available {
+            AdStructure::CompleteLocalName(name.as_bytes()).encode(&mut w)?;
+            return Ok((w.len(), 0));
+        }
+
+        let mut end = available;
+        while end > 0 && !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end > 0 {
+            AdStructure::ShortenedLocalName(&name.as_bytes()[..end]).encode(&mut w)?;
+        }
+
+        let scan_len = match scan_dest {
+            Some(scan_dest) => {
+                let mut s = WriteCursor::new(scan_dest);
+                AdStructure::CompleteLocalName(name.as_bytes()).encode(&mut s)?;
+                s.len()
+            }
+            None if end == 0 => return Err(codec::Error::InsufficientSpace),
+            None => 0,
+        };
+        Ok((w.len(), scan_len))
+    }
+
     pub(crate) fn encode(&self, w: &mut WriteCursor<'_>) -> Result<(), codec::Error> {
         match self {
             AdStructure::Flags(flags) => {
@@ -442,6 +515,10 @@ impl AdStructure<'_> {
                 w.write(Uuid::Uuid16(*uuid))?;
                 w.append(data)?;
             }
+            AdStructure::Appearance(appearance) => {
+                w.append(&[0x03, 0x19])?;
+                w.append(&appearance.to_le_bytes())?;
+            }
             AdStructure::ManufacturerSpecificData {
                 company_identifier,
                 payload,
@@ -535,7 +612,12 @@ impl<'d> AdStructureIter<'d> {
             /*
             0x17 Public Target Address
             0x18 Random Target Address
-            0x19 Appearance
+            */
+            // Appearance
+            0x19 if data.len() == 2 => Ok(AdStructure::Appearance(Appearance::from_u16(u16::from_le_bytes([
+                data[0], data[1],
+            ])))),
+            /*
             0x1A Advertising Interval
             0x1B LE Bluetooth Device Address
             0x1C LE Role
@@ -601,4 +683,70 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn adv_name_placement() {
+        let flags = [AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED)];
+        let mut adv_data = [0; 31];
+        let mut scan_data = [0; 31];
+
+        let (adv_len, scan_len) = unwrap!(AdStructure::encode_slice_with_name(
+            &flags,
+            "short",
+            &mut adv_data[..],
+            Some(&mut scan_data[..])
+        ));
+        assert_eq!(&adv_data[3..adv_len], b"\x06\x09short");
+        assert_eq!(scan_len, 0);
+
+        let name = "this name needs the scan rsp";
+        let (adv_len, scan_len) = unwrap!(AdStructure::encode_slice_with_name(
+            &flags,
+            name,
+            &mut adv_data[..],
+            Some(&mut scan_data[..])
+        ));
+        assert_eq!(adv_len, 31);
+        assert_eq!(&adv_data[3..6], &[27, 0x08, b't']);
+        assert_eq!(&adv_data[5..31], &name.as_bytes()[..26]);
+        assert_eq!(&scan_data[..2], &[name.len() as u8 + 1, 0x09]);
+        assert_eq!(&scan_data[2..scan_len], name.as_bytes());
+
+        let mut full = [0; 3];
+        assert!(AdStructure::encode_slice_with_name(&flags, name, &mut full[..], None).is_err());
+    }
+
+    #[test]
+    fn adv_appearance_roundtrip() {
+        let mut adv_data = [0; 31];
+        let len = unwrap!(AdStructure::encode_slice(
+            &[
+                AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+                AdStructure::Appearance(Appearance::HEART_RATE_BELT),
+            ],
+            &mut adv_data[..],
+        ));
+        assert_eq!(&adv_data[3..len], &[0x03, 0x19, 0x41, 0x03]);
+
+        let mut found = None;
+        for item in AdStructure::decode(&adv_data[..len]) {
+            if let Ok(AdStructure::Appearance(appearance)) = item {
+                found = Some(appearance);
+            }
+        }
+        assert_eq!(found, Some(Appearance::HEART_RATE_BELT));
+    }
+
+    #[test]
+    fn adv_rotation_duration() {
+        let params = AdvertisementParameters {
+            interval_max: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let entry = AdvRotationEntry {
+            data: Advertisement::NonconnectableNonscannableUndirected { adv_data: &[] },
+            events: 10,
+        };
+        assert_eq!(entry.duration(&params), Duration::from_millis(1050));
+    }
 }
@@ -4,6 +4,7 @@ use core::mem;
 
 use crate::codec;
 use crate::cursor::{ReadCursor, WriteCursor};
+use crate::types::gatt_traits::FromGattError;
 use crate::types::uuid::*;
 
 pub(crate) const ATT_READ_BY_GROUP_TYPE_REQ: u8 = 0x10;
@@ -26,13 +27,16 @@ pub(crate) const ATT_PREPARE_WRITE_REQ: u8 = 0x16;
 pub(crate) const ATT_PREPARE_WRITE_RSP: u8 = 0x17;
 pub(crate) const ATT_EXECUTE_WRITE_REQ: u8 = 0x18;
 pub(crate) const ATT_EXECUTE_WRITE_RSP: u8 = 0x19;
-pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x20;
-pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x21;
+pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x0e;
+pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x0f;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_REQ: u8 = 0x20;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_RSP: u8 = 0x21;
 pub(crate) const ATT_READ_BLOB_REQ: u8 = 0x0c;
 pub(crate) const ATT_READ_BLOB_RSP: u8 = 0x0d;
 pub(crate) const ATT_HANDLE_VALUE_NTF: u8 = 0x1b;
 pub(crate) const ATT_HANDLE_VALUE_IND: u8 = 0x1d;
 pub(crate) const ATT_HANDLE_VALUE_CMF: u8 = 0x1e;
+pub(crate) const ATT_MULTIPLE_HANDLE_VALUE_NTF: u8 = 0x23;
 
 /// Attribute Error Code
 ///
@@ -94,6 +98,16 @@ impl AttErrorCode {
     pub const PROCEDURE_ALREADY_IN_PROGRESS: Self = Self { value: 0xFE };
     /// The attribute value is out of range as defined by a profile or service specification
     pub const OUT_OF_RANGE: Self = Self { value: 0xFF };
+
+    /// An application error code, defined by the higher layer specification of the service.
+    ///
+    /// Returns `None` if `code` is outside of the application error range `0x80..=0x9F`.
+    pub const fn application(code: u8) -> Option<Self> {
+        match code {
+            0x80..=0x9F => Some(Self { value: code }),
+            _ => None,
+        }
+    }
 }
 
 impl Display for AttErrorCode {
@@ -248,6 +262,11 @@ pub enum AttReq<'d> {
         /// Attribute handles
         handles: &'d [u8],
     },
+    /// Read Multiple Variable Length Request
+    ReadMultipleVariable {
+        /// Attribute handles
+        handles: &'d [u8],
+    },
     /// Read Blob Request
     ReadBlob {
         /// Attribute handle
@@ -321,6 +340,11 @@ pub enum AttRsp<'d> {
         /// Iterator over the found handles
         it: ReadByTypeIter<'d>,
     },
+    /// Read By Group Type Response
+    ReadByGroupType {
+        /// Iterator over the found attribute groups
+        it: ReadByGroupTypeIter<'d>,
+    },
     /// Read Response
     Read {
         /// Attribute value
@@ -331,8 +355,29 @@ pub enum AttRsp<'d> {
         /// Attribute value part
         data: &'d [u8],
     },
+    /// Read Multiple Response
+    ReadMultiple {
+        /// Concatenated attribute values
+        data: &'d [u8],
+    },
+    /// Read Multiple Variable Length Response
+    ReadMultipleVariable {
+        /// Length Value Tuple List, see [`LengthValueTuples`]
+        tuples: &'d [u8],
+    },
     /// Write Response
     Write,
+    /// Prepare Write Response
+    PrepareWrite {
+        /// Attribute handle
+        handle: u16,
+        /// Attribute offset
+        offset: u16,
+        /// Attribute value part, as queued by the server
+        value: &'d [u8],
+    },
+    /// Execute Write Response
+    ExecuteWrite,
 }
 
 /// ATT Unsolicited PDU
@@ -353,6 +398,69 @@ pub enum AttUns<'d> {
         /// Attribute value
         data: &'d [u8],
     },
+    /// Multiple Handle Value Notification
+    NotifyMultiple {
+        /// Handle Length Value Tuple List, see [`HandleValueTuples`]
+        tuples: &'d [u8],
+    },
+}
+
+/// Iterator over the handle/value pairs of a Multiple Handle Value Notification.
+///
+/// Each tuple is encoded as a 2 byte handle, a 2 byte length and the value. Iteration stops at the
+/// first malformed tuple.
+#[derive(Debug, Clone)]
+pub struct HandleValueTuples<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> HandleValueTuples<'d> {
+    /// Iterate over an encoded Handle Length Value Tuple List.
+    pub fn new(data: &'d [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'d> Iterator for HandleValueTuples<'d> {
+    type Item = (u16, &'d [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut r = ReadCursor::new(self.data);
+        let handle: u16 = r.read().ok()?;
+        let len: u16 = r.read().ok()?;
+        let value = r.slice(len as usize).ok()?;
+        self.data = r.remaining();
+        Some((handle, value))
+    }
+}
+
+/// Iterator over the values of a Read Multiple Variable Length Response.
+///
+/// Each tuple is encoded as a 2 byte length and the value. The length is the one of the whole value, but
+/// the last value may be truncated to fit the ATT MTU, in which case only the part received is returned.
+#[derive(Debug, Clone)]
+pub struct LengthValueTuples<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> LengthValueTuples<'d> {
+    /// Iterate over an encoded Length Value Tuple List.
+    pub fn new(data: &'d [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'d> Iterator for LengthValueTuples<'d> {
+    type Item = &'d [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut r = ReadCursor::new(self.data);
+        let len: u16 = r.read().ok()?;
+        let rest = r.remaining();
+        let (value, rest) = rest.split_at((len as usize).min(rest.len()));
+        self.data = rest;
+        Some(value)
+    }
 }
 
 /// ATT Protocol Data Unit (PDU)
@@ -418,6 +526,32 @@ impl<'d> ReadByTypeIter<'d> {
     }
 }
 
+/// An Iterator-like type for iterating over the attribute groups in a Read By Group Type Response
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct ReadByGroupTypeIter<'d> {
+    item_len: usize,
+    cursor: ReadCursor<'d>,
+}
+
+impl<'d> ReadByGroupTypeIter<'d> {
+    /// Get the next attribute handle, end group handle and attribute value
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(u16, u16, &'d [u8]), crate::Error>> {
+        if self.item_len >= 4 && self.cursor.available() >= self.item_len {
+            let res = (|| {
+                let handle: u16 = self.cursor.read()?;
+                let end: u16 = self.cursor.read()?;
+                let value = self.cursor.slice(self.item_len - 4)?;
+                Ok((handle, end, value))
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Copy, Clone)]
 enum FindInformationUuidFormat {
@@ -487,7 +621,9 @@ impl<'d> AttServer<'d> {
 
     fn decode_with_opcode(opcode: u8, r: ReadCursor<'d>) -> Result<Self, codec::Error> {
         let decoded = match opcode {
-            ATT_HANDLE_VALUE_NTF | ATT_HANDLE_VALUE_IND => Self::Unsolicited(AttUns::decode_with_opcode(opcode, r)?),
+            ATT_HANDLE_VALUE_NTF | ATT_HANDLE_VALUE_IND | ATT_MULTIPLE_HANDLE_VALUE_NTF => {
+                Self::Unsolicited(AttUns::decode_with_opcode(opcode, r)?)
+            }
             _ => Self::Response(AttRsp::decode_with_opcode(opcode, r)?),
         };
         Ok(decoded)
@@ -503,8 +639,13 @@ impl<'d> AttRsp<'d> {
             Self::Error { .. } => 4,
             Self::Read { data } => data.len(),
             Self::ReadBlob { data } => data.len(),
+            Self::ReadMultiple { data } => data.len(),
+            Self::ReadMultipleVariable { tuples } => tuples.len(),
             Self::ReadByType { it } => it.cursor.len(),
+            Self::ReadByGroupType { it } => 1 + it.cursor.len(), // 1 for length byte
             Self::Write => 0,
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite => 0,
         }
     }
 
@@ -547,6 +688,16 @@ impl<'d> AttRsp<'d> {
                     w.append(item)?;
                 }
             }
+            Self::ReadByGroupType { it } => {
+                w.write(ATT_READ_BY_GROUP_TYPE_RSP)?;
+                w.write(it.item_len as u8)?;
+                let mut it = it.clone();
+                while let Some(Ok((handle, end, value))) = it.next() {
+                    w.write(handle)?;
+                    w.write(end)?;
+                    w.append(value)?;
+                }
+            }
             Self::Read { data } => {
                 w.write(ATT_READ_RSP)?;
                 w.append(data)?;
@@ -555,9 +706,26 @@ impl<'d> AttRsp<'d> {
                 w.write(ATT_READ_BLOB_RSP)?;
                 w.append(data)?;
             }
+            Self::ReadMultiple { data } => {
+                w.write(ATT_READ_MULTIPLE_RSP)?;
+                w.append(data)?;
+            }
+            Self::ReadMultipleVariable { tuples } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_RSP)?;
+                w.append(tuples)?;
+            }
             Self::Write => {
                 w.write(ATT_WRITE_RSP)?;
             }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_RSP)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite => {
+                w.write(ATT_EXECUTE_WRITE_RSP)?;
+            }
         }
         Ok(())
     }
@@ -585,6 +753,8 @@ impl<'d> AttRsp<'d> {
             }
             ATT_READ_RSP => Ok(Self::Read { data: r.remaining() }),
             ATT_READ_BLOB_RSP => Ok(Self::ReadBlob { data: r.remaining() }),
+            ATT_READ_MULTIPLE_RSP => Ok(Self::ReadMultiple { data: r.remaining() }),
+            ATT_READ_MULTIPLE_VARIABLE_RSP => Ok(Self::ReadMultipleVariable { tuples: r.remaining() }),
             ATT_READ_BY_TYPE_RSP => {
                 let item_len: u8 = r.read()?;
                 Ok(Self::ReadByType {
@@ -594,7 +764,26 @@ impl<'d> AttRsp<'d> {
                     },
                 })
             }
+            ATT_READ_BY_GROUP_TYPE_RSP => {
+                let item_len: u8 = r.read()?;
+                Ok(Self::ReadByGroupType {
+                    it: ReadByGroupTypeIter {
+                        item_len: item_len as usize,
+                        cursor: r,
+                    },
+                })
+            }
             ATT_WRITE_RSP => Ok(Self::Write),
+            ATT_PREPARE_WRITE_RSP => {
+                let handle = r.read()?;
+                let offset = r.read()?;
+                Ok(Self::PrepareWrite {
+                    handle,
+                    offset,
+                    value: r.remaining(),
+                })
+            }
+            ATT_EXECUTE_WRITE_RSP => Ok(Self::ExecuteWrite),
             _ => Err(codec::Error::InvalidValue),
         }
     }
@@ -605,6 +794,7 @@ impl<'d> AttUns<'d> {
         1 + match self {
             Self::Notify { data, .. } => 2 + data.len(),
             Self::Indicate { data, .. } => 2 + data.len(),
+            Self::NotifyMultiple { tuples } => tuples.len(),
         }
     }
 
@@ -621,6 +811,10 @@ impl<'d> AttUns<'d> {
                 w.write(*handle)?;
                 w.append(data)?;
             }
+            Self::NotifyMultiple { tuples } => {
+                w.write(ATT_MULTIPLE_HANDLE_VALUE_NTF)?;
+                w.append(tuples)?;
+            }
         }
         Ok(())
     }
@@ -641,6 +835,7 @@ impl<'d> AttUns<'d> {
                     data: r.remaining(),
                 })
             }
+            ATT_MULTIPLE_HANDLE_VALUE_NTF => Ok(Self::NotifyMultiple { tuples: r.remaining() }),
             _ => Err(codec::Error::InvalidValue),
         }
     }
@@ -694,7 +889,11 @@ impl<'d> AttReq<'d> {
             } => 4 + attribute_type.as_raw().len(),
             Self::Read { .. } => 2,
             Self::ReadBlob { .. } => 4, // handle (2 bytes) + offset (2 bytes)
+            Self::ReadMultiple { handles } => handles.len(),
+            Self::ReadMultipleVariable { handles } => handles.len(),
             Self::Write { handle, data } => 2 + data.len(),
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite { .. } => 1,
             _ => unimplemented!(),
         }
     }
@@ -744,11 +943,29 @@ impl<'d> AttReq<'d> {
                 w.write(*handle)?;
                 w.write(*offset)?;
             }
+            Self::ReadMultiple { handles } => {
+                w.write(ATT_READ_MULTIPLE_REQ)?;
+                w.append(handles)?;
+            }
+            Self::ReadMultipleVariable { handles } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_REQ)?;
+                w.append(handles)?;
+            }
             Self::Write { handle, data } => {
                 w.write(ATT_WRITE_REQ)?;
                 w.write(*handle)?;
                 w.append(data)?;
             }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_REQ)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite { flags } => {
+                w.write(ATT_EXECUTE_WRITE_REQ)?;
+                w.write(*flags)?;
+            }
             _ => unimplemented!(),
         }
         Ok(())
@@ -846,6 +1063,7 @@ impl<'d> AttReq<'d> {
                 Ok(Self::ExecuteWrite { flags })
             }
             ATT_READ_MULTIPLE_REQ => Ok(Self::ReadMultiple { handles: payload }),
+            ATT_READ_MULTIPLE_VARIABLE_REQ => Ok(Self::ReadMultipleVariable { handles: payload }),
             ATT_READ_BLOB_REQ => {
                 let handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
                 let offset = (payload[2] as u16) + ((payload[3] as u16) << 8);
@@ -959,6 +1177,15 @@ impl From<codec::Error> for AttErrorCode {
     }
 }
 
+impl From<FromGattError> for AttErrorCode {
+    fn from(e: FromGattError) -> Self {
+        match e {
+            FromGattError::InvalidLength => AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH,
+            FromGattError::InvalidCharacter | FromGattError::InvalidValue => AttErrorCode::VALUE_NOT_ALLOWED,
+        }
+    }
+}
+
 impl codec::Type for Att<'_> {
     fn size(&self) -> usize {
         Self::size(self)
@@ -976,3 +1203,128 @@ impl<'d> codec::Decode<'d> for Att<'d> {
         Self::decode(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_code_from_gatt_error() {
+        assert_eq!(
+            AttErrorCode::from(FromGattError::InvalidLength),
+            AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH
+        );
+        assert_eq!(
+            AttErrorCode::from(FromGattError::InvalidValue),
+            AttErrorCode::VALUE_NOT_ALLOWED
+        );
+        assert_eq!(AttErrorCode::application(0x80).map(|code| code.value), Some(0x80));
+        assert_eq!(AttErrorCode::application(0xa0), None);
+    }
+
+    #[test]
+    fn prepare_and_execute_write() {
+        let req = AttReq::PrepareWrite {
+            handle: 0x0003,
+            offset: 0x0012,
+            value: &[0xaa, 0xbb],
+        };
+        let mut buf = [0; 7];
+        assert_eq!(req.size(), buf.len());
+        unwrap!(req.encode(&mut buf));
+        assert_eq!(buf, [ATT_PREPARE_WRITE_REQ, 0x03, 0x00, 0x12, 0x00, 0xaa, 0xbb]);
+
+        let req = AttReq::ExecuteWrite { flags: 0x01 };
+        let mut buf = [0; 2];
+        assert_eq!(req.size(), buf.len());
+        unwrap!(req.encode(&mut buf));
+        assert_eq!(buf, [ATT_EXECUTE_WRITE_REQ, 0x01]);
+
+        let pdu = [ATT_PREPARE_WRITE_RSP, 0x03, 0x00, 0x12, 0x00, 0xaa, 0xbb];
+        let Ok(Att::Server(AttServer::Response(AttRsp::PrepareWrite { handle, offset, value }))) = Att::decode(&pdu)
+        else {
+            panic!("unexpected decode result");
+        };
+        assert_eq!((handle, offset, value), (0x0003, 0x0012, &[0xaa, 0xbb][..]));
+
+        let pdu = [ATT_EXECUTE_WRITE_RSP];
+        assert!(matches!(
+            Att::decode(&pdu),
+            Ok(Att::Server(AttServer::Response(AttRsp::ExecuteWrite)))
+        ));
+    }
+
+    #[test]
+    fn multiple_handle_value_notification() {
+        let pdu = [
+            ATT_MULTIPLE_HANDLE_VALUE_NTF,
+            0x03,
+            0x00,
+            0x01,
+            0x00,
+            0xaa,
+            0x05,
+            0x00,
+            0x02,
+            0x00,
+            0xbb,
+            0xcc,
+        ];
+        let Ok(Att::Server(AttServer::Unsolicited(AttUns::NotifyMultiple { tuples }))) = Att::decode(&pdu) else {
+            panic!("unexpected decode result");
+        };
+        let mut it = HandleValueTuples::new(tuples);
+        assert_eq!(it.next(), Some((3, &[0xaa][..])));
+        assert_eq!(it.next(), Some((5, &[0xbb, 0xcc][..])));
+        assert_eq!(it.next(), None);
+
+        // A truncated tuple ends iteration
+        let mut it = HandleValueTuples::new(&[0x03, 0x00, 0x04, 0x00, 0xaa]);
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn read_by_group_type_response() {
+        let pdu = [
+            ATT_READ_BY_GROUP_TYPE_RSP,
+            0x06,
+            0x01,
+            0x00,
+            0x05,
+            0x00,
+            0x00,
+            0x18,
+            0x06,
+            0x00,
+            0x09,
+            0x00,
+            0x0f,
+            0x18,
+        ];
+        let Ok(Att::Server(AttServer::Response(AttRsp::ReadByGroupType { mut it }))) = Att::decode(&pdu) else {
+            panic!("unexpected decode result");
+        };
+        assert_eq!(it.next().unwrap().unwrap(), (1, 5, &[0x00, 0x18][..]));
+        assert_eq!(it.next().unwrap().unwrap(), (6, 9, &[0x0f, 0x18][..]));
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn read_multiple_variable() {
+        let pdu = [ATT_READ_MULTIPLE_VARIABLE_REQ, 0x03, 0x00, 0x05, 0x00];
+        let Ok(Att::Client(AttClient::Request(AttReq::ReadMultipleVariable { handles }))) = Att::decode(&pdu) else {
+            panic!("unexpected decode result");
+        };
+        assert_eq!(handles, &[0x03, 0x00, 0x05, 0x00]);
+
+        // The last value is truncated
+        let pdu = [ATT_READ_MULTIPLE_VARIABLE_RSP, 0x01, 0x00, 0xaa, 0x03, 0x00, 0xbb, 0xcc];
+        let Ok(Att::Server(AttServer::Response(AttRsp::ReadMultipleVariable { tuples }))) = Att::decode(&pdu) else {
+            panic!("unexpected decode result");
+        };
+        let mut it = LengthValueTuples::new(tuples);
+        assert_eq!(it.next(), Some(&[0xaa][..]));
+        assert_eq!(it.next(), Some(&[0xbb, 0xcc][..]));
+        assert_eq!(it.next(), None);
+    }
+}
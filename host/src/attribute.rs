@@ -3,8 +3,8 @@ use core::cell::RefCell;
 use core::fmt;
 use core::marker::PhantomData;
 
-use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
-use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
+use bt_hci::uuid::declarations::{CHARACTERISTIC, INCLUDE, PRIMARY_SERVICE, SECONDARY_SERVICE};
+use bt_hci::uuid::descriptors::{CHARACTERISTIC_EXTENDED_PROPERTIES, CLIENT_CHARACTERISTIC_CONFIGURATION};
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use heapless::Vec;
@@ -13,12 +13,16 @@ use crate::att::AttErrorCode;
 use crate::attribute_server::AttributeServer;
 use crate::cursor::{ReadCursor, WriteCursor};
 use crate::prelude::{AsGatt, FixedGattValue, FromGatt, GattConnection};
-use crate::types::gatt_traits::FromGattError;
+use crate::types::gatt_traits::{FromGattError, LeBytes};
 pub use crate::types::uuid::Uuid;
 use crate::{Error, PacketPool, MAX_INVALID_DATA_LEN};
 
+/// UUID of the Service Changed characteristic of the GATT service.
+pub(crate) const SERVICE_CHANGED: Uuid = Uuid::new_short(0x2a05);
+
 /// Characteristic properties
-#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum CharacteristicProp {
     /// Broadcast
@@ -50,13 +54,18 @@ pub struct Attribute<'a> {
 impl<'a> Attribute<'a> {
     const EMPTY: Option<Attribute<'a>> = None;
 
-    pub(crate) fn read(&self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+    pub(crate) fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         if !self.data.readable() {
             return Err(AttErrorCode::READ_NOT_PERMITTED);
         }
         self.data.read(offset, data)
     }
 
+    /// Read the value without checking the read permission, to send it in a notification or indication.
+    pub(crate) fn read_unchecked(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+        self.data.read_unchecked(offset, data)
+    }
+
     pub(crate) fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
         if !self.data.writable() {
             return Err(AttErrorCode::WRITE_NOT_PERMITTED);
@@ -64,6 +73,49 @@ impl<'a> Attribute<'a> {
 
         self.data.write(offset, data)
     }
+
+    /// Check that `len` bytes can be written at `offset`, without writing them.
+    pub(crate) fn check_write(&self, offset: usize, len: usize) -> Result<(), AttErrorCode> {
+        if !self.data.writable() {
+            return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+        }
+        match &self.data {
+            AttributeData::Data { value, .. } if offset > value.len() => Err(AttErrorCode::INVALID_OFFSET),
+            AttributeData::Data { value, .. } if offset + len > value.len() => {
+                Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+            }
+            AttributeData::Cccd { .. } if offset > 0 => Err(AttErrorCode::INVALID_OFFSET),
+            AttributeData::Cccd { .. } if len == 0 => Err(AttErrorCode::UNLIKELY_ERROR),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Copy the part of `value` starting at `offset` into `data`, as for a read or read blob request.
+///
+/// Reading at the end of the value gives no data, past it is an invalid offset.
+fn read_at(value: &[u8], offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+    if offset > value.len() {
+        return Err(AttErrorCode::INVALID_OFFSET);
+    }
+    let len = data.len().min(value.len() - offset);
+    data[..len].copy_from_slice(&value[offset..offset + len]);
+    Ok(len)
+}
+
+/// Produces and consumes the value of a characteristic on demand, instead of storing it in the attribute table.
+///
+/// Added with [`ServiceBuilder::add_characteristic_callback`]. The methods are called while the attribute table is
+/// locked, when a client reads or writes the value, so they must not access the table and should return quickly.
+pub trait AttributeCallback {
+    /// Read the part of the value starting at `offset` into `data`, returning the number of bytes read.
+    ///
+    /// `data` may be shorter than the rest of the value, in which case as much as fits is read and clients read the
+    /// rest at later offsets. Reading at the end of the value gives no data, past it is an invalid offset.
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode>;
+
+    /// Write `data` to the value at `offset`.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode>;
 }
 
 pub(crate) enum AttributeData<'d> {
@@ -85,23 +137,37 @@ pub(crate) enum AttributeData<'d> {
         handle: u16,
         uuid: Uuid,
     },
+    Include {
+        handle: u16,
+        end: u16,
+        uuid: Uuid,
+    },
     Cccd {
         notifications: bool,
         indications: bool,
     },
+    ExtendedProperties {
+        props: CharacteristicExtendedProps,
+    },
+    Callback {
+        props: CharacteristicProps,
+        callback: &'d mut dyn AttributeCallback,
+    },
 }
 
 impl AttributeData<'_> {
     pub(crate) fn readable(&self) -> bool {
         match self {
-            Self::Data { props, .. } => props.0 & (CharacteristicProp::Read as u8) != 0,
+            Self::Data { props, .. } | Self::ReadOnlyData { props, .. } | Self::Callback { props, .. } => {
+                props.0 & (CharacteristicProp::Read as u8) != 0
+            }
             _ => true,
         }
     }
 
     pub(crate) fn writable(&self) -> bool {
         match self {
-            Self::Data { props, .. } => {
+            Self::Data { props, .. } | Self::Callback { props, .. } => {
                 props.0
                     & (CharacteristicProp::Write as u8
                         | CharacteristicProp::WriteWithoutResponse as u8
@@ -116,91 +182,60 @@ impl AttributeData<'_> {
         }
     }
 
-    fn read(&self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+    fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         if !self.readable() {
             return Err(AttErrorCode::READ_NOT_PERMITTED);
         }
+        self.read_unchecked(offset, data)
+    }
+
+    fn read_unchecked(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         match self {
-            Self::ReadOnlyData { props, value } => {
-                if offset > value.len() {
-                    return Ok(0);
-                }
-                let len = data.len().min(value.len() - offset);
-                if len > 0 {
-                    data[..len].copy_from_slice(&value[offset..offset + len]);
-                }
-                Ok(len)
-            }
+            Self::ReadOnlyData { props, value } => read_at(value, offset, data),
             Self::Data {
                 props,
                 value,
                 variable_len,
                 len,
-            } => {
-                let value = &value[..*len as usize];
-                if offset > value.len() {
-                    return Ok(0);
-                }
-                let len = data.len().min(value.len() - offset);
-                if len > 0 {
-                    data[..len].copy_from_slice(&value[offset..offset + len]);
-                }
-                Ok(len)
-            }
-            Self::Service { uuid } => {
-                let val = uuid.as_raw();
-                if offset > val.len() {
-                    return Ok(0);
-                }
-                let len = data.len().min(val.len() - offset);
-                if len > 0 {
-                    data[..len].copy_from_slice(&val[offset..offset + len]);
-                }
-                Ok(len)
-            }
+            } => read_at(&value[..*len as usize], offset, data),
+            Self::Service { uuid } => read_at(uuid.as_raw(), offset, data),
             Self::Cccd {
                 notifications,
                 indications,
             } => {
-                if offset > 0 {
-                    return Err(AttErrorCode::INVALID_OFFSET);
-                }
-                if data.len() < 2 {
-                    return Err(AttErrorCode::UNLIKELY_ERROR);
-                }
-                let mut v = 0;
+                let mut v = 0u16;
                 if *notifications {
                     v |= 0x01;
                 }
-
                 if *indications {
                     v |= 0x02;
                 }
-                data[0] = v;
-                Ok(2)
+                read_at(&v.to_le_bytes(), offset, data)
             }
+            Self::ExtendedProperties { props } => read_at(&props.raw().to_le_bytes(), offset, data),
             Self::Declaration { props, handle, uuid } => {
-                let val = uuid.as_raw();
-                if offset > val.len() + 3 {
-                    return Ok(0);
-                }
-                let mut w = WriteCursor::new(data);
-                if offset == 0 {
-                    w.write(props.0)?;
-                    w.write(*handle)?;
-                } else if offset == 1 {
-                    w.write(*handle)?;
-                } else if offset == 2 {
-                    w.write(handle.to_le_bytes()[1])?;
-                }
-
-                let to_write = w.available().min(val.len());
-
-                if to_write > 0 {
-                    w.append(&val[..to_write])?;
-                }
-                Ok(w.len())
+                let uuid = uuid.as_raw();
+                let mut val = [0; 19];
+                val[0] = props.0;
+                val[1..3].copy_from_slice(&handle.to_le_bytes());
+                val[3..3 + uuid.len()].copy_from_slice(uuid);
+                read_at(&val[..3 + uuid.len()], offset, data)
             }
+            Self::Include { handle, end, uuid } => {
+                let mut val = [0; 6];
+                val[0..2].copy_from_slice(&handle.to_le_bytes());
+                val[2..4].copy_from_slice(&end.to_le_bytes());
+                // The service UUID is only part of the value if it is a 16-bit UUID.
+                let uuid = uuid.as_raw();
+                let len = if uuid.len() == 2 {
+                    val[4..6].copy_from_slice(uuid);
+                    6
+                } else {
+                    4
+                };
+                read_at(&val[..len], offset, data)
+            }
+            Self::Callback { callback, .. } => callback.read(offset, data),
         }
     }
 
@@ -242,6 +277,12 @@ impl AttributeData<'_> {
                 *indications = data[0] & 0x02 != 0;
                 Ok(())
             }
+            Self::Callback { callback, .. } => {
+                if !writable {
+                    return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                }
+                callback.write(offset, data)
+            }
             _ => Err(AttErrorCode::WRITE_NOT_PERMITTED),
         }
     }
@@ -289,16 +330,30 @@ impl<'a> Attribute<'a> {
 /// A table of attributes.
 pub struct AttributeTable<'d, M: RawMutex, const MAX: usize> {
     inner: Mutex<M, RefCell<InnerTable<'d, MAX>>>,
-    handle: u16,
 }
 
 pub(crate) struct InnerTable<'d, const MAX: usize> {
     attributes: Vec<Attribute<'d>, MAX>,
+    /// Handle of the next attribute.
+    handle: u16,
+    /// Whether attributes that don't fit are dropped instead of panicking, see `try_add_service`.
+    fallible: bool,
+    /// Whether an attribute was dropped because the table was full.
+    overflow: bool,
 }
 
 impl<'d, const MAX: usize> InnerTable<'d, MAX> {
-    fn push(&mut self, attribute: Attribute<'d>) {
-        self.attributes.push(attribute).unwrap();
+    fn push(&mut self, mut attribute: Attribute<'d>) -> u16 {
+        let handle = self.handle;
+        attribute.handle = handle;
+        if self.attributes.push(attribute).is_err() {
+            if !self.fallible {
+                panic!("attribute table is full");
+            }
+            self.overflow = true;
+        }
+        self.handle += 1;
+        handle
     }
 }
 
@@ -312,15 +367,19 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
     /// Create a new GATT table.
     pub fn new() -> Self {
         Self {
-            handle: 1,
-            inner: Mutex::new(RefCell::new(InnerTable { attributes: Vec::new() })),
+            inner: Mutex::new(RefCell::new(InnerTable {
+                attributes: Vec::new(),
+                handle: 1,
+                fallible: false,
+                overflow: false,
+            })),
         }
     }
 
-    pub(crate) fn with_inner<F: Fn(&mut InnerTable<'d, MAX>)>(&self, f: F) {
+    pub(crate) fn with_inner<F: FnOnce(&mut InnerTable<'d, MAX>) -> R, R>(&self, f: F) -> R {
         self.inner.lock(|inner| {
             let mut table = inner.borrow_mut();
-            f(&mut table);
+            f(&mut table)
         })
     }
 
@@ -335,65 +394,155 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         })
     }
 
-    fn push(&mut self, mut attribute: Attribute<'d>) -> u16 {
-        let handle = self.handle;
-        attribute.handle = handle;
-        self.inner.lock(|inner| {
-            let mut inner = inner.borrow_mut();
-            inner.push(attribute);
-        });
-        self.handle += 1;
-        handle
+    fn push(&self, attribute: Attribute<'d>) -> u16 {
+        self.with_inner(|inner| inner.push(attribute))
+    }
+
+    fn next_handle(&self) -> u16 {
+        self.with_inner(|inner| inner.handle)
+    }
+
+    fn skip_to(&self, handle: u16) -> Result<(), Error> {
+        self.with_inner(|inner| {
+            if handle < inner.handle {
+                return Err(Error::InvalidValue);
+            }
+            inner.handle = handle;
+            Ok(())
+        })
+    }
+
+    /// Make the next service start at `handle`.
+    ///
+    /// Fixing the handles of a service keeps them the same when services before it are added or grow, so clients
+    /// which cache handles keep working. Fails with [`Error::InvalidValue`] if `handle` is already taken or below
+    /// a taken handle.
+    pub fn set_next_handle(&mut self, handle: u16) -> Result<(), Error> {
+        self.skip_to(handle)
     }
 
     /// Add a service to the attribute table (group of characteristics)
     pub fn add_service(&mut self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
-        let len = self.inner.lock(|i| i.borrow().attributes.len());
-        let handle = self.handle;
-        self.push(Attribute {
-            uuid: PRIMARY_SERVICE.into(),
+        self.start_service(service)
+    }
+
+    /// Add a secondary service to the attribute table.
+    ///
+    /// Secondary services are not discovered as primary services, they are only reachable from the services which
+    /// include them with [`ServiceBuilder::include_service`].
+    pub fn add_secondary_service(&mut self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
+        self.start_service_of_type(service, SECONDARY_SERVICE.into())
+    }
+
+    fn start_service(&self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
+        self.start_service_of_type(service, PRIMARY_SERVICE.into())
+    }
+
+    fn start_service_of_type(&self, service: Service, service_type: Uuid) -> ServiceBuilder<'_, 'd, M, MAX> {
+        let handle = self.push(Attribute {
+            uuid: service_type,
             handle: 0,
             last_handle_in_group: 0,
             data: AttributeData::Service { uuid: service.uuid },
         });
-        ServiceBuilder {
-            handle,
-            start: len,
-            table: self,
-        }
+        ServiceBuilder { handle, table: self }
+    }
+
+    /// Add a service to a table that may already be in use, returning the first and last handle of the service.
+    ///
+    /// Unlike [`add_service`](Self::add_service), running out of space is not a panic: the partially added
+    /// service is removed and [`Error::InsufficientSpace`] is returned.
+    pub(crate) fn try_add_service<F>(&self, service: Service, build: F) -> Result<(u16, u16), Error>
+    where
+        F: FnOnce(&mut ServiceBuilder<'_, 'd, M, MAX>),
+    {
+        self.with_inner(|inner| {
+            inner.fallible = true;
+            inner.overflow = false;
+        });
+        let start = {
+            let mut builder = self.start_service(service);
+            build(&mut builder);
+            builder.handle
+        };
+        self.with_inner(|inner| {
+            inner.fallible = false;
+            if inner.overflow {
+                inner.attributes.retain(|att| att.handle < start);
+                return Err(Error::InsufficientSpace);
+            }
+            let end = inner
+                .attributes
+                .iter()
+                .find(|att| att.handle == start)
+                .map(|att| att.last_handle_in_group)
+                .ok_or(Error::NotFound)?;
+            Ok((start, end))
+        })
+    }
+
+    /// Remove the service declared at `handle` with all its attributes, returning its first and last handle.
+    pub(crate) fn remove_service(&self, handle: u16) -> Result<(u16, u16), Error> {
+        self.with_inner(|inner| {
+            let end = inner
+                .attributes
+                .iter()
+                .find(|att| att.handle == handle && matches!(att.data, AttributeData::Service { .. }))
+                .map(|att| att.last_handle_in_group)
+                .ok_or(Error::NotFound)?;
+            inner.attributes.retain(|att| att.handle < handle || att.handle > end);
+            Ok((handle, end))
+        })
     }
 
     pub(crate) fn set_raw(&self, attribute: u16, input: &[u8]) -> Result<(), Error> {
-        self.iterate(|mut it| {
-            while let Some(att) = it.next() {
-                if att.handle == attribute {
-                    if let AttributeData::Data {
-                        props: _,
-                        value,
-                        variable_len,
-                        len,
-                    } = &mut att.data
-                    {
-                        let expected_len = value.len();
-                        let actual_len = input.len();
+        self.set_raw_all(&[(attribute, input)])
+    }
+
+    /// Set the values of several attributes, given as pairs of value handle and value.
+    ///
+    /// The values are written while holding the table lock, and only once all of them have been checked, so
+    /// either every value is updated or none is, and readers never observe a partial update.
+    pub(crate) fn set_raw_all(&self, values: &[(u16, &[u8])]) -> Result<(), Error> {
+        self.inner.lock(|inner| {
+            let mut table = inner.borrow_mut();
+            for (handle, input) in values {
+                let att = table
+                    .attributes
+                    .iter()
+                    .find(|att| {
+                        att.handle == *handle
+                            && matches!(att.data, AttributeData::Data { .. } | AttributeData::Callback { .. })
+                    })
+                    .ok_or(Error::NotFound)?;
+                if let AttributeData::Data {
+                    value, variable_len, ..
+                } = &att.data
+                {
+                    let expected_len = value.len();
+                    let actual_len = input.len();
+                    if expected_len != actual_len && !(*variable_len && actual_len <= expected_len) {
+                        return Err(Error::UnexpectedDataLength {
+                            expected: expected_len,
+                            actual: actual_len,
+                        });
+                    }
+                }
+            }
 
-                        if expected_len == actual_len {
+            for (handle, input) in values {
+                for att in table.attributes.iter_mut().filter(|att| att.handle == *handle) {
+                    if let AttributeData::Data { value, len, .. } = &mut att.data {
+                        if value.len() == input.len() {
                             value.copy_from_slice(input);
-                            return Ok(());
-                        } else if *variable_len && actual_len <= expected_len {
+                        } else {
                             value[..input.len()].copy_from_slice(input);
                             *len = input.len() as u16;
-                            return Ok(());
-                        } else {
-                            return Err(Error::UnexpectedDataLength {
-                                expected: expected_len,
-                                actual: actual_len,
-                            });
                         }
                     }
                 }
             }
-            Err(Error::NotFound)
+            Ok(())
         })
     }
 
@@ -449,34 +598,30 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
     /// If no characteristic corresponding to the given value handle was found, returns an error
     pub fn find_characteristic_by_value_handle<T: AsGatt>(&self, handle: u16) -> Result<Characteristic<T>, Error> {
         self.iterate(|mut it| {
+            let mut props = CharacteristicProps(0);
             while let Some(att) = it.next() {
                 if att.handle == handle {
-                    // If next is CCCD
-                    if let Some(next) = it.next() {
-                        if let AttributeData::Cccd {
-                            notifications: _,
-                            indications: _,
-                        } = &next.data
-                        {
-                            return Ok(Characteristic {
-                                handle,
-                                cccd_handle: Some(next.handle),
-                                phantom: PhantomData,
-                            });
-                        } else {
-                            return Ok(Characteristic {
-                                handle,
-                                cccd_handle: None,
-                                phantom: PhantomData,
-                            });
+                    // Descriptors follow the value until the next declaration
+                    let mut cccd_handle = None;
+                    let mut extended_props = None;
+                    while let Some(att) = it.next() {
+                        match &att.data {
+                            AttributeData::Cccd { .. } => cccd_handle = Some(att.handle),
+                            AttributeData::ExtendedProperties { props } => extended_props = Some(*props),
+                            AttributeData::Declaration { .. } | AttributeData::Service { .. } => break,
+                            _ => {}
                         }
-                    } else {
-                        return Ok(Characteristic {
-                            handle,
-                            cccd_handle: None,
-                            phantom: PhantomData,
-                        });
                     }
+                    return Ok(Characteristic {
+                        handle,
+                        cccd_handle,
+                        props,
+                        extended_props,
+                        phantom: PhantomData,
+                    });
+                }
+                if let AttributeData::Declaration { props: p, .. } = &att.data {
+                    props = *p;
                 }
             }
             Err(Error::NotFound)
@@ -504,8 +649,7 @@ impl<T: AsGatt> AttributeHandle for Characteristic<T> {
 /// Builder for constructing GATT service definitions.
 pub struct ServiceBuilder<'r, 'd, M: RawMutex, const MAX: usize> {
     handle: u16,
-    start: usize,
-    table: &'r mut AttributeTable<'d, M, MAX>,
+    table: &'r AttributeTable<'d, M, MAX>,
 }
 
 impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
@@ -516,8 +660,8 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
         data: AttributeData<'d>,
     ) -> CharacteristicBuilder<'_, 'd, T, M, MAX> {
         // First the characteristic declaration
-        let next = self.table.handle + 1;
-        let cccd = self.table.handle + 2;
+        let next = self.table.next_handle() + 1;
+        let cccd = self.table.next_handle() + 2;
         self.table.push(Attribute {
             uuid: CHARACTERISTIC.into(),
             handle: 0,
@@ -557,6 +701,8 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
             handle: Characteristic {
                 handle: next,
                 cccd_handle,
+                props,
+                extended_props: None,
                 phantom: PhantomData,
             },
             table: self.table,
@@ -605,6 +751,82 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
         )
     }
 
+    /// Add a characteristic to this service whose value is read from and written to `callback` on demand.
+    ///
+    /// The value is not stored in the table: setting it, e.g. to notify it, only sends it to clients, and getting it
+    /// from the table fails with [`Error::NotFound`].
+    pub fn add_characteristic_callback<T: AsGatt, U: Into<Uuid>>(
+        &mut self,
+        uuid: U,
+        props: &[CharacteristicProp],
+        callback: &'d mut dyn AttributeCallback,
+    ) -> CharacteristicBuilder<'_, 'd, T, M, MAX> {
+        let props = props.into();
+        self.add_characteristic_internal(uuid.into(), props, AttributeData::Callback { props, callback })
+    }
+
+    /// Add a read-only characteristic backed by borrowed bytes.
+    pub(crate) fn add_characteristic_ro_bytes<T: AsGatt, U: Into<Uuid>>(
+        &mut self,
+        uuid: U,
+        value: &'d [u8],
+    ) -> CharacteristicBuilder<'_, 'd, T, M, MAX> {
+        let props = [CharacteristicProp::Read].into();
+        self.add_characteristic_internal(uuid.into(), props, AttributeData::ReadOnlyData { props, value })
+    }
+
+    /// Make the next characteristic of this service start at `handle`, with its value at the handle after it.
+    ///
+    /// Fails with [`Error::InvalidValue`] if `handle` is already taken or below a taken handle.
+    pub fn set_next_handle(&mut self, handle: u16) -> Result<(), Error> {
+        self.table.skip_to(handle)
+    }
+
+    /// Include the service declared at `handle`, which must already be in the table, in this service.
+    ///
+    /// Includes must be added before any characteristic of this service. Returns the handle of the include
+    /// definition, or [`Error::NotFound`] if there is no service at `handle`.
+    pub fn include_service(&mut self, handle: u16) -> Result<u16, Error> {
+        let start = self.handle;
+        let (uuid, end) = self.table.with_inner(|inner| {
+            if inner
+                .attributes
+                .iter()
+                .any(|att| att.handle > start && !matches!(att.data, AttributeData::Include { .. }))
+            {
+                return Err(Error::InvalidValue);
+            }
+            inner
+                .attributes
+                .iter()
+                .find_map(|att| match &att.data {
+                    AttributeData::Service { uuid } if att.handle == handle => {
+                        Some((uuid.clone(), att.last_handle_in_group))
+                    }
+                    _ => None,
+                })
+                .ok_or(Error::NotFound)
+        })?;
+        Ok(self.table.push(Attribute {
+            uuid: INCLUDE.into(),
+            handle: 0,
+            last_handle_in_group: 0,
+            data: AttributeData::Include { handle, end, uuid },
+        }))
+    }
+
+    /// Add the Service Changed characteristic of the GATT service.
+    ///
+    /// Its value is only ever indicated, so it is not readable.
+    pub(crate) fn add_service_changed(&mut self) -> CharacteristicBuilder<'_, 'd, [u8; 4], M, MAX> {
+        let props = [CharacteristicProp::Indicate].into();
+        self.add_characteristic_internal(
+            SERVICE_CHANGED,
+            props,
+            AttributeData::ReadOnlyData { props, value: &[0; 4] },
+        )
+    }
+
     /// Finish construction of the service and return a handle.
     pub fn build(self) -> u16 {
         self.handle
@@ -613,15 +835,16 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
 
 impl<M: RawMutex, const MAX: usize> Drop for ServiceBuilder<'_, '_, M, MAX> {
     fn drop(&mut self) {
-        let last_handle = self.table.handle;
+        let start = self.handle;
         self.table.with_inner(|inner| {
-            for item in inner.attributes[self.start..].iter_mut() {
+            let last_handle = inner.handle;
+            for item in inner.attributes.iter_mut().filter(|item| item.handle >= start) {
                 item.last_handle_in_group = last_handle;
             }
-        });
 
-        // Jump to next 16-aligned
-        self.table.handle = self.table.handle + (0x10 - (self.table.handle % 0x10));
+            // Jump to next 16-aligned
+            inner.handle += 0x10 - (inner.handle % 0x10);
+        });
     }
 }
 
@@ -633,6 +856,10 @@ pub struct Characteristic<T: AsGatt> {
     pub cccd_handle: Option<u16>,
     /// Handle value assigned to this characteristic when it is added to the Gatt Attribute Table
     pub handle: u16,
+    /// Properties from the characteristic declaration
+    pub props: CharacteristicProps,
+    /// Value of the Characteristic Extended Properties descriptor (if any)
+    pub extended_props: Option<CharacteristicExtendedProps>,
     pub(crate) phantom: PhantomData<T>,
 }
 
@@ -654,19 +881,79 @@ impl<T: FromGatt> Characteristic<T> {
             return Ok(());
         }
 
-        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
-        let mut w = WriteCursor::new(tx.as_mut());
-        let (mut header, mut data) = w.split(4)?;
-        data.write(crate::att::ATT_HANDLE_VALUE_NTF)?;
-        data.write(self.handle)?;
-        data.append(value)?;
+        let pdu = crate::gatt::value_pdu::<P>(crate::att::ATT_HANDLE_VALUE_NTF, self.handle, value)?;
+        connection.send(pdu).await;
+        Ok(())
+    }
 
-        header.write(data.len() as u16)?;
-        header.write(4_u16)?;
-        let total = header.len() + data.len();
+    /// Write a value to a characteristic, and notify a connection with the new value without waiting.
+    ///
+    /// Behaves like [`notify`](Self::notify), but returns [`Error::Busy`] instead of waiting when no TX buffer
+    /// is available or the outbound queue is full, e.g. because the controller is out of credits. The value of
+    /// the characteristic is updated even if the notification is not sent.
+    pub fn try_notify<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &T) -> Result<(), Error> {
+        let value = value.as_gatt();
+        let server = connection.server;
+        server.set(self.handle, value)?;
 
-        let pdu = crate::pdu::Pdu::new(tx, total);
-        connection.send(pdu).await;
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        let connection = connection.raw();
+        if !server.should_notify(connection, cccd_handle) {
+            return Ok(());
+        }
+
+        let pdu = match crate::gatt::value_pdu::<P>(crate::att::ATT_HANDLE_VALUE_NTF, self.handle, value) {
+            Err(Error::OutOfMemory) => return Err(Error::Busy),
+            result => result?,
+        };
+        connection.try_send(pdu).map_err(|_| Error::Busy)
+    }
+
+    /// Write a value to a characteristic, and indicate the new value to a connection.
+    ///
+    /// Completes once the client confirms the indication, or fails with [`Error::Timeout`] if it does not
+    /// within the ATT transaction timeout of 30 seconds, after which no more indications are sent on the
+    /// connection, or with [`Error::Disconnected`] if the connection is lost first. Confirmations are received
+    /// by [`GattConnection::next`], which must keep running while this is awaited. Only one indication is
+    /// outstanding at a time; concurrent calls wait for their turn.
+    ///
+    /// If the provided connection has not subscribed for indications of this characteristic, it will not be
+    /// indicated.
+    ///
+    /// If the characteristic does not support indications, an error is returned.
+    pub async fn indicate<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = value.as_gatt();
+        let server = connection.server;
+        server.set(self.handle, value)?;
+
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        if !server.should_indicate(connection.raw(), cccd_handle) {
+            return Ok(());
+        }
+
+        connection.indicate_confirmed(self.handle, value).await
+    }
+
+    /// Write a value to a characteristic, and notify every connected client subscribed to it.
+    ///
+    /// The notifications are queued for each subscribed client and sent by its [`GattConnection::next`], which
+    /// must keep running for them to go out. Each carries the value of the characteristic at the time it is
+    /// sent, truncated to the ATT MTU of that connection. If a client's queue is full the notification for it
+    /// is dropped.
+    ///
+    /// If the characteristic does not support notifications, an error is returned.
+    pub fn notify_all<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        value: &T,
+    ) -> Result<(), Error> {
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        server.table().set_raw(self.handle, value.as_gatt())?;
+        server.queue_notification(self.handle, cccd_handle);
         Ok(())
     }
 
@@ -699,6 +986,8 @@ impl<T: FromGatt> Characteristic<T> {
 }
 
 /// Attribute handle for a characteristic's properties
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CharacteristicPropertiesHandle(u16);
 
 impl AttributeHandle for CharacteristicPropertiesHandle {
@@ -712,7 +1001,7 @@ impl AttributeHandle for CharacteristicPropertiesHandle {
 /// Builder for characteristics.
 pub struct CharacteristicBuilder<'r, 'd, T: AsGatt, M: RawMutex, const MAX: usize> {
     handle: Characteristic<T>,
-    table: &'r mut AttributeTable<'d, M, MAX>,
+    table: &'r AttributeTable<'d, M, MAX>,
 }
 
 impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd, T, M, MAX> {
@@ -722,8 +1011,7 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
         props: CharacteristicProps,
         data: AttributeData<'d>,
     ) -> Descriptor<DT> {
-        let handle = self.table.handle;
-        self.table.push(Attribute {
+        let handle = self.table.push(Attribute {
             uuid,
             handle: 0,
             last_handle_in_group: 0,
@@ -763,6 +1051,33 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
         self.add_descriptor_internal(uuid.into(), props, AttributeData::ReadOnlyData { props, value: data })
     }
 
+    /// Add a Characteristic Extended Properties descriptor for this characteristic.
+    ///
+    /// The [`CharacteristicProp::Extended`] bit is set in the characteristic declaration so that clients
+    /// look for the descriptor, e.g. before using the Reliable Write procedure.
+    pub fn add_extended_properties(
+        &mut self,
+        extended_props: CharacteristicExtendedProps,
+    ) -> Descriptor<CharacteristicExtendedProps> {
+        let declaration = self.handle.handle - 1;
+        self.table.with_inner(|inner| {
+            for att in inner.attributes.iter_mut() {
+                if att.handle == declaration {
+                    if let AttributeData::Declaration { props, .. } = &mut att.data {
+                        props.0 |= CharacteristicProp::Extended as u8;
+                    }
+                }
+            }
+        });
+        self.handle.props.0 |= CharacteristicProp::Extended as u8;
+        self.handle.extended_props = Some(extended_props);
+        self.add_descriptor_internal(
+            CHARACTERISTIC_EXTENDED_PROPERTIES.into(),
+            [CharacteristicProp::Read].into(),
+            AttributeData::ExtendedProperties { props: extended_props },
+        )
+    }
+
     /// Return the built characteristic.
     pub fn build(self) -> Characteristic<T> {
         self.handle
@@ -802,9 +1117,16 @@ impl<'d> AttributeIterator<'_, 'd> {
             None
         }
     }
+
+    /// Start over from the first attribute.
+    pub(crate) fn rewind(&mut self) {
+        self.pos = 0;
+    }
 }
 
 /// A GATT service.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Service {
     /// UUID of the service.
     pub uuid: Uuid,
@@ -818,7 +1140,8 @@ impl Service {
 }
 
 /// Properties of a characteristic.
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CharacteristicProps(u8);
 
 impl<'a> From<&'a [CharacteristicProp]> for CharacteristicProps {
@@ -851,6 +1174,78 @@ impl CharacteristicProps {
         }
         false
     }
+
+    /// Check if a property is set.
+    pub fn contains(&self, prop: CharacteristicProp) -> bool {
+        (prop as u8) & self.0 != 0
+    }
+
+    /// Create properties from the raw bitfield of a characteristic declaration.
+    pub const fn from_raw(value: u8) -> Self {
+        Self(value)
+    }
+
+    /// The raw properties bitfield.
+    pub fn raw(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Value of a Characteristic Extended Properties descriptor.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CharacteristicExtendedProps(u16);
+
+impl CharacteristicExtendedProps {
+    const RELIABLE_WRITE: u16 = 0x0001;
+    const WRITABLE_AUXILIARIES: u16 = 0x0002;
+
+    /// Create extended properties from the Reliable Write and Writable Auxiliaries flags.
+    pub const fn new(reliable_write: bool, writable_auxiliaries: bool) -> Self {
+        let mut value = 0;
+        if reliable_write {
+            value |= Self::RELIABLE_WRITE;
+        }
+        if writable_auxiliaries {
+            value |= Self::WRITABLE_AUXILIARIES;
+        }
+        Self(value)
+    }
+
+    /// Create extended properties from the raw descriptor value.
+    pub const fn from_raw(value: u16) -> Self {
+        Self(value)
+    }
+
+    /// The raw descriptor value.
+    pub const fn raw(&self) -> u16 {
+        self.0
+    }
+
+    /// Whether the characteristic value supports the Reliable Write procedure.
+    pub const fn reliable_write(&self) -> bool {
+        self.0 & Self::RELIABLE_WRITE != 0
+    }
+
+    /// Whether the Characteristic User Description descriptor is writable.
+    pub const fn writable_auxiliaries(&self) -> bool {
+        self.0 & Self::WRITABLE_AUXILIARIES != 0
+    }
+}
+
+impl FixedGattValue for CharacteristicExtendedProps {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        if data.len() != Self::SIZE {
+            return Err(FromGattError::InvalidLength);
+        }
+        Ok(Self(u16::from_le_bytes([data[0], data[1]])))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        FixedGattValue::as_gatt(&self.0)
+    }
 }
 
 impl FixedGattValue for CharacteristicProps {
@@ -869,6 +1264,212 @@ impl FixedGattValue for CharacteristicProps {
     }
 }
 
+/// Value of a Characteristic Presentation Format descriptor.
+///
+/// The value is kept encoded as on the air, so that it can be stored in the attribute table as is.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PresentationFormat([u8; 7]);
+
+impl PresentationFormat {
+    /// Boolean format.
+    pub const FORMAT_BOOLEAN: u8 = 0x01;
+    /// Unsigned 8-bit integer format.
+    pub const FORMAT_UINT8: u8 = 0x04;
+    /// Unsigned 16-bit integer format.
+    pub const FORMAT_UINT16: u8 = 0x06;
+    /// Unsigned 32-bit integer format.
+    pub const FORMAT_UINT32: u8 = 0x08;
+    /// Signed 8-bit integer format.
+    pub const FORMAT_SINT8: u8 = 0x0c;
+    /// Signed 16-bit integer format.
+    pub const FORMAT_SINT16: u8 = 0x0e;
+    /// Signed 32-bit integer format.
+    pub const FORMAT_SINT32: u8 = 0x10;
+    /// IEEE-754 32-bit floating point format.
+    pub const FORMAT_FLOAT32: u8 = 0x14;
+    /// UTF-8 string format.
+    pub const FORMAT_UTF8: u8 = 0x19;
+    /// Opaque structure format.
+    pub const FORMAT_STRUCT: u8 = 0x1b;
+    /// The unitless unit.
+    pub const UNITLESS: u16 = 0x2700;
+    /// The Bluetooth SIG namespace.
+    pub const NAMESPACE_BLUETOOTH_SIG: u8 = 0x01;
+
+    /// Create a presentation format.
+    ///
+    /// `format` is for example [`PresentationFormat::FORMAT_UINT16`], `exponent` the base 10 exponent applied to
+    /// the value, `unit` a Bluetooth SIG unit UUID, `namespace` the namespace of `description`, which describes the
+    /// instance within the namespace, for example its index.
+    pub const fn new(format: u8, exponent: i8, unit: u16, namespace: u8, description: u16) -> Self {
+        let unit = unit.to_le_bytes();
+        let description = description.to_le_bytes();
+        Self([
+            format,
+            exponent as u8,
+            unit[0],
+            unit[1],
+            namespace,
+            description[0],
+            description[1],
+        ])
+    }
+
+    /// Format of the value.
+    pub const fn format(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Base 10 exponent applied to the value.
+    pub const fn exponent(&self) -> i8 {
+        self.0[1] as i8
+    }
+
+    /// Unit of the value, as a Bluetooth SIG unit UUID.
+    pub const fn unit(&self) -> u16 {
+        u16::from_le_bytes([self.0[2], self.0[3]])
+    }
+
+    /// Namespace of the description, `0x01` for Bluetooth SIG.
+    pub const fn namespace(&self) -> u8 {
+        self.0[4]
+    }
+
+    /// Description of the instance within the namespace.
+    pub const fn description(&self) -> u16 {
+        u16::from_le_bytes([self.0[5], self.0[6]])
+    }
+
+    /// Encode the descriptor value.
+    pub const fn to_bytes(self) -> [u8; 7] {
+        self.0
+    }
+}
+
+impl fmt::Debug for PresentationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PresentationFormat")
+            .field("format", &self.format())
+            .field("exponent", &self.exponent())
+            .field("unit", &self.unit())
+            .field("namespace", &self.namespace())
+            .field("description", &self.description())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PresentationFormat {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "PresentationFormat {{ format: {}, exponent: {}, unit: {}, namespace: {}, description: {} }}",
+            self.format(),
+            self.exponent(),
+            self.unit(),
+            self.namespace(),
+            self.description()
+        )
+    }
+}
+
+impl FixedGattValue for PresentationFormat {
+    const SIZE: usize = 7;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let bytes = data.try_into().map_err(|_| FromGattError::InvalidLength)?;
+        Ok(Self(bytes))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Largest encoded bound of a [`ValidRange`].
+const VALID_RANGE_BOUND_MAX: usize = 8;
+
+/// Value of a Valid Range descriptor: the inclusive bounds of a characteristic value.
+///
+/// The bounds are kept encoded as on the air, so that the value can be stored in the attribute table as is.
+#[derive(Clone, Copy)]
+pub struct ValidRange<T> {
+    bytes: [u8; 2 * VALID_RANGE_BOUND_MAX],
+    phantom: PhantomData<T>,
+}
+
+#[allow(private_bounds)]
+impl<T: LeBytes> ValidRange<T> {
+    /// Create a valid range from its inclusive bounds.
+    pub fn new(lower: T, upper: T) -> Self {
+        let size = core::mem::size_of::<T>();
+        let mut bytes = [0; 2 * VALID_RANGE_BOUND_MAX];
+        lower.write_le(&mut bytes[..size]);
+        upper.write_le(&mut bytes[size..]);
+        Self {
+            bytes,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Lowest valid value.
+    pub fn lower(&self) -> T {
+        T::read_le(&self.bytes[..core::mem::size_of::<T>()])
+    }
+
+    /// Highest valid value.
+    pub fn upper(&self) -> T {
+        let size = core::mem::size_of::<T>();
+        T::read_le(&self.bytes[size..2 * size])
+    }
+}
+
+#[allow(private_bounds)]
+impl<T: LeBytes + PartialEq> PartialEq for ValidRange<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.lower() == other.lower() && self.upper() == other.upper()
+    }
+}
+
+#[allow(private_bounds)]
+impl<T: LeBytes + fmt::Debug> fmt::Debug for ValidRange<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidRange")
+            .field("lower", &self.lower())
+            .field("upper", &self.upper())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+#[allow(private_bounds)]
+impl<T: LeBytes + defmt::Format> defmt::Format for ValidRange<T> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "ValidRange {{ lower: {}, upper: {} }}", self.lower(), self.upper())
+    }
+}
+
+#[allow(private_bounds)]
+impl<T: LeBytes> FixedGattValue for ValidRange<T> {
+    const SIZE: usize = 2 * core::mem::size_of::<T>();
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        if data.len() != Self::SIZE {
+            return Err(FromGattError::InvalidLength);
+        }
+        let mut bytes = [0; 2 * VALID_RANGE_BOUND_MAX];
+        bytes[..data.len()].copy_from_slice(data);
+        Ok(Self {
+            bytes,
+            phantom: PhantomData,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.bytes[..Self::SIZE]
+    }
+}
+
 /// A value of an attribute.
 pub struct AttributeValue<'d, M: RawMutex> {
     value: Mutex<M, &'d mut [u8]>,
@@ -877,7 +1478,8 @@ pub struct AttributeValue<'d, M: RawMutex> {
 impl<M: RawMutex> AttributeValue<'_, M> {}
 
 /// CCCD flag values.
-#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CCCDFlag {
     /// Notifications enabled.
     Notify = 0x1,
@@ -933,8 +1535,278 @@ impl CCCD {
         self.0 = if is_enabled { self.0 | mask } else { self.0 & !mask };
     }
 
+    /// Enable or disable indications
+    pub fn set_indicate(&mut self, is_enabled: bool) {
+        let mask: u16 = CCCDFlag::Indicate as u16;
+        self.0 = if is_enabled { self.0 | mask } else { self.0 & !mask };
+    }
+
     /// Check if notifications are enabled
     pub fn should_notify(&self) -> bool {
         (self.0 & (CCCDFlag::Notify as u16)) != 0
     }
+
+    /// Check if indications are enabled
+    pub fn should_indicate(&self) -> bool {
+        (self.0 & (CCCDFlag::Indicate as u16)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn characteristic_props_by_value_handle() {
+        let mut store = [0; 4];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let built = svc
+            .add_characteristic(
+                0x2a19u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                0u8,
+                &mut store,
+            )
+            .build();
+        svc.build();
+
+        let found: Characteristic<u8> = unwrap!(table.find_characteristic_by_value_handle(built.handle));
+        assert_eq!(found, built);
+        assert!(found.props.contains(CharacteristicProp::Notify));
+        assert!(!found.props.contains(CharacteristicProp::Write));
+        assert!(found.cccd_handle.is_some());
+    }
+
+    #[test]
+    fn standard_descriptors_little_endian() {
+        let format = PresentationFormat::new(PresentationFormat::FORMAT_SINT16, -2, 0x272f, 0x01, 0x0102);
+        assert_eq!(format.as_gatt(), &[0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x02, 0x01]);
+        assert_eq!(unwrap!(PresentationFormat::from_gatt(format.as_gatt())), format);
+        assert_eq!(format.unit(), 0x272f);
+        assert_eq!(format.description(), 0x0102);
+
+        let range = ValidRange::new(-2i16, 0x1234);
+        assert_eq!(range.as_gatt(), &[0xfe, 0xff, 0x34, 0x12]);
+        let decoded = unwrap!(ValidRange::<i16>::from_gatt(&[0xfe, 0xff, 0x34, 0x12]));
+        assert_eq!((decoded.lower(), decoded.upper()), (-2, 0x1234));
+        assert!(ValidRange::<u32>::from_gatt(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn characteristic_extended_properties() {
+        let mut store = [0; 4];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let mut builder = svc.add_characteristic(
+            0x2a19u16,
+            &[
+                CharacteristicProp::Read,
+                CharacteristicProp::Write,
+                CharacteristicProp::Notify,
+            ],
+            0u8,
+            &mut store,
+        );
+        let descriptor = builder.add_extended_properties(CharacteristicExtendedProps::new(true, false));
+        let built = builder.build();
+        svc.build();
+
+        assert!(built.props.contains(CharacteristicProp::Extended));
+        let found: Characteristic<u8> = unwrap!(table.find_characteristic_by_value_handle(built.handle));
+        assert_eq!(found, built);
+        assert!(found.cccd_handle.is_some());
+        assert!(unwrap!(found.extended_props).reliable_write());
+        assert!(!unwrap!(found.extended_props).writable_auxiliaries());
+
+        let mut buf = [0; 8];
+        table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == built.handle - 1 {
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 5);
+                    assert_eq!(
+                        buf[0] & CharacteristicProp::Extended as u8,
+                        CharacteristicProp::Extended as u8
+                    );
+                } else if att.handle == descriptor.handle {
+                    assert_eq!(att.uuid, CHARACTERISTIC_EXTENDED_PROPERTIES.into());
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 2);
+                    assert_eq!(&buf[..2], &[0x01, 0x00]);
+                    assert!(att.write(0, &[0x00, 0x00]).is_err());
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn set_values_all_or_nothing() {
+        let mut first = [0; 1];
+        let mut second = [0; 2];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let a = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Read], 0u8, &mut first)
+            .build();
+        let b = svc
+            .add_characteristic(0x2a1au16, &[CharacteristicProp::Read], 0u16, &mut second)
+            .build();
+        svc.build();
+
+        assert!(table.set_raw_all(&[(a.handle, &[1]), (b.handle, &[1, 2, 3])]).is_err());
+        assert_eq!(unwrap!(table.get(&a)), 0);
+        assert_eq!(unwrap!(table.get(&b)), 0);
+
+        unwrap!(table.set_raw_all(&[(a.handle, &[1]), (b.handle, &[2, 1])]));
+        assert_eq!(unwrap!(table.get(&a)), 1);
+        assert_eq!(unwrap!(table.get(&b)), 0x0102);
+    }
+
+    #[test]
+    fn read_long_value_at_offset() {
+        let value: [u8; 40] = core::array::from_fn(|i| i as u8);
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let built = svc.add_characteristic_ro(0x2a19u16, &value).build();
+        svc.build();
+
+        let mut buf = [0; 22];
+        table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == built.handle {
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 22);
+                    assert_eq!(buf, value[..22]);
+                    assert_eq!(unwrap!(att.read(22, &mut buf)), 18);
+                    assert_eq!(buf[..18], value[22..]);
+                    assert_eq!(unwrap!(att.read(40, &mut buf)), 0);
+                    assert_eq!(att.read(41, &mut buf), Err(AttErrorCode::INVALID_OFFSET));
+                } else if att.handle == built.handle - 1 {
+                    // The declaration is 5 bytes: properties, value handle and 16-bit UUID.
+                    assert_eq!(unwrap!(att.read(3, &mut buf)), 2);
+                    assert_eq!(buf[..2], [0x19, 0x2a]);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn callback_value() {
+        struct Counter(u32);
+
+        impl AttributeCallback for Counter {
+            fn read(&mut self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
+                self.0 += 1;
+                read_at(&self.0.to_le_bytes(), offset, data)
+            }
+
+            fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
+                if offset != 0 {
+                    return Err(AttErrorCode::INVALID_OFFSET);
+                }
+                self.0 = u32::from_gatt(data).map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+                Ok(())
+            }
+        }
+
+        let mut counter = Counter(0);
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let built = svc
+            .add_characteristic_callback::<u32, _>(
+                0x2a19u16,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::Notify,
+                ],
+                &mut counter,
+            )
+            .build();
+        svc.build();
+        assert!(built.cccd_handle.is_some());
+
+        let mut buf = [0; 4];
+        table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == built.handle {
+                    // Every read asks the callback for the current value.
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 4);
+                    assert_eq!(buf, 1u32.to_le_bytes());
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 4);
+                    assert_eq!(buf, 2u32.to_le_bytes());
+                    unwrap!(att.write(0, &10u32.to_le_bytes()));
+                    assert_eq!(att.write(1, &[0]), Err(AttErrorCode::INVALID_OFFSET));
+                    assert_eq!(unwrap!(att.read(2, &mut buf)), 2);
+                    assert_eq!(buf[..2], [0, 0]);
+                }
+            }
+        });
+
+        // The value can be set for notifications, but is not kept in the table.
+        unwrap!(table.set(&built, &5));
+        assert!(matches!(table.get(&built), Err(Error::NotFound)));
+        drop(table);
+        assert_eq!(counter.0, 11);
+    }
+
+    #[test]
+    fn included_service() {
+        let mut store = [0; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let battery = table.add_secondary_service(Service::new(0x180fu16)).build();
+        let custom = table
+            .add_secondary_service(Service::new(Uuid::new_long([1; 16])))
+            .build();
+
+        let mut svc = table.add_service(Service::new(0x1812u16));
+        let include = unwrap!(svc.include_service(battery));
+        let long_include = unwrap!(svc.include_service(custom));
+        assert!(matches!(svc.include_service(include), Err(Error::NotFound)));
+        svc.add_characteristic(0x2a4du16, &[CharacteristicProp::Read], 0u8, &mut store);
+        // Includes must come before the characteristics.
+        assert!(matches!(svc.include_service(battery), Err(Error::InvalidValue)));
+        svc.build();
+
+        let mut buf = [0; 8];
+        table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == battery {
+                    assert_eq!(att.uuid, SECONDARY_SERVICE.into());
+                } else if att.handle == include {
+                    assert_eq!(att.uuid, INCLUDE.into());
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 6);
+                    let [lo, hi] = battery.to_le_bytes();
+                    assert_eq!(buf[..2], [lo, hi]);
+                    assert_eq!(buf[4..6], [0x0f, 0x18]);
+                } else if att.handle == long_include {
+                    // 128-bit service UUIDs are left out of the include definition.
+                    assert_eq!(unwrap!(att.read(0, &mut buf)), 4);
+                    let [lo, hi] = custom.to_le_bytes();
+                    assert_eq!(buf[..2], [lo, hi]);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn fixed_handles() {
+        let mut store = [0; 2];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        unwrap!(table.set_next_handle(0x100));
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        unwrap!(svc.set_next_handle(0x110));
+        let built = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Read], 0u8, &mut store[..1])
+            .build();
+        assert!(matches!(svc.set_next_handle(0x111), Err(Error::InvalidValue)));
+        let start = svc.build();
+        assert_eq!(start, 0x100);
+        assert_eq!(built.handle, 0x111);
+
+        assert!(matches!(table.set_next_handle(0x100), Err(Error::InvalidValue)));
+        let next = table.add_service(Service::new(0x180au16)).build();
+        assert_eq!(next, 0x120);
+        assert_eq!(unwrap!(table.get(&built)), 0);
+    }
 }
@@ -1,28 +1,178 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::marker::PhantomData;
+use core::task::{Context, Poll};
 
+use bt_hci::param::BdAddr;
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::waitqueue::WakerRegistration;
 
 use crate::att::{self, AttClient, AttCmd, AttErrorCode, AttReq};
-use crate::attribute::{Attribute, AttributeData, AttributeTable, CCCD};
-use crate::cursor::WriteCursor;
-use crate::prelude::Connection;
+use crate::attribute::{
+    Attribute, AttributeData, AttributeTable, Characteristic, Service, ServiceBuilder, CCCD, SERVICE_CHANGED,
+};
+use crate::config::{GATT_SERVER_NOTIFICATION_QUEUE_SIZE, GATT_SERVER_PREPARE_QUEUE_SIZE};
+use crate::cursor::{ReadCursor, WriteCursor};
+use crate::gatt::{GattConnection, ServiceHandle};
+use crate::prelude::{Connection, SecurityLevel};
+use crate::types::gatt_traits::FromGatt;
 use crate::types::uuid::Uuid;
 use crate::{codec, Error, Identity, PacketPool};
 
+/// Version of the blob written by [`AttributeServer::export_cccd_state`].
+const CCCD_STATE_VERSION: u8 = 1;
+
 #[derive(Default)]
 struct Client {
     identity: Identity,
     is_connected: bool,
+    /// Handle range changed since the client was last told, see [`AttributeServer::add_service`].
+    service_changed: Option<(u16, u16)>,
+    waker: WakerRegistration,
+    /// Prepared writes awaiting execution, as a packed list of (handle, offset, length, value).
+    prepare_queue: PrepareQueue,
+    /// Value handles notified to all clients, waiting to be sent to this one.
+    notifications: heapless::Vec<u16, GATT_SERVER_NOTIFICATION_QUEUE_SIZE>,
 }
 
+type PrepareQueue = heapless::Vec<u8, GATT_SERVER_PREPARE_QUEUE_SIZE>;
+
+/// Size of the (handle, offset, length) header of a prepared write.
+const PREPARED_WRITE_HEADER: usize = 6;
+
 impl Client {
     fn set_identity(&mut self, identity: Identity) {
         self.identity = identity;
+        self.service_changed = None;
+        self.prepare_queue.clear();
+        self.notifications.clear();
+    }
+
+    fn prepare_write(&mut self, handle: u16, offset: u16, value: &[u8]) -> Result<(), AttErrorCode> {
+        let queue = &mut self.prepare_queue;
+        // Extend the last write if this continues it, as clients split long values in consecutive parts.
+        if let Some((start, last_handle, last_offset, last_len)) = last_prepared_write(queue) {
+            if last_handle == handle && usize::from(last_offset) + usize::from(last_len) == usize::from(offset) {
+                let len = u16::try_from(usize::from(last_len) + value.len())
+                    .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+                queue
+                    .extend_from_slice(value)
+                    .map_err(|_| AttErrorCode::PREPARE_QUEUE_FULL)?;
+                queue[start + 4..start + 6].copy_from_slice(&len.to_le_bytes());
+                return Ok(());
+            }
+        }
+        if queue.capacity() - queue.len() < PREPARED_WRITE_HEADER + value.len() {
+            return Err(AttErrorCode::PREPARE_QUEUE_FULL);
+        }
+        let _ = queue.extend_from_slice(&handle.to_le_bytes());
+        let _ = queue.extend_from_slice(&offset.to_le_bytes());
+        let _ = queue.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        let _ = queue.extend_from_slice(value);
+        Ok(())
+    }
+}
+
+/// Iterate over the (handle, offset, value) of the writes in a prepare queue.
+fn prepared_writes(queue: &[u8]) -> impl Iterator<Item = (u16, u16, &[u8])> {
+    let mut rest = queue;
+    core::iter::from_fn(move || {
+        if rest.len() < PREPARED_WRITE_HEADER {
+            return None;
+        }
+        let handle = u16::from_le_bytes([rest[0], rest[1]]);
+        let offset = u16::from_le_bytes([rest[2], rest[3]]);
+        let len = usize::from(u16::from_le_bytes([rest[4], rest[5]]));
+        let (value, next) = rest[PREPARED_WRITE_HEADER..].split_at(len);
+        rest = next;
+        Some((handle, offset, value))
+    })
+}
+
+/// Length of the whole value of an attribute, of which the first `read` bytes were read.
+fn value_len(att: &mut Attribute<'_>, read: usize) -> usize {
+    let mut len = read;
+    let mut rest = [0; 16];
+    loop {
+        match att.read_unchecked(len, &mut rest) {
+            Ok(0) | Err(_) => return len,
+            Ok(n) => len += n,
+        }
     }
 }
 
+/// Position, handle, offset and length of the last write in a prepare queue.
+fn last_prepared_write(queue: &[u8]) -> Option<(usize, u16, u16, u16)> {
+    let mut last = None;
+    let mut pos = 0;
+    for (handle, offset, value) in prepared_writes(queue) {
+        last = Some((pos, handle, offset, value.len() as u16));
+        pos += PREPARED_WRITE_HEADER + value.len();
+    }
+    last
+}
+
+/// Persistent storage for the CCCD values of bonded clients.
+///
+/// The GATT specification requires the client configuration of a bonded client to be kept across
+/// connections. Once a store is set with [`AttributeServer::set_cccd_store`], the server saves the CCCD
+/// values of a bonded client whenever the client writes one of them or completes bonding, and loads them
+/// back when the client reconnects. Clients without a bond are never saved. The values are keyed by the
+/// identity of the bond, so a client using resolvable private addresses finds them on every connection.
+///
+/// The methods are called while the attribute table is locked, so they must not access the server.
+pub trait CccdStore {
+    /// Save the CCCD values of the client with the given identity, replacing any saved before.
+    fn save(&self, identity: &Identity, cccds: &[(u16, CCCD)]);
+
+    /// Load the CCCD values saved for the client with the given identity into `cccds`, returning how many
+    /// were loaded.
+    fn load(&self, identity: &Identity, cccds: &mut [(u16, CCCD)]) -> usize;
+}
+
+/// Application-level access control for the attributes of a server.
+///
+/// Attribute permissions are fixed when the table is built. Once an authorizer is set with
+/// [`AttributeServer::set_authorizer`], it is also consulted before a client reads or writes a characteristic value
+/// or descriptor, so access can depend on application state such as a user having logged in. Service and
+/// characteristic declarations are always readable, so that clients can discover the table.
+///
+/// The method is called while the attribute table is locked, so it must not access the server.
+pub trait AttributeAuthorizer {
+    /// Decide whether a client may perform an access, returning the ATT error to reject it with otherwise.
+    ///
+    /// Typical errors are [`AttErrorCode::INSUFFICIENT_AUTHORISATION`], [`AttErrorCode::INSUFFICIENT_AUTHENTICATION`]
+    /// or an [application error](AttErrorCode::application).
+    fn authorize(&self, access: &AttributeAccess<'_>) -> Result<(), AttErrorCode>;
+}
+
+/// The kind of access to an attribute.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperation {
+    /// The client reads the attribute value.
+    Read,
+    /// The client writes the attribute value, with or without a response or as a prepared write.
+    Write,
+}
+
+/// An access to an attribute, passed to an [`AttributeAuthorizer`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub struct AttributeAccess<'a> {
+    /// Handle of the attribute.
+    pub handle: u16,
+    /// Type of the attribute.
+    pub uuid: &'a Uuid,
+    /// Whether the attribute is read or written.
+    pub operation: AttributeOperation,
+    /// Security level of the connection of the client.
+    pub security_level: SecurityLevel,
+    /// Identity of the client.
+    pub peer_identity: Identity,
+}
+
 /// A table of CCCD values.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone, Debug)]
@@ -58,12 +208,30 @@ impl<const ENTRIES: usize> CccdTable<ENTRIES> {
         }
     }
 
+    fn remove_handles(&mut self, start: u16, end: u16) {
+        for (handle, value) in self.inner.iter_mut() {
+            if *handle >= start && *handle <= end {
+                *handle = 0;
+                value.disable();
+            }
+        }
+    }
+
     fn disable_all(&mut self) {
         for (_, value) in self.inner.iter_mut() {
             value.disable();
         }
     }
 
+    fn set(&mut self, cccd_handle: u16, cccd: CCCD) {
+        for (handle, value) in self.inner.iter_mut() {
+            if *handle == cccd_handle {
+                *value = cccd;
+                break;
+            }
+        }
+    }
+
     fn get_raw(&self, cccd_handle: u16) -> Option<[u8; 2]> {
         for (handle, value) in self.inner.iter() {
             if *handle == cccd_handle {
@@ -91,6 +259,15 @@ impl<const ENTRIES: usize> CccdTable<ENTRIES> {
         }
         false
     }
+
+    fn should_indicate(&self, cccd_handle: u16) -> bool {
+        for (handle, value) in self.inner.iter() {
+            if *handle == cccd_handle {
+                return value.should_indicate();
+            }
+        }
+        false
+    }
 }
 
 /// A table of CCCD values for each connected client.
@@ -161,6 +338,8 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
             for (client, _) in n.iter_mut() {
                 if client.identity.match_identity(peer_identity) {
                     client.is_connected = false;
+                    client.prepare_queue.clear();
+                    client.notifications.clear();
                     break;
                 }
             }
@@ -179,6 +358,100 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
         })
     }
 
+    fn prepare_write(
+        &self,
+        peer_identity: &Identity,
+        handle: u16,
+        offset: u16,
+        value: &[u8],
+    ) -> Result<(), AttErrorCode> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, _) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    return client.prepare_write(handle, offset, value);
+                }
+            }
+            Err(AttErrorCode::UNLIKELY_ERROR)
+        })
+    }
+
+    /// The prepared writes of a client, if they are a single write such as the parts of a long value.
+    fn prepared_write(&self, peer_identity: &Identity) -> Option<sealed::PreparedWrite> {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            let (client, _) = n
+                .iter()
+                .find(|(client, _)| client.identity.match_identity(peer_identity))?;
+            let mut writes = prepared_writes(&client.prepare_queue);
+            let (handle, offset, value) = writes.next()?;
+            if writes.next().is_some() {
+                return None;
+            }
+            Some(sealed::PreparedWrite {
+                handle,
+                offset,
+                value: unwrap!(PrepareQueue::from_slice(value)),
+            })
+        })
+    }
+
+    /// Remove the prepared writes of a client, to execute or cancel them.
+    fn take_prepared_writes(&self, peer_identity: &Identity) -> PrepareQueue {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, _) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    return core::mem::take(&mut client.prepare_queue);
+                }
+            }
+            PrepareQueue::new()
+        })
+    }
+
+    fn set_value(&self, peer_identity: &Identity, cccd_handle: u16, cccd: CCCD) {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, table) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    table.set(cccd_handle, cccd);
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Replace the CCCD values of a client with values loaded from a [`CccdStore`].
+    fn restore(&self, peer_identity: &Identity, cccds: &[(u16, CCCD)]) {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, table) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    table.disable_all();
+                    for (handle, value) in cccds {
+                        table.set(*handle, *value);
+                    }
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Pass the CCCD values of a client to a [`CccdStore`].
+    fn save(&self, peer_identity: &Identity, bonded_identity: &Identity, store: &dyn CccdStore) {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            for (client, table) in n.iter() {
+                if client.identity.match_identity(peer_identity) {
+                    let cccds: heapless::Vec<(u16, CCCD), CCCD_MAX> =
+                        table.inner.iter().filter(|(handle, _)| *handle != 0).copied().collect();
+                    store.save(bonded_identity, &cccds);
+                    break;
+                }
+            }
+        })
+    }
+
     fn set_notify(&self, peer_identity: &Identity, cccd_handle: u16, is_enabled: bool) {
         self.state.lock(|n| {
             let mut n = n.borrow_mut();
@@ -203,6 +476,18 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
         })
     }
 
+    fn should_indicate(&self, peer_identity: &Identity, cccd_handle: u16) -> bool {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            for (client, table) in n.iter() {
+                if client.identity.match_identity(peer_identity) {
+                    return table.should_indicate(cccd_handle);
+                }
+            }
+            false
+        })
+    }
+
     fn get_cccd_table(&self, peer_identity: &Identity) -> Option<CccdTable<CCCD_MAX>> {
         self.state.lock(|n| {
             let n = n.borrow();
@@ -228,18 +513,235 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
         })
     }
 
+    fn export(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            let mut w = WriteCursor::new(buf);
+            w.write(CCCD_STATE_VERSION)?;
+            for (client, table) in n.iter() {
+                let enabled = table
+                    .inner
+                    .iter()
+                    .filter(|(handle, value)| *handle != 0 && value.raw() != 0);
+                let count = enabled.clone().count();
+                if client.identity == Identity::default() || count == 0 {
+                    continue;
+                }
+                w.append(client.identity.bd_addr.raw())?;
+                #[cfg(feature = "security")]
+                let irk = client.identity.irk.map(|irk| irk.to_le_bytes());
+                #[cfg(not(feature = "security"))]
+                let irk: Option<[u8; 16]> = None;
+                match irk {
+                    Some(irk) => {
+                        w.write(1u8)?;
+                        w.append(&irk)?;
+                    }
+                    None => w.write(0u8)?,
+                }
+                w.write(count as u8)?;
+                for (handle, value) in enabled {
+                    w.write(*handle)?;
+                    w.write(value.raw() as u8)?;
+                }
+            }
+            Ok(w.len())
+        })
+    }
+
+    fn import(&self, data: &[u8]) -> Result<(), Error> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            // Validate the whole blob, and that every client in it gets a slot, before applying anything.
+            let mut slots = ImportSlots::new(n.iter().map(|(client, _)| client));
+            parse_cccd_state(data, |identity, _| slots.assign(&identity).map(|_| ()))?;
+
+            let mut slots = ImportSlots::new(n.iter().map(|(client, _)| client));
+            parse_cccd_state(data, |identity, entries| {
+                let slot = slots.assign(&identity)?;
+                let (client, table) = &mut n[slot];
+                if !client.identity.match_identity(&identity) {
+                    client.is_connected = false;
+                    client.set_identity(identity);
+                }
+                table.disable_all();
+                for entry in entries.chunks_exact(3) {
+                    let handle = u16::from_le_bytes([entry[0], entry[1]]);
+                    table.set(handle, CCCD(entry[2] as u16));
+                }
+                Ok(())
+            })
+        })
+    }
+
     fn update_identity(&self, identity: Identity) -> Result<(), Error> {
         self.state.lock(|n| {
             let mut n = n.borrow_mut();
             for (client, _) in n.iter_mut() {
                 if identity.match_identity(&client.identity) {
-                    client.set_identity(identity);
+                    client.identity = identity;
                     return Ok(());
                 }
             }
             Err(Error::NotFound)
         })
     }
+
+    fn add_handles(&self, handles: impl Iterator<Item = u16> + Clone) {
+        self.state.lock(|n| {
+            for (_, table) in n.borrow_mut().iter_mut() {
+                for handle in handles.clone() {
+                    table.add_handle(handle);
+                }
+            }
+        })
+    }
+
+    fn remove_handles(&self, start: u16, end: u16) {
+        self.state.lock(|n| {
+            for (_, table) in n.borrow_mut().iter_mut() {
+                table.remove_handles(start, end);
+            }
+        })
+    }
+
+    /// Record a change of the handles from `start` to `end` for every known client.
+    fn service_changed(&self, start: u16, end: u16) {
+        self.state.lock(|n| {
+            for (client, _) in n.borrow_mut().iter_mut() {
+                if client.identity == Identity::default() {
+                    continue;
+                }
+                client.service_changed = Some(match client.service_changed {
+                    Some((s, e)) => (s.min(start), e.max(end)),
+                    None => (start, end),
+                });
+                client.waker.wake();
+            }
+        })
+    }
+
+    /// Queue a notification of the value at `value_handle` for every connected client subscribed to it.
+    fn queue_notification(&self, value_handle: u16, cccd_handle: u16) {
+        self.state.lock(|n| {
+            for (client, table) in n.borrow_mut().iter_mut() {
+                if !client.is_connected || !table.should_notify(cccd_handle) {
+                    continue;
+                }
+                // A queued notification is sent with the value at the time it is sent, so it covers this one too.
+                if !client.notifications.contains(&value_handle) {
+                    if client.notifications.push(value_handle).is_err() {
+                        warn!(
+                            "[server] notification queue full, dropping notification for {:?}",
+                            client.identity
+                        );
+                        continue;
+                    }
+                    client.waker.wake();
+                }
+            }
+        })
+    }
+
+    fn poll_notification(&self, peer_identity: &Identity, cx: &mut Context<'_>) -> Poll<u16> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, _) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    if !client.notifications.is_empty() {
+                        return Poll::Ready(client.notifications.remove(0));
+                    }
+                    client.waker.register(cx.waker());
+                    break;
+                }
+            }
+            Poll::Pending
+        })
+    }
+
+    fn poll_service_changed(&self, peer_identity: &Identity, cx: &mut Context<'_>) -> Poll<(u16, u16)> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, _) in n.iter_mut() {
+                if client.identity.match_identity(peer_identity) {
+                    if let Some(range) = client.service_changed.take() {
+                        return Poll::Ready(range);
+                    }
+                    client.waker.register(cx.waker());
+                    break;
+                }
+            }
+            Poll::Pending
+        })
+    }
+}
+
+/// Walk the client entries of a CCCD state blob, passing each identity and its packed
+/// `(handle, value)` entries to `f`.
+/// Slots of the clients known to the CCCD tables, as assigned while importing a CCCD state blob.
+struct ImportSlots<const CONN_MAX: usize> {
+    slots: [(Identity, bool); CONN_MAX],
+}
+
+impl<const CONN_MAX: usize> ImportSlots<CONN_MAX> {
+    fn new<'a>(clients: impl Iterator<Item = &'a Client>) -> Self {
+        let mut slots = [(Identity::default(), false); CONN_MAX];
+        for (slot, client) in slots.iter_mut().zip(clients) {
+            // Connected clients keep their slot.
+            *slot = (client.identity, client.is_connected);
+        }
+        Self { slots }
+    }
+
+    /// Find the slot of a client: the one with the same identity, else a free slot, else the slot of a
+    /// disconnected client that was not imported from the same blob.
+    fn assign(&mut self, identity: &Identity) -> Result<usize, Error> {
+        let slot = self
+            .slots
+            .iter()
+            .position(|(known, _)| known.match_identity(identity))
+            .or_else(|| self.slots.iter().position(|(known, _)| *known == Identity::default()))
+            .or_else(|| self.slots.iter().position(|(_, taken)| !taken))
+            .ok_or(Error::InsufficientSpace)?;
+        self.slots[slot] = (*identity, true);
+        Ok(slot)
+    }
+}
+
+fn parse_cccd_state(data: &[u8], mut f: impl FnMut(Identity, &[u8]) -> Result<(), Error>) -> Result<(), Error> {
+    let mut r = ReadCursor::new(data);
+    let version: u8 = r.read()?;
+    if version != CCCD_STATE_VERSION {
+        return Err(Error::InvalidValue);
+    }
+    while r.available() > 0 {
+        let mut bd_addr = [0; 6];
+        bd_addr.copy_from_slice(r.slice(6)?);
+        let has_irk: u8 = r.read()?;
+        let irk = match has_irk {
+            0 => None,
+            1 => Some(r.slice(16)?),
+            _ => return Err(Error::InvalidValue),
+        };
+        let count: u8 = r.read()?;
+        let entries = r.slice(count as usize * 3)?;
+
+        #[cfg_attr(not(feature = "security"), allow(unused_mut))]
+        let mut identity = Identity {
+            bd_addr: BdAddr::new(bd_addr),
+            ..Default::default()
+        };
+        #[cfg(feature = "security")]
+        if let Some(irk) = irk {
+            let mut key = [0; 16];
+            key.copy_from_slice(irk);
+            identity.irk = Some(crate::security_manager::IdentityResolvingKey::from_le_bytes(key));
+        }
+        #[cfg(not(feature = "security"))]
+        let _ = irk;
+        f(identity, entries)?;
+    }
+    Ok(())
 }
 
 /// A GATT server capable of processing the GATT protocol using the provided table of attributes.
@@ -253,12 +755,21 @@ pub struct AttributeServer<
 > {
     att_table: AttributeTable<'values, M, ATT_MAX>,
     cccd_tables: CccdTables<M, CCCD_MAX, CONN_MAX>,
+    cccd_store: Mutex<M, Cell<Option<&'values dyn CccdStore>>>,
+    authorizer: Mutex<M, Cell<Option<&'values dyn AttributeAuthorizer>>>,
     _p: PhantomData<P>,
 }
 
 pub(crate) mod sealed {
     use super::*;
 
+    /// A write prepared by a client, waiting for the client to execute it.
+    pub struct PreparedWrite {
+        pub handle: u16,
+        pub offset: u16,
+        pub value: PrepareQueue,
+    }
+
     pub trait DynamicAttributeServer<P: PacketPool> {
         fn connect(&self, connection: &Connection<'_, P>) -> Result<(), Error>;
         fn disconnect(&self, connection: &Connection<'_, P>);
@@ -268,9 +779,21 @@ pub(crate) mod sealed {
             packet: &AttClient,
             rx: &mut [u8],
         ) -> Result<Option<usize>, Error>;
+        fn write_command(&self, connection: &Connection<'_, P>, handle: u16, data: &[u8]) -> Result<(), AttErrorCode>;
         fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool;
+        fn should_indicate(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool;
+        fn cccd_handle(&self, value_handle: u16) -> Option<u16>;
         fn set(&self, characteristic: u16, input: &[u8]) -> Result<(), Error>;
+        fn set_all(&self, values: &[(u16, &[u8])]) -> Result<(), Error>;
         fn update_identity(&self, identity: Identity) -> Result<(), Error>;
+        fn save_cccds(&self, connection: &Connection<'_, P>);
+        fn prepared_write(&self, connection: &Connection<'_, P>) -> Option<PreparedWrite>;
+        fn cancel_prepared_writes(&self, connection: &Connection<'_, P>);
+        fn service_changed_handle(&self) -> Option<u16>;
+        fn poll_service_changed(&self, connection: &Connection<'_, P>, cx: &mut Context<'_>) -> Poll<(u16, u16)>;
+        fn poll_notification(&self, connection: &Connection<'_, P>, cx: &mut Context<'_>) -> Poll<u16>;
+        fn read_value(&self, handle: u16, data: &mut [u8]) -> Result<usize, Error>;
+        fn check_read(&self, connection: &Connection<'_, P>, handle: u16) -> Result<(), AttErrorCode>;
     }
 }
 
@@ -302,17 +825,92 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
         Ok(res)
     }
 
+    fn write_command(&self, connection: &Connection<'_, P>, handle: u16, data: &[u8]) -> Result<(), AttErrorCode> {
+        AttributeServer::write_command(self, connection, handle, data)
+    }
+
     fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool {
         AttributeServer::should_notify(self, connection, cccd_handle)
     }
 
+    fn should_indicate(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool {
+        AttributeServer::should_indicate(self, connection, cccd_handle)
+    }
+
+    fn cccd_handle(&self, value_handle: u16) -> Option<u16> {
+        self.att_table
+            .find_characteristic_by_value_handle::<&'static [u8]>(value_handle)
+            .ok()
+            .and_then(|c| c.cccd_handle)
+    }
+
     fn set(&self, characteristic: u16, input: &[u8]) -> Result<(), Error> {
         self.att_table.set_raw(characteristic, input)
     }
 
+    fn set_all(&self, values: &[(u16, &[u8])]) -> Result<(), Error> {
+        self.att_table.set_raw_all(values)
+    }
+
     fn update_identity(&self, identity: Identity) -> Result<(), Error> {
         self.cccd_tables.update_identity(identity)
     }
+
+    fn save_cccds(&self, connection: &Connection<'_, P>) {
+        AttributeServer::save_cccds(self, connection)
+    }
+
+    fn prepared_write(&self, connection: &Connection<'_, P>) -> Option<sealed::PreparedWrite> {
+        self.cccd_tables.prepared_write(&connection.peer_identity())
+    }
+
+    fn cancel_prepared_writes(&self, connection: &Connection<'_, P>) {
+        self.cccd_tables.take_prepared_writes(&connection.peer_identity());
+    }
+
+    fn service_changed_handle(&self) -> Option<u16> {
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.uuid == SERVICE_CHANGED {
+                    return Some(att.handle);
+                }
+            }
+            None
+        })
+    }
+
+    fn poll_service_changed(&self, connection: &Connection<'_, P>, cx: &mut Context<'_>) -> Poll<(u16, u16)> {
+        self.cccd_tables.poll_service_changed(&connection.peer_identity(), cx)
+    }
+
+    fn poll_notification(&self, connection: &Connection<'_, P>, cx: &mut Context<'_>) -> Poll<u16> {
+        self.cccd_tables.poll_notification(&connection.peer_identity(), cx)
+    }
+
+    fn read_value(&self, handle: u16, data: &mut [u8]) -> Result<usize, Error> {
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == handle {
+                    return att.read_unchecked(0, data).map_err(|_| Error::NotFound);
+                }
+            }
+            Err(Error::NotFound)
+        })
+    }
+
+    fn check_read(&self, connection: &Connection<'_, P>, handle: u16) -> Result<(), AttErrorCode> {
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == handle {
+                    if !att.data.readable() {
+                        return Err(AttErrorCode::READ_NOT_PERMITTED);
+                    }
+                    return self.authorize(connection, att, AttributeOperation::Read);
+                }
+            }
+            Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+        })
+    }
 }
 
 impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, const CONN_MAX: usize>
@@ -326,18 +924,79 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         AttributeServer {
             att_table,
             cccd_tables,
+            cccd_store: Mutex::new(Cell::new(None)),
+            authorizer: Mutex::new(Cell::new(None)),
             _p: PhantomData,
         }
     }
 
     pub(crate) fn connect(&self, connection: &Connection<'_, P>) -> Result<(), Error> {
-        self.cccd_tables.connect(&connection.peer_identity())
+        let identity = connection.peer_identity();
+        self.cccd_tables.connect(&identity)?;
+        if let Some(store) = self.cccd_store() {
+            if let Some(bonded_identity) = connection.bonded_identity() {
+                let mut cccds = [(0, CCCD(0)); CCCD_MAX];
+                let len = store.load(&bonded_identity, &mut cccds).min(CCCD_MAX);
+                trace!("[server] restoring {} CCCD values for {:?}", len, bonded_identity);
+                self.cccd_tables.restore(&identity, &cccds[..len]);
+            }
+        }
+        Ok(())
+    }
+
+    fn cccd_store(&self) -> Option<&'values dyn CccdStore> {
+        self.cccd_store.lock(|store| store.get())
+    }
+
+    /// Save the CCCD values of a bonded client to the CCCD store, if any.
+    pub(crate) fn save_cccds(&self, connection: &Connection<'_, P>) {
+        if let Some(store) = self.cccd_store() {
+            if let Some(bonded_identity) = connection.bonded_identity() {
+                self.cccd_tables
+                    .save(&connection.peer_identity(), &bonded_identity, store);
+            }
+        }
     }
 
     pub(crate) fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool {
         self.cccd_tables.should_notify(&connection.peer_identity(), cccd_handle)
     }
 
+    pub(crate) fn should_indicate(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool {
+        self.cccd_tables
+            .should_indicate(&connection.peer_identity(), cccd_handle)
+    }
+
+    /// Queue a notification of the value at `value_handle` for every connected client subscribed to it.
+    pub(crate) fn queue_notification(&self, value_handle: u16, cccd_handle: u16) {
+        self.cccd_tables.queue_notification(value_handle, cccd_handle);
+    }
+
+    /// Ask the authorizer, if any, whether the client may access a characteristic value or descriptor.
+    fn authorize(
+        &self,
+        connection: &Connection<'_, P>,
+        att: &Attribute<'values>,
+        operation: AttributeOperation,
+    ) -> Result<(), AttErrorCode> {
+        if matches!(
+            att.data,
+            AttributeData::Service { .. } | AttributeData::Declaration { .. } | AttributeData::Include { .. }
+        ) {
+            return Ok(());
+        }
+        let Some(authorizer) = self.authorizer.lock(|a| a.get()) else {
+            return Ok(());
+        };
+        authorizer.authorize(&AttributeAccess {
+            handle: att.handle,
+            uuid: &att.uuid,
+            operation,
+            security_level: connection.security_level().unwrap_or(SecurityLevel::NoEncryption),
+            peer_identity: connection.peer_identity(),
+        })
+    }
+
     fn read_attribute_data(
         &self,
         connection: &Connection<'_, P>,
@@ -345,6 +1004,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         att: &mut Attribute<'values>,
         data: &mut [u8],
     ) -> Result<usize, AttErrorCode> {
+        self.authorize(connection, att, AttributeOperation::Read)?;
         if let AttributeData::Cccd { .. } = att.data {
             // CCCD values for each connected client are held in the CCCD tables:
             // the value is written back into att.data so att.read() has the final
@@ -363,6 +1023,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         att: &mut Attribute<'values>,
         data: &[u8],
     ) -> Result<(), AttErrorCode> {
+        self.authorize(connection, att, AttributeOperation::Write)?;
         let err = att.write(offset, data);
         if err.is_ok() {
             if let AttributeData::Cccd {
@@ -370,8 +1031,12 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 indications,
             } = att.data
             {
+                let mut cccd = CCCD(0);
+                cccd.set_notify(notifications);
+                cccd.set_indicate(indications);
                 self.cccd_tables
-                    .set_notify(&connection.peer_identity(), att.handle, notifications);
+                    .set_value(&connection.peer_identity(), att.handle, cccd);
+                self.save_cccds(connection);
             }
         }
         err
@@ -571,16 +1236,21 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         handle: u16,
         data: &[u8],
     ) -> Result<usize, codec::Error> {
+        // Write commands can't respond with an error.
+        let _ = self.write_command(connection, handle, data);
+        Ok(0)
+    }
+
+    /// Apply a Write Command, returning the error it would have been answered with if it were a request.
+    fn write_command(&self, connection: &Connection<'_, P>, handle: u16, data: &[u8]) -> Result<(), AttErrorCode> {
         self.att_table.iterate(|mut it| {
             while let Some(att) = it.next() {
                 if att.handle == handle {
-                    // Write commands can't respond with an error.
-                    let _ = self.write_attribute_data(connection, 0, att, data);
-                    break;
+                    return self.write_attribute_data(connection, 0, att, data);
                 }
             }
-        });
-        Ok(0)
+            Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+        })
     }
 
     fn handle_write_req(
@@ -714,28 +1384,83 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         w.write(handle)?;
         w.write(offset)?;
 
+        // Permissions are checked now, offsets and lengths when the writes are executed.
         let err = self.att_table.iterate(|mut it| {
-            let mut err = Err(AttErrorCode::ATTRIBUTE_NOT_FOUND);
             while let Some(att) = it.next() {
                 if att.handle == handle {
-                    err = self.write_attribute_data(connection, offset as usize, att, value);
-                    w.append(value)?;
-                    break;
+                    if !att.data.writable() {
+                        return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                    }
+                    return self.authorize(connection, att, AttributeOperation::Write);
                 }
             }
-            err
+            Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+        });
+        let err = err.and_then(|_| {
+            self.cccd_tables
+                .prepare_write(&connection.peer_identity(), handle, offset, value)
         });
 
         match err {
-            Ok(()) => Ok(w.len()),
+            Ok(()) => {
+                // The value is echoed back so the client can check it for reliable writes.
+                w.append(value)?;
+                Ok(w.len())
+            }
             Err(e) => Ok(Self::error_response(w, att::ATT_PREPARE_WRITE_REQ, handle, e)?),
         }
     }
 
-    fn handle_execute_write(&self, buf: &mut [u8], _flags: u8) -> Result<usize, codec::Error> {
+    fn handle_execute_write(
+        &self,
+        connection: &Connection<'_, P>,
+        buf: &mut [u8],
+        flags: u8,
+    ) -> Result<usize, codec::Error> {
+        let queue = self.cccd_tables.take_prepared_writes(&connection.peer_identity());
         let mut w = WriteCursor::new(buf);
-        w.write(att::ATT_EXECUTE_WRITE_RSP)?;
-        Ok(w.len())
+        if flags & 0x01 == 0 {
+            // Cancel all prepared writes.
+            w.write(att::ATT_EXECUTE_WRITE_RSP)?;
+            return Ok(w.len());
+        }
+
+        // Check every write before applying any of them, so the values are written all or nothing.
+        let err = self.att_table.iterate(|mut it| {
+            for (handle, offset, value) in prepared_writes(&queue) {
+                it.rewind();
+                let mut err = Err((handle, AttErrorCode::ATTRIBUTE_NOT_FOUND));
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        err = self
+                            .authorize(connection, att, AttributeOperation::Write)
+                            .and_then(|_| att.check_write(offset.into(), value.len()))
+                            .map_err(|e| (handle, e));
+                        break;
+                    }
+                }
+                err?;
+            }
+            for (handle, offset, value) in prepared_writes(&queue) {
+                it.rewind();
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        self.write_attribute_data(connection, offset.into(), att, value)
+                            .map_err(|e| (handle, e))?;
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        match err {
+            Ok(()) => {
+                w.write(att::ATT_EXECUTE_WRITE_RSP)?;
+                Ok(w.len())
+            }
+            Err((handle, e)) => Ok(Self::error_response(w, att::ATT_EXECUTE_WRITE_REQ, handle, e)?),
+        }
     }
 
     fn handle_read_blob(
@@ -768,14 +1493,63 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         }
     }
 
-    fn handle_read_multiple(&self, buf: &mut [u8], handles: &[u8]) -> Result<usize, codec::Error> {
-        let w = WriteCursor::new(buf);
-        Self::error_response(
-            w,
-            att::ATT_READ_MULTIPLE_REQ,
-            u16::from_le_bytes([handles[0], handles[1]]),
-            AttErrorCode::ATTRIBUTE_NOT_FOUND,
-        )
+    fn handle_read_multiple(
+        &self,
+        connection: &Connection<'_, P>,
+        buf: &mut [u8],
+        handles: &[u8],
+        variable: bool,
+    ) -> Result<usize, codec::Error> {
+        let (request, response) = if variable {
+            (att::ATT_READ_MULTIPLE_VARIABLE_REQ, att::ATT_READ_MULTIPLE_VARIABLE_RSP)
+        } else {
+            (att::ATT_READ_MULTIPLE_REQ, att::ATT_READ_MULTIPLE_RSP)
+        };
+        let mut w = WriteCursor::new(buf);
+        if handles.len() < 4 || handles.len() % 2 != 0 {
+            return Self::error_response(w, request, 0, AttErrorCode::INVALID_PDU);
+        }
+        w.write(response)?;
+
+        // Values are appended until the buffer is full; the response is then truncated to the ATT MTU.
+        let err = self.att_table.iterate(|mut it| {
+            for handle in handles.chunks_exact(2).map(|h| u16::from_le_bytes([h[0], h[1]])) {
+                it.rewind();
+                let mut err = Err(AttErrorCode::ATTRIBUTE_NOT_FOUND);
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        err = if variable {
+                            let buf = w.write_buf();
+                            if buf.len() < 2 {
+                                Ok(0)
+                            } else {
+                                let (len, value) = buf.split_at_mut(2);
+                                let capacity = value.len();
+                                self.read_attribute_data(connection, 0, att, value).map(|n| {
+                                    // The length is the one of the whole value, even if it is truncated.
+                                    let total = if n == capacity { value_len(att, n) } else { n };
+                                    len.copy_from_slice(&(total as u16).to_le_bytes());
+                                    2 + n
+                                })
+                            }
+                        } else {
+                            self.read_attribute_data(connection, 0, att, w.write_buf())
+                        };
+                        break;
+                    }
+                }
+                match err {
+                    Ok(n) => w.commit(n).map_err(|_| (handle, AttErrorCode::UNLIKELY_ERROR))?,
+                    Err(e) => return Err((handle, e)),
+                }
+            }
+            Ok(())
+        });
+
+        match err {
+            Ok(()) => Ok(w.len()),
+            Err((handle, e)) => Ok(Self::error_response(w, request, handle, e)?),
+        }
     }
 
     /// Process an event and produce a response if necessary
@@ -824,13 +1598,19 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 self.handle_prepare_write(connection, rx, *handle, *offset, value)?
             }
 
-            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(rx, *flags)?,
+            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(connection, rx, *flags)?,
 
             AttClient::Request(AttReq::ReadBlob { handle, offset }) => {
                 self.handle_read_blob(connection, rx, *handle, *offset)?
             }
 
-            AttClient::Request(AttReq::ReadMultiple { handles }) => self.handle_read_multiple(rx, handles)?,
+            AttClient::Request(AttReq::ReadMultiple { handles }) => {
+                self.handle_read_multiple(connection, rx, handles, false)?
+            }
+
+            AttClient::Request(AttReq::ReadMultipleVariable { handles }) => {
+                self.handle_read_multiple(connection, rx, handles, true)?
+            }
 
             AttClient::Confirmation(_) => 0,
         };
@@ -846,6 +1626,76 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         &self.att_table
     }
 
+    /// Add a service while the server is running, using `build` to add its characteristics.
+    ///
+    /// The CCCDs of the service are registered for every client, and clients are sent a Service Changed
+    /// indication for the handles of the service if the table has a Service Changed characteristic and they
+    /// enabled indications for it. Returns [`Error::InsufficientSpace`] if the attribute table is full, in
+    /// which case nothing is added.
+    pub fn add_service<F>(&self, service: Service, build: F) -> Result<ServiceHandle, Error>
+    where
+        F: FnOnce(&mut ServiceBuilder<'_, 'values, M, ATT_MAX>),
+    {
+        let uuid = service.uuid.clone();
+        let (start, end) = self.att_table.try_add_service(service, build)?;
+        let mut cccds: heapless::Vec<u16, CCCD_MAX> = heapless::Vec::new();
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle >= start && att.handle <= end && matches!(att.data, AttributeData::Cccd { .. }) {
+                    if cccds.push(att.handle).is_err() {
+                        warn!("[server] no room for the CCCD at handle {}", att.handle);
+                    }
+                }
+            }
+        });
+        self.cccd_tables.add_handles(cccds.iter().copied());
+        self.cccd_tables.service_changed(start, end);
+        Ok(ServiceHandle { start, end, uuid })
+    }
+
+    /// Remove a service added with [`add_service`](Self::add_service), or any other service of the table.
+    ///
+    /// The subscriptions of clients to its characteristics are dropped, and clients are told about the
+    /// removed handles like for [`add_service`](Self::add_service).
+    pub fn remove_service(&self, service: &ServiceHandle) -> Result<(), Error> {
+        let (start, end) = self.att_table.remove_service(service.start())?;
+        self.cccd_tables.remove_handles(start, end);
+        self.cccd_tables.service_changed(start, end);
+        Ok(())
+    }
+
+    /// Tell clients that the attributes from `start` to `end` changed, so that they discover them again.
+    ///
+    /// Connected clients are sent a Service Changed indication by [`GattConnection::next`](crate::gatt::GattConnection::next)
+    /// if they enabled indications for the Service Changed characteristic, and disconnected clients known to the server
+    /// are sent it when they reconnect. Changes made through [`add_service`](Self::add_service) and
+    /// [`remove_service`](Self::remove_service) are reported already; this is for changes the server cannot see, such
+    /// as a firmware update adding services. In that case, restore the clients with
+    /// [`import_cccd_state`](Self::import_cccd_state) first so that bonded clients are told too.
+    ///
+    /// Returns [`Error::InvalidValue`] if the range is empty or starts at handle 0.
+    pub fn service_changed(&self, start: u16, end: u16) -> Result<(), Error> {
+        if start == 0 || start > end {
+            return Err(Error::InvalidValue);
+        }
+        self.cccd_tables.service_changed(start, end);
+        Ok(())
+    }
+
+    /// Set the storage used to keep the CCCD values of bonded clients across connections, or remove it with `None`.
+    ///
+    /// See [`CccdStore`].
+    pub fn set_cccd_store(&self, store: Option<&'values dyn CccdStore>) {
+        self.cccd_store.lock(|s| s.set(store));
+    }
+
+    /// Set the hook deciding whether clients may access attributes, or remove it with `None`.
+    ///
+    /// See [`AttributeAuthorizer`].
+    pub fn set_authorizer(&self, authorizer: Option<&'values dyn AttributeAuthorizer>) {
+        self.authorizer.lock(|a| a.set(authorizer));
+    }
+
     /// Get the CCCD table for a connection
     pub fn get_cccd_table(&self, connection: &Connection<'_, P>) -> Option<CccdTable<CCCD_MAX>> {
         self.cccd_tables.get_cccd_table(&connection.peer_identity())
@@ -855,6 +1705,156 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
     pub fn set_cccd_table(&self, connection: &Connection<'_, P>, table: CccdTable<CCCD_MAX>) {
         self.cccd_tables.set_cccd_table(&connection.peer_identity(), table);
     }
+
+    /// Upper bound on the length of the blob written by [`export_cccd_state`](Self::export_cccd_state).
+    pub const CCCD_STATE_MAX_LEN: usize = 1 + CONN_MAX * (6 + 1 + 16 + 1 + 3 * CCCD_MAX);
+
+    /// Snapshot the CCCD values of every known client into `buf` as a compact blob, returning its length.
+    ///
+    /// Clients are identified by their identity address and, with the `security` feature, their IRK. Only
+    /// clients with at least one notification or indication enabled are included, so the blob can be stored
+    /// alongside existing application settings and restored with [`import_cccd_state`](Self::import_cccd_state)
+    /// after a reset. Returns [`Error::InsufficientSpace`] if `buf` is too small; see [`Self::CCCD_STATE_MAX_LEN`].
+    pub fn export_cccd_state(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.cccd_tables.export(buf)
+    }
+
+    /// Restore CCCD values from a blob written by [`export_cccd_state`](Self::export_cccd_state).
+    ///
+    /// Each client in the blob replaces the CCCD values of a known client with the same identity, or takes a free
+    /// or disconnected slot. CCCD handles that no longer exist in the attribute table are ignored. Nothing is
+    /// restored if the blob is malformed, or if it has more clients than slots, in which case
+    /// [`Error::InsufficientSpace`] is returned.
+    pub fn import_cccd_state(&self, data: &[u8]) -> Result<(), Error> {
+        self.cccd_tables.import(data)
+    }
+}
+
+/// What a [`CharacteristicNotificationQueue`] does with a value pushed while it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NotificationQueuePolicy {
+    /// Wait for room in the queue, holding back the producer.
+    #[default]
+    Block,
+    /// Discard the oldest queued value to make room for the new one.
+    DropOldest,
+    /// Replace every queued value with the new one, so only the latest value is notified.
+    CoalesceLatest,
+}
+
+/// Queue of values to notify for a single characteristic to a single connection.
+///
+/// Decouples a producer from the radio: values are pushed with [`push`](Self::push) or
+/// [`try_push`](Self::try_push) and notified to a connection by [`run`](Self::run), which waits for the
+/// connection to accept each notification. Up to `N` values are queued, after which the
+/// [`NotificationQueuePolicy`] applies.
+///
+/// Every value is notified once, by the [`run`](Self::run) that takes it off the queue. Running the same
+/// queue for several connections splits the values between them rather than notifying each connection of
+/// all of them, so use one queue per connection, or [`Characteristic::notify_all`] to reach every
+/// subscribed client.
+pub struct CharacteristicNotificationQueue<M: RawMutex, T: FromGatt, const N: usize> {
+    characteristic: Characteristic<T>,
+    policy: NotificationQueuePolicy,
+    queue: Channel<M, T, N>,
+    /// Value taken off the queue whose notification failed, notified first by the next run.
+    pending: Mutex<M, RefCell<Option<T>>>,
+}
+
+impl<M: RawMutex, T: FromGatt, const N: usize> CharacteristicNotificationQueue<M, T, N> {
+    /// Create a queue for notifications of `characteristic`.
+    pub fn new(characteristic: Characteristic<T>, policy: NotificationQueuePolicy) -> Self {
+        Self {
+            characteristic,
+            policy,
+            queue: Channel::new(),
+            pending: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// The policy applied when the queue is full.
+    pub fn policy(&self) -> NotificationQueuePolicy {
+        self.policy
+    }
+
+    /// Queue a value for notification, waiting for room if the policy is [`NotificationQueuePolicy::Block`].
+    pub async fn push(&self, value: T) {
+        match self.policy {
+            NotificationQueuePolicy::Block => self.queue.send(value).await,
+            _ => self.push_overflowing(value),
+        }
+    }
+
+    /// Queue a value for notification without waiting.
+    ///
+    /// Returns [`Error::Busy`] if the queue is full and the policy is [`NotificationQueuePolicy::Block`].
+    pub fn try_push(&self, value: T) -> Result<(), Error> {
+        match self.policy {
+            NotificationQueuePolicy::Block => self.queue.try_send(value).map_err(|_| Error::Busy),
+            _ => {
+                self.push_overflowing(value);
+                Ok(())
+            }
+        }
+    }
+
+    fn push_overflowing(&self, value: T) {
+        match self.policy {
+            NotificationQueuePolicy::CoalesceLatest => self.clear(),
+            _ if self.queue.is_full() => {
+                trace!("[server] notification queue full, dropping oldest value");
+                let next = self.queue.try_receive().ok();
+                // A pending value is older than the queued ones, so it is the one dropped.
+                self.pending.lock(|pending| {
+                    let mut pending = pending.borrow_mut();
+                    if pending.is_some() {
+                        *pending = next;
+                    }
+                });
+            }
+            _ => {}
+        }
+        let _ = self.queue.try_send(value);
+    }
+
+    fn has_pending(&self) -> bool {
+        self.pending.lock(|pending| pending.borrow().is_some())
+    }
+
+    /// Number of values waiting to be notified.
+    pub fn len(&self) -> usize {
+        self.queue.len() + usize::from(self.has_pending())
+    }
+
+    /// Whether no values are waiting to be notified.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && !self.has_pending()
+    }
+
+    /// Discard all queued values.
+    pub fn clear(&self) {
+        self.pending.lock(|pending| pending.take());
+        self.queue.clear();
+    }
+
+    /// Notify queued values to the connection as they are pushed.
+    ///
+    /// Each value is written to the characteristic and notified with [`Characteristic::notify`], waiting for the
+    /// connection to accept it before taking the next one. Only returns if a notification fails, in which case
+    /// the value that failed stays queued and is the first one notified by the next run.
+    pub async fn run<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+        loop {
+            let value = match self.pending.lock(|pending| pending.take()) {
+                Some(value) => value,
+                None => self.queue.receive().await,
+            };
+            if let Err(e) = self.characteristic.notify(connection, &value).await {
+                self.pending.lock(|pending| pending.replace(Some(value)));
+                return Err(e);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -865,7 +1865,7 @@ mod tests {
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 
     use super::*;
-    use crate::connection_manager::tests::{setup, ADDR_1};
+    use crate::connection_manager::tests::{setup, ADDR_1, ADDR_2};
     use crate::prelude::*;
 
     #[test]
@@ -996,4 +1996,533 @@ mod tests {
             };
         }
     }
+
+    #[test]
+    fn cccd_state_export_import() {
+        let mut store = [0u8; 2];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let characteristic = {
+            let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+            svc.add_characteristic(
+                Uuid::new_short(0x2a19),
+                &[CharacteristicProp::Notify, CharacteristicProp::Indicate],
+                0u8,
+                &mut store,
+            )
+            .build()
+        };
+        let cccd_handle = unwrap!(characteristic.cccd_handle);
+
+        let peer = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            ..Default::default()
+        };
+        let other = Identity {
+            bd_addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+            ..Default::default()
+        };
+        let tables: CccdTables<NoopRawMutex, 4, 2> = CccdTables::new(&table);
+        unwrap!(tables.connect(&peer));
+        unwrap!(tables.connect(&other));
+        tables.set_notify(&peer, cccd_handle, true);
+
+        let mut buf = [0u8; 64];
+        let len = unwrap!(tables.export(&mut buf));
+        // Only the subscribed client is exported.
+        assert_eq!(
+            &buf[..len],
+            &[
+                CCCD_STATE_VERSION,
+                0x11,
+                0x22,
+                0x33,
+                0x44,
+                0x55,
+                0x66,
+                0,
+                1,
+                cccd_handle as u8,
+                0,
+                1
+            ]
+        );
+        assert!(tables.export(&mut buf[..4]).is_err());
+
+        let restored: CccdTables<NoopRawMutex, 4, 2> = CccdTables::new(&table);
+        unwrap!(restored.import(&buf[..len]));
+        assert!(restored.should_notify(&peer, cccd_handle));
+        assert!(!restored.should_indicate(&peer, cccd_handle));
+        unwrap!(restored.connect(&peer));
+        assert!(restored.should_notify(&peer, cccd_handle));
+
+        assert!(restored.import(&[CCCD_STATE_VERSION + 1]).is_err());
+        assert!(restored.import(&buf[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn cccd_state_import_needs_slots() {
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let characteristic = {
+            let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+            svc.add_characteristic(Uuid::new_short(0x2a19), &[CharacteristicProp::Notify], 0u8, &mut store)
+                .build()
+        };
+        let cccd_handle = unwrap!(characteristic.cccd_handle);
+
+        let peer = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            ..Default::default()
+        };
+        let other = Identity {
+            bd_addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+            ..Default::default()
+        };
+        let third = Identity {
+            bd_addr: BdAddr::new([7, 8, 9, 10, 11, 12]),
+            ..Default::default()
+        };
+        let exported: CccdTables<NoopRawMutex, 4, 2> = CccdTables::new(&table);
+        unwrap!(exported.connect(&peer));
+        unwrap!(exported.connect(&third));
+        exported.set_notify(&peer, cccd_handle, true);
+        exported.set_notify(&third, cccd_handle, true);
+        let mut buf = [0u8; 64];
+        let len = unwrap!(exported.export(&mut buf));
+
+        // Both slots are taken by connected clients, so the third client has nowhere to go.
+        let tables: CccdTables<NoopRawMutex, 4, 2> = CccdTables::new(&table);
+        unwrap!(tables.connect(&peer));
+        unwrap!(tables.connect(&other));
+        assert!(matches!(tables.import(&buf[..len]), Err(Error::InsufficientSpace)));
+        assert!(!tables.should_notify(&peer, cccd_handle));
+
+        // Once the other client disconnects, its slot is reused and both clients are restored.
+        tables.disconnect(&other);
+        unwrap!(tables.import(&buf[..len]));
+        assert!(tables.should_notify(&peer, cccd_handle));
+        assert!(tables.should_notify(&third, cccd_handle));
+        assert!(!tables.should_notify(&other, cccd_handle));
+    }
+
+    #[cfg(feature = "security")]
+    #[test]
+    fn cccd_store_bonded_client() {
+        struct TestStore(RefCell<Option<(Identity, heapless::Vec<(u16, CCCD), 4>)>>);
+
+        impl CccdStore for TestStore {
+            fn save(&self, identity: &Identity, cccds: &[(u16, CCCD)]) {
+                self.0
+                    .replace(Some((*identity, unwrap!(heapless::Vec::from_slice(cccds)))));
+            }
+
+            fn load(&self, identity: &Identity, cccds: &mut [(u16, CCCD)]) -> usize {
+                match &*self.0.borrow() {
+                    Some((saved, values)) if saved.match_identity(identity) => {
+                        cccds[..values.len()].copy_from_slice(values);
+                        values.len()
+                    }
+                    _ => 0,
+                }
+            }
+        }
+
+        fn battery_server(store: &mut [u8]) -> (AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 4, 2>, u16) {
+            let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+            let characteristic = {
+                let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+                svc.add_characteristic(
+                    Uuid::new_short(0x2a19),
+                    &[CharacteristicProp::Notify, CharacteristicProp::Indicate],
+                    0u8,
+                    store,
+                )
+                .build()
+            };
+            (AttributeServer::new(table), unwrap!(characteristic.cccd_handle))
+        }
+
+        // The peer connects with a resolvable private address generated with the IRK of its bond.
+        let identity = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            irk: Some(IdentityResolvingKey::new(0x8b3958c158ed64467bd27bc90d3cf54d)),
+        };
+        let mgr = setup();
+        unwrap!(mgr.security_manager.add_bond_information(BondInformation::new(
+            identity,
+            LongTermKey::new(1),
+            SecurityLevel::Encrypted,
+            true
+        )));
+        unwrap!(mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new([0x92, 0xf2, 0x8f, 0x84, 0x72, 0x4f]),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(connection) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert!(connection.is_bonded());
+
+        let cccd_store = TestStore(RefCell::new(None));
+        let mut store = [0u8; 1];
+        let (server, cccd_handle) = battery_server(&mut store);
+        server.set_cccd_store(Some(&cccd_store));
+        unwrap!(server.connect(&connection));
+        let mut buffer = [0u8; 16];
+        let len = unwrap!(server.handle_write_req(&connection, &mut buffer, cccd_handle, &[2, 0]));
+        assert_eq!(&buffer[..len], &[att::ATT_WRITE_RSP]);
+        // The values are saved under the identity of the bond rather than the private address.
+        assert_eq!(
+            cccd_store.0.borrow().as_ref().map(|(saved, _)| saved.bd_addr),
+            Some(BdAddr::new(ADDR_1))
+        );
+
+        // A fresh server (e.g. after a reset) gets the subscription back when the peer reconnects.
+        let mut store = [0u8; 1];
+        let (server, cccd_handle) = battery_server(&mut store);
+        server.set_cccd_store(Some(&cccd_store));
+        unwrap!(server.connect(&connection));
+        assert!(server.should_indicate(&connection, cccd_handle));
+        assert!(!server.should_notify(&connection, cccd_handle));
+    }
+
+    #[cfg(feature = "gatt-server-prepare-queue-size-512")]
+    #[test]
+    fn prepare_queue() {
+        let mut client = Client::default();
+        unwrap!(client.prepare_write(3, 0, &[1, 2, 3]));
+        // Consecutive parts of a value are merged.
+        unwrap!(client.prepare_write(3, 3, &[4, 5]));
+        unwrap!(client.prepare_write(7, 0, &[6]));
+        unwrap!(client.prepare_write(3, 10, &[7]));
+
+        let mut writes = prepared_writes(&client.prepare_queue);
+        assert_eq!(writes.next(), Some((3, 0, &[1, 2, 3, 4, 5][..])));
+        assert_eq!(writes.next(), Some((7, 0, &[6][..])));
+        assert_eq!(writes.next(), Some((3, 10, &[7][..])));
+        assert_eq!(writes.next(), None);
+
+        let full = [0; GATT_SERVER_PREPARE_QUEUE_SIZE];
+        assert_eq!(client.prepare_write(9, 0, &full), Err(AttErrorCode::PREPARE_QUEUE_FULL));
+        assert_eq!(prepared_writes(&client.prepare_queue).count(), 3);
+
+        client.set_identity(Identity::default());
+        assert!(client.prepare_queue.is_empty());
+    }
+
+    #[test]
+    fn add_remove_service_at_runtime() {
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        table.add_service(Service::new(Uuid::new_short(0x1800)));
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+
+        let peer = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            ..Default::default()
+        };
+        unwrap!(server.cccd_tables.connect(&peer));
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+        assert!(server.cccd_tables.poll_service_changed(&peer, &mut cx).is_pending());
+
+        let mut characteristic = None;
+        let service = unwrap!(server.add_service(Service::new(Uuid::new_short(0x180f)), |svc| {
+            characteristic = Some(
+                svc.add_characteristic(Uuid::new_short(0x2a19), &[CharacteristicProp::Notify], 0u8, &mut store)
+                    .build(),
+            );
+        }));
+        let cccd_handle = unwrap!(unwrap!(characteristic).cccd_handle);
+        assert_eq!(service.start(), 16);
+        assert!(cccd_handle > service.start() && cccd_handle <= service.end());
+
+        // The new CCCD can be subscribed to, and the client is told about the new handles once.
+        server.cccd_tables.set_notify(&peer, cccd_handle, true);
+        assert!(server.cccd_tables.should_notify(&peer, cccd_handle));
+        assert_eq!(
+            server.cccd_tables.poll_service_changed(&peer, &mut cx),
+            Poll::Ready((service.start(), service.end()))
+        );
+        assert!(server.cccd_tables.poll_service_changed(&peer, &mut cx).is_pending());
+
+        unwrap!(server.remove_service(&service));
+        assert!(!server.cccd_tables.should_notify(&peer, cccd_handle));
+        assert_eq!(
+            server.cccd_tables.poll_service_changed(&peer, &mut cx),
+            Poll::Ready((service.start(), service.end()))
+        );
+        assert!(server.remove_service(&service).is_err());
+
+        // Running out of space leaves the table untouched.
+        let result = server.add_service(Service::new(Uuid::new_short(0x180a)), |svc| {
+            for _ in 0..8 {
+                svc.add_characteristic_ro::<[u8; 2], _>(Uuid::new_short(0x2a29), &[0, 0]);
+            }
+        });
+        assert!(matches!(result, Err(Error::InsufficientSpace)));
+        let count = server.att_table.iterate(|mut it| {
+            let mut n = 0;
+            while it.next().is_some() {
+                n += 1;
+            }
+            n
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn service_changed() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        unwrap!(GapConfig::default("trouble").build_with_service_changed(&mut table));
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 2> = AttributeServer::new(table);
+
+        // The GATT service has a Service Changed characteristic that can only be indicated.
+        let handle = unwrap!(sealed::DynamicAttributeServer::service_changed_handle(&server));
+        let characteristic = unwrap!(server.att_table.find_characteristic_by_value_handle::<[u8; 4]>(handle));
+        assert!(characteristic.cccd_handle.is_some());
+        let mut buf = [0; 4];
+        let readable = server.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == handle {
+                    return att.read(0, &mut buf).is_ok();
+                }
+            }
+            true
+        });
+        assert!(!readable);
+
+        let peer = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            ..Default::default()
+        };
+        unwrap!(server.cccd_tables.connect(&peer));
+        server.cccd_tables.disconnect(&peer);
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+
+        // Disconnected clients keep the change until they reconnect, and ranges are merged.
+        unwrap!(server.service_changed(0x20, 0x2f));
+        unwrap!(server.service_changed(0x40, 0x4f));
+        unwrap!(server.cccd_tables.connect(&peer));
+        assert_eq!(
+            server.cccd_tables.poll_service_changed(&peer, &mut cx),
+            Poll::Ready((0x20, 0x4f))
+        );
+        assert!(server.cccd_tables.poll_service_changed(&peer, &mut cx).is_pending());
+
+        assert!(server.service_changed(0, 0xffff).is_err());
+        assert!(server.service_changed(0x30, 0x20).is_err());
+    }
+
+    #[test]
+    fn notify_all() {
+        let mut store = [0u8; 1];
+        let mut read_store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let (characteristic, read_only) = {
+            let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+            let characteristic = svc
+                .add_characteristic(Uuid::new_short(0x2a19), &[CharacteristicProp::Notify], 0u8, &mut store)
+                .build();
+            let read_only = svc
+                .add_characteristic(
+                    Uuid::new_short(0x2a1a),
+                    &[CharacteristicProp::Read],
+                    0u8,
+                    &mut read_store,
+                )
+                .build();
+            (characteristic, read_only)
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let cccd_handle = unwrap!(characteristic.cccd_handle);
+
+        let subscribed = Identity {
+            bd_addr: BdAddr::new(ADDR_1),
+            ..Default::default()
+        };
+        let unsubscribed = Identity {
+            bd_addr: BdAddr::new(ADDR_2),
+            ..Default::default()
+        };
+        unwrap!(server.cccd_tables.connect(&subscribed));
+        unwrap!(server.cccd_tables.connect(&unsubscribed));
+        server.cccd_tables.set_notify(&subscribed, cccd_handle, true);
+        let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+
+        // Only subscribed clients get the notification, and repeated updates before it is sent are merged.
+        unwrap!(characteristic.notify_all(&server, &42));
+        unwrap!(characteristic.notify_all(&server, &43));
+        assert_eq!(
+            server.cccd_tables.poll_notification(&subscribed, &mut cx),
+            Poll::Ready(characteristic.handle)
+        );
+        assert!(server.cccd_tables.poll_notification(&subscribed, &mut cx).is_pending());
+        assert!(server
+            .cccd_tables
+            .poll_notification(&unsubscribed, &mut cx)
+            .is_pending());
+
+        // The notification carries the value at the time it is sent.
+        let mut value = [0; 1];
+        assert_eq!(
+            sealed::DynamicAttributeServer::read_value(&server, characteristic.handle, &mut value),
+            Ok(1)
+        );
+        assert_eq!(value, [43]);
+
+        // Disconnected clients are not notified, and lose what was queued for them.
+        unwrap!(characteristic.notify_all(&server, &44));
+        server.cccd_tables.disconnect(&subscribed);
+        unwrap!(server.cccd_tables.connect(&subscribed));
+        assert!(server.cccd_tables.poll_notification(&subscribed, &mut cx).is_pending());
+
+        // A characteristic that cannot notify keeps its value.
+        assert_eq!(read_only.notify_all(&server, &7), Err(Error::NotFound));
+        assert_eq!(unwrap!(read_only.get(&server)), 0);
+    }
+
+    #[test]
+    fn characteristic_notification_queue() {
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let characteristic = table
+            .add_service(Service::new(Uuid::new_short(0x180f)))
+            .add_characteristic(Uuid::new_short(0x2a19), &[CharacteristicProp::Notify], 0u8, &mut store)
+            .build();
+
+        let queued = |queue: &CharacteristicNotificationQueue<NoopRawMutex, u8, 2>| {
+            let mut values = heapless::Vec::<u8, 2>::new();
+            while let Ok(value) = queue.queue.try_receive() {
+                unwrap!(values.push(value));
+            }
+            values
+        };
+
+        let block = CharacteristicNotificationQueue::<NoopRawMutex, u8, 2>::new(characteristic, Default::default());
+        assert_eq!(block.policy(), NotificationQueuePolicy::Block);
+        unwrap!(block.try_push(1));
+        unwrap!(block.try_push(2));
+        assert_eq!(block.try_push(3), Err(Error::Busy));
+        assert_eq!(&queued(&block)[..], &[1, 2]);
+
+        let drop_oldest = CharacteristicNotificationQueue::<NoopRawMutex, u8, 2>::new(
+            characteristic,
+            NotificationQueuePolicy::DropOldest,
+        );
+        for value in 1..=3 {
+            unwrap!(drop_oldest.try_push(value));
+        }
+        assert_eq!(&queued(&drop_oldest)[..], &[2, 3]);
+
+        let coalesce = CharacteristicNotificationQueue::<NoopRawMutex, u8, 2>::new(
+            characteristic,
+            NotificationQueuePolicy::CoalesceLatest,
+        );
+        embassy_futures::block_on(coalesce.push(1));
+        embassy_futures::block_on(coalesce.push(2));
+        assert_eq!(coalesce.len(), 1);
+        assert_eq!(&queued(&coalesce)[..], &[2]);
+        assert!(coalesce.is_empty());
+    }
+
+    #[test]
+    fn read_multiple_variable_truncated() {
+        let mut stores = [[0u8; 4]; 2];
+        let [a_store, b_store] = &mut stores;
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let (a, b) = {
+            let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+            let props = [CharacteristicProp::Read];
+            let a = svc
+                .add_characteristic(Uuid::new_short(0x2a19), &props, [1u8, 2, 3, 4], a_store)
+                .build();
+            let b = svc
+                .add_characteristic(Uuid::new_short(0x2a1a), &props, [5u8, 6, 7, 8], b_store)
+                .build();
+            (a, b)
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 1, 1> = AttributeServer::new(table);
+
+        let mgr = setup();
+        assert!(mgr.poll_accept(LeConnRole::Peripheral, &[], None).is_pending());
+        unwrap!(mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(connection) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        let [a_lo, a_hi] = a.handle.to_le_bytes();
+        let [b_lo, b_hi] = b.handle.to_le_bytes();
+        let handles = [a_lo, a_hi, b_lo, b_hi];
+        // The second value is truncated to fit the buffer, but its tuple has the length of the whole value.
+        let mut buffer = [0u8; 11];
+        let len = unwrap!(server.handle_read_multiple(&connection, &mut buffer, &handles, true));
+        assert_eq!(
+            &buffer[..len],
+            &[att::ATT_READ_MULTIPLE_VARIABLE_RSP, 4, 0, 1, 2, 3, 4, 4, 0, 5, 6]
+        );
+    }
+
+    #[test]
+    fn authorizer() {
+        struct LoggedIn(Cell<bool>);
+
+        impl AttributeAuthorizer for LoggedIn {
+            fn authorize(&self, access: &AttributeAccess<'_>) -> Result<(), AttErrorCode> {
+                match access.operation {
+                    AttributeOperation::Read => Ok(()),
+                    AttributeOperation::Write if self.0.get() => Ok(()),
+                    AttributeOperation::Write => Err(unwrap!(AttErrorCode::application(0x80))),
+                }
+            }
+        }
+
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let characteristic = {
+            let mut svc = table.add_service(Service::new(Uuid::new_short(0x180f)));
+            svc.add_characteristic(
+                Uuid::new_short(0x2a19),
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                0u8,
+                &mut store,
+            )
+            .build()
+        };
+        let logged_in = LoggedIn(Cell::new(false));
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 1> = AttributeServer::new(table);
+        server.set_authorizer(Some(&logged_in));
+
+        let mgr = setup();
+        assert!(mgr.poll_accept(LeConnRole::Peripheral, &[], None).is_pending());
+        unwrap!(mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(connection) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        let mut buffer = [0u8; 16];
+        let handle = characteristic.handle;
+        let len = unwrap!(server.handle_read_req(&connection, &mut buffer, handle));
+        assert_eq!(&buffer[..len], &[att::ATT_READ_RSP, 0]);
+
+        // Writes are rejected with the error of the authorizer until it allows them.
+        let len = unwrap!(server.handle_write_req(&connection, &mut buffer, handle, &[1]));
+        let [lo, hi] = handle.to_le_bytes();
+        assert_eq!(&buffer[..len], &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, lo, hi, 0x80]);
+        logged_in.0.set(true);
+        let len = unwrap!(server.handle_write_req(&connection, &mut buffer, handle, &[1]));
+        assert_eq!(&buffer[..len], &[att::ATT_WRITE_RSP]);
+        assert_eq!(unwrap!(server.table().get(&characteristic)), 1);
+    }
 }
@@ -134,13 +134,7 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
     where
         C: ControllerCmdSync<LeClearFilterAcceptList> + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
-        let host = &self.stack.host;
-        host.command(LeClearFilterAcceptList::new()).await?;
-        for entry in filter_accept_list {
-            host.command(LeAddDeviceToFilterAcceptList::new(entry.0, *entry.1))
-                .await?;
-        }
-        Ok(())
+        self.stack.host.set_filter_accept_list(filter_accept_list).await
     }
 }
 
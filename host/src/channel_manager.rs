@@ -5,32 +5,74 @@ use core::task::{Context, Poll};
 use bt_hci::controller::{blocking, Controller};
 use bt_hci::param::ConnHandle;
 use bt_hci::FromHciBytes;
+use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
 
 use crate::connection_manager::ConnectionManager;
 use crate::cursor::WriteCursor;
-use crate::host::BleHost;
+use crate::host::{encode_signal, BleHost, OnDrop};
 #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
 use crate::l2cap::sar::PacketReassembly;
-use crate::l2cap::L2capChannel;
+use crate::l2cap::{L2capChannel, L2capChannelInfo, L2capChannelState};
 use crate::pdu::{Pdu, Sdu};
 use crate::prelude::{ConnectionEvent, L2capChannelConfig};
+use crate::table::Table;
 use crate::types::l2cap::{
-    CommandRejectRes, ConnParamUpdateReq, ConnParamUpdateRes, DisconnectionReq, DisconnectionRes, L2capSignalCode,
-    L2capSignalHeader, LeCreditConnReq, LeCreditConnRes, LeCreditConnResultCode, LeCreditFlowInd,
+    parse_cids, CommandRejectRes, ConnParamUpdateReq, ConnParamUpdateRes, CreditConnReconfigReq, CreditConnReconfigRes,
+    CreditConnReconfigResult, CreditConnReq, CreditConnRes, DisconnectionReq, DisconnectionRes, L2capSignalCode,
+    L2capSignalHeader, LeCreditConnReq, LeCreditConnRes, LeCreditConnResultCode, LeCreditFlowInd, L2CAP_CID_ATT,
+    L2CAP_CID_DYN_START, L2CAP_CID_LE_U_SECURITY_MANAGER, L2CAP_CID_LE_U_SIGNAL, L2CAP_ECFC_MAX_CHANNELS,
+    L2CAP_ECFC_MIN_MTU,
 };
 use crate::{config, BleHostError, Error, PacketPool};
 
 const BASE_ID: u16 = 0x40;
 
+/// Maximum number of PSMs with a registered listener.
+const L2CAP_MAX_LISTENERS: usize = 8;
+const L2CAP_MAX_ACCEPTS: usize = 8;
+
+/// Maximum number of fixed channels opened by the application.
+const L2CAP_MAX_FIXED_CHANNELS: usize = 4;
+
 struct State<'d, P> {
     next_req_id: u8,
-    channels: &'d mut [ChannelStorage<P>],
+    channels: Table<'d, ChannelStorage<P>>,
     accept_waker: WakerRegistration,
     create_waker: WakerRegistration,
     disconnect_waker: WakerRegistration,
+    idle_waker: WakerRegistration,
+    reconfig_waker: WakerRegistration,
+    /// Responses to reconfigure requests of peers, waiting to be sent.
+    signal_responses: heapless::Deque<SignalResponse, 4>,
+    signal_response_waker: WakerRegistration,
+    /// PSMs with a registered listener.
+    listeners: heapless::Vec<u16, L2CAP_MAX_LISTENERS>,
+    /// Connections and PSMs with a pending accept.
+    accepting: heapless::Vec<(ConnHandle, u16), L2CAP_MAX_ACCEPTS>,
+    /// Fixed channels opened by the application.
+    fixed: [FixedChannelStorage<P>; L2CAP_MAX_FIXED_CHANNELS],
+}
+
+/// A fixed channel of a connection, with the PDUs received on it.
+struct FixedChannelStorage<P> {
+    conn: Option<ConnHandle>,
+    /// Channel id, 0 if the slot is free.
+    cid: u16,
+    inbound: PacketChannel<P, { config::L2CAP_RX_QUEUE_SIZE }>,
+}
+
+impl<P> FixedChannelStorage<P> {
+    const fn new() -> Self {
+        Self {
+            conn: None,
+            cid: 0,
+            inbound: PacketChannel::new(),
+        }
+    }
 }
 
 /// Channel manager for L2CAP channels used directly by clients.
@@ -67,9 +109,18 @@ impl<P, const QLEN: usize> PacketChannel<P, QLEN> {
         self.chan.poll_receive(cx)
     }
 
+    /// Receive a queued PDU, `Some(None)` if the channel was closed and `None` if the queue is empty.
+    pub fn try_receive(&self) -> Option<Option<Pdu<P>>> {
+        self.chan.try_receive().ok()
+    }
+
     pub fn clear(&self) {
         self.chan.clear()
     }
+
+    pub fn len(&self) -> usize {
+        self.chan.len()
+    }
 }
 
 impl<P> State<'_, P> {
@@ -94,10 +145,18 @@ impl<P> State<'_, P> {
         let state = &mut self.channels[index.0 as usize];
         state.refcount = unwrap!(state.refcount.checked_add(1), "Too many references to the same channel");
     }
+
+    fn next_idle_deadline(&self) -> Option<Instant> {
+        self.channels
+            .iter()
+            .filter(|chan| chan.state == ChannelState::Connected)
+            .filter_map(|chan| chan.idle_timeout.map(|timeout| chan.last_activity + timeout))
+            .min()
+    }
 }
 
 impl<'d, P: PacketPool> ChannelManager<'d, P> {
-    pub fn new(channels: &'d mut [ChannelStorage<P::Packet>]) -> Self {
+    pub(crate) fn new(channels: Table<'d, ChannelStorage<P::Packet>>) -> Self {
         Self {
             state: RefCell::new(State {
                 next_req_id: 0,
@@ -105,6 +164,13 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 accept_waker: WakerRegistration::new(),
                 create_waker: WakerRegistration::new(),
                 disconnect_waker: WakerRegistration::new(),
+                idle_waker: WakerRegistration::new(),
+                reconfig_waker: WakerRegistration::new(),
+                signal_responses: heapless::Deque::new(),
+                signal_response_waker: WakerRegistration::new(),
+                listeners: heapless::Vec::new(),
+                accepting: heapless::Vec::new(),
+                fixed: [const { FixedChannelStorage::new() }; L2CAP_MAX_FIXED_CHANNELS],
             }),
         }
     }
@@ -113,6 +179,19 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         self.state.borrow_mut().next_request_id()
     }
 
+    pub(crate) fn info(&self, index: ChannelIndex) -> L2capChannelInfo {
+        self.with_mut(|state| {
+            let chan = &state.channels[index.0 as usize];
+            L2capChannelInfo {
+                psm: chan.psm,
+                peer_mtu: chan.peer_mtu,
+                peer_mps: chan.peer_mps,
+                mps: chan.mps,
+                initial_credits: chan.initial_peer_credits,
+            }
+        })
+    }
+
     pub(crate) fn psm(&self, index: ChannelIndex) -> u16 {
         self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
@@ -120,11 +199,42 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         })
     }
 
+    pub(crate) fn mtu(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| state.channels[index.0 as usize].mtu)
+    }
+
+    pub(crate) fn mps(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| state.channels[index.0 as usize].mps)
+    }
+
+    pub(crate) fn tx_credits(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| state.channels[index.0 as usize].peer_credits)
+    }
+
+    pub(crate) fn flow_policy(&self, index: ChannelIndex) -> CreditFlowPolicy {
+        self.with_mut(|state| state.channels[index.0 as usize].flow_control.policy)
+    }
+
+    pub(crate) fn set_idle_timeout(&self, index: ChannelIndex, timeout: Option<Duration>) {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            chan.idle_timeout = timeout;
+            chan.last_activity = Instant::now();
+            state.idle_waker.wake();
+        })
+    }
+
+    pub(crate) fn set_rx_quota(&self, index: ChannelIndex, quota: Option<u16>) {
+        self.with_mut(|state| state.channels[index.0 as usize].rx_quota = quota)
+    }
+
     pub(crate) fn disconnect(&self, index: ChannelIndex) {
         self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
             if chan.state == ChannelState::Connected {
                 chan.state = ChannelState::Disconnecting;
+                chan.state_waker.wake();
+                chan.readable_waker.wake();
                 let _ = chan.inbound.close();
                 #[cfg(feature = "channel-metrics")]
                 chan.metrics.reset();
@@ -133,6 +243,27 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         })
     }
 
+    pub(crate) fn channel_state(&self, index: ChannelIndex) -> L2capChannelState {
+        self.with_mut(|state| state.channels[index.0 as usize].public_state())
+    }
+
+    /// Poll for the channel to leave the state `from`, returning the new state.
+    pub(crate) fn poll_state_changed(
+        &self,
+        index: ChannelIndex,
+        from: L2capChannelState,
+        cx: &mut Context<'_>,
+    ) -> Poll<L2capChannelState> {
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[index.0 as usize];
+        let current = chan.public_state();
+        if current != from || current == L2capChannelState::Disconnected {
+            return Poll::Ready(current);
+        }
+        chan.state_waker.register(cx.waker());
+        Poll::Pending
+    }
+
     pub(crate) fn disconnected(&self, conn: ConnHandle) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
         for storage in state.channels.iter_mut() {
@@ -145,27 +276,184 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 storage.close();
             }
         }
+        for fixed in state.fixed.iter_mut() {
+            if fixed.cid != 0 && fixed.conn == Some(conn) {
+                let _ = fixed.inbound.close();
+                fixed.conn = None;
+            }
+        }
         state.accept_waker.wake();
         state.create_waker.wake();
+        state.reconfig_waker.wake();
         Ok(())
     }
 
     fn alloc<F: FnOnce(&mut ChannelStorage<P::Packet>)>(&self, conn: ConnHandle, f: F) -> Result<ChannelIndex, Error> {
         let mut state = self.state.borrow_mut();
-        for (idx, storage) in state.channels.iter_mut().enumerate() {
-            if ChannelState::Disconnected == storage.state && storage.refcount == 0 {
-                // Ensure inbound is empty.
-                storage.inbound.clear();
-                #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
-                storage.reassembly.clear();
-                let cid: u16 = BASE_ID + idx as u16;
-                storage.conn = Some(conn);
-                storage.cid = cid;
-                f(storage);
-                return Ok(ChannelIndex(idx as u8));
+        let free = state
+            .channels
+            .iter()
+            .position(|storage| ChannelState::Disconnected == storage.state && storage.refcount == 0);
+        let idx = free
+            .or_else(|| state.channels.grow(ChannelStorage::new))
+            .ok_or(Error::NoChannelAvailable)?;
+        let storage = &mut state.channels[idx];
+        // Ensure inbound is empty.
+        storage.inbound.clear();
+        storage.peeked = None;
+        #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
+        storage.reassembly.clear();
+        let cid: u16 = BASE_ID + idx as u16;
+        storage.conn = Some(conn);
+        storage.cid = cid;
+        storage.reader_free = false;
+        storage.writer_free = false;
+        f(storage);
+        Ok(ChannelIndex(idx as u8))
+    }
+
+    /// Open the fixed channel `cid` of a connection for the application.
+    ///
+    /// The channels used by the host itself and the dynamically allocated range cannot be opened.
+    /// Allocate a connected channel with `credits` to send, for tests of the channel endpoints.
+    #[cfg(test)]
+    pub(crate) fn alloc_connected(&self, conn: ConnHandle, mtu: u16, mps: u16, credits: u16) -> ChannelIndex {
+        self.alloc(conn, |storage| {
+            storage.state = ChannelState::Connected;
+            storage.peer_cid = 0x40;
+            storage.mtu = mtu;
+            storage.mps = mps;
+            storage.peer_credits = credits;
+            storage.flow_control = CreditFlowControl::new(CreditFlowPolicy::Manual, 8);
+            storage.refcount = 1;
+        })
+        .unwrap()
+    }
+
+    pub(crate) fn open_fixed(&self, conn: ConnHandle, cid: u16) -> Result<ChannelIndex, Error> {
+        if cid == 0
+            || cid >= L2CAP_CID_DYN_START
+            || [L2CAP_CID_ATT, L2CAP_CID_LE_U_SIGNAL, L2CAP_CID_LE_U_SECURITY_MANAGER].contains(&cid)
+        {
+            return Err(Error::InvalidChannelId);
+        }
+        let mut state = self.state.borrow_mut();
+        if state
+            .fixed
+            .iter()
+            .any(|fixed| fixed.cid == cid && fixed.conn == Some(conn))
+        {
+            return Err(Error::Busy);
+        }
+        let index = state
+            .fixed
+            .iter()
+            .position(|fixed| fixed.cid == 0)
+            .ok_or(Error::NoChannelAvailable)?;
+        let fixed = &mut state.fixed[index];
+        fixed.inbound.clear();
+        fixed.conn = Some(conn);
+        fixed.cid = cid;
+        Ok(ChannelIndex(index as u8))
+    }
+
+    pub(crate) fn close_fixed(&self, index: ChannelIndex) {
+        let mut state = self.state.borrow_mut();
+        let fixed = &mut state.fixed[index.0 as usize];
+        fixed.inbound.clear();
+        fixed.conn = None;
+        fixed.cid = 0;
+    }
+
+    /// Returns true if the application opened the fixed channel `cid` of the connection.
+    pub(crate) fn has_fixed(&self, conn: ConnHandle, cid: u16) -> bool {
+        let state = self.state.borrow();
+        state
+            .fixed
+            .iter()
+            .any(|fixed| fixed.cid == cid && fixed.conn == Some(conn))
+    }
+
+    /// Queue a PDU received on a fixed channel opened by the application.
+    ///
+    /// Fixed channels have no flow control, so the PDU is dropped if the queue is full.
+    pub(crate) fn dispatch_fixed(&self, conn: ConnHandle, cid: u16, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        let state = self.state.borrow();
+        let fixed = state
+            .fixed
+            .iter()
+            .find(|fixed| fixed.cid == cid && fixed.conn == Some(conn))
+            .ok_or(Error::NotFound)?;
+        if fixed.inbound.try_send(pdu).is_err() {
+            warn!("[l2cap][cid = {}] fixed channel queue full, dropping PDU", cid);
+        }
+        Ok(())
+    }
+
+    /// Receive the next PDU on a fixed channel.
+    pub(crate) async fn receive_fixed(&self, index: ChannelIndex) -> Result<Pdu<P::Packet>, Error> {
+        poll_fn(|cx| {
+            let state = self.state.borrow();
+            let fixed = &state.fixed[index.0 as usize];
+            if fixed.conn.is_none() {
+                return Poll::Ready(Err(Error::Disconnected));
+            }
+            match fixed.inbound.poll_receive(cx) {
+                Poll::Ready(Some(pdu)) => Poll::Ready(Ok(pdu)),
+                Poll::Ready(None) => Poll::Ready(Err(Error::Disconnected)),
+                Poll::Pending => Poll::Pending,
             }
+        })
+        .await
+    }
+
+    /// Send a PDU on a fixed channel.
+    pub(crate) async fn send_fixed<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        data: &[u8],
+        p_buf: &mut [u8],
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, cid) = {
+            let state = self.state.borrow();
+            let fixed = &state.fixed[index.0 as usize];
+            (fixed.conn.ok_or(Error::Disconnected)?, fixed.cid)
+        };
+        let len = encode(data, p_buf, cid, None)?;
+        ble.l2cap(conn, data.len() as u16, 1).await?.send(&p_buf[..len]).await
+    }
+
+    /// Register a listener for the PSM.
+    pub(crate) fn listen(&self, psm: u16) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        if state.listeners.contains(&psm) {
+            return Err(Error::Busy);
+        }
+        state.listeners.push(psm).map_err(|_| Error::OutOfMemory)
+    }
+
+    pub(crate) fn unlisten(&self, psm: u16) {
+        self.state.borrow_mut().listeners.retain(|listener| *listener != psm);
+    }
+
+    /// Register a pending accept for the PSMs on a connection, until the returned guard is dropped.
+    fn register_accept<'a>(&'a self, conn: ConnHandle, psm: &'a [u16]) -> Result<OnDrop<impl FnOnce() + 'a>, Error> {
+        let mut state = self.state.borrow_mut();
+        if state.accepting.len() + psm.len() > state.accepting.capacity() {
+            return Err(Error::OutOfMemory);
+        }
+        for psm in psm {
+            let _ = state.accepting.push((conn, *psm));
         }
-        Err(Error::NoChannelAvailable)
+        Ok(OnDrop::new(move || {
+            let mut state = self.state.borrow_mut();
+            for psm in psm {
+                if let Some(pos) = state.accepting.iter().position(|entry| *entry == (conn, *psm)) {
+                    state.accepting.swap_remove(pos);
+                }
+            }
+        }))
     }
 
     pub(crate) async fn accept<T: Controller>(
@@ -175,33 +463,51 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         config: &L2capChannelConfig,
         ble: &BleHost<'d, T, P>,
     ) -> Result<L2capChannel<'d, P>, BleHostError<T::Error>> {
+        let (_, channel) = self.accept_from(Some(conn), psm, config, ble).await?;
+        Ok(channel)
+    }
+
+    /// Accept a connection request for one of the PSMs, on the given connection or on any connection.
+    ///
+    /// A request is taken by an accept pending on its connection before a listener for its PSM.
+    pub(crate) async fn accept_from<T: Controller>(
+        &'d self,
+        conn: Option<ConnHandle>,
+        psm: &[u16],
+        config: &L2capChannelConfig,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(ConnHandle, L2capChannel<'d, P>), BleHostError<T::Error>> {
         let L2capChannelConfig {
-            mtu,
-            mps,
-            flow_policy,
-            initial_credits,
+            mtu, mps, flow_policy, ..
         } = config;
 
+        config.validate::<P>()?;
+        if self.state.borrow().channels.max_len() == 0 {
+            return Err(Error::InvalidConfiguration("no L2CAP channels configured in HostResources").into());
+        }
         let mtu = mtu.unwrap_or(P::MTU as u16 - 6);
         let mps = mps.unwrap_or(P::MTU as u16 - 4);
-        if mps > P::MTU as u16 - 4 {
-            return Err(Error::InsufficientSpace.into());
-        }
+        let _accepting = conn.map(|conn| self.register_accept(conn, psm)).transpose()?;
 
         // Wait until we find a channel for our connection in the connecting state matching our PSM.
-        let (channel, req_id, mps, mtu, cid, credits) = poll_fn(|cx| {
+        let (channel, conn, req_id, mps, mtu, cid, credits) = poll_fn(|cx| {
             let mut state = self.state.borrow_mut();
             state.accept_waker.register(cx.waker());
+            let accepting = state.accepting.clone();
+            let matches = |chan_conn: ConnHandle, chan_psm: u16| match conn {
+                Some(conn) => conn == chan_conn,
+                None => !accepting.contains(&(chan_conn, chan_psm)),
+            };
             for (idx, chan) in state.channels.iter_mut().enumerate() {
-                match chan.state {
-                    ChannelState::PeerConnecting(req_id) if chan.conn == Some(conn) && psm.contains(&chan.psm) => {
+                match (chan.state.clone(), chan.conn) {
+                    (ChannelState::PeerConnecting(req_id), Some(chan_conn))
+                        if matches(chan_conn, chan.psm) && !chan.enhanced && psm.contains(&chan.psm) =>
+                    {
                         chan.mtu = chan.mtu.min(mtu);
                         chan.mps = chan.mps.min(mps);
-                        chan.flow_control = CreditFlowControl::new(
-                            *flow_policy,
-                            initial_credits.unwrap_or(config::L2CAP_RX_QUEUE_SIZE.min(P::capacity()) as u16),
-                        );
+                        chan.flow_control = CreditFlowControl::new(*flow_policy, config.credits::<P>());
                         chan.state = ChannelState::Connected;
+                        chan.last_activity = Instant::now();
                         let mps = chan.mps;
                         let mtu = chan.mtu;
                         let cid = chan.cid;
@@ -213,8 +519,10 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                         assert_eq!(chan.refcount, 0);
                         let index = ChannelIndex(idx as u8);
 
+                        state.idle_waker.wake();
                         state.inc_ref(index);
-                        return Poll::Ready((L2capChannel::new(index, self), req_id, mps, mtu, cid, available));
+                        let channel = L2capChannel::new(index, self);
+                        return Poll::Ready((channel, chan_conn, req_id, mps, mtu, cid, available));
                     }
                     _ => {}
                 }
@@ -238,7 +546,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             &mut tx[..],
         )
         .await?;
-        Ok(channel)
+        Ok((conn, channel))
     }
 
     pub(crate) async fn create<T: Controller>(
@@ -249,26 +557,24 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         ble: &BleHost<'_, T, P>,
     ) -> Result<L2capChannel<'d, P>, BleHostError<T::Error>> {
         let L2capChannelConfig {
-            mtu,
-            mps,
-            flow_policy,
-            initial_credits,
+            mtu, mps, flow_policy, ..
         } = config;
 
         let req_id = self.next_request_id();
         let mut credits = 0;
         let mut cid: u16 = 0;
 
+        config.validate::<P>()?;
+        if self.state.borrow().channels.max_len() == 0 {
+            return Err(Error::InvalidConfiguration("no L2CAP channels configured in HostResources").into());
+        }
         let mtu = mtu.unwrap_or(P::MTU as u16 - 6);
         let mps = mps.unwrap_or(P::MTU as u16 - 4);
-        if mps > P::MTU as u16 - 4 {
-            return Err(Error::InsufficientSpace.into());
-        }
 
         // Allocate space for our new channel.
         let idx = self.alloc(conn, |storage| {
             cid = storage.cid;
-            credits = initial_credits.unwrap_or(config::L2CAP_RX_QUEUE_SIZE.min(P::capacity()) as u16);
+            credits = config.credits::<P>();
             storage.psm = psm;
             storage.mtu = mtu;
             storage.mps = mps;
@@ -291,6 +597,209 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         poll_fn(|cx| self.poll_created(conn, idx, ble, Some(cx))).await
     }
 
+    /// Await a credit based connection request from the peer in enhanced credit based flow control mode, and
+    /// accept up to `max` of the requested channels.
+    pub(crate) async fn accept_enhanced<T: Controller>(
+        &'d self,
+        conn: ConnHandle,
+        psm: &[u16],
+        max: usize,
+        config: &L2capChannelConfig,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<heapless::Vec<L2capChannel<'d, P>, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        config.validate::<P>()?;
+        if self.state.borrow().channels.max_len() == 0 {
+            return Err(Error::InvalidConfiguration("no L2CAP channels configured in HostResources").into());
+        }
+        let accepted = poll_fn(|cx| self.poll_accept_enhanced(conn, psm, max, config, Some(cx))).await;
+
+        let mtu = config.mtu.unwrap_or(P::MTU as u16 - 6);
+        let mps = config.mps.unwrap_or(P::MTU as u16 - 4);
+        let credits = config.credits::<P>();
+        let result = if accepted.dcids.iter().all(|cid| *cid != 0) {
+            LeCreditConnResultCode::Success
+        } else {
+            LeCreditConnResultCode::NoResources
+        };
+        let mut tx = [0; 32];
+        ble.l2cap_signal_with_cids(
+            conn,
+            accepted.identifier,
+            &CreditConnRes {
+                mtu,
+                mps,
+                credits,
+                result: result as u16,
+            },
+            &accepted.dcids,
+            &mut tx[..],
+        )
+        .await?;
+        Ok(accepted.channels)
+    }
+
+    /// Take the channels of a pending enhanced credit based connection request for one of the `psm`, accepting
+    /// up to `max` of them.
+    fn poll_accept_enhanced(
+        &'d self,
+        conn: ConnHandle,
+        psm: &[u16],
+        max: usize,
+        config: &L2capChannelConfig,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<EnhancedAccept<'d, P>> {
+        let mtu = config.mtu.unwrap_or(P::MTU as u16 - 6);
+        let mps = config.mps.unwrap_or(P::MTU as u16 - 4);
+        let credits = config.credits::<P>();
+
+        let mut state = self.state.borrow_mut();
+        if let Some(cx) = cx {
+            state.accept_waker.register(cx.waker());
+        }
+        let req_id = state.channels.iter().find_map(|chan| match chan.state {
+            ChannelState::PeerConnecting(req_id)
+                if chan.conn == Some(conn) && chan.enhanced && psm.contains(&chan.psm) =>
+            {
+                Some(req_id)
+            }
+            _ => None,
+        });
+        let Some(req_id) = req_id else {
+            return Poll::Pending;
+        };
+        let mut channels = heapless::Vec::new();
+        let mut dcids = heapless::Vec::new();
+        let mut requested = 0;
+        for idx in 0..state.channels.len() {
+            let chan = &mut state.channels[idx];
+            if chan.state != ChannelState::PeerConnecting(req_id) || chan.conn != Some(conn) || !chan.enhanced {
+                continue;
+            }
+            requested = chan.requested as usize;
+            if channels.len() >= max {
+                // Refused, the peer is told with a zero destination CID.
+                chan.close();
+                let _ = dcids.push(0);
+                continue;
+            }
+            // SDUs are sent with the values of the peer, received with ours.
+            chan.local_mtu = mtu;
+            chan.local_mps = mps;
+            chan.mps = chan.peer_mps.min(P::MTU as u16 - 4);
+            chan.flow_control = CreditFlowControl::new(config.flow_policy, credits);
+            chan.state = ChannelState::Connected;
+            chan.last_activity = Instant::now();
+            let _ = dcids.push(chan.cid);
+            let index = ChannelIndex(idx as u8);
+            state.inc_ref(index);
+            let _ = channels.push(L2capChannel::new(index, self));
+        }
+        // Channels that could not be allocated when the request was received are refused too.
+        while dcids.len() < requested {
+            let _ = dcids.push(0);
+        }
+        state.idle_waker.wake();
+        Poll::Ready(EnhancedAccept {
+            identifier: req_id,
+            channels,
+            dcids,
+        })
+    }
+
+    /// Open `count` channels in enhanced credit based flow control mode with a single credit based
+    /// connection request.
+    ///
+    /// Returns the channels accepted by the peer, which may be fewer than requested.
+    pub(crate) async fn create_enhanced<T: Controller>(
+        &'d self,
+        conn: ConnHandle,
+        psm: u16,
+        count: usize,
+        config: &L2capChannelConfig,
+        ble: &BleHost<'_, T, P>,
+    ) -> Result<heapless::Vec<L2capChannel<'d, P>, L2CAP_ECFC_MAX_CHANNELS>, BleHostError<T::Error>> {
+        if count == 0 || count > L2CAP_ECFC_MAX_CHANNELS {
+            return Err(Error::InvalidValue.into());
+        }
+        config.validate::<P>()?;
+        if self.state.borrow().channels.max_len() == 0 {
+            return Err(Error::InvalidConfiguration("no L2CAP channels configured in HostResources").into());
+        }
+        let mtu = config.mtu.unwrap_or(P::MTU as u16 - 6);
+        let mps = config.mps.unwrap_or(P::MTU as u16 - 4);
+        let credits = config.credits::<P>();
+        let req_id = self.next_request_id();
+
+        let mut indices: heapless::Vec<ChannelIndex, L2CAP_ECFC_MAX_CHANNELS> = heapless::Vec::new();
+        let mut scids: heapless::Vec<u16, L2CAP_ECFC_MAX_CHANNELS> = heapless::Vec::new();
+        for _ in 0..count {
+            let mut cid = 0;
+            let allocated = self.alloc(conn, |storage| {
+                cid = storage.cid;
+                storage.psm = psm;
+                storage.mtu = mtu;
+                storage.mps = mps;
+                storage.local_mtu = mtu;
+                storage.local_mps = mps;
+                storage.flow_control = CreditFlowControl::new(config.flow_policy, credits);
+                storage.enhanced = true;
+                storage.state = ChannelState::Connecting(req_id);
+            });
+            match allocated {
+                Ok(index) => {
+                    let _ = indices.push(index);
+                    let _ = scids.push(cid);
+                }
+                Err(e) => {
+                    self.with_mut(|state| {
+                        for index in indices.iter() {
+                            state.channels[index.0 as usize].close();
+                        }
+                    });
+                    return Err(e.into());
+                }
+            }
+        }
+
+        let mut tx = [0; 32];
+        let command = CreditConnReq {
+            spsm: psm,
+            mtu,
+            mps,
+            credits,
+        };
+        ble.l2cap_signal_with_cids(conn, req_id, &command, &scids, &mut tx[..])
+            .await?;
+
+        // Wait until the response has been received.
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.create_waker.register(cx.waker());
+            if !ble.connections.is_handle_connected(conn) {
+                return Poll::Ready(Err(Error::Disconnected.into()));
+            }
+            let pending = indices
+                .iter()
+                .any(|index| state.channels[index.0 as usize].state == ChannelState::Connecting(req_id));
+            if pending {
+                return Poll::Pending;
+            }
+            let mut channels = heapless::Vec::new();
+            for index in indices.iter() {
+                if state.channels[index.0 as usize].state == ChannelState::Connected {
+                    state.inc_ref(*index);
+                    let _ = channels.push(L2capChannel::new(*index, self));
+                }
+            }
+            if channels.is_empty() {
+                Poll::Ready(Err(Error::NotSupported.into()))
+            } else {
+                Poll::Ready(Ok(channels))
+            }
+        })
+        .await
+    }
+
     fn poll_created<T: Controller>(
         &'d self,
         conn: ConnHandle,
@@ -353,6 +862,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                         return Err(Error::OutOfMemory);
                     }
                     storage.flow_control.confirm_received(1);
+                    storage.last_activity = Instant::now();
                     #[cfg(feature = "channel-metrics")]
                     storage.metrics.received(1);
                     return Ok(());
@@ -378,6 +888,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             let storage = &mut state.channels[chan];
             match storage.state {
                 ChannelState::Connected if channel == storage.cid => {
+                    storage.last_activity = Instant::now();
                     // Reassembly and accounting is already done
                     #[cfg(feature = "l2cap-sdu-reassembly-optimization")]
                     sdu.replace(pdu);
@@ -422,7 +933,12 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             }
 
             if let Some(sdu) = sdu {
+                #[cfg(feature = "channel-metrics")]
+                let len = sdu.len();
                 storage.inbound.try_send(sdu)?;
+                storage.readable_waker.wake();
+                #[cfg(feature = "channel-metrics")]
+                storage.metrics.sdu_received(len, storage.queued() as usize);
             }
 
             Ok(())
@@ -446,12 +962,31 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         match header.code {
             L2capSignalCode::LeCreditConnReq => {
                 let req = LeCreditConnReq::from_hci_bytes_complete(data)?;
-                self.handle_connect_request(conn, header.identifier, &req)?;
+                self.handle_connect_request(conn, header.identifier, &req, manager)?;
             }
             L2capSignalCode::LeCreditConnRes => {
                 let res = LeCreditConnRes::from_hci_bytes_complete(data)?;
                 self.handle_connect_response(conn, header.identifier, &res)?;
             }
+            L2capSignalCode::CreditConnReq => {
+                let (req, cids) = CreditConnReq::from_hci_bytes(data)?;
+                let cids = parse_cids(cids)?;
+                self.handle_enhanced_connect_request(conn, header.identifier, &req, &cids, manager)?;
+            }
+            L2capSignalCode::CreditConnRes => {
+                let (res, cids) = CreditConnRes::from_hci_bytes(data)?;
+                let cids = parse_cids(cids)?;
+                self.handle_enhanced_connect_response(conn, header.identifier, &res, &cids)?;
+            }
+            L2capSignalCode::CreditConnReconfigReq => {
+                let (req, cids) = CreditConnReconfigReq::from_hci_bytes(data)?;
+                let cids = parse_cids(cids)?;
+                self.handle_reconfigure_request(conn, header.identifier, &req, &cids, manager)?;
+            }
+            L2capSignalCode::CreditConnReconfigRes => {
+                let res = CreditConnReconfigRes::from_hci_bytes_complete(data)?;
+                self.handle_reconfigure_response(conn, header.identifier, &res)?;
+            }
             L2capSignalCode::LeCreditFlowInd => {
                 let req = LeCreditFlowInd::from_hci_bytes_complete(data)?;
                 //trace!("[l2cap] credit flow: {:?}", req);
@@ -502,18 +1037,51 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
-    fn handle_connect_request(&self, conn: ConnHandle, identifier: u8, req: &LeCreditConnReq) -> Result<(), Error> {
-        self.alloc(conn, |storage| {
-            storage.conn = Some(conn);
-            storage.psm = req.psm;
-            storage.peer_cid = req.scid;
-            storage.peer_credits = req.credits;
-            storage.mps = req.mps;
-            storage.mtu = req.mtu;
-            storage.state = ChannelState::PeerConnecting(identifier);
-        })?;
-        self.state.borrow_mut().accept_waker.wake();
-        Ok(())
+    fn handle_connect_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &LeCreditConnReq,
+        manager: &ConnectionManager<'_, P>,
+    ) -> Result<(), Error> {
+        // Requests are held for a listener of the PSM or an accept pending on the connection, and refused
+        // when there is none.
+        let expected = {
+            let state = self.state.borrow();
+            state.listeners.contains(&req.psm) || state.accepting.contains(&(conn, req.psm))
+        };
+        let allocated = expected.then(|| {
+            self.alloc(conn, |storage| {
+                storage.conn = Some(conn);
+                storage.psm = req.psm;
+                storage.peer_cid = req.scid;
+                storage.peer_credits = req.credits;
+                storage.initial_peer_credits = req.credits;
+                storage.mps = req.mps;
+                storage.mtu = req.mtu;
+                storage.peer_mps = req.mps;
+                storage.peer_mtu = req.mtu;
+                storage.state = ChannelState::PeerConnecting(identifier);
+            })
+        });
+        let result = match allocated {
+            Some(Ok(_)) => {
+                self.state.borrow_mut().accept_waker.wake();
+                return Ok(());
+            }
+            Some(Err(_)) => LeCreditConnResultCode::NoResources,
+            None => LeCreditConnResultCode::SpsmNotSupported,
+        };
+        warn!(
+            "[l2cap][conn = {:?}] refusing connection request for psm {}: {:?}",
+            conn, req.psm, result
+        );
+        let response = SignalResponse {
+            handle: conn,
+            identifier,
+            kind: SignalResponseKind::RefuseLe(result),
+        };
+        Self::respond(&mut self.state.borrow_mut(), manager, response)
     }
 
     fn handle_connect_response(&self, conn: ConnHandle, identifier: u8, res: &LeCreditConnRes) -> Result<(), Error> {
@@ -526,10 +1094,15 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                         ChannelState::Connecting(req_id) if identifier == req_id && Some(conn) == storage.conn => {
                             storage.peer_cid = res.dcid;
                             storage.peer_credits = res.credits;
+                            storage.initial_peer_credits = res.credits;
+                            storage.peer_mps = res.mps;
+                            storage.peer_mtu = res.mtu;
                             storage.mps = storage.mps.min(res.mps);
                             storage.mtu = storage.mtu.min(res.mtu);
                             storage.state = ChannelState::Connected;
+                            storage.last_activity = Instant::now();
                             state.create_waker.wake();
+                            state.idle_waker.wake();
                             return Ok(());
                         }
                         _ => {}
@@ -549,35 +1122,243 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         }
     }
 
-    fn handle_credit_flow(&self, conn: ConnHandle, req: &LeCreditFlowInd) -> Result<(), Error> {
-        let mut state = self.state.borrow_mut();
-        for storage in state.channels.iter_mut() {
-            match storage.state {
-                ChannelState::Connected if storage.peer_cid == req.cid && Some(conn) == storage.conn => {
-                    trace!(
-                        "[l2cap][handle_credit_flow][cid = {}] {} += {} credits",
-                        req.cid,
-                        storage.peer_credits,
-                        req.credits
-                    );
-                    storage.peer_credits = storage.peer_credits.saturating_add(req.credits);
-                    storage.credit_waker.wake();
-                    return Ok(());
-                }
-                _ => {}
+    fn handle_enhanced_connect_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &CreditConnReq,
+        cids: &[u16],
+        manager: &ConnectionManager<'_, P>,
+    ) -> Result<(), Error> {
+        let mut allocated = 0;
+        for cid in cids {
+            let result = self.alloc(conn, |storage| {
+                storage.psm = req.spsm;
+                storage.peer_cid = *cid;
+                storage.peer_credits = req.credits;
+                storage.initial_peer_credits = req.credits;
+                storage.mps = req.mps;
+                storage.mtu = req.mtu;
+                storage.peer_mps = req.mps;
+                storage.peer_mtu = req.mtu;
+                storage.enhanced = true;
+                storage.requested = cids.len() as u8;
+                storage.state = ChannelState::PeerConnecting(identifier);
+            });
+            if result.is_ok() {
+                allocated += 1;
             }
         }
-        //    trace!("[l2cap][handle_credit_flow] peer channel {} not found", req.cid);
-        Err(Error::NotFound)
+        let mut state = self.state.borrow_mut();
+        if allocated == 0 {
+            // Nothing waits for these channels, so the peer is told right away.
+            warn!(
+                "[l2cap][conn = {:?}] no channel available for enhanced connection request",
+                conn
+            );
+            let response = SignalResponse {
+                handle: conn,
+                identifier,
+                kind: SignalResponseKind::Refuse {
+                    count: cids.len() as u8,
+                    result: LeCreditConnResultCode::NoResources,
+                },
+            };
+            return Self::respond(&mut state, manager, response);
+        }
+        state.accept_waker.wake();
+        Ok(())
     }
 
-    fn handle_disconnect_request(&self, cid: u16) -> Result<(), Error> {
-        let mut state = self.state.borrow_mut();
+    fn handle_enhanced_connect_response(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        res: &CreditConnRes,
+        cids: &[u16],
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let mut cids = cids.iter();
+        let mut found = false;
+        for storage in state.channels.iter_mut() {
+            match storage.state {
+                ChannelState::Connecting(req_id) if identifier == req_id && Some(conn) == storage.conn => {
+                    found = true;
+                    match cids.next() {
+                        Some(dcid) if *dcid != 0 => {
+                            storage.peer_cid = *dcid;
+                            storage.peer_credits = res.credits;
+                            storage.initial_peer_credits = res.credits;
+                            storage.peer_mps = res.mps;
+                            storage.peer_mtu = res.mtu;
+                            storage.mps = res.mps.min(P::MTU as u16 - 4);
+                            storage.mtu = res.mtu;
+                            storage.state = ChannelState::Connected;
+                            storage.last_activity = Instant::now();
+                        }
+                        _ => {
+                            debug!(
+                                "[l2cap][cid = {}] channel refused by peer, result {}",
+                                storage.cid, res.result
+                            );
+                            storage.close();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        state.create_waker.wake();
+        state.idle_waker.wake();
+        if found {
+            Ok(())
+        } else {
+            Err(Error::NotFound)
+        }
+    }
+
+    fn handle_reconfigure_request(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        req: &CreditConnReconfigReq,
+        cids: &[u16],
+        manager: &ConnectionManager<'_, P>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        let matches = |chan: &ChannelStorage<P::Packet>, cid: u16| {
+            chan.conn == Some(conn) && chan.enhanced && chan.state == ChannelState::Connected && chan.peer_cid == cid
+        };
+        // Without room to queue the response, it is sent right away and may be lost, so the request is
+        // refused rather than applied without the peer knowing.
+        let mut result = if state.signal_responses.is_full()
+            || req.mtu < L2CAP_ECFC_MIN_MTU
+            || req.mps < L2CAP_ECFC_MIN_MTU
+            || cids.is_empty()
+        {
+            CreditConnReconfigResult::UnacceptableParameters
+        } else {
+            CreditConnReconfigResult::Success
+        };
+        for cid in cids {
+            if result != CreditConnReconfigResult::Success {
+                break;
+            }
+            result = match state.channels.iter().find(|chan| matches(chan, *cid)) {
+                None => CreditConnReconfigResult::InvalidDcid,
+                Some(chan) if req.mtu < chan.peer_mtu => CreditConnReconfigResult::MtuReductionNotAllowed,
+                // The MPS may only be reduced when reconfiguring a single channel.
+                Some(chan) if cids.len() > 1 && req.mps < chan.peer_mps => {
+                    CreditConnReconfigResult::MpsReductionNotAllowed
+                }
+                Some(_) => CreditConnReconfigResult::Success,
+            };
+        }
+        if result == CreditConnReconfigResult::Success {
+            for chan in state.channels.iter_mut() {
+                if cids.iter().any(|cid| matches(&*chan, *cid)) {
+                    debug!(
+                        "[l2cap][cid = {}] peer reconfigured mtu = {}, mps = {}",
+                        chan.cid, req.mtu, req.mps
+                    );
+                    chan.peer_mtu = req.mtu;
+                    chan.peer_mps = req.mps;
+                    chan.mtu = req.mtu;
+                    chan.mps = req.mps.min(P::MTU as u16 - 4);
+                }
+            }
+        } else {
+            warn!("[l2cap][conn = {:?}] rejecting reconfigure request: {:?}", conn, result);
+        }
+        let response = SignalResponse {
+            handle: conn,
+            identifier,
+            kind: SignalResponseKind::Reconfigure(result),
+        };
+        Self::respond(&mut state, manager, response)
+    }
+
+    /// Queue a response to a signal of the peer for the control runner, or send it through the outbound queue
+    /// of the connection if too many responses are pending.
+    fn respond(
+        state: &mut State<'d, P::Packet>,
+        manager: &ConnectionManager<'_, P>,
+        response: SignalResponse,
+    ) -> Result<(), Error> {
+        match state.signal_responses.push_back(response) {
+            Ok(()) => {
+                state.signal_response_waker.wake();
+                Ok(())
+            }
+            Err(response) => {
+                warn!(
+                    "[l2cap][conn = {:?}] too many pending signal responses",
+                    response.handle
+                );
+                let mut packet = P::allocate().ok_or(Error::OutOfMemory)?;
+                let len = response.encode(packet.as_mut())?.len();
+                manager.try_outbound(response.handle, Pdu::new(packet, len))
+            }
+        }
+    }
+
+    fn handle_reconfigure_response(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        res: &CreditConnReconfigRes,
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        for chan in state.channels.iter_mut() {
+            match chan.reconfig.as_mut() {
+                Some(reconfig) if reconfig.identifier == identifier && chan.conn == Some(conn) => {
+                    reconfig.result = Some(res.result);
+                    // Only what we receive changes, what we send is limited by the values of the peer.
+                    if res.result == CreditConnReconfigResult::Success as u16 {
+                        chan.local_mtu = reconfig.mtu;
+                        chan.local_mps = reconfig.mps;
+                    }
+                    state.reconfig_waker.wake();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(Error::NotFound)
+    }
+
+    fn handle_credit_flow(&self, conn: ConnHandle, req: &LeCreditFlowInd) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        for storage in state.channels.iter_mut() {
+            match storage.state {
+                ChannelState::Connected if storage.peer_cid == req.cid && Some(conn) == storage.conn => {
+                    trace!(
+                        "[l2cap][handle_credit_flow][cid = {}] {} += {} credits",
+                        req.cid,
+                        storage.peer_credits,
+                        req.credits
+                    );
+                    storage.peer_credits = storage.peer_credits.saturating_add(req.credits);
+                    storage.credit_waker.wake();
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        //    trace!("[l2cap][handle_credit_flow] peer channel {} not found", req.cid);
+        Err(Error::NotFound)
+    }
+
+    fn handle_disconnect_request(&self, cid: u16) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
         for (idx, storage) in state.channels.iter_mut().enumerate() {
             if cid == storage.cid {
                 storage.state = ChannelState::PeerDisconnecting;
+                storage.state_waker.wake();
+                storage.readable_waker.wake();
                 let _ = storage.inbound.close();
                 state.disconnect_waker.wake();
+                state.reconfig_waker.wake();
                 break;
             }
         }
@@ -609,6 +1390,23 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(Sdu::from_pdu(pdu))
     }
 
+    /// Receive the next SDU on a given channel, waiting at most `timeout` for it.
+    ///
+    /// Once taken from the queue, the SDU is returned even if granting credits for it takes longer.
+    pub(crate) async fn receive_sdu_timeout<T: Controller>(
+        &self,
+        chan: ChannelIndex,
+        ble: &BleHost<'d, T, P>,
+        timeout: Duration,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        let pdu = with_timeout(timeout, self.receive_pdu(&ble.connections, chan))
+            .await
+            .map_err(|_| Error::Timeout)??;
+        let mut p_buf: [u8; 16] = [0; 16];
+        self.flow_control(chan, ble, &mut p_buf).await?;
+        Ok(Sdu::from_pdu(pdu))
+    }
+
     /// Receive data on a given channel and copy it into the buffer.
     ///
     /// The length provided buffer slice must be equal or greater to the agreed MTU.
@@ -629,26 +1427,139 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(to_copy)
     }
 
+    /// Receive the next SDU queued on a given channel without waiting.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU is queued.
+    pub(crate) fn try_receive_sdu<T: Controller + blocking::Controller>(
+        &self,
+        chan: ChannelIndex,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        let pdu = self.try_receive_pdu(chan)?;
+        let mut p_buf: [u8; 16] = [0; 16];
+        self.try_flow_control(chan, ble, &mut p_buf)?;
+        Ok(Sdu::from_pdu(pdu))
+    }
+
+    /// Receive the next SDU queued on a given channel without waiting, and copy it into the buffer.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU is queued.
+    pub(crate) fn try_receive<T: Controller + blocking::Controller>(
+        &self,
+        chan: ChannelIndex,
+        buf: &mut [u8],
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<usize, BleHostError<T::Error>> {
+        let pdu = self.try_receive_pdu(chan)?;
+        let to_copy = pdu.len().min(buf.len());
+        buf[..to_copy].copy_from_slice(&pdu.as_ref()[..to_copy]);
+        let mut p_buf: [u8; 16] = [0; 16];
+        self.try_flow_control(chan, ble, &mut p_buf)?;
+        Ok(to_copy)
+    }
+
+    fn try_receive_pdu(&self, chan: ChannelIndex) -> Result<Pdu<P::Packet>, Error> {
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[chan.0 as usize];
+        if chan.state != ChannelState::Connected {
+            return Err(Error::ChannelClosed);
+        }
+        if let Some(pdu) = chan.peeked.take() {
+            return Ok(pdu);
+        }
+        match chan.inbound.try_receive() {
+            Some(Some(pdu)) => Ok(pdu),
+            Some(None) => Err(Error::ChannelClosed),
+            None => Err(Error::Busy),
+        }
+    }
+
+    /// Poll whether a receive on the channel would complete right away, with an SDU or an error.
+    pub(crate) fn poll_readable(&self, index: ChannelIndex, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[index.0 as usize];
+        if chan.state != ChannelState::Connected || chan.queued() > 0 {
+            return Poll::Ready(());
+        }
+        chan.readable_waker.register(cx.waker());
+        Poll::Pending
+    }
+
     async fn receive_pdu<'m>(
         &self,
         ble: &'m ConnectionManager<'d, P>,
         chan: ChannelIndex,
     ) -> Result<Pdu<P::Packet>, Error> {
-        poll_fn(|cx| {
-            let state = self.state.borrow();
-            let chan = &state.channels[chan.0 as usize];
-            if chan.state == ChannelState::Connected {
-                let conn = chan.conn.unwrap();
-                match chan.inbound.poll_receive(cx) {
-                    Poll::Ready(Some(pdu)) => Poll::Ready(Ok(pdu)),
-                    Poll::Ready(None) => Poll::Ready(Err(Error::ChannelClosed)),
-                    Poll::Pending => Poll::Pending,
-                }
-            } else {
-                Poll::Ready(Err(Error::ChannelClosed))
+        poll_fn(|cx| self.poll_receive_pdu(chan, cx)).await
+    }
+
+    fn poll_receive_pdu(&self, chan: ChannelIndex, cx: &mut Context<'_>) -> Poll<Result<Pdu<P::Packet>, Error>> {
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[chan.0 as usize];
+        if chan.state == ChannelState::Connected {
+            if let Some(pdu) = chan.peeked.take() {
+                return Poll::Ready(Ok(pdu));
+            }
+            match chan.inbound.poll_receive(cx) {
+                Poll::Ready(Some(pdu)) => Poll::Ready(Ok(pdu)),
+                Poll::Ready(None) => Poll::Ready(Err(Error::ChannelClosed)),
+                Poll::Pending => Poll::Pending,
+            }
+        } else {
+            Poll::Ready(Err(Error::ChannelClosed))
+        }
+    }
+
+    /// Wait for the next SDU on a given channel and pass it to `f` without taking it out of the queue.
+    ///
+    /// The channel state is not borrowed while `f` runs, so `f` may use the channel.
+    pub(crate) async fn peek_sdu<F: FnOnce(&[u8]) -> R, R>(&self, index: ChannelIndex, f: F) -> Result<R, Error> {
+        let pdu = poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state != ChannelState::Connected {
+                return Poll::Ready(Err(Error::ChannelClosed));
+            }
+            if let Some(pdu) = chan.peeked.take() {
+                return Poll::Ready(Ok(pdu));
+            }
+            match chan.inbound.poll_receive(cx) {
+                Poll::Ready(Some(pdu)) => Poll::Ready(Ok(pdu)),
+                Poll::Ready(None) => Poll::Ready(Err(Error::ChannelClosed)),
+                Poll::Pending => Poll::Pending,
             }
         })
-        .await
+        .await?;
+        let result = f(pdu.as_ref());
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            // Put the SDU back in front of the queue, unless `f` closed the channel.
+            if chan.state == ChannelState::Connected && chan.peeked.is_none() {
+                chan.peeked = Some(pdu);
+            }
+        });
+        Ok(result)
+    }
+
+    /// Poll for the next SDU on a given channel.
+    ///
+    /// Credits are granted to the peer without waiting, like with [`try_receive_sdu`](Self::try_receive_sdu).
+    pub(crate) fn poll_receive_sdu<T: Controller + blocking::Controller>(
+        &self,
+        chan: ChannelIndex,
+        ble: &BleHost<'d, T, P>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Sdu<P::Packet>, BleHostError<T::Error>>> {
+        let pdu = match self.poll_receive_pdu(chan, cx) {
+            Poll::Ready(Ok(pdu)) => pdu,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+            Poll::Pending => return Poll::Pending,
+        };
+        let mut p_buf: [u8; 16] = [0; 16];
+        if let Err(e) = self.try_flow_control(chan, ble, &mut p_buf) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(Sdu::from_pdu(pdu)))
     }
 
     /// Send the provided buffer over a given l2cap channel.
@@ -672,7 +1583,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         let n_packets = len.div_ceil(mps);
         // info!("[host] sending {} LE K frames, len {}, mps {}", n_packets, len, mps);
 
-        let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, Some(cx))).await?;
+        let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, buf.len(), Some(cx))).await?;
 
         // Segment using mps
         let (first, remaining) = buf.split_at(buf.len().min(mps as usize - 2));
@@ -691,6 +1602,122 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
+    /// Send an SDU assembled from several buffers over a given l2cap channel.
+    ///
+    /// The buffers are copied into the frames directly, so the SDU never exists as one contiguous buffer.
+    pub(crate) async fn send_vectored<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        bufs: &[&[u8]],
+        p_buf: &mut [u8],
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if total > mtu as usize {
+            return Err(Error::InsufficientSpace.into());
+        }
+        let n_packets = (total as u16).saturating_add(2).div_ceil(mps);
+        let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, total, Some(cx))).await?;
+
+        let mut offset = 0;
+        let mut sdu_len = Some(total as u16);
+        loop {
+            let header = if sdu_len.is_some() { 6 } else { 4 };
+            let chunk = (total - offset).min(mps as usize + 4 - header);
+            if p_buf.len() < header + chunk {
+                return Err(Error::InsufficientSpace.into());
+            }
+            let mut w = WriteCursor::new(&mut p_buf[..header]);
+            w.write((header - 4 + chunk) as u16)?;
+            w.write(peer_cid)?;
+            if let Some(sdu_len) = sdu_len.take() {
+                w.write(sdu_len)?;
+            }
+            gather(bufs, offset, &mut p_buf[header..header + chunk]);
+            ble.l2cap(conn, (header - 4 + chunk) as u16, 1)
+                .await?
+                .send(&p_buf[..header + chunk])
+                .await?;
+            grant.confirm(1);
+            offset += chunk;
+            if offset == total {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reserve the credits to send an SDU of `len` bytes in frames written by the caller.
+    ///
+    /// Returns the connection, MPS and peer CID of the channel with the credits. The frames are written into
+    /// packets of the pool, so the MPS must leave room for the L2CAP header in a packet.
+    pub(crate) async fn reserve_sdu(
+        &self,
+        index: ChannelIndex,
+        len: usize,
+    ) -> Result<(ConnHandle, u16, u16, CreditGrant<'_, 'd, P::Packet>), Error> {
+        let (conn, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
+        if len > mtu as usize || mps as usize + 4 > P::MTU {
+            return Err(Error::InsufficientSpace);
+        }
+        let n_packets = (len as u16).saturating_add(2).div_ceil(mps);
+        let grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, len, Some(cx))).await?;
+        Ok((conn, mps, peer_cid, grant))
+    }
+
+    /// Send an SDU held in a packet of the pool, framing it inside the same packet.
+    ///
+    /// The payload is moved up by the 6 bytes of L2CAP headers preceding the first frame, and the header of
+    /// each following frame overwrites the end of the frame sent before it. Packets without room for the
+    /// headers are copied into a new packet instead.
+    pub(crate) async fn send_sdu<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        sdu: Sdu<P::Packet>,
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
+        let len = sdu.len();
+        if len > mtu as usize {
+            return Err(Error::InsufficientSpace.into());
+        }
+        let mut packet = sdu.into_inner();
+        if packet.as_ref().len() < len + 6 {
+            let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+            return self.send(index, &packet.as_ref()[..len], p_buf.as_mut(), ble).await;
+        }
+        let n_packets = (len as u16).saturating_add(2).div_ceil(mps);
+        let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, len, Some(cx))).await?;
+
+        let buf = packet.as_mut();
+        buf.copy_within(..len, 6);
+        let first = len.min(mps as usize - 2);
+        let mut w = WriteCursor::new(&mut buf[..6]);
+        w.write(first as u16 + 2)?;
+        w.write(peer_cid)?;
+        w.write(len as u16)?;
+        ble.l2cap(conn, first as u16 + 2, 1)
+            .await?
+            .send(&buf[..6 + first])
+            .await?;
+        grant.confirm(1);
+
+        let mut pos = 6 + first;
+        while pos < 6 + len {
+            let chunk = (6 + len - pos).min(mps as usize);
+            let mut w = WriteCursor::new(&mut buf[pos - 4..pos]);
+            w.write(chunk as u16)?;
+            w.write(peer_cid)?;
+            ble.l2cap(conn, chunk as u16, 1)
+                .await?
+                .send(&buf[pos - 4..pos + chunk])
+                .await?;
+            grant.confirm(1);
+            pos += chunk;
+        }
+        Ok(())
+    }
+
     /// Send the provided buffer over a given l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -700,27 +1727,58 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         &self,
         index: ChannelIndex,
         buf: &[u8],
-        p_buf: &mut [u8],
         ble: &BleHost<'d, T, P>,
     ) -> Result<(), BleHostError<T::Error>> {
+        match self.poll_send(index, buf, ble, None) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(Error::Busy.into()),
+        }
+    }
+
+    /// Poll to send the provided buffer over a given l2cap channel.
+    ///
+    /// The SDU is sent once the credits and controller buffers for all of its frames are available. The
+    /// context, if any, is woken when they may have become available. The frames are encoded in a packet of
+    /// the pool, allocated once the credits and buffers are reserved. If the pool is exhausted, the context
+    /// is woken right away to try again, and [`Error::OutOfMemory`] is returned without a context.
+    pub(crate) fn poll_send<T: Controller + blocking::Controller>(
+        &self,
+        index: ChannelIndex,
+        buf: &[u8],
+        ble: &BleHost<'d, T, P>,
+        mut cx: Option<&mut Context<'_>>,
+    ) -> Poll<Result<(), BleHostError<T::Error>>> {
         let (conn, mps, mtu, peer_cid) = self.connected_channel_params(index)?;
         if buf.len() > mtu as usize {
-            return Err(Error::InsufficientSpace.into());
+            return Poll::Ready(Err(Error::InsufficientSpace.into()));
         }
 
         // The number of packets we'll need to send for this payload
         let len = (buf.len() as u16).saturating_add(2);
         let n_packets = len.div_ceil(mps);
 
-        let mut grant = match self.poll_request_to_send(index, n_packets, None) {
+        let mut grant = match self.poll_request_to_send(index, n_packets, buf.len(), cx.as_deref_mut()) {
             Poll::Ready(res) => res?,
-            Poll::Pending => {
-                return Err(Error::Busy.into());
-            }
+            Poll::Pending => return Poll::Pending,
+        };
+
+        // Pre-request. Dropping the grant returns the credits and wakes other senders waiting for them, but not
+        // this one, as it only waits for credits when it lacks them.
+        let mut sender = match ble.poll_l2cap(conn, len, n_packets, cx.as_deref_mut()) {
+            Poll::Ready(res) => res?,
+            Poll::Pending => return Poll::Pending,
         };
 
-        // Pre-request
-        let mut sender = ble.try_l2cap(conn, len, n_packets)?;
+        let Some(mut packet) = P::allocate() else {
+            return match cx {
+                Some(cx) => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                None => Poll::Ready(Err(Error::OutOfMemory.into())),
+            };
+        };
+        let p_buf = packet.as_mut();
 
         // Segment using mps
         let (first, remaining) = buf.split_at(buf.len().min(mps as usize - 2));
@@ -737,7 +1795,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             sender.try_send(&p_buf[..len])?;
             grant.confirm(1);
         }
-        Ok(())
+        Poll::Ready(Ok(()))
     }
 
     pub(crate) async fn send_conn_param_update_req<T: Controller>(
@@ -783,7 +1841,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         let (conn, cid, credits) = self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
             if chan.state == ChannelState::Connected {
-                return Ok((chan.conn.unwrap(), chan.cid, chan.flow_control.process()));
+                return Ok((chan.conn.unwrap(), chan.cid, chan.credits_to_grant()));
             }
             debug!("[l2cap][flow_control_process] channel {:?} not found", index);
             Err(Error::NotFound)
@@ -809,43 +1867,218 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Ok(())
     }
 
-    fn with_mut<F: FnOnce(&mut State<'d, P::Packet>) -> R, R>(&self, f: F) -> R {
-        let mut state = self.state.borrow_mut();
-        f(&mut state)
-    }
-
-    fn poll_request_to_send(
+    /// Grant the peer credits to send more frames on a given channel.
+    pub(crate) async fn grant_credits<T: Controller>(
         &self,
         index: ChannelIndex,
         credits: u16,
-        cx: Option<&mut Context<'_>>,
-    ) -> Poll<Result<CreditGrant<'_, 'd, P::Packet>, Error>> {
-        let mut state = self.state.borrow_mut();
-        let chan = &mut state.channels[index.0 as usize];
-        if chan.state == ChannelState::Connected {
-            if let Some(cx) = cx {
-                chan.credit_waker.register(cx.waker());
-            }
-            if credits <= chan.peer_credits {
-                chan.peer_credits -= credits;
-                #[cfg(feature = "channel-metrics")]
-                chan.metrics.sent(credits as usize);
-                return Poll::Ready(Ok(CreditGrant::new(&self.state, index, credits)));
-            } else {
-                #[cfg(feature = "channel-metrics")]
-                chan.metrics.blocked_send();
-                return Poll::Pending;
+        ble: &BleHost<'d, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, cid, credits) = self.with_mut(|state| {
+            let chan = &state.channels[index.0 as usize];
+            if chan.state == ChannelState::Connected {
+                return Ok((chan.conn.unwrap(), chan.cid, chan.limit_granted_credits(credits)));
             }
+            Err(Error::ChannelClosed)
+        })?;
+        if credits == 0 {
+            return Ok(());
         }
-        debug!("[l2cap][pool_request_to_send] channel index {:?} not found", index);
-        Poll::Ready(Err(Error::NotFound))
+        let identifier = self.next_request_id();
+        let signal = LeCreditFlowInd { cid, credits };
+        let mut p_buf: [u8; 16] = [0; 16];
+        ble.l2cap_signal(conn, identifier, &signal, &mut p_buf).await?;
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state == ChannelState::Connected {
+                chan.flow_control.confirm_granted(credits);
+            }
+        });
+        Ok(())
     }
 
-    pub(crate) fn poll_disconnecting<'m>(&'m self, cx: Option<&mut Context<'_>>) -> Poll<DisconnectRequest<'m, 'd, P>> {
-        let mut state = self.state.borrow_mut();
-        if let Some(cx) = cx {
-            state.disconnect_waker.register(cx.waker());
-        }
+    // Like flow_control, but without waiting. Credits that cannot be granted right away are granted
+    // on the next receive.
+    fn try_flow_control<T: Controller + blocking::Controller>(
+        &self,
+        index: ChannelIndex,
+        ble: &BleHost<'d, T, P>,
+        p_buf: &mut [u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let (conn, cid, credits) = self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state == ChannelState::Connected {
+                return Ok((chan.conn.unwrap(), chan.cid, chan.credits_to_grant()));
+            }
+            Err(Error::NotFound)
+        })?;
+
+        if let Some(credits) = credits {
+            let identifier = self.next_request_id();
+            let signal = LeCreditFlowInd { cid, credits };
+            match ble.try_l2cap_signal(conn, identifier, &signal, p_buf) {
+                Ok(()) => {}
+                Err(BleHostError::BleHost(Error::Busy)) => {
+                    // Leave the credits to the control runner, as nothing more may be received until the
+                    // peer has them.
+                    let response = SignalResponse {
+                        handle: conn,
+                        identifier,
+                        kind: SignalResponseKind::Credits { cid, credits },
+                    };
+                    if self
+                        .with_mut(|state| Self::respond(state, &ble.connections, response))
+                        .is_err()
+                    {
+                        return Ok(());
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+            self.with_mut(|state| {
+                let chan = &mut state.channels[index.0 as usize];
+                if chan.state == ChannelState::Connected {
+                    chan.flow_control.confirm_granted(credits);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn with_mut<F: FnOnce(&mut State<'d, P::Packet>) -> R, R>(&self, f: F) -> R {
+        let mut state = self.state.borrow_mut();
+        f(&mut state)
+    }
+
+    fn poll_request_to_send(
+        &self,
+        index: ChannelIndex,
+        credits: u16,
+        len: usize,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<Result<CreditGrant<'_, 'd, P::Packet>, Error>> {
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[index.0 as usize];
+        if chan.state == ChannelState::Connected {
+            if credits <= chan.peer_credits {
+                chan.peer_credits -= credits;
+                chan.last_activity = Instant::now();
+                #[cfg(feature = "channel-metrics")]
+                {
+                    chan.metrics.sent(credits as usize, len);
+                    chan.send_stalled = false;
+                }
+                return Poll::Ready(Ok(CreditGrant::new(&self.state, index, credits)));
+            } else {
+                // Only a sender waiting for credits is woken when they are returned.
+                if let Some(cx) = cx {
+                    chan.credit_waker.register(cx.waker());
+                }
+                #[cfg(feature = "channel-metrics")]
+                {
+                    chan.metrics.blocked_send(!chan.send_stalled);
+                    chan.send_stalled = true;
+                }
+                return Poll::Pending;
+            }
+        }
+        debug!("[l2cap][pool_request_to_send] channel index {:?} not found", index);
+        Poll::Ready(Err(Error::NotFound))
+    }
+
+    /// Ask the peer to accept a new MTU and MPS for the data it sends on an enhanced channel, and wait
+    /// for the response.
+    pub(crate) async fn reconfigure<T: Controller>(
+        &self,
+        index: ChannelIndex,
+        mtu: u16,
+        mps: u16,
+        ble: &BleHost<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let config = L2capChannelConfig {
+            mtu: Some(mtu),
+            mps: Some(mps),
+            ..Default::default()
+        };
+        config.validate::<P>()?;
+        if mtu < L2CAP_ECFC_MIN_MTU || mps < L2CAP_ECFC_MIN_MTU {
+            return Err(Error::InvalidValue.into());
+        }
+        let identifier = self.next_request_id();
+        let (conn, cid) = self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if !chan.enhanced || chan.state != ChannelState::Connected {
+                return Err(Error::NotSupported);
+            }
+            if chan.reconfig.is_some() {
+                return Err(Error::Busy);
+            }
+            // The MTU of a channel can only grow.
+            if mtu < chan.local_mtu {
+                return Err(Error::InvalidValue);
+            }
+            chan.reconfig = Some(Reconfigure {
+                identifier,
+                mtu,
+                mps,
+                result: None,
+            });
+            Ok((unwrap!(chan.conn), chan.cid))
+        })?;
+
+        let mut tx = [0; 18];
+        let command = CreditConnReconfigReq { mtu, mps };
+        if let Err(e) = ble
+            .l2cap_signal_with_cids(conn, identifier, &command, &[cid], &mut tx[..])
+            .await
+        {
+            self.with_mut(|state| state.channels[index.0 as usize].reconfig = None);
+            return Err(e);
+        }
+
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.reconfig_waker.register(cx.waker());
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state != ChannelState::Connected {
+                chan.reconfig = None;
+                return Poll::Ready(Err(Error::Disconnected.into()));
+            }
+            match chan.reconfig {
+                Some(Reconfigure {
+                    result: Some(result), ..
+                }) => {
+                    chan.reconfig = None;
+                    if result == CreditConnReconfigResult::Success as u16 {
+                        Poll::Ready(Ok(()))
+                    } else {
+                        warn!(
+                            "[l2cap][cid = {}] reconfigure rejected by peer, result {}",
+                            chan.cid, result
+                        );
+                        Poll::Ready(Err(Error::InvalidValue.into()))
+                    }
+                }
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    pub(crate) fn poll_signal_response(&self, cx: &mut Context<'_>) -> Poll<SignalResponse> {
+        let mut state = self.state.borrow_mut();
+        state.signal_response_waker.register(cx.waker());
+        match state.signal_responses.pop_front() {
+            Some(response) => Poll::Ready(response),
+            None => Poll::Pending,
+        }
+    }
+
+    pub(crate) fn poll_disconnecting<'m>(&'m self, cx: Option<&mut Context<'_>>) -> Poll<DisconnectRequest<'m, 'd, P>> {
+        let mut state = self.state.borrow_mut();
+        if let Some(cx) = cx {
+            state.disconnect_waker.register(cx.waker());
+        }
         for (idx, storage) in state.channels.iter().enumerate() {
             match storage.state {
                 ChannelState::Disconnecting | ChannelState::PeerDisconnecting => {
@@ -861,12 +2094,94 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         Poll::Pending
     }
 
+    /// Wait until a channel has been idle for longer than its configured timeout and start disconnecting it.
+    ///
+    /// Returns the connection and PSM of the channel that timed out.
+    pub(crate) async fn wait_idle_expired(&self) -> (ConnHandle, u16) {
+        loop {
+            let deadline = poll_fn(|cx| {
+                let mut state = self.state.borrow_mut();
+                state.idle_waker.register(cx.waker());
+                match state.next_idle_deadline() {
+                    Some(deadline) => Poll::Ready(deadline),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+
+            // Re-evaluate the deadline if a channel with a timeout is opened in the meantime.
+            let mut registered = false;
+            let changed = poll_fn(|cx| {
+                if registered {
+                    Poll::Ready(())
+                } else {
+                    registered = true;
+                    self.state.borrow_mut().idle_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            });
+            if let Either::Second(_) = select(Timer::at(deadline), changed).await {
+                continue;
+            }
+
+            let now = Instant::now();
+            let expired = self.with_mut(|state| {
+                for chan in state.channels.iter_mut() {
+                    match (chan.state.clone(), chan.idle_timeout) {
+                        (ChannelState::Connected, Some(timeout)) if chan.last_activity + timeout <= now => {
+                            debug!("[l2cap][cid = {}] idle timeout, disconnecting", chan.cid);
+                            chan.state = ChannelState::Disconnecting;
+                            chan.state_waker.wake();
+                            chan.readable_waker.wake();
+                            let _ = chan.inbound.close();
+                            #[cfg(feature = "channel-metrics")]
+                            chan.metrics.reset();
+                            state.disconnect_waker.wake();
+                            return chan.conn.map(|conn| (conn, chan.psm));
+                        }
+                        _ => {}
+                    }
+                }
+                None
+            });
+            if let Some(expired) = expired {
+                return expired;
+            }
+        }
+    }
+
     pub(crate) fn inc_ref(&self, index: ChannelIndex) {
         self.with_mut(|state| {
             state.inc_ref(index);
         });
     }
 
+    /// Record that no handle can read from or write to the channel anymore.
+    pub(crate) fn release(&self, index: ChannelIndex, reader: bool, writer: bool) {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            chan.reader_free |= reader;
+            chan.writer_free |= writer;
+        })
+    }
+
+    /// Take a reference to the channel for a new reader or writer, if none exists.
+    pub(crate) fn upgrade(&self, index: ChannelIndex, reader: bool, writer: bool) -> Result<(), Error> {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            if chan.state != ChannelState::Connected {
+                return Err(Error::ChannelClosed);
+            }
+            if (reader && !chan.reader_free) || (writer && !chan.writer_free) {
+                return Err(Error::InvalidState);
+            }
+            chan.reader_free &= !reader;
+            chan.writer_free &= !writer;
+            state.inc_ref(index);
+            Ok(())
+        })
+    }
+
     pub(crate) fn dec_ref(&self, index: ChannelIndex) {
         self.with_mut(|state| {
             let state = &mut state.channels[index.0 as usize];
@@ -902,6 +2217,11 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             f(&state.metrics)
         })
     }
+
+    #[cfg(feature = "channel-metrics")]
+    pub(crate) fn reset_metrics(&self, index: ChannelIndex) {
+        self.with_mut(|state| state.channels[index.0 as usize].metrics.reset())
+    }
 }
 
 pub struct DisconnectRequest<'a, 'd, P: PacketPool> {
@@ -941,7 +2261,114 @@ impl<'a, 'd, P: PacketPool> DisconnectRequest<'a, 'd, P> {
     }
 
     pub fn confirm(self) {
-        self.state.borrow_mut().channels[self.index.0 as usize].state = ChannelState::Disconnected;
+        let mut state = self.state.borrow_mut();
+        let chan = &mut state.channels[self.index.0 as usize];
+        chan.state = ChannelState::Disconnected;
+        chan.state_waker.wake();
+        chan.readable_waker.wake();
+    }
+}
+
+/// Signal sent by the control runner, mostly in response to a signal of the peer.
+#[derive(Debug, Clone, Copy)]
+pub struct SignalResponse {
+    handle: ConnHandle,
+    identifier: u8,
+    kind: SignalResponseKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignalResponseKind {
+    /// Result of a reconfigure request.
+    Reconfigure(CreditConnReconfigResult),
+    /// Refusal of all `count` channels of an enhanced credit based connection request.
+    Refuse { count: u8, result: LeCreditConnResultCode },
+    /// Refusal of an LE credit based connection request.
+    RefuseLe(LeCreditConnResultCode),
+    /// Credits granted to the peer that could not be sent right away.
+    Credits { cid: u16, credits: u16 },
+}
+
+impl SignalResponse {
+    /// Encode the response, including its L2CAP header.
+    fn encode<'b>(&self, buf: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        match self.kind {
+            SignalResponseKind::Reconfigure(result) => {
+                let res = CreditConnReconfigRes { result: result as u16 };
+                encode_signal(self.identifier, &res, &[], buf)
+            }
+            SignalResponseKind::Refuse { count, result } => {
+                let res = CreditConnRes {
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result: result as u16,
+                };
+                let dcids = [0; L2CAP_ECFC_MAX_CHANNELS];
+                let count = (count as usize).min(L2CAP_ECFC_MAX_CHANNELS);
+                encode_signal(self.identifier, &res, &dcids[..count], buf)
+            }
+            SignalResponseKind::RefuseLe(result) => {
+                let res = LeCreditConnRes {
+                    dcid: 0,
+                    mtu: 0,
+                    mps: 0,
+                    credits: 0,
+                    result,
+                };
+                encode_signal(self.identifier, &res, &[], buf)
+            }
+            SignalResponseKind::Credits { cid, credits } => {
+                encode_signal(self.identifier, &LeCreditFlowInd { cid, credits }, &[], buf)
+            }
+        }
+    }
+
+    pub async fn send<T: Controller, P: PacketPool>(
+        &self,
+        host: &BleHost<'_, T, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut tx = [0; 32];
+        let data = self.encode(&mut tx)?;
+        let mut sender = host.l2cap(self.handle, data.len() as u16, 1).await?;
+        sender.send(data).await?;
+        Ok(())
+    }
+}
+
+/// Channels taken from an enhanced credit based connection request of the peer.
+struct EnhancedAccept<'d, P: PacketPool> {
+    /// Identifier of the request.
+    identifier: u8,
+    channels: heapless::Vec<L2capChannel<'d, P>, L2CAP_ECFC_MAX_CHANNELS>,
+    /// CIDs to answer the request with, 0 for each refused channel.
+    dcids: heapless::Vec<u16, L2CAP_ECFC_MAX_CHANNELS>,
+}
+
+/// Reconfiguration of a channel requested by us, waiting for the response of the peer.
+#[derive(Debug, Clone, Copy)]
+struct Reconfigure {
+    identifier: u8,
+    mtu: u16,
+    mps: u16,
+    result: Option<u16>,
+}
+
+/// Copy the bytes of the concatenated buffers starting at `offset` into `dest`.
+fn gather(bufs: &[&[u8]], mut offset: usize, dest: &mut [u8]) {
+    let mut pos = 0;
+    for buf in bufs {
+        if pos == dest.len() {
+            break;
+        }
+        if offset >= buf.len() {
+            offset -= buf.len();
+            continue;
+        }
+        let n = (buf.len() - offset).min(dest.len() - pos);
+        dest[pos..pos + n].copy_from_slice(&buf[offset..offset + n]);
+        pos += n;
+        offset = 0;
     }
 }
 
@@ -971,17 +2398,47 @@ pub struct ChannelStorage<P> {
     mtu: u16,
     flow_control: CreditFlowControl,
     refcount: u8,
+    /// Set once the reader or writer of the channel, or the channel handle holding both, was dropped.
+    reader_free: bool,
+    writer_free: bool,
+    idle_timeout: Option<Duration>,
+    /// Maximum number of SDUs held by `inbound`, enforced through the credits granted to the peer.
+    rx_quota: Option<u16>,
+    last_activity: Instant,
+    /// Opened in enhanced credit based flow control mode.
+    enhanced: bool,
+    /// Number of channels in the credit based connection request of the peer that opened this channel.
+    requested: u8,
+    /// MTU and MPS announced by us for an enhanced channel, and by the peer. `mtu` and `mps` are those used
+    /// for sending: the smaller of the two for an LE credit based channel, the values of the peer for an
+    /// enhanced one, with the MPS limited to the packets of the pool.
+    local_mtu: u16,
+    local_mps: u16,
+    peer_mtu: u16,
+    peer_mps: u16,
+    reconfig: Option<Reconfigure>,
 
     peer_cid: u16,
     peer_credits: u16,
+    /// Credits granted by the peer when the channel was opened.
+    initial_peer_credits: u16,
     credit_waker: WakerRegistration,
+    state_waker: WakerRegistration,
+    /// Waker of a task waiting for the channel to become readable, kept apart from the receive queue so that
+    /// it does not displace a task receiving on the channel.
+    readable_waker: WakerRegistration,
 
     inbound: PacketChannel<P, { config::L2CAP_RX_QUEUE_SIZE }>,
+    /// SDU taken from `inbound` to be inspected, returned by the next receive.
+    peeked: Option<Pdu<P>>,
     #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
     reassembly: PacketReassembly<P>,
 
     #[cfg(feature = "channel-metrics")]
     metrics: Metrics,
+    /// Set while a send waits for credits, so that polling it again is not counted as another stall.
+    #[cfg(feature = "channel-metrics")]
+    send_stalled: bool,
 }
 
 /// Metrics for this channel
@@ -996,6 +2453,18 @@ pub struct Metrics {
     pub blocked_send: usize,
     /// Number of l2cap packets blocked from receiving.
     pub blocked_receive: usize,
+    /// Number of SDUs sent.
+    pub sdus_sent: usize,
+    /// Number of SDUs received.
+    pub sdus_received: usize,
+    /// Number of SDU payload bytes sent.
+    pub bytes_sent: u64,
+    /// Number of SDU payload bytes received.
+    pub bytes_received: u64,
+    /// Number of sends that had to wait for credits from the peer.
+    pub credit_stalls: usize,
+    /// Largest number of SDUs held in the RX queue at once.
+    pub rx_queue_high_water: usize,
 }
 
 #[cfg(feature = "channel-metrics")]
@@ -1006,18 +2475,36 @@ impl Metrics {
             num_received: 0,
             blocked_send: 0,
             blocked_receive: 0,
+            sdus_sent: 0,
+            sdus_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            credit_stalls: 0,
+            rx_queue_high_water: 0,
         }
     }
-    pub(crate) fn sent(&mut self, num: usize) {
+    pub(crate) fn sent(&mut self, num: usize, len: usize) {
         self.num_sent = self.num_sent.wrapping_add(num);
+        self.sdus_sent = self.sdus_sent.wrapping_add(1);
+        self.bytes_sent = self.bytes_sent.wrapping_add(len as u64);
     }
 
     pub(crate) fn received(&mut self, num: usize) {
         self.num_received = self.num_received.wrapping_add(num);
     }
 
-    pub(crate) fn blocked_send(&mut self) {
+    pub(crate) fn sdu_received(&mut self, len: usize, queued: usize) {
+        self.sdus_received = self.sdus_received.wrapping_add(1);
+        self.bytes_received = self.bytes_received.wrapping_add(len as u64);
+        self.rx_queue_high_water = self.rx_queue_high_water.max(queued);
+    }
+
+    /// Count a send blocked for credits, and a new stall if it did not already wait for them.
+    pub(crate) fn blocked_send(&mut self, stall: bool) {
         self.blocked_send = self.blocked_send.wrapping_add(1);
+        if stall {
+            self.credit_stalls = self.credit_stalls.wrapping_add(1);
+        }
     }
 
     pub(crate) fn blocked_receive(&mut self) {
@@ -1041,6 +2528,16 @@ impl defmt::Format for Metrics {
             self.blocked_send,
             self.blocked_receive,
         );
+        defmt::write!(
+            f,
+            ", sdus sent = {}, sdus recvd = {}, bytes sent = {}, bytes recvd = {}, credit stalls = {}, rx high water = {}",
+            self.sdus_sent,
+            self.sdus_received,
+            self.bytes_sent,
+            self.bytes_received,
+            self.credit_stalls,
+            self.rx_queue_high_water,
+        );
     }
 }
 
@@ -1097,18 +2594,39 @@ impl<P> ChannelStorage<P> {
             flow_control: CreditFlowControl::new(CreditFlowPolicy::Every(1), 0),
             peer_cid: 0,
             peer_credits: 0,
+            initial_peer_credits: 0,
             credit_waker: WakerRegistration::new(),
+            state_waker: WakerRegistration::new(),
+            readable_waker: WakerRegistration::new(),
             refcount: 0,
+            reader_free: false,
+            writer_free: false,
+            idle_timeout: None,
+            rx_quota: None,
+            last_activity: Instant::from_ticks(0),
+            enhanced: false,
+            requested: 0,
+            local_mtu: 0,
+            local_mps: 0,
+            peer_mtu: 0,
+            peer_mps: 0,
+            reconfig: None,
             inbound: PacketChannel::new(),
+            peeked: None,
             #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
             reassembly: PacketReassembly::new(),
             #[cfg(feature = "channel-metrics")]
             metrics: Metrics::new(),
+            #[cfg(feature = "channel-metrics")]
+            send_stalled: false,
         }
     }
 
     fn close(&mut self) {
         self.state = ChannelState::Disconnected;
+        self.state_waker.wake();
+        self.readable_waker.wake();
+        self.peeked = None;
         self.cid = 0;
         self.conn = None;
         self.mps = 0;
@@ -1117,6 +2635,49 @@ impl<P> ChannelStorage<P> {
         self.peer_cid = 0;
         self.flow_control = CreditFlowControl::new(CreditFlowPolicy::Every(1), 0);
         self.peer_credits = 0;
+        self.initial_peer_credits = 0;
+        self.idle_timeout = None;
+        self.rx_quota = None;
+        self.enhanced = false;
+        #[cfg(feature = "channel-metrics")]
+        {
+            self.send_stalled = false;
+        }
+        self.requested = 0;
+        self.local_mtu = 0;
+        self.local_mps = 0;
+        self.peer_mtu = 0;
+        self.peer_mps = 0;
+        self.reconfig = None;
+    }
+
+    /// Number of received SDUs not taken by the application yet.
+    fn queued(&self) -> u16 {
+        self.inbound.len() as u16 + u16::from(self.peeked.is_some())
+    }
+
+    fn public_state(&self) -> L2capChannelState {
+        match self.state {
+            ChannelState::Connected => L2capChannelState::Connected,
+            ChannelState::Disconnecting | ChannelState::PeerDisconnecting => L2capChannelState::Disconnecting,
+            _ => L2capChannelState::Disconnected,
+        }
+    }
+
+    /// Credits to grant the peer according to the flow control policy and the RX quota.
+    fn credits_to_grant(&mut self) -> Option<u16> {
+        let queued = self.queued();
+        self.flow_control.process_with_quota(self.rx_quota, queued)
+    }
+
+    /// Limit credits granted by the application to what fits in the RX quota, and to the 65535 credits the
+    /// peer may hold.
+    fn limit_granted_credits(&self, credits: u16) -> u16 {
+        let queued = self.queued();
+        let credits = credits.min(u16::MAX - self.flow_control.available());
+        self.flow_control
+            .room(self.rx_quota, queued)
+            .map_or(credits, |room| credits.min(room))
     }
 }
 
@@ -1134,11 +2695,14 @@ pub enum ChannelState {
 /// Control how credits are issued by the receiving end.
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum CreditFlowPolicy {
     /// Issue credits for every N messages received
     Every(u16),
     /// Issue credits when below a threshold
     MinThreshold(u16),
+    /// Only issue credits granted by the application with `grant_credits`
+    Manual,
 }
 
 impl Default for CreditFlowPolicy {
@@ -1195,8 +2759,25 @@ impl CreditFlowControl {
                     None
                 }
             }
+            CreditFlowPolicy::Manual => None,
+        }
+    }
+
+    // Like process, but never lets the peer send more frames than fit in the RX quota next to the
+    // SDUs already queued. Every SDU takes at least one frame, so the queue stays within the quota.
+    fn process_with_quota(&mut self, quota: Option<u16>, queued: u16) -> Option<u16> {
+        let credits = self.process()?;
+        match self.room(quota, queued) {
+            None => Some(credits),
+            Some(0) => None,
+            Some(room) => Some(credits.min(room)),
         }
     }
+
+    // Number of credits that can be granted without exceeding the quota, `None` without a quota.
+    fn room(&self, quota: Option<u16>, queued: u16) -> Option<u16> {
+        quota.map(|quota| quota.saturating_sub(queued).saturating_sub(self.credits))
+    }
 }
 
 pub struct CreditGrant<'reference, 'state, P> {
@@ -1242,10 +2823,14 @@ impl<P> Drop for CreditGrant<'_, '_, P> {
 mod tests {
     extern crate std;
 
+    use core::future::Future;
+    use core::pin::pin;
+
     use bt_hci::param::{AddrKind, BdAddr, LeConnRole, Status};
 
     use super::*;
     use crate::mock_controller::MockController;
+    use crate::poll::{Poller, WakeFlag};
     use crate::prelude::DefaultPacketPool;
     use crate::HostResources;
 
@@ -1280,4 +2865,987 @@ mod tests {
             Poll::Ready(Err(BleHostError::BleHost(Error::Disconnected)))
         ));
     }
+
+    #[test]
+    fn idle_timeout_disconnects() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.psm = 0x81;
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        let mut expired = pin!(ble.channels.wait_idle_expired());
+        assert!(expired.as_mut().poll(&mut cx).is_pending());
+
+        ble.channels.set_idle_timeout(idx, Some(Duration::from_millis(10)));
+        assert_eq!(embassy_futures::block_on(expired), (conn, 0x81));
+        assert_eq!(ble.channels.channel_state(idx), L2capChannelState::Disconnecting);
+    }
+
+    #[test]
+    fn negotiated_parameters() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.psm = 0x81;
+                storage.mtu = 120;
+                storage.mps = 60;
+                storage.peer_credits = 5;
+                storage.flow_control = CreditFlowControl::new(CreditFlowPolicy::Every(2), 4);
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+        ble.channels.inc_ref(idx);
+        let channel = L2capChannel::new(idx, &ble.channels);
+        assert_eq!(channel.psm(), 0x81);
+        assert_eq!(channel.mtu(), 120);
+        assert_eq!(channel.mps(), 60);
+        assert_eq!(channel.tx_credits(), 5);
+        assert!(matches!(channel.flow_policy(), CreditFlowPolicy::Every(2)));
+
+        let (_writer, mut reader) = channel.split();
+        let channel_ref = reader.channel_ref();
+        assert_eq!(channel_ref.mtu(), 120);
+        assert_eq!(channel_ref.mps(), 60);
+        assert_eq!(channel_ref.tx_credits(), 5);
+        assert!(matches!(channel_ref.flow_policy(), CreditFlowPolicy::Every(2)));
+    }
+
+    #[test]
+    fn manual_credit_policy() {
+        let mut flow = CreditFlowControl::new(CreditFlowPolicy::Manual, 4);
+        flow.confirm_received(4);
+        assert_eq!(flow.process(), None);
+        flow.confirm_granted(2);
+        assert_eq!(flow.available(), 2);
+    }
+
+    #[test]
+    fn granted_credits_limited() {
+        let mut chan: ChannelStorage<<DefaultPacketPool as PacketPool>::Packet> = ChannelStorage::new();
+        chan.flow_control = CreditFlowControl::new(CreditFlowPolicy::Manual, u16::MAX - 5);
+        // The peer may not hold more than 65535 credits.
+        assert_eq!(chan.limit_granted_credits(10), 5);
+        chan.flow_control.confirm_received(5);
+        assert_eq!(chan.limit_granted_credits(10), 10);
+        chan.flow_control.confirm_granted(u16::MAX);
+        assert_eq!(chan.limit_granted_credits(10), 0);
+    }
+
+    #[test]
+    fn rx_quota_limits_credits() {
+        let mut flow = CreditFlowControl::new(CreditFlowPolicy::Every(1), 3);
+        flow.confirm_received(3);
+        // Three SDUs queued, nothing can be granted.
+        assert_eq!(flow.process_with_quota(Some(3), 3), None);
+        // One SDU taken from the queue.
+        assert_eq!(flow.process_with_quota(Some(3), 2), Some(1));
+        flow.confirm_granted(1);
+        assert_eq!(flow.process_with_quota(Some(3), 0), Some(2));
+        assert_eq!(flow.process_with_quota(None, 3), Some(2));
+        assert_eq!(flow.room(Some(3), 0), Some(2));
+    }
+
+    #[test]
+    fn set_rx_quota() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let idx = ble.channels.alloc_connected(ConnHandle::new(33), 23, 23, 0);
+        ble.channels.with_mut(|state| {
+            state.channels[idx.0 as usize].flow_control = CreditFlowControl::new(CreditFlowPolicy::Manual, 1);
+        });
+        ble.channels.set_rx_quota(idx, Some(3));
+        let state = ble.channels.state.borrow();
+        assert_eq!(state.channels[idx.0 as usize].limit_granted_credits(5), 2);
+        drop(state);
+
+        ble.channels.set_rx_quota(idx, None);
+        let state = ble.channels.state.borrow();
+        assert_eq!(state.channels[idx.0 as usize].limit_granted_credits(5), 5);
+    }
+
+    #[cfg(feature = "channel-metrics")]
+    #[test]
+    fn metrics_count_stalls() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        // A send polled again while waiting for the same credits is one stall.
+        let idx = ble.channels.alloc_connected(ConnHandle::new(33), 23, 23, 0);
+        assert!(ble.channels.poll_request_to_send(idx, 2, 40, None).is_pending());
+        assert!(ble.channels.poll_request_to_send(idx, 2, 40, None).is_pending());
+        ble.channels
+            .with_mut(|state| state.channels[idx.0 as usize].peer_credits = 2);
+        let Poll::Ready(Ok(mut grant)) = ble.channels.poll_request_to_send(idx, 2, 40, None) else {
+            panic!("expected credits");
+        };
+        grant.confirm(2);
+        drop(grant);
+        assert!(ble.channels.poll_request_to_send(idx, 2, 40, None).is_pending());
+
+        ble.channels.metrics(idx, |metrics| {
+            assert_eq!(metrics.blocked_send, 3);
+            assert_eq!(metrics.credit_stalls, 2);
+            assert_eq!(metrics.bytes_sent, 40);
+        });
+
+        let mut metrics = Metrics::new();
+        metrics.sdu_received(10, 3);
+        metrics.sdu_received(10, 1);
+        assert_eq!(metrics.sdus_received, 2);
+        assert_eq!(metrics.rx_queue_high_water, 3);
+
+        metrics.reset();
+        assert_eq!(metrics.bytes_received, 0);
+    }
+
+    #[test]
+    fn gather_buffers() {
+        let bufs: [&[u8]; 3] = [&[1, 2], &[], &[3, 4, 5]];
+        let mut dest = [0; 3];
+        gather(&bufs, 1, &mut dest);
+        assert_eq!(dest, [2, 3, 4]);
+        let mut dest = [0; 2];
+        gather(&bufs, 3, &mut dest);
+        assert_eq!(dest, [4, 5]);
+    }
+
+    #[test]
+    fn listener_registration() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        ble.channels.listen(0x81).unwrap();
+        assert!(matches!(ble.channels.listen(0x81), Err(Error::Busy)));
+        ble.channels.listen(0x83).unwrap();
+        ble.channels.unlisten(0x81);
+        ble.channels.listen(0x81).unwrap();
+    }
+
+    #[test]
+    fn connect_request_without_listener() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let req = LeCreditConnReq {
+            psm: 0x85,
+            scid: 0x40,
+            mtu: 100,
+            mps: 50,
+            credits: 7,
+        };
+        ble.channels
+            .handle_connect_request(conn, 2, &req, &ble.connections)
+            .unwrap();
+        let mut state = ble.channels.state.borrow_mut();
+        let response = state.signal_responses.pop_front().unwrap();
+        assert_eq!((response.handle, response.identifier), (conn, 2));
+        assert_eq!(
+            response.kind,
+            SignalResponseKind::RefuseLe(LeCreditConnResultCode::SpsmNotSupported)
+        );
+        assert!(state
+            .channels
+            .iter()
+            .all(|chan| chan.state == ChannelState::Disconnected));
+    }
+
+    #[test]
+    fn accept_before_listener() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let config = L2capChannelConfig::default();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        ble.channels.listen(0x83).unwrap();
+        let mut accept = pin!(ble.channels.accept(conn, &[0x83], &config, &ble));
+        assert!(accept.as_mut().poll(&mut cx).is_pending());
+
+        let req = LeCreditConnReq {
+            psm: 0x83,
+            scid: 0x40,
+            mtu: 100,
+            mps: 50,
+            credits: 7,
+        };
+        ble.channels
+            .handle_connect_request(conn, 1, &req, &ble.connections)
+            .unwrap();
+        // The listener leaves the request to the accept pending on its connection.
+        let mut listener = pin!(ble.channels.accept_from(None, &[0x83], &config, &ble));
+        assert!(listener.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(
+            ble.channels.state.borrow().channels[0].state,
+            ChannelState::PeerConnecting(1)
+        );
+        // The accept takes it, and waits to send its response.
+        assert!(accept.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(ble.channels.state.borrow().channels[0].state, ChannelState::Connected);
+    }
+
+    #[test]
+    fn channel_info_from_connect_request() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let req = LeCreditConnReq {
+            psm: 0x83,
+            scid: 0x40,
+            mtu: 100,
+            mps: 50,
+            credits: 7,
+        };
+        ble.channels.listen(0x83).unwrap();
+        ble.channels
+            .handle_connect_request(conn, 1, &req, &ble.connections)
+            .unwrap();
+        let info = ble.channels.info(ChannelIndex(0));
+        assert_eq!(
+            info,
+            L2capChannelInfo {
+                psm: 0x83,
+                peer_mtu: 100,
+                peer_mps: 50,
+                mps: 50,
+                initial_credits: 7,
+            }
+        );
+    }
+
+    #[test]
+    fn upgrade_released_halves() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+        ble.channels.inc_ref(idx);
+        assert!(matches!(
+            ble.channels.upgrade(idx, true, false),
+            Err(Error::InvalidState)
+        ));
+
+        ble.channels.release(idx, true, false);
+        ble.channels.upgrade(idx, true, false).unwrap();
+        assert!(matches!(
+            ble.channels.upgrade(idx, true, false),
+            Err(Error::InvalidState)
+        ));
+        assert!(matches!(
+            ble.channels.upgrade(idx, false, true),
+            Err(Error::InvalidState)
+        ));
+
+        ble.channels.disconnect(idx);
+        ble.channels.release(idx, true, true);
+        assert!(matches!(
+            ble.channels.upgrade(idx, false, true),
+            Err(Error::ChannelClosed)
+        ));
+    }
+
+    #[test]
+    fn fixed_channels() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        assert!(matches!(
+            ble.channels.open_fixed(conn, L2CAP_CID_ATT),
+            Err(Error::InvalidChannelId)
+        ));
+        assert!(matches!(
+            ble.channels.open_fixed(conn, 0x40),
+            Err(Error::InvalidChannelId)
+        ));
+
+        let index = ble.channels.open_fixed(conn, 0x3a).unwrap();
+        assert!(matches!(ble.channels.open_fixed(conn, 0x3a), Err(Error::Busy)));
+        assert!(ble.channels.has_fixed(conn, 0x3a));
+        assert!(!ble.channels.has_fixed(ConnHandle::new(34), 0x3a));
+
+        ble.channels.close_fixed(index);
+        assert!(!ble.channels.has_fixed(conn, 0x3a));
+    }
+
+    #[test]
+    fn peek_keeps_sdu_queued() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+                let mut packet = DefaultPacketPool::allocate().unwrap();
+                packet.as_mut()[..3].copy_from_slice(&[1, 2, 3]);
+                storage.inbound.try_send(Pdu::new(packet, 3)).unwrap();
+            })
+            .unwrap();
+
+        let header = embassy_futures::block_on(ble.channels.peek_sdu(idx, |sdu| sdu[0])).unwrap();
+        assert_eq!(header, 1);
+        assert_eq!(ble.channels.state.borrow().channels[idx.0 as usize].queued(), 1);
+        let pdu = ble.channels.try_receive_pdu(idx).unwrap();
+        assert_eq!(pdu.as_ref(), &[1, 2, 3]);
+        assert!(matches!(ble.channels.try_receive_pdu(idx), Err(Error::Busy)));
+    }
+
+    #[test]
+    fn peek_does_not_borrow_channel_state() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+                let mut packet = DefaultPacketPool::allocate().unwrap();
+                packet.as_mut()[..3].copy_from_slice(&[1, 2, 3]);
+                storage.inbound.try_send(Pdu::new(packet, 3)).unwrap();
+            })
+            .unwrap();
+
+        // The channel can be used from the closure.
+        let len = embassy_futures::block_on(ble.channels.peek_sdu(idx, |sdu| {
+            ble.channels.set_rx_quota(idx, Some(1));
+            sdu.len()
+        }))
+        .unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(ble.channels.try_receive_pdu(idx).unwrap().as_ref(), &[1, 2, 3]);
+
+        // An SDU peeked while the closure closes the channel is not kept.
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[0] = 4;
+        ble.channels.with_mut(|state| {
+            state.channels[idx.0 as usize]
+                .inbound
+                .try_send(Pdu::new(packet, 1))
+                .unwrap();
+        });
+        embassy_futures::block_on(ble.channels.peek_sdu(idx, |_| ble.channels.disconnect(idx))).unwrap();
+        assert!(ble.channels.state.borrow().channels[idx.0 as usize].peeked.is_none());
+    }
+
+    #[test]
+    fn try_receive_leaves_credits_to_runner_when_busy() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        // Without ACL buffers from the controller, the credits cannot be sent right away.
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let idx = ble.channels.alloc_connected(conn, 23, 23, 0);
+        let cid = {
+            let mut state = ble.channels.state.borrow_mut();
+            let chan = &mut state.channels[idx.0 as usize];
+            chan.flow_control = CreditFlowControl::new(CreditFlowPolicy::Every(1), 1);
+            chan.flow_control.confirm_received(1);
+            let mut packet = DefaultPacketPool::allocate().unwrap();
+            packet.as_mut()[..3].copy_from_slice(&[1, 2, 3]);
+            chan.inbound.try_send(Pdu::new(packet, 3)).unwrap();
+            chan.cid
+        };
+
+        let sdu = ble.channels.try_receive_sdu(idx, &ble).unwrap();
+        assert_eq!(sdu.as_ref(), &[1, 2, 3]);
+
+        let mut state = ble.channels.state.borrow_mut();
+        assert_eq!(state.channels[idx.0 as usize].flow_control.available(), 1);
+        let response = state.signal_responses.pop_front().unwrap();
+        assert_eq!(response.kind, SignalResponseKind::Credits { cid, credits: 1 });
+        let mut buf = [0; 32];
+        let [lo, hi] = cid.to_le_bytes();
+        assert_eq!(
+            response.encode(&mut buf).unwrap(),
+            &[8, 0, 5, 0, 0x16, response.identifier, 4, 0, lo, hi, 1, 0]
+        );
+    }
+
+    /// A connected channel with 16 credits and a host that writes every frame in one ACL packet.
+    fn sending_channel<'d>(
+        resources: &'d mut HostResources<DefaultPacketPool, 2, 2>,
+        mtu: u16,
+        mps: u16,
+    ) -> (BleHost<'d, MockController, DefaultPacketPool>, ChannelIndex) {
+        let ble = crate::new(MockController::new(), resources).host;
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        ble.init_without_controller(mps + 4, 16);
+        let idx = ble.channels.alloc_connected(conn, mtu, mps, 16);
+        (ble, idx)
+    }
+
+    /// The K-frames of an SDU on a channel with the given MPS and peer CID, with their L2CAP headers.
+    fn k_frames(sdu: &[u8], mps: usize, cid: u16) -> std::vec::Vec<std::vec::Vec<u8>> {
+        let (first, rest) = sdu.split_at(sdu.len().min(mps - 2));
+        let mut frames = std::vec![[
+            &(first.len() as u16 + 2).to_le_bytes()[..],
+            &cid.to_le_bytes(),
+            &(sdu.len() as u16).to_le_bytes(),
+            first,
+        ]
+        .concat()];
+        for chunk in rest.chunks(mps) {
+            frames.push([&(chunk.len() as u16).to_le_bytes()[..], &cid.to_le_bytes(), chunk].concat());
+        }
+        frames
+    }
+
+    fn sdu_of(data: &[u8]) -> Sdu<<DefaultPacketPool as PacketPool>::Packet> {
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[..data.len()].copy_from_slice(data);
+        Sdu::new(packet, data.len())
+    }
+
+    /// Send an SDU with [`ChannelManager::send_sdu`] and return the frames written to the controller.
+    fn send_sdu_frames(mtu: u16, mps: u16, data: &[u8]) -> std::vec::Vec<std::vec::Vec<u8>> {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let (ble, idx) = sending_channel(&mut resources, mtu, mps);
+        embassy_futures::block_on(ble.channels.send_sdu(idx, sdu_of(data), &ble)).unwrap();
+        assert_eq!(ble.channels.tx_credits(idx), 16 - (data.len() as u16 + 2).div_ceil(mps));
+        ble.controller.take_acl_data()
+    }
+
+    #[test]
+    fn send_sdu_single_frame() {
+        let frames = send_sdu_frames(100, 23, &[1, 2, 3, 4, 5]);
+        assert_eq!(frames, [[7, 0, 0x40, 0, 5, 0, 1, 2, 3, 4, 5]]);
+    }
+
+    #[test]
+    fn send_sdu_several_frames() {
+        let data: std::vec::Vec<u8> = (0..60).collect();
+        let frames = send_sdu_frames(100, 23, &data);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames.iter().map(|f| f.len() - 4).collect::<std::vec::Vec<_>>(),
+            [23, 23, 16]
+        );
+        assert_eq!(frames, k_frames(&data, 23, 0x40));
+    }
+
+    #[test]
+    fn send_sdu_exact_multiple_of_mps() {
+        // Both the SDU with its length field and the SDU alone fill a whole number of frames.
+        for len in [3 * 23 - 2, 2 * 23] {
+            let data: std::vec::Vec<u8> = (0..len as u8).collect();
+            let frames = send_sdu_frames(100, 23, &data);
+            assert_eq!(frames.len(), (len + 2).div_ceil(23));
+            assert!(frames.iter().all(|f| !f[4..].is_empty()));
+            assert_eq!(frames, k_frames(&data, 23, 0x40));
+        }
+    }
+
+    #[test]
+    fn send_sdu_copies_without_room_for_headers() {
+        // The packet holding the SDU has no room left for the 6 bytes of headers.
+        let data: std::vec::Vec<u8> = (0..DefaultPacketPool::MTU - 5).map(|i| i as u8).collect();
+        let frames = send_sdu_frames(512, 100, &data);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames, k_frames(&data, 100, 0x40));
+    }
+
+    #[test]
+    fn send_vectored_across_frames() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let (ble, idx) = sending_channel(&mut resources, 100, 10);
+        let data: std::vec::Vec<u8> = (0..30).collect();
+        // The buffers end inside frames, at frame boundaries, and include empty slices.
+        let bufs: [&[u8]; 6] = [&data[..5], &[], &data[5..8], &data[8..18], &[], &data[18..]];
+        let mut p_buf = [0; 64];
+        embassy_futures::block_on(ble.channels.send_vectored(idx, &bufs, &mut p_buf, &ble)).unwrap();
+        let frames = ble.controller.take_acl_data();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames, k_frames(&data, 10, 0x40));
+        assert_eq!(ble.channels.tx_credits(idx), 12);
+    }
+
+    #[test]
+    fn send_vectored_empty() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let (ble, idx) = sending_channel(&mut resources, 100, 10);
+        let mut p_buf = [0; 64];
+        let cases: [&[&[u8]]; 2] = [&[], &[&[], &[]]];
+        for bufs in cases {
+            embassy_futures::block_on(ble.channels.send_vectored(idx, bufs, &mut p_buf, &ble)).unwrap();
+            assert_eq!(ble.controller.take_acl_data(), [[2, 0, 0x40, 0, 0, 0]]);
+        }
+    }
+
+    #[test]
+    fn send_vectored_larger_than_mtu() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let (ble, idx) = sending_channel(&mut resources, 20, 10);
+        let mut p_buf = [0; 64];
+        let bufs: [&[u8]; 2] = [&[0; 15], &[0; 6]];
+        assert!(matches!(
+            embassy_futures::block_on(ble.channels.send_vectored(idx, &bufs, &mut p_buf, &ble)),
+            Err(BleHostError::BleHost(Error::InsufficientSpace))
+        ));
+        assert!(ble.controller.take_acl_data().is_empty());
+        assert_eq!(ble.channels.tx_credits(idx), 16);
+    }
+
+    #[test]
+    fn reserve_sdu_frames_fit_packets() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let fits = ble
+            .channels
+            .alloc_connected(conn, 512, DefaultPacketPool::MTU as u16 - 4, 2);
+        let (_, mps, peer_cid, grant) = embassy_futures::block_on(ble.channels.reserve_sdu(fits, 300)).unwrap();
+        assert_eq!(mps, DefaultPacketPool::MTU as u16 - 4);
+        assert_eq!(peer_cid, 0x40);
+        assert_eq!(grant.remaining(), 2);
+
+        let exceeds = ble
+            .channels
+            .alloc_connected(conn, 512, DefaultPacketPool::MTU as u16, 2);
+        assert!(matches!(
+            embassy_futures::block_on(ble.channels.reserve_sdu(exceeds, 10)),
+            Err(Error::InsufficientSpace)
+        ));
+    }
+
+    #[test]
+    fn receive_sdu_within_timeout() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let idx = ble.channels.alloc_connected(ConnHandle::new(33), 23, 23, 0);
+        let timeout = Duration::from_millis(10);
+        assert!(matches!(
+            embassy_futures::block_on(ble.channels.receive_sdu_timeout(idx, &ble, timeout)),
+            Err(BleHostError::BleHost(Error::Timeout))
+        ));
+
+        {
+            let mut state = ble.channels.state.borrow_mut();
+            let mut packet = DefaultPacketPool::allocate().unwrap();
+            packet.as_mut()[..3].copy_from_slice(&[1, 2, 3]);
+            state.channels[idx.0 as usize]
+                .inbound
+                .try_send(Pdu::new(packet, 3))
+                .unwrap();
+        }
+        let sdu = embassy_futures::block_on(ble.channels.receive_sdu_timeout(idx, &ble, timeout)).unwrap();
+        assert_eq!(sdu.as_ref(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn poll_send_returns_credits_to_waiting_sender() {
+        static WAITING: WakeFlag = WakeFlag::new();
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        // Without ACL buffers from the controller, no frame can be sent.
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let idx = ble.channels.alloc_connected(conn, 23, 23, 1);
+
+        let mut waiting = pin!(Poller::new(
+            poll_fn(|cx| ble
+                .channels
+                .poll_request_to_send(idx, 2, 40, Some(cx))
+                .map(|grant| grant.is_ok())),
+            &WAITING
+        ));
+        assert!(waiting.as_mut().poll().is_pending());
+        assert!(!waiting.is_woken());
+
+        // The credit taken while waiting for ACL buffers is returned to the channel, waking the other sender.
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+        assert!(ble
+            .channels
+            .poll_send(idx, &[1, 2, 3], &ble, Some(&mut cx))
+            .is_pending());
+        assert!(waiting.is_woken());
+        assert_eq!(ble.channels.tx_credits(idx), 1);
+        assert!(matches!(
+            ble.channels.try_send(idx, &[1, 2, 3], &ble),
+            Err(BleHostError::BleHost(Error::Busy))
+        ));
+    }
+
+    #[test]
+    fn state_changed_on_disconnect() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+
+        let from = ble.channels.channel_state(idx);
+        assert_eq!(from, L2capChannelState::Connected);
+        assert!(ble.channels.poll_state_changed(idx, from, &mut cx).is_pending());
+
+        ble.channels.handle_disconnect_request(BASE_ID + idx.0 as u16).unwrap();
+        assert_eq!(
+            ble.channels.poll_state_changed(idx, from, &mut cx),
+            Poll::Ready(L2capChannelState::Disconnecting)
+        );
+
+        ble.channels.disconnected(conn).unwrap();
+        assert_eq!(
+            ble.channels
+                .poll_state_changed(idx, L2capChannelState::Disconnected, &mut cx),
+            Poll::Ready(L2capChannelState::Disconnected)
+        );
+    }
+
+    #[test]
+    fn peer_reconfigure() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.enhanced = true;
+                storage.peer_cid = 0x50;
+                storage.local_mtu = 200;
+                storage.local_mps = 100;
+                storage.peer_mtu = 100;
+                storage.peer_mps = 100;
+                storage.mtu = 100;
+                storage.mps = 100;
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+
+        let req = CreditConnReconfigReq { mtu: 80, mps: 100 };
+        ble.channels
+            .handle_reconfigure_request(conn, 1, &req, &[0x50], &ble.connections)
+            .unwrap();
+        let req = CreditConnReconfigReq { mtu: 300, mps: 100 };
+        ble.channels
+            .handle_reconfigure_request(conn, 2, &req, &[0x50], &ble.connections)
+            .unwrap();
+        let req = CreditConnReconfigReq { mtu: 300, mps: 100 };
+        ble.channels
+            .handle_reconfigure_request(conn, 3, &req, &[0x51], &ble.connections)
+            .unwrap();
+
+        let mut state = ble.channels.state.borrow_mut();
+        let results: std::vec::Vec<_> = core::iter::from_fn(|| state.signal_responses.pop_front())
+            .map(|response| (response.identifier, response.kind))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (
+                    1,
+                    SignalResponseKind::Reconfigure(CreditConnReconfigResult::MtuReductionNotAllowed)
+                ),
+                (2, SignalResponseKind::Reconfigure(CreditConnReconfigResult::Success)),
+                (
+                    3,
+                    SignalResponseKind::Reconfigure(CreditConnReconfigResult::InvalidDcid)
+                ),
+            ]
+        );
+        // SDUs are sent with the MTU of the peer, even if it is larger than ours.
+        let chan = &state.channels[idx.0 as usize];
+        assert_eq!(chan.peer_mtu, 300);
+        assert_eq!(chan.mtu, 300);
+        assert_eq!(chan.local_mtu, 200);
+    }
+
+    #[test]
+    fn peer_reconfigure_overflow() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.enhanced = true;
+                storage.peer_cid = 0x50;
+                storage.peer_mtu = 100;
+                storage.peer_mps = 100;
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+
+        for identifier in 1..=4 {
+            let req = CreditConnReconfigReq {
+                mtu: 100 + identifier as u16,
+                mps: 100,
+            };
+            ble.channels
+                .handle_reconfigure_request(conn, identifier, &req, &[0x50], &ble.connections)
+                .unwrap();
+        }
+        // No response can be queued anymore, so the request is refused through the outbound queue.
+        let req = CreditConnReconfigReq { mtu: 200, mps: 100 };
+        ble.channels
+            .handle_reconfigure_request(conn, 5, &req, &[0x50], &ble.connections)
+            .unwrap();
+        let (handle, pdu) = embassy_futures::block_on(ble.connections.outbound());
+        assert_eq!(handle, conn);
+        assert_eq!(pdu.as_ref(), [6, 0, 5, 0, 0x1A, 5, 2, 0, 4, 0]);
+        let state = ble.channels.state.borrow();
+        assert_eq!(state.signal_responses.len(), 4);
+        assert_eq!(state.channels[idx.0 as usize].peer_mtu, 104);
+    }
+
+    #[test]
+    fn own_reconfigure() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.enhanced = true;
+                storage.local_mtu = 100;
+                storage.local_mps = 100;
+                storage.peer_mtu = 100;
+                storage.peer_mps = 100;
+                storage.mtu = 100;
+                storage.mps = 100;
+                storage.reconfig = Some(Reconfigure {
+                    identifier: 9,
+                    mtu: 200,
+                    mps: 150,
+                    result: None,
+                });
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+
+        let res = CreditConnReconfigRes {
+            result: CreditConnReconfigResult::Success as u16,
+        };
+        ble.channels.handle_reconfigure_response(conn, 9, &res).unwrap();
+        let state = ble.channels.state.borrow();
+        let chan = &state.channels[idx.0 as usize];
+        assert_eq!((chan.local_mtu, chan.local_mps), (200, 150));
+        // What we send is still limited by the peer.
+        assert_eq!((chan.mtu, chan.mps), (100, 100));
+        assert_eq!(chan.reconfig.map(|reconfig| reconfig.result), Some(Some(0)));
+    }
+
+    #[test]
+    fn accept_enhanced_request() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let req = CreditConnReq {
+            spsm: 0x27,
+            mtu: 100,
+            mps: 80,
+            credits: 5,
+        };
+        // Three channels are requested, but only two can be allocated.
+        ble.channels
+            .handle_enhanced_connect_request(conn, 4, &req, &[0x40, 0x41, 0x42], &ble.connections)
+            .unwrap();
+        let config = L2capChannelConfig {
+            mtu: Some(90),
+            mps: Some(90),
+            ..Default::default()
+        };
+        assert!(ble
+            .channels
+            .poll_accept_enhanced(conn, &[0x25], 2, &config, None)
+            .is_pending());
+        let Poll::Ready(accepted) = ble.channels.poll_accept_enhanced(conn, &[0x27], 1, &config, None) else {
+            panic!("request not found");
+        };
+        assert_eq!(accepted.identifier, 4);
+        assert_eq!(accepted.channels.len(), 1);
+        assert_eq!(accepted.dcids, [BASE_ID, 0, 0]);
+        {
+            let state = ble.channels.state.borrow();
+            let chan = &state.channels[0];
+            assert_eq!(chan.state, ChannelState::Connected);
+            assert_eq!((chan.peer_cid, chan.peer_credits), (0x40, 5));
+            assert_eq!((chan.local_mtu, chan.local_mps), (90, 90));
+            assert_eq!((chan.mtu, chan.mps), (100, 80));
+            assert_eq!(state.channels[1].state, ChannelState::Disconnected);
+        }
+        // The request has been answered.
+        assert!(ble
+            .channels
+            .poll_accept_enhanced(conn, &[0x27], 1, &config, None)
+            .is_pending());
+    }
+
+    #[test]
+    fn refuse_enhanced_request_without_channels() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 0> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        let req = CreditConnReq {
+            spsm: 0x27,
+            mtu: 100,
+            mps: 80,
+            credits: 5,
+        };
+        ble.channels
+            .handle_enhanced_connect_request(conn, 4, &req, &[0x40, 0x41], &ble.connections)
+            .unwrap();
+        let mut state = ble.channels.state.borrow_mut();
+        let response = state.signal_responses.pop_front().unwrap();
+        assert_eq!((response.handle, response.identifier), (conn, 4));
+        assert_eq!(
+            response.kind,
+            SignalResponseKind::Refuse {
+                count: 2,
+                result: LeCreditConnResultCode::NoResources,
+            }
+        );
+    }
+
+    #[test]
+    fn create_enhanced_response() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        for _ in 0..2 {
+            ble.channels
+                .alloc(conn, |storage| {
+                    storage.mtu = 100;
+                    storage.mps = 100;
+                    storage.enhanced = true;
+                    storage.state = ChannelState::Connecting(7);
+                })
+                .unwrap();
+        }
+        // The peer accepts the first channel and refuses the second.
+        let res = CreditConnRes {
+            mtu: 80,
+            mps: 120,
+            credits: 3,
+            result: LeCreditConnResultCode::NoResources as u16,
+        };
+        ble.channels
+            .handle_enhanced_connect_response(conn, 7, &res, &[0x60, 0])
+            .unwrap();
+        let state = ble.channels.state.borrow();
+        let chan = &state.channels[0];
+        assert_eq!(chan.state, ChannelState::Connected);
+        assert_eq!((chan.peer_cid, chan.peer_credits), (0x60, 3));
+        assert_eq!((chan.mtu, chan.mps), (80, 120));
+        assert_eq!(state.channels[1].state, ChannelState::Disconnected);
+        drop(state);
+
+        assert_eq!(
+            ble.channels.handle_enhanced_connect_response(conn, 8, &res, &[0x61]),
+            Err(Error::NotFound)
+        );
+    }
 }
@@ -95,6 +95,11 @@ impl<CTX: Clone + Copy> CommandState<CTX> {
         })
     }
 
+    /// Whether a command is active and has not been canceled.
+    pub fn is_active(&self) -> bool {
+        self.with_inner(|inner| matches!(inner.state, State::Active))
+    }
+
     pub fn done(&self) {
         self.with_inner(|inner| {
             inner.state = State::Idle;
@@ -64,6 +64,22 @@ pub const DEFAULT_PACKET_POOL_SIZE: usize = raw::DEFAULT_PACKET_POOL_SIZE;
 /// Default: 251.
 pub const DEFAULT_PACKET_POOL_MTU: usize = raw::DEFAULT_PACKET_POOL_MTU;
 
+/// L2CAP default packet pool small packet count
+///
+/// Number of additional small packets in the default packet pool, used for short packets
+/// such as received ATT requests and security manager commands. When they are exhausted, packets of
+/// the regular size are used instead.
+///
+/// Default: 0.
+pub const DEFAULT_PACKET_POOL_SMALL_SIZE: usize = raw::DEFAULT_PACKET_POOL_SMALL_SIZE;
+
+/// L2CAP default packet pool small packet mtu
+///
+/// Size of the small packets of the default packet pool, including the 4 byte L2CAP header.
+///
+/// Default: 27.
+pub const DEFAULT_PACKET_POOL_SMALL_MTU: usize = raw::DEFAULT_PACKET_POOL_SMALL_MTU;
+
 /// Default: 1.
 pub const GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS: usize = raw::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
 
@@ -71,3 +87,50 @@ pub const GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS: usize = raw::GATT_CLIENT_NOT
 ///
 /// Default: 1.
 pub const GATT_CLIENT_NOTIFICATION_QUEUE_SIZE: usize = raw::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
+
+/// GATT server prepare queue size.
+///
+/// Number of bytes of prepared writes the GATT server queues for each client until they are executed,
+/// used by clients to write values longer than the ATT MTU. Each queued write takes 6 bytes on top of its
+/// value, but consecutive parts of the same value share them. With 0, prepared writes are rejected, so
+/// servers with values longer than the ATT MTU should enable a queue, e.g. `gatt-server-prepare-queue-size-512`.
+///
+/// Default: 0.
+pub const GATT_SERVER_PREPARE_QUEUE_SIZE: usize = raw::GATT_SERVER_PREPARE_QUEUE_SIZE;
+
+/// GATT server notification queue size.
+///
+/// Number of characteristics notified with `Characteristic::notify_all` that can wait to be sent to each client.
+/// Notifying a characteristic that is already waiting does not take another entry.
+///
+/// Default: 4.
+pub const GATT_SERVER_NOTIFICATION_QUEUE_SIZE: usize = raw::GATT_SERVER_NOTIFICATION_QUEUE_SIZE;
+
+const _: () = {
+    assert!(
+        CONNECTION_EVENT_QUEUE_SIZE > 0,
+        "connection event queue size must be at least 1"
+    );
+    assert!(L2CAP_TX_QUEUE_SIZE > 0, "L2CAP TX queue size must be at least 1");
+    assert!(L2CAP_RX_QUEUE_SIZE > 0, "L2CAP RX queue size must be at least 1");
+    assert!(
+        DEFAULT_PACKET_POOL_SIZE > 0,
+        "default packet pool size must be at least 1"
+    );
+    assert!(
+        DEFAULT_PACKET_POOL_MTU >= 27,
+        "default packet pool MTU must be at least 27 bytes (minimum ATT MTU of 23 plus the 4 byte L2CAP header)"
+    );
+    assert!(
+        DEFAULT_PACKET_POOL_SMALL_SIZE == 0 || DEFAULT_PACKET_POOL_SMALL_MTU <= DEFAULT_PACKET_POOL_MTU,
+        "default packet pool small MTU must not exceed the default packet pool MTU"
+    );
+    assert!(
+        GATT_CLIENT_NOTIFICATION_QUEUE_SIZE > 0,
+        "GATT client notification queue size must be at least 1"
+    );
+    assert!(
+        GATT_SERVER_NOTIFICATION_QUEUE_SIZE > 0,
+        "GATT server notification queue size must be at least 1"
+    );
+};
@@ -1,15 +1,22 @@
 //! BLE connection.
 
-use bt_hci::cmd::le::{LeConnUpdate, LeReadLocalSupportedFeatures, LeReadPhy, LeSetDataLength, LeSetPhy};
+use core::future::poll_fn;
+
+use bt_hci::cmd::le::{
+    LeConnUpdate, LeReadLocalSupportedFeatures, LeReadPhy, LeReadRemoteFeatures, LeSetDataLength, LeSetPhy,
+};
+use bt_hci::cmd::link_control::ReadRemoteVersionInformation;
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
 use bt_hci::param::{
-    AddrKind, AllPhys, BdAddr, ConnHandle, DisconnectReason, LeConnRole, PhyKind, PhyMask, PhyOptions, Status,
+    AddrKind, AllPhys, BdAddr, ConnHandle, CoreSpecificationVersion, DisconnectReason, LeConnRole, LeFeatureMask,
+    PhyKind, PhyMask, PhyOptions, Status,
 };
 #[cfg(feature = "gatt")]
 use embassy_sync::blocking_mutex::raw::RawMutex;
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant};
 
+use crate::activity::RadioActivity;
 use crate::connection_manager::ConnectionManager;
 #[cfg(feature = "connection-metrics")]
 pub use crate::connection_manager::Metrics as ConnectionMetrics;
@@ -27,6 +34,7 @@ use crate::{bt_hci_duration, BleHostError, Error, Identity, PacketPool, Stack};
 ///
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SecurityLevel {
     /// No encryption and no authentication. All connections start on this security level.
     NoEncryption,
@@ -49,6 +57,8 @@ impl SecurityLevel {
 }
 
 /// Connection configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ConnectConfig<'d> {
     /// Scan configuration to use while connecting.
     pub scan_config: ScanConfig<'d>,
@@ -57,6 +67,8 @@ pub struct ConnectConfig<'d> {
 }
 
 /// Scan/connect configuration.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct ScanConfig<'d> {
     /// Active scanning.
     pub active: bool,
@@ -87,7 +99,7 @@ impl Default for ScanConfig<'_> {
 
 /// PHYs to scan on.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
 pub enum PhySet {
     /// 1Mbps phy
@@ -109,21 +121,85 @@ pub enum PhySet {
 /// Connection parameters.
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnectParams {
     /// Minimum connection interval.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::duration"))]
     pub min_connection_interval: Duration,
     /// Maximum connection interval.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::duration"))]
     pub max_connection_interval: Duration,
     /// Maximum slave latency.
     pub max_latency: u16,
     /// Event length.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::duration"))]
     pub min_event_length: Duration,
     /// Event length.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::duration"))]
     pub max_event_length: Duration,
     /// Supervision timeout.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::duration"))]
     pub supervision_timeout: Duration,
 }
 
+/// Link layer version information of the peer controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RemoteVersion {
+    /// Bluetooth Core Specification version implemented by the peer link layer.
+    pub version: CoreSpecificationVersion,
+    /// Company identifier of the peer controller manufacturer.
+    pub company_id: u16,
+    /// Manufacturer specific revision of the peer link layer.
+    pub subversion: u16,
+}
+
+/// Timing of the connection events of a link, relative to a reference connection event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionEventTiming {
+    /// Connection event counter of the reference event.
+    pub event_counter: u16,
+    /// Anchor point of the reference event.
+    pub anchor: Instant,
+    /// Connection interval.
+    pub interval: Duration,
+    /// Whether the anchor was reported by the controller.
+    ///
+    /// Otherwise the host estimated it from the time the connection or parameter update event was
+    /// received, which lags the actual anchor point by the HCI transport latency.
+    pub reported: bool,
+}
+
+impl ConnectionEventTiming {
+    /// The counter and anchor point of the first connection event at or after `at`.
+    ///
+    /// Assumes that no connection event was skipped by the link layer since the reference event.
+    pub fn next_event(&self, at: Instant) -> (u16, Instant) {
+        let interval = self.interval.as_ticks().max(1);
+        let events = if at > self.anchor {
+            (at - self.anchor).as_ticks().div_ceil(interval)
+        } else {
+            0
+        };
+        (
+            self.event_counter.wrapping_add(events as u16),
+            self.anchor + Duration::from_ticks(events * interval),
+        )
+    }
+
+    /// Re-base the timing on the next connection event at or after `at`, with a new interval.
+    pub(crate) fn rebase(&self, at: Instant, interval: Duration) -> Self {
+        let (event_counter, anchor) = self.next_event(at);
+        Self {
+            event_counter,
+            anchor,
+            interval,
+            reported: false,
+        }
+    }
+}
+
 /// A connection event.
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -160,6 +236,11 @@ pub enum ConnectionEvent {
         /// Max RX time.
         max_rx_time: u16,
     },
+    /// An L2CAP channel was disconnected because it was idle for longer than its configured timeout.
+    L2capChannelIdle {
+        /// PSM of the channel.
+        psm: u16,
+    },
     /// A request to change the connection parameters.
     RequestConnectionParams {
         /// Minimum connection interval.
@@ -191,6 +272,29 @@ pub enum ConnectionEvent {
     #[cfg(feature = "security")]
     /// Pairing completed
     PairingFailed(Error),
+    #[cfg(feature = "security")]
+    /// Pairing completed with bonding, and the bond awaits approval by the application.
+    ///
+    /// Sent instead of [`ConnectionEvent::PairingComplete`] when bond approval is required, see
+    /// [`Connection::set_bond_approval_required`]. The link is encrypted, but the keys are discarded
+    /// on disconnect unless the bond is approved with [`Connection::approve_bond`].
+    BondingRequested {
+        /// Security level of this pairing
+        security_level: SecurityLevel,
+        /// Bond information to store if the bond is approved.
+        bond: BondInformation,
+    },
+    #[cfg(feature = "security")]
+    /// A pairing attempt by the peer was rejected, because an earlier pairing with it failed recently.
+    ///
+    /// See [`PairingBackoff`](crate::PairingBackoff). Peers that keep failing may be added to a block list
+    /// by the application.
+    PairingRejected {
+        /// Number of failed pairing attempts by the peer.
+        failed_attempts: u8,
+        /// Time left before the peer may attempt pairing again.
+        retry_after: Duration,
+    },
 }
 
 impl Default for ConnectParams {
@@ -221,6 +325,27 @@ impl<P: PacketPool> Clone for Connection<'_, P> {
     }
 }
 
+impl<P: PacketPool> core::fmt::Debug for Connection<'_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Connection")
+            .field("index", &self.index)
+            .field("handle", &self.handle().raw())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for Connection<'_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "Connection {{ index: {}, handle: {} }}",
+            self.index,
+            self.handle().raw()
+        );
+    }
+}
+
 impl<P: PacketPool> Drop for Connection<'_, P> {
     fn drop(&mut self) {
         self.manager.dec_ref(self.index);
@@ -248,6 +373,16 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.try_send(self.index, pdu)
     }
 
+    /// Count of ACL packets the link will have completed once the packets now in flight are completed.
+    pub(crate) fn tx_target(&self) -> u32 {
+        self.manager.tx_target(self.index)
+    }
+
+    /// Wait until the link has completed `target` ACL packets, see [`tx_target`](Self::tx_target).
+    pub(crate) async fn tx_completed(&self, target: u32) -> Result<(), Error> {
+        poll_fn(|cx| self.manager.poll_tx_completed(self.index, target, cx)).await
+    }
+
     pub(crate) async fn post_event(&self, event: ConnectionEvent) {
         self.manager.post_event(self.index, event).await
     }
@@ -277,6 +412,27 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.get_att_mtu()
     }
 
+    /// Timing of the connection events, for aligning application activity with radio events.
+    ///
+    /// The timing is estimated by the host unless the application reported the anchor points of the
+    /// controller with [`Stack::set_connection_event_anchor`]. Returns `None` if it is unknown.
+    pub fn event_timing(&self) -> Option<ConnectionEventTiming> {
+        self.manager.event_timing(self.index)
+    }
+
+    /// The counter and anchor point of the next connection event, if the timing is known.
+    pub fn next_connection_event(&self) -> Option<(u16, Instant)> {
+        self.event_timing().map(|timing| timing.next_event(Instant::now()))
+    }
+
+    /// Estimated radio activity of the connection events since the link was established.
+    ///
+    /// Assumes both sides send an empty packet in every connection event, so data transfers and the
+    /// peripheral latency are not accounted for. The estimate stops when the link is disconnected.
+    pub fn radio_activity(&self) -> RadioActivity {
+        self.manager.radio_activity(self.index)
+    }
+
     /// The connection role for this connection.
     pub fn role(&self) -> LeConnRole {
         self.manager.role(self.index)
@@ -307,6 +463,16 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.get_security_level(self.index)
     }
 
+    /// Whether the peer of this connection is bonded.
+    pub fn is_bonded(&self) -> bool {
+        self.bonded_identity().is_some()
+    }
+
+    /// Identity of the bond of the peer, which stays the same when the peer uses resolvable private addresses.
+    pub(crate) fn bonded_identity(&self) -> Option<Identity> {
+        self.manager.bonded_identity(self.index)
+    }
+
     /// Get whether the connection is set as bondable or not.
     ///
     /// This is only relevant before pairing has started.
@@ -332,6 +498,34 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.set_bondable(self.index, bondable)
     }
 
+    /// Get whether bonds made on this connection need approval by the application.
+    pub fn bond_approval_required(&self) -> Result<bool, Error> {
+        self.manager.get_bond_approval_required(self.index)
+    }
+
+    /// Set whether bonds made on this connection need approval by the application.
+    ///
+    /// By default bonds are stored as soon as pairing completes. When approval is required, a bonding
+    /// pairing ends with a [`ConnectionEvent::BondingRequested`] event instead of
+    /// [`ConnectionEvent::PairingComplete`], and the keys are only kept after disconnecting once the bond
+    /// is approved with [`approve_bond()`](Self::approve_bond). This allows products with limited bond
+    /// slots or an explicit pairing mode to decide which peers to remember.
+    ///
+    /// Like [`set_bondable()`](Self::set_bondable), this must be set before pairing is initiated.
+    pub fn set_bond_approval_required(&self, required: bool) -> Result<(), Error> {
+        self.manager.set_bond_approval_required(self.index, required)
+    }
+
+    /// Approve the bond requested with [`ConnectionEvent::BondingRequested`].
+    ///
+    /// The keys are kept after disconnecting, and the returned bond information should be stored in
+    /// non-volatile memory like the bond of a [`ConnectionEvent::PairingComplete`] event. A bond that is not
+    /// approved is discarded on disconnect.
+    #[cfg(feature = "security")]
+    pub fn approve_bond(&self) -> Result<BondInformation, Error> {
+        self.manager.approve_bond(self.index)
+    }
+
     /// Confirm that the displayed pass key matches the one displayed on the other party
     pub fn pass_key_confirm(&self) -> Result<(), Error> {
         self.manager.pass_key_confirm(self.index, true)
@@ -353,6 +547,14 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
             .request_disconnect(self.index, DisconnectReason::RemoteUserTerminatedConn);
     }
 
+    /// Set the transmit priority for this connection.
+    ///
+    /// Controller ACL buffers are shared between connections and handed out round-robin. A connection
+    /// with priority `n` is served up to `n` times per round. The default priority is 1, and 0 is treated as 1.
+    pub fn set_tx_priority(&self, priority: u8) {
+        self.manager.set_tx_priority(self.index, priority);
+    }
+
     /// Read metrics for this connection
     #[cfg(feature = "connection-metrics")]
     pub fn metrics<F: FnOnce(&ConnectionMetrics) -> R, R>(&self, f: F) -> R {
@@ -369,6 +571,48 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         Ok(ret.rssi)
     }
 
+    /// Read the link layer version information of the peer.
+    ///
+    /// The information is requested from the peer the first time and cached for the lifetime of the connection.
+    pub async fn remote_version<T>(&self, stack: &Stack<'_, T, P>) -> Result<RemoteVersion, BleHostError<T::Error>>
+    where
+        T: ControllerCmdAsync<ReadRemoteVersionInformation>,
+    {
+        if let Some(version) = self.manager.remote_version(self.index) {
+            return Ok(version);
+        }
+        stack
+            .host
+            .async_command(ReadRemoteVersionInformation::new(self.handle()))
+            .await?;
+        Ok(self
+            .manager
+            .wait_remote_info(self.index, |storage| &mut storage.remote_version)
+            .await?)
+    }
+
+    /// Read the LE features supported by the peer.
+    ///
+    /// Use this to check for e.g. 2M PHY or data length extension support before starting a procedure
+    /// that would otherwise fail with `UNSUPPORTED_REMOTE_FEATURE`. The features are requested from the peer
+    /// the first time and cached for the lifetime of the connection.
+    pub async fn remote_features<T>(&self, stack: &Stack<'_, T, P>) -> Result<LeFeatureMask, BleHostError<T::Error>>
+    where
+        T: ControllerCmdAsync<LeReadRemoteFeatures>,
+    {
+        if let Some(features) = self.manager.remote_features(self.index) {
+            return Ok(features);
+        }
+        stack
+            .host
+            .async_command(LeReadRemoteFeatures::new(self.handle()))
+            .await?;
+        Ok(self
+            .manager
+            .wait_remote_info(self.index, |storage| &mut storage.remote_features)
+            .await?)
+    }
+
     /// Update phy for this connection.
     ///
     /// This updates both TX and RX phy of the connection. For more fine grained control,
@@ -4,27 +4,35 @@ use core::future::poll_fn;
 use core::future::Future;
 use core::task::{Context, Poll};
 
-use bt_hci::param::{AddrKind, BdAddr, ConnHandle, DisconnectReason, LeConnRole, Status};
+use bt_hci::param::{AddrKind, BdAddr, ConnHandle, DisconnectReason, LeConnRole, LeFeatureMask, Status};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::Instant;
 #[cfg(feature = "security")]
 use embassy_time::TimeoutError;
 
-use crate::connection::{Connection, ConnectionEvent, SecurityLevel};
-use crate::host::EventHandler;
+use crate::activity::{ActivityTimer, RadioActivity, CONNECTION_EVENT_AIRTIME};
+use crate::connection::{Connection, ConnectionEvent, ConnectionEventTiming, RemoteVersion, SecurityLevel};
+use crate::host::{EventHandler, OnDrop};
 use crate::pdu::Pdu;
 use crate::prelude::sar::PacketReassembly;
 #[cfg(feature = "security")]
-use crate::security_manager::{SecurityEventData, SecurityManager};
+use crate::security_manager::{BondInformation, SecurityEventData, SecurityManager};
+use crate::table::Table;
 use crate::{config, Error, Identity, PacketPool};
 
 struct State<'d, P> {
-    connections: &'d mut [ConnectionStorage<P>],
+    connections: Table<'d, ConnectionStorage<P>>,
     central_waker: WakerRegistration,
     peripheral_waker: WakerRegistration,
     disconnect_waker: WakerRegistration,
-    default_link_credits: usize,
+    /// ACL buffers available in the controller, shared by all links.
+    link_credits: usize,
+    /// Index of the link currently being served by the TX scheduler.
+    tx_turn: usize,
+    /// Grants left for the link being served before moving on to the next one.
+    tx_quota: u8,
     default_att_mtu: u16,
 }
 
@@ -44,6 +52,43 @@ impl<P> State<'_, P> {
             "Too many references to the same connection"
         );
     }
+
+    /// Find the link that should be granted ACL buffers next, treating `index` as waiting.
+    ///
+    /// Links are served round-robin starting from the link whose turn it is, skipping those
+    /// that are not waiting to send.
+    fn next_sender(&self, index: usize) -> usize {
+        let n = self.connections.len();
+        for i in 0..n {
+            let j = (self.tx_turn + i) % n;
+            let storage = &self.connections[j];
+            if j == index || (storage.state == ConnectionState::Connected && storage.tx_waiting) {
+                return j;
+            }
+        }
+        index
+    }
+
+    /// Account for a grant to `index`, moving to the next link once its weighted share is used up.
+    fn advance_turn(&mut self, index: usize) {
+        if index != self.tx_turn || self.tx_quota == 0 {
+            self.tx_turn = index;
+            self.tx_quota = self.connections[index].tx_priority.max(1);
+        }
+        self.tx_quota -= 1;
+        if self.tx_quota == 0 {
+            self.tx_turn = (index + 1) % self.connections.len();
+        }
+    }
+
+    /// Let waiting links re-check whether it is their turn to send.
+    ///
+    /// Links polling without holding their place in line are woken too.
+    fn wake_senders(&mut self) {
+        for storage in self.connections.iter_mut() {
+            storage.link_credit_waker.wake();
+        }
+    }
 }
 
 type EventChannel = Channel<NoopRawMutex, ConnectionEvent, { config::CONNECTION_EVENT_QUEUE_SIZE }>;
@@ -57,14 +102,16 @@ pub(crate) struct ConnectionManager<'d, P: PacketPool> {
 }
 
 impl<'d, P: PacketPool> ConnectionManager<'d, P> {
-    pub(crate) fn new(connections: &'d mut [ConnectionStorage<P::Packet>], default_att_mtu: u16) -> Self {
+    pub(crate) fn new(connections: Table<'d, ConnectionStorage<P::Packet>>, default_att_mtu: u16) -> Self {
         Self {
             state: RefCell::new(State {
                 connections,
                 central_waker: WakerRegistration::new(),
                 peripheral_waker: WakerRegistration::new(),
                 disconnect_waker: WakerRegistration::new(),
-                default_link_credits: 0,
+                link_credits: 0,
+                tx_turn: 0,
+                tx_quota: 0,
                 default_att_mtu,
             }),
             outbound: Channel::new(),
@@ -233,6 +280,106 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         Err(Error::Disconnected)
     }
 
+    /// The remote version information, if it has already been read for this link.
+    pub(crate) fn remote_version(&self, index: u8) -> Option<RemoteVersion> {
+        match self.state.borrow().connections[index as usize].remote_version {
+            RemoteInfo::Known(version) => Some(version),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_remote_version(
+        &self,
+        h: ConnHandle,
+        version: Result<RemoteVersion, bt_hci::param::Error>,
+    ) -> Result<(), Error> {
+        self.with_connected_handle(h, |storage| {
+            storage.remote_version = match version {
+                Ok(version) => RemoteInfo::Known(version),
+                Err(e) => RemoteInfo::Failed(e),
+            };
+            storage.remote_info_waker.wake();
+            Ok(())
+        })
+    }
+
+    pub(crate) fn event_timing(&self, index: u8) -> Option<ConnectionEventTiming> {
+        self.state.borrow().connections[index as usize].event_timing
+    }
+
+    /// Update the connection event timing of a link.
+    pub(crate) fn update_event_timing<F>(&self, h: ConnHandle, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(Option<ConnectionEventTiming>) -> Result<ConnectionEventTiming, Error>,
+    {
+        self.with_connected_handle(h, |storage| {
+            let timing = f(storage.event_timing)?;
+            storage
+                .activity
+                .set_interval(Instant::now(), timing.interval, CONNECTION_EVENT_AIRTIME);
+            storage.event_timing = Some(timing);
+            Ok(())
+        })
+    }
+
+    /// Estimated radio activity of a link since it was established.
+    pub(crate) fn radio_activity(&self, index: u8) -> RadioActivity {
+        self.state.borrow().connections[index as usize]
+            .activity
+            .activity(Instant::now())
+    }
+
+    /// The remote LE features, if they have already been read for this link.
+    pub(crate) fn remote_features(&self, index: u8) -> Option<LeFeatureMask> {
+        match self.state.borrow().connections[index as usize].remote_features {
+            RemoteInfo::Known(features) => Some(features),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_remote_features(
+        &self,
+        h: ConnHandle,
+        features: Result<LeFeatureMask, bt_hci::param::Error>,
+    ) -> Result<(), Error> {
+        self.with_connected_handle(h, |storage| {
+            storage.remote_features = match features {
+                Ok(features) => RemoteInfo::Known(features),
+                Err(e) => RemoteInfo::Failed(e),
+            };
+            storage.remote_info_waker.wake();
+            Ok(())
+        })
+    }
+
+    /// Wait for a remote information request on this link to complete.
+    pub(crate) async fn wait_remote_info<T: Copy>(
+        &self,
+        index: u8,
+        f: impl Fn(&mut ConnectionStorage<P::Packet>) -> &mut RemoteInfo<T>,
+    ) -> Result<T, Error> {
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            let storage = &mut state.connections[index as usize];
+            if storage.state != ConnectionState::Connected {
+                return Poll::Ready(Err(Error::Disconnected));
+            }
+            match *f(storage) {
+                RemoteInfo::Known(value) => Poll::Ready(Ok(value)),
+                RemoteInfo::Failed(e) => {
+                    // Allow the request to be retried
+                    *f(storage) = RemoteInfo::Unknown;
+                    Poll::Ready(Err(Error::Hci(e)))
+                }
+                RemoteInfo::Unknown => {
+                    storage.remote_info_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
     pub(crate) fn received(&self, h: ConnHandle) -> Result<(), Error> {
         self.with_connected_handle(h, |storage| {
             #[cfg(feature = "connection-metrics")]
@@ -255,21 +402,12 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
 
     pub(crate) fn disconnected(&self, h: ConnHandle, reason: Status) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
+        let state = &mut *state;
         for (idx, storage) in state.connections.iter_mut().enumerate() {
             if Some(h) == storage.handle && storage.state != ConnectionState::Disconnected {
-                storage.state = ConnectionState::Disconnected;
-                storage.reassembly.clear();
-                let _ = storage.events.try_send(ConnectionEvent::Disconnected { reason });
-                #[cfg(feature = "gatt")]
-                storage.gatt.clear();
-                #[cfg(feature = "connection-metrics")]
-                storage.metrics.reset();
-                #[cfg(feature = "security")]
-                {
-                    storage.security_level = SecurityLevel::NoEncryption;
-                    storage.bondable = false;
-                    let _ = self.security_manager.disconnect(h, storage.peer_identity);
-                }
+                // The controller flushes any packets still queued for the link.
+                state.link_credits += self.mark_disconnected(storage, h, reason);
+                state.wake_senders();
                 return Ok(());
             }
         }
@@ -277,6 +415,51 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         Err(Error::NotFound)
     }
 
+    /// Mark every link as disconnected, invoking `f` for each handle that was not already disconnected.
+    ///
+    /// Used when the controller is reset and all links are lost without disconnection events.
+    pub(crate) fn disconnected_all<F: FnMut(ConnHandle)>(&self, reason: Status, mut f: F) {
+        let mut state = self.state.borrow_mut();
+        for storage in state.connections.iter_mut() {
+            if storage.state != ConnectionState::Disconnected {
+                if let Some(h) = storage.handle {
+                    let _ = self.mark_disconnected(storage, h, reason);
+                    f(h);
+                } else {
+                    storage.state = ConnectionState::Disconnected;
+                }
+            }
+        }
+        state.disconnect_waker.wake();
+    }
+
+    /// Returns the number of ACL packets that were still in flight for the link.
+    fn mark_disconnected(&self, storage: &mut ConnectionStorage<P::Packet>, h: ConnHandle, reason: Status) -> usize {
+        let in_flight = core::mem::take(&mut storage.in_flight);
+        storage.tx_waiting = false;
+        storage.link_credit_waker.wake();
+        storage.tx_completed_waker.wake();
+        storage.state = ConnectionState::Disconnected;
+        storage.activity.stop(Instant::now());
+        storage.reassembly.clear();
+        storage.remote_version = RemoteInfo::Unknown;
+        storage.remote_features = RemoteInfo::Unknown;
+        storage.remote_info_waker.wake();
+        let _ = storage.events.try_send(ConnectionEvent::Disconnected { reason });
+        #[cfg(feature = "gatt")]
+        storage.gatt.clear();
+        #[cfg(feature = "connection-metrics")]
+        storage.metrics.reset();
+        #[cfg(feature = "security")]
+        {
+            storage.security_level = SecurityLevel::NoEncryption;
+            storage.bondable = false;
+            storage.bond_approval = false;
+            let _ = self.security_manager.disconnect(h, storage.peer_identity);
+        }
+        in_flight
+    }
+
     pub(crate) fn connect(
         &self,
         handle: ConnHandle,
@@ -285,38 +468,46 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         role: LeConnRole,
     ) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
-        let default_credits = state.default_link_credits;
         let default_att_mtu = state.default_att_mtu;
-        for (idx, storage) in state.connections.iter_mut().enumerate() {
-            if ConnectionState::Disconnected == storage.state && storage.refcount == 0 {
-                storage.events.clear();
-                storage.reassembly.clear();
-                storage.state = ConnectionState::Connecting;
-                storage.link_credits = default_credits;
-                // Default ATT MTU is 23
-                storage.att_mtu = 23;
-                storage.handle.replace(handle);
-                storage.peer_addr_kind.replace(peer_addr_kind);
-                storage.peer_identity.replace(Identity {
-                    bd_addr: peer_addr,
-                    #[cfg(feature = "security")]
-                    irk: None,
-                });
-                storage.role.replace(role);
+        let free = state
+            .connections
+            .iter()
+            .position(|storage| ConnectionState::Disconnected == storage.state && storage.refcount == 0);
+        let Some(idx) = free.or_else(|| state.connections.grow(ConnectionStorage::new)) else {
+            warn!("[link][connect] no available slot found for handle {:?}", handle);
+            return Err(Error::NotFound);
+        };
+        let storage = &mut state.connections[idx];
+        storage.events.clear();
+        storage.reassembly.clear();
+        storage.state = ConnectionState::Connecting;
+        storage.in_flight = 0;
+        storage.tx_waiting = false;
+        storage.tx_priority = 1;
+        storage.remote_version = RemoteInfo::Unknown;
+        storage.remote_features = RemoteInfo::Unknown;
+        storage.event_timing = None;
+        storage.activity = ActivityTimer::new();
+        // Default ATT MTU is 23
+        storage.att_mtu = 23;
+        storage.handle.replace(handle);
+        storage.peer_addr_kind.replace(peer_addr_kind);
+        storage.peer_identity.replace(Identity {
+            bd_addr: peer_addr,
+            #[cfg(feature = "security")]
+            irk: None,
+        });
+        storage.role.replace(role);
 
-                match role {
-                    LeConnRole::Central => {
-                        state.central_waker.wake();
-                    }
-                    LeConnRole::Peripheral => {
-                        state.peripheral_waker.wake();
-                    }
-                }
-                return Ok(());
+        match role {
+            LeConnRole::Central => {
+                state.central_waker.wake();
+            }
+            LeConnRole::Peripheral => {
+                state.peripheral_waker.wake();
             }
         }
-        warn!("[link][connect] no available slot found for handle {:?}", handle);
-        Err(Error::NotFound)
+        Ok(())
     }
 
     pub(crate) fn poll_accept(
@@ -405,10 +596,43 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
 
     pub(crate) fn set_link_credits(&self, credits: usize) {
         let mut state = self.state.borrow_mut();
-        state.default_link_credits = credits;
+        state.link_credits = credits;
         for storage in state.connections.iter_mut() {
-            storage.link_credits = credits;
+            let in_flight = storage.in_flight;
+            storage.complete(in_flight);
         }
+        state.wake_senders();
+    }
+
+    /// Count of ACL packets the link will have completed once the packets now in flight are completed.
+    ///
+    /// The count wraps around; compare it with [`poll_tx_completed`](Self::poll_tx_completed).
+    pub(crate) fn tx_target(&self, index: u8) -> u32 {
+        self.with_mut(|state| {
+            let storage = &state.connections[index as usize];
+            storage.tx_completed.wrapping_add(storage.in_flight as u32)
+        })
+    }
+
+    /// Poll until the link has completed `target` ACL packets, see [`tx_target`](Self::tx_target).
+    pub(crate) fn poll_tx_completed(&self, index: u8, target: u32, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.with_mut(|state| {
+            let storage = &mut state.connections[index as usize];
+            if storage.state != ConnectionState::Connected {
+                return Poll::Ready(Err(Error::Disconnected));
+            }
+            if storage.tx_completed.wrapping_sub(target) as i32 >= 0 {
+                return Poll::Ready(Ok(()));
+            }
+            storage.tx_completed_waker.register(cx.waker());
+            Poll::Pending
+        })
+    }
+
+    pub(crate) fn set_tx_priority(&self, index: u8, priority: u8) {
+        self.with_mut(|state| {
+            state.connections[index as usize].tx_priority = priority.max(1);
+        })
     }
 
     pub(crate) fn set_default_att_mtu(&self, att_mtu: u16) {
@@ -418,11 +642,14 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
 
     pub(crate) fn confirm_sent(&self, handle: ConnHandle, packets: usize) -> Result<(), Error> {
         let mut state = self.state.borrow_mut();
+        let state = &mut *state;
         for storage in state.connections.iter_mut() {
             match storage.state {
                 ConnectionState::Connected if handle == storage.handle.unwrap() => {
-                    storage.link_credits += packets;
-                    storage.link_credit_waker.wake();
+                    let packets = packets.min(storage.in_flight);
+                    storage.complete(packets);
+                    state.link_credits += packets;
+                    state.wake_senders();
                     return Ok(());
                 }
                 _ => {}
@@ -431,35 +658,75 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         Err(Error::NotFound)
     }
 
+    /// Request ACL buffers for sending on a link.
+    ///
+    /// Buffers are shared between all links and handed out round-robin, weighted by each link's
+    /// TX priority, so a busy link cannot starve the others.
+    ///
+    /// The context, if any, is woken when buffers may have become available. A pending request only holds
+    /// the link's place in line if `reserve` is set, in which case the caller must give it up with
+    /// [`Self::cancel_request_to_send`] if it stops polling; otherwise an abandoned request would hold back
+    /// the other links.
     pub(crate) fn poll_request_to_send(
         &self,
         handle: ConnHandle,
         packets: usize,
         cx: Option<&mut Context<'_>>,
+        reserve: bool,
     ) -> Poll<Result<PacketGrant<'_, 'd, P::Packet>, Error>> {
         let mut state = self.state.borrow_mut();
-        for storage in state.connections.iter_mut() {
-            match storage.state {
-                ConnectionState::Connected if storage.handle.unwrap() == handle => {
-                    if packets <= storage.link_credits {
-                        storage.link_credits -= packets;
+        let Some(index) = state
+            .connections
+            .iter()
+            .position(|s| s.state == ConnectionState::Connected && s.handle == Some(handle))
+        else {
+            warn!("[link][pool_request_to_send] connection {:?} not found", handle);
+            return Poll::Ready(Err(Error::NotFound));
+        };
 
-                        return Poll::Ready(Ok(PacketGrant::new(&self.state, handle, packets)));
-                    } else {
-                        if let Some(cx) = cx {
-                            storage.link_credit_waker.register(cx.waker());
-                        }
-                        #[cfg(feature = "connection-metrics")]
-                        storage.metrics.blocked_send();
+        if packets <= state.link_credits && state.next_sender(index) == index {
+            state.link_credits -= packets;
+            state.advance_turn(index);
+            let storage = &mut state.connections[index];
+            storage.in_flight += packets;
+            storage.tx_waiting = false;
+            if state.link_credits > 0 {
+                state.wake_senders();
+            }
+            return Poll::Ready(Ok(PacketGrant::new(&self.state, handle, packets)));
+        }
 
-                        return Poll::Pending;
-                    }
-                }
-                _ => {}
+        let storage = &mut state.connections[index];
+        if let Some(cx) = cx {
+            storage.link_credit_waker.register(cx.waker());
+            storage.tx_waiting |= reserve;
+        }
+        #[cfg(feature = "connection-metrics")]
+        storage.metrics.blocked_send();
+        Poll::Pending
+    }
+
+    /// Wait for ACL buffers for sending on a link, see [`Self::poll_request_to_send`].
+    pub(crate) async fn request_to_send(
+        &self,
+        handle: ConnHandle,
+        packets: usize,
+    ) -> Result<PacketGrant<'_, 'd, P::Packet>, Error> {
+        // Give up our turn if the request is cancelled, so other links are not held back.
+        let guard = OnDrop::new(|| self.cancel_request_to_send(handle));
+        let grant = poll_fn(|cx| self.poll_request_to_send(handle, packets, Some(cx), true)).await;
+        guard.defuse();
+        grant
+    }
+
+    fn cancel_request_to_send(&self, handle: ConnHandle) {
+        let mut state = self.state.borrow_mut();
+        for storage in state.connections.iter_mut() {
+            if storage.handle == Some(handle) {
+                storage.tx_waiting = false;
             }
         }
-        warn!("[link][pool_request_to_send] connection {:?} not found", handle);
-        Poll::Ready(Err(Error::NotFound))
+        state.wake_senders();
     }
 
     pub(crate) fn get_att_mtu(&self, index: u8) -> u16 {
@@ -576,6 +843,20 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         }
     }
 
+    pub(crate) fn bonded_identity(&self, index: u8) -> Option<Identity> {
+        #[cfg(feature = "security")]
+        {
+            let state = self.state.borrow();
+            let storage = &state.connections[index as usize];
+            match (storage.state, storage.peer_identity) {
+                (ConnectionState::Connected, Some(identity)) => self.security_manager.bonded_identity(&identity),
+                _ => None,
+            }
+        }
+        #[cfg(not(feature = "security"))]
+        None
+    }
+
     pub(crate) fn get_bondable(&self, index: u8) -> Result<bool, Error> {
         let state = self.state.borrow();
         match state.connections[index as usize].state {
@@ -607,6 +888,47 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         Err(Error::NotSupported)
     }
 
+    pub(crate) fn get_bond_approval_required(&self, index: u8) -> Result<bool, Error> {
+        let state = self.state.borrow();
+        match state.connections[index as usize].state {
+            ConnectionState::Connected => {
+                #[cfg(feature = "security")]
+                {
+                    Ok(state.connections[index as usize].bond_approval)
+                }
+                #[cfg(not(feature = "security"))]
+                Ok(false)
+            }
+            _ => Err(Error::Disconnected),
+        }
+    }
+
+    pub(crate) fn set_bond_approval_required(&self, index: u8, required: bool) -> Result<(), Error> {
+        #[cfg(feature = "security")]
+        {
+            let mut state = self.state.borrow_mut();
+            match state.connections[index as usize].state {
+                ConnectionState::Connected => {
+                    state.connections[index as usize].bond_approval = required;
+                    Ok(())
+                }
+                _ => Err(Error::Disconnected),
+            }
+        }
+        #[cfg(not(feature = "security"))]
+        Err(Error::NotSupported)
+    }
+
+    #[cfg(feature = "security")]
+    pub(crate) fn approve_bond(&self, index: u8) -> Result<BondInformation, Error> {
+        let state = self.state.borrow();
+        let storage = &state.connections[index as usize];
+        match (storage.state, storage.peer_identity) {
+            (ConnectionState::Connected, Some(identity)) => self.security_manager.approve_bond(&identity),
+            _ => Err(Error::Disconnected),
+        }
+    }
+
     pub(crate) fn handle_security_channel(
         &self,
         handle: ConnHandle,
@@ -778,8 +1100,14 @@ pub struct ConnectionStorage<P> {
     pub peer_addr_kind: Option<AddrKind>,
     pub peer_identity: Option<Identity>,
     pub att_mtu: u16,
-    pub link_credits: usize,
+    /// ACL packets granted to this link and not yet completed by the controller.
+    pub in_flight: usize,
+    /// ACL packets granted to this link and no longer in flight, wrapping around.
+    pub tx_completed: u32,
+    pub tx_completed_waker: WakerRegistration,
     pub link_credit_waker: WakerRegistration,
+    pub tx_waiting: bool,
+    pub tx_priority: u8,
     pub refcount: u8,
     #[cfg(feature = "connection-metrics")]
     pub metrics: Metrics,
@@ -787,10 +1115,28 @@ pub struct ConnectionStorage<P> {
     pub security_level: SecurityLevel,
     #[cfg(feature = "security")]
     pub bondable: bool,
+    #[cfg(feature = "security")]
+    pub bond_approval: bool,
     pub events: EventChannel,
     pub reassembly: PacketReassembly<P>,
     #[cfg(feature = "gatt")]
     pub gatt: GattChannel<P>,
+    pub remote_version: RemoteInfo<RemoteVersion>,
+    pub remote_features: RemoteInfo<LeFeatureMask>,
+    pub remote_info_waker: WakerRegistration,
+    pub event_timing: Option<ConnectionEventTiming>,
+    pub activity: ActivityTimer,
+}
+
+/// Information read from the peer controller, cached for the lifetime of the link.
+#[derive(Debug, Clone, Copy)]
+pub enum RemoteInfo<T> {
+    /// Not read yet, or the last request failed and was reported.
+    Unknown,
+    /// The last request failed.
+    Failed(bt_hci::param::Error),
+    /// The value reported by the peer.
+    Known(T),
 }
 
 /// Connection metrics
@@ -856,6 +1202,13 @@ impl defmt::Format for Metrics {
 }
 
 impl<P> ConnectionStorage<P> {
+    /// Take `packets` out of flight, waking anyone waiting for them to complete.
+    fn complete(&mut self, packets: usize) {
+        self.in_flight -= packets;
+        self.tx_completed = self.tx_completed.wrapping_add(packets as u32);
+        self.tx_completed_waker.wake();
+    }
+
     pub(crate) const fn new() -> ConnectionStorage<P> {
         ConnectionStorage {
             state: ConnectionState::Disconnected,
@@ -864,8 +1217,12 @@ impl<P> ConnectionStorage<P> {
             peer_addr_kind: None,
             peer_identity: None,
             att_mtu: 23,
-            link_credits: 0,
+            in_flight: 0,
+            tx_completed: 0,
+            tx_completed_waker: WakerRegistration::new(),
             link_credit_waker: WakerRegistration::new(),
+            tx_waiting: false,
+            tx_priority: 1,
             refcount: 0,
             #[cfg(feature = "connection-metrics")]
             metrics: Metrics::new(),
@@ -877,6 +1234,13 @@ impl<P> ConnectionStorage<P> {
             reassembly: PacketReassembly::new(),
             #[cfg(feature = "security")]
             bondable: false,
+            #[cfg(feature = "security")]
+            bond_approval: false,
+            remote_version: RemoteInfo::Unknown,
+            remote_features: RemoteInfo::Unknown,
+            remote_info_waker: WakerRegistration::new(),
+            event_timing: None,
+            activity: ActivityTimer::new(),
         }
     }
 }
@@ -904,7 +1268,7 @@ impl<P> defmt::Format for ConnectionStorage<P> {
             "state = {}, conn = {}, flow = {}",
             self.state,
             self.handle,
-            self.link_credits,
+            self.in_flight,
         );
 
         defmt::write!(
@@ -964,11 +1328,14 @@ impl<P> Drop for PacketGrant<'_, '_, P> {
     fn drop(&mut self) {
         if self.packets > 0 {
             let mut state = self.state.borrow_mut();
+            let state = &mut *state;
             for storage in state.connections.iter_mut() {
                 match storage.state {
                     ConnectionState::Connected if self.handle == storage.handle.unwrap() => {
-                        storage.link_credits += self.packets;
-                        storage.link_credit_waker.wake();
+                        // Packets granted but not sent will not be completed by the controller.
+                        storage.complete(self.packets.min(storage.in_flight));
+                        state.link_credits += self.packets;
+                        state.wake_senders();
                         return;
                     }
                     _ => {}
@@ -988,6 +1355,7 @@ pub(crate) mod tests {
     use std::boxed::Box;
 
     use embassy_futures::block_on;
+    use embassy_time::Duration;
 
     use crate::prelude::*;
 
@@ -996,7 +1364,7 @@ pub(crate) mod tests {
 
     pub fn setup() -> &'static ConnectionManager<'static, DefaultPacketPool> {
         let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 3]));
-        let mgr = ConnectionManager::new(&mut storage[..], 23);
+        let mgr = ConnectionManager::new(Table::from(&mut storage[..]), 23);
         Box::leak(Box::new(mgr))
     }
 
@@ -1093,6 +1461,196 @@ pub(crate) mod tests {
         assert!(mgr.poll_disconnecting(None).is_pending());
     }
 
+    #[test]
+    fn controller_reset_disconnects_all() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(2),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_2),
+            LeConnRole::Peripheral
+        ));
+
+        let Poll::Ready(central) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        let mut handles = std::vec::Vec::new();
+        mgr.disconnected_all(Status::HARDWARE_FAILURE, |h| handles.push(h));
+
+        // Both the accepted and the pending link are dropped.
+        assert_eq!(handles.len(), 2);
+        assert!(!mgr.is_handle_connected(ConnHandle::new(3)));
+        assert!(!mgr.is_handle_connected(ConnHandle::new(2)));
+        assert!(mgr.poll_accept(LeConnRole::Peripheral, &[], None).is_pending());
+        assert!(mgr.poll_disconnecting(None).is_pending());
+    }
+
+    #[test]
+    fn link_credits_shared_round_robin() {
+        let mgr = setup();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        unwrap!(mgr.connect(
+            ConnHandle::new(2),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_2),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(a) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let Poll::Ready(b) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        mgr.set_link_credits(2);
+
+        // With nobody else waiting, a single link may use all credits.
+        for _ in 0..2 {
+            let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(a.handle(), 1, Some(&mut cx), true) else {
+                panic!("expected credits to be granted");
+            };
+            grant.confirm(1);
+        }
+
+        // Both links are now waiting for credits.
+        assert!(mgr
+            .poll_request_to_send(a.handle(), 1, Some(&mut cx), true)
+            .is_pending());
+        assert!(mgr
+            .poll_request_to_send(b.handle(), 1, Some(&mut cx), true)
+            .is_pending());
+
+        // A returned credit goes to the other link first, even though the busy link asked first.
+        unwrap!(mgr.confirm_sent(a.handle(), 1));
+        assert!(mgr
+            .poll_request_to_send(a.handle(), 1, Some(&mut cx), true)
+            .is_pending());
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(b.handle(), 1, Some(&mut cx), true) else {
+            panic!("expected credits to be granted");
+        };
+        grant.confirm(1);
+
+        // Credits in flight on a link are reclaimed when it disconnects.
+        unwrap!(mgr.disconnected(b.handle(), Status::UNSPECIFIED));
+        assert!(matches!(
+            mgr.poll_request_to_send(a.handle(), 1, Some(&mut cx), true),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn abandoned_request_to_send() {
+        let mgr = setup();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        unwrap!(mgr.connect(
+            ConnHandle::new(2),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_2),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(a) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let Poll::Ready(b) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        mgr.set_link_credits(1);
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(a.handle(), 1, Some(&mut cx), true) else {
+            panic!("expected credits to be granted");
+        };
+        grant.confirm(1);
+
+        // It is now the other link's turn, but it stops polling for credits.
+        assert!(mgr
+            .poll_request_to_send(b.handle(), 1, Some(&mut cx), false)
+            .is_pending());
+        {
+            let mut request = core::pin::pin!(mgr.request_to_send(b.handle(), 1));
+            assert!(request.as_mut().poll(&mut cx).is_pending());
+        }
+
+        // Neither request holds back the busy link.
+        unwrap!(mgr.confirm_sent(a.handle(), 1));
+        assert!(matches!(
+            mgr.poll_request_to_send(a.handle(), 1, Some(&mut cx), true),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn tx_completion() {
+        let mgr = setup();
+        let mut cx = Context::from_waker(core::task::Waker::noop());
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        mgr.set_link_credits(4);
+        // The only connection takes the first slot.
+        let index = 0;
+
+        // Nothing in flight completes right away.
+        let idle = mgr.tx_target(index);
+        assert_eq!(mgr.poll_tx_completed(index, idle, &mut cx), Poll::Ready(Ok(())));
+
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(conn.handle(), 2, Some(&mut cx), true) else {
+            panic!("expected credits to be granted");
+        };
+        grant.confirm(2);
+        let target = mgr.tx_target(index);
+        assert_eq!(target, idle.wrapping_add(2));
+        assert!(mgr.poll_tx_completed(index, target, &mut cx).is_pending());
+
+        unwrap!(mgr.confirm_sent(conn.handle(), 1));
+        assert!(mgr.poll_tx_completed(index, target, &mut cx).is_pending());
+        unwrap!(mgr.confirm_sent(conn.handle(), 1));
+        assert_eq!(mgr.poll_tx_completed(index, target, &mut cx), Poll::Ready(Ok(())));
+
+        // Granted packets that are not sent do not hold back completion.
+        let Poll::Ready(Ok(grant)) = mgr.poll_request_to_send(conn.handle(), 1, Some(&mut cx), true) else {
+            panic!("expected credits to be granted");
+        };
+        let target = mgr.tx_target(index);
+        drop(grant);
+        assert_eq!(mgr.poll_tx_completed(index, target, &mut cx), Poll::Ready(Ok(())));
+
+        unwrap!(mgr.disconnected(conn.handle(), Status::UNSPECIFIED));
+        assert_eq!(
+            mgr.poll_tx_completed(index, target.wrapping_add(1), &mut cx),
+            Poll::Ready(Err(Error::Disconnected))
+        );
+    }
+
     #[test]
     fn controller_disconnects_after_host() {
         let mgr = setup();
@@ -1285,4 +1843,123 @@ pub(crate) mod tests {
 
         assert!(!mgr.is_handle_connected(ConnHandle::new(3)));
     }
+
+    #[test]
+    fn remote_version_cached_until_disconnect() {
+        use bt_hci::param::CoreSpecificationVersion;
+        use bt_hci::FromHciBytes;
+
+        let mgr = setup();
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(_handle) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert!(mgr.remote_version(0).is_none());
+
+        // A failed request is reported once and can then be retried
+        unwrap!(mgr.set_remote_version(
+            ConnHandle::new(3),
+            Err(bt_hci::param::Error::UNSUPPORTED_REMOTE_FEATURE)
+        ));
+        let result = block_on(mgr.wait_remote_info(0, |storage| &mut storage.remote_version));
+        assert!(matches!(result, Err(Error::Hci(_))));
+        assert!(matches!(
+            mgr.state.borrow().connections[0].remote_version,
+            RemoteInfo::Unknown
+        ));
+
+        let version = RemoteVersion {
+            version: unwrap!(CoreSpecificationVersion::from_hci_bytes_complete(&[0x0d])),
+            company_id: 0x0059,
+            subversion: 0x1234,
+        };
+        unwrap!(mgr.set_remote_version(ConnHandle::new(3), Ok(version)));
+        let result = block_on(mgr.wait_remote_info(0, |storage| &mut storage.remote_version));
+        assert_eq!(unwrap!(result), version);
+        assert_eq!(mgr.remote_version(0), Some(version));
+
+        unwrap!(mgr.disconnected(ConnHandle::new(3), Status::UNSPECIFIED));
+        assert!(mgr.remote_version(0).is_none());
+    }
+
+    #[test]
+    fn remote_features_cached() {
+        let mgr = setup();
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        let Poll::Ready(_handle) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert!(mgr.remote_features(0).is_none());
+
+        let features = LeFeatureMask::new().set_le_2m_phy(true);
+        unwrap!(mgr.set_remote_features(ConnHandle::new(3), Ok(features)));
+        let result = block_on(mgr.wait_remote_info(0, |storage| &mut storage.remote_features));
+        assert!(unwrap!(result).supports_le_2m_phy());
+        assert!(unwrap!(mgr.remote_features(0)).supports_le_2m_phy());
+        assert!(!unwrap!(mgr.remote_features(0)).supports_le_coded_phy());
+    }
+
+    #[test]
+    fn connection_event_timing() {
+        let mgr = setup();
+        unwrap!(mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert_eq!(conn.event_timing(), None);
+
+        let anchor = Instant::from_secs(1);
+        let interval = Duration::from_millis(30);
+        let timing = ConnectionEventTiming {
+            event_counter: 0xfffe,
+            anchor,
+            interval,
+            reported: true,
+        };
+        unwrap!(mgr.update_event_timing(ConnHandle::new(0), |_| Ok(timing)));
+        assert_eq!(conn.event_timing(), Some(timing));
+
+        // The next event at or after a point in time, with the counter wrapping around.
+        assert_eq!(timing.next_event(anchor - Duration::from_millis(5)), (0xfffe, anchor));
+        assert_eq!(timing.next_event(anchor), (0xfffe, anchor));
+        assert_eq!(
+            timing.next_event(anchor + Duration::from_millis(1)),
+            (0xffff, anchor + interval)
+        );
+        assert_eq!(
+            timing.next_event(anchor + Duration::from_millis(61)),
+            (1, anchor + Duration::from_millis(90))
+        );
+
+        // A parameter update re-bases the timing on the next event, with the new interval.
+        assert_eq!(
+            timing.rebase(anchor + Duration::from_millis(45), Duration::from_millis(50)),
+            ConnectionEventTiming {
+                event_counter: 0,
+                anchor: anchor + Duration::from_millis(60),
+                interval: Duration::from_millis(50),
+                reported: false,
+            }
+        );
+
+        // Only connected links have a timing.
+        assert!(mgr
+            .update_event_timing(ConnHandle::new(1), |timing| timing.ok_or(Error::NotFound))
+            .is_err());
+    }
 }
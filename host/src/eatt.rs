@@ -0,0 +1,206 @@
+//! Enhanced ATT (EATT) bearers.
+//!
+//! An EATT bearer runs ATT over an L2CAP channel in enhanced credit based flow control mode, alongside the
+//! fixed ATT channel of the connection. Each bearer carries its own sequence of requests and responses, so
+//! a client can have one request outstanding per bearer, and a slow request on one bearer does not hold back
+//! requests or notifications on the others.
+//!
+//! The bearers are opened on the [`EATT_PSM`] once the link is encrypted. Announcing support through the
+//! Server Supported Features and Client Supported Features characteristics is left to the application.
+use bt_hci::controller::Controller;
+
+use crate::att::{self, Att, AttClient, AttErrorCode, AttReq, AttRsp, AttServer, AttUns};
+use crate::connection::Connection;
+use crate::gatt::{GattConnection, GattData, GattEvent, Reply};
+use crate::l2cap::{L2capChannel, L2capChannelConfig, MAX_ENHANCED_CHANNELS};
+use crate::pdu::{Pdu, Sdu};
+use crate::{BleHostError, Error, Packet, PacketPool, Stack};
+
+/// PSM of the Enhanced ATT protocol.
+pub const EATT_PSM: u16 = 0x0027;
+
+/// Smallest MTU allowed for an EATT bearer.
+pub const EATT_MIN_MTU: u16 = 64;
+
+/// An ATT bearer on an enhanced credit based L2CAP channel.
+pub struct EattBearer<'d, P: PacketPool> {
+    channel: L2capChannel<'d, P>,
+}
+
+impl<'d, P: PacketPool> EattBearer<'d, P> {
+    /// Await a request from the peer to open EATT bearers, accepting up to `max` of them.
+    pub async fn accept<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        max: usize,
+        config: &L2capChannelConfig,
+    ) -> Result<heapless::Vec<Self, MAX_ENHANCED_CHANNELS>, BleHostError<T::Error>> {
+        check_config(config)?;
+        let channels = L2capChannel::accept_enhanced(stack, connection, &[EATT_PSM], max, config).await?;
+        Ok(channels.into_iter().map(|channel| Self { channel }).collect())
+    }
+
+    /// Open up to `count` EATT bearers with a single request.
+    ///
+    /// Returns the bearers accepted by the peer, which may be fewer than `count`.
+    pub async fn create<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        count: usize,
+        config: &L2capChannelConfig,
+    ) -> Result<heapless::Vec<Self, MAX_ENHANCED_CHANNELS>, BleHostError<T::Error>> {
+        check_config(config)?;
+        let channels = L2capChannel::create_enhanced(stack, connection, EATT_PSM, count, config).await?;
+        Ok(channels.into_iter().map(|channel| Self { channel }).collect())
+    }
+
+    /// The ATT MTU of this bearer, which is the MTU of the underlying channel.
+    pub fn mtu(&self) -> u16 {
+        self.channel.mtu()
+    }
+
+    /// The underlying L2CAP channel.
+    pub fn channel(&mut self) -> &mut L2capChannel<'d, P> {
+        &mut self.channel
+    }
+
+    /// Receive the next ATT PDU on this bearer.
+    pub async fn receive<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        self.channel.receive_sdu(stack).await
+    }
+
+    /// Receive the next request or command on this bearer and let the attribute server of the GATT connection
+    /// handle it.
+    ///
+    /// The request is accepted without being reported to the application, see
+    /// [`process_with`](Self::process_with) to handle it as a [`GattEvent`].
+    pub async fn process<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        connection: &GattConnection<'_, '_, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        self.process_with(stack, connection, GattEvent::accept).await
+    }
+
+    /// Receive the next request or command on this bearer and hand it to `f` as a [`GattEvent`].
+    ///
+    /// The event is accepted or rejected by `f` like one returned by [`GattConnection::next`], and the reply
+    /// it returns is sent on this bearer. Server PDUs received on the bearer are ignored.
+    pub async fn process_with<'stack, 'server, T, F>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        connection: &GattConnection<'stack, 'server, P>,
+        f: F,
+    ) -> Result<(), BleHostError<T::Error>>
+    where
+        T: Controller,
+        F: FnOnce(GattEvent<'stack, 'server, P>) -> Result<Reply<'stack, P>, Error>,
+    {
+        let sdu = self.channel.receive_sdu(stack).await?;
+        match Att::decode(sdu.as_ref()) {
+            Ok(Att::Client(_)) => {}
+            Ok(Att::Server(_)) => {
+                warn!("[eatt] ignoring server PDU received by the attribute server");
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("[eatt] error decoding ATT PDU: {:?}", e);
+                let request = sdu.as_ref().first().copied().unwrap_or(0);
+                let rsp = Att::Server(AttServer::Response(AttRsp::Error {
+                    request,
+                    handle: 0,
+                    code: AttErrorCode::INVALID_PDU,
+                }));
+                let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+                let len = encode(&rsp, tx.as_mut())?.min(self.mtu() as usize);
+                return self.channel.send(stack, &tx.as_ref()[..len]).await;
+            }
+        }
+        let len = sdu.len();
+        let data = GattData::on_bearer(Pdu::new(sdu.into_inner(), len), connection.raw().clone(), self.mtu());
+        if let Some(pdu) = f(GattEvent::new(data, connection.server))?.into_pdu() {
+            // The reply is framed for the fixed ATT channel, the bearer only carries the ATT PDU.
+            self.channel.send(stack, &pdu.as_ref()[4..]).await?;
+        }
+        Ok(())
+    }
+
+    /// Process requests on this bearer until the channel is closed.
+    pub async fn run<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        connection: &GattConnection<'_, '_, P>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        loop {
+            self.process(stack, connection).await?;
+        }
+    }
+
+    /// Notify the client of a new value of the attribute with the given value handle on this bearer.
+    ///
+    /// The value is stored in the attribute table, and only sent if the client has enabled notifications for
+    /// the attribute. Values longer than the MTU of the bearer are truncated.
+    pub async fn notify<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        connection: &GattConnection<'_, '_, P>,
+        handle: u16,
+        value: &[u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        connection.server.set(handle, value)?;
+        if !connection.notifications_enabled(handle) {
+            return Ok(());
+        }
+        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let uns = Att::Server(AttServer::Unsolicited(AttUns::Notify { handle, data: value }));
+        let len = encode(&uns, tx.as_mut())?.min(self.mtu() as usize);
+        self.channel.send(stack, &tx.as_ref()[..len]).await
+    }
+
+    /// Send a request to the server and wait for its response.
+    ///
+    /// The returned SDU holds the response, which can be decoded with [`Att::decode`]. Notifications received
+    /// on this bearer while waiting are discarded, and indications are confirmed and discarded.
+    pub async fn request<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        req: AttReq<'_>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let len = encode(&Att::Client(AttClient::Request(req)), tx.as_mut())?;
+        self.channel.send(stack, &tx.as_ref()[..len]).await?;
+        drop(tx);
+        loop {
+            let sdu = self.channel.receive_sdu(stack).await?;
+            match Att::decode(sdu.as_ref()) {
+                Ok(Att::Server(AttServer::Response(_))) => return Ok(sdu),
+                Ok(Att::Server(AttServer::Unsolicited(AttUns::Indicate { handle, .. }))) => {
+                    trace!(
+                        "[eatt] confirming indication of handle {} while waiting for response",
+                        handle
+                    );
+                    // The server does not send anything else on this bearer until it is confirmed.
+                    self.channel.send(stack, &[att::ATT_HANDLE_VALUE_CMF]).await?;
+                }
+                Ok(att) => trace!("[eatt] discarding PDU while waiting for response: {:?}", att),
+                Err(e) => warn!("[eatt] error decoding ATT PDU: {:?}", e),
+            }
+        }
+    }
+}
+
+fn check_config(config: &L2capChannelConfig) -> Result<(), Error> {
+    match config.mtu {
+        Some(mtu) if mtu < EATT_MIN_MTU => Err(Error::InvalidValue),
+        _ => Ok(()),
+    }
+}
+
+fn encode(att: &Att<'_>, dest: &mut [u8]) -> Result<usize, Error> {
+    let len = att.size();
+    att.encode(dest.get_mut(..len).ok_or(Error::InsufficientSpace)?)?;
+    Ok(len)
+}
@@ -0,0 +1,161 @@
+//! Entry points for fuzzing the protocol parsers.
+//!
+//! Each function feeds arbitrary bytes into one of the parsers used on data received from a peer
+//! or from the controller, and discards the result. None of them may panic, whatever the input.
+//!
+//! These are intended to be driven from a host (`std`) fuzzing harness, see the `fuzz` directory
+//! of this crate for `cargo fuzz` targets.
+
+use bt_hci::event::le::{LeAdvertisingReport, LeExtendedAdvertisingReport};
+use bt_hci::FromHciBytes;
+
+use crate::advertise::AdStructure;
+use crate::att::Att;
+use crate::types::l2cap::{
+    CommandRejectRes, ConnParamUpdateReq, ConnParamUpdateRes, DisconnectionReq, DisconnectionRes, L2capSignalCode,
+    L2capSignalHeader, LeCreditConnReq, LeCreditConnRes, LeCreditFlowInd,
+};
+
+/// Decode an ATT PDU.
+pub fn att(data: &[u8]) {
+    let _ = Att::decode(data);
+}
+
+/// Handle a Security Manager PDU received on a new link, once in each role.
+///
+/// The PDU goes through the same path as one received from a peer, so the pairing state machine is
+/// exercised as well as the parser.
+#[cfg(all(feature = "security", feature = "default-packet-pool"))]
+pub fn smp(data: &[u8]) {
+    use core::task::Poll;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+
+    use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+    use crate::host::DummyHandler;
+    use crate::packet_pool::DefaultPacketPool;
+    use crate::pdu::Pdu;
+    use crate::table::Table;
+    use crate::{Address, PacketPool};
+
+    for role in [LeConnRole::Peripheral, LeConnRole::Central] {
+        let mut storage = [const { ConnectionStorage::new() }; 1];
+        let connections: ConnectionManager<'_, DefaultPacketPool> =
+            ConnectionManager::new(Table::from(&mut storage[..]), 23);
+        connections
+            .security_manager
+            .set_local_address(Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]));
+        let handle = ConnHandle::new(0);
+        if connections
+            .connect(
+                handle,
+                AddrKind::RANDOM,
+                BdAddr::new([0x11, 0x22, 0x33, 0x44, 0x55, 0xc6]),
+                role,
+            )
+            .is_err()
+        {
+            return;
+        }
+        let Poll::Ready(_connection) = connections.poll_accept(role, &[], None) else {
+            return;
+        };
+        let Some(mut packet) = DefaultPacketPool::allocate() else {
+            return;
+        };
+        let len = data.len().min(packet.as_ref().len());
+        packet.as_mut()[..len].copy_from_slice(&data[..len]);
+        let _ = connections.handle_security_channel(handle, Pdu::new(packet, len), &DummyHandler);
+    }
+}
+
+/// Decode an L2CAP LE signaling PDU.
+pub fn l2cap_signal(data: &[u8]) {
+    let Ok((header, data)) = L2capSignalHeader::from_hci_bytes(data) else {
+        return;
+    };
+    match header.code {
+        L2capSignalCode::LeCreditConnReq => {
+            let _ = LeCreditConnReq::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::LeCreditConnRes => {
+            let _ = LeCreditConnRes::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::LeCreditFlowInd => {
+            let _ = LeCreditFlowInd::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::CommandRejectRes => {
+            let _ = CommandRejectRes::from_hci_bytes(data);
+        }
+        L2capSignalCode::DisconnectionReq => {
+            let _ = DisconnectionReq::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::DisconnectionRes => {
+            let _ = DisconnectionRes::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::ConnParamUpdateReq => {
+            let _ = ConnParamUpdateReq::from_hci_bytes_complete(data);
+        }
+        L2capSignalCode::ConnParamUpdateRes => {
+            let _ = ConnParamUpdateRes::from_hci_bytes_complete(data);
+        }
+        _ => {}
+    }
+}
+
+/// Decode advertising data structures.
+pub fn adv_data(data: &[u8]) {
+    for item in AdStructure::decode(data) {
+        let _ = item;
+    }
+}
+
+/// Decode the parameters of an LE Advertising Report event, including the advertising data of each report.
+pub fn adv_reports(data: &[u8]) {
+    let Ok(event) = LeAdvertisingReport::from_hci_bytes_complete(data) else {
+        return;
+    };
+    let mut reports = event.reports.iter();
+    while let Some(Ok(report)) = reports.next() {
+        adv_data(report.data);
+    }
+}
+
+/// Decode the parameters of an LE Extended Advertising Report event, including the advertising data of each report.
+pub fn ext_adv_reports(data: &[u8]) {
+    let Ok(event) = LeExtendedAdvertisingReport::from_hci_bytes_complete(data) else {
+        return;
+    };
+    let mut reports = event.reports.iter();
+    while let Some(Ok(report)) = reports.next() {
+        adv_data(report.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_inputs() {
+        let inputs: &[&[u8]] = &[
+            &[],
+            &[0x00],
+            &[0xff; 3],
+            &[0x01, 0x02],
+            &[0x20, 0x01, 0x04, 0x00, 0x00],
+            &[0x14, 0x01, 0x0a, 0x00, 0x80],
+            &[0x05, 0x09, b'a'],
+            &[0x01, 0x00, 0x00, 0x00],
+        ];
+        for input in inputs {
+            att(input);
+            #[cfg(all(feature = "security", feature = "default-packet-pool"))]
+            smp(input);
+            l2cap_signal(input);
+            adv_data(input);
+            adv_reports(input);
+            ext_adv_reports(input);
+        }
+    }
+}
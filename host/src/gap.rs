@@ -9,7 +9,6 @@
 
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use heapless::String;
-use static_cell::StaticCell;
 
 use crate::prelude::*;
 
@@ -17,15 +16,23 @@ use crate::prelude::*;
 const DEVICE_NAME_MAX_LENGTH: usize = 22;
 
 /// The number of attributes added by the GAP and GATT services
-/// GAP_SERVICE:       1
-/// ├── DEVICE_NAME:   2
-/// └── APPEARANCE:    2
-/// GATT_SERVICE:    + 1
-///                  ---
-///                  = 6
+/// GAP_SERVICE:         1
+/// ├── DEVICE_NAME:     2
+/// └── APPEARANCE:      2
+/// GATT_SERVICE:      + 1
+///                    ---
+///                    = 6
 pub const GAP_SERVICE_ATTRIBUTE_COUNT: usize = 6;
 
+/// The number of attributes added to the GATT service by [`GapConfig::build_with_service_changed`].
+pub const SERVICE_CHANGED_ATTRIBUTE_COUNT: usize = 3;
+
+/// The number of CCCDs added by [`GapConfig::build_with_service_changed`].
+pub const SERVICE_CHANGED_CCCD_COUNT: usize = 1;
+
 /// Configuration for the GAP Service.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum GapConfig<'a> {
     /// Peripheral device configuration.
     Peripheral(PeripheralConfig<'a>),
@@ -34,24 +41,32 @@ pub enum GapConfig<'a> {
 }
 
 /// Configuration for a peripheral device GAP Service.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct PeripheralConfig<'a> {
     /// The name of the peripheral device.
     pub name: &'a str,
     /// The representation of the external appearance of the device.
     ///
     /// Example: `&appearance::sensor::GENERIC_SENSOR.`
+    ///
+    /// A typed [`Appearance`] converts into this with [`BluetoothUuid16::from`].
     pub appearance: &'a BluetoothUuid16,
     // TODO: Add more GAP parameters
     // pub preferred_connection_parameters: Option<ConnectionParameters>,
 }
 
 /// Configuration for a central device GAP Service.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct CentralConfig<'a> {
     /// The name of the central device.
     pub name: &'a str,
     /// The representation of the external appearance of the device.
     ///
     /// Example: `&appearance::sensor::GENERIC_SENSOR`
+    ///
+    /// A typed [`Appearance`] converts into this with [`BluetoothUuid16::from`].
     pub appearance: &'a BluetoothUuid16,
     // TODO: Add more GAP parameters
 }
@@ -73,27 +88,53 @@ impl<'a> GapConfig<'a> {
         table: &mut AttributeTable<'a, M, MAX>,
     ) -> Result<(), &'static str> {
         match self {
-            GapConfig::Peripheral(config) => config.build(table),
-            GapConfig::Central(config) => config.build(table),
+            GapConfig::Peripheral(config) => config.build(table, false),
+            GapConfig::Central(config) => config.build(table, false),
+        }
+    }
+
+    /// Add the GAP config to the attribute table, with a Service Changed characteristic in the GATT service.
+    ///
+    /// This takes [`SERVICE_CHANGED_ATTRIBUTE_COUNT`] more attributes and [`SERVICE_CHANGED_CCCD_COUNT`] more
+    /// CCCDs than [`build`](Self::build). Clients can then be told about services added or removed while the
+    /// server is running.
+    pub fn build_with_service_changed<M: RawMutex, const MAX: usize>(
+        self,
+        table: &mut AttributeTable<'a, M, MAX>,
+    ) -> Result<(), &'static str> {
+        match self {
+            GapConfig::Peripheral(config) => config.build(table, true),
+            GapConfig::Central(config) => config.build(table, true),
         }
     }
 }
 
 impl<'a> PeripheralConfig<'a> {
     /// Add the peripheral GAP config to the attribute table
-    fn build<M: RawMutex, const MAX: usize>(self, table: &mut AttributeTable<'a, M, MAX>) -> Result<(), &'static str> {
-        static PERIPHERAL_NAME: StaticCell<String<DEVICE_NAME_MAX_LENGTH>> = StaticCell::new();
-        let peripheral_name = PERIPHERAL_NAME.init(String::new());
-        peripheral_name
-            .push_str(self.name)
-            .map_err(|_| "Device name is too long. Max length is 22 bytes")?;
+    fn build<M: RawMutex, const MAX: usize>(
+        self,
+        table: &mut AttributeTable<'a, M, MAX>,
+        service_changed: bool,
+    ) -> Result<(), &'static str> {
+        if self.name.len() > DEVICE_NAME_MAX_LENGTH {
+            return Err("Device name is too long. Max length is 22 bytes");
+        }
 
+        // The name is borrowed for the lifetime of the table, so multiple tables (and stacks) can each
+        // hold their own name.
         let mut gap_builder = table.add_service(Service::new(service::GAP));
-        gap_builder.add_characteristic_ro(characteristic::DEVICE_NAME, peripheral_name);
+        gap_builder.add_characteristic_ro_bytes::<String<DEVICE_NAME_MAX_LENGTH>, _>(
+            characteristic::DEVICE_NAME,
+            self.name.as_bytes(),
+        );
         gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
         gap_builder.build();
 
-        table.add_service(Service::new(service::GATT));
+        let mut gatt_builder = table.add_service(Service::new(service::GATT));
+        if service_changed {
+            gatt_builder.add_service_changed();
+        }
+        gatt_builder.build();
 
         Ok(())
     }
@@ -101,20 +142,51 @@ impl<'a> PeripheralConfig<'a> {
 
 impl<'a> CentralConfig<'a> {
     /// Add the peripheral GAP config to the attribute table
-    fn build<M: RawMutex, const MAX: usize>(self, table: &mut AttributeTable<'a, M, MAX>) -> Result<(), &'static str> {
-        static CENTRAL_NAME: StaticCell<String<DEVICE_NAME_MAX_LENGTH>> = StaticCell::new();
-        let central_name = CENTRAL_NAME.init(String::new());
-        central_name
-            .push_str(self.name)
-            .map_err(|_| "Device name is too long. Max length is 22 bytes")?;
+    fn build<M: RawMutex, const MAX: usize>(
+        self,
+        table: &mut AttributeTable<'a, M, MAX>,
+        service_changed: bool,
+    ) -> Result<(), &'static str> {
+        if self.name.len() > DEVICE_NAME_MAX_LENGTH {
+            return Err("Device name is too long. Max length is 22 bytes");
+        }
 
+        // The name is borrowed for the lifetime of the table, so multiple tables (and stacks) can each
+        // hold their own name.
         let mut gap_builder = table.add_service(Service::new(service::GAP));
-        gap_builder.add_characteristic_ro(characteristic::DEVICE_NAME, central_name);
+        gap_builder.add_characteristic_ro_bytes::<String<DEVICE_NAME_MAX_LENGTH>, _>(
+            characteristic::DEVICE_NAME,
+            self.name.as_bytes(),
+        );
         gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
         gap_builder.build();
 
-        table.add_service(Service::new(service::GATT));
+        let mut gatt_builder = table.add_service(Service::new(service::GATT));
+        if service_changed {
+            gatt_builder.add_service_changed();
+        }
+        gatt_builder.build();
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn multiple_gap_services() {
+        let mut first: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut second: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+
+        unwrap!(GapConfig::default("first").build(&mut first));
+        unwrap!(GapConfig::default("second").build(&mut second));
+
+        assert!(GapConfig::default("a name that is far too long for gap")
+            .build(&mut second)
+            .is_err());
+    }
+}
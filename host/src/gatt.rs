@@ -1,21 +1,28 @@
 //! GATT server and client implementation.
-use core::cell::RefCell;
-use core::future::Future;
+use core::cell::{Cell, RefCell};
+use core::future::{poll_fn, Future};
 use core::marker::PhantomData;
+use core::pin::pin;
+use core::task::Poll;
 
 use bt_hci::controller::Controller;
 use bt_hci::param::{ConnHandle, PhyKind, Status};
-use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
-use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
-use embassy_futures::select::{select, Either};
+use bt_hci::uuid::declarations::{CHARACTERISTIC, INCLUDE, PRIMARY_SERVICE};
+use bt_hci::uuid::descriptors::{CHARACTERISTIC_EXTENDED_PROPERTIES, CLIENT_CHARACTERISTIC_CONFIGURATION};
+use embassy_futures::select::{select, select4, Either, Either4};
 use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
 use embassy_sync::channel::{Channel, DynamicReceiver};
-use embassy_sync::pubsub::{self, PubSubChannel, WaitResult};
-use embassy_time::Duration;
-use heapless::Vec;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use heapless::{Deque, Vec};
 
 use crate::att::{self, Att, AttClient, AttCmd, AttErrorCode, AttReq, AttRsp, AttServer, AttUns, ATT_HANDLE_VALUE_NTF};
-use crate::attribute::{AttributeData, Characteristic, CharacteristicProp, Uuid};
+use crate::attribute::{
+    AttributeData, Characteristic, CharacteristicExtendedProps, CharacteristicProp, CharacteristicProps, Uuid,
+    SERVICE_CHANGED,
+};
+use crate::attribute_server::sealed::PreparedWrite;
 use crate::attribute_server::{AttributeServer, DynamicAttributeServer};
 use crate::connection::Connection;
 #[cfg(feature = "security")]
@@ -29,7 +36,7 @@ use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
 use crate::types::l2cap::L2capHeader;
 #[cfg(feature = "security")]
 use crate::BondInformation;
-use crate::{config, BleHostError, Error, PacketPool, Stack};
+use crate::{config, BleHostError, Error, Packet, PacketPool, Stack};
 
 /// A GATT connection event.
 pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
@@ -76,6 +83,11 @@ pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
         /// Max RX time.
         max_rx_time: u16,
     },
+    /// An L2CAP channel on this connection was disconnected because it was idle for too long.
+    L2capChannelIdle {
+        /// PSM of the channel.
+        psm: u16,
+    },
     /// GATT event.
     Gatt {
         /// The event that was returned
@@ -102,12 +114,249 @@ pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
     #[cfg(feature = "security")]
     /// Pairing failed
     PairingFailed(Error),
+    #[cfg(feature = "security")]
+    /// Pairing completed with bonding, and the bond awaits approval
+    BondingRequested {
+        /// Security level of this pairing
+        security_level: SecurityLevel,
+        /// Bond information to store if the bond is approved.
+        bond: BondInformation,
+    },
+    #[cfg(feature = "security")]
+    /// A pairing attempt by the peer was rejected, because an earlier pairing with it failed recently
+    PairingRejected {
+        /// Number of failed pairing attempts by the peer.
+        failed_attempts: u8,
+        /// Time left before the peer may attempt pairing again.
+        retry_after: Duration,
+    },
+}
+
+impl<P: PacketPool> core::fmt::Debug for GattConnectionEvent<'_, '_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Disconnected { reason } => f.debug_struct("Disconnected").field("reason", reason).finish(),
+            Self::PhyUpdated { tx_phy, rx_phy } => f
+                .debug_struct("PhyUpdated")
+                .field("tx_phy", tx_phy)
+                .field("rx_phy", rx_phy)
+                .finish(),
+            Self::ConnectionParamsUpdated {
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout,
+            } => f
+                .debug_struct("ConnectionParamsUpdated")
+                .field("conn_interval", conn_interval)
+                .field("peripheral_latency", peripheral_latency)
+                .field("supervision_timeout", supervision_timeout)
+                .finish(),
+            Self::RequestConnectionParams {
+                min_connection_interval,
+                max_connection_interval,
+                max_latency,
+                supervision_timeout,
+            } => f
+                .debug_struct("RequestConnectionParams")
+                .field("min_connection_interval", min_connection_interval)
+                .field("max_connection_interval", max_connection_interval)
+                .field("max_latency", max_latency)
+                .field("supervision_timeout", supervision_timeout)
+                .finish(),
+            Self::DataLengthUpdated {
+                max_tx_octets,
+                max_tx_time,
+                max_rx_octets,
+                max_rx_time,
+            } => f
+                .debug_struct("DataLengthUpdated")
+                .field("max_tx_octets", max_tx_octets)
+                .field("max_tx_time", max_tx_time)
+                .field("max_rx_octets", max_rx_octets)
+                .field("max_rx_time", max_rx_time)
+                .finish(),
+            Self::L2capChannelIdle { psm } => f.debug_struct("L2capChannelIdle").field("psm", psm).finish(),
+            Self::Gatt { event } => f.debug_struct("Gatt").field("event", event).finish(),
+            #[cfg(feature = "security")]
+            Self::PassKeyDisplay(key) => f.debug_tuple("PassKeyDisplay").field(key).finish(),
+            #[cfg(feature = "security")]
+            Self::PassKeyConfirm(key) => f.debug_tuple("PassKeyConfirm").field(key).finish(),
+            #[cfg(feature = "security")]
+            Self::PassKeyInput => f.write_str("PassKeyInput"),
+            #[cfg(feature = "security")]
+            Self::PairingComplete { security_level, bond } => f
+                .debug_struct("PairingComplete")
+                .field("security_level", security_level)
+                .field("bond", bond)
+                .finish(),
+            #[cfg(feature = "security")]
+            Self::PairingFailed(err) => f.debug_tuple("PairingFailed").field(err).finish(),
+            #[cfg(feature = "security")]
+            Self::BondingRequested { security_level, bond } => f
+                .debug_struct("BondingRequested")
+                .field("security_level", security_level)
+                .field("bond", bond)
+                .finish(),
+            #[cfg(feature = "security")]
+            Self::PairingRejected {
+                failed_attempts,
+                retry_after,
+            } => f
+                .debug_struct("PairingRejected")
+                .field("failed_attempts", failed_attempts)
+                .field("retry_after", retry_after)
+                .finish(),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for GattConnectionEvent<'_, '_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            Self::Disconnected { reason } => defmt::write!(f, "Disconnected {{ reason: {} }}", reason),
+            Self::PhyUpdated { tx_phy, rx_phy } => {
+                defmt::write!(f, "PhyUpdated {{ tx_phy: {}, rx_phy: {} }}", tx_phy, rx_phy)
+            }
+            Self::ConnectionParamsUpdated {
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout,
+            } => defmt::write!(
+                f,
+                "ConnectionParamsUpdated {{ conn_interval: {}, peripheral_latency: {}, supervision_timeout: {} }}",
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout
+            ),
+            Self::RequestConnectionParams {
+                min_connection_interval,
+                max_connection_interval,
+                max_latency,
+                supervision_timeout,
+            } => defmt::write!(
+                f,
+                "RequestConnectionParams {{ min_connection_interval: {}, max_connection_interval: {}, max_latency: {}, supervision_timeout: {} }}",
+                min_connection_interval,
+                max_connection_interval,
+                max_latency,
+                supervision_timeout
+            ),
+            Self::DataLengthUpdated {
+                max_tx_octets,
+                max_tx_time,
+                max_rx_octets,
+                max_rx_time,
+            } => defmt::write!(
+                f,
+                "DataLengthUpdated {{ max_tx_octets: {}, max_tx_time: {}, max_rx_octets: {}, max_rx_time: {} }}",
+                max_tx_octets,
+                max_tx_time,
+                max_rx_octets,
+                max_rx_time
+            ),
+            Self::L2capChannelIdle { psm } => defmt::write!(f, "L2capChannelIdle {{ psm: {} }}", psm),
+            Self::Gatt { event } => defmt::write!(f, "Gatt {{ event: {} }}", event),
+            #[cfg(feature = "security")]
+            Self::PassKeyDisplay(key) => defmt::write!(f, "PassKeyDisplay({})", key),
+            #[cfg(feature = "security")]
+            Self::PassKeyConfirm(key) => defmt::write!(f, "PassKeyConfirm({})", key),
+            #[cfg(feature = "security")]
+            Self::PassKeyInput => defmt::write!(f, "PassKeyInput"),
+            #[cfg(feature = "security")]
+            Self::PairingComplete { security_level, bond } => defmt::write!(
+                f,
+                "PairingComplete {{ security_level: {}, bond: {} }}",
+                security_level,
+                bond
+            ),
+            #[cfg(feature = "security")]
+            Self::PairingFailed(err) => defmt::write!(f, "PairingFailed({})", err),
+            #[cfg(feature = "security")]
+            Self::BondingRequested { security_level, bond } => defmt::write!(
+                f,
+                "BondingRequested {{ security_level: {}, bond: {} }}",
+                security_level,
+                bond
+            ),
+            #[cfg(feature = "security")]
+            Self::PairingRejected {
+                failed_attempts,
+                retry_after,
+            } => defmt::write!(
+                f,
+                "PairingRejected {{ failed_attempts: {}, retry_after: {} }}",
+                failed_attempts,
+                retry_after
+            ),
+        }
+    }
 }
 
+/// How long to wait for the client to confirm an indication, as the ATT transaction timeout.
+const INDICATION_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Used to manage a GATT connection with a client.
 pub struct GattConnection<'stack, 'server, P: PacketPool> {
     connection: Connection<'stack, P>,
     pub(crate) server: &'server dyn DynamicAttributeServer<P>,
+    write_budget: RefCell<Option<WriteBudget>>,
+    /// Held while an indication awaits its confirmation, as only one may be outstanding.
+    indication: Mutex<NoopRawMutex, ()>,
+    /// Whether an indication was sent and its confirmation has not arrived yet.
+    indication_pending: Cell<bool>,
+    /// Set once an indication was not confirmed in time, which ends indications on this connection.
+    indication_timed_out: Cell<bool>,
+    confirmation: Signal<NoopRawMutex, Result<(), Error>>,
+}
+
+/// What to do with inbound writes exceeding a [`WriteRateLimit`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteRateLimitPolicy {
+    /// Hold the write back until the next window starts, delaying its response.
+    Delay,
+    /// Respond to write requests with the given error; write commands are dropped.
+    Reject(AttErrorCode),
+    /// Disconnect the client.
+    Disconnect,
+}
+
+/// Budget of inbound ATT writes accepted from a client per time window.
+///
+/// Write requests, write commands and prepare write requests count towards the budget.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteRateLimit {
+    /// Number of writes accepted per window.
+    pub max_writes: u16,
+    /// Length of the window.
+    pub window: Duration,
+    /// What to do with writes over the budget.
+    pub policy: WriteRateLimitPolicy,
+}
+
+#[derive(Debug)]
+struct WriteBudget {
+    limit: WriteRateLimit,
+    window_start: Instant,
+    used: u16,
+}
+
+impl WriteBudget {
+    /// Count a write, returning the end of the current window if the budget is exhausted.
+    fn take(&mut self, now: Instant) -> Result<(), Instant> {
+        if now >= self.window_start + self.limit.window {
+            self.window_start = now;
+            self.used = 0;
+        }
+        if self.used < self.limit.max_writes {
+            self.used += 1;
+            Ok(())
+        } else {
+            Err(self.window_start + self.limit.window)
+        }
+    }
 }
 
 impl<P: PacketPool> Drop for GattConnection<'_, '_, P> {
@@ -126,7 +375,65 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
     ) -> Result<Self, Error> {
         trace!("[gatt {}] connecting to server", connection.handle().raw());
         server.connect(&connection)?;
-        Ok(Self { connection, server })
+        Ok(Self {
+            connection,
+            server,
+            write_budget: RefCell::new(None),
+            indication: Mutex::new(()),
+            indication_pending: Cell::new(false),
+            indication_timed_out: Cell::new(false),
+            confirmation: Signal::new(),
+        })
+    }
+
+    /// Limit the rate of inbound ATT writes from the client, or remove the limit with `None`.
+    pub fn set_write_rate_limit(&self, limit: Option<WriteRateLimit>) {
+        self.write_budget.replace(limit.map(|limit| WriteBudget {
+            limit,
+            window_start: Instant::now(),
+            used: 0,
+        }));
+    }
+
+    /// Apply the write rate limit to an inbound PDU, returning it if it should be processed.
+    async fn rate_limit(&self, mut data: GattData<'stack, P>) -> Option<GattData<'stack, P>> {
+        let (is_write, is_command) = match data.incoming() {
+            AttClient::Request(AttReq::Write { .. }) | AttClient::Request(AttReq::PrepareWrite { .. }) => (true, false),
+            AttClient::Command(AttCmd::Write { .. }) => (true, true),
+            _ => (false, false),
+        };
+        if !is_write {
+            return Some(data);
+        }
+        loop {
+            let (policy, until) = {
+                let mut budget = self.write_budget.borrow_mut();
+                let Some(budget) = budget.as_mut() else {
+                    return Some(data);
+                };
+                match budget.take(Instant::now()) {
+                    Ok(()) => return Some(data),
+                    Err(until) => (budget.limit.policy, until),
+                }
+            };
+            match policy {
+                WriteRateLimitPolicy::Delay => Timer::at(until).await,
+                WriteRateLimitPolicy::Reject(code) => {
+                    warn!("[gatt] write rate limit exceeded, rejecting write");
+                    if !is_command {
+                        if let Ok(reply) = process(&mut data, self.server, Err(code)) {
+                            reply.send().await;
+                        }
+                    }
+                    return None;
+                }
+                WriteRateLimitPolicy::Disconnect => {
+                    warn!("[gatt] write rate limit exceeded, disconnecting");
+                    self.connection.disconnect();
+                    return None;
+                }
+            }
+        }
     }
 
     /// Confirm that the displayed pass key matches the one displayed on the other party
@@ -144,13 +451,179 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
         self.connection.pass_key_input(pass_key)
     }
 
+    /// Write a value to the attribute with the given value handle, and notify the client with the new value.
+    ///
+    /// This is the untyped counterpart of [`Characteristic::notify`] for tables built at runtime or for
+    /// values forwarded from another device. If the client has not subscribed to notifications for
+    /// this attribute, only the stored value is updated.
+    ///
+    /// Returns [`Error::NotFound`] if the attribute has no Client Characteristic Configuration Descriptor,
+    /// in which case the stored value is left unchanged.
+    pub async fn notify_raw(&self, handle: u16, value: &[u8]) -> Result<(), Error> {
+        self.send_value(att::ATT_HANDLE_VALUE_NTF, handle, value).await
+    }
+
+    /// Write a value to the attribute with the given value handle, and indicate the new value to the client.
+    ///
+    /// Behaves like [`notify_raw`](Self::notify_raw), but sends a Handle Value Indication and, like
+    /// [`Characteristic::indicate`], completes once the client confirms it.
+    pub async fn indicate_raw(&self, handle: u16, value: &[u8]) -> Result<(), Error> {
+        self.send_value(att::ATT_HANDLE_VALUE_IND, handle, value).await
+    }
+
+    /// Whether the client has enabled notifications for the attribute with the given value handle.
+    pub(crate) fn notifications_enabled(&self, handle: u16) -> bool {
+        self.server
+            .cccd_handle(handle)
+            .is_some_and(|cccd_handle| self.server.should_notify(&self.connection, cccd_handle))
+    }
+
+    /// Whether the client has enabled indications for the attribute with the given value handle.
+    pub(crate) fn indications_enabled(&self, handle: u16) -> bool {
+        self.server
+            .cccd_handle(handle)
+            .is_some_and(|cccd_handle| self.server.should_indicate(&self.connection, cccd_handle))
+    }
+
+    /// Send a Handle Value Indication and wait for the client to confirm it.
+    ///
+    /// Indications are sent one at a time, each after the previous one was confirmed. Returns
+    /// [`Error::Timeout`] if the confirmation does not arrive within the ATT transaction timeout, after which no
+    /// more indications are sent, and [`Error::Disconnected`] if the connection is lost first.
+    pub(crate) async fn indicate_confirmed(&self, handle: u16, value: &[u8]) -> Result<(), Error> {
+        let _guard = self.indication.lock().await;
+        if self.indication_pending.get() {
+            // The indication of a cancelled call is still outstanding.
+            self.wait_confirmation().await?;
+        }
+        if self.indication_timed_out.get() {
+            return Err(Error::Timeout);
+        }
+        if !self.connection.is_connected() {
+            return Err(Error::Disconnected);
+        }
+        let pdu = value_pdu::<P>(att::ATT_HANDLE_VALUE_IND, handle, value)?;
+        self.confirmation.reset();
+        self.indication_pending.set(true);
+        self.connection.send(pdu).await;
+        self.wait_confirmation().await
+    }
+
+    /// Wait for the confirmation of the outstanding indication.
+    async fn wait_confirmation(&self) -> Result<(), Error> {
+        match with_timeout(INDICATION_TIMEOUT, self.confirmation.wait()).await {
+            Ok(result) => result,
+            Err(_) => {
+                // A later confirmation could not be told apart from that of the next indication.
+                self.indication_pending.set(false);
+                self.indication_timed_out.set(true);
+                Err(Error::Timeout)
+            }
+        }
+    }
+
+    /// Complete the outstanding indication, if any.
+    fn complete_indication(&self, result: Result<(), Error>) {
+        if self.indication_pending.replace(false) {
+            self.confirmation.signal(result);
+        }
+    }
+
+    async fn send_value(&self, opcode: u8, handle: u16, value: &[u8]) -> Result<(), Error> {
+        // Look up the CCCD first, so that a characteristic that cannot notify keeps its value.
+        let cccd_handle = self.server.cccd_handle(handle).ok_or(Error::NotFound)?;
+        self.server.set(handle, value)?;
+        let subscribed = if opcode == att::ATT_HANDLE_VALUE_IND {
+            self.server.should_indicate(&self.connection, cccd_handle)
+        } else {
+            self.server.should_notify(&self.connection, cccd_handle)
+        };
+        if !subscribed {
+            return Ok(());
+        }
+        if opcode == att::ATT_HANDLE_VALUE_IND {
+            return self.indicate_confirmed(handle, value).await;
+        }
+
+        let pdu = value_pdu::<P>(opcode, handle, value)?;
+        self.connection.send(pdu).await;
+        Ok(())
+    }
+
     /// Wait for the next GATT connection event.
     ///
     /// Uses the attribute server to handle the protocol.
     pub async fn next(&self) -> GattConnectionEvent<'stack, 'server, P> {
-        match select(self.connection.next(), self.connection.next_gatt()).await {
-            Either::First(event) => match event {
-                ConnectionEvent::Disconnected { reason } => GattConnectionEvent::Disconnected { reason },
+        loop {
+            if let Some(event) = self.next_event().await {
+                return event;
+            }
+        }
+    }
+
+    /// Wait for the next connection event, passing writes to the characteristics of `listeners` to them.
+    ///
+    /// A Write Request, Write Command or long write for a characteristic with a listener is decoded into the
+    /// characteristic's type, processed by the attribute server and, if the server accepts it, queued on the
+    /// listener for [`WriteListener::on_write`]. Values that fail to decode are rejected with the matching
+    /// [`AttErrorCode`]. All other events are returned as with [`next`](Self::next).
+    pub async fn next_dispatching(&self, listeners: &[&dyn WriteDispatch]) -> GattConnectionEvent<'stack, 'server, P> {
+        loop {
+            let event = match self.next().await {
+                GattConnectionEvent::Gatt {
+                    event: GattEvent::Write(event),
+                } => event,
+                other => return other,
+            };
+            let handle = event.handle();
+            // Listeners take whole values, not the part of a long write at an offset.
+            let listener = listeners
+                .iter()
+                .find(|listener| listener.handle() == handle && event.offset() == 0);
+            let Some(listener) = listener else {
+                return GattConnectionEvent::Gatt {
+                    event: GattEvent::Write(event),
+                };
+            };
+            let reply = match listener.stage(event.data()) {
+                Ok(()) => event.accept(),
+                Err(code) => event.reject(code),
+            };
+            match reply {
+                Ok(reply) => {
+                    if !reply.is_rejected() {
+                        listener.commit();
+                    }
+                    reply.send().await;
+                }
+                Err(e) => warn!("[gatt] failed to process write to {}: {:?}", handle, e),
+            }
+        }
+    }
+
+    async fn next_event(&self) -> Option<GattConnectionEvent<'stack, 'server, P>> {
+        let service_changed = poll_fn(|cx| {
+            // Changes wait for the outstanding indication to be confirmed. This is polled again on the next
+            // call, after the confirmation was received.
+            if self.indication_pending.get() || self.indication_timed_out.get() {
+                return Poll::Pending;
+            }
+            self.server.poll_service_changed(&self.connection, cx)
+        });
+        let notification = poll_fn(|cx| self.server.poll_notification(&self.connection, cx));
+        let event = match select4(
+            self.connection.next(),
+            self.connection.next_gatt(),
+            service_changed,
+            notification,
+        )
+        .await
+        {
+            Either4::First(event) => match event {
+                ConnectionEvent::Disconnected { reason } => {
+                    self.complete_indication(Err(Error::Disconnected));
+                    GattConnectionEvent::Disconnected { reason }
+                }
                 ConnectionEvent::ConnectionParamsUpdated {
                     conn_interval,
                     peripheral_latency,
@@ -172,6 +645,7 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
                     supervision_timeout,
                 },
                 ConnectionEvent::PhyUpdated { tx_phy, rx_phy } => GattConnectionEvent::PhyUpdated { tx_phy, rx_phy },
+                ConnectionEvent::L2capChannelIdle { psm } => GattConnectionEvent::L2capChannelIdle { psm },
                 ConnectionEvent::DataLengthUpdated {
                     max_tx_octets,
                     max_tx_time,
@@ -195,15 +669,87 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
 
                 #[cfg(feature = "security")]
                 ConnectionEvent::PairingComplete { security_level, bond } => {
+                    if bond.is_some() {
+                        self.server.save_cccds(&self.connection);
+                    }
                     GattConnectionEvent::PairingComplete { security_level, bond }
                 }
 
                 #[cfg(feature = "security")]
                 ConnectionEvent::PairingFailed(err) => GattConnectionEvent::PairingFailed(err),
+
+                #[cfg(feature = "security")]
+                ConnectionEvent::BondingRequested { security_level, bond } => {
+                    GattConnectionEvent::BondingRequested { security_level, bond }
+                }
+
+                #[cfg(feature = "security")]
+                ConnectionEvent::PairingRejected {
+                    failed_attempts,
+                    retry_after,
+                } => GattConnectionEvent::PairingRejected {
+                    failed_attempts,
+                    retry_after,
+                },
             },
-            Either::Second(data) => GattConnectionEvent::Gatt {
-                event: GattEvent::new(GattData::new(data, self.connection.clone()), self.server),
-            },
+            Either4::Second(data) => {
+                let data = GattData::new(data, self.connection.clone());
+                if let AttClient::Confirmation(_) = data.incoming() {
+                    self.complete_indication(Ok(()));
+                }
+                let data = self.rate_limit(data).await?;
+                GattConnectionEvent::Gatt {
+                    event: GattEvent::new(data, self.server),
+                }
+            }
+            Either4::Third((start, end)) => {
+                self.indicate_service_changed(start, end).await;
+                return None;
+            }
+            Either4::Fourth(handle) => {
+                self.send_queued_notification(handle).await;
+                return None;
+            }
+        };
+        Some(event)
+    }
+
+    /// Tell the client that the handles from `start` to `end` changed, if it enabled indications on the
+    /// Service Changed characteristic.
+    ///
+    /// The indication is outstanding like one sent by [`indicate_confirmed`](Self::indicate_confirmed), which
+    /// waits for its confirmation before sending another one. It is not awaited here, as the confirmation is
+    /// received by the caller.
+    async fn indicate_service_changed(&self, start: u16, end: u16) {
+        let Some(handle) = self.server.service_changed_handle() else {
+            return;
+        };
+        if !self.indications_enabled(handle) {
+            return;
+        }
+        let mut value = [0; 4];
+        value[..2].copy_from_slice(&start.to_le_bytes());
+        value[2..].copy_from_slice(&end.to_le_bytes());
+        match value_pdu::<P>(att::ATT_HANDLE_VALUE_IND, handle, &value) {
+            Ok(pdu) => {
+                self.confirmation.reset();
+                self.indication_pending.set(true);
+                self.connection.send(pdu).await;
+            }
+            Err(e) => warn!("[gatt] failed to indicate service changed: {:?}", e),
+        }
+    }
+
+    /// Notify the client of the current value at `handle`, queued by [`Characteristic::notify_all`], if it is
+    /// still subscribed.
+    async fn send_queued_notification(&self, handle: u16) {
+        if !self.notifications_enabled(handle) {
+            return;
+        }
+        let mtu = self.connection.att_mtu() as usize;
+        match notification_pdu::<P>(self.server, handle, mtu) {
+            Ok(pdu) => self.connection.send(pdu).await,
+            Err(e) => warn!("[gatt] failed to send queued notification: {:?}", e),
         }
     }
 
@@ -217,6 +763,8 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
 pub struct GattData<'stack, P: PacketPool> {
     pdu: Option<Pdu<P::Packet>>,
     connection: Connection<'stack, P>,
+    /// MTU of the EATT bearer the PDU was received on, `None` for the fixed ATT channel.
+    bearer_mtu: Option<u16>,
 }
 
 impl<'stack, P: PacketPool> GattData<'stack, P> {
@@ -224,9 +772,24 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
         Self {
             pdu: Some(pdu),
             connection,
+            bearer_mtu: None,
+        }
+    }
+
+    /// A PDU received on an EATT bearer with the given MTU, which is answered on that bearer.
+    pub(crate) const fn on_bearer(pdu: Pdu<P::Packet>, connection: Connection<'stack, P>, mtu: u16) -> Self {
+        Self {
+            pdu: Some(pdu),
+            connection,
+            bearer_mtu: Some(mtu),
         }
     }
 
+    /// ATT MTU of the bearer the PDU was received on.
+    fn att_mtu(&self) -> u16 {
+        self.bearer_mtu.unwrap_or_else(|| self.connection.get_att_mtu())
+    }
+
     /// Return the characteristic handle that this GATT request is related to, if applicable.
     ///
     /// Returns `None` if the request is not related to a characteristic handle (e.g. a service discovery request).
@@ -242,10 +805,14 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
 
     /// Get the raw incoming ATT PDU.
     pub fn incoming(&self) -> AttClient<'_> {
+        self.incoming_from(self.pdu.as_ref().unwrap())
+    }
+
+    fn incoming_from<'a>(&self, pdu: &'a Pdu<P::Packet>) -> AttClient<'a> {
         // We know that:
         // - The PDU is decodable, as it was already decoded once before adding it to the connection queue
         // - The PDU is of type `Att::Client` because only those types of PDUs are added to the connection queue
-        let att = unwrap!(Att::decode(self.pdu.as_ref().unwrap().as_ref()));
+        let att = unwrap!(Att::decode(pdu.as_ref()));
         let Att::Client(client) = att else {
             unreachable!("Expected Att::Client, got {:?}", att)
         };
@@ -255,19 +822,46 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
 
     /// Respond directly to request.
     pub async fn reply(self, rsp: AttRsp<'_>) -> Result<(), Error> {
-        let pdu = send(&self.connection, AttServer::Response(rsp))?;
+        let pdu = send::<P>(AttServer::Response(rsp), self.att_mtu())?;
         self.connection.send(pdu).await;
         Ok(())
     }
 
     /// Send an unsolicited ATT PDU without having a request (e.g. notification or indication)
     pub async fn send_unsolicited(connection: &Connection<'_, P>, uns: AttUns<'_>) -> Result<(), Error> {
-        let pdu = send(connection, AttServer::Unsolicited(uns))?;
+        let pdu = send::<P>(AttServer::Unsolicited(uns), connection.get_att_mtu())?;
         connection.send(pdu).await;
         Ok(())
     }
 }
 
+impl<P: PacketPool> GattData<'_, P> {
+    fn attribute_handle(&self) -> Option<u16> {
+        self.pdu.as_ref().and_then(|_| self.handle())
+    }
+}
+
+impl<P: PacketPool> core::fmt::Debug for GattData<'_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GattData")
+            .field("connection", &self.connection)
+            .field("handle", &self.attribute_handle())
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for GattData<'_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "GattData {{ connection: {}, handle: {} }}",
+            self.connection,
+            self.attribute_handle()
+        );
+    }
+}
+
 /// An event returned while processing GATT requests.
 pub enum GattEvent<'stack, 'server, P: PacketPool> {
     /// A characteristic was read.
@@ -284,7 +878,21 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
         let att = data.incoming();
         match att {
             AttClient::Request(AttReq::Write { .. }) | AttClient::Command(AttCmd::Write { .. }) => {
-                GattEvent::Write(WriteEvent { data, server })
+                GattEvent::Write(WriteEvent {
+                    data,
+                    server,
+                    prepared: None,
+                })
+            }
+            AttClient::Request(AttReq::ExecuteWrite { flags }) if flags & 0x01 != 0 => {
+                match server.prepared_write(&data.connection) {
+                    Some(prepared) => GattEvent::Write(WriteEvent {
+                        data,
+                        server,
+                        prepared: Some(prepared),
+                    }),
+                    None => GattEvent::Other(OtherEvent { data, server }),
+                }
             }
             AttClient::Request(AttReq::Read { .. }) | AttClient::Request(AttReq::ReadBlob { .. }) => {
                 GattEvent::Read(ReadEvent { data, server })
@@ -311,6 +919,14 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
         }
     }
 
+    /// Accept the event if `result` is `Ok`, or reject it with the error code.
+    pub fn respond(self, result: Result<(), AttErrorCode>) -> Result<Reply<'stack, P>, Error> {
+        match result {
+            Ok(()) => self.accept(),
+            Err(err) => self.reject(err),
+        }
+    }
+
     /// Get a reference to the underlying `GattData` payload that this event is enclosing
     pub fn payload(&self) -> &GattData<'stack, P> {
         match self {
@@ -335,6 +951,27 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
     }
 }
 
+impl<P: PacketPool> core::fmt::Debug for GattEvent<'_, '_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Read(e) => f.debug_tuple("Read").field(e).finish(),
+            Self::Write(e) => f.debug_tuple("Write").field(e).finish(),
+            Self::Other(e) => f.debug_tuple("Other").field(e).finish(),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for GattEvent<'_, '_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        match self {
+            Self::Read(e) => defmt::write!(f, "Read({})", e),
+            Self::Write(e) => defmt::write!(f, "Write({})", e),
+            Self::Other(e) => defmt::write!(f, "Other({})", e),
+        }
+    }
+}
+
 /// A characteristic read event returned while processing GATT requests.
 pub struct ReadEvent<'stack, 'server, P: PacketPool> {
     data: GattData<'stack, P>,
@@ -349,6 +986,63 @@ impl<'stack, P: PacketPool> ReadEvent<'stack, '_, P> {
         unwrap!(self.data.handle())
     }
 
+    /// Offset into the value requested by the client.
+    ///
+    /// This is 0 for a Read request and the requested offset for a Read Blob request.
+    pub fn offset(&self) -> u16 {
+        match self.data.incoming() {
+            AttClient::Request(AttReq::ReadBlob { offset, .. }) => offset,
+            _ => 0,
+        }
+    }
+
+    /// Maximum number of value bytes that fit in the response given the negotiated ATT MTU.
+    pub fn max_len(&self) -> usize {
+        (self.data.att_mtu() as usize).saturating_sub(1)
+    }
+
+    /// Respond with a value produced on demand instead of the one stored in the attribute table.
+    ///
+    /// The closure is called with the requested [`offset`](Self::offset) and a buffer of at most
+    /// [`max_len`](Self::max_len) bytes, and returns how many bytes it wrote. This lets large dynamic
+    /// values be produced one chunk at a time for Read Blob, without holding the whole value in RAM.
+    /// Returning an error sends an ATT error response with that code instead.
+    ///
+    /// The closure is only called if the client may read the characteristic: its read property and the
+    /// authorizer of the server are checked first, as for [`accept`](Self::accept), and a failed check is
+    /// answered with its error.
+    pub fn reply_with<F>(mut self, f: F) -> Result<Reply<'stack, P>, Error>
+    where
+        F: FnOnce(usize, &mut [u8]) -> Result<usize, AttErrorCode>,
+    {
+        let Some(pdu) = self.data.pdu.take() else {
+            return Ok(Reply::new(self.data.connection.clone(), None));
+        };
+        let connection = &self.data.connection;
+        let (opcode, handle, offset) = match self.data.incoming_from(&pdu) {
+            AttClient::Request(AttReq::ReadBlob { handle, offset }) => (att::ATT_READ_BLOB_RSP, handle, offset),
+            AttClient::Request(AttReq::Read { handle }) => (att::ATT_READ_RSP, handle, 0),
+            _ => unreachable!("ReadEvent wraps Read and Read Blob requests only"),
+        };
+        if let Err(code) = self.server.check_read(connection, handle) {
+            return process_reject(&pdu, connection, code, self.data.att_mtu());
+        }
+
+        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut w = WriteCursor::new(tx.as_mut());
+        let (mut header, mut data) = w.split(4)?;
+        data.write(opcode)?;
+        let budget = self.max_len().min(data.available());
+        match f(offset as usize, &mut data.write_buf()[..budget]) {
+            Ok(written) => data.commit(written.min(budget))?,
+            Err(code) => return process_reject(&pdu, connection, code, self.data.att_mtu()),
+        }
+        header.write(data.len() as u16)?;
+        header.write(4_u16)?;
+        let len = header.len() + data.len();
+        Ok(Reply::new(connection.clone(), Some(Pdu::new(tx, len))))
+    }
+
     /// Accept the event, making it processed by the server.
     ///
     /// Automatically called if drop() is invoked.
@@ -376,10 +1070,24 @@ impl<'stack, P: PacketPool> ReadEvent<'stack, '_, P> {
         GattData {
             pdu: self.data.pdu.take(),
             connection: self.data.connection.clone(),
+            bearer_mtu: self.data.bearer_mtu,
         }
     }
 }
 
+impl<P: PacketPool> core::fmt::Debug for ReadEvent<'_, '_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReadEvent").field("data", &self.data).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for ReadEvent<'_, '_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "ReadEvent {{ data: {} }}", self.data);
+    }
+}
+
 impl<P: PacketPool> Drop for ReadEvent<'_, '_, P> {
     fn drop(&mut self) {
         let _ = process(&mut self.data, self.server, Ok(()));
@@ -387,23 +1095,41 @@ impl<P: PacketPool> Drop for ReadEvent<'_, '_, P> {
 }
 
 /// A characteristic write event returned while processing GATT requests.
+///
+/// Besides Write Requests and Write Commands, this is returned when a client executes a long write: the value
+/// prepared for a single characteristic with Prepare Write requests, which is written when the event is accepted.
+/// Executed writes of several characteristics are returned as [`GattEvent::Other`].
 pub struct WriteEvent<'stack, 'server, P: PacketPool> {
     data: GattData<'stack, P>,
     server: &'server dyn DynamicAttributeServer<P>,
+    prepared: Option<PreparedWrite>,
 }
 
 impl<'stack, P: PacketPool> WriteEvent<'stack, '_, P> {
     /// Characteristic handle that was written
     pub fn handle(&self) -> u16 {
-        // We know that the unwrap cannot fail, because `ReadEvent` wraps
-        // ATT payloads that always do have a handle
-        unwrap!(self.data.handle())
+        match &self.prepared {
+            Some(prepared) => prepared.handle,
+            // We know that the unwrap cannot fail, because `WriteEvent` wraps
+            // ATT payloads that always do have a handle
+            None => unwrap!(self.data.handle()),
+        }
+    }
+
+    /// Offset into the value at which the data is written.
+    ///
+    /// This is 0 unless a long write starts past the beginning of the value.
+    pub fn offset(&self) -> u16 {
+        self.prepared.as_ref().map_or(0, |prepared| prepared.offset)
     }
 
     /// Raw data to be written
     pub fn data(&self) -> &[u8] {
-        // Note: write event data is always at offset 3, right?
-        &self.data.pdu.as_ref().unwrap().as_ref()[3..]
+        match &self.prepared {
+            Some(prepared) => &prepared.value,
+            // Note: write event data is always at offset 3, right?
+            None => &self.data.pdu.as_ref().unwrap().as_ref()[3..],
+        }
     }
 
     /// Characteristic data to be written
@@ -419,10 +1145,33 @@ impl<'stack, P: PacketPool> WriteEvent<'stack, '_, P> {
     }
 
     /// Reject the event with the provided error code, it will not be processed by the attribute server.
+    ///
+    /// The error code may be a standard one such as [`AttErrorCode::WRITE_NOT_PERMITTED`], or one defined by the
+    /// service, created with [`AttErrorCode::application`]. Write commands are dropped without a response.
     pub fn reject(mut self, err: AttErrorCode) -> Result<Reply<'stack, P>, Error> {
+        if self.prepared.is_some() {
+            // Like writes the server fails to execute, rejected writes are removed from the queue.
+            self.server.cancel_prepared_writes(&self.data.connection);
+        }
         process(&mut self.data, self.server, Err(err))
     }
 
+    /// Accept the event if the result of a write handler is `Ok`, or reject it with the error code.
+    ///
+    /// ```rust,ignore
+    /// let result = event
+    ///     .value(&control_point)
+    ///     .map_err(AttErrorCode::from)
+    ///     .and_then(|opcode| handle_control_point(opcode));
+    /// event.respond(result)?.send().await;
+    /// ```
+    pub fn respond(self, result: Result<(), AttErrorCode>) -> Result<Reply<'stack, P>, Error> {
+        match result {
+            Ok(()) => self.accept(),
+            Err(err) => self.reject(err),
+        }
+    }
+
     /// Get a reference to the underlying `GattData` payload that this event is enclosing
     pub fn payload(&self) -> &GattData<'stack, P> {
         &self.data
@@ -438,10 +1187,24 @@ impl<'stack, P: PacketPool> WriteEvent<'stack, '_, P> {
         GattData {
             pdu: self.data.pdu.take(),
             connection: self.data.connection.clone(),
+            bearer_mtu: self.data.bearer_mtu,
         }
     }
 }
 
+impl<P: PacketPool> core::fmt::Debug for WriteEvent<'_, '_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WriteEvent").field("data", &self.data).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for WriteEvent<'_, '_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "WriteEvent {{ data: {} }}", self.data);
+    }
+}
+
 impl<P: PacketPool> Drop for WriteEvent<'_, '_, P> {
     fn drop(&mut self) {
         let _ = process(&mut self.data, self.server, Ok(()));
@@ -482,10 +1245,24 @@ impl<'stack, P: PacketPool> OtherEvent<'stack, '_, P> {
         GattData {
             pdu: self.data.pdu.take(),
             connection: self.data.connection.clone(),
+            bearer_mtu: self.data.bearer_mtu,
         }
     }
 }
 
+impl<P: PacketPool> core::fmt::Debug for OtherEvent<'_, '_, P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("OtherEvent").field("data", &self.data).finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<P: PacketPool> defmt::Format for OtherEvent<'_, '_, P> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "OtherEvent {{ data: {} }}", self.data);
+    }
+}
+
 impl<P: PacketPool> Drop for OtherEvent<'_, '_, P> {
     fn drop(&mut self) {
         let _ = process(&mut self.data, self.server, Ok(()));
@@ -502,8 +1279,8 @@ where
 {
     if let Some(pdu) = data.pdu.take() {
         let res = match result {
-            Ok(_) => process_accept(&pdu, &data.connection, server),
-            Err(code) => process_reject(&pdu, &data.connection, code),
+            Ok(_) => process_accept(&pdu, &data.connection, server, data.att_mtu()),
+            Err(code) => process_reject(&pdu, &data.connection, code, data.att_mtu()),
         };
         res
     } else {
@@ -515,6 +1292,7 @@ fn process_accept<'stack, P>(
     pdu: &Pdu<P::Packet>,
     connection: &Connection<'stack, P>,
     server: &dyn DynamicAttributeServer<P>,
+    mtu: u16,
 ) -> Result<Reply<'stack, P>, Error>
 where
     P: PacketPool,
@@ -525,11 +1303,17 @@ where
     let Att::Client(att) = att else {
         unreachable!("Expected Att::Client, got {:?}", att)
     };
+    if let AttClient::Command(AttCmd::Write { handle, data }) = att {
+        // There is no response to a command, so the result is kept on the reply instead.
+        return Ok(match server.write_command(connection, handle, data) {
+            Ok(()) => Reply::new(connection.clone(), None),
+            Err(_) => Reply::rejected(connection.clone()),
+        });
+    }
     let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
     let mut w = WriteCursor::new(tx.as_mut());
     let (mut header, mut data) = w.split(4)?;
     if let Some(written) = server.process(connection, &att, data.write_buf())? {
-        let mtu = connection.get_att_mtu();
         data.commit(written)?;
         data.truncate(mtu as usize);
         header.write(data.len() as u16)?;
@@ -546,6 +1330,7 @@ fn process_reject<'stack, P: PacketPool>(
     pdu: &Pdu<P::Packet>,
     connection: &Connection<'stack, P>,
     code: AttErrorCode,
+    mtu: u16,
 ) -> Result<Reply<'stack, P>, Error> {
     // - The PDU is decodable, as it was already decoded once before adding it to the connection queue
     // - The PDU is of type `Att::Client` because only those types of PDUs are added to the connection queue
@@ -554,8 +1339,9 @@ fn process_reject<'stack, P: PacketPool>(
         unreachable!("Expected Att::Client, got {:?}", att)
     };
     let handle = match att {
+        // Commands have no response, so there is nowhere to report the error.
+        AttClient::Command(_) => return Ok(Reply::rejected(connection.clone())),
         AttClient::Request(AttReq::Write { handle, .. }) => handle,
-        AttClient::Command(AttCmd::Write { handle, .. }) => handle,
         AttClient::Request(AttReq::Read { handle }) => handle,
         AttClient::Request(AttReq::ReadBlob { handle, .. }) => handle,
         _ => 0, // As per spec, if the incoming ATT does not have an ATT handle, we should report with handle 0
@@ -563,17 +1349,16 @@ fn process_reject<'stack, P: PacketPool>(
     // We know it has been checked, therefore this cannot fail
     let request = pdu.as_ref()[0];
     let rsp = AttRsp::Error { request, handle, code };
-    let pdu = send(connection, AttServer::Response(rsp))?;
+    let pdu = send::<P>(AttServer::Response(rsp), mtu)?;
     Ok(Reply::new(connection.clone(), Some(pdu)))
 }
 
-fn send<'stack, P: PacketPool>(conn: &Connection<'stack, P>, att: AttServer<'_>) -> Result<Pdu<P::Packet>, Error> {
+fn send<P: PacketPool>(att: AttServer<'_>, mtu: u16) -> Result<Pdu<P::Packet>, Error> {
     let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
     let mut w = WriteCursor::new(tx.as_mut());
     let (mut header, mut data) = w.split(4)?;
     data.write(Att::Server(att))?;
 
-    let mtu = conn.get_att_mtu();
     data.truncate(mtu as usize);
     header.write(data.len() as u16)?;
     header.write(4_u16)?;
@@ -588,11 +1373,25 @@ fn send<'stack, P: PacketPool>(conn: &Connection<'stack, P>, att: AttServer<'_>)
 pub struct Reply<'stack, P: PacketPool> {
     connection: Connection<'stack, P>,
     pdu: Option<Pdu<P::Packet>>,
+    rejected: bool,
 }
 
 impl<'stack, P: PacketPool> Reply<'stack, P> {
     fn new(connection: Connection<'stack, P>, pdu: Option<Pdu<P::Packet>>) -> Self {
-        Self { connection, pdu }
+        Self {
+            connection,
+            pdu,
+            rejected: false,
+        }
+    }
+
+    /// The reply to a command that was not applied, which has nothing to send.
+    fn rejected(connection: Connection<'stack, P>) -> Self {
+        Self {
+            connection,
+            pdu: None,
+            rejected: true,
+        }
     }
 
     /// Send the reply.
@@ -612,51 +1411,708 @@ impl<'stack, P: PacketPool> Reply<'stack, P> {
             self.connection.send(pdu).await
         }
     }
-}
 
-impl<P: PacketPool> Drop for Reply<'_, P> {
-    fn drop(&mut self) {
-        if let Some(pdu) = self.pdu.take() {
-            if self.connection.try_send(pdu).is_err() {
-                warn!("[gatt] error sending reply (outbound buffer full)");
-            }
-        }
+    /// Take the PDU of the reply, to send it on another bearer than the fixed ATT channel.
+    pub(crate) fn into_pdu(mut self) -> Option<Pdu<P::Packet>> {
+        self.pdu.take()
+    }
+
+    /// Whether the request was refused with an Error Response, or the command was not applied.
+    pub(crate) fn is_rejected(&self) -> bool {
+        self.rejected
+            || self
+                .pdu
+                .as_ref()
+                .is_some_and(|pdu| pdu.as_ref().get(4) == Some(&att::ATT_ERROR_RSP))
     }
 }
 
-/// Notification listener for GATT client.
-pub struct NotificationListener<'lst, const MTU: usize> {
+/// Receiver of the writes to a characteristic, see [`GattConnection::next_dispatching`].
+pub trait WriteDispatch {
+    /// Value handle of the characteristic.
+    fn handle(&self) -> u16;
+
+    /// Decode a written value and hold it until the attribute server has processed the write.
+    fn stage(&self, data: &[u8]) -> Result<(), AttErrorCode>;
+
+    /// Deliver the value held by [`stage`](Self::stage), as the attribute server accepted the write.
+    fn commit(&self);
+}
+
+/// Queue of the values written to a single characteristic.
+///
+/// Pass the listener to [`GattConnection::next_dispatching`] and await the decoded values with
+/// [`on_write`](Self::on_write), instead of matching on the handles of write events. Up to `N` values are
+/// queued, after which the oldest is discarded.
+pub struct WriteListener<M: RawMutex, T: FromGatt, const N: usize> {
     handle: u16,
-    listener: pubsub::DynSubscriber<'lst, Notification<MTU>>,
+    staged: embassy_sync::blocking_mutex::Mutex<M, RefCell<Option<T>>>,
+    queue: Channel<M, T, N>,
 }
 
-impl<'lst, const MTU: usize> NotificationListener<'lst, MTU> {
-    #[allow(clippy::should_implement_trait)]
-    /// Get the next (len: u16, Packet) tuple from the rx queue
-    pub async fn next(&mut self) -> Notification<MTU> {
-        loop {
-            if let WaitResult::Message(m) = self.listener.next_message().await {
-                if m.handle == self.handle {
-                    return m;
-                }
-            }
+impl<M: RawMutex, T: FromGatt, const N: usize> WriteListener<M, T, N> {
+    /// Create a listener for writes to `characteristic`.
+    pub fn new(characteristic: &Characteristic<T>) -> Self {
+        Self {
+            handle: characteristic.handle,
+            staged: embassy_sync::blocking_mutex::Mutex::new(RefCell::new(None)),
+            queue: Channel::new(),
         }
     }
+
+    /// Wait for the next value written to the characteristic.
+    pub async fn on_write(&self) -> T {
+        self.queue.receive().await
+    }
+
+    /// Take the next value written to the characteristic, if any.
+    pub fn try_on_write(&self) -> Option<T> {
+        self.queue.try_receive().ok()
+    }
 }
 
-const MAX_NOTIF: usize = config::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
-const NOTIF_QSIZE: usize = config::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
+impl<M: RawMutex, T: FromGatt, const N: usize> WriteDispatch for WriteListener<M, T, N> {
+    fn handle(&self) -> u16 {
+        self.handle
+    }
 
-/// A GATT client capable of using the GATT protocol.
+    fn stage(&self, data: &[u8]) -> Result<(), AttErrorCode> {
+        let value = T::from_gatt(data)?;
+        self.staged.lock(|staged| staged.replace(Some(value)));
+        Ok(())
+    }
+
+    fn commit(&self) {
+        let Some(value) = self.staged.lock(|staged| staged.take()) else {
+            return;
+        };
+        if self.queue.is_full() {
+            trace!("[gatt] write queue full, dropping oldest value");
+            let _ = self.queue.try_receive();
+        }
+        let _ = self.queue.try_send(value);
+    }
+}
+
+impl<P: PacketPool> Drop for Reply<'_, P> {
+    fn drop(&mut self) {
+        if let Some(pdu) = self.pdu.take() {
+            if self.connection.try_send(pdu).is_err() {
+                warn!("[gatt] error sending reply (outbound buffer full)");
+            }
+        }
+    }
+}
+
+/// Encode attribute handles for a Read Multiple request into `buf`.
+fn encode_handles<'a>(handles: &[u16], buf: &'a mut [u8]) -> Result<&'a [u8], Error> {
+    let len = handles.len() * 2;
+    if handles.len() < 2 {
+        return Err(Error::InvalidValue);
+    }
+    let buf = buf.get_mut(..len).ok_or(Error::InsufficientSpace)?;
+    for (raw, handle) in buf.chunks_exact_mut(2).zip(handles) {
+        raw.copy_from_slice(&handle.to_le_bytes());
+    }
+    Ok(buf)
+}
+
+/// Build a Handle Value Notification or Indication PDU for the given attribute value.
+pub(crate) fn value_pdu<P: PacketPool>(opcode: u8, handle: u16, value: &[u8]) -> Result<Pdu<P::Packet>, Error> {
+    let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+    let mut w = WriteCursor::new(tx.as_mut());
+    let (mut header, mut data) = w.split(4)?;
+    data.write(opcode)?;
+    data.write(handle)?;
+    data.append(value)?;
+
+    header.write(data.len() as u16)?;
+    header.write(4_u16)?;
+    let total = header.len() + data.len();
+    Ok(Pdu::new(tx, total))
+}
+
+/// Build a Handle Value Notification with the current value at `handle`, truncated to fit the ATT MTU.
+fn notification_pdu<P: PacketPool>(
+    server: &dyn DynamicAttributeServer<P>,
+    handle: u16,
+    att_mtu: usize,
+) -> Result<Pdu<P::Packet>, Error> {
+    let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+    let mut w = WriteCursor::new(tx.as_mut());
+    let (mut header, mut data) = w.split(4)?;
+    data.write(ATT_HANDLE_VALUE_NTF)?;
+    data.write(handle)?;
+    // The opcode and handle take 3 bytes of the ATT MTU.
+    let max = data.available().min(att_mtu.saturating_sub(3));
+    let len = server.read_value(handle, &mut data.write_buf()[..max])?;
+    data.commit(len)?;
+
+    header.write(data.len() as u16)?;
+    header.write(4_u16)?;
+    let total = header.len() + data.len();
+    Ok(Pdu::new(tx, total))
+}
+
+/// A set of characteristic updates sent to a client in a single Multiple Handle Value Notification.
+///
+/// Sharing one PDU lets several small values go out in one packet and one connection event.
+/// The client must have indicated support for this PDU in its Client Supported Features
+/// characteristic before it is used.
+pub struct MultipleNotification<'a, 'stack, 'server, P: PacketPool> {
+    connection: &'a GattConnection<'stack, 'server, P>,
+    tx: P::Packet,
+    len: usize,
+    count: usize,
+}
+
+impl<'a, 'stack, 'server, P: PacketPool> MultipleNotification<'a, 'stack, 'server, P> {
+    /// Start a new, empty, set of notifications for the given connection.
+    pub fn new(connection: &'a GattConnection<'stack, 'server, P>) -> Result<Self, Error> {
+        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        tx.as_mut()[4] = att::ATT_MULTIPLE_HANDLE_VALUE_NTF;
+        Ok(Self {
+            connection,
+            tx,
+            len: 1,
+            count: 0,
+        })
+    }
+
+    /// Write a value to a characteristic, and add it to the notification if the client subscribed to it.
+    ///
+    /// Returns `Ok(false)` if the client is not subscribed, in which case only the stored value is updated.
+    /// Returns [`Error::InsufficientSpace`] without updating the value if it does not fit in the
+    /// remaining ATT MTU; the caller should send this notification and add the value to a new one.
+    pub fn add<T: FromGatt>(&mut self, characteristic: &Characteristic<T>, value: &T) -> Result<bool, Error> {
+        let value = value.as_gatt();
+        let server = self.connection.server;
+        let cccd_handle = characteristic.cccd_handle.ok_or(Error::NotFound)?;
+        if !server.should_notify(self.connection.raw(), cccd_handle) {
+            server.set(characteristic.handle, value)?;
+            return Ok(false);
+        }
+
+        self.push(characteristic.handle, value)?;
+        server.set(characteristic.handle, value)?;
+        Ok(true)
+    }
+
+    /// Add a value to the notification without updating the attribute table.
+    fn push(&mut self, handle: u16, value: &[u8]) -> Result<(), Error> {
+        let mtu = (self.connection.raw().get_att_mtu() as usize).min(self.tx.as_ref().len() - 4);
+        if self.len + 4 + value.len() > mtu {
+            return Err(Error::InsufficientSpace);
+        }
+        let mut w = WriteCursor::new(&mut self.tx.as_mut()[4 + self.len..]);
+        w.write(handle)?;
+        w.write(value.len() as u16)?;
+        w.append(value)?;
+        self.len += w.len();
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of values added so far.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if no values have been added.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Send the notification. Nothing is sent if no values were added, and a single value is sent as a
+    /// plain Handle Value Notification.
+    pub async fn send(self) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut tx = self.tx;
+        let mut len = self.len;
+        if self.count == 1 {
+            // Drop the length field: opcode, handle, length and value become opcode, handle and value.
+            let data = &mut tx.as_mut()[4..4 + len];
+            data[0] = ATT_HANDLE_VALUE_NTF;
+            data.copy_within(5.., 3);
+            len -= 2;
+        }
+        let mut header = WriteCursor::new(&mut tx.as_mut()[..4]);
+        header.write(len as u16)?;
+        header.write(4_u16)?;
+        let pdu = Pdu::new(tx, 4 + len);
+        self.connection.raw().send(pdu).await;
+        Ok(())
+    }
+}
+
+/// New values for several characteristics, written to the attribute table in one step.
+///
+/// Values are kept in declaration order, and clients reading the attribute table either see all the
+/// new values or none of them. When sent to a client, the values it subscribed to are notified or
+/// indicated in declaration order, with consecutive notifications sharing Multiple Handle Value
+/// Notification PDUs if enabled with [`CharacteristicUpdates::set_multiple_notifications`].
+pub struct CharacteristicUpdates<'v, const N: usize> {
+    values: Vec<(u16, &'v [u8]), N>,
+    multiple_notifications: bool,
+}
+
+impl<const N: usize> Default for CharacteristicUpdates<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'v, const N: usize> CharacteristicUpdates<'v, N> {
+    /// Create an empty set of updates.
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            multiple_notifications: false,
+        }
+    }
+
+    /// Coalesce notifications into Multiple Handle Value Notification PDUs.
+    ///
+    /// The client must have indicated support for this PDU in its Client Supported Features
+    /// characteristic before it is enabled.
+    pub fn set_multiple_notifications(&mut self, enabled: bool) {
+        self.multiple_notifications = enabled;
+    }
+
+    /// Add a new value for a characteristic, replacing any value added for it before.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if `N` values have been added already.
+    pub fn add<T: AsGatt>(&mut self, characteristic: &Characteristic<T>, value: &'v T) -> Result<(), Error> {
+        let value = value.as_gatt();
+        match self
+            .values
+            .binary_search_by_key(&characteristic.handle, |(handle, _)| *handle)
+        {
+            Ok(pos) => {
+                self.values[pos].1 = value;
+                Ok(())
+            }
+            Err(pos) => self
+                .values
+                .insert(pos, (characteristic.handle, value))
+                .map_err(|_| Error::InsufficientSpace),
+        }
+    }
+
+    /// Number of values added so far.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if no values have been added.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Write the values to the attribute table of the provided attribute server.
+    ///
+    /// If any value cannot be written, none are and an error is returned.
+    pub fn apply<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+    ) -> Result<(), Error> {
+        server.table().set_raw_all(&self.values)
+    }
+
+    /// Write the values to the attribute table, and send them to a connection.
+    ///
+    /// If any value cannot be written, none are and an error is returned without sending anything.
+    /// Only values of characteristics the client subscribed to are sent. Each indication waits for the
+    /// client's confirmation before the following values are sent, so [`GattConnection::next`] must keep
+    /// running while this is awaited.
+    ///
+    /// Writing the same values again is harmless, so the updates can be sent to several connections in turn.
+    pub async fn notify<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+        let server = connection.server;
+        server.set_all(&self.values)?;
+
+        let raw = connection.raw();
+        let mut batch: Option<MultipleNotification<'_, '_, '_, P>> = None;
+        for (handle, value) in self.values.iter() {
+            let Some(cccd_handle) = server.cccd_handle(*handle) else {
+                continue;
+            };
+            if server.should_indicate(raw, cccd_handle) {
+                if let Some(batch) = batch.take() {
+                    batch.send().await?;
+                }
+                connection.indicate_confirmed(*handle, value).await?;
+            } else if server.should_notify(raw, cccd_handle) {
+                if !self.multiple_notifications {
+                    raw.send(value_pdu::<P>(att::ATT_HANDLE_VALUE_NTF, *handle, value)?)
+                        .await;
+                    continue;
+                }
+                if let Some(pending) = batch.as_mut() {
+                    if pending.push(*handle, value).is_ok() {
+                        continue;
+                    }
+                    if let Some(full) = batch.take() {
+                        full.send().await?;
+                    }
+                }
+                let mut next = MultipleNotification::new(connection)?;
+                if next.push(*handle, value).is_ok() {
+                    batch = Some(next);
+                } else {
+                    // Too long to share a PDU, send it on its own.
+                    raw.send(value_pdu::<P>(att::ATT_HANDLE_VALUE_NTF, *handle, value)?)
+                        .await;
+                }
+            }
+        }
+        if let Some(batch) = batch {
+            batch.send().await?;
+        }
+        Ok(())
+    }
+}
+
+/// What a [`NotificationListener`] does with a notification received while its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NotificationOverflowPolicy {
+    /// Discard the oldest queued notification to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Discard the new notification.
+    DropNewest,
+    /// Discard the new notification and report [`Error::NotificationOverflowed`] from
+    /// [`NotificationListener::next_checked`] once the queued notifications have been received.
+    ///
+    /// Further notifications are discarded until the error has been reported.
+    Fail,
+}
+
+/// Queue configuration of a [`NotificationListener`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NotificationQueueConfig {
+    /// Number of notifications queued before the overflow policy applies.
+    ///
+    /// Clamped to between 1 and the size set with the `gatt-client-notification-queue-size-N` features.
+    pub capacity: usize,
+    /// What to do with notifications received while the queue is full.
+    pub overflow: NotificationOverflowPolicy,
+}
+
+impl Default for NotificationQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: NOTIF_QSIZE,
+            overflow: NotificationOverflowPolicy::DropOldest,
+        }
+    }
+}
+
+/// Notification queue of a single listener.
+struct NotificationQueue<const MTU: usize> {
+    handle: Cell<Option<u16>>,
+    config: Cell<NotificationQueueConfig>,
+    overflowed: Cell<bool>,
+    queue: Channel<NoopRawMutex, Notification<MTU>, NOTIF_QSIZE>,
+}
+
+impl<const MTU: usize> NotificationQueue<MTU> {
+    const fn new() -> Self {
+        Self {
+            handle: Cell::new(None),
+            config: Cell::new(NotificationQueueConfig {
+                capacity: NOTIF_QSIZE,
+                overflow: NotificationOverflowPolicy::DropOldest,
+            }),
+            overflowed: Cell::new(false),
+            queue: Channel::new(),
+        }
+    }
+
+    fn push(&self, notification: Notification<MTU>) {
+        if self.overflowed.get() {
+            return;
+        }
+        let config = self.config.get();
+        if self.queue.len() >= config.capacity {
+            match config.overflow {
+                NotificationOverflowPolicy::DropOldest => {
+                    let _ = self.queue.try_receive();
+                }
+                NotificationOverflowPolicy::DropNewest => {
+                    trace!("[gatt] notification queue full, dropping notification");
+                    return;
+                }
+                NotificationOverflowPolicy::Fail => {
+                    warn!("[gatt] notification queue overflowed");
+                    self.overflowed.set(true);
+                    return;
+                }
+            }
+        }
+        let _ = self.queue.try_send(notification);
+    }
+
+    fn release(&self) {
+        self.handle.set(None);
+        self.overflowed.set(false);
+        self.queue.clear();
+    }
+}
+
+/// Notification listener for GATT client.
+///
+/// Each listener queues the notifications of its characteristic separately, according to its
+/// [`NotificationQueueConfig`].
+pub struct NotificationListener<'lst, const MTU: usize> {
+    queue: &'lst NotificationQueue<MTU>,
+}
+
+impl<'lst, const MTU: usize> NotificationListener<'lst, MTU> {
+    #[allow(clippy::should_implement_trait)]
+    /// Get the next notification from the queue.
+    ///
+    /// Overflows reported by the [`NotificationOverflowPolicy::Fail`] policy are skipped; use
+    /// [`next_checked`](Self::next_checked) to observe them.
+    pub async fn next(&mut self) -> Notification<MTU> {
+        loop {
+            if let Ok(notification) = self.next_checked().await {
+                return notification;
+            }
+        }
+    }
+
+    /// Get the next notification from the queue, or [`Error::NotificationOverflowed`] if notifications were
+    /// discarded by the [`NotificationOverflowPolicy::Fail`] policy.
+    pub async fn next_checked(&mut self) -> Result<Notification<MTU>, Error> {
+        if self.queue.overflowed.get() && self.queue.queue.is_empty() {
+            self.queue.overflowed.set(false);
+            return Err(Error::NotificationOverflowed);
+        }
+        Ok(self.queue.queue.receive().await)
+    }
+
+    /// The queue configuration of this listener.
+    pub fn queue_config(&self) -> NotificationQueueConfig {
+        self.queue.config.get()
+    }
+
+    /// Change the queue configuration of this listener.
+    ///
+    /// Notifications already queued are kept, even if they exceed the new capacity.
+    pub fn set_queue_config(&mut self, config: NotificationQueueConfig) {
+        self.queue.config.set(NotificationQueueConfig {
+            capacity: config.capacity.clamp(1, NOTIF_QSIZE),
+            ..config
+        });
+    }
+}
+
+impl<const MTU: usize> Drop for NotificationListener<'_, MTU> {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+const MAX_NOTIF: usize = config::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
+/// Most paced Write Commands that may be in flight, see [`GattClient::set_write_command_limit`].
+const WRITE_COMMAND_WINDOW: usize = 8;
+const NOTIF_QSIZE: usize = config::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
+
+/// A subscription to a characteristic that decodes its notifications or indications, see
+/// [`GattClient::subscribe_values`].
+///
+/// Dropping the subscription unsubscribes from the characteristic.
+pub struct ValueSubscription<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize, T: FromGatt> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    listener: NotificationListener<'a, 512>,
+    handle: u16,
+    cccd_handle: u16,
+    phantom: PhantomData<T>,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize, T: FromGatt>
+    ValueSubscription<'_, '_, C, P, MAX_SERVICES, T>
+{
+    /// Get the next value of the characteristic.
+    #[allow(clippy::should_implement_trait)]
+    pub async fn next(&mut self) -> Result<T, FromGattError> {
+        let notification = self.listener.next().await;
+        T::from_gatt(notification.as_ref())
+    }
+
+    /// Unsubscribe from the characteristic, waiting for the peer to accept the CCCD write.
+    ///
+    /// If the write fails, the CCCD is cleared again by [`GattClient::task`] as when the subscription is dropped.
+    pub async fn unsubscribe(mut self) -> Result<(), BleHostError<C::Error>> {
+        self.client.write_cccd(self.cccd_handle, 0).await?;
+        self.client.forget_subscription(self.handle);
+        self.cccd_handle = 0;
+        Ok(())
+    }
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize, T: FromGatt> Drop
+    for ValueSubscription<'_, '_, C, P, MAX_SERVICES, T>
+{
+    fn drop(&mut self) {
+        // Unsubscribed already.
+        if self.cccd_handle == 0 {
+            return;
+        }
+        self.client.forget_subscription(self.handle);
+        if self.client.pending_unsubscribes.try_send(self.cccd_handle).is_err() {
+            warn!(
+                "[gatt] too many pending unsubscribes, not clearing CCCD {}",
+                self.cccd_handle
+            );
+        }
+    }
+}
+
+/// A reliable write of one or more characteristics, see [`GattClient::begin_reliable_write`].
+///
+/// Each value is queued on the peer with Prepare Write requests and checked against the part echoed by the peer.
+/// If queueing a value fails, the values queued so far are discarded and the transaction can no longer be
+/// committed. A transaction dropped without [`commit`](Self::commit) or [`abort`](Self::abort) is discarded
+/// before the next long or reliable write.
+///
+/// The peer has a single queue for each client, so starting another reliable write, or a long write that has to
+/// discard the values queued so far, ends the transaction as well.
+pub struct ReliableWrite<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    /// Value of [`GattClient::prepare_generation`] while this transaction owns the queue of the peer.
+    generation: u32,
+    failed: bool,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize> ReliableWrite<'_, '_, C, P, MAX_SERVICES> {
+    /// Whether the values queued by this transaction are still queued on the peer, and no more than those.
+    fn is_open(&self) -> bool {
+        !self.failed && self.client.prepare_generation.get() == self.generation
+    }
+
+    /// Queue a value to write to the characteristic.
+    ///
+    /// Returns [`Error::InvalidState`] if queueing a previous value failed or the transaction was ended by
+    /// another long or reliable write.
+    pub async fn queue_write<T: FromGatt>(
+        &mut self,
+        characteristic: &Characteristic<T>,
+        value: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let _guard = self.client.prepare_queue.lock().await;
+        if !self.is_open() {
+            return Err(Error::InvalidState.into());
+        }
+        if let Err(e) = self.client.prepare_write(characteristic.handle, value).await {
+            self.failed = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Write all queued values.
+    ///
+    /// Returns [`Error::InvalidState`] if queueing a value failed or the transaction was ended by another long or
+    /// reliable write, in which case nothing is written.
+    pub async fn commit(self) -> Result<(), BleHostError<C::Error>> {
+        let _guard = self.client.prepare_queue.lock().await;
+        if !self.is_open() {
+            return Err(Error::InvalidState.into());
+        }
+        self.client.execute_write(true).await
+    }
+
+    /// Discard all queued values.
+    pub async fn abort(self) -> Result<(), BleHostError<C::Error>> {
+        let _guard = self.client.prepare_queue.lock().await;
+        if !self.is_open() {
+            return Ok(());
+        }
+        self.client.execute_write(false).await
+    }
+}
+
+/// Completion of a Write Command sent by [`GattClient::write_characteristic_paced`].
+pub struct WriteCommandCompletion<'a, 'reference, P: PacketPool> {
+    connection: &'a Connection<'reference, P>,
+    target: u32,
+}
+
+impl<P: PacketPool> WriteCommandCompletion<'_, '_, P> {
+    /// Wait for the controller to complete the write, meaning it was sent to the peer.
+    ///
+    /// Returns [`Error::Disconnected`] if the connection is lost first.
+    pub async fn wait(self) -> Result<(), Error> {
+        self.connection.tx_completed(self.target).await
+    }
+}
+
+/// A GATT client capable of using the GATT protocol.
 pub struct GattClient<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> {
     known_services: RefCell<Vec<ServiceHandle, MAX_SERVICES>>,
     rx: DynamicReceiver<'reference, (ConnHandle, Pdu<P::Packet>)>,
     stack: &'reference Stack<'reference, T, P>,
     connection: Connection<'reference, P>,
     response_channel: Channel<NoopRawMutex, (ConnHandle, Pdu<P::Packet>), 1>,
+    /// Held while a request waits for its response, so that each response is taken by the request it answers.
+    requests: Mutex<NoopRawMutex, ()>,
 
     // TODO: Wait for something like https://github.com/rust-lang/rust/issues/132980 (min_generic_const_args) to allow using P::MTU
-    notifications: PubSubChannel<NoopRawMutex, Notification<512>, NOTIF_QSIZE, MAX_NOTIF, 1>,
+    notifications: [NotificationQueue<512>; MAX_NOTIF],
+    subscriptions: RefCell<Vec<Subscription, MAX_NOTIF>>,
+    /// CCCDs to clear for dropped [`ValueSubscription`]s, written by [`GattClient::task`].
+    pending_unsubscribes: Channel<NoopRawMutex, u16, MAX_NOTIF>,
+    /// Whether values may be queued on the peer by Prepare Write requests.
+    prepared_writes: Cell<bool>,
+    /// Held while values are queued on the peer or written from its queue, so that these procedures do not
+    /// interleave.
+    prepare_queue: Mutex<NoopRawMutex, ()>,
+    /// Changed whenever the queued values are discarded or a reliable write starts, ending the open
+    /// [`ReliableWrite`].
+    prepare_generation: Cell<u32>,
+    /// ACL completion targets of the paced Write Commands that may still be in flight.
+    write_commands: RefCell<Deque<u32, WRITE_COMMAND_WINDOW>>,
+    write_command_limit: Cell<usize>,
+    /// Value handle of the peer's Service Changed characteristic, once known from a [`DiscoveryCache`].
+    service_changed_handle: Cell<Option<u16>>,
+    /// Whether the peer indicated Service Changed since its attribute database was last discovered.
+    services_changed: Cell<bool>,
+}
+
+/// A characteristic subscription made by a [`GattClient`].
+///
+/// Subscriptions can be stored alongside the bond of a peer and restored on a later connection with
+/// [`GattClient::restore_subscriptions`] and [`GattClient::resubscribe_all`].
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Subscription {
+    /// Value handle of the characteristic.
+    pub handle: u16,
+    /// Handle of the Client Characteristic Configuration Descriptor of the characteristic.
+    pub cccd_handle: u16,
+    /// Whether indications are enabled rather than notifications.
+    pub indication: bool,
+    /// UUIDs of the service and the characteristic, used to find the characteristic again if the
+    /// peer's attribute database changed. Only known for subscriptions made with
+    /// [`GattClient::subscribe_by_uuid`].
+    pub uuids: Option<(Uuid, Uuid)>,
+}
+
+impl Subscription {
+    fn cccd_value(&self) -> u16 {
+        if self.indication {
+            0x02
+        } else {
+            0x01
+        }
+    }
 }
 
 /// A notification payload.
@@ -678,9 +2134,691 @@ impl<const MTU: usize> AsRef<[u8]> for Notification<MTU> {
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Clone)]
 pub struct ServiceHandle {
+    pub(crate) start: u16,
+    pub(crate) end: u16,
+    pub(crate) uuid: Uuid,
+}
+
+impl ServiceHandle {
+    /// Handle of the service declaration.
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Last handle in the service.
+    pub fn end(&self) -> u16 {
+        self.end
+    }
+
+    /// UUID of the service.
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+}
+
+/// A characteristic declaration found by [`GattClient::discover_characteristics`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveredCharacteristic {
+    /// Handle of the characteristic declaration.
+    pub declaration_handle: u16,
+    /// Handle of the characteristic value.
+    pub handle: u16,
+    /// Properties of the characteristic.
+    pub props: CharacteristicProps,
+    /// UUID of the characteristic.
+    pub uuid: Uuid,
+}
+
+/// A descriptor found by [`GattClient::discover_descriptors`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DiscoveredDescriptor {
+    /// Handle of the descriptor.
+    pub handle: u16,
+    /// UUID of the descriptor.
+    pub uuid: Uuid,
+}
+
+impl DiscoveredDescriptor {
+    /// Whether this is a Client Characteristic Configuration Descriptor.
+    pub fn is_cccd(&self) -> bool {
+        self.uuid == CLIENT_CHARACTERISTIC_CONFIGURATION.into()
+    }
+}
+
+/// Version of the blob written by [`DiscoveryCache::export`].
+const DISCOVERY_CACHE_VERSION: u8 = 1;
+
+/// Database Hash characteristic of the GATT service.
+const DATABASE_HASH: Uuid = Uuid::new_short(0x2b2a);
+
+/// An attribute recorded in a [`DiscoveryCache`].
+enum CachedAttribute {
+    Service(ServiceHandle),
+    Characteristic(DiscoveredCharacteristic),
+    Descriptor(DiscoveredDescriptor),
+    /// A Characteristic Extended Properties descriptor and its value.
+    ExtendedProps(u16, CharacteristicExtendedProps),
+}
+
+impl CachedAttribute {
+    const SERVICE: u8 = 1;
+    const CHARACTERISTIC: u8 = 2;
+    const DESCRIPTOR: u8 = 3;
+    const EXTENDED_PROPS: u8 = 4;
+    /// Longest encoding of an attribute, a characteristic with a 128-bit UUID.
+    const MAX_LEN: usize = 1 + 2 + 2 + 1 + 1 + 16;
+
+    fn encode(&self, w: &mut WriteCursor<'_>) -> Result<(), Error> {
+        match self {
+            Self::Service(service) => {
+                w.write(Self::SERVICE)?;
+                w.write(service.start)?;
+                w.write(service.end)?;
+                Self::encode_uuid(w, &service.uuid)
+            }
+            Self::Characteristic(characteristic) => {
+                w.write(Self::CHARACTERISTIC)?;
+                w.write(characteristic.declaration_handle)?;
+                w.write(characteristic.handle)?;
+                w.write(characteristic.props.raw())?;
+                Self::encode_uuid(w, &characteristic.uuid)
+            }
+            Self::Descriptor(descriptor) => {
+                w.write(Self::DESCRIPTOR)?;
+                w.write(descriptor.handle)?;
+                Self::encode_uuid(w, &descriptor.uuid)
+            }
+            Self::ExtendedProps(handle, value) => {
+                w.write(Self::EXTENDED_PROPS)?;
+                w.write(*handle)?;
+                w.write(value.raw())
+            }
+        }
+    }
+
+    fn decode(r: &mut ReadCursor<'_>) -> Result<Self, Error> {
+        let tag: u8 = r.read()?;
+        match tag {
+            Self::SERVICE => Ok(Self::Service(ServiceHandle {
+                start: r.read()?,
+                end: r.read()?,
+                uuid: Self::decode_uuid(r)?,
+            })),
+            Self::CHARACTERISTIC => Ok(Self::Characteristic(DiscoveredCharacteristic {
+                declaration_handle: r.read()?,
+                handle: r.read()?,
+                props: CharacteristicProps::from_raw(r.read()?),
+                uuid: Self::decode_uuid(r)?,
+            })),
+            Self::DESCRIPTOR => Ok(Self::Descriptor(DiscoveredDescriptor {
+                handle: r.read()?,
+                uuid: Self::decode_uuid(r)?,
+            })),
+            Self::EXTENDED_PROPS => Ok(Self::ExtendedProps(
+                r.read()?,
+                CharacteristicExtendedProps::from_raw(r.read()?),
+            )),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    fn encode_uuid(w: &mut WriteCursor<'_>, uuid: &Uuid) -> Result<(), Error> {
+        w.write(uuid.as_raw().len() as u8)?;
+        w.append(uuid.as_raw())
+    }
+
+    fn decode_uuid(r: &mut ReadCursor<'_>) -> Result<Uuid, Error> {
+        let len: u8 = r.read()?;
+        Uuid::try_from(r.slice(len as usize)?)
+    }
+}
+
+/// The attribute database of a peer, as found by [`GattClient::discover_all`].
+///
+/// Services, characteristics and descriptors are kept as compact records in at most `N` bytes. The cache can be
+/// stored with the bond of the peer using [`export`](Self::export) and loaded again with [`import`](Self::import), so
+/// that after [`GattClient::check_discovery_cache`] confirms the peer's database did not change, characteristics are
+/// looked up in the cache instead of being discovered again.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DiscoveryCache<const N: usize> {
+    database_hash: Option<[u8; 16]>,
+    records: Vec<u8, N>,
+}
+
+impl<const N: usize> DiscoveryCache<N> {
+    /// Upper bound on the length of the blob written by [`export`](Self::export).
+    pub const MAX_LEN: usize = 1 + 1 + 16 + N;
+
+    /// Create an empty cache.
+    pub const fn new() -> Self {
+        Self {
+            database_hash: None,
+            records: Vec::new(),
+        }
+    }
+
+    /// Whether nothing has been discovered into the cache.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Forget everything in the cache.
+    pub fn clear(&mut self) {
+        self.database_hash = None;
+        self.records.clear();
+    }
+
+    /// Database Hash of the peer when it was discovered, if the peer has one.
+    pub fn database_hash(&self) -> Option<[u8; 16]> {
+        self.database_hash
+    }
+
+    /// The primary services of the peer.
+    pub fn services(&self) -> impl Iterator<Item = ServiceHandle> + '_ {
+        self.attributes().filter_map(|attribute| match attribute {
+            CachedAttribute::Service(service) => Some(service),
+            _ => None,
+        })
+    }
+
+    /// Value handle of the Service Changed characteristic of the peer, if it has one.
+    fn service_changed_handle(&self) -> Option<u16> {
+        self.attributes().find_map(|attribute| match attribute {
+            CachedAttribute::Characteristic(c) if c.uuid == SERVICE_CHANGED => Some(c.handle),
+            _ => None,
+        })
+    }
+
+    /// The first primary service with a UUID.
+    pub fn service_by_uuid(&self, uuid: &Uuid) -> Option<ServiceHandle> {
+        self.services().find(|service| service.uuid == *uuid)
+    }
+
+    /// The characteristics of a service.
+    pub fn characteristics(&self, service: &ServiceHandle) -> impl Iterator<Item = DiscoveredCharacteristic> + '_ {
+        let (start, end) = (service.start, service.end);
+        self.attributes().filter_map(move |attribute| match attribute {
+            CachedAttribute::Characteristic(c) if c.declaration_handle > start && c.declaration_handle <= end => {
+                Some(c)
+            }
+            _ => None,
+        })
+    }
+
+    /// The descriptors of a characteristic of a service.
+    pub fn descriptors(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> impl Iterator<Item = DiscoveredDescriptor> + '_ {
+        self.characteristic_descriptors(service, characteristic)
+            .filter_map(|attribute| match attribute {
+                CachedAttribute::Descriptor(descriptor) => Some(descriptor),
+                CachedAttribute::ExtendedProps(handle, _) => Some(DiscoveredDescriptor {
+                    handle,
+                    uuid: CHARACTERISTIC_EXTENDED_PROPERTIES.into(),
+                }),
+                _ => None,
+            })
+    }
+
+    /// Find a characteristic of a service using a UUID.
+    ///
+    /// Like [`GattClient::characteristic_by_uuid`], the returned characteristic carries its CCCD and extended
+    /// properties, so it can be read, written and subscribed to without any discovery. Returns
+    /// [`Error::NotFound`] if the characteristic is not in the cache.
+    pub fn characteristic_by_uuid<T: AsGatt>(
+        &self,
+        service: &ServiceHandle,
+        uuid: &Uuid,
+    ) -> Result<Characteristic<T>, Error> {
+        let characteristic = self
+            .characteristics(service)
+            .find(|characteristic| characteristic.uuid == *uuid)
+            .ok_or(Error::NotFound)?;
+        let props = characteristic.props;
+        let extended = props.contains(CharacteristicProp::Extended);
+        let mut cccd_handle = None;
+        let mut extended_props = None;
+        for attribute in self.characteristic_descriptors(service, &characteristic) {
+            match attribute {
+                CachedAttribute::Descriptor(descriptor) if descriptor.is_cccd() => {
+                    cccd_handle.get_or_insert(descriptor.handle);
+                }
+                CachedAttribute::ExtendedProps(_, value) if extended => {
+                    extended_props.get_or_insert(value);
+                }
+                _ => {}
+            }
+        }
+        if props.any(&[CharacteristicProp::Indicate, CharacteristicProp::Notify]) && cccd_handle.is_none() {
+            return Err(Error::NotFound);
+        }
+        Ok(Characteristic {
+            handle: characteristic.handle,
+            cccd_handle,
+            props,
+            extended_props,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Write the cache into `buf` as a compact blob, returning its length.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if `buf` is too small; see [`Self::MAX_LEN`].
+    pub fn export(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut w = WriteCursor::new(buf);
+        w.write(DISCOVERY_CACHE_VERSION)?;
+        match self.database_hash {
+            Some(hash) => {
+                w.write(1u8)?;
+                w.append(&hash)?;
+            }
+            None => w.write(0u8)?,
+        }
+        w.append(&self.records)?;
+        Ok(w.len())
+    }
+
+    /// Load a cache from a blob written by [`export`](Self::export).
+    ///
+    /// Returns [`Error::InvalidValue`] if the blob is malformed, and [`Error::InsufficientSpace`] if its records
+    /// do not fit in `N` bytes.
+    pub fn import(data: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(data);
+        let version: u8 = r.read().map_err(|_| Error::InvalidValue)?;
+        if version != DISCOVERY_CACHE_VERSION {
+            return Err(Error::InvalidValue);
+        }
+        let has_hash: u8 = r.read().map_err(|_| Error::InvalidValue)?;
+        let database_hash = match has_hash {
+            0 => None,
+            1 => {
+                let mut hash = [0; 16];
+                hash.copy_from_slice(r.slice(16).map_err(|_| Error::InvalidValue)?);
+                Some(hash)
+            }
+            _ => return Err(Error::InvalidValue),
+        };
+        let records = r.remaining();
+        let mut r = ReadCursor::new(records);
+        while r.available() > 0 {
+            CachedAttribute::decode(&mut r).map_err(|_| Error::InvalidValue)?;
+        }
+        Ok(Self {
+            database_hash,
+            records: Vec::from_slice(records).map_err(|_| Error::InsufficientSpace)?,
+        })
+    }
+
+    fn attributes(&self) -> impl Iterator<Item = CachedAttribute> + '_ {
+        let mut r = ReadCursor::new(&self.records);
+        // Records are validated when added or imported.
+        core::iter::from_fn(move || match r.available() {
+            0 => None,
+            _ => CachedAttribute::decode(&mut r).ok(),
+        })
+    }
+
+    /// Descriptors between the value of a characteristic and the next characteristic declaration or the end of
+    /// the service.
+    fn characteristic_descriptors(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> impl Iterator<Item = CachedAttribute> + '_ {
+        let start = characteristic.handle;
+        let end = self
+            .characteristics(service)
+            .map(|next| next.declaration_handle)
+            .filter(|declaration| *declaration > start)
+            .min()
+            .map_or(service.end, |declaration| declaration - 1);
+        self.attributes().filter(move |attribute| match attribute {
+            CachedAttribute::Descriptor(DiscoveredDescriptor { handle, .. })
+            | CachedAttribute::ExtendedProps(handle, _) => *handle > start && *handle <= end,
+            _ => false,
+        })
+    }
+
+    fn push(&mut self, attribute: CachedAttribute) -> Result<(), Error> {
+        let mut buf = [0; CachedAttribute::MAX_LEN];
+        let mut w = WriteCursor::new(&mut buf);
+        attribute.encode(&mut w)?;
+        let len = w.len();
+        self.records
+            .extend_from_slice(&buf[..len])
+            .map_err(|_| Error::InsufficientSpace)
+    }
+}
+
+/// Progress of a discovery procedure.
+///
+/// Only the last response is kept, and items are decoded from it one at a time.
+struct DiscoveryState<P> {
     start: u16,
     end: u16,
-    uuid: Uuid,
+    done: bool,
+    response: Option<Pdu<P>>,
+    index: usize,
+    /// Whether the last response moved the discovery forward.
+    progressed: bool,
+}
+
+impl<P: Packet> DiscoveryState<P> {
+    fn new(start: u16, end: u16) -> Self {
+        Self {
+            start,
+            end,
+            done: start > end,
+            response: None,
+            index: 0,
+            progressed: false,
+        }
+    }
+
+    /// Continue the discovery after the attribute with handle `last`.
+    fn advance(&mut self, last: u16) {
+        self.index += 1;
+        if last >= self.end {
+            self.done = true;
+            self.progressed = true;
+        } else if last >= self.start {
+            self.start = last + 1;
+            self.progressed = true;
+        }
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        self.response = None;
+    }
+
+    /// Keep `response` for decoding if it is expected, or end the discovery.
+    ///
+    /// An Attribute Not Found error response is the regular end of a discovery.
+    fn accept<E>(&mut self, response: Pdu<P>, expected: fn(&AttRsp<'_>) -> bool) -> Result<(), BleHostError<E>> {
+        let result = match Att::decode(response.as_ref()) {
+            Ok(Att::Server(AttServer::Response(rsp))) if expected(&rsp) => Ok(true),
+            Ok(Att::Server(AttServer::Response(AttRsp::Error { code, .. }))) => {
+                if code == AttErrorCode::ATTRIBUTE_NOT_FOUND {
+                    Ok(false)
+                } else {
+                    Err(Error::Att(code))
+                }
+            }
+            Ok(_) => Err(Error::UnexpectedGattResponse),
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok(true) => {
+                self.response = Some(response);
+                self.index = 0;
+                self.progressed = false;
+                Ok(())
+            }
+            Ok(false) => {
+                self.finish();
+                Ok(())
+            }
+            Err(e) => {
+                self.finish();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Decode the next item of the last response with `f`, which gets the response and the number of items
+    /// already taken from it.
+    ///
+    /// A response without any item past the handles already discovered ends the discovery with an error, as
+    /// requesting the same handles again would never finish.
+    fn next_item<T>(
+        &mut self,
+        f: impl FnOnce(AttRsp<'_>, usize) -> Result<Option<T>, Error>,
+    ) -> Option<Result<T, Error>> {
+        let response = self.response.as_ref()?;
+        let item = match Att::decode(response.as_ref()) {
+            Ok(Att::Server(AttServer::Response(rsp))) => f(rsp, self.index),
+            Ok(_) => Err(Error::UnexpectedGattResponse),
+            Err(e) => Err(e.into()),
+        };
+        match item {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) if self.progressed => {
+                self.response = None;
+                None
+            }
+            Ok(None) => {
+                self.finish();
+                Some(Err(Error::UnexpectedGattResponse))
+            }
+            Err(e) => {
+                self.finish();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Services discovered by [`GattClient::discover_services`] or [`GattClient::discover_services_by_uuid`].
+///
+/// Services are decoded from the responses as they arrive, so the number of services is not limited.
+pub struct ServiceDiscovery<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    uuid: Option<Uuid>,
+    state: DiscoveryState<P::Packet>,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize> ServiceDiscovery<'_, '_, C, P, MAX_SERVICES> {
+    /// Get the next service, or `None` once all services have been discovered.
+    pub async fn next(&mut self) -> Option<Result<ServiceHandle, BleHostError<C::Error>>> {
+        loop {
+            let uuid = self.uuid.as_ref();
+            let item = self.state.next_item(|rsp, index| match rsp {
+                AttRsp::FindByTypeValue { mut it } => {
+                    for _ in 0..index {
+                        it.next();
+                    }
+                    match it.next() {
+                        Some(res) => {
+                            let (start, end) = res?;
+                            Ok(Some(ServiceHandle {
+                                start,
+                                end,
+                                uuid: uuid.cloned().ok_or(Error::UnexpectedGattResponse)?,
+                            }))
+                        }
+                        None => Ok(None),
+                    }
+                }
+                AttRsp::ReadByGroupType { mut it } => {
+                    for _ in 0..index {
+                        it.next();
+                    }
+                    match it.next() {
+                        Some(res) => {
+                            let (start, end, value) = res?;
+                            let uuid = Uuid::try_from(value).map_err(|_| Error::InvalidValue)?;
+                            Ok(Some(ServiceHandle { start, end, uuid }))
+                        }
+                        None => Ok(None),
+                    }
+                }
+                _ => Err(Error::UnexpectedGattResponse),
+            });
+            match item {
+                Some(Ok(service)) => {
+                    self.state.advance(service.end);
+                    return Some(Ok(service));
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                None if self.state.done => return None,
+                None => {}
+            }
+
+            let req = match &self.uuid {
+                Some(uuid) => AttReq::FindByTypeValue {
+                    start_handle: self.state.start,
+                    end_handle: self.state.end,
+                    att_type: PRIMARY_SERVICE.into(),
+                    att_value: uuid.as_raw(),
+                },
+                None => AttReq::ReadByGroupType {
+                    start: self.state.start,
+                    end: self.state.end,
+                    group_type: PRIMARY_SERVICE.into(),
+                },
+            };
+            let response = match self.client.request(req).await {
+                Ok(response) => response,
+                Err(e) => {
+                    self.state.finish();
+                    return Some(Err(e));
+                }
+            };
+            if let Err(e) = self.state.accept(response.pdu, |rsp| {
+                matches!(rsp, AttRsp::FindByTypeValue { .. } | AttRsp::ReadByGroupType { .. })
+            }) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Characteristics discovered by [`GattClient::discover_characteristics`].
+///
+/// Characteristics are decoded from the responses as they arrive, so the number of characteristics is not limited.
+pub struct CharacteristicDiscovery<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    state: DiscoveryState<P::Packet>,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize> CharacteristicDiscovery<'_, '_, C, P, MAX_SERVICES> {
+    /// Get the next characteristic, or `None` once all characteristics of the service have been discovered.
+    pub async fn next(&mut self) -> Option<Result<DiscoveredCharacteristic, BleHostError<C::Error>>> {
+        loop {
+            let item = self.state.next_item(|rsp, index| match rsp {
+                AttRsp::ReadByType { mut it } => {
+                    for _ in 0..index {
+                        it.next();
+                    }
+                    match it.next() {
+                        Some(res) => {
+                            let (declaration_handle, item) = res?;
+                            if item.len() < 5 {
+                                return Err(Error::MalformedCharacteristicDeclaration {
+                                    expected: 5,
+                                    actual: item.len(),
+                                });
+                            }
+                            match AttributeData::decode_declaration(item)? {
+                                AttributeData::Declaration { props, handle, uuid } => {
+                                    Ok(Some(DiscoveredCharacteristic {
+                                        declaration_handle,
+                                        handle,
+                                        props,
+                                        uuid,
+                                    }))
+                                }
+                                _ => Err(Error::InvalidCharacteristicDeclarationData),
+                            }
+                        }
+                        None => Ok(None),
+                    }
+                }
+                _ => Err(Error::UnexpectedGattResponse),
+            });
+            match item {
+                Some(Ok(characteristic)) => {
+                    self.state.advance(characteristic.declaration_handle);
+                    return Some(Ok(characteristic));
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                None if self.state.done => return None,
+                None => {}
+            }
+
+            let req = AttReq::ReadByType {
+                start: self.state.start,
+                end: self.state.end,
+                attribute_type: CHARACTERISTIC.into(),
+            };
+            let response = match self.client.request(req).await {
+                Ok(response) => response,
+                Err(e) => {
+                    self.state.finish();
+                    return Some(Err(e));
+                }
+            };
+            if let Err(e) = self
+                .state
+                .accept(response.pdu, |rsp| matches!(rsp, AttRsp::ReadByType { .. }))
+            {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Descriptors discovered by [`GattClient::discover_descriptors`].
+///
+/// Descriptors are decoded from the responses as they arrive, so the number of descriptors is not limited.
+pub struct DescriptorDiscovery<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    state: DiscoveryState<P::Packet>,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize> DescriptorDiscovery<'_, '_, C, P, MAX_SERVICES> {
+    /// Get the next descriptor, or `None` once all descriptors in the handle range have been discovered.
+    pub async fn next(&mut self) -> Option<Result<DiscoveredDescriptor, BleHostError<C::Error>>> {
+        loop {
+            let item = self.state.next_item(|rsp, index| match rsp {
+                AttRsp::FindInformation { mut it } => {
+                    for _ in 0..index {
+                        it.next();
+                    }
+                    match it.next() {
+                        Some(res) => {
+                            let (handle, uuid) = res?;
+                            Ok(Some(DiscoveredDescriptor { handle, uuid }))
+                        }
+                        None => Ok(None),
+                    }
+                }
+                _ => Err(Error::UnexpectedGattResponse),
+            });
+            match item {
+                Some(Ok(descriptor)) => {
+                    self.state.advance(descriptor.handle);
+                    return Some(Ok(descriptor));
+                }
+                Some(Err(e)) => return Some(Err(e.into())),
+                None if self.state.done => return None,
+                None => {}
+            }
+
+            let req = AttReq::FindInformation {
+                start_handle: self.state.start,
+                end_handle: self.state.end,
+            };
+            let response = match self.client.request(req).await {
+                Ok(response) => response,
+                Err(e) => {
+                    self.state.finish();
+                    return Some(Err(e));
+                }
+            };
+            if let Err(e) = self
+                .state
+                .accept(response.pdu, |rsp| matches!(rsp, AttRsp::FindInformation { .. }))
+            {
+                return Some(Err(e));
+            }
+        }
+    }
 }
 
 pub(crate) struct Response<P> {
@@ -701,6 +2839,7 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> Client
     async fn request(&self, req: AttReq<'_>) -> Result<Response<P::Packet>, BleHostError<T::Error>> {
         let data = Att::Client(AttClient::Request(req));
 
+        let _guard = self.requests.lock().await;
         self.send_att_data(data).await?;
 
         let (h, pdu) = self.response_channel.receive().await;
@@ -750,84 +2889,288 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
             mtu: P::MTU as u16 - 4,
         })))?;
 
-        let len = w.len();
-        connection.send(Pdu::new(buf, len)).await;
-        Ok(Self {
-            known_services: RefCell::new(heapless::Vec::new()),
-            rx: stack.host.att_client.receiver().into(),
-            stack,
-            connection: connection.clone(),
+        let len = w.len();
+        connection.send(Pdu::new(buf, len)).await;
+        Ok(Self {
+            known_services: RefCell::new(heapless::Vec::new()),
+            rx: stack.host.att_client.receiver().into(),
+            stack,
+            connection: connection.clone(),
+
+            response_channel: Channel::new(),
+            requests: Mutex::new(()),
+
+            notifications: [const { NotificationQueue::new() }; MAX_NOTIF],
+            subscriptions: RefCell::new(Vec::new()),
+            pending_unsubscribes: Channel::new(),
+            prepared_writes: Cell::new(false),
+            prepare_queue: Mutex::new(()),
+            prepare_generation: Cell::new(0),
+            write_commands: RefCell::new(Deque::new()),
+            write_command_limit: Cell::new(1),
+            service_changed_handle: Cell::new(None),
+            services_changed: Cell::new(false),
+        })
+    }
+
+    /// Discover primary services associated with a UUID.
+    ///
+    /// The number of services found is limited by `MAX_SERVICES`, use [`GattClient::discover_services_by_uuid`]
+    /// to go through them one at a time instead.
+    pub async fn services_by_uuid(
+        &self,
+        uuid: &Uuid,
+    ) -> Result<Vec<ServiceHandle, MAX_SERVICES>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        let mut services = self.discover_services_by_uuid(uuid);
+        while let Some(svc) = services.next().await {
+            let svc = svc?;
+            result.push(svc.clone()).map_err(|_| Error::InsufficientSpace)?;
+            self.known_services
+                .borrow_mut()
+                .push(svc)
+                .map_err(|_| Error::InsufficientSpace)?;
+        }
+        Ok(result)
+    }
+
+    /// Discover all primary services of the peer.
+    ///
+    /// Services are returned one at a time as the responses from the peer arrive.
+    pub fn discover_services(&self) -> ServiceDiscovery<'_, 'reference, C, P, MAX_SERVICES> {
+        ServiceDiscovery {
+            client: self,
+            uuid: None,
+            state: DiscoveryState::new(0x0001, 0xffff),
+        }
+    }
+
+    /// Discover primary services associated with a UUID.
+    ///
+    /// Services are returned one at a time as the responses from the peer arrive.
+    pub fn discover_services_by_uuid(&self, uuid: &Uuid) -> ServiceDiscovery<'_, 'reference, C, P, MAX_SERVICES> {
+        ServiceDiscovery {
+            client: self,
+            uuid: Some(uuid.clone()),
+            state: DiscoveryState::new(0x0001, 0xffff),
+        }
+    }
+
+    /// Discover all characteristics of a service.
+    ///
+    /// Characteristics are returned one at a time as the responses from the peer arrive.
+    pub fn discover_characteristics(
+        &self,
+        service: &ServiceHandle,
+    ) -> CharacteristicDiscovery<'_, 'reference, C, P, MAX_SERVICES> {
+        CharacteristicDiscovery {
+            client: self,
+            state: DiscoveryState::new(service.start, service.end),
+        }
+    }
 
-            response_channel: Channel::new(),
+    /// Discover all descriptors in a range of handles.
+    ///
+    /// The descriptors of a characteristic are found between the handle following its value handle and the
+    /// handle before the next characteristic declaration, or the end of the service. Descriptors are returned
+    /// one at a time as the responses from the peer arrive.
+    pub fn discover_descriptors(
+        &self,
+        start: u16,
+        end: u16,
+    ) -> DescriptorDiscovery<'_, 'reference, C, P, MAX_SERVICES> {
+        DescriptorDiscovery {
+            client: self,
+            state: DiscoveryState::new(start, end),
+        }
+    }
 
-            notifications: PubSubChannel::new(),
+    /// Discover the descriptors of a characteristic.
+    ///
+    /// `characteristic` is one found in `service` by [`discover_characteristics`](Self::discover_characteristics).
+    /// The end of the characteristic is found by looking for the next characteristic declaration in `service`,
+    /// after which descriptors are returned one at a time as the responses from the peer arrive.
+    pub async fn discover_characteristic_descriptors(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> Result<DescriptorDiscovery<'_, 'reference, C, P, MAX_SERVICES>, BleHostError<C::Error>> {
+        let end = self.characteristic_end(service, characteristic).await?;
+        Ok(DescriptorDiscovery {
+            client: self,
+            state: DiscoveryState::new(characteristic.handle.saturating_add(1), end),
         })
     }
 
-    /// Discover primary services associated with a UUID.
-    pub async fn services_by_uuid(
+    /// Get a typed handle for a characteristic found by [`discover_characteristics`](Self::discover_characteristics).
+    ///
+    /// The handle carries the CCCD and extended properties of the characteristic, so it can be read, written and
+    /// subscribed to like one returned by [`characteristic_by_uuid`](Self::characteristic_by_uuid).
+    pub async fn characteristic<T: AsGatt>(
         &self,
-        uuid: &Uuid,
-    ) -> Result<Vec<ServiceHandle, MAX_SERVICES>, BleHostError<C::Error>> {
-        let mut start: u16 = 0x0001;
-        let mut result = Vec::new();
+        service: &ServiceHandle,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> Result<Characteristic<T>, BleHostError<C::Error>> {
+        if !Self::has_known_descriptors(characteristic.props) {
+            return Ok(Characteristic {
+                handle: characteristic.handle,
+                cccd_handle: None,
+                props: characteristic.props,
+                extended_props: None,
+                phantom: PhantomData,
+            });
+        }
+        let end = self.characteristic_end(service, characteristic).await?;
+        self.characteristic_with_descriptors(characteristic.handle, end, characteristic.props)
+            .await
+    }
+
+    /// Last handle of a characteristic, before the next characteristic declaration or the end of the service.
+    async fn characteristic_end(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &DiscoveredCharacteristic,
+    ) -> Result<u16, BleHostError<C::Error>> {
+        if characteristic.handle >= service.end {
+            return Ok(service.end);
+        }
+        let mut next = CharacteristicDiscovery {
+            client: self,
+            state: DiscoveryState::new(characteristic.handle + 1, service.end),
+        };
+        match next.next().await {
+            Some(Ok(next)) => Ok(next.declaration_handle - 1),
+            Some(Err(e)) => Err(e),
+            None => Ok(service.end),
+        }
+    }
+
+    /// Discover every service, characteristic and descriptor of the peer into `cache`.
+    ///
+    /// The values of Characteristic Extended Properties descriptors are read too, along with the Database Hash of
+    /// the peer if it has one. Store the cache with the bond of the peer to skip discovery on the next connection,
+    /// see [`check_discovery_cache`](Self::check_discovery_cache). Returns [`Error::InsufficientSpace`] if the
+    /// attribute database does not fit in the cache.
+    pub async fn discover_all<const N: usize>(
+        &self,
+        cache: &mut DiscoveryCache<N>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        cache.clear();
+        self.services_changed.set(false);
+        let database_hash = self.read_database_hash().await?;
+
+        {
+            let mut services = self.discover_services();
+            while let Some(service) = services.next().await {
+                cache.push(CachedAttribute::Service(service?))?;
+            }
+        }
 
+        let mut index = 0;
         loop {
-            let data = att::AttReq::FindByTypeValue {
-                start_handle: start,
-                end_handle: 0xffff,
-                att_type: PRIMARY_SERVICE.into(),
-                att_value: uuid.as_raw(),
+            let Some(service) = cache.services().nth(index) else {
+                break;
             };
+            index += 1;
 
-            let response = self.request(data).await?;
-            let res = Self::response(response.pdu.as_ref())?;
-            match res {
-                AttRsp::Error { request, handle, code } => {
-                    if code == att::AttErrorCode::ATTRIBUTE_NOT_FOUND {
-                        break;
-                    }
-                    return Err(Error::Att(code).into());
-                }
-                AttRsp::FindByTypeValue { mut it } => {
-                    let mut end: u16 = 0;
-                    while let Some(res) = it.next() {
-                        let (handle, e) = res?;
-                        end = e;
-                        let svc = ServiceHandle {
-                            start: handle,
-                            end,
-                            uuid: uuid.clone(),
-                        };
-                        result.push(svc.clone()).map_err(|_| Error::InsufficientSpace)?;
-                        self.known_services
-                            .borrow_mut()
-                            .push(svc)
-                            .map_err(|_| Error::InsufficientSpace)?;
-                    }
-                    if end == 0xFFFF {
-                        break;
-                    }
-                    start = end + 1;
+            {
+                let mut characteristics = self.discover_characteristics(&service);
+                while let Some(characteristic) = characteristics.next().await {
+                    cache.push(CachedAttribute::Characteristic(characteristic?))?;
                 }
-                res => {
-                    trace!("[gatt client] response: {:?}", res);
-                    return Err(Error::UnexpectedGattResponse.into());
+            }
+
+            // Find Information returns every attribute of the service, so skip the include declarations,
+            // characteristic declarations and values to keep the descriptors.
+            let mut descriptors = self.discover_descriptors(service.start.saturating_add(1), service.end);
+            while let Some(descriptor) = descriptors.next().await {
+                let descriptor = descriptor?;
+                let handle = descriptor.handle;
+                if handle == service.start
+                    || descriptor.uuid == INCLUDE.into()
+                    || cache
+                        .characteristics(&service)
+                        .any(|c| c.declaration_handle == handle || c.handle == handle)
+                {
+                    continue;
                 }
+                let attribute = if descriptor.uuid == CHARACTERISTIC_EXTENDED_PROPERTIES.into() {
+                    CachedAttribute::ExtendedProps(handle, self.read_extended_props(handle).await?)
+                } else {
+                    CachedAttribute::Descriptor(descriptor)
+                };
+                cache.push(attribute)?;
             }
         }
 
-        Ok(result)
+        cache.database_hash = database_hash;
+        self.service_changed_handle.set(cache.service_changed_handle());
+        Ok(())
+    }
+
+    /// Check whether a cache filled by [`discover_all`](Self::discover_all) on an earlier connection to this peer
+    /// can be used instead of discovering its attribute database again.
+    ///
+    /// The cache is up to date if the Database Hash of the peer did not change. A peer without a Database Hash
+    /// indicates changes to its database with Service Changed instead, so its cache is only accepted if the peer
+    /// is bonded and has a Service Changed characteristic. Once the peer indicates Service Changed, which a bonded
+    /// peer does when the link is encrypted again, the cache is no longer accepted, so check it before requesting
+    /// security. If `false` is returned, fill the cache again with `discover_all`.
+    pub async fn check_discovery_cache<const N: usize>(
+        &self,
+        cache: &DiscoveryCache<N>,
+    ) -> Result<bool, BleHostError<C::Error>> {
+        if cache.is_empty() {
+            return Ok(false);
+        }
+        let service_changed_handle = cache.service_changed_handle();
+        self.service_changed_handle.set(service_changed_handle);
+        if self.services_changed.get() {
+            return Ok(false);
+        }
+        match (self.read_database_hash().await?, cache.database_hash) {
+            (Some(hash), Some(cached)) => Ok(hash == cached),
+            (None, None) => Ok(service_changed_handle.is_some() && self.connection.is_bonded()),
+            _ => Ok(false),
+        }
+    }
+
+    /// Read the Database Hash of the peer, if it has one.
+    async fn read_database_hash(&self) -> Result<Option<[u8; 16]>, BleHostError<C::Error>> {
+        let data = att::AttReq::ReadByType {
+            start: 0x0001,
+            end: 0xffff,
+            attribute_type: DATABASE_HASH,
+        };
+        let response = self.request(data).await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadByType { mut it } => match it.next() {
+                Some(item) => {
+                    let (_handle, data) = item?;
+                    Ok(Some(data.try_into().map_err(|_| Error::InvalidValue)?))
+                }
+                None => Ok(None),
+            },
+            AttRsp::Error { request, handle, code } => match code {
+                att::AttErrorCode::ATTRIBUTE_NOT_FOUND => Ok(None),
+                _ => Err(Error::Att(code).into()),
+            },
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
     }
 
     /// Discover characteristics in a given service using a UUID.
+    ///
+    /// The returned characteristic carries the properties from its declaration and, if the
+    /// characteristic has extended properties, the value of its Extended Properties descriptor.
     pub async fn characteristic_by_uuid<T: AsGatt>(
         &self,
         service: &ServiceHandle,
         uuid: &Uuid,
     ) -> Result<Characteristic<T>, BleHostError<C::Error>> {
         let mut start: u16 = service.start;
-        let mut found_indicate_or_notify_uuid = Option::None;
+        let mut found: Option<(u16, CharacteristicProps)> = None;
 
         loop {
             let data = att::AttReq::ReadByType {
@@ -856,25 +3199,25 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                             uuid: decl_uuid,
                         } = AttributeData::decode_declaration(item)?
                         {
-                            if let Some(start_handle) = found_indicate_or_notify_uuid {
-                                return Ok(Characteristic {
-                                    handle: start_handle,
-                                    cccd_handle: Some(self.get_characteristic_cccd(start_handle, handle).await?),
-                                    phantom: PhantomData,
-                                });
+                            if let Some((start_handle, found_props)) = found {
+                                return self
+                                    .characteristic_with_descriptors(start_handle, handle, found_props)
+                                    .await;
                             }
 
                             if *uuid == decl_uuid {
-                                // If there are "notify" and "indicate" characteristic properties we need to find the
-                                // next characteristic so we can determine the search space for the CCCD
-                                if !props.any(&[CharacteristicProp::Indicate, CharacteristicProp::Notify]) {
+                                // If the characteristic has descriptors we care about, we need to find the
+                                // next characteristic so we can determine the search space for them
+                                if !Self::has_known_descriptors(props) {
                                     return Ok(Characteristic {
                                         handle,
                                         cccd_handle: None,
+                                        props,
+                                        extended_props: None,
                                         phantom: PhantomData,
                                     });
                                 }
-                                found_indicate_or_notify_uuid = Some(handle);
+                                found = Some((handle, props));
                             }
 
                             if handle == 0xFFFF {
@@ -887,13 +3230,9 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                     }
                 }
                 AttRsp::Error { request, handle, code } => match code {
-                    att::AttErrorCode::ATTRIBUTE_NOT_FOUND => match found_indicate_or_notify_uuid {
-                        Some(handle) => {
-                            return Ok(Characteristic {
-                                handle,
-                                cccd_handle: Some(self.get_characteristic_cccd(handle, service.end).await?),
-                                phantom: PhantomData,
-                            });
+                    att::AttErrorCode::ATTRIBUTE_NOT_FOUND => match found {
+                        Some((handle, props)) => {
+                            return self.characteristic_with_descriptors(handle, service.end, props).await;
                         }
                         None => return Err(Error::NotFound.into()),
                     },
@@ -904,14 +3243,64 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
-    async fn get_characteristic_cccd(
+    fn has_known_descriptors(props: CharacteristicProps) -> bool {
+        props.any(&[
+            CharacteristicProp::Indicate,
+            CharacteristicProp::Notify,
+            CharacteristicProp::Extended,
+        ])
+    }
+
+    async fn characteristic_with_descriptors<T: AsGatt>(
+        &self,
+        handle: u16,
+        end: u16,
+        props: CharacteristicProps,
+    ) -> Result<Characteristic<T>, BleHostError<C::Error>> {
+        let (cccd_handle, extended_handle) = self.get_characteristic_descriptors(handle, end).await?;
+        if props.any(&[CharacteristicProp::Indicate, CharacteristicProp::Notify]) && cccd_handle.is_none() {
+            return Err(Error::NotFound.into());
+        }
+
+        let extended_props = match extended_handle {
+            Some(extended_handle) if props.contains(CharacteristicProp::Extended) => {
+                Some(self.read_extended_props(extended_handle).await?)
+            }
+            _ => None,
+        };
+
+        Ok(Characteristic {
+            handle,
+            cccd_handle,
+            props,
+            extended_props,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Read the value of a Characteristic Extended Properties descriptor.
+    async fn read_extended_props(&self, handle: u16) -> Result<CharacteristicExtendedProps, BleHostError<C::Error>> {
+        let response = self.request(att::AttReq::Read { handle }).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::Read { data } => {
+                Ok(<CharacteristicExtendedProps as FromGatt>::from_gatt(data).map_err(|_| Error::InvalidValue)?)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Find the CCCD and Extended Properties descriptor handles of a characteristic.
+    async fn get_characteristic_descriptors(
         &self,
         char_start_handle: u16,
         char_end_handle: u16,
-    ) -> Result<u16, BleHostError<C::Error>> {
+    ) -> Result<(Option<u16>, Option<u16>), BleHostError<C::Error>> {
         let mut start_handle = char_start_handle;
+        let mut cccd = None;
+        let mut extended = None;
 
-        while start_handle <= char_end_handle {
+        while start_handle <= char_end_handle && (cccd.is_none() || extended.is_none()) {
             let data = att::AttReq::FindInformation {
                 start_handle,
                 end_handle: char_end_handle,
@@ -921,18 +3310,31 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
 
             match Self::response(response.pdu.as_ref())? {
                 AttRsp::FindInformation { mut it } => {
+                    let mut last = None;
                     while let Some(Ok((handle, uuid))) = it.next() {
                         if uuid == CLIENT_CHARACTERISTIC_CONFIGURATION.into() {
-                            return Ok(handle);
+                            cccd.get_or_insert(handle);
+                        } else if uuid == CHARACTERISTIC_EXTENDED_PROPERTIES.into() {
+                            extended.get_or_insert(handle);
                         }
-                        start_handle = handle + 1;
+                        last = Some(handle);
+                    }
+                    match last {
+                        Some(handle) if handle >= start_handle && handle < 0xFFFF => start_handle = handle + 1,
+                        // A response without descriptors past the start would be requested again forever.
+                        _ => break,
+                    }
+                }
+                AttRsp::Error { request, handle, code } => {
+                    if code == att::AttErrorCode::ATTRIBUTE_NOT_FOUND {
+                        break;
                     }
+                    return Err(Error::Att(code).into());
                 }
-                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
                 _ => return Err(Error::UnexpectedGattResponse.into()),
             }
         }
-        Err(Error::NotFound.into())
+        Ok((cccd, extended))
     }
 
     /// Read a characteristic described by a handle.
@@ -1059,6 +3461,64 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
+    /// Read the values of several characteristics with one Read Multiple request.
+    ///
+    /// The values are copied one after the other to `dest`, returning the total length. As the
+    /// response does not delimit them, this is only useful for fixed-size values; the last value is
+    /// truncated if the values do not fit in the ATT MTU.
+    pub async fn read_multiple(&self, handles: &[u16], dest: &mut [u8]) -> Result<usize, BleHostError<C::Error>> {
+        let mut raw = P::allocate().ok_or(Error::OutOfMemory)?;
+        let handles = encode_handles(handles, raw.as_mut())?;
+        let response = self.request(att::AttReq::ReadMultiple { handles }).await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultiple { data } => {
+                let to_copy = data.len().min(dest.len());
+                dest[..to_copy].copy_from_slice(&data[..to_copy]);
+                Ok(to_copy)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Read the values of several characteristics with one Read Multiple Variable Length request.
+    ///
+    /// The values are copied one after the other to `dest`, and the length copied of each value is
+    /// written to `lengths`, which must have an entry for each handle. Returns the total length. A value
+    /// that does not fit in the ATT MTU or in `dest` is truncated, and the ones after it are empty.
+    pub async fn read_multiple_variable(
+        &self,
+        handles: &[u16],
+        dest: &mut [u8],
+        lengths: &mut [usize],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        if lengths.len() < handles.len() {
+            return Err(Error::InsufficientSpace.into());
+        }
+        let mut raw = P::allocate().ok_or(Error::OutOfMemory)?;
+        let encoded = encode_handles(handles, raw.as_mut())?;
+        let response = self
+            .request(att::AttReq::ReadMultipleVariable { handles: encoded })
+            .await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultipleVariable { tuples } => {
+                lengths[..handles.len()].fill(0);
+                let mut offset = 0;
+                for (value, length) in att::LengthValueTuples::new(tuples).zip(lengths.iter_mut()) {
+                    let to_copy = value.len().min(dest.len() - offset);
+                    dest[offset..offset + to_copy].copy_from_slice(&value[..to_copy]);
+                    offset += to_copy;
+                    *length = to_copy;
+                }
+                Ok(offset)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
     /// Write to a characteristic described by a handle.
     pub async fn write_characteristic<T: FromGatt>(
         &self,
@@ -1078,65 +3538,378 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
-    /// Write without waiting for a response to a characteristic described by a handle.
-    pub async fn write_characteristic_without_response<T: FromGatt>(
+    /// Write a value of any length to a characteristic described by a handle.
+    ///
+    /// Values that fit in a Write Request are written with one. Longer values are queued on the peer in parts with
+    /// Prepare Write requests and then written at once with an Execute Write request. Each part echoed by the peer
+    /// is checked, and the queued parts are cancelled if one does not match.
+    pub async fn write_characteristic_long<T: FromGatt>(
+        &self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let att_mtu = self.connection.att_mtu() as usize;
+        // The opcode and handle take 3 bytes of a Write Request, and the offset 2 more for a Prepare Write.
+        if buf.len() <= att_mtu - 3 {
+            return self.write_characteristic(handle, buf).await;
+        }
+
+        let _guard = self.prepare_queue.lock().await;
+        self.discard_prepared_writes().await?;
+        self.prepare_write(handle.handle, buf).await?;
+        self.execute_write(true).await
+    }
+
+    /// Start a reliable write of one or more characteristics.
+    ///
+    /// Values are queued on the peer with [`ReliableWrite::queue_write`] and written together by
+    /// [`ReliableWrite::commit`], so either all of them are written or none are. A reliable write that is still
+    /// open is ended, and its values are discarded.
+    pub async fn begin_reliable_write(
+        &self,
+    ) -> Result<ReliableWrite<'_, 'reference, C, P, MAX_SERVICES>, BleHostError<C::Error>> {
+        let _guard = self.prepare_queue.lock().await;
+        self.discard_prepared_writes().await?;
+        let generation = self.prepare_generation.get().wrapping_add(1);
+        self.prepare_generation.set(generation);
+        Ok(ReliableWrite {
+            client: self,
+            generation,
+            failed: false,
+        })
+    }
+
+    /// Queue `value` on the peer with Prepare Write requests, checking each part echoed by the peer.
+    ///
+    /// On any error, including a failed request or a response that cannot be decoded, the parts queued so far
+    /// are cancelled with an Execute Write. The original error is returned, being more useful than one from
+    /// cancelling.
+    async fn prepare_write(&self, handle: u16, value: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        let result = self.prepare_parts(handle, value).await;
+        if result.is_err() && self.prepared_writes.get() {
+            let _ = self.execute_write(false).await;
+        }
+        result
+    }
+
+    async fn prepare_parts(&self, handle: u16, value: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        if value.len() > u16::MAX as usize {
+            return Err(Error::InsufficientSpace.into());
+        }
+        // The opcode, handle and offset take 5 bytes of the ATT MTU.
+        let part_len = self.connection.att_mtu() as usize - 5;
+        let parts = value.len().div_ceil(part_len).max(1);
+        for i in 0..parts {
+            let start = i * part_len;
+            let part = &value[start..value.len().min(start + part_len)];
+            let offset = start as u16;
+            self.prepared_writes.set(true);
+            let response = self
+                .request(att::AttReq::PrepareWrite {
+                    handle,
+                    offset,
+                    value: part,
+                })
+                .await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::PrepareWrite {
+                    handle: echoed,
+                    offset: echoed_offset,
+                    value,
+                } if echoed == handle && echoed_offset == offset && value == part => {}
+                AttRsp::PrepareWrite { .. } => return Err(Error::InvalidValue.into()),
+                AttRsp::Error { code, .. } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Discard values left queued on the peer by a [`ReliableWrite`] that was dropped without finishing, or is
+    /// still open.
+    async fn discard_prepared_writes(&self) -> Result<(), BleHostError<C::Error>> {
+        if self.prepared_writes.get() {
+            self.prepare_generation
+                .set(self.prepare_generation.get().wrapping_add(1));
+            self.execute_write(false).await?;
+        }
+        Ok(())
+    }
+
+    /// Write the prepared values queued on the peer, or discard them if `commit` is not set.
+    async fn execute_write(&self, commit: bool) -> Result<(), BleHostError<C::Error>> {
+        let response = self.request(att::AttReq::ExecuteWrite { flags: commit as u8 }).await?;
+        self.prepared_writes.set(false);
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ExecuteWrite => Ok(()),
+            AttRsp::Error { code, .. } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Write without waiting for a response to a characteristic described by a handle.
+    pub async fn write_characteristic_without_response<T: FromGatt>(
+        &self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let data = att::AttCmd::Write {
+            handle: handle.handle,
+            data: buf,
+        };
+
+        self.command(data).await?;
+
+        Ok(())
+    }
+
+    /// Limit the number of Write Commands sent by [`write_characteristic_paced`](Self::write_characteristic_paced)
+    /// that may be in flight at once.
+    ///
+    /// Clamped to between 1 and 8; the default is 1.
+    pub fn set_write_command_limit(&self, limit: usize) {
+        self.write_command_limit.set(limit.clamp(1, WRITE_COMMAND_WINDOW));
+    }
+
+    /// Write without response to a characteristic described by a handle, paced by the controller.
+    ///
+    /// Waits until fewer paced writes than the limit set with
+    /// [`set_write_command_limit`](Self::set_write_command_limit) are in flight, and for an ACL buffer of the
+    /// controller, before sending the Write Command. The returned [`WriteCommandCompletion`] resolves once the
+    /// controller reports the write as completed, so senders neither overrun the controller's buffers nor have to
+    /// guess at delays.
+    pub async fn write_characteristic_paced<T: FromGatt>(
+        &self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<WriteCommandCompletion<'_, 'reference, P>, BleHostError<C::Error>> {
+        self.reserve_write_command().await?;
+
+        let data = Att::Client(AttClient::Command(AttCmd::Write {
+            handle: handle.handle,
+            data: buf,
+        }));
+        let header = L2capHeader {
+            channel: crate::types::l2cap::L2CAP_CID_ATT,
+            length: data.size() as u16,
+        };
+        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut w = WriteCursor::new(tx.as_mut());
+        w.write_hci(&header)?;
+        w.write(data)?;
+        let len = w.len();
+        self.stack
+            .host
+            .l2cap(self.connection.handle(), header.length, 1)
+            .await?
+            .send(&tx.as_ref()[..len])
+            .await?;
+
+        let target = self.connection.tx_target();
+        if self.write_commands.borrow_mut().push_back(target).is_err() {
+            warn!("[gatt] too many paced writes in flight");
+        }
+        Ok(WriteCommandCompletion {
+            connection: &self.connection,
+            target,
+        })
+    }
+
+    /// Wait until fewer paced writes than the limit are in flight, dropping the ones the link has completed.
+    async fn reserve_write_command(&self) -> Result<(), Error> {
+        loop {
+            let oldest = {
+                let in_flight = self.write_commands.borrow();
+                if in_flight.len() < self.write_command_limit.get() {
+                    return Ok(());
+                }
+                in_flight.front().copied()
+            };
+            if let Some(target) = oldest {
+                self.connection.tx_completed(target).await?;
+                // Another writer waiting on the same write may have dropped it already.
+                let mut in_flight = self.write_commands.borrow_mut();
+                if in_flight.front() == Some(&target) {
+                    in_flight.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Subscribe to indication/notification of a given Characteristic
+    ///
+    /// A listener is returned, which has a `next()` method
+    pub async fn subscribe<T: AsGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        indication: bool,
+    ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
+        let subscription = Subscription {
+            handle: characteristic.handle,
+            cccd_handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
+            indication,
+            uuids: None,
+        };
+        self.enable_subscription(subscription).await
+    }
+
+    /// Discover a characteristic by service and characteristic UUID, and subscribe to it.
+    ///
+    /// Unlike [`subscribe`](Self::subscribe), the UUIDs are remembered with the subscription so that
+    /// [`resubscribe_all`](Self::resubscribe_all) can find the characteristic again if its handles changed.
+    pub async fn subscribe_by_uuid(
+        &self,
+        service: &Uuid,
+        characteristic: &Uuid,
+        indication: bool,
+    ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
+        let (handle, cccd_handle) = self.find_subscribable(service, characteristic).await?;
+        let subscription = Subscription {
+            handle,
+            cccd_handle,
+            indication,
+            uuids: Some((service.clone(), characteristic.clone())),
+        };
+        self.enable_subscription(subscription).await
+    }
+
+    /// Subscribe to indication/notification of a given Characteristic, receiving decoded values.
+    ///
+    /// The returned subscription decodes each value into `T`. Dropping it unsubscribes: the CCCD is cleared with
+    /// a Write Request sent by [`task`](Self::task). Use [`ValueSubscription::unsubscribe`] to wait for the peer
+    /// to confirm instead.
+    pub async fn subscribe_values<T: FromGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        indication: bool,
+    ) -> Result<ValueSubscription<'_, 'reference, C, P, MAX_SERVICES, T>, BleHostError<C::Error>> {
+        let listener = self.subscribe(characteristic, indication).await?;
+        Ok(ValueSubscription {
+            client: self,
+            listener,
+            handle: characteristic.handle,
+            cccd_handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Unsubscribe from a given Characteristic
+    pub async fn unsubscribe<T: AsGatt>(
         &self,
-        handle: &Characteristic<T>,
-        buf: &[u8],
+        characteristic: &Characteristic<T>,
     ) -> Result<(), BleHostError<C::Error>> {
-        let data = att::AttCmd::Write {
-            handle: handle.handle,
-            data: buf,
-        };
-
-        self.command(data).await?;
-
+        self.write_cccd(characteristic.cccd_handle.ok_or(Error::NotSupported)?, 0)
+            .await?;
+        self.forget_subscription(characteristic.handle);
         Ok(())
     }
 
-    /// Subscribe to indication/notification of a given Characteristic
+    fn forget_subscription(&self, handle: u16) {
+        self.subscriptions.borrow_mut().retain(|s| s.handle != handle);
+    }
+
+    /// Create a listener for notifications or indications of a subscribed characteristic.
     ///
-    /// A listener is returned, which has a `next()` method
-    pub async fn subscribe<T: AsGatt>(
+    /// Used to receive values again after restoring subscriptions with [`resubscribe_all`](Self::resubscribe_all).
+    pub fn listen(&self, subscription: &Subscription) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
+        self.listen_with_config(subscription, NotificationQueueConfig::default())
+    }
+
+    /// Create a listener like [`listen`](Self::listen), with the given queue configuration.
+    pub fn listen_with_config(
         &self,
-        characteristic: &Characteristic<T>,
-        indication: bool,
+        subscription: &Subscription,
+        config: NotificationQueueConfig,
     ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
-        let properties = u16::to_le_bytes(if indication { 0x02 } else { 0x01 });
+        let queue = self
+            .notifications
+            .iter()
+            .find(|queue| queue.handle.get().is_none())
+            .ok_or(Error::GattSubscriberLimitReached)?;
+        queue.handle.set(Some(subscription.handle));
+        let mut listener = NotificationListener { queue };
+        listener.set_queue_config(config);
+        Ok(listener)
+    }
 
-        let data = att::AttReq::Write {
-            handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
-            data: &properties,
-        };
+    /// The characteristics currently subscribed to on this connection.
+    ///
+    /// Store them with the bond of the peer to restore them on the next connection.
+    pub fn subscriptions(&self) -> Vec<Subscription, MAX_NOTIF> {
+        self.subscriptions.borrow().clone()
+    }
 
-        // set the CCCD
-        let response = self.request(data).await?;
+    /// Replace the remembered subscriptions, typically with ones stored for a bonded peer.
+    ///
+    /// This only updates the client's bookkeeping; call [`resubscribe_all`](Self::resubscribe_all) to
+    /// write the CCCDs on the peer.
+    pub fn restore_subscriptions(&self, subscriptions: &[Subscription]) -> Result<(), Error> {
+        let restored = Vec::from_slice(subscriptions).map_err(|_| Error::InsufficientSpace)?;
+        self.subscriptions.replace(restored);
+        Ok(())
+    }
 
-        match Self::response(response.pdu.as_ref())? {
-            AttRsp::Write => match self.notifications.dyn_subscriber() {
-                Ok(listener) => Ok(NotificationListener {
-                    listener,
-                    handle: characteristic.handle,
-                }),
-                Err(embassy_sync::pubsub::Error::MaximumSubscribersReached) => {
-                    Err(Error::GattSubscriberLimitReached.into())
-                }
-                Err(_) => Err(Error::Other.into()),
-            },
-            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
-            _ => Err(Error::UnexpectedGattResponse.into()),
+    /// Write the CCCDs of all remembered subscriptions again, so notification streams resume after reconnecting.
+    ///
+    /// A bonded peer keeps CCCD values across connections, but a peer that lost them, or a peer that is not
+    /// bonded, needs them to be written again. If `rediscover` is set, the handles of subscriptions made with
+    /// [`subscribe_by_uuid`](Self::subscribe_by_uuid) are discovered again first, for when the peer's attribute
+    /// database changed; updated handles are reflected in [`subscriptions`](Self::subscriptions).
+    pub async fn resubscribe_all(&self, rediscover: bool) -> Result<(), BleHostError<C::Error>> {
+        for original in self.subscriptions() {
+            let mut subscription = original.clone();
+            if let (true, Some((service, characteristic))) = (rediscover, &subscription.uuids) {
+                let (handle, cccd_handle) = self.find_subscribable(service, characteristic).await?;
+                subscription.handle = handle;
+                subscription.cccd_handle = cccd_handle;
+            }
+            self.write_cccd(subscription.cccd_handle, subscription.cccd_value())
+                .await?;
+            // Subscriptions may have been added or removed while waiting for the peer.
+            if let Some(existing) = self.subscriptions.borrow_mut().iter_mut().find(|s| **s == original) {
+                *existing = subscription;
+            }
         }
+        Ok(())
     }
 
-    /// Unsubscribe from a given Characteristic
-    pub async fn unsubscribe<T: AsGatt>(
+    async fn enable_subscription(
         &self,
-        characteristic: &Characteristic<T>,
-    ) -> Result<(), BleHostError<C::Error>> {
-        let properties = u16::to_le_bytes(0);
+        subscription: Subscription,
+    ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
+        self.write_cccd(subscription.cccd_handle, subscription.cccd_value())
+            .await?;
+        let listener = self.listen(&subscription)?;
+
+        let mut subscriptions = self.subscriptions.borrow_mut();
+        if let Some(existing) = subscriptions.iter_mut().find(|s| s.handle == subscription.handle) {
+            let uuids = subscription.uuids.clone().or(existing.uuids.take());
+            *existing = Subscription { uuids, ..subscription };
+        } else if subscriptions.push(subscription).is_err() {
+            warn!("[gatt] too many subscriptions, not remembering subscription");
+        }
+        Ok(listener)
+    }
+
+    async fn find_subscribable(
+        &self,
+        service: &Uuid,
+        characteristic: &Uuid,
+    ) -> Result<(u16, u16), BleHostError<C::Error>> {
+        let services = self.services_by_uuid(service).await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        let characteristic: Characteristic<&'static [u8]> =
+            self.characteristic_by_uuid(service, characteristic).await?;
+        Ok((
+            characteristic.handle,
+            characteristic.cccd_handle.ok_or(Error::NotSupported)?,
+        ))
+    }
+
+    async fn write_cccd(&self, cccd_handle: u16, value: u16) -> Result<(), BleHostError<C::Error>> {
         let data = att::AttReq::Write {
-            handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
-            data: &[0, 0],
+            handle: cccd_handle,
+            data: &value.to_le_bytes(),
         };
 
         // set the CCCD
@@ -1153,10 +3926,30 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
     async fn handle_notification_packet(&self, data: &[u8]) -> Result<(), BleHostError<C::Error>> {
         let mut r = ReadCursor::new(data);
         let value_handle: u16 = r.read()?;
-        let value_attr = r.remaining();
+        self.publish_notification(value_handle, r.remaining());
+        Ok(())
+    }
+
+    /// Handle an indication that was received, confirming it to the peer.
+    async fn handle_indication_packet(&self, data: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        let mut r = ReadCursor::new(data);
+        let value_handle: u16 = r.read()?;
+        if self.service_changed_handle.get() == Some(value_handle) {
+            self.services_changed.set(true);
+        }
+        self.publish_notification(value_handle, r.remaining());
+        self.send_att_data(Att::Client(AttClient::Confirmation(att::AttCfm::ConfirmIndication)))
+            .await
+    }
 
-        let handle = value_handle;
+    /// Handle a multiple handle value notification that was received.
+    fn handle_multiple_notification_packet(&self, data: &[u8]) {
+        for (handle, value) in att::HandleValueTuples::new(data) {
+            self.publish_notification(handle, value);
+        }
+    }
 
+    fn publish_notification(&self, handle: u16, value_attr: &[u8]) {
         // TODO
         let mut data = [0u8; 512];
         let to_copy = data.len().min(value_attr.len());
@@ -1166,18 +3959,58 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
             data,
             len: to_copy,
         };
-        self.notifications.immediate_publisher().publish_immediate(n);
-        Ok(())
+        for queue in self
+            .notifications
+            .iter()
+            .filter(|queue| queue.handle.get() == Some(handle))
+        {
+            queue.push(n.clone());
+        }
+    }
+
+    /// Wait for the CCCD of a dropped subscription to clear, and for the turn to send a request.
+    async fn next_unsubscribe(&self) -> (u16, MutexGuard<'_, NoopRawMutex, ()>) {
+        let cccd_handle = self.pending_unsubscribes.receive().await;
+        (cccd_handle, self.requests.lock().await)
     }
 
-    /// Task which handles GATT rx data (needed for notifications to work)
+    /// Task which handles GATT rx data (needed for notifications and indications to work)
     pub async fn task(&self) -> Result<(), BleHostError<C::Error>> {
+        // The turn to send requests, held by the task until the peer answers the unsubscribe it sent.
+        let mut unsubscribing = None;
+        // Kept across iterations, so a CCCD taken from the queue is not lost when a PDU arrives first.
+        let mut unsubscribe = pin!(self.next_unsubscribe());
         loop {
-            let (handle, pdu) = self.rx.receive().await;
+            let (handle, pdu) = match select(self.rx.receive(), unsubscribe.as_mut()).await {
+                Either::First(received) => received,
+                Either::Second((cccd_handle, guard)) => {
+                    let req = AttReq::Write {
+                        handle: cccd_handle,
+                        data: &0u16.to_le_bytes(),
+                    };
+                    match self.send_att_data(Att::Client(AttClient::Request(req))).await {
+                        Ok(()) => unsubscribing = Some(guard),
+                        Err(e) => warn!("[gatt] failed to unsubscribe: {:?}", e),
+                    }
+                    unsubscribe.set(self.next_unsubscribe());
+                    continue;
+                }
+            };
             let data = pdu.as_ref();
             // handle notifications
             if pdu.as_ref()[0] == ATT_HANDLE_VALUE_NTF {
                 self.handle_notification_packet(&pdu.as_ref()[1..]).await?;
+            } else if pdu.as_ref()[0] == att::ATT_HANDLE_VALUE_IND {
+                self.handle_indication_packet(&pdu.as_ref()[1..]).await?;
+            } else if pdu.as_ref()[0] == att::ATT_MULTIPLE_HANDLE_VALUE_NTF {
+                self.handle_multiple_notification_packet(&pdu.as_ref()[1..]);
+            } else if unsubscribing.take().is_some() {
+                // No other request is sent before the unsubscribe is answered, so this is its response.
+                match Self::response(pdu.as_ref()) {
+                    Ok(AttRsp::Write) => {}
+                    Ok(AttRsp::Error { code, .. }) => warn!("[gatt] failed to unsubscribe: {:?}", code),
+                    _ => warn!("[gatt] unexpected response to unsubscribe"),
+                }
             } else {
                 self.response_channel.send((handle, pdu)).await;
             }
@@ -1192,3 +4025,1554 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, LeConnRole};
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::att::{
+        ATT_ERROR_RSP, ATT_EXECUTE_WRITE_REQ, ATT_EXECUTE_WRITE_RSP, ATT_FIND_INFORMATION_REQ,
+        ATT_FIND_INFORMATION_RSP, ATT_HANDLE_VALUE_CMF, ATT_HANDLE_VALUE_IND, ATT_HANDLE_VALUE_NTF,
+        ATT_PREPARE_WRITE_REQ, ATT_PREPARE_WRITE_RSP, ATT_READ_BY_GROUP_TYPE_REQ, ATT_READ_BY_GROUP_TYPE_RSP,
+        ATT_READ_BY_TYPE_REQ, ATT_READ_BY_TYPE_RSP, ATT_READ_REQ, ATT_READ_RSP, ATT_WRITE_REQ, ATT_WRITE_RSP,
+    };
+    use crate::attribute::{AttributeTable, Service};
+    use crate::connection_manager::tests::{setup, ADDR_1};
+    use crate::connection_manager::ConnectionManager;
+    use crate::mock_controller::MockController;
+    use crate::prelude::*;
+
+    const HANDLE: u16 = 3;
+
+    fn att_pdu(data: &[u8]) -> Pdu<<DefaultPacketPool as PacketPool>::Packet> {
+        let mut packet = unwrap!(DefaultPacketPool::allocate());
+        packet.as_mut()[..data.len()].copy_from_slice(data);
+        Pdu::new(packet, data.len())
+    }
+
+    fn connect(mgr: &'static ConnectionManager<'static, DefaultPacketPool>) -> Connection<'static, DefaultPacketPool> {
+        unwrap!(mgr.connect(
+            ConnHandle::new(HANDLE),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        conn
+    }
+
+    type TestClient = GattClient<'static, MockController, DefaultPacketPool, 4>;
+
+    /// Create a client on a central connection, and take the Exchange MTU request it sends off the queue.
+    fn client() -> (&'static ConnectionManager<'static, DefaultPacketPool>, TestClient) {
+        let resources: &'static mut HostResources<DefaultPacketPool, 2, 2> = Box::leak(Box::new(HostResources::new()));
+        let stack: &'static Stack<'static, MockController, DefaultPacketPool> =
+            Box::leak(Box::new(crate::new(MockController::new(), resources)));
+        let mgr = &stack.host.connections;
+        unwrap!(mgr.connect(
+            ConnHandle::new(HANDLE),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let client = unwrap!(block_on(GattClient::new(stack, &conn)));
+        block_on(mgr.outbound());
+        (mgr, client)
+    }
+
+    /// Pass a response from the peer to a client, as its task would.
+    fn respond(client: &TestClient, data: &[u8]) {
+        let pdu = att_pdu(data);
+        block_on(client.response_channel.send((ConnHandle::new(HANDLE), pdu)));
+    }
+
+    /// Write the CCCD of a characteristic as the client would, and process the write.
+    fn write_cccd(
+        mgr: &ConnectionManager<'_, DefaultPacketPool>,
+        gatt: &GattConnection<'_, '_, DefaultPacketPool>,
+        cccd_handle: u16,
+        value: u16,
+    ) {
+        let [handle_lo, handle_hi] = cccd_handle.to_le_bytes();
+        let [value_lo, value_hi] = value.to_le_bytes();
+        unwrap!(mgr.post_gatt(
+            ConnHandle::new(HANDLE),
+            att_pdu(&[ATT_WRITE_REQ, handle_lo, handle_hi, value_lo, value_hi])
+        ));
+        block_on(async {
+            let GattConnectionEvent::Gatt { event } = gatt.next().await else {
+                panic!("expected a GATT event");
+            };
+            unwrap!(event.accept()).send().await;
+        });
+    }
+
+    #[test]
+    fn indication_confirmation() {
+        let mgr = setup();
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Indicate], 0u8, &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        write_cccd(mgr, &gatt, unwrap!(characteristic.cccd_handle), 0x02);
+        // The write response.
+        block_on(mgr.outbound());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let handle = characteristic.handle;
+        let [handle_lo, handle_hi] = handle.to_le_bytes();
+
+        let mut indication = pin!(gatt.indicate_raw(handle, &[1]));
+        assert!(indication.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_IND, handle_lo, handle_hi, 1]);
+
+        // The confirmation completes the indication, and is passed on to the application.
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_HANDLE_VALUE_CMF])));
+        assert!(matches!(
+            block_on(gatt.next()),
+            GattConnectionEvent::Gatt {
+                event: GattEvent::Other(_)
+            }
+        ));
+        assert_eq!(indication.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        // The indication of a cancelled call is still outstanding, and holds back the next one.
+        {
+            let mut cancelled = pin!(gatt.indicate_raw(handle, &[2]));
+            assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        }
+        block_on(mgr.outbound());
+        let mut indication = pin!(gatt.indicate_raw(handle, &[3]));
+        let mut outbound = pin!(mgr.outbound());
+        assert!(indication.as_mut().poll(&mut cx).is_pending());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_HANDLE_VALUE_CMF])));
+        block_on(gatt.next());
+        assert!(indication.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(outbound.as_mut());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_IND, handle_lo, handle_hi, 3]);
+
+        // A disconnection ends the wait for the confirmation.
+        unwrap!(mgr.disconnected(ConnHandle::new(HANDLE), Status::UNSPECIFIED));
+        assert!(matches!(
+            block_on(gatt.next()),
+            GattConnectionEvent::Disconnected { .. }
+        ));
+        assert_eq!(indication.as_mut().poll(&mut cx), Poll::Ready(Err(Error::Disconnected)));
+    }
+
+    #[test]
+    fn notification_queue_run() {
+        let mgr = setup();
+        let mut stores = [[0u8; 1]; 2];
+        let [notify_store, read_store] = &mut stores;
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Notify], 0u8, notify_store)
+            .build();
+        let read_only = svc
+            .add_characteristic(0x2a1au16, &[CharacteristicProp::Read], 0u8, read_store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        write_cccd(mgr, &gatt, unwrap!(characteristic.cccd_handle), 0x01);
+        // The write response.
+        block_on(mgr.outbound());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let [handle_lo, handle_hi] = characteristic.handle.to_le_bytes();
+        let queue = CharacteristicNotificationQueue::<NoopRawMutex, u8, 4>::new(characteristic, Default::default());
+        unwrap!(queue.try_push(1));
+        unwrap!(queue.try_push(2));
+
+        // Queued values are notified in order, after which the queue waits for more.
+        {
+            let mut run = pin!(queue.run(&gatt));
+            for value in [1, 2] {
+                assert!(run.as_mut().poll(&mut cx).is_pending());
+                let (_, pdu) = block_on(mgr.outbound());
+                assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_NTF, handle_lo, handle_hi, value]);
+            }
+            assert!(run.as_mut().poll(&mut cx).is_pending());
+            assert!(queue.is_empty());
+            unwrap!(queue.try_push(3));
+            assert!(run.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_NTF, handle_lo, handle_hi, 3]);
+        }
+        assert!(queue.is_empty());
+
+        // A characteristic that cannot notify keeps its value.
+        assert_eq!(block_on(gatt.notify_raw(read_only.handle, &[9])), Err(Error::NotFound));
+        assert_eq!(
+            block_on(gatt.indicate_raw(read_only.handle, &[9])),
+            Err(Error::NotFound)
+        );
+        assert_eq!(unwrap!(read_only.get(&server)), 0);
+
+        // A value whose notification fails is not lost, and is tried again first by the next run.
+        let failing = CharacteristicNotificationQueue::<NoopRawMutex, u8, 4>::new(read_only, Default::default());
+        unwrap!(failing.try_push(5));
+        unwrap!(failing.try_push(6));
+        assert_eq!(block_on(failing.run(&gatt)), Err(Error::NotFound));
+        assert_eq!(failing.len(), 2);
+        assert_eq!(block_on(failing.run(&gatt)), Err(Error::NotFound));
+        assert_eq!(failing.len(), 2);
+        failing.clear();
+        assert!(failing.is_empty());
+    }
+
+    #[test]
+    fn read_event_reply_with_checks_access() {
+        /// Only allows reads of the secret characteristic on encrypted links.
+        struct NeedsEncryption(u16);
+
+        impl AttributeAuthorizer for NeedsEncryption {
+            fn authorize(&self, access: &AttributeAccess<'_>) -> Result<(), AttErrorCode> {
+                if access.handle == self.0 && !access.security_level.encrypted() {
+                    return Err(AttErrorCode::INSUFFICIENT_ENCRYPTION);
+                }
+                Ok(())
+            }
+        }
+
+        let mgr = setup();
+        let mut stores = [[0u8; 1]; 3];
+        let [public_store, secret_store, notify_store] = &mut stores;
+        let mut table: AttributeTable<'_, NoopRawMutex, 16> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let public = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Read], 0u8, public_store)
+            .build();
+        let secret = svc
+            .add_characteristic(0x2a1au16, &[CharacteristicProp::Read], 0u8, secret_store)
+            .build();
+        let notify_only = svc
+            .add_characteristic(0x2a1bu16, &[CharacteristicProp::Notify], 0u8, notify_store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 16, 2, 2> = AttributeServer::new(table);
+        let authorizer = NeedsEncryption(secret.handle);
+        server.set_authorizer(Some(&authorizer));
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+
+        let read = |handle: u16| {
+            let [lo, hi] = handle.to_le_bytes();
+            unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_READ_REQ, lo, hi])));
+            let GattConnectionEvent::Gatt {
+                event: GattEvent::Read(event),
+            } = block_on(gatt.next())
+            else {
+                panic!("expected a read event");
+            };
+            let mut called = false;
+            let reply = unwrap!(event.reply_with(|_, buf| {
+                called = true;
+                buf[..2].copy_from_slice(&[0xca, 0xfe]);
+                Ok(2)
+            }));
+            unwrap!(reply.try_send());
+            let (_, pdu) = block_on(mgr.outbound());
+            (called, std::vec::Vec::from(&pdu.as_ref()[4..]))
+        };
+
+        assert_eq!(read(public.handle), (true, std::vec![ATT_READ_RSP, 0xca, 0xfe]));
+
+        // The value is not produced for a client that may not read it.
+        let [lo, hi] = secret.handle.to_le_bytes();
+        assert_eq!(
+            read(secret.handle),
+            (false, std::vec![ATT_ERROR_RSP, ATT_READ_REQ, lo, hi, 0x0f])
+        );
+        let [lo, hi] = notify_only.handle.to_le_bytes();
+        assert_eq!(
+            read(notify_only.handle),
+            (false, std::vec![ATT_ERROR_RSP, ATT_READ_REQ, lo, hi, 0x02])
+        );
+    }
+
+    #[test]
+    fn service_changed_indication() {
+        let mgr = setup();
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 16> = AttributeTable::new();
+        unwrap!(GapConfig::default("trouble").build_with_service_changed(&mut table));
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Indicate], 0u8, &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 16, 2, 2> = AttributeServer::new(table);
+        let service_changed = unwrap!(server.service_changed_handle());
+        let service_changed_cccd = unwrap!(server
+            .table()
+            .find_characteristic_by_value_handle::<[u8; 4]>(service_changed))
+        .cccd_handle;
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        for cccd_handle in [service_changed_cccd, characteristic.cccd_handle] {
+            write_cccd(mgr, &gatt, unwrap!(cccd_handle), 0x02);
+            block_on(mgr.outbound());
+        }
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let [sc_lo, sc_hi] = service_changed.to_le_bytes();
+        unwrap!(server.service_changed(0x20, 0x2f));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[ATT_HANDLE_VALUE_IND, sc_lo, sc_hi, 0x20, 0, 0x2f, 0]
+        );
+
+        // Neither further changes nor other indications are sent before the confirmation.
+        unwrap!(server.service_changed(0x40, 0x4f));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let mut indication = pin!(gatt.indicate_raw(characteristic.handle, &[1]));
+        assert!(indication.as_mut().poll(&mut cx).is_pending());
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_HANDLE_VALUE_CMF])));
+        block_on(gatt.next());
+        assert!(indication.as_mut().poll(&mut cx).is_pending());
+        let [handle_lo, handle_hi] = characteristic.handle.to_le_bytes();
+        let (_, pdu) = block_on(outbound.as_mut());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_IND, handle_lo, handle_hi, 1]);
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_HANDLE_VALUE_CMF])));
+        block_on(gatt.next());
+        assert_eq!(indication.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[ATT_HANDLE_VALUE_IND, sc_lo, sc_hi, 0x40, 0, 0x4f, 0]
+        );
+    }
+
+    #[test]
+    fn notify_all_truncates_to_mtu() {
+        let mgr = setup();
+        let mut store = [0u8; 30];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Notify], [0u8; 30], &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        write_cccd(mgr, &gatt, unwrap!(characteristic.cccd_handle), 0x01);
+        block_on(mgr.outbound());
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let [handle_lo, handle_hi] = characteristic.handle.to_le_bytes();
+        let value: [u8; 30] = core::array::from_fn(|i| i as u8);
+
+        // The default ATT MTU of 23 leaves room for 20 bytes of the value.
+        unwrap!(characteristic.notify_all(&server, &value));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..7], &[ATT_HANDLE_VALUE_NTF, handle_lo, handle_hi]);
+        assert_eq!(&pdu.as_ref()[7..], &value[..20]);
+
+        // A larger MTU of the connection carries the whole value.
+        gatt.raw().set_att_mtu(64);
+        unwrap!(characteristic.notify_all(&server, &value));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[7..], &value[..]);
+    }
+
+    #[test]
+    fn try_notify_busy() {
+        let mgr = setup();
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Notify], 0u8, &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        write_cccd(mgr, &gatt, unwrap!(characteristic.cccd_handle), 0x01);
+        block_on(mgr.outbound());
+
+        // Fill the outbound queue, after which notifications are refused instead of waiting for room.
+        for value in 0..crate::config::L2CAP_TX_QUEUE_SIZE {
+            unwrap!(characteristic.try_notify(&gatt, &(value as u8)));
+        }
+        assert_eq!(characteristic.try_notify(&gatt, &0xaa), Err(Error::Busy));
+        // The value is updated even though it was not sent.
+        assert_eq!(unwrap!(characteristic.get(&server)), 0xaa);
+
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(pdu.as_ref()[7], 0);
+        unwrap!(characteristic.try_notify(&gatt, &0xbb));
+    }
+
+    #[test]
+    fn characteristic_updates_notify() {
+        let mgr = setup();
+        let mut stores = [[0u8; 1]; 4];
+        let [a_store, b_store, c_store, d_store] = &mut stores;
+        let mut table: AttributeTable<'_, NoopRawMutex, 16> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let props = [CharacteristicProp::Notify];
+        let a = svc.add_characteristic(0x2a19u16, &props, 0u8, a_store).build();
+        let b = svc.add_characteristic(0x2a1au16, &props, 0u8, b_store).build();
+        let c = svc
+            .add_characteristic(0x2a1bu16, &[CharacteristicProp::Indicate], 0u8, c_store)
+            .build();
+        let d = svc.add_characteristic(0x2a1cu16, &props, 0u8, d_store).build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 16, 4, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        for (characteristic, value) in [(&a, 0x01), (&b, 0x01), (&c, 0x02), (&d, 0x01)] {
+            write_cccd(mgr, &gatt, unwrap!(characteristic.cccd_handle), value);
+            block_on(mgr.outbound());
+        }
+
+        let values = [1u8, 2, 3, 4];
+        let mut updates: CharacteristicUpdates<'_, 4> = CharacteristicUpdates::new();
+        updates.set_multiple_notifications(true);
+        for (characteristic, value) in [&a, &b, &c, &d].into_iter().zip(values.iter()) {
+            unwrap!(updates.add(characteristic, value));
+        }
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut notify = pin!(updates.notify(&gatt));
+        assert!(notify.as_mut().poll(&mut cx).is_pending());
+
+        // Consecutive notifications share a PDU, up to the indication.
+        let [a_lo, a_hi] = a.handle.to_le_bytes();
+        let [b_lo, b_hi] = b.handle.to_le_bytes();
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[
+                att::ATT_MULTIPLE_HANDLE_VALUE_NTF,
+                a_lo,
+                a_hi,
+                1,
+                0,
+                1,
+                b_lo,
+                b_hi,
+                1,
+                0,
+                2
+            ]
+        );
+        let [c_lo, c_hi] = c.handle.to_le_bytes();
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_IND, c_lo, c_hi, 3]);
+
+        // The rest is only sent once the indication is confirmed, and a single value is a plain notification.
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_HANDLE_VALUE_CMF])));
+        block_on(gatt.next());
+        assert_eq!(notify.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        let [d_lo, d_hi] = d.handle.to_le_bytes();
+        let (_, pdu) = block_on(outbound.as_mut());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_NTF, d_lo, d_hi, 4]);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn next_dispatching_commits_accepted_writes() {
+        use crate::att::ATT_WRITE_CMD;
+
+        let mgr = setup();
+        let mut stores = [[0u8; 1]; 2];
+        let [writable_store, read_only_store] = &mut stores;
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let props = [CharacteristicProp::Write, CharacteristicProp::WriteWithoutResponse];
+        let writable = svc.add_characteristic(0x2a19u16, &props, 0u8, writable_store).build();
+        let read_only = svc
+            .add_characteristic(0x2a1au16, &[CharacteristicProp::Read], 0u8, read_only_store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        let writable_listener: WriteListener<NoopRawMutex, u8, 2> = WriteListener::new(&writable);
+        let read_only_listener: WriteListener<NoopRawMutex, u8, 2> = WriteListener::new(&read_only);
+        let listeners: [&dyn WriteDispatch; 2] = [&writable_listener, &read_only_listener];
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut write = |opcode: u8, handle: u16, data: &[u8]| {
+            let [handle_lo, handle_hi] = handle.to_le_bytes();
+            let mut pdu = std::vec![opcode, handle_lo, handle_hi];
+            pdu.extend_from_slice(data);
+            unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&pdu)));
+            assert!(pin!(gatt.next_dispatching(&listeners)).poll(&mut cx).is_pending());
+        };
+
+        // Accepted requests and commands are delivered to the listener.
+        write(ATT_WRITE_REQ, writable.handle, &[1]);
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_RSP]);
+        assert_eq!(writable_listener.try_on_write(), Some(1));
+        write(ATT_WRITE_CMD, writable.handle, &[2]);
+        assert_eq!(writable_listener.try_on_write(), Some(2));
+        assert_eq!(unwrap!(writable.get(&server)), 2);
+
+        // Values that do not decode are refused before they reach the server.
+        write(ATT_WRITE_REQ, writable.handle, &[3, 3]);
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(pdu.as_ref()[4], ATT_ERROR_RSP);
+        write(ATT_WRITE_CMD, writable.handle, &[4, 4]);
+        assert_eq!(writable_listener.try_on_write(), None);
+        assert_eq!(unwrap!(writable.get(&server)), 2);
+
+        // Writes the server refuses are not delivered either, including commands that get no response.
+        write(ATT_WRITE_REQ, read_only.handle, &[5]);
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(pdu.as_ref()[4], ATT_ERROR_RSP);
+        write(ATT_WRITE_CMD, read_only.handle, &[6]);
+        assert_eq!(read_only_listener.try_on_write(), None);
+        assert_eq!(unwrap!(read_only.get(&server)), 0);
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn macro_handlers_dispatch() {
+        use core::sync::atomic::{AtomicU16, Ordering};
+
+        static WRITTEN: AtomicU16 = AtomicU16::new(0);
+
+        #[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b1")]
+        struct HandlerService {
+            #[characteristic(uuid = "2a37", read, on_read = Self::read)]
+            level: u8,
+            #[characteristic(uuid = "2a38", write, on_write = Self::written)]
+            control: u16,
+        }
+
+        impl HandlerService {
+            fn read(&self) -> u8 {
+                42
+            }
+
+            fn written(&self, value: &u16) {
+                WRITTEN.store(*value, Ordering::Relaxed);
+            }
+        }
+
+        #[gatt_server]
+        struct HandlerServer {
+            handlers: HandlerService,
+        }
+
+        let mgr = setup();
+        let mut storage = HandlerServerStorage::new();
+        let server = unwrap!(HandlerServer::new_default("handlers", &mut storage));
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server.server));
+
+        // The read handler refreshes the value before it is read.
+        let [handle_lo, handle_hi] = server.handlers.level.handle.to_le_bytes();
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&[ATT_READ_REQ, handle_lo, handle_hi])));
+        let GattConnectionEvent::Gatt { event } = block_on(gatt.next()) else {
+            panic!("expected a GATT event");
+        };
+        assert!(unwrap!(server.dispatch(&event)));
+        block_on(unwrap!(event.accept()).send());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_READ_RSP, 42]);
+
+        // The write handler gets the decoded value.
+        let [handle_lo, handle_hi] = server.handlers.control.handle.to_le_bytes();
+        unwrap!(mgr.post_gatt(
+            ConnHandle::new(HANDLE),
+            att_pdu(&[ATT_WRITE_REQ, handle_lo, handle_hi, 0x34, 0x12])
+        ));
+        let GattConnectionEvent::Gatt { event } = block_on(gatt.next()) else {
+            panic!("expected a GATT event");
+        };
+        assert!(unwrap!(server.dispatch(&event)));
+        assert_eq!(WRITTEN.load(Ordering::Relaxed), 0x1234);
+        block_on(unwrap!(event.accept()).send());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_RSP]);
+    }
+
+    #[cfg(feature = "gatt-server-prepare-queue-size-512")]
+    #[test]
+    fn long_write_event() {
+        let mgr = setup();
+        let mut store = [0u8; 4];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Write], [0u8; 4], &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        let [lo, hi] = characteristic.handle.to_le_bytes();
+
+        let process = |data: &[u8]| {
+            unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(data)));
+            let GattConnectionEvent::Gatt { event } = block_on(gatt.next()) else {
+                panic!("expected a GATT event");
+            };
+            event
+        };
+        let respond = |event: GattEvent<'_, '_, DefaultPacketPool>| {
+            block_on(unwrap!(event.accept()).send());
+            let (_, pdu) = block_on(mgr.outbound());
+            pdu
+        };
+
+        // The parts of the value are returned as a single write when the client executes them.
+        for part in [
+            [att::ATT_PREPARE_WRITE_REQ, lo, hi, 0, 0, 1, 2],
+            [att::ATT_PREPARE_WRITE_REQ, lo, hi, 2, 0, 3, 4],
+        ] {
+            let event = process(&part);
+            assert!(matches!(event, GattEvent::Other(_)));
+            respond(event);
+        }
+        let GattEvent::Write(event) = process(&[att::ATT_EXECUTE_WRITE_REQ, 1]) else {
+            panic!("expected a write event");
+        };
+        assert_eq!(event.handle(), characteristic.handle);
+        assert_eq!(event.offset(), 0);
+        assert_eq!(event.data(), &[1, 2, 3, 4]);
+        let pdu = respond(GattEvent::Write(event));
+        assert_eq!(&pdu.as_ref()[4..], &[att::ATT_EXECUTE_WRITE_RSP]);
+        assert_eq!(unwrap!(server.table().get(&characteristic)), [1, 2, 3, 4]);
+
+        // The application can reject the write, which is then not executed.
+        respond(process(&[att::ATT_PREPARE_WRITE_REQ, lo, hi, 0, 0, 9]));
+        let GattEvent::Write(event) = process(&[att::ATT_EXECUTE_WRITE_REQ, 1]) else {
+            panic!("expected a write event");
+        };
+        block_on(unwrap!(event.reject(AttErrorCode::WRITE_NOT_PERMITTED)).send());
+        block_on(mgr.outbound());
+        let pdu = respond(process(&[att::ATT_EXECUTE_WRITE_REQ, 1]));
+        assert_eq!(&pdu.as_ref()[4..], &[att::ATT_EXECUTE_WRITE_RSP]);
+        assert_eq!(unwrap!(server.table().get(&characteristic)), [1, 2, 3, 4]);
+
+        // A value longer than the characteristic is rejected when it is executed.
+        respond(process(&[att::ATT_PREPARE_WRITE_REQ, lo, hi, 2, 0, 5, 6, 7]));
+        let pdu = respond(process(&[att::ATT_EXECUTE_WRITE_REQ, 1]));
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[ATT_ERROR_RSP, att::ATT_EXECUTE_WRITE_REQ, lo, hi, 0x0d]
+        );
+        assert_eq!(unwrap!(server.table().get(&characteristic)), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn discovery_cache_export_import() {
+        let battery = ServiceHandle {
+            start: 0x0010,
+            end: 0x0016,
+            uuid: Uuid::new_short(0x180f),
+        };
+        let level = DiscoveredCharacteristic {
+            declaration_handle: 0x0011,
+            handle: 0x0012,
+            props: [CharacteristicProp::Read, CharacteristicProp::Notify].into(),
+            uuid: Uuid::new_short(0x2a19),
+        };
+        let custom = DiscoveredCharacteristic {
+            declaration_handle: 0x0014,
+            handle: 0x0015,
+            props: [CharacteristicProp::Write, CharacteristicProp::Extended].into(),
+            uuid: Uuid::new_long([0x42; 16]),
+        };
+
+        let mut cache: DiscoveryCache<64> = DiscoveryCache::new();
+        assert!(cache.is_empty());
+        unwrap!(cache.push(CachedAttribute::Service(battery.clone())));
+        unwrap!(cache.push(CachedAttribute::Characteristic(level.clone())));
+        unwrap!(cache.push(CachedAttribute::Characteristic(custom.clone())));
+        unwrap!(cache.push(CachedAttribute::Descriptor(DiscoveredDescriptor {
+            handle: 0x0013,
+            uuid: CLIENT_CHARACTERISTIC_CONFIGURATION.into(),
+        })));
+        unwrap!(cache.push(CachedAttribute::ExtendedProps(
+            0x0016,
+            CharacteristicExtendedProps::new(true, false)
+        )));
+        cache.database_hash = Some([0xab; 16]);
+
+        let mut buf = [0u8; DiscoveryCache::<64>::MAX_LEN];
+        let len = unwrap!(cache.export(&mut buf));
+        assert!(cache.export(&mut buf[..len - 1]).is_err());
+        let cache: DiscoveryCache<64> = unwrap!(DiscoveryCache::import(&buf[..len]));
+        assert_eq!(cache.database_hash(), Some([0xab; 16]));
+        assert_eq!(cache.service_by_uuid(&Uuid::new_short(0x180f)), Some(battery.clone()));
+
+        let mut characteristics = cache.characteristics(&battery);
+        assert_eq!(characteristics.next(), Some(level.clone()));
+        assert_eq!(characteristics.next(), Some(custom.clone()));
+        assert_eq!(characteristics.next(), None);
+        // Descriptors belong to the characteristic declared before them.
+        assert_eq!(
+            cache.descriptors(&battery, &level).map(|d| d.handle).next(),
+            Some(0x0013)
+        );
+        assert_eq!(
+            cache.descriptors(&battery, &custom).map(|d| d.handle).next(),
+            Some(0x0016)
+        );
+
+        let characteristic: Characteristic<u8> = unwrap!(cache.characteristic_by_uuid(&battery, &level.uuid));
+        assert_eq!(characteristic.handle, 0x0012);
+        assert_eq!(characteristic.cccd_handle, Some(0x0013));
+        let characteristic: Characteristic<u8> = unwrap!(cache.characteristic_by_uuid(&battery, &custom.uuid));
+        assert_eq!(characteristic.cccd_handle, None);
+        assert_eq!(
+            characteristic.extended_props,
+            Some(CharacteristicExtendedProps::new(true, false))
+        );
+        assert!(matches!(
+            cache.characteristic_by_uuid::<u8>(&battery, &Uuid::new_short(0x2a00)),
+            Err(Error::NotFound)
+        ));
+
+        // Malformed or oversized blobs are rejected.
+        assert!(matches!(DiscoveryCache::<64>::import(&[]), Err(Error::InvalidValue)));
+        assert!(matches!(
+            DiscoveryCache::<64>::import(&buf[..len - 1]),
+            Err(Error::InvalidValue)
+        ));
+        assert!(matches!(
+            DiscoveryCache::<8>::import(&buf[..len]),
+            Err(Error::InsufficientSpace)
+        ));
+    }
+
+    #[test]
+    fn resubscribe_all_with_changing_subscriptions() {
+        let (mgr, client) = client();
+        let first = Subscription {
+            handle: 0x10,
+            cccd_handle: 0x11,
+            indication: false,
+            uuids: None,
+        };
+        let second = Subscription {
+            handle: 0x20,
+            cccd_handle: 0x21,
+            indication: true,
+            uuids: None,
+        };
+        unwrap!(client.restore_subscriptions(&[first, second.clone()]));
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut resubscribe = pin!(client.resubscribe_all(false));
+        assert!(resubscribe.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x11, 0x00, 0x01, 0x00]);
+
+        // The first subscription is dropped while its CCCD is being written.
+        client.forget_subscription(0x10);
+        respond(&client, &[ATT_WRITE_RSP]);
+        assert!(resubscribe.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x21, 0x00, 0x02, 0x00]);
+
+        respond(&client, &[ATT_WRITE_RSP]);
+        assert!(matches!(resubscribe.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        assert_eq!(&client.subscriptions()[..], &[second][..]);
+    }
+
+    #[test]
+    fn write_long_cancels_on_failed_part() {
+        let (mgr, client) = client();
+        let characteristic: Characteristic<&'static [u8]> = Characteristic {
+            handle: 0x10,
+            cccd_handle: None,
+            props: [CharacteristicProp::Write].into(),
+            extended_props: None,
+            phantom: PhantomData,
+        };
+        let part_len = client.connection.att_mtu() as usize - 5;
+        let value: std::vec::Vec<u8> = (0..(2 * part_len + 4) as u8).collect();
+        let mut cx = Context::from_waker(Waker::noop());
+
+        // The value is queued in parts at increasing offsets, each echoed back by the peer.
+        let mut write = pin!(client.write_characteristic_long(&characteristic, &value));
+        let mut prepare = |offset: usize, echo: &[u8]| {
+            assert!(write.as_mut().poll(&mut cx).is_pending());
+            let part = &value[offset..value.len().min(offset + part_len)];
+            let (_, pdu) = block_on(mgr.outbound());
+            let [offset_lo, offset_hi] = (offset as u16).to_le_bytes();
+            let header = [ATT_PREPARE_WRITE_REQ, 0x10, 0x00, offset_lo, offset_hi];
+            assert_eq!(&pdu.as_ref()[4..9], &header);
+            assert_eq!(&pdu.as_ref()[9..], part);
+            let mut rsp = std::vec![ATT_PREPARE_WRITE_RSP, 0x10, 0x00, offset_lo, offset_hi];
+            rsp.extend_from_slice(echo);
+            respond(&client, &rsp);
+        };
+        prepare(0, &value[..part_len]);
+        prepare(part_len, &value[part_len..2 * part_len]);
+        // The last part is echoed with different data.
+        prepare(2 * part_len, &[0xff; 4]);
+
+        // What was queued is cancelled, and the mismatch reported.
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_EXECUTE_WRITE_REQ, 0x00]);
+        respond(&client, &[ATT_EXECUTE_WRITE_RSP]);
+        assert!(matches!(
+            write.as_mut().poll(&mut cx),
+            Poll::Ready(Err(BleHostError::BleHost(Error::InvalidValue)))
+        ));
+
+        // A response that cannot be decoded is cancelled the same way.
+        let mut write = pin!(client.write_characteristic_long(&characteristic, &value));
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(pdu.as_ref()[4], ATT_PREPARE_WRITE_REQ);
+        respond(&client, &[ATT_PREPARE_WRITE_RSP, 0x10]);
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_EXECUTE_WRITE_REQ, 0x00]);
+        respond(&client, &[ATT_EXECUTE_WRITE_RSP]);
+        assert!(matches!(write.as_mut().poll(&mut cx), Poll::Ready(Err(_))));
+    }
+
+    #[test]
+    fn write_budget_windows() {
+        let start = Instant::from_secs(1);
+        let mut budget = WriteBudget {
+            limit: WriteRateLimit {
+                max_writes: 2,
+                window: Duration::from_millis(100),
+                policy: WriteRateLimitPolicy::Delay,
+            },
+            window_start: start,
+            used: 0,
+        };
+        assert_eq!(budget.take(start), Ok(()));
+        assert_eq!(budget.take(start + Duration::from_millis(10)), Ok(()));
+        assert_eq!(
+            budget.take(start + Duration::from_millis(20)),
+            Err(start + Duration::from_millis(100))
+        );
+        // The budget is renewed once the window has ended.
+        assert_eq!(budget.take(start + Duration::from_millis(100)), Ok(()));
+        assert_eq!(budget.take(start + Duration::from_millis(150)), Ok(()));
+        assert_eq!(
+            budget.take(start + Duration::from_millis(199)),
+            Err(start + Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn write_rate_limit_policies() {
+        let mgr = setup();
+        let mut store = [0u8; 1];
+        let mut table: AttributeTable<'_, NoopRawMutex, 8> = AttributeTable::new();
+        let mut svc = table.add_service(Service::new(0x180fu16));
+        let characteristic = svc
+            .add_characteristic(0x2a19u16, &[CharacteristicProp::Write], 0u8, &mut store)
+            .build();
+        svc.build();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 8, 2, 2> = AttributeServer::new(table);
+        let gatt = unwrap!(connect(mgr).with_attribute_server(&server));
+        let [handle_lo, handle_hi] = characteristic.handle.to_le_bytes();
+        let write = [ATT_WRITE_REQ, handle_lo, handle_hi, 1];
+        let mut cx = Context::from_waker(Waker::noop());
+
+        gatt.set_write_rate_limit(Some(WriteRateLimit {
+            max_writes: 1,
+            window: Duration::from_secs(60),
+            policy: WriteRateLimitPolicy::Reject(AttErrorCode::INSUFFICIENT_RESOURCES),
+        }));
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&write)));
+        let GattConnectionEvent::Gatt { event } = block_on(gatt.next()) else {
+            panic!("expected a GATT event");
+        };
+        block_on(unwrap!(event.accept()).send());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_RSP]);
+
+        // The second write in the window is rejected without reaching the application.
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&write)));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[ATT_ERROR_RSP, ATT_WRITE_REQ, handle_lo, handle_hi, 0x11]
+        );
+
+        // Without a limit, writes are passed on again.
+        gatt.set_write_rate_limit(None);
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&write)));
+        assert!(matches!(block_on(gatt.next()), GattConnectionEvent::Gatt { .. }));
+
+        gatt.set_write_rate_limit(Some(WriteRateLimit {
+            max_writes: 0,
+            window: Duration::from_secs(60),
+            policy: WriteRateLimitPolicy::Disconnect,
+        }));
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), att_pdu(&write)));
+        assert!(pin!(gatt.next()).poll(&mut cx).is_pending());
+        assert!(!gatt.raw().is_connected());
+    }
+
+    #[test]
+    fn notification_overflow_policies() {
+        fn notification(value: u8) -> Notification<4> {
+            Notification {
+                handle: 1,
+                data: [value, 0, 0, 0],
+                len: 1,
+            }
+        }
+
+        let queue = NotificationQueue::<4>::new();
+        queue.handle.set(Some(1));
+        let mut listener = NotificationListener { queue: &queue };
+
+        // The capacity is clamped to the queue size.
+        listener.set_queue_config(NotificationQueueConfig {
+            capacity: 0,
+            overflow: NotificationOverflowPolicy::DropOldest,
+        });
+        assert_eq!(listener.queue_config().capacity, 1);
+        listener.set_queue_config(NotificationQueueConfig {
+            capacity: usize::MAX,
+            overflow: NotificationOverflowPolicy::DropOldest,
+        });
+        assert_eq!(listener.queue_config().capacity, NOTIF_QSIZE);
+
+        let config = |overflow| NotificationQueueConfig { capacity: 1, overflow };
+        listener.set_queue_config(config(NotificationOverflowPolicy::DropOldest));
+        queue.push(notification(1));
+        queue.push(notification(2));
+        assert_eq!(block_on(listener.next_checked()), Ok(notification(2)));
+
+        listener.set_queue_config(config(NotificationOverflowPolicy::DropNewest));
+        queue.push(notification(1));
+        queue.push(notification(2));
+        assert_eq!(block_on(listener.next_checked()), Ok(notification(1)));
+
+        // The overflow is reported after the queued notification, and nothing is queued until then.
+        listener.set_queue_config(config(NotificationOverflowPolicy::Fail));
+        queue.push(notification(1));
+        queue.push(notification(2));
+        assert_eq!(block_on(listener.next_checked()), Ok(notification(1)));
+        queue.push(notification(3));
+        assert_eq!(block_on(listener.next_checked()), Err(Error::NotificationOverflowed));
+        queue.push(notification(4));
+        assert_eq!(block_on(listener.next_checked()), Ok(notification(4)));
+
+        // Releasing the queue for another listener resets it.
+        queue.push(notification(5));
+        queue.push(notification(6));
+        queue.release();
+        assert_eq!(queue.handle.get(), None);
+        assert!(!queue.overflowed.get());
+        assert!(queue.queue.is_empty());
+    }
+
+    #[test]
+    fn discover_services_streams_responses() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut services = client.discover_services();
+        {
+            let mut next = pin!(services.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(
+                &pdu.as_ref()[4..],
+                &[ATT_READ_BY_GROUP_TYPE_REQ, 0x01, 0x00, 0xff, 0xff, 0x00, 0x28]
+            );
+            // Two services, with 16-bit UUIDs.
+            let rsp = [
+                ATT_READ_BY_GROUP_TYPE_RSP,
+                6,
+                0x01,
+                0x00,
+                0x05,
+                0x00,
+                0x0f,
+                0x18,
+                0x06,
+                0x00,
+                0x09,
+                0x00,
+                0x0a,
+                0x18,
+            ];
+            respond(&client, &rsp);
+            let Poll::Ready(Some(Ok(service))) = next.as_mut().poll(&mut cx) else {
+                panic!("expected a service");
+            };
+            assert_eq!((service.start(), service.end()), (0x01, 0x05));
+            assert_eq!(service.uuid(), &Uuid::new_short(0x180f));
+        }
+
+        // The second service is taken from the same response.
+        let Poll::Ready(Some(Ok(service))) = pin!(services.next()).poll(&mut cx) else {
+            panic!("expected a service");
+        };
+        assert_eq!((service.start(), service.end()), (0x06, 0x09));
+        assert_eq!(service.uuid(), &Uuid::new_short(0x180a));
+
+        // The next request continues after the last service, until the peer has no more.
+        {
+            let mut next = pin!(services.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(
+                &pdu.as_ref()[4..],
+                &[ATT_READ_BY_GROUP_TYPE_REQ, 0x0a, 0x00, 0xff, 0xff, 0x00, 0x28]
+            );
+            respond(&client, &[ATT_ERROR_RSP, ATT_READ_BY_GROUP_TYPE_REQ, 0x0a, 0x00, 0x0a]);
+            assert!(matches!(next.as_mut().poll(&mut cx), Poll::Ready(None)));
+        }
+        assert!(matches!(pin!(services.next()).poll(&mut cx), Poll::Ready(None)));
+    }
+
+    /// Answer the Read By Type request for the characteristic declarations from `start` to `end` with one at
+    /// `next`, as `characteristic_end` sends.
+    fn respond_next_characteristic(
+        mgr: &ConnectionManager<'_, DefaultPacketPool>,
+        client: &TestClient,
+        start: u16,
+        end: u16,
+        next: u16,
+    ) {
+        let (_, pdu) = block_on(mgr.outbound());
+        let [start_lo, start_hi] = start.to_le_bytes();
+        let [end_lo, end_hi] = end.to_le_bytes();
+        assert_eq!(
+            &pdu.as_ref()[4..],
+            &[ATT_READ_BY_TYPE_REQ, start_lo, start_hi, end_lo, end_hi, 0x03, 0x28]
+        );
+        let [next_lo, next_hi] = next.to_le_bytes();
+        let [value_lo, value_hi] = (next + 1).to_le_bytes();
+        respond(
+            client,
+            &[
+                ATT_READ_BY_TYPE_RSP,
+                7,
+                next_lo,
+                next_hi,
+                0x02,
+                value_lo,
+                value_hi,
+                0x1a,
+                0x2a,
+            ],
+        );
+    }
+
+    #[test]
+    fn discover_characteristic_descriptors() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let service = ServiceHandle {
+            start: 0x01,
+            end: 0x08,
+            uuid: Uuid::new_short(0x180f),
+        };
+        let characteristic = DiscoveredCharacteristic {
+            declaration_handle: 0x02,
+            handle: 0x03,
+            props: [CharacteristicProp::Notify].into(),
+            uuid: Uuid::new_short(0x2a19),
+        };
+
+        // The descriptors end before the next characteristic declaration.
+        let mut descriptors = {
+            let mut discovery = pin!(client.discover_characteristic_descriptors(&service, &characteristic));
+            assert!(discovery.as_mut().poll(&mut cx).is_pending());
+            respond_next_characteristic(mgr, &client, 0x04, 0x08, 0x06);
+            let Poll::Ready(Ok(descriptors)) = discovery.as_mut().poll(&mut cx) else {
+                panic!("expected the descriptor discovery");
+            };
+            descriptors
+        };
+        {
+            let mut next = pin!(descriptors.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_FIND_INFORMATION_REQ, 0x04, 0x00, 0x05, 0x00]);
+            respond(
+                &client,
+                &[
+                    ATT_FIND_INFORMATION_RSP,
+                    0x01,
+                    0x04,
+                    0x00,
+                    0x02,
+                    0x29,
+                    0x05,
+                    0x00,
+                    0x01,
+                    0x29,
+                ],
+            );
+            let Poll::Ready(Some(Ok(descriptor))) = next.as_mut().poll(&mut cx) else {
+                panic!("expected a descriptor");
+            };
+            assert_eq!(descriptor.handle, 0x04);
+            assert!(descriptor.is_cccd());
+        }
+        let Poll::Ready(Some(Ok(descriptor))) = pin!(descriptors.next()).poll(&mut cx) else {
+            panic!("expected a descriptor");
+        };
+        assert_eq!(descriptor.handle, 0x05);
+        assert_eq!(descriptor.uuid, Uuid::new_short(0x2901));
+        // The last descriptor reached the end of the characteristic, so no more requests are sent.
+        assert!(matches!(pin!(descriptors.next()).poll(&mut cx), Poll::Ready(None)));
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn characteristic_from_discovery() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let service = ServiceHandle {
+            start: 0x01,
+            end: 0x08,
+            uuid: Uuid::new_short(0x180f),
+        };
+
+        // Without properties that need descriptors, no request is sent.
+        let readable = DiscoveredCharacteristic {
+            declaration_handle: 0x02,
+            handle: 0x03,
+            props: [CharacteristicProp::Read].into(),
+            uuid: Uuid::new_short(0x2a19),
+        };
+        let Poll::Ready(Ok(characteristic)) = pin!(client.characteristic::<u8>(&service, &readable)).poll(&mut cx)
+        else {
+            panic!("expected a characteristic");
+        };
+        assert_eq!((characteristic.handle, characteristic.cccd_handle), (0x03, None));
+
+        // The CCCD and extended properties are found up to the next characteristic.
+        let notifying = DiscoveredCharacteristic {
+            props: [CharacteristicProp::Notify, CharacteristicProp::Extended].into(),
+            ..readable.clone()
+        };
+        {
+            let mut characteristic = pin!(client.characteristic::<u8>(&service, &notifying));
+            assert!(characteristic.as_mut().poll(&mut cx).is_pending());
+            respond_next_characteristic(mgr, &client, 0x04, 0x08, 0x06);
+            assert!(characteristic.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_FIND_INFORMATION_REQ, 0x03, 0x00, 0x05, 0x00]);
+            respond(
+                &client,
+                &[
+                    ATT_FIND_INFORMATION_RSP,
+                    0x01,
+                    0x03,
+                    0x00,
+                    0x19,
+                    0x2a,
+                    0x04,
+                    0x00,
+                    0x02,
+                    0x29,
+                    0x05,
+                    0x00,
+                    0x00,
+                    0x29,
+                ],
+            );
+            assert!(characteristic.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_READ_REQ, 0x05, 0x00]);
+            respond(&client, &[ATT_READ_RSP, 0x01, 0x00]);
+            let Poll::Ready(Ok(characteristic)) = characteristic.as_mut().poll(&mut cx) else {
+                panic!("expected a characteristic");
+            };
+            assert_eq!(characteristic.cccd_handle, Some(0x04));
+            assert_eq!(
+                characteristic.extended_props,
+                Some(CharacteristicExtendedProps::new(true, false))
+            );
+        }
+
+        // A response without descriptors ends the search instead of sending the same request again.
+        let mut characteristic = pin!(client.characteristic::<u8>(&service, &notifying));
+        assert!(characteristic.as_mut().poll(&mut cx).is_pending());
+        respond_next_characteristic(mgr, &client, 0x04, 0x08, 0x06);
+        assert!(characteristic.as_mut().poll(&mut cx).is_pending());
+        block_on(mgr.outbound());
+        respond(&client, &[ATT_FIND_INFORMATION_RSP, 0x01]);
+        assert!(matches!(
+            characteristic.as_mut().poll(&mut cx),
+            Poll::Ready(Err(BleHostError::BleHost(Error::NotFound)))
+        ));
+    }
+
+    /// Pass a PDU from the peer to the task of a client.
+    fn receive(client: &TestClient, data: &[u8]) {
+        block_on(
+            client
+                .stack
+                .host
+                .att_client
+                .send((ConnHandle::new(HANDLE), att_pdu(data))),
+        );
+    }
+
+    /// Subscribe to notifications of the characteristic with value handle 0x03 and CCCD 0x04.
+    fn subscribe_values(
+        client: &TestClient,
+    ) -> ValueSubscription<'_, 'static, MockController, DefaultPacketPool, 4, u8> {
+        let characteristic: Characteristic<u8> = Characteristic {
+            handle: 0x03,
+            cccd_handle: Some(0x04),
+            props: [CharacteristicProp::Notify].into(),
+            extended_props: None,
+            phantom: PhantomData,
+        };
+        let mgr = &client.stack.host.connections;
+        let mut subscribe = pin!(client.subscribe_values(&characteristic, false));
+        assert!(subscribe
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+            .is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x04, 0x00, 0x01, 0x00]);
+        respond(client, &[ATT_WRITE_RSP]);
+        let Poll::Ready(Ok(subscription)) = subscribe.as_mut().poll(&mut Context::from_waker(Waker::noop())) else {
+            panic!("expected a subscription");
+        };
+        subscription
+    }
+
+    #[test]
+    fn unsubscribe_on_drop() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        drop(subscribe_values(&client));
+        assert!(client.subscriptions().is_empty());
+
+        // The task clears the CCCD with a Write Request.
+        let mut task = pin!(client.task());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x04, 0x00, 0x00, 0x00]);
+
+        // Other requests wait until the task took the response to its own.
+        let mut write = pin!(client.write_cccd(0x08, 1));
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+        receive(&client, &[ATT_WRITE_RSP]);
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert!(write.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(outbound.as_mut());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x08, 0x00, 0x01, 0x00]);
+        receive(&client, &[ATT_WRITE_RSP]);
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        assert!(matches!(write.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    }
+
+    #[test]
+    fn unsubscribe_retried_after_failure() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let subscription = subscribe_values(&client);
+        {
+            let mut unsubscribe = pin!(subscription.unsubscribe());
+            assert!(unsubscribe.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x04, 0x00, 0x00, 0x00]);
+            respond(&client, &[ATT_ERROR_RSP, ATT_WRITE_REQ, 0x04, 0x00, 0x0e]);
+            assert!(matches!(
+                unsubscribe.as_mut().poll(&mut cx),
+                Poll::Ready(Err(BleHostError::BleHost(Error::Att(AttErrorCode::UNLIKELY_ERROR))))
+            ));
+        }
+        assert!(client.subscriptions().is_empty());
+
+        // The failed write is sent again by the task.
+        let mut task = pin!(client.task());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_WRITE_REQ, 0x04, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn reliable_write_ended_by_another() {
+        use crate::att::{ATT_EXECUTE_WRITE_REQ, ATT_EXECUTE_WRITE_RSP, ATT_PREPARE_WRITE_REQ, ATT_PREPARE_WRITE_RSP};
+
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let characteristic: Characteristic<[u8; 2]> = Characteristic {
+            handle: 0x03,
+            cccd_handle: None,
+            props: [CharacteristicProp::Write].into(),
+            extended_props: None,
+            phantom: PhantomData,
+        };
+        let Poll::Ready(Ok(mut first)) = pin!(client.begin_reliable_write()).poll(&mut cx) else {
+            panic!("expected a reliable write");
+        };
+        {
+            let mut queue = pin!(first.queue_write(&characteristic, &[1, 2]));
+            assert!(queue.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(
+                &pdu.as_ref()[4..],
+                &[ATT_PREPARE_WRITE_REQ, 0x03, 0x00, 0x00, 0x00, 1, 2]
+            );
+            respond(&client, &[ATT_PREPARE_WRITE_RSP, 0x03, 0x00, 0x00, 0x00, 1, 2]);
+            assert!(matches!(queue.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        }
+
+        // A second reliable write discards the values of the first.
+        let mut second = {
+            let mut begin = pin!(client.begin_reliable_write());
+            assert!(begin.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_EXECUTE_WRITE_REQ, 0]);
+            respond(&client, &[ATT_EXECUTE_WRITE_RSP]);
+            let Poll::Ready(Ok(second)) = begin.as_mut().poll(&mut cx) else {
+                panic!("expected a reliable write");
+            };
+            second
+        };
+
+        // The first can no longer queue or commit values, as that would write those of the second.
+        assert!(matches!(
+            pin!(first.queue_write(&characteristic, &[3, 4])).poll(&mut cx),
+            Poll::Ready(Err(BleHostError::BleHost(Error::InvalidState)))
+        ));
+        assert!(matches!(
+            pin!(first.commit()).poll(&mut cx),
+            Poll::Ready(Err(BleHostError::BleHost(Error::InvalidState)))
+        ));
+        let mut outbound = pin!(mgr.outbound());
+        assert!(outbound.as_mut().poll(&mut cx).is_pending());
+
+        {
+            let mut queue = pin!(second.queue_write(&characteristic, &[5, 6]));
+            assert!(queue.as_mut().poll(&mut cx).is_pending());
+            block_on(outbound.as_mut());
+            respond(&client, &[ATT_PREPARE_WRITE_RSP, 0x03, 0x00, 0x00, 0x00, 5, 6]);
+            assert!(matches!(queue.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        }
+        let mut commit = pin!(second.commit());
+        assert!(commit.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_EXECUTE_WRITE_REQ, 1]);
+        respond(&client, &[ATT_EXECUTE_WRITE_RSP]);
+        assert!(matches!(commit.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+    }
+
+    #[test]
+    fn discovery_ends_on_empty_response() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut descriptors = client.discover_descriptors(0x10, 0x20);
+        {
+            let mut next = pin!(descriptors.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(&pdu.as_ref()[4..], &[ATT_FIND_INFORMATION_REQ, 0x10, 0x00, 0x20, 0x00]);
+            // A response without any descriptor would have the same request sent forever.
+            respond(&client, &[ATT_FIND_INFORMATION_RSP, 0x01]);
+            assert!(matches!(
+                next.as_mut().poll(&mut cx),
+                Poll::Ready(Some(Err(BleHostError::BleHost(Error::UnexpectedGattResponse))))
+            ));
+        }
+        assert!(matches!(pin!(descriptors.next()).poll(&mut cx), Poll::Ready(None)));
+    }
+
+    /// Put one ACL packet of the client's link in flight, returning the count that completes it.
+    fn send_packet(mgr: &ConnectionManager<'_, DefaultPacketPool>, client: &TestClient) -> u32 {
+        let mut cx = Context::from_waker(Waker::noop());
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(ConnHandle::new(HANDLE), 1, Some(&mut cx), true)
+        else {
+            panic!("expected credits to be granted");
+        };
+        grant.confirm(1);
+        client.connection.tx_target()
+    }
+
+    #[test]
+    fn paced_writes_wait_for_completion() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        mgr.set_link_credits(4);
+        client.set_write_command_limit(1);
+
+        let first = send_packet(mgr, &client);
+        unwrap!(client.write_commands.borrow_mut().push_back(first));
+
+        // Two writers wait on the same write in flight.
+        let mut a = pin!(client.reserve_write_command());
+        let mut b = pin!(client.reserve_write_command());
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+
+        unwrap!(mgr.confirm_sent(ConnHandle::new(HANDLE), 1));
+        assert!(matches!(a.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        let second = send_packet(mgr, &client);
+        unwrap!(client.write_commands.borrow_mut().push_back(second));
+
+        // The other writer keeps the write sent meanwhile, and waits for it.
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(client.write_commands.borrow().len(), 1);
+        unwrap!(mgr.confirm_sent(ConnHandle::new(HANDLE), 1));
+        assert!(matches!(b.as_mut().poll(&mut cx), Poll::Ready(Ok(()))));
+        assert!(client.write_commands.borrow().is_empty());
+
+        // A higher limit lets writes through while others are in flight.
+        client.set_write_command_limit(2);
+        let third = send_packet(mgr, &client);
+        unwrap!(client.write_commands.borrow_mut().push_back(third));
+        assert!(matches!(
+            pin!(client.reserve_write_command()).poll(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+    }
+
+    #[test]
+    fn discover_all_skips_includes() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut cache: DiscoveryCache<64> = DiscoveryCache::new();
+        {
+            let mut discovery = pin!(client.discover_all(&mut cache));
+            // A GATT service with an include declaration and the Service Changed characteristic.
+            while discovery.as_mut().poll(&mut cx).is_pending() {
+                let (_, pdu) = block_on(mgr.outbound());
+                let req = &pdu.as_ref()[4..];
+                match (req[0], req[1]) {
+                    (ATT_READ_BY_GROUP_TYPE_REQ, 0x01) => respond(
+                        &client,
+                        &[ATT_READ_BY_GROUP_TYPE_RSP, 6, 0x01, 0x00, 0x05, 0x00, 0x01, 0x18],
+                    ),
+                    (ATT_READ_BY_TYPE_REQ, 0x01) if req[5..] == [0x03, 0x28] => respond(
+                        &client,
+                        &[ATT_READ_BY_TYPE_RSP, 7, 0x03, 0x00, 0x20, 0x04, 0x00, 0x05, 0x2a],
+                    ),
+                    (ATT_FIND_INFORMATION_REQ, 0x02) => respond(
+                        &client,
+                        &[
+                            ATT_FIND_INFORMATION_RSP,
+                            0x01,
+                            0x02,
+                            0x00,
+                            0x02,
+                            0x28,
+                            0x03,
+                            0x00,
+                            0x03,
+                            0x28,
+                            0x04,
+                            0x00,
+                            0x05,
+                            0x2a,
+                            0x05,
+                            0x00,
+                            0x02,
+                            0x29,
+                        ],
+                    ),
+                    (opcode, lo) => respond(&client, &[ATT_ERROR_RSP, opcode, lo, req[2], 0x0a]),
+                }
+            }
+        }
+
+        let service = unwrap!(cache.service_by_uuid(&Uuid::new_short(0x1801)));
+        let service_changed = unwrap!(cache.characteristics(&service).next());
+        assert_eq!(service_changed.handle, 0x04);
+        let mut descriptors = cache.descriptors(&service, &service_changed);
+        assert_eq!(descriptors.next().map(|d| d.handle), Some(0x05));
+        assert_eq!(descriptors.next(), None);
+        assert_eq!(cache.database_hash(), None);
+        assert_eq!(client.service_changed_handle.get(), Some(0x04));
+    }
+
+    #[test]
+    fn discovery_cache_service_changed() {
+        let (mgr, client) = client();
+        let mut cx = Context::from_waker(Waker::noop());
+        let service = ServiceHandle {
+            start: 0x01,
+            end: 0x05,
+            uuid: Uuid::new_short(0x1801),
+        };
+        let mut cache: DiscoveryCache<64> = DiscoveryCache::new();
+        unwrap!(cache.push(CachedAttribute::Service(service)));
+        unwrap!(cache.push(CachedAttribute::Characteristic(DiscoveredCharacteristic {
+            declaration_handle: 0x03,
+            handle: 0x04,
+            props: [CharacteristicProp::Indicate].into(),
+            uuid: SERVICE_CHANGED,
+        })));
+
+        // Without a Database Hash, the peer must be bonded for Service Changed to cover the time in between.
+        {
+            let mut check = pin!(client.check_discovery_cache(&cache));
+            assert!(check.as_mut().poll(&mut cx).is_pending());
+            let (_, pdu) = block_on(mgr.outbound());
+            assert_eq!(
+                &pdu.as_ref()[4..],
+                &[ATT_READ_BY_TYPE_REQ, 0x01, 0x00, 0xff, 0xff, 0x2a, 0x2b]
+            );
+            respond(&client, &[ATT_ERROR_RSP, ATT_READ_BY_TYPE_REQ, 0x01, 0x00, 0x0a]);
+            assert!(matches!(check.as_mut().poll(&mut cx), Poll::Ready(Ok(false))));
+        }
+
+        cache.database_hash = Some([0xab; 16]);
+        let mut hash_rsp = [0xab; 20];
+        hash_rsp[..4].copy_from_slice(&[ATT_READ_BY_TYPE_RSP, 18, 0x10, 0x00]);
+        {
+            let mut check = pin!(client.check_discovery_cache(&cache));
+            assert!(check.as_mut().poll(&mut cx).is_pending());
+            block_on(mgr.outbound());
+            respond(&client, &hash_rsp);
+            assert!(matches!(check.as_mut().poll(&mut cx), Poll::Ready(Ok(true))));
+        }
+
+        // The indication is confirmed, and the cache is stale from then on.
+        let mut task = pin!(client.task());
+        receive(&client, &[ATT_HANDLE_VALUE_IND, 0x04, 0x00, 0x01, 0x00, 0xff, 0xff]);
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        let (_, pdu) = block_on(mgr.outbound());
+        assert_eq!(&pdu.as_ref()[4..], &[ATT_HANDLE_VALUE_CMF]);
+        assert!(matches!(
+            pin!(client.check_discovery_cache(&cache)).poll(&mut cx),
+            Poll::Ready(Ok(false))
+        ));
+    }
+}
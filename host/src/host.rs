@@ -4,7 +4,7 @@
 use core::cell::RefCell;
 use core::future::poll_fn;
 use core::mem::MaybeUninit;
-use core::task::Poll;
+use core::task::{Context, Poll};
 
 use bt_hci::cmd::controller_baseband::{
     HostBufferSize, HostNumberOfCompletedPackets, Reset, SetControllerToHostFlowControl, SetEventMask,
@@ -12,9 +12,12 @@ use bt_hci::cmd::controller_baseband::{
 };
 use bt_hci::cmd::info::ReadBdAddr;
 use bt_hci::cmd::le::{
-    LeConnUpdate, LeCreateConnCancel, LeEnableEncryption, LeLongTermKeyRequestReply, LeReadBufferSize,
-    LeReadFilterAcceptListSize, LeSetAdvEnable, LeSetEventMask, LeSetExtAdvEnable, LeSetExtScanEnable, LeSetRandomAddr,
-    LeSetScanEnable,
+    LeAddDeviceToFilterAcceptList, LeAddDeviceToResolvingList, LeClearFilterAcceptList, LeConnUpdate,
+    LeCreateConnCancel, LeEnableEncryption, LeLongTermKeyRequestReply, LeReadBufferSize, LeReadBufferSizeV2,
+    LeReadFilterAcceptListSize, LeReadLocalSupportedFeatures, LeReadMaxAdvDataLength, LeReadSupportedStates,
+    LeSetAddrResolutionEnable, LeSetAdvData, LeSetAdvEnable, LeSetAdvParams, LeSetAdvSetRandomAddr, LeSetEventMask,
+    LeSetExtAdvData, LeSetExtAdvEnable, LeSetExtAdvParams, LeSetExtScanEnable, LeSetExtScanParams,
+    LeSetExtScanResponseData, LeSetRandomAddr, LeSetScanEnable, LeSetScanParams, LeSetScanResponseData,
 };
 use bt_hci::cmd::link_control::Disconnect;
 use bt_hci::cmd::{AsyncCmd, SyncCmd};
@@ -26,12 +29,16 @@ use bt_hci::event::le::LeAdvertisingReport;
 use bt_hci::event::le::LeExtendedAdvertisingReport;
 use bt_hci::event::le::{
     LeAdvertisingSetTerminated, LeConnectionComplete, LeConnectionUpdateComplete, LeDataLengthChange,
-    LeEnhancedConnectionComplete, LeEventKind, LeEventPacket, LePhyUpdateComplete, LeRemoteConnectionParameterRequest,
+    LeEnhancedConnectionComplete, LeEventKind, LeEventPacket, LePhyUpdateComplete, LeReadRemoteFeaturesComplete,
+    LeRemoteConnectionParameterRequest,
+};
+use bt_hci::event::{
+    DisconnectionComplete, EventKind, HardwareError, NumberOfCompletedPackets, ReadRemoteVersionInformationComplete,
+    Vendor,
 };
-use bt_hci::event::{DisconnectionComplete, EventKind, NumberOfCompletedPackets, Vendor};
 use bt_hci::param::{
-    AddrKind, AdvHandle, AdvSet, BdAddr, ConnHandle, DisconnectReason, EventMask, EventMaskPage2, FilterDuplicates,
-    LeConnRole, LeEventMask, Status,
+    AddrKind, AdvChannelMap, AdvHandle, AdvSet, BdAddr, ConnHandle, DisconnectReason, EventMask, EventMaskPage2,
+    FilterDuplicates, LeConnRole, LeEventMask, LeFeatureMask, LeStates, Operation, Status,
 };
 use bt_hci::{ControllerToHostPacket, FromHciBytes, WriteHci};
 use embassy_futures::select::{select3, select4, Either3, Either4};
@@ -39,23 +46,29 @@ use embassy_sync::once_lock::OnceLock;
 use embassy_sync::waitqueue::WakerRegistration;
 #[cfg(feature = "gatt")]
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
-use embassy_time::Duration;
+use embassy_time::{with_timeout, Duration, Instant};
 use futures::pin_mut;
 
+use crate::activity::ActivityState;
 use crate::att::{AttClient, AttServer};
 use crate::channel_manager::{ChannelManager, ChannelStorage};
 use crate::command::CommandState;
-use crate::connection::ConnectionEvent;
+use crate::connection::{ConnectionEvent, ConnectionEventTiming, RemoteVersion};
 use crate::connection_manager::{ConnectionManager, ConnectionStorage, PacketGrant};
 use crate::cursor::WriteCursor;
+use crate::iso::IsoData;
 use crate::pdu::Pdu;
+#[cfg(feature = "scan")]
+use crate::periodic_sync::PeriodicSyncEvent;
+use crate::radio_state::{PendingRestore, RadioState, RestoredState, SavedAdvertising, RESTORABLE_ADV_SETS};
 #[cfg(feature = "security")]
 use crate::security_manager::SecurityEventData;
+use crate::table::Table;
 use crate::types::l2cap::{
     ConnParamUpdateReq, ConnParamUpdateRes, L2capHeader, L2capSignal, L2capSignalHeader, L2CAP_CID_ATT,
     L2CAP_CID_DYN_START, L2CAP_CID_LE_U_SECURITY_MANAGER, L2CAP_CID_LE_U_SIGNAL,
 };
-use crate::{att, Address, BleHostError, Error, PacketPool, Stack};
+use crate::{att, bt_hci_duration, bt_hci_ext_duration, Address, BleHostError, Error, PacketPool, Stack};
 
 /// A BLE Host.
 ///
@@ -77,11 +90,45 @@ pub(crate) struct BleHost<'d, T, P: PacketPool> {
     pub(crate) advertise_command_state: CommandState<bool>,
     pub(crate) connect_command_state: CommandState<bool>,
     pub(crate) scan_command_state: CommandState<bool>,
+    #[cfg(feature = "scan")]
+    pub(crate) periodic_sync: crate::periodic_sync::PeriodicSyncState,
+    pub(crate) watchdog_timeout: Option<Duration>,
+    pub(crate) reset_state: ResetState,
+    pub(crate) iso_credits: IsoCredits,
+    pub(crate) radio_state: RefCell<RadioState>,
+    pub(crate) activity: RefCell<ActivityState>,
+    filter_accept_list: RefCell<Option<heapless::Vec<(AddrKind, BdAddr), FILTER_ACCEPT_LIST_TRACKED>>>,
 }
 
+/// Number of filter accept list entries the host remembers for [`ConnectionRequest::in_filter_accept_list`].
+const FILTER_ACCEPT_LIST_TRACKED: usize = 16;
+
 #[derive(Clone, Copy)]
 pub(crate) struct InitialState {
     acl_max: usize,
+    info: ControllerInfo,
+}
+
+/// Capabilities and limits reported by the controller during initialization.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerInfo {
+    /// Supported LE features.
+    pub le_features: LeFeatureMask,
+    /// Supported LE states and state combinations.
+    pub le_states: LeStates,
+    /// Maximum length of the data portion of an LE ACL data packet.
+    pub acl_max_len: u16,
+    /// Number of LE ACL data packets the controller can buffer.
+    pub acl_max_num: u8,
+    /// Number of entries in the filter accept list.
+    pub filter_accept_list_size: u8,
+    /// Maximum advertising data length, 31 bytes if extended advertising is not supported.
+    pub max_adv_data_len: u16,
+    /// Maximum length of the data portion of an ISO data packet, 0 if ISO is not supported.
+    pub iso_max_len: u16,
+    /// Number of ISO data packets the controller can buffer, 0 if ISO is not supported.
+    pub iso_max_num: u8,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -138,6 +185,15 @@ impl<'d> AdvState<'d> {
         state.handles.len()
     }
 
+    /// Whether the advertising set is still advertising.
+    pub(crate) fn is_advertising(&self, handle: AdvHandle) -> bool {
+        let state = self.state.borrow();
+        state
+            .handles
+            .iter()
+            .any(|entry| matches!(entry, AdvHandleState::Advertising(h) if *h == handle))
+    }
+
     pub(crate) fn start(&self, sets: &[AdvSet]) {
         let mut state = self.state.borrow_mut();
         assert!(sets.len() <= state.handles.len());
@@ -175,10 +231,264 @@ impl<'d> AdvState<'d> {
         })
         .await;
     }
+
+    /// Mark a single advertising set as advertising again after it was enabled on its own.
+    pub(crate) fn restart(&self, handle: AdvHandle) {
+        let mut state = self.state.borrow_mut();
+        let known = state.handles.iter().position(
+            |entry| matches!(entry, AdvHandleState::Advertising(h) | AdvHandleState::Terminated(h) if *h == handle),
+        );
+        let free = state
+            .handles
+            .iter()
+            .position(|entry| matches!(entry, AdvHandleState::None));
+        if let Some(idx) = known.or(free) {
+            state.handles[idx] = AdvHandleState::Advertising(handle);
+        }
+    }
+
+    /// Wait until the advertising set is no longer advertising.
+    pub(crate) async fn wait_terminated(&self, handle: AdvHandle) {
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.waker.register(cx.waker());
+            if state
+                .handles
+                .iter()
+                .any(|entry| matches!(entry, AdvHandleState::Advertising(h) if *h == handle))
+            {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+    }
+}
+
+pub(crate) struct ResetInner {
+    requested: bool,
+    recoveries: u32,
+    restored: RestoredState,
+    /// A task waits in [`Stack::radio_state_restored`] to restore the radio state.
+    restorer: bool,
+    /// Radio state left for the restorer to re-apply after the last recovery.
+    pending: Option<PendingRestore>,
+    control: WakerRegistration,
+    waiter: WakerRegistration,
+}
+
+/// Controller buffers for ISO data.
+///
+/// ISO buffers are separate from the ACL buffers, so they are accounted for separately: completed packets
+/// reported for handles that are not ACL links are credited here.
+pub(crate) struct IsoCredits {
+    inner: RefCell<IsoCreditsInner>,
+}
+
+struct IsoCreditsInner {
+    total: usize,
+    available: usize,
+    /// Packets in flight for each isochronous stream.
+    in_flight: heapless::Vec<(ConnHandle, usize), ISO_STREAMS_TRACKED>,
+    waker: WakerRegistration,
+}
+
+/// Number of isochronous streams that can have packets in flight at the same time.
+const ISO_STREAMS_TRACKED: usize = 8;
+
+impl IsoCredits {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: RefCell::new(IsoCreditsInner {
+                total: 0,
+                available: 0,
+                in_flight: heapless::Vec::new(),
+                waker: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Set the number of ISO buffers of the controller, dropping any packets in flight.
+    pub(crate) fn set(&self, total: usize) {
+        let mut inner = self.inner.borrow_mut();
+        inner.total = total;
+        inner.available = total;
+        inner.in_flight.clear();
+        inner.waker.wake();
+    }
+
+    /// Wait for a free ISO buffer and claim it for a packet of the given stream.
+    ///
+    /// Fails with [`Error::NotSupported`] if the controller has no ISO buffers.
+    pub(crate) async fn acquire(&self, handle: ConnHandle) -> Result<(), Error> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.borrow_mut();
+            if inner.total == 0 {
+                return Poll::Ready(Err(Error::NotSupported));
+            }
+            if inner.available > 0 {
+                if let Some((_, packets)) = inner.in_flight.iter_mut().find(|(h, _)| *h == handle) {
+                    *packets += 1;
+                } else if inner.in_flight.push((handle, 1)).is_err() {
+                    inner.waker.register(cx.waker());
+                    return Poll::Pending;
+                }
+                inner.available -= 1;
+                Poll::Ready(Ok(()))
+            } else {
+                inner.waker.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Return buffers of a stream reported as completed by the controller.
+    ///
+    /// Returns `false` if no packets are in flight for the handle, in which case nothing is released.
+    pub(crate) fn release(&self, handle: ConnHandle, packets: usize) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let Some(index) = inner.in_flight.iter().position(|(h, _)| *h == handle) else {
+            return false;
+        };
+        let released = packets.min(inner.in_flight[index].1);
+        inner.in_flight[index].1 -= released;
+        if inner.in_flight[index].1 == 0 {
+            inner.in_flight.swap_remove(index);
+        }
+        inner.available = (inner.available + released).min(inner.total);
+        inner.waker.wake();
+        true
+    }
+
+    /// Return the buffers of a stream that was disconnected, whose packets the controller dropped.
+    pub(crate) fn disconnected(&self, handle: ConnHandle) {
+        self.release(handle, usize::MAX);
+    }
+}
+
+/// Tracks requests to reset the controller and completed recoveries.
+pub(crate) struct ResetState {
+    inner: RefCell<ResetInner>,
+}
+
+impl ResetState {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: RefCell::new(ResetInner {
+                requested: false,
+                recoveries: 0,
+                restored: RestoredState::default(),
+                restorer: false,
+                pending: None,
+                control: WakerRegistration::new(),
+                waiter: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Request that the control runner resets and re-initializes the controller.
+    pub(crate) fn request(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.requested = true;
+        inner.control.wake();
+    }
+
+    pub(crate) fn poll_requested(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.borrow_mut();
+        inner.control.register(cx.waker());
+        if inner.requested {
+            inner.requested = false;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Record a completed recovery, leaving the `pending` radio state for the restorer.
+    ///
+    /// Returns the pending radio state back if no restorer is waiting for it anymore.
+    pub(crate) fn recovered(&self, restored: RestoredState, pending: Option<PendingRestore>) -> Option<PendingRestore> {
+        let mut inner = self.inner.borrow_mut();
+        inner.recoveries = inner.recoveries.wrapping_add(1);
+        inner.restored = RestoredState {
+            recoveries: inner.recoveries,
+            ..restored
+        };
+        inner.waiter.wake();
+        if inner.restorer {
+            inner.pending = pending;
+            None
+        } else {
+            inner.pending = None;
+            pending
+        }
+    }
+
+    /// The configuration restored by the last recovery.
+    pub(crate) fn restored(&self) -> RestoredState {
+        self.inner.borrow().restored
+    }
+
+    /// Add the parts of the radio state re-applied by the restorer to the last recovery.
+    pub(crate) fn set_restored(&self, restored: RestoredState) {
+        let mut inner = self.inner.borrow_mut();
+        inner.restored = RestoredState {
+            recoveries: inner.recoveries,
+            random_address: inner.restored.random_address,
+            filter_accept_list: inner.restored.filter_accept_list,
+            ..restored
+        };
+    }
+
+    /// Register or unregister the task restoring the radio state after a recovery.
+    ///
+    /// Returns the radio state still waiting to be restored when unregistering.
+    pub(crate) fn set_restorer(&self, restorer: bool) -> Option<PendingRestore> {
+        let mut inner = self.inner.borrow_mut();
+        inner.restorer = restorer;
+        if restorer {
+            None
+        } else {
+            inner.pending.take()
+        }
+    }
+
+    /// Whether a task is waiting to restore the radio state after a recovery.
+    pub(crate) fn has_restorer(&self) -> bool {
+        self.inner.borrow().restorer
+    }
+
+    /// The radio state waiting to be restored after the last recovery.
+    pub(crate) fn pending(&self) -> Option<PendingRestore> {
+        self.inner.borrow().pending
+    }
+
+    /// Mark the pending radio state as restored.
+    pub(crate) fn take_pending(&self) -> Option<PendingRestore> {
+        self.inner.borrow_mut().pending.take()
+    }
+
+    pub(crate) fn recoveries(&self) -> u32 {
+        self.inner.borrow().recoveries
+    }
+
+    /// Wait until the number of recoveries differs from `seen`.
+    pub(crate) fn poll_recovered(&self, seen: u32, cx: &mut Context<'_>) -> Poll<u32> {
+        let mut inner = self.inner.borrow_mut();
+        inner.waiter.register(cx.waker());
+        if inner.recoveries != seen {
+            Poll::Ready(inner.recoveries)
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
 /// Host metrics
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct HostMetrics {
     /// How many connect events have been received.
     pub connect_events: u32,
@@ -186,6 +496,10 @@ pub struct HostMetrics {
     pub disconnect_events: u32,
     /// How many errors processing received data.
     pub rx_errors: u32,
+    /// How many HCI commands or credit waits exceeded the watchdog timeout.
+    pub watchdog_timeouts: u32,
+    /// How many times the controller has been reset and re-initialized after startup.
+    pub controller_resets: u32,
 }
 
 impl<'d, T, P> BleHost<'d, T, P>
@@ -200,8 +514,8 @@ where
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         controller: T,
-        connections: &'d mut [ConnectionStorage<P::Packet>],
-        channels: &'d mut [ChannelStorage<P::Packet>],
+        connections: Table<'d, ConnectionStorage<P::Packet>>,
+        channels: Table<'d, ChannelStorage<P::Packet>>,
         advertise_handles: &'d mut [AdvHandleState],
     ) -> Self {
         Self {
@@ -216,7 +530,78 @@ where
             advertise_state: AdvState::new(advertise_handles),
             advertise_command_state: CommandState::new(),
             scan_command_state: CommandState::new(),
+            #[cfg(feature = "scan")]
+            periodic_sync: crate::periodic_sync::PeriodicSyncState::new(),
             connect_command_state: CommandState::new(),
+            watchdog_timeout: None,
+            reset_state: ResetState::new(),
+            iso_credits: IsoCredits::new(),
+            radio_state: RefCell::new(RadioState::default()),
+            activity: RefCell::new(ActivityState::new()),
+            filter_accept_list: RefCell::new(Some(heapless::Vec::new())),
+        }
+    }
+
+    /// Record a watchdog expiry and request a controller reset.
+    pub(crate) fn watchdog_expired(&self) {
+        warn!("[host] controller watchdog expired, requesting reset");
+        let mut m = self.metrics.borrow_mut();
+        m.watchdog_timeouts = m.watchdog_timeouts.wrapping_add(1);
+        self.reset_state.request();
+    }
+
+    /// Replace the contents of the controller filter accept list.
+    ///
+    /// The list is shared by scanning, connecting and advertising.
+    pub(crate) async fn set_filter_accept_list(
+        &self,
+        filter_accept_list: &[(AddrKind, &BdAddr)],
+    ) -> Result<(), BleHostError<T::Error>>
+    where
+        T: ControllerCmdSync<LeClearFilterAcceptList> + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        if let Some(info) = self.controller_info() {
+            if filter_accept_list.len() > info.filter_accept_list_size as usize {
+                return Err(Error::InsufficientSpace.into());
+            }
+        }
+        // The list is unknown until every entry has been added.
+        self.filter_accept_list.replace(None);
+        self.command(LeClearFilterAcceptList::new()).await?;
+        for entry in filter_accept_list {
+            self.command(LeAddDeviceToFilterAcceptList::new(entry.0, *entry.1))
+                .await?;
+        }
+        if filter_accept_list.len() <= FILTER_ACCEPT_LIST_TRACKED {
+            let tracked = filter_accept_list.iter().map(|(kind, addr)| (*kind, **addr)).collect();
+            self.filter_accept_list.replace(Some(tracked));
+        }
+        Ok(())
+    }
+
+    /// Whether the peer is in the filter accept list, if the host knows the list contents.
+    fn in_filter_accept_list(&self, kind: AddrKind, addr: BdAddr) -> Option<bool> {
+        self.filter_accept_list
+            .borrow()
+            .as_ref()
+            .map(|list| list.iter().any(|entry| *entry == (kind, addr)))
+    }
+
+    /// Consult the connection policy of the event handler for a newly established connection.
+    ///
+    /// Returns the disconnect reason if the connection should be terminated before it is registered.
+    fn check_connection_policy(
+        &self,
+        event_handler: &dyn EventHandler,
+        mut request: ConnectionRequest,
+    ) -> Option<DisconnectReason> {
+        request.in_filter_accept_list = self.in_filter_accept_list(request.peer_addr_kind, request.peer_addr);
+        match event_handler.accept_connection(&request) {
+            ConnectionDecision::Accept => None,
+            ConnectionDecision::Reject(reason) => {
+                info!("[host] connection {:?} rejected by connection policy", request.handle);
+                Some(reason)
+            }
         }
     }
 
@@ -227,7 +612,16 @@ where
         T: ControllerCmdSync<C>,
     {
         let _ = self.initialized.get().await;
-        let ret = cmd.exec(&self.controller).await?;
+        let ret = match self.watchdog_timeout {
+            Some(timeout) => match with_timeout(timeout, cmd.exec(&self.controller)).await {
+                Ok(ret) => ret?,
+                Err(_) => {
+                    self.watchdog_expired();
+                    return Err(Error::Timeout.into());
+                }
+            },
+            None => cmd.exec(&self.controller).await?,
+        };
         Ok(ret)
     }
 
@@ -238,7 +632,16 @@ where
         T: ControllerCmdAsync<C>,
     {
         let _ = self.initialized.get().await;
-        cmd.exec(&self.controller).await?;
+        match self.watchdog_timeout {
+            Some(timeout) => match with_timeout(timeout, cmd.exec(&self.controller)).await {
+                Ok(ret) => ret?,
+                Err(_) => {
+                    self.watchdog_expired();
+                    return Err(Error::Timeout.into());
+                }
+            },
+            None => cmd.exec(&self.controller).await?,
+        }
         Ok(())
     }
 
@@ -286,6 +689,59 @@ where
         true
     }
 
+    /// Apply the connection policy to a connection reported by the controller and register it.
+    async fn connection_complete(&self, event_handler: &dyn EventHandler, status: Status, request: ConnectionRequest)
+    where
+        T: ControllerCmdSync<Disconnect>,
+    {
+        if status.to_result().is_ok() {
+            if let Some(reason) = self.check_connection_policy(event_handler, request) {
+                let _ = self.command(Disconnect::new(request.handle, reason)).await;
+                match request.role {
+                    LeConnRole::Peripheral => self.advertise_state.reset(),
+                    LeConnRole::Central => self.connect_command_state.canceled(),
+                }
+                return;
+            }
+        }
+        if !self.handle_connection(
+            status,
+            request.handle,
+            request.peer_addr_kind,
+            request.peer_addr,
+            request.role,
+        ) {
+            let _ = self
+                .command(Disconnect::new(
+                    request.handle,
+                    DisconnectReason::RemoteDeviceTerminatedConnLowResources,
+                ))
+                .await;
+            self.connect_command_state.canceled();
+        } else if status.to_result().is_ok() {
+            // The event is received right after the first connection event.
+            let _ = self.connections.update_event_timing(request.handle, |_| {
+                Ok(ConnectionEventTiming {
+                    event_counter: 0,
+                    anchor: Instant::now(),
+                    interval: request.conn_interval,
+                    reported: false,
+                })
+            });
+        }
+    }
+
+    /// Allocate a packet for an inbound PDU of `len` bytes.
+    ///
+    /// The first K-frame of an SDU is reused to reassemble the SDU, so dynamic channels always get a full packet.
+    fn allocate_pdu(channel: u16, len: u16) -> Option<P::Packet> {
+        if channel < L2CAP_CID_DYN_START {
+            P::allocate_sized(len as usize)
+        } else {
+            P::allocate()
+        }
+    }
+
     fn handle_acl(&self, acl: AclPacket<'_>, event_handler: &dyn EventHandler) -> Result<(), Error> {
         self.connections.received(acl.handle())?;
         let handle = acl.handle();
@@ -297,6 +753,7 @@ where
                 if header.channel < L2CAP_CID_DYN_START
                     && !(&[L2CAP_CID_LE_U_SIGNAL, L2CAP_CID_ATT, L2CAP_CID_LE_U_SECURITY_MANAGER]
                         .contains(&header.channel))
+                    && !self.channels.has_fixed(handle, header.channel)
                 {
                     warn!("[host] unsupported l2cap channel id {}", header.channel);
                     return Err(Error::NotSupported);
@@ -348,7 +805,7 @@ where
                         return Ok(());
                     }
 
-                    let Some(packet) = P::allocate() else {
+                    let Some(packet) = Self::allocate_pdu(header.channel, header.length) else {
                         warn!("[host] no memory for packets on channel {}", header.channel);
                         return Err(Error::OutOfMemory);
                     };
@@ -395,7 +852,7 @@ where
                     if let Some((state, pdu)) = result {
                         (state, pdu)
                     } else {
-                        let Some(packet) = P::allocate() else {
+                        let Some(packet) = Self::allocate_pdu(header.channel, header.length) else {
                             warn!("[host] no memory for packets on channel {}", header.channel);
                             return Err(Error::OutOfMemory);
                         };
@@ -526,6 +983,9 @@ where
                     return Err(e);
                 }
             },
+            chan if self.channels.has_fixed(acl.handle(), chan) => {
+                self.channels.dispatch_fixed(acl.handle(), chan, pdu)?;
+            }
             chan => {
                 debug!(
                     "[host] conn {:?} attempted to use unsupported l2cap channel {}, ignoring",
@@ -545,33 +1005,69 @@ where
         identifier: u8,
         signal: &D,
         p_buf: &mut [u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        self.l2cap_signal_with_cids(conn, identifier, signal, &[], p_buf).await
+    }
+
+    /// Send an L2CAP signal followed by a list of channel identifiers, as used by the credit based
+    /// connection signals.
+    pub(crate) async fn l2cap_signal_with_cids<D: L2capSignal>(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        signal: &D,
+        cids: &[u16],
+        p_buf: &mut [u8],
     ) -> Result<(), BleHostError<T::Error>> {
         //trace!(
         //    "[l2cap] sending control signal (req = {}) signal: {:?}",
         //    identifier,
         //    signal
         //);
-        let header = L2capSignalHeader {
-            identifier,
-            code: D::code(),
-            length: signal.size() as u16,
-        };
-        let l2cap = L2capHeader {
-            channel: D::channel(),
-            length: header.size() as u16 + header.length,
-        };
-
-        let mut w = WriteCursor::new(p_buf);
-        w.write_hci(&l2cap)?;
-        w.write_hci(&header)?;
-        w.write_hci(signal)?;
+        let data = encode_signal(identifier, signal, cids, p_buf)?;
+        let mut sender = self.l2cap(conn, data.len() as u16, 1).await?;
+        sender.send(data).await?;
 
-        let mut sender = self.l2cap(conn, w.len() as u16, 1).await?;
-        sender.send(w.finish()).await?;
+        Ok(())
+    }
 
+    /// Send an L2CAP signal without waiting, returning [`Error::Busy`] if it cannot be sent right away.
+    pub(crate) fn try_l2cap_signal<D: L2capSignal>(
+        &self,
+        conn: ConnHandle,
+        identifier: u8,
+        signal: &D,
+        p_buf: &mut [u8],
+    ) -> Result<(), BleHostError<T::Error>>
+    where
+        T: blocking::Controller,
+    {
+        let data = encode_signal(identifier, signal, &[], p_buf)?;
+        let mut sender = self.try_l2cap(conn, data.len() as u16, 1)?;
+        sender.try_send(data)?;
         Ok(())
     }
 
+    /// Mark the host initialized without a controller, with `acl_max_num` ACL buffers of `acl_max_len` bytes.
+    #[cfg(test)]
+    pub(crate) fn init_without_controller(&self, acl_max_len: u16, acl_max_num: u8) {
+        let info = ControllerInfo {
+            le_features: LeFeatureMask::new(),
+            le_states: unwrap!(LeStates::from_hci_bytes(&[0; 8]).ok()).0,
+            acl_max_len,
+            acl_max_num,
+            filter_accept_list_size: 0,
+            max_adv_data_len: 31,
+            iso_max_len: 0,
+            iso_max_num: 0,
+        };
+        let _ = self.initialized.init(InitialState {
+            acl_max: acl_max_len as usize,
+            info,
+        });
+        self.connections.set_link_credits(acl_max_num as usize);
+    }
+
     // Request to an L2CAP payload of len to the HCI controller for a connection.
     //
     // This function will request the appropriate number of ACL packets to be sent and
@@ -586,7 +1082,7 @@ where
         let acl_max = self.initialized.get().await.acl_max as u16;
         let len = len + (4 * n_packets);
         let n_acl = len.div_ceil(acl_max);
-        let grant = poll_fn(|cx| self.connections.poll_request_to_send(handle, n_acl as usize, Some(cx))).await?;
+        let grant = self.connections.request_to_send(handle, n_acl as usize).await?;
         trace!("[host] granted send packets = {}, len = {}", n_packets, len);
         Ok(L2capSender {
             controller: &self.controller,
@@ -606,21 +1102,35 @@ where
         len: u16,
         n_packets: u16,
     ) -> Result<L2capSender<'_, 'd, T, P::Packet>, BleHostError<T::Error>> {
+        match self.poll_l2cap(handle, len, n_packets, None) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(Error::Busy.into()),
+        }
+    }
+
+    // Like try_l2cap, but registers the context, if any, to be woken when ACL packets may be available.
+    //
+    // A pending call does not hold the link's place in line, as the caller may stop polling at any time.
+    pub(crate) fn poll_l2cap(
+        &self,
+        handle: ConnHandle,
+        len: u16,
+        n_packets: u16,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<Result<L2capSender<'_, 'd, T, P::Packet>, BleHostError<T::Error>>> {
         let acl_max = self.initialized.try_get().map(|i| i.acl_max).unwrap_or(27) as u16;
         let len = len + (4 * n_packets);
         let n_acl = len.div_ceil(acl_max);
-        let grant = match self.connections.poll_request_to_send(handle, n_acl as usize, None) {
+        let grant = match self.connections.poll_request_to_send(handle, n_acl as usize, cx, false) {
             Poll::Ready(res) => res?,
-            Poll::Pending => {
-                return Err(Error::Busy.into());
-            }
+            Poll::Pending => return Poll::Pending,
         };
-        Ok(L2capSender {
+        Poll::Ready(Ok(L2capSender {
             controller: &self.controller,
             handle,
             grant,
             fragment_size: acl_max,
-        })
+        }))
     }
 
     pub(crate) async fn send_conn_param_update_req(
@@ -639,6 +1149,258 @@ where
         self.channels.send_conn_param_update_res(handle, self, param).await
     }
 
+    /// Stop advertising and scanning that no longer run after a controller reset.
+    pub(crate) fn stop_radio_activity(&self, advertising: bool, scanning: bool) {
+        if advertising {
+            self.advertise_state.reset();
+            self.activity.borrow_mut().advertising_stopped(None);
+            self.advertise_command_state.canceled();
+        }
+        if scanning {
+            self.activity.borrow_mut().scan_stopped();
+            self.scan_command_state.canceled();
+        }
+    }
+
+    /// Re-apply the radio state left by the last controller recovery.
+    ///
+    /// Advertising and scanning that cannot be restored are stopped.
+    pub(crate) async fn restore_radio_state(&self) -> RestoredState
+    where
+        T: ControllerCmdSync<LeAddDeviceToResolvingList>
+            + ControllerCmdSync<LeSetAddrResolutionEnable>
+            + ControllerCmdSync<LeSetExtAdvParams>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+            + ControllerCmdSync<LeSetScanParams>
+            + ControllerCmdSync<LeSetExtScanParams>,
+    {
+        let Some(pending) = self.reset_state.pending() else {
+            return self.reset_state.restored();
+        };
+        let restored = RestoredState {
+            resolving_list: self.restore_resolving_list().await,
+            advertising: pending.advertising && self.restore_advertising().await,
+            scanning: pending.scanning && self.restore_scan().await,
+            ..Default::default()
+        };
+        self.reset_state.take_pending();
+        self.stop_radio_activity(
+            pending.advertising && !restored.advertising,
+            pending.scanning && !restored.scanning,
+        );
+        self.reset_state.set_restored(restored);
+        let restored = self.reset_state.restored();
+        info!("[host] radio state restored: {:?}", restored);
+        restored
+    }
+
+    async fn restore_resolving_list(&self) -> bool
+    where
+        T: ControllerCmdSync<LeAddDeviceToResolvingList> + ControllerCmdSync<LeSetAddrResolutionEnable>,
+    {
+        let Some(entry) = self.radio_state.borrow().resolving_list else {
+            return false;
+        };
+        let res = async {
+            self.command(LeAddDeviceToResolvingList::new(
+                entry.kind, entry.addr, entry.irk, [0; 16],
+            ))
+            .await?;
+            self.command(LeSetAddrResolutionEnable::new(true)).await
+        }
+        .await;
+        if res.is_err() {
+            warn!("[host] failed to restore the resolving list");
+        }
+        res.is_ok()
+    }
+
+    async fn restore_advertising(&self) -> bool
+    where
+        T: ControllerCmdSync<LeSetAdvParams>
+            + ControllerCmdSync<LeSetAdvData>
+            + ControllerCmdSync<LeSetScanResponseData>
+            + ControllerCmdSync<LeSetAdvEnable>
+            + ControllerCmdSync<LeSetExtAdvParams>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>,
+    {
+        let Some(saved) = self
+            .radio_state
+            .borrow()
+            .advertising_to_restore(|handle| self.advertise_state.is_advertising(handle))
+        else {
+            return false;
+        };
+        let own_addr_kind = self.address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC);
+
+        let res = async {
+            match &saved {
+                SavedAdvertising::Legacy { kind, set } => {
+                    self.command(LeSetAdvParams::new(
+                        bt_hci_duration(set.params.interval_min),
+                        bt_hci_duration(set.params.interval_max),
+                        *kind,
+                        own_addr_kind,
+                        set.peer.kind,
+                        set.peer.addr,
+                        set.params.channel_map.unwrap_or(AdvChannelMap::ALL),
+                        set.params.filter_policy,
+                    ))
+                    .await?;
+                    if !set.adv_data.is_empty() {
+                        let mut buf = [0; 31];
+                        let to_copy = set.adv_data.len().min(buf.len());
+                        buf[..to_copy].copy_from_slice(&set.adv_data[..to_copy]);
+                        self.command(LeSetAdvData::new(to_copy as u8, buf)).await?;
+                    }
+                    if !set.scan_data.is_empty() {
+                        let mut buf = [0; 31];
+                        let to_copy = set.scan_data.len().min(buf.len());
+                        buf[..to_copy].copy_from_slice(&set.scan_data[..to_copy]);
+                        self.command(LeSetScanResponseData::new(to_copy as u8, buf)).await?;
+                    }
+                    self.command(LeSetAdvEnable::new(true)).await
+                }
+                SavedAdvertising::Extended(sets) => {
+                    let mut handles: heapless::Vec<AdvSet, RESTORABLE_ADV_SETS> = heapless::Vec::new();
+                    for set in sets {
+                        let handle = set.set.adv_handle;
+                        self.command(LeSetExtAdvParams::new(
+                            handle,
+                            set.props,
+                            bt_hci_ext_duration(set.params.interval_min),
+                            bt_hci_ext_duration(set.params.interval_max),
+                            set.params.channel_map.unwrap_or(AdvChannelMap::ALL),
+                            own_addr_kind,
+                            set.peer.kind,
+                            set.peer.addr,
+                            set.params.filter_policy,
+                            set.params.tx_power as i8,
+                            set.params.primary_phy,
+                            0,
+                            set.params.secondary_phy,
+                            0,
+                            false,
+                        ))
+                        .await?;
+                        if let Some(address) = self.address.as_ref() {
+                            self.command(LeSetAdvSetRandomAddr::new(handle, address.addr)).await?;
+                        }
+                        if !set.adv_data.is_empty() {
+                            self.command(LeSetExtAdvData::new(
+                                handle,
+                                Operation::Complete,
+                                set.params.fragment,
+                                &set.adv_data,
+                            ))
+                            .await?;
+                        }
+                        if !set.scan_data.is_empty() {
+                            self.command(LeSetExtScanResponseData::new(
+                                handle,
+                                Operation::Complete,
+                                set.params.fragment,
+                                &set.scan_data,
+                            ))
+                            .await?;
+                        }
+                        // Fits, as there are no more handles than saved sets.
+                        let _ = handles.push(set.set);
+                    }
+                    if handles.is_empty() {
+                        return Err(Error::NotFound.into());
+                    }
+                    self.command(LeSetExtAdvEnable::new(true, &handles)).await
+                }
+            }
+        }
+        .await;
+        if res.is_err() {
+            warn!("[host] failed to restore advertising");
+        }
+        res.is_ok()
+    }
+
+    #[cfg(feature = "scan")]
+    async fn restore_scan(&self) -> bool
+    where
+        T: ControllerCmdSync<LeSetScanParams>
+            + ControllerCmdSync<LeSetScanEnable>
+            + ControllerCmdSync<LeSetExtScanParams>
+            + ControllerCmdSync<LeSetExtScanEnable>,
+    {
+        let Some(scan) = self.radio_state.borrow().scan else {
+            return false;
+        };
+        let own_addr_kind = self.address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC);
+        let filter_policy = if scan.filtered {
+            bt_hci::param::ScanningFilterPolicy::BasicFiltered
+        } else {
+            bt_hci::param::ScanningFilterPolicy::BasicUnfiltered
+        };
+        // Scanning that would have timed out by now is not restarted.
+        let Some(timeout) = scan.remaining(Instant::now()) else {
+            return false;
+        };
+
+        let res = async {
+            if scan.extended {
+                let scanning = bt_hci::param::ScanningPhy {
+                    active_scan: scan.active,
+                    scan_interval: bt_hci_duration(scan.interval),
+                    scan_window: bt_hci_duration(scan.window),
+                };
+                self.command(LeSetExtScanParams::new(
+                    own_addr_kind,
+                    filter_policy,
+                    crate::central::create_phy_params(scanning, scan.phys),
+                ))
+                .await?;
+                self.command(LeSetExtScanEnable::new(
+                    true,
+                    FilterDuplicates::Disabled,
+                    bt_hci_duration(timeout),
+                    bt_hci::param::Duration::from_secs(0),
+                ))
+                .await
+            } else {
+                self.command(LeSetScanParams::new(
+                    if scan.active {
+                        bt_hci::param::LeScanKind::Active
+                    } else {
+                        bt_hci::param::LeScanKind::Passive
+                    },
+                    bt_hci_duration(scan.interval),
+                    bt_hci_duration(scan.window),
+                    own_addr_kind,
+                    filter_policy,
+                ))
+                .await?;
+                self.command(LeSetScanEnable::new(true, true)).await
+            }
+        }
+        .await;
+        if res.is_err() {
+            warn!("[host] failed to restore scanning");
+        }
+        res.is_ok()
+    }
+
+    #[cfg(not(feature = "scan"))]
+    async fn restore_scan(&self) -> bool {
+        false
+    }
+
+    /// Capabilities reported by the controller, available once the host is initialized.
+    pub(crate) fn controller_info(&self) -> Option<ControllerInfo> {
+        self.initialized.try_get().map(|i| i.info)
+    }
+
     /// Read current host metrics
     pub(crate) fn metrics<F: FnOnce(&HostMetrics) -> R, R>(&self, f: F) -> R {
         let m = self.metrics.borrow();
@@ -651,6 +1413,8 @@ where
         debug!("[host] connect events: {}", m.connect_events);
         debug!("[host] disconnect events: {}", m.disconnect_events);
         debug!("[host] rx errors: {}", m.rx_errors);
+        debug!("[host] watchdog timeouts: {}", m.watchdog_timeouts);
+        debug!("[host] controller resets: {}", m.controller_resets);
         self.connections.log_status(verbose);
         self.channels.log_status(verbose);
     }
@@ -678,19 +1442,165 @@ pub struct TxRunner<'d, C, P: PacketPool> {
     stack: &'d Stack<'d, C, P>,
 }
 
+/// HCI event code of LE Meta events.
+const LE_META_EVENT: u8 = 0x3e;
+
+/// Selects the raw HCI events passed to [`EventHandler::on_hci_event`].
+///
+/// Only events enabled in the controller event masks are reported by the controller.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HciEventFilter {
+    events: [u32; 8],
+    le_subevents: [u32; 8],
+}
+
+impl HciEventFilter {
+    /// A filter that selects no events.
+    pub const fn new() -> Self {
+        Self {
+            events: [0; 8],
+            le_subevents: [0; 8],
+        }
+    }
+
+    /// Select all events with the given event code, including all subevents of the LE Meta event (0x3e).
+    pub const fn event(mut self, code: u8) -> Self {
+        self.events[code as usize / 32] |= 1 << (code % 32);
+        self
+    }
+
+    /// Select the LE Meta event with the given subevent code.
+    pub const fn le_subevent(mut self, subevent: u8) -> Self {
+        self.le_subevents[subevent as usize / 32] |= 1 << (subevent % 32);
+        self
+    }
+
+    /// Whether no events are selected.
+    pub const fn is_empty(&self) -> bool {
+        let mut i = 0;
+        while i < 8 {
+            if self.events[i] != 0 || self.le_subevents[i] != 0 {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Whether an event with the given code, and subevent code for LE Meta events, is selected.
+    pub const fn matches(&self, code: u8, subevent: Option<u8>) -> bool {
+        if self.events[code as usize / 32] & (1 << (code % 32)) != 0 {
+            return true;
+        }
+        match subevent {
+            Some(subevent) if code == LE_META_EVENT => {
+                self.le_subevents[subevent as usize / 32] & (1 << (subevent % 32)) != 0
+            }
+            _ => false,
+        }
+    }
+}
+
 /// Event handler.
 pub trait EventHandler {
     /// Handle vendor events
     fn on_vendor(&self, vendor: &Vendor) {}
+    /// Raw HCI events to pass to [`EventHandler::on_hci_event`], read once when the runner starts.
+    fn hci_event_filter(&self) -> HciEventFilter {
+        HciEventFilter::new()
+    }
+    /// Observe a raw HCI event selected by [`EventHandler::hci_event_filter`].
+    ///
+    /// Called before the host processes the event. For LE Meta events `subevent` is the subevent code,
+    /// and `params` are the event parameters following the event header and subevent code.
+    fn on_hci_event(&self, code: u8, subevent: Option<u8>, params: &[u8]) {}
     /// Handle advertising reports
     #[cfg(feature = "scan")]
     fn on_adv_reports(&self, reports: bt_hci::param::LeAdvReportsIter) {}
     /// Handle extended advertising reports
     #[cfg(feature = "scan")]
     fn on_ext_adv_reports(&self, reports: bt_hci::param::LeExtAdvReportsIter) {}
+    /// Handle an advertising report from a legacy or extended scan, including all the metadata
+    /// reported by the controller.
+    #[cfg(feature = "scan")]
+    fn on_scan_report(&self, report: &crate::scan::ScanReport<'_>) {}
+    /// Handle an event of one of the periodic advertising syncs.
+    #[cfg(feature = "scan")]
+    fn on_periodic_sync_event(&self, event: &crate::periodic_sync::PeriodicSyncEvent<'_>) {}
+    /// Handle an ISO data packet received on an isochronous stream.
+    fn on_iso_data(&self, data: &crate::iso::IsoData<'_>) {}
+    /// Decide whether to keep a newly established connection.
+    ///
+    /// Called when the controller reports a connection, before the connection is handed to the application
+    /// and before any ATT, L2CAP or SMP traffic on it is processed. Rejected connections are disconnected
+    /// immediately. A rejected peripheral connection has already ended advertising, so a pending
+    /// [`Advertiser::accept`](crate::peripheral::Advertiser::accept) returns [`Error::Timeout`].
+    fn accept_connection(&self, request: &ConnectionRequest) -> ConnectionDecision {
+        ConnectionDecision::Accept
+    }
+}
+
+/// A newly established connection presented to [`EventHandler::accept_connection`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct ConnectionRequest {
+    /// Handle of the connection.
+    pub handle: ConnHandle,
+    /// Local role in the connection.
+    pub role: LeConnRole,
+    /// Address kind of the peer.
+    pub peer_addr_kind: AddrKind,
+    /// Address of the peer, the identity address if the controller resolved it.
+    pub peer_addr: BdAddr,
+    /// Connection interval.
+    pub conn_interval: Duration,
+    /// Peripheral latency, in connection events.
+    pub peripheral_latency: u16,
+    /// Supervision timeout.
+    pub supervision_timeout: Duration,
+    /// Whether the peer is in the filter accept list set by the host.
+    ///
+    /// `None` if the host does not know the list contents, for instance because it holds more
+    /// entries than the host tracks or an update failed.
+    pub in_filter_accept_list: Option<bool>,
 }
 
-struct DummyHandler;
+impl ConnectionRequest {
+    fn new(
+        handle: ConnHandle,
+        role: LeConnRole,
+        peer_addr_kind: AddrKind,
+        peer_addr: BdAddr,
+        conn_interval: Duration,
+        peripheral_latency: u16,
+        supervision_timeout: Duration,
+    ) -> Self {
+        Self {
+            handle,
+            role,
+            peer_addr_kind,
+            peer_addr,
+            conn_interval,
+            peripheral_latency,
+            supervision_timeout,
+            in_filter_accept_list: None,
+        }
+    }
+}
+
+/// Outcome of [`EventHandler::accept_connection`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionDecision {
+    /// Keep the connection.
+    Accept,
+    /// Disconnect the peer with the given reason.
+    Reject(DisconnectReason),
+}
+
+pub(crate) struct DummyHandler;
 impl EventHandler for DummyHandler {}
 
 impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
@@ -718,6 +1628,9 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
             + ControllerCmdSync<HostBufferSize>
             + ControllerCmdAsync<LeConnUpdate>
             + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
             + ControllerCmdSync<SetControllerToHostFlowControl>
             + ControllerCmdSync<Reset>
             + ControllerCmdSync<LeCreateConnCancel>
@@ -727,6 +1640,7 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
             + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
             + for<'t> ControllerCmdSync<HostNumberOfCompletedPackets<'t>>
             + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeReadBufferSizeV2>
             + ControllerCmdSync<LeLongTermKeyRequestReply>
             + ControllerCmdAsync<LeEnableEncryption>
             + ControllerCmdSync<ReadBdAddr>,
@@ -744,6 +1658,9 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
             + ControllerCmdSync<LeSetEventMask>
             + ControllerCmdSync<LeSetRandomAddr>
             + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
             + ControllerCmdSync<HostBufferSize>
             + ControllerCmdAsync<LeConnUpdate>
             + ControllerCmdSync<SetControllerToHostFlowControl>
@@ -755,6 +1672,7 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
             + ControllerCmdSync<Reset>
             + ControllerCmdSync<LeCreateConnCancel>
             + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeReadBufferSizeV2>
             + ControllerCmdSync<LeLongTermKeyRequestReply>
             + ControllerCmdAsync<LeEnableEncryption>
             + ControllerCmdSync<ReadBdAddr>,
@@ -798,6 +1716,7 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
     {
         const MAX_HCI_PACKET_LEN: usize = 259;
         let host = &self.stack.host;
+        let hci_event_filter = event_handler.hci_event_filter();
         // use embassy_time::Instant;
         // let mut last = Instant::now();
         loop {
@@ -838,50 +1757,51 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                     }
                 },
                 Ok(ControllerToHostPacket::Event(event)) => {
+                    if !hci_event_filter.is_empty() {
+                        let code = event.kind.0;
+                        let (subevent, params) = match event.data.split_first() {
+                            Some((subevent, params)) if code == LE_META_EVENT => (Some(*subevent), params),
+                            _ => (None, event.data),
+                        };
+                        if hci_event_filter.matches(code, subevent) {
+                            event_handler.on_hci_event(code, subevent, params);
+                        }
+                    }
                     match event.kind {
                         EventKind::Le => {
                             let event = unwrap!(LeEventPacket::from_hci_bytes_complete(event.data));
                             match event.kind {
                                 LeEventKind::LeConnectionComplete => {
                                     let e = unwrap!(LeConnectionComplete::from_hci_bytes_complete(event.data));
-                                    if !host.handle_connection(
-                                        e.status,
+                                    let request = ConnectionRequest::new(
                                         e.handle,
+                                        e.role,
                                         e.peer_addr_kind,
                                         e.peer_addr,
-                                        e.role,
-                                    ) {
-                                        let _ = host
-                                            .command(Disconnect::new(
-                                                e.handle,
-                                                DisconnectReason::RemoteDeviceTerminatedConnLowResources,
-                                            ))
-                                            .await;
-                                        host.connect_command_state.canceled();
-                                    }
+                                        Duration::from_micros(e.conn_interval.as_micros()),
+                                        e.peripheral_latency,
+                                        Duration::from_micros(e.supervision_timeout.as_micros()),
+                                    );
+                                    host.connection_complete(event_handler, e.status, request).await;
                                 }
                                 LeEventKind::LeEnhancedConnectionComplete => {
                                     let e = unwrap!(LeEnhancedConnectionComplete::from_hci_bytes_complete(event.data));
-                                    if !host.handle_connection(
-                                        e.status,
+                                    let request = ConnectionRequest::new(
                                         e.handle,
+                                        e.role,
                                         e.peer_addr_kind,
                                         e.peer_addr,
-                                        e.role,
-                                    ) {
-                                        let _ = host
-                                            .command(Disconnect::new(
-                                                e.handle,
-                                                DisconnectReason::RemoteDeviceTerminatedConnLowResources,
-                                            ))
-                                            .await;
-                                        host.connect_command_state.canceled();
-                                    }
+                                        Duration::from_micros(e.conn_interval.as_micros()),
+                                        e.peripheral_latency,
+                                        Duration::from_micros(e.supervision_timeout.as_micros()),
+                                    );
+                                    host.connection_complete(event_handler, e.status, request).await;
                                 }
                                 LeEventKind::LeScanTimeout => {}
                                 LeEventKind::LeAdvertisingSetTerminated => {
                                     let set = unwrap!(LeAdvertisingSetTerminated::from_hci_bytes_complete(event.data));
                                     host.advertise_state.terminate(set.adv_handle);
+                                    host.activity.borrow_mut().advertising_stopped(Some(set.adv_handle));
                                 }
                                 LeEventKind::LeExtendedAdvertisingReport => {
                                     #[cfg(feature = "scan")]
@@ -889,6 +1809,9 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                                         let data =
                                             unwrap!(LeExtendedAdvertisingReport::from_hci_bytes_complete(event.data));
                                         event_handler.on_ext_adv_reports(data.reports.iter());
+                                        for report in crate::scan::ScanReports::extended(event.data) {
+                                            event_handler.on_scan_report(&report);
+                                        }
                                     }
                                 }
                                 LeEventKind::LeAdvertisingReport => {
@@ -896,6 +1819,36 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                                     {
                                         let data = unwrap!(LeAdvertisingReport::from_hci_bytes_complete(event.data));
                                         event_handler.on_adv_reports(data.reports.iter());
+                                        for report in crate::scan::ScanReports::legacy(event.data) {
+                                            event_handler.on_scan_report(&report);
+                                        }
+                                    }
+                                }
+                                #[cfg(feature = "scan")]
+                                LeEventKind::LePeriodicAdvertisingSyncEstablished => {
+                                    match PeriodicSyncEvent::established(event.data) {
+                                        Ok(result) => {
+                                            host.periodic_sync.established(result);
+                                            if let Ok(info) = result {
+                                                let established = PeriodicSyncEvent::Established(info);
+                                                event_handler.on_periodic_sync_event(&established);
+                                            }
+                                        }
+                                        Err(e) => warn!("[host] malformed periodic sync established event: {:?}", e),
+                                    }
+                                }
+                                #[cfg(feature = "scan")]
+                                LeEventKind::LePeriodicAdvertisingReport => {
+                                    match PeriodicSyncEvent::report(event.data) {
+                                        Ok(report) => event_handler.on_periodic_sync_event(&report),
+                                        Err(e) => warn!("[host] malformed periodic advertising report: {:?}", e),
+                                    }
+                                }
+                                #[cfg(feature = "scan")]
+                                LeEventKind::LePeriodicAdvertisingSyncLost => {
+                                    match PeriodicSyncEvent::lost(event.data) {
+                                        Ok(lost) => event_handler.on_periodic_sync_event(&lost),
+                                        Err(e) => warn!("[host] malformed periodic sync lost event: {:?}", e),
                                     }
                                 }
                                 LeEventKind::LeLongTermKeyRequest => {
@@ -924,6 +1877,19 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                                             event.handle, e
                                         );
                                     } else {
+                                        let now = Instant::now();
+                                        let interval = Duration::from_micros(event.conn_interval.as_micros());
+                                        let _ = host.connections.update_event_timing(event.handle, |timing| {
+                                            Ok(match timing {
+                                                Some(timing) => timing.rebase(now, interval),
+                                                None => ConnectionEventTiming {
+                                                    event_counter: 0,
+                                                    anchor: now,
+                                                    interval,
+                                                    reported: false,
+                                                },
+                                            })
+                                        });
                                         let _ = host.connections.post_handle_event(
                                             event.handle,
                                             ConnectionEvent::ConnectionParamsUpdated {
@@ -948,6 +1914,14 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                                         },
                                     );
                                 }
+                                LeEventKind::LeReadRemoteFeaturesComplete => {
+                                    let e = unwrap!(LeReadRemoteFeaturesComplete::from_hci_bytes_complete(event.data));
+                                    let features = e.status.to_result().map(|_| e.le_features);
+                                    if let Err(err) = features {
+                                        warn!("[host] error reading remote features for {:?}: {:?}", e.handle, err);
+                                    }
+                                    let _ = host.connections.set_remote_features(e.handle, features);
+                                }
                                 LeEventKind::LeRemoteConnectionParameterRequest => {
                                     let event = unwrap!(LeRemoteConnectionParameterRequest::from_hci_bytes_complete(
                                         event.data
@@ -991,6 +1965,7 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                             .unwrap_or(Status::UNSPECIFIED);
                             let _ = host.connections.disconnected(handle, reason);
                             let _ = host.channels.disconnected(handle);
+                            host.iso_credits.disconnected(handle);
                             let mut m = host.metrics.borrow_mut();
                             m.disconnect_events = m.disconnect_events.wrapping_add(1);
                         }
@@ -1000,7 +1975,14 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                             for entry in c.completed_packets.iter() {
                                 match (entry.handle(), entry.num_completed_packets()) {
                                     (Ok(handle), Ok(completed)) => {
-                                        let _ = host.connections.confirm_sent(handle, completed as usize);
+                                        // Handles that are not ACL links may belong to isochronous streams.
+                                        if let Err(Error::NotFound) =
+                                            host.connections.confirm_sent(handle, completed as usize)
+                                        {
+                                            if !host.iso_credits.release(handle, completed as usize) {
+                                                warn!("[host] completed packets for unknown handle {:?}", handle);
+                                            }
+                                        }
                                     }
                                     (Ok(handle), Err(e)) => {
                                         warn!("[host] error processing completed packets for {:?}: {:?}", handle, e);
@@ -1016,10 +1998,41 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
                         EventKind::EncryptionChangeV1 => {
                             host.connections.handle_security_hci_event(event)?;
                         }
+                        EventKind::ReadRemoteVersionInformationComplete => {
+                            let e = unwrap!(ReadRemoteVersionInformationComplete::from_hci_bytes_complete(
+                                event.data
+                            ));
+                            let version = e.status.to_result().map(|_| RemoteVersion {
+                                version: e.version,
+                                company_id: e.company_id,
+                                subversion: e.subversion,
+                            });
+                            if let Err(err) = version {
+                                warn!("[host] error reading remote version for {:?}: {:?}", e.handle, err);
+                            }
+                            let _ = host.connections.set_remote_version(e.handle, version);
+                        }
+                        EventKind::HardwareError => {
+                            let e = unwrap!(HardwareError::from_hci_bytes_complete(event.data));
+                            warn!("[host] controller hardware error {}, requesting reset", e.hardware_code);
+                            host.reset_state.request();
+                        }
                         // Ignore
                         _ => {}
                     }
                 }
+                Ok(ControllerToHostPacket::Iso(iso)) => {
+                    let mut buf = [0; MAX_HCI_PACKET_LEN];
+                    let mut w = WriteCursor::new(&mut buf);
+                    let len = match w.write_hci(&iso) {
+                        Ok(_) => w.len(),
+                        Err(_) => 0,
+                    };
+                    match IsoData::parse(&buf[..len]) {
+                        Ok(data) => event_handler.on_iso_data(&data),
+                        Err(e) => warn!("[host] malformed ISO data packet: {:?}", e),
+                    }
+                }
                 // Ignore
                 Ok(_) => {}
                 Err(e) => {
@@ -1031,32 +2044,28 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
 }
 
 impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
-    /// Run the control loop for the host
-    pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>>
+    /// Reset the controller, program the host configuration and read the controller capabilities.
+    ///
+    /// This is used both at startup and when recovering from a controller reset.
+    async fn init_controller(&self) -> Result<ControllerInfo, BleHostError<C::Error>>
     where
-        C: ControllerCmdSync<Disconnect>
-            + ControllerCmdSync<SetEventMask>
+        C: ControllerCmdSync<SetEventMask>
             + ControllerCmdSync<SetEventMaskPage2>
             + ControllerCmdSync<LeSetEventMask>
             + ControllerCmdSync<LeSetRandomAddr>
             + ControllerCmdSync<HostBufferSize>
-            + ControllerCmdAsync<LeConnUpdate>
             + ControllerCmdSync<LeReadFilterAcceptListSize>
-            + ControllerCmdSync<SetControllerToHostFlowControl>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
             + ControllerCmdSync<Reset>
-            + ControllerCmdSync<LeCreateConnCancel>
-            + for<'t> ControllerCmdSync<LeSetAdvEnable>
-            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
-            + ControllerCmdSync<LeSetScanEnable>
-            + ControllerCmdSync<LeSetExtScanEnable>
-            + for<'t> ControllerCmdSync<HostNumberOfCompletedPackets<'t>>
             + ControllerCmdSync<LeReadBufferSize>
-            + ControllerCmdSync<LeLongTermKeyRequestReply>
-            + ControllerCmdAsync<LeEnableEncryption>
-            + ControllerCmdSync<ReadBdAddr>,
+            + ControllerCmdSync<LeReadBufferSizeV2>,
     {
         let host = &self.stack.host;
         Reset::new().exec(&host.controller).await?;
+        // A controller reset empties the filter accept list.
+        host.filter_accept_list.replace(Some(heapless::Vec::new()));
 
         if let Some(addr) = host.address {
             LeSetRandomAddr::new(addr.addr).exec(&host.controller).await?;
@@ -1069,6 +2078,7 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                 .enable_conn_complete(true)
                 .enable_hardware_error(true)
                 .enable_disconnection_complete(true)
+                .enable_read_remote_version_information_complete(true)
                 .enable_encryption_change_v1(true),
         )
         .exec(&host.controller)
@@ -1087,9 +2097,13 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                 .enable_le_adv_report(true)
                 .enable_le_scan_timeout(true)
                 .enable_le_ext_adv_report(true)
+                .enable_le_periodic_adv_sync_established(true)
+                .enable_le_periodic_adv_report(true)
+                .enable_le_periodic_adv_sync_lost(true)
                 .enable_le_long_term_key_request(true)
                 .enable_le_phy_update_complete(true)
                 .enable_le_remote_conn_parameter_request(true)
+                .enable_le_read_remote_features_complete(true)
                 .enable_le_data_length_change(true),
         )
         .exec(&host.controller)
@@ -1101,16 +2115,42 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
             P::capacity(),
         );
 
-        let ret = LeReadFilterAcceptListSize::new().exec(&host.controller).await?;
-        info!("[host] filter accept list size: {}", ret);
+        let le_features = LeReadLocalSupportedFeatures::new().exec(&host.controller).await?;
+        let le_states = LeReadSupportedStates::new().exec(&host.controller).await?;
 
-        let ret = LeReadBufferSize::new().exec(&host.controller).await?;
+        let filter_accept_list_size = LeReadFilterAcceptListSize::new().exec(&host.controller).await?;
+        info!("[host] filter accept list size: {}", filter_accept_list_size);
+
+        let max_adv_data_len = if le_features.supports_le_extended_advertising() {
+            LeReadMaxAdvDataLength::new().exec(&host.controller).await?
+        } else {
+            31
+        };
+        info!("[host] max advertising data length: {}", max_adv_data_len);
+
+        // Controllers supporting ISO report their ISO buffers separately with version 2 of the command.
+        let (acl_max_len, acl_max_num, iso_max_len, iso_max_num) =
+            match LeReadBufferSizeV2::new().exec(&host.controller).await {
+                Ok(ret) => (
+                    ret.le_acl_data_packet_length,
+                    ret.total_num_le_acl_data_packets,
+                    ret.iso_data_packet_length,
+                    ret.total_num_iso_data_packets,
+                ),
+                Err(_) => {
+                    let ret = LeReadBufferSize::new().exec(&host.controller).await?;
+                    (ret.le_acl_data_packet_length, ret.total_num_le_acl_data_packets, 0, 0)
+                }
+            };
         info!(
             "[host] setting txq to {}, fragmenting at {}",
-            ret.total_num_le_acl_data_packets as usize, ret.le_acl_data_packet_length as usize
+            acl_max_num as usize, acl_max_len as usize
         );
-        host.connections
-            .set_link_credits(ret.total_num_le_acl_data_packets as usize);
+        host.connections.set_link_credits(acl_max_num as usize);
+        if iso_max_num > 0 {
+            info!("[host] {} ISO buffers of size {}", iso_max_num, iso_max_len);
+        }
+        host.iso_credits.set(iso_max_num as usize);
 
         const ACL_LEN: u16 = 255;
         const ACL_N: u16 = 1;
@@ -1130,8 +2170,142 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                 }
         */
 
+        Ok(ControllerInfo {
+            le_features,
+            le_states,
+            acl_max_len,
+            acl_max_num,
+            filter_accept_list_size,
+            max_adv_data_len,
+            iso_max_len,
+            iso_max_num,
+        })
+    }
+
+    /// Tear down host state tied to the controller, then reset and re-initialize it.
+    ///
+    /// The filter accept list is restored. Advertising and scanning that are still active are kept if a task
+    /// waits in [`Stack::radio_state_restored`] to restore them, together with the resolving list.
+    async fn recover(&self) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<SetEventMask>
+            + ControllerCmdSync<SetEventMaskPage2>
+            + ControllerCmdSync<LeSetEventMask>
+            + ControllerCmdSync<LeSetRandomAddr>
+            + ControllerCmdSync<HostBufferSize>
+            + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
+            + ControllerCmdSync<Reset>
+            + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeReadBufferSizeV2>
+            + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let host = &self.stack.host;
+        warn!("[host] resetting controller");
+
+        let restorer = host.reset_state.has_restorer();
+        let advertising =
+            restorer && host.advertise_command_state.is_active() && host.radio_state.borrow().advertising.is_some();
+        let scanning = restorer && host.scan_command_state.is_active() && host.radio_state.borrow().scan.is_some();
+        let filter_accept_list = host.filter_accept_list.borrow().clone();
+
+        // A reset drops all links, advertising sets and pending procedures in the controller.
+        host.connections.disconnected_all(Status::HARDWARE_FAILURE, |handle| {
+            let _ = host.channels.disconnected(handle);
+        });
+        host.connect_command_state.canceled();
+        host.stop_radio_activity(!advertising, !scanning);
+
+        let init = self.init_controller();
+        match host.watchdog_timeout {
+            Some(timeout) => match with_timeout(timeout, init).await {
+                Ok(res) => res?,
+                Err(_) => {
+                    host.watchdog_expired();
+                    return Err(Error::Timeout.into());
+                }
+            },
+            None => init.await?,
+        };
+
+        let restored = RestoredState {
+            random_address: host.address.is_some(),
+            filter_accept_list: match filter_accept_list {
+                Some(list) if !list.is_empty() => self.restore_filter_accept_list(&list).await,
+                _ => false,
+            },
+            ..Default::default()
+        };
+
+        {
+            let mut m = host.metrics.borrow_mut();
+            m.controller_resets = m.controller_resets.wrapping_add(1);
+        }
+        let pending = restorer.then_some(PendingRestore { advertising, scanning });
+        // The restorer may have gone away while the controller was re-initialized.
+        if let Some(pending) = host.reset_state.recovered(restored, pending) {
+            host.stop_radio_activity(pending.advertising, pending.scanning);
+        }
+        info!("[host] controller recovered: {:?}", restored);
+        Ok(())
+    }
+
+    async fn restore_filter_accept_list(&self, list: &[(AddrKind, BdAddr)]) -> bool
+    where
+        C: ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let host = &self.stack.host;
+        for (kind, addr) in list {
+            if host
+                .command(LeAddDeviceToFilterAcceptList::new(*kind, *addr))
+                .await
+                .is_err()
+            {
+                warn!("[host] failed to restore the filter accept list");
+                host.filter_accept_list.replace(None);
+                return false;
+            }
+        }
+        host.filter_accept_list.replace(heapless::Vec::from_slice(list).ok());
+        true
+    }
+
+    /// Run the control loop for the host
+    pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<Disconnect>
+            + ControllerCmdSync<SetEventMask>
+            + ControllerCmdSync<SetEventMaskPage2>
+            + ControllerCmdSync<LeSetEventMask>
+            + ControllerCmdSync<LeSetRandomAddr>
+            + ControllerCmdSync<HostBufferSize>
+            + ControllerCmdAsync<LeConnUpdate>
+            + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
+            + ControllerCmdSync<SetControllerToHostFlowControl>
+            + ControllerCmdSync<Reset>
+            + ControllerCmdSync<LeCreateConnCancel>
+            + for<'t> ControllerCmdSync<LeSetAdvEnable>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
+            + ControllerCmdSync<LeSetScanEnable>
+            + ControllerCmdSync<LeSetExtScanEnable>
+            + for<'t> ControllerCmdSync<HostNumberOfCompletedPackets<'t>>
+            + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeReadBufferSizeV2>
+            + ControllerCmdSync<LeLongTermKeyRequestReply>
+            + ControllerCmdAsync<LeEnableEncryption>
+            + ControllerCmdSync<ReadBdAddr>,
+    {
+        let host = &self.stack.host;
+        let info = self.init_controller().await?;
+
         let _ = host.initialized.init(InitialState {
-            acl_max: ret.le_acl_data_packet_length as usize,
+            acl_max: info.acl_max_len as usize,
+            info,
         });
         info!("[host] initialized");
 
@@ -1149,9 +2323,13 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
         }
 
         loop {
-            match select3(
+            match select4(
                 poll_fn(|cx| host.connections.poll_disconnecting(Some(cx))),
-                poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
+                select3(
+                    poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
+                    host.channels.wait_idle_expired(),
+                    poll_fn(|cx| host.channels.poll_signal_response(cx)),
+                ),
                 select4(
                     poll_fn(|cx| host.connect_command_state.poll_cancelled(cx)),
                     poll_fn(|cx| host.advertise_command_state.poll_cancelled(cx)),
@@ -1165,21 +2343,24 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                         poll_fn(|cx| Poll::<()>::Pending)
                     },
                 ),
+                poll_fn(|cx| host.reset_state.poll_requested(cx)),
             )
             .await
             {
-                Either3::First(request) => {
+                Either4::First(request) => {
                     trace!("[host] poll disconnecting links");
                     match host.command(Disconnect::new(request.handle(), request.reason())).await {
                         Ok(_) => {}
                         Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
+                        // The watchdog has requested a reset, which will drop the link anyway.
+                        Err(BleHostError::BleHost(Error::Timeout)) if host.watchdog_timeout.is_some() => {}
                         Err(e) => {
                             return Err(e);
                         }
                     }
                     request.confirm();
                 }
-                Either3::Second(request) => {
+                Either4::Second(Either3::First(request)) => {
                     trace!("[host] poll disconnecting channels");
                     match request.send(host).await {
                         Ok(_) => {}
@@ -1191,7 +2372,23 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                     }
                     request.confirm();
                 }
-                Either3::Third(states) => match states {
+                Either4::Second(Either3::Second((conn, psm))) => {
+                    // The channel is disconnected on the next iteration, let the application know why.
+                    let _ = host
+                        .connections
+                        .post_handle_event(conn, ConnectionEvent::L2capChannelIdle { psm });
+                }
+                Either4::Second(Either3::Third(response)) => {
+                    trace!("[host] send signal response");
+                    match response.send(host).await {
+                        Ok(_) => {}
+                        Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
+                        Err(e) => {
+                            return Err(e);
+                        }
+                    }
+                }
+                Either4::Third(states) => match states {
                     Either4::First(_) => {
                         trace!("[host] cancel connection create");
                         // trace!("[host] cancelling create connection");
@@ -1208,6 +2405,7 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                         } else {
                             host.command(LeSetAdvEnable::new(false)).await?
                         }
+                        host.activity.borrow_mut().advertising_stopped(None);
                         host.advertise_command_state.canceled();
                     }
                     Either4::Third(ext) => {
@@ -1224,6 +2422,7 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                         } else {
                             host.command(LeSetScanEnable::new(false, false)).await?;
                         }
+                        host.activity.borrow_mut().scan_stopped();
                         host.scan_command_state.canceled();
                     }
                     Either4::Fourth(request) => {
@@ -1234,6 +2433,9 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
                         }
                     }
                 },
+                Either4::Fourth(_) => {
+                    self.recover().await?;
+                }
             }
         }
     }
@@ -1246,7 +2448,20 @@ impl<'d, C: Controller, P: PacketPool> TxRunner<'d, C, P> {
         let params = host.initialized.get().await;
         loop {
             let (conn, pdu) = host.connections.outbound().await;
-            match host.l2cap(conn, pdu.len() as u16, 1).await {
+            let grant = host.l2cap(conn, pdu.len() as u16, 1);
+            let grant = match host.watchdog_timeout {
+                Some(timeout) => match with_timeout(timeout, grant).await {
+                    Ok(grant) => grant,
+                    Err(_) => {
+                        // The controller has not returned any credits in time, the pdu is dropped
+                        // as the reset will tear down the link.
+                        host.watchdog_expired();
+                        continue;
+                    }
+                },
+                None => grant.await,
+            };
+            match grant {
                 Ok(mut sender) => {
                     if let Err(e) = sender.send(pdu.as_ref()).await {
                         warn!("[host] error sending outbound pdu");
@@ -1275,6 +2490,33 @@ pub struct L2capSender<'a, 'd, T: Controller, P> {
     pub(crate) fragment_size: u16,
 }
 
+/// Write an L2CAP signal, followed by a list of channel identifiers, to the buffer.
+pub(crate) fn encode_signal<'b, D: L2capSignal>(
+    identifier: u8,
+    signal: &D,
+    cids: &[u16],
+    p_buf: &'b mut [u8],
+) -> Result<&'b [u8], Error> {
+    let header = L2capSignalHeader {
+        identifier,
+        code: D::code(),
+        length: (signal.size() + 2 * cids.len()) as u16,
+    };
+    let l2cap = L2capHeader {
+        channel: D::channel(),
+        length: header.size() as u16 + header.length,
+    };
+
+    let mut w = WriteCursor::new(p_buf);
+    w.write_hci(&l2cap)?;
+    w.write_hci(&header)?;
+    w.write_hci(signal)?;
+    for cid in cids {
+        w.write(*cid)?;
+    }
+    Ok(w.finish())
+}
+
 impl<'a, 'd, T: Controller, P> L2capSender<'a, 'd, T, P> {
     pub(crate) fn try_send(&mut self, pdu: &[u8]) -> Result<(), BleHostError<T::Error>>
     where
@@ -1348,3 +2590,190 @@ impl<F: FnOnce()> Drop for OnDrop<F> {
         unsafe { self.f.as_ptr().read()() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::l2cap::{parse_cids, CreditConnReq, CreditConnRes};
+
+    #[test]
+    fn iso_credits() {
+        let stream = ConnHandle::new(0x60);
+        let other = ConnHandle::new(0x61);
+        let credits = IsoCredits::new();
+        assert_eq!(
+            embassy_futures::block_on(credits.acquire(stream)),
+            Err(Error::NotSupported)
+        );
+
+        credits.set(3);
+        assert_eq!(embassy_futures::block_on(credits.acquire(stream)), Ok(()));
+        assert_eq!(embassy_futures::block_on(credits.acquire(stream)), Ok(()));
+        assert_eq!(embassy_futures::block_on(credits.acquire(other)), Ok(()));
+        assert!(embassy_futures::poll_once(credits.acquire(stream)).is_pending());
+
+        // Completions for handles without packets in flight are ignored.
+        assert!(!credits.release(ConnHandle::new(1), 1));
+        assert_eq!(credits.inner.borrow().available, 0);
+
+        // Completions are capped by the packets in flight for the stream.
+        assert!(credits.release(stream, 5));
+        assert_eq!(credits.inner.borrow().available, 2);
+        assert!(!credits.release(stream, 1));
+
+        credits.disconnected(other);
+        assert_eq!(credits.inner.borrow().available, 3);
+    }
+
+    #[test]
+    fn adv_state_restart() {
+        let mut handles = [AdvHandleState::None, AdvHandleState::None];
+        let state = AdvState::new(&mut handles);
+        let set = AdvHandle::new(1);
+        state.start(&[AdvSet {
+            adv_handle: AdvHandle::new(0),
+            duration: bt_hci::param::Duration::from_secs(0),
+            max_ext_adv_events: 0,
+        }]);
+
+        // A set that was not started together with the others takes a free slot.
+        state.restart(set);
+        assert!(state.is_advertising(set));
+        assert!(embassy_futures::poll_once(state.wait_terminated(set)).is_pending());
+        state.terminate(set);
+        assert!(embassy_futures::poll_once(state.wait_terminated(set)).is_ready());
+        assert!(embassy_futures::poll_once(state.wait()).is_pending());
+
+        // Restarting the terminated set reuses its slot.
+        state.restart(set);
+        assert!(state.is_advertising(set));
+        assert!(state.is_advertising(AdvHandle::new(0)));
+        state.reset();
+        assert!(embassy_futures::poll_once(state.wait_terminated(set)).is_ready());
+    }
+
+    #[test]
+    fn default_connection_policy_accepts() {
+        let request = ConnectionRequest::new(
+            ConnHandle::new(1),
+            LeConnRole::Peripheral,
+            AddrKind::RANDOM,
+            BdAddr::new([1, 2, 3, 4, 5, 6]),
+            Duration::from_millis(30),
+            0,
+            Duration::from_secs(4),
+        );
+        assert_eq!(request.in_filter_accept_list, None);
+        assert_eq!(DummyHandler.accept_connection(&request), ConnectionDecision::Accept);
+    }
+
+    #[test]
+    fn hci_event_filter() {
+        assert!(HciEventFilter::new().is_empty());
+
+        let filter = HciEventFilter::new().event(0xff).le_subevent(0x02);
+        assert!(!filter.is_empty());
+        assert!(filter.matches(0xff, None));
+        assert!(!filter.matches(0x05, None));
+        assert!(filter.matches(LE_META_EVENT, Some(0x02)));
+        assert!(!filter.matches(LE_META_EVENT, Some(0x0d)));
+        assert!(!filter.matches(0x05, Some(0x02)));
+
+        let filter = HciEventFilter::new().event(LE_META_EVENT);
+        assert!(filter.matches(LE_META_EVENT, Some(0x0d)));
+        assert!(filter.matches(LE_META_EVENT, Some(0x33)));
+    }
+
+    #[test]
+    fn reset_restorer() {
+        let reset = ResetState::new();
+        let pending = PendingRestore {
+            advertising: true,
+            scanning: false,
+        };
+        let restored = RestoredState {
+            random_address: true,
+            filter_accept_list: true,
+            ..Default::default()
+        };
+
+        // Without a restorer, the radio state is handed back to be stopped.
+        assert_eq!(reset.recovered(restored, Some(pending)), Some(pending));
+        assert_eq!(reset.pending(), None);
+        assert_eq!(reset.restored().recoveries, 1);
+
+        reset.set_restorer(true);
+        assert!(reset.has_restorer());
+        assert_eq!(reset.recovered(restored, Some(pending)), None);
+        assert_eq!(reset.pending(), Some(pending));
+        reset.set_restored(RestoredState {
+            advertising: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            reset.restored(),
+            RestoredState {
+                recoveries: 2,
+                random_address: true,
+                filter_accept_list: true,
+                advertising: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(reset.take_pending(), Some(pending));
+        assert_eq!(reset.set_restorer(false), None);
+
+        // A restorer that goes away before restoring hands the radio state back.
+        reset.set_restorer(true);
+        assert_eq!(reset.recovered(restored, Some(pending)), None);
+        assert_eq!(reset.set_restorer(false), Some(pending));
+        assert!(!reset.has_restorer());
+        assert_eq!(reset.pending(), None);
+    }
+
+    #[test]
+    fn credit_connection_signals() {
+        let mut buf = [0; 32];
+        let req = CreditConnReq {
+            spsm: 0x27,
+            mtu: 100,
+            mps: 80,
+            credits: 5,
+        };
+        let data = encode_signal(3, &req, &[0x40, 0x41], &mut buf).unwrap();
+        assert_eq!(
+            data,
+            [16, 0, 5, 0, 0x17, 3, 12, 0, 0x27, 0, 100, 0, 80, 0, 5, 0, 0x40, 0, 0x41, 0]
+        );
+        let (header, data) = L2capSignalHeader::from_hci_bytes(&data[4..]).unwrap();
+        assert_eq!(header.identifier, 3);
+        let (req, cids) = CreditConnReq::from_hci_bytes(data).unwrap();
+        assert_eq!((req.spsm, req.mtu, req.mps, req.credits), (0x27, 100, 80, 5));
+        assert_eq!(parse_cids(cids).unwrap(), [0x40, 0x41]);
+
+        let res = CreditConnRes {
+            mtu: 64,
+            mps: 64,
+            credits: 2,
+            result: 0x0004,
+        };
+        let data = encode_signal(3, &res, &[0x40, 0], &mut buf).unwrap();
+        assert_eq!(
+            data,
+            [16, 0, 5, 0, 0x18, 3, 12, 0, 64, 0, 64, 0, 2, 0, 4, 0, 0x40, 0, 0, 0]
+        );
+        let (_, data) = L2capSignalHeader::from_hci_bytes(&data[4..]).unwrap();
+        let (res, cids) = CreditConnRes::from_hci_bytes(data).unwrap();
+        assert_eq!((res.mtu, res.mps, res.credits, res.result), (64, 64, 2, 4));
+        assert_eq!(parse_cids(cids).unwrap(), [0x40, 0]);
+    }
+
+    #[test]
+    fn parse_cid_list() {
+        assert!(parse_cids(&[]).unwrap().is_empty());
+        assert_eq!(parse_cids(&[0x40, 0x00, 0x41]), Err(Error::InvalidValue));
+        // At most five channels can be opened with one request.
+        assert_eq!(parse_cids(&[0; 10]).unwrap().len(), 5);
+        assert_eq!(parse_cids(&[0; 12]), Err(Error::InvalidValue));
+    }
+}
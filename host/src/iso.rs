@@ -0,0 +1,221 @@
+//! Isochronous (ISO) data.
+//!
+//! ISO data is exchanged on connected and broadcast isochronous streams set up by the application with
+//! the corresponding HCI commands. Received data is delivered to
+//! [`EventHandler::on_iso_data`](crate::prelude::EventHandler::on_iso_data) one HCI packet at a time,
+//! together with the SDU metadata reported by the controller, so that jitter buffers can order SDUs by
+//! sequence number and schedule them by timestamp.
+use bt_hci::param::ConnHandle;
+
+use crate::cursor::{ReadCursor, WriteCursor};
+use crate::Error;
+
+/// Length of the HCI ISO data packet header.
+const HEADER_LEN: usize = 4;
+
+/// Maximum length of the SDU data sent in a single HCI ISO data packet.
+pub const ISO_MAX_PACKET_DATA_LEN: usize = 251;
+
+/// Maximum length of an HCI ISO data packet sent by the host.
+pub(crate) const ISO_MAX_PACKET_LEN: usize = HEADER_LEN + 8 + ISO_MAX_PACKET_DATA_LEN;
+
+/// Position of a fragment within an SDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IsoBoundary {
+    /// First fragment of a fragmented SDU.
+    First,
+    /// Continuation fragment of a fragmented SDU.
+    Continuation,
+    /// A complete SDU.
+    Complete,
+    /// Last fragment of a fragmented SDU.
+    Last,
+}
+
+/// Reception status of an SDU, as reported by the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum IsoPacketStatus {
+    /// The data was received correctly.
+    Valid,
+    /// The data may contain errors or be incomplete.
+    PossiblyInvalid,
+    /// Parts of the SDU were lost. The data is empty if the whole SDU was lost.
+    Lost,
+}
+
+/// SDU metadata, present in the first fragment of an SDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IsoSduInfo {
+    /// Time stamp of the SDU in microseconds, in the time base of the controller.
+    pub timestamp: Option<u32>,
+    /// Packet sequence number of the SDU.
+    pub sequence_number: u16,
+    /// Total length of the SDU.
+    pub sdu_len: u16,
+    /// Reception status of the SDU. Always [`IsoPacketStatus::Valid`] for sent SDUs.
+    pub status: IsoPacketStatus,
+}
+
+/// A received HCI ISO data packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IsoData<'a> {
+    /// Handle of the isochronous stream.
+    pub handle: ConnHandle,
+    /// Position of this fragment within the SDU.
+    pub boundary: IsoBoundary,
+    /// SDU metadata, for [`IsoBoundary::First`] and [`IsoBoundary::Complete`] fragments.
+    pub info: Option<IsoSduInfo>,
+    /// SDU data carried by this packet.
+    pub data: &'a [u8],
+}
+
+impl<'a> IsoData<'a> {
+    /// Parse an HCI ISO data packet, including its 4 byte header.
+    pub fn parse(packet: &'a [u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(packet);
+        let handle: u16 = r.read()?;
+        let len: u16 = r.read()?;
+        let mut r = ReadCursor::new(r.slice((len & 0x3fff) as usize)?);
+
+        let boundary = match (handle >> 12) & 0b11 {
+            0b00 => IsoBoundary::First,
+            0b01 => IsoBoundary::Continuation,
+            0b10 => IsoBoundary::Complete,
+            _ => IsoBoundary::Last,
+        };
+        let timestamp = if handle & (1 << 14) != 0 {
+            Some(r.read::<u32>()?)
+        } else {
+            None
+        };
+        let info = match boundary {
+            IsoBoundary::First | IsoBoundary::Complete => {
+                let sequence_number: u16 = r.read()?;
+                let len: u16 = r.read()?;
+                let status = match len >> 14 {
+                    0b00 => IsoPacketStatus::Valid,
+                    0b01 => IsoPacketStatus::PossiblyInvalid,
+                    0b10 => IsoPacketStatus::Lost,
+                    _ => return Err(Error::InvalidValue),
+                };
+                Some(IsoSduInfo {
+                    timestamp,
+                    sequence_number,
+                    sdu_len: len & 0x0fff,
+                    status,
+                })
+            }
+            IsoBoundary::Continuation | IsoBoundary::Last => None,
+        };
+        let data = r.slice(r.available())?;
+        Ok(Self {
+            handle: ConnHandle::new(handle & 0x0fff),
+            boundary,
+            info,
+            data,
+        })
+    }
+}
+
+/// Encode a complete SDU as an HCI ISO data packet into `dest`, returning the packet length.
+pub(crate) fn encode_sdu(
+    dest: &mut [u8],
+    handle: ConnHandle,
+    timestamp: Option<u32>,
+    sequence_number: u16,
+    sdu: &[u8],
+) -> Result<usize, Error> {
+    if sdu.len() > ISO_MAX_PACKET_DATA_LEN {
+        return Err(Error::InsufficientSpace);
+    }
+    let load_len = timestamp.map_or(0, |_| 4) + 4 + sdu.len();
+    let mut flags = 0b10 << 12;
+    if timestamp.is_some() {
+        flags |= 1 << 14;
+    }
+    let mut w = WriteCursor::new(dest);
+    w.write(handle.raw() | flags)?;
+    w.write(load_len as u16)?;
+    if let Some(timestamp) = timestamp {
+        w.write(timestamp)?;
+    }
+    w.write(sequence_number)?;
+    w.write(sdu.len() as u16)?;
+    w.append(sdu)?;
+    Ok(w.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_complete_sdu_with_timestamp() {
+        let packet = [
+            0x01, 0x60, // handle 1, complete, timestamp
+            0x0b, 0x00, // length
+            0x78, 0x56, 0x34, 0x12, // timestamp
+            0x05, 0x00, // sequence number
+            0x03, 0x40, // sdu length 3, possibly invalid
+            0xaa, 0xbb, 0xcc,
+        ];
+        let iso = IsoData::parse(&packet).unwrap();
+        assert_eq!(iso.handle, ConnHandle::new(1));
+        assert_eq!(iso.boundary, IsoBoundary::Complete);
+        assert_eq!(
+            iso.info,
+            Some(IsoSduInfo {
+                timestamp: Some(0x1234_5678),
+                sequence_number: 5,
+                sdu_len: 3,
+                status: IsoPacketStatus::PossiblyInvalid,
+            })
+        );
+        assert_eq!(iso.data, &[0xaa, 0xbb, 0xcc]);
+    }
+
+    #[test]
+    fn parse_fragments() {
+        let first = [0x02, 0x00, 0x06, 0x00, 0x07, 0x00, 0x04, 0x80, 0x01, 0x02];
+        let iso = IsoData::parse(&first).unwrap();
+        assert_eq!(iso.boundary, IsoBoundary::First);
+        let info = iso.info.unwrap();
+        assert_eq!(info.timestamp, None);
+        assert_eq!(info.status, IsoPacketStatus::Lost);
+        assert_eq!(info.sdu_len, 4);
+
+        let last = [0x02, 0x30, 0x02, 0x00, 0x03, 0x04];
+        let iso = IsoData::parse(&last).unwrap();
+        assert_eq!(iso.boundary, IsoBoundary::Last);
+        assert_eq!(iso.info, None);
+        assert_eq!(iso.data, &[0x03, 0x04]);
+
+        assert!(IsoData::parse(&last[..5]).is_err());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut buf = [0; ISO_MAX_PACKET_LEN];
+        let len = encode_sdu(&mut buf, ConnHandle::new(3), Some(1000), 9, &[1, 2, 3]).unwrap();
+        let iso = IsoData::parse(&buf[..len]).unwrap();
+        assert_eq!(iso.handle, ConnHandle::new(3));
+        assert_eq!(iso.boundary, IsoBoundary::Complete);
+        let info = iso.info.unwrap();
+        assert_eq!(info.timestamp, Some(1000));
+        assert_eq!(info.sequence_number, 9);
+        assert_eq!(info.sdu_len, 3);
+        assert_eq!(iso.data, &[1, 2, 3]);
+
+        let len = encode_sdu(&mut buf, ConnHandle::new(3), None, 10, &[]).unwrap();
+        assert_eq!(&buf[..len], &[0x03, 0x20, 0x04, 0x00, 0x0a, 0x00, 0x00, 0x00]);
+
+        assert_eq!(
+            encode_sdu(&mut buf, ConnHandle::new(3), None, 0, &[0; ISO_MAX_PACKET_DATA_LEN + 1]),
+            Err(Error::InsufficientSpace)
+        );
+    }
+}
@@ -1,16 +1,33 @@
 //! L2CAP channels.
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
 use bt_hci::controller::{blocking, Controller};
+use bt_hci::param::ConnHandle;
+use embassy_time::{with_timeout, Duration};
 
 pub use crate::channel_manager::CreditFlowPolicy;
 #[cfg(feature = "channel-metrics")]
 pub use crate::channel_manager::Metrics as ChannelMetrics;
-use crate::channel_manager::{ChannelIndex, ChannelManager};
+use crate::channel_manager::{ChannelIndex, ChannelManager, CreditGrant};
 use crate::connection::Connection;
+use crate::l2cap::sar::SduSegmentation;
 use crate::pdu::Sdu;
-use crate::{BleHostError, Error, PacketPool, Stack};
+use crate::types::l2cap::L2CAP_ECFC_MAX_CHANNELS;
+use crate::{config, BleHostError, Error, PacketPool, Stack};
 
+mod fixed;
+#[cfg(feature = "embedded-io-async")]
+mod io;
 pub(crate) mod sar;
 
+pub use fixed::FixedChannel;
+#[cfg(feature = "embedded-io-async")]
+pub use io::{L2capStreamReader, L2capStreamWriter};
+
+/// Maximum number of channels opened together in enhanced credit based flow control mode.
+pub const MAX_ENHANCED_CHANNELS: usize = L2CAP_ECFC_MAX_CHANNELS;
+
 /// Handle representing an L2CAP channel.
 pub struct L2capChannel<'d, P: PacketPool> {
     index: ChannelIndex,
@@ -35,6 +52,31 @@ pub struct L2capChannelRef<'d, P: PacketPool> {
     manager: &'d ChannelManager<'d, P>,
 }
 
+/// Writer sending one SDU on an L2CAP channel in chunks.
+///
+/// The length of the SDU is announced in its first frame, so it is fixed when the writer is created. Frames
+/// are sent as soon as they are filled. Dropping the writer before [`finish`](SduWriter::finish) leaves a
+/// partial SDU on the channel, which should then be disconnected.
+pub struct SduWriter<'a, 'd, T: Controller, P: PacketPool> {
+    stack: &'a Stack<'d, T, P>,
+    conn: ConnHandle,
+    grant: CreditGrant<'d, 'd, P::Packet>,
+    frame: P::Packet,
+    sar: SduSegmentation,
+    _writer: &'a mut L2capChannelWriter<'d, P>,
+}
+
+/// Listener accepting channels for a PSM from any connection.
+///
+/// Only one listener can be registered for a PSM. Connection requests received while no
+/// [`accept`](L2capListener::accept) is pending are held until the next one, unless an
+/// [`L2capChannel::accept`] for the PSM is pending on their connection, which takes them first.
+pub struct L2capListener<'d, P: PacketPool> {
+    psm: u16,
+    config: L2capChannelConfig,
+    manager: &'d ChannelManager<'d, P>,
+}
+
 #[cfg(feature = "defmt")]
 impl<P: PacketPool> defmt::Format for L2capChannel<'_, P> {
     fn format(&self, f: defmt::Formatter<'_>) {
@@ -45,10 +87,17 @@ impl<P: PacketPool> defmt::Format for L2capChannel<'_, P> {
 
 impl<P: PacketPool> Drop for L2capChannel<'_, P> {
     fn drop(&mut self) {
+        self.manager.release(self.index, true, true);
         self.manager.dec_ref(self.index);
     }
 }
 
+impl<P: PacketPool> Drop for L2capListener<'_, P> {
+    fn drop(&mut self) {
+        self.manager.unlisten(self.psm);
+    }
+}
+
 impl<P: PacketPool> Drop for L2capChannelRef<'_, P> {
     fn drop(&mut self) {
         self.manager.dec_ref(self.index);
@@ -65,6 +114,7 @@ impl<P: PacketPool> defmt::Format for L2capChannelWriter<'_, P> {
 
 impl<P: PacketPool> Drop for L2capChannelWriter<'_, P> {
     fn drop(&mut self) {
+        self.manager.release(self.index, false, true);
         self.manager.dec_ref(self.index);
     }
 }
@@ -79,12 +129,14 @@ impl<P: PacketPool> defmt::Format for L2capChannelReader<'_, P> {
 
 impl<P: PacketPool> Drop for L2capChannelReader<'_, P> {
     fn drop(&mut self) {
+        self.manager.release(self.index, true, false);
         self.manager.dec_ref(self.index);
     }
 }
 
 /// Configuration for an L2CAP channel.
-#[derive(Default)]
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct L2capChannelConfig {
     /// Size of Service Data Unit. Defaults to packet allocator MTU-6.
     pub mtu: Option<u16>,
@@ -96,6 +148,68 @@ pub struct L2capChannelConfig {
     pub initial_credits: Option<u16>,
 }
 
+/// Parameters of an L2CAP channel, as requested by the peer and negotiated when the channel was opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct L2capChannelInfo {
+    /// PSM the channel was opened on.
+    pub psm: u16,
+    /// MTU announced by the peer: the largest SDU it can receive.
+    pub peer_mtu: u16,
+    /// MPS announced by the peer: the largest PDU payload it can receive.
+    pub peer_mps: u16,
+    /// MPS used on the channel, the smaller of ours and the peer's.
+    pub mps: u16,
+    /// Credits granted by the peer when the channel was opened.
+    pub initial_credits: u16,
+}
+
+/// State of an L2CAP channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum L2capChannelState {
+    /// The channel is open.
+    Connected,
+    /// The channel is being closed by us or by the peer.
+    Disconnecting,
+    /// The channel is closed.
+    Disconnected,
+}
+
+/// Smallest MTU and MPS allowed on an LE credit based channel.
+const L2CAP_LE_MIN_MTU: u16 = 23;
+
+impl L2capChannelConfig {
+    /// Check that this configuration can be used with the packet pool `P`.
+    ///
+    /// Returns [`Error::InvalidConfiguration`] describing the first inconsistency found.
+    pub fn validate<P: PacketPool>(&self) -> Result<(), Error> {
+        let max_mps = P::MTU.saturating_sub(4).min(u16::MAX as usize) as u16;
+        if let Some(mps) = self.mps {
+            if mps > max_mps {
+                return Err(Error::InvalidConfiguration(
+                    "L2CAP MPS exceeds the packet pool MTU minus the 4 byte L2CAP header",
+                ));
+            }
+            if mps < L2CAP_LE_MIN_MTU {
+                return Err(Error::InvalidConfiguration("L2CAP MPS must be at least 23 bytes"));
+            }
+        }
+        if let Some(mtu) = self.mtu {
+            if mtu < L2CAP_LE_MIN_MTU {
+                return Err(Error::InvalidConfiguration("L2CAP MTU must be at least 23 bytes"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Credits granted to the peer when the channel is opened.
+    pub(crate) fn credits<P: PacketPool>(&self) -> u16 {
+        self.initial_credits
+            .unwrap_or(config::L2CAP_RX_QUEUE_SIZE.min(P::capacity()) as u16)
+    }
+}
+
 impl<'d, P: PacketPool> L2capChannel<'d, P> {
     pub(crate) fn new(index: ChannelIndex, manager: &'d ChannelManager<'d, P>) -> Self {
         Self { index, manager }
@@ -111,6 +225,56 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
         self.manager.psm(self.index)
     }
 
+    /// Get the parameters the channel was opened with.
+    ///
+    /// Tells which PSM the peer connected to when accepting on several PSMs, and what it proposed.
+    pub fn info(&self) -> L2capChannelInfo {
+        self.manager.info(self.index)
+    }
+
+    /// The MTU of the channel: the largest SDU that can be sent or received, as negotiated with the peer.
+    ///
+    /// For a channel in enhanced credit based flow control mode, this is the MTU of the peer, which limits the
+    /// SDUs sent; received SDUs are limited by the MTU announced by us.
+    pub fn mtu(&self) -> u16 {
+        self.manager.mtu(self.index)
+    }
+
+    /// The MPS of the channel: the largest PDU payload, as negotiated with the peer.
+    ///
+    /// For a channel in enhanced credit based flow control mode, this is the MPS used for sending.
+    pub fn mps(&self) -> u16 {
+        self.manager.mps(self.index)
+    }
+
+    /// Credits granted by the peer that are available for sending, one per PDU.
+    pub fn tx_credits(&self) -> u16 {
+        self.manager.tx_credits(self.index)
+    }
+
+    /// The policy used to issue credits to the peer.
+    pub fn flow_policy(&self) -> CreditFlowPolicy {
+        self.manager.flow_policy(self.index)
+    }
+
+    /// Disconnect the channel if no data is sent or received for this long, or never if `None`.
+    ///
+    /// A [`ConnectionEvent::L2capChannelIdle`](crate::connection::ConnectionEvent::L2capChannelIdle)
+    /// event is posted to the connection when this happens. The timer restarts when the timeout is set.
+    pub fn set_idle_timeout(&self, timeout: Option<embassy_time::Duration>) {
+        self.manager.set_idle_timeout(self.index, timeout)
+    }
+
+    /// Limit the number of packets from the pool held by the RX queue of the channel, or remove the limit
+    /// if `None`.
+    ///
+    /// Credits are only granted to the peer while the frames it may send fit in the quota, so a channel
+    /// that is not read fast enough cannot take all packets of the pool. Credits granted before the quota
+    /// is set are not taken back, so the initial credits of the channel should fit in it.
+    pub fn set_rx_quota(&self, quota: Option<u16>) {
+        self.manager.set_rx_quota(self.index, quota)
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -130,6 +294,52 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
             .await
     }
 
+    /// Send the provided buffer over this l2cap channel, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if the SDU could not be sent in time, for instance because the peer
+    /// stopped granting credits. Credits for the whole SDU are reserved before its first frame is sent, so
+    /// the timeout only interrupts an SDU midway if the controller runs out of buffers.
+    pub async fn send_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<(), BleHostError<T::Error>> {
+        with_timeout(timeout, self.send(stack, buf))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Send an SDU made of the concatenation of several buffers over this l2cap channel.
+    ///
+    /// Useful when a header and a payload are held in different buffers. The total length must be equal
+    /// to or smaller than the MTU agreed for the channel.
+    pub async fn send_vectored<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        bufs: &[&[u8]],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        stack
+            .host
+            .channels
+            .send_vectored(self.index, bufs, p_buf.as_mut(), &stack.host)
+            .await
+    }
+
+    /// Send an SDU allocated from the packet pool over this l2cap channel.
+    ///
+    /// Unlike [`send`](Self::send), the SDU is framed inside its own packet instead of being copied into a
+    /// second one. This requires the packet to have room for 6 bytes more than the SDU payload; smaller
+    /// packets are copied as with `send`. The SDU must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_sdu<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        sdu: Sdu<P::Packet>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack.host.channels.send_sdu(self.index, sdu, &stack.host).await
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -141,11 +351,21 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
-        stack
-            .host
-            .channels
-            .try_send(self.index, buf, p_buf.as_mut(), &stack.host)
+        stack.host.channels.try_send(self.index, buf, &stack.host)
+    }
+
+    /// Poll to send the provided buffer over this l2cap channel.
+    ///
+    /// Sends the buffer as one SDU if the credits and controller buffers for all of its frames are available,
+    /// and otherwise returns [`Poll::Pending`] and wakes the context once they may be. Allows driving the
+    /// channel from a custom executor or a hand-written future.
+    pub fn poll_send<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+    ) -> Poll<Result<(), BleHostError<T::Error>>> {
+        stack.host.channels.poll_send(self.index, buf, &stack.host, Some(cx))
     }
 
     /// Receive data on this channel and copy it into the buffer.
@@ -169,13 +389,140 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
         stack.host.channels.receive_sdu(self.index, &stack.host).await
     }
 
+    /// Wait for the next SDU on this channel and inspect it without taking it out of the queue.
+    ///
+    /// The SDU is passed to `f`, and is returned by the next receive on the channel. Allows routing code to
+    /// look at a header before handing the channel to the consumer of the payload.
+    pub async fn peek_sdu<F: FnOnce(&[u8]) -> R, R>(&mut self, f: F) -> Result<R, Error> {
+        self.manager.peek_sdu(self.index, f).await
+    }
+
+    /// Receive data on this channel and copy it into the buffer, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if no SDU was received in time. The timeout only applies to waiting for the
+    /// SDU, a received SDU is not lost while credits are granted for it.
+    pub async fn receive_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, BleHostError<T::Error>> {
+        let sdu = self.receive_sdu_timeout(stack, timeout).await?;
+        let len = sdu.len().min(buf.len());
+        buf[..len].copy_from_slice(&sdu.as_ref()[..len]);
+        Ok(len)
+    }
+
+    /// Receive the next SDU available on this channel, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if no SDU was received in time. The timeout only applies to waiting for the
+    /// SDU, a received SDU is not lost while credits are granted for it.
+    pub async fn receive_sdu_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        timeout: Duration,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .receive_sdu_timeout(self.index, &stack.host, timeout)
+            .await
+    }
+
+    /// Grant the peer credits to send `credits` more frames on this channel.
+    ///
+    /// Used with [`CreditFlowPolicy::Manual`], where no credits are granted otherwise. The application must
+    /// not grant more credits than it can buffer frames for.
+    /// Credits that would exceed the [RX quota](L2capChannel::set_rx_quota) of the channel, or the 65535
+    /// credits the peer may hold, are not granted.
+    pub async fn grant_credits<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        credits: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .grant_credits(self.index, credits, &stack.host)
+            .await
+    }
+
+    /// Receive the next SDU on the first of several channels to have one available.
+    ///
+    /// Returns the position of that channel in the slice with the result of receiving on it. Waits forever
+    /// if the slice is empty.
+    pub async fn receive_any<T: Controller>(
+        stack: &Stack<'_, T, P>,
+        channels: &mut [Self],
+    ) -> (usize, Result<Sdu<P::Packet>, BleHostError<T::Error>>) {
+        let idx = poll_readable(channels.iter().map(|chan| (chan.manager, chan.index))).await;
+        (idx, channels[idx].receive_sdu(stack).await)
+    }
+
+    /// Receive data on this channel and copy it into the buffer, without waiting.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU has been received.
+    pub fn try_receive<T: Controller + blocking::Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+    ) -> Result<usize, BleHostError<T::Error>> {
+        stack.host.channels.try_receive(self.index, buf, &stack.host)
+    }
+
+    /// Receive the next SDU available on this channel, without waiting.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU has been received.
+    pub fn try_receive_sdu<T: Controller + blocking::Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        stack.host.channels.try_receive_sdu(self.index, &stack.host)
+    }
+
+    /// Poll for data on this channel and copy it into the buffer.
+    ///
+    /// The length provided buffer slice must be equal or greater to the agreed MTU.
+    pub fn poll_receive<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, BleHostError<T::Error>>> {
+        self.poll_receive_sdu(cx, stack).map_ok(|sdu| {
+            let len = sdu.len().min(buf.len());
+            buf[..len].copy_from_slice(&sdu.as_ref()[..len]);
+            len
+        })
+    }
+
+    /// Poll for the next SDU available on this channel.
+    ///
+    /// Returns [`Poll::Pending`] and wakes the context once an SDU is received if none is available.
+    pub fn poll_receive_sdu<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+    ) -> Poll<Result<Sdu<P::Packet>, BleHostError<T::Error>>> {
+        stack.host.channels.poll_receive_sdu(self.index, &stack.host, cx)
+    }
+
     /// Read metrics of the l2cap channel.
     #[cfg(feature = "channel-metrics")]
     pub fn metrics<F: FnOnce(&ChannelMetrics) -> R, R>(&self, f: F) -> R {
         self.manager.metrics(self.index, f)
     }
 
+    /// Reset the metrics of the l2cap channel.
+    #[cfg(feature = "channel-metrics")]
+    pub fn reset_metrics(&self) {
+        self.manager.reset_metrics(self.index)
+    }
+
     /// Await an incoming connection request matching the list of PSM.
+    ///
+    /// Requests on the connection are held for a PSM while an accept is pending for it or an [`L2capListener`]
+    /// is registered for it, and refused otherwise. A pending accept takes a request before the listener.
     pub async fn accept<T: Controller>(
         stack: &'d Stack<'d, T, P>,
         connection: &Connection<'_, P>,
@@ -200,36 +547,104 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
             .await
     }
 
+    /// Await a request from the peer to open channels in enhanced credit based flow control mode.
+    ///
+    /// Accepts up to `max` of the channels requested together with a PSM from the list, and refuses the others.
+    pub async fn accept_enhanced<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        psm: &[u16],
+        max: usize,
+        config: &L2capChannelConfig,
+    ) -> Result<heapless::Vec<Self, MAX_ENHANCED_CHANNELS>, BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .accept_enhanced(connection.handle(), psm, max, config, &stack.host)
+            .await
+    }
+
+    /// Open up to [`MAX_ENHANCED_CHANNELS`] channels in enhanced credit based flow control mode with a
+    /// single request.
+    ///
+    /// Returns the channels accepted by the peer, which may be fewer than `count`.
+    pub async fn create_enhanced<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        psm: u16,
+        count: usize,
+        config: &L2capChannelConfig,
+    ) -> Result<heapless::Vec<Self, MAX_ENHANCED_CHANNELS>, BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .create_enhanced(connection.handle(), psm, count, config, &stack.host)
+            .await
+    }
+
+    /// Renegotiate the MTU and MPS of a channel opened in enhanced credit based flow control mode.
+    ///
+    /// Asks the peer to accept SDUs of up to `mtu` bytes in frames of up to `mps` bytes on this channel, and
+    /// waits for its response. The MTU can only grow; both values must be at least 64 bytes and fit the packet
+    /// pool. SDUs sent on the channel remain limited by the values of the peer until it reconfigures too.
+    ///
+    /// Returns [`Error::NotSupported`] for channels in LE credit based flow control mode, and
+    /// [`Error::InvalidValue`] if the values are invalid or refused by the peer.
+    pub async fn reconfigure<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        mtu: u16,
+        mps: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack.host.channels.reconfigure(self.index, mtu, mps, &stack.host).await
+    }
+
     /// Split the channel into a writer and reader for concurrently
     /// writing to/reading from the channel.
     pub fn split(self) -> (L2capChannelWriter<'d, P>, L2capChannelReader<'d, P>) {
-        self.manager.inc_ref(self.index);
-        self.manager.inc_ref(self.index);
+        let (index, manager) = (self.index, self.manager);
+        // The halves take over reading and writing from the channel, which must not release them.
+        core::mem::forget(self);
+        manager.inc_ref(index);
         (
-            L2capChannelWriter {
-                index: self.index,
-                manager: self.manager,
-            },
-            L2capChannelReader {
-                index: self.index,
-                manager: self.manager,
-            },
+            L2capChannelWriter { index, manager },
+            L2capChannelReader { index, manager },
         )
     }
 
     /// Merge writer and reader into a single channel again.
     ///
-    /// This function will panic if the channels are not referring to the same channel id.
+    /// This function will panic if the channels are not referring to the same channel id, see
+    /// [`L2capChannel::try_merge`] for a non-panicking variant.
     pub fn merge(writer: L2capChannelWriter<'d, P>, reader: L2capChannelReader<'d, P>) -> Self {
+        match Self::try_merge(writer, reader) {
+            Ok(channel) => channel,
+            Err(_) => panic!("writer and reader refer to different channels"),
+        }
+    }
+
+    /// Merge writer and reader into a single channel again.
+    ///
+    /// If the channels are not referring to the same channel id, both halves are handed back
+    /// unchanged, so that the channels they belong to stay open.
+    pub fn try_merge(
+        writer: L2capChannelWriter<'d, P>,
+        reader: L2capChannelReader<'d, P>,
+    ) -> Result<Self, (L2capChannelWriter<'d, P>, L2capChannelReader<'d, P>)> {
         // A channel will not be reused unless the refcount is 0, so the index could
         // never be stale.
-        assert_eq!(writer.index, reader.index);
+        if writer.index != reader.index {
+            return Err((writer, reader));
+        }
 
         let manager = writer.manager;
         let index = writer.index;
-        manager.inc_ref(index);
+        // The channel takes over reading and writing from the halves, which must not release them.
+        core::mem::forget(writer);
+        core::mem::forget(reader);
+        manager.dec_ref(index);
 
-        Self { index, manager }
+        Ok(Self { index, manager })
     }
 }
 
@@ -260,12 +675,136 @@ impl<'d, P: PacketPool> L2capChannelReader<'d, P> {
         stack.host.channels.receive_sdu(self.index, &stack.host).await
     }
 
+    /// Wait for the next SDU on this channel and inspect it without taking it out of the queue.
+    ///
+    /// The SDU is passed to `f`, and is returned by the next receive on the channel. Allows routing code to
+    /// look at a header before handing the channel to the consumer of the payload.
+    pub async fn peek_sdu<F: FnOnce(&[u8]) -> R, R>(&mut self, f: F) -> Result<R, Error> {
+        self.manager.peek_sdu(self.index, f).await
+    }
+
+    /// Receive data on this channel and copy it into the buffer, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if no SDU was received in time. The timeout only applies to waiting for the
+    /// SDU, a received SDU is not lost while credits are granted for it.
+    pub async fn receive_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, BleHostError<T::Error>> {
+        let sdu = self.receive_sdu_timeout(stack, timeout).await?;
+        let len = sdu.len().min(buf.len());
+        buf[..len].copy_from_slice(&sdu.as_ref()[..len]);
+        Ok(len)
+    }
+
+    /// Receive the next SDU available on this channel, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if no SDU was received in time. The timeout only applies to waiting for the
+    /// SDU, a received SDU is not lost while credits are granted for it.
+    pub async fn receive_sdu_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        timeout: Duration,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .receive_sdu_timeout(self.index, &stack.host, timeout)
+            .await
+    }
+
+    /// Grant the peer credits to send `credits` more frames on this channel.
+    ///
+    /// Used with [`CreditFlowPolicy::Manual`], where no credits are granted otherwise. The application must
+    /// not grant more credits than it can buffer frames for.
+    /// Credits that would exceed the [RX quota](L2capChannel::set_rx_quota) of the channel, or the 65535
+    /// credits the peer may hold, are not granted.
+    pub async fn grant_credits<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        credits: u16,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack
+            .host
+            .channels
+            .grant_credits(self.index, credits, &stack.host)
+            .await
+    }
+
+    /// Receive the next SDU on the first of several channels to have one available.
+    ///
+    /// Returns the position of that reader in the slice with the result of receiving on it. Waits forever
+    /// if the slice is empty.
+    pub async fn receive_any<T: Controller>(
+        stack: &Stack<'_, T, P>,
+        readers: &mut [Self],
+    ) -> (usize, Result<Sdu<P::Packet>, BleHostError<T::Error>>) {
+        let idx = poll_readable(readers.iter().map(|reader| (reader.manager, reader.index))).await;
+        (idx, readers[idx].receive_sdu(stack).await)
+    }
+
+    /// Receive data on this channel and copy it into the buffer, without waiting.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU has been received.
+    pub fn try_receive<T: Controller + blocking::Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+    ) -> Result<usize, BleHostError<T::Error>> {
+        stack.host.channels.try_receive(self.index, buf, &stack.host)
+    }
+
+    /// Receive the next SDU available on this channel, without waiting.
+    ///
+    /// Returns [`Error::Busy`] if no complete SDU has been received.
+    pub fn try_receive_sdu<T: Controller + blocking::Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        stack.host.channels.try_receive_sdu(self.index, &stack.host)
+    }
+
+    /// Poll for data on this channel and copy it into the buffer.
+    ///
+    /// The length provided buffer slice must be equal or greater to the agreed MTU.
+    pub fn poll_receive<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, BleHostError<T::Error>>> {
+        self.poll_receive_sdu(cx, stack).map_ok(|sdu| {
+            let len = sdu.len().min(buf.len());
+            buf[..len].copy_from_slice(&sdu.as_ref()[..len]);
+            len
+        })
+    }
+
+    /// Poll for the next SDU available on this channel.
+    ///
+    /// Returns [`Poll::Pending`] and wakes the context once an SDU is received if none is available.
+    pub fn poll_receive_sdu<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+    ) -> Poll<Result<Sdu<P::Packet>, BleHostError<T::Error>>> {
+        stack.host.channels.poll_receive_sdu(self.index, &stack.host, cx)
+    }
+
     /// Read metrics of the l2cap channel.
     #[cfg(feature = "channel-metrics")]
     pub fn metrics<F: FnOnce(&ChannelMetrics) -> R, R>(&self, f: F) -> R {
         self.manager.metrics(self.index, f)
     }
 
+    /// Reset the metrics of the l2cap channel.
+    #[cfg(feature = "channel-metrics")]
+    pub fn reset_metrics(&self) {
+        self.manager.reset_metrics(self.index)
+    }
+
     /// Create a channel reference for the l2cap channel.
     pub fn channel_ref(&mut self) -> L2capChannelRef<'d, P> {
         self.manager.inc_ref(self.index);
@@ -277,11 +816,95 @@ impl<'d, P: PacketPool> L2capChannelReader<'d, P> {
 }
 
 impl<'d, P: PacketPool> L2capChannelRef<'d, P> {
+    /// Get the PSM for this channel.
+    pub fn psm(&self) -> u16 {
+        self.manager.psm(self.index)
+    }
+
+    /// Get the parameters the channel was opened with.
+    ///
+    /// Tells which PSM the peer connected to when accepting on several PSMs, and what it proposed.
+    pub fn info(&self) -> L2capChannelInfo {
+        self.manager.info(self.index)
+    }
+
+    /// The MTU of the channel: the largest SDU that can be sent or received, as negotiated with the peer.
+    ///
+    /// For a channel in enhanced credit based flow control mode, this is the MTU of the peer, which limits the
+    /// SDUs sent; received SDUs are limited by the MTU announced by us.
+    pub fn mtu(&self) -> u16 {
+        self.manager.mtu(self.index)
+    }
+
+    /// The MPS of the channel: the largest PDU payload, as negotiated with the peer.
+    ///
+    /// For a channel in enhanced credit based flow control mode, this is the MPS used for sending.
+    pub fn mps(&self) -> u16 {
+        self.manager.mps(self.index)
+    }
+
+    /// Credits granted by the peer that are available for sending, one per PDU.
+    pub fn tx_credits(&self) -> u16 {
+        self.manager.tx_credits(self.index)
+    }
+
+    /// The policy used to issue credits to the peer.
+    pub fn flow_policy(&self) -> CreditFlowPolicy {
+        self.manager.flow_policy(self.index)
+    }
+
+    /// Create a read endpoint for the channel if no other handle can read from it.
+    ///
+    /// Allows reading from the channel again after the reader, or the channel it was split from, was
+    /// dropped. Returns [`Error::InvalidState`] if a reader exists, and [`Error::ChannelClosed`] if the channel
+    /// is not connected.
+    pub fn try_upgrade_reader(&self) -> Result<L2capChannelReader<'d, P>, Error> {
+        self.manager.upgrade(self.index, true, false)?;
+        Ok(L2capChannelReader {
+            index: self.index,
+            manager: self.manager,
+        })
+    }
+
+    /// Create a write endpoint for the channel if no other handle can write to it.
+    ///
+    /// Allows writing to the channel again after the writer, or the channel it was split from, was
+    /// dropped. Returns [`Error::InvalidState`] if a writer exists, and [`Error::ChannelClosed`] if the channel
+    /// is not connected.
+    pub fn try_upgrade_writer(&self) -> Result<L2capChannelWriter<'d, P>, Error> {
+        self.manager.upgrade(self.index, false, true)?;
+        Ok(L2capChannelWriter {
+            index: self.index,
+            manager: self.manager,
+        })
+    }
+
+    /// The current state of the channel.
+    pub fn state(&self) -> L2capChannelState {
+        self.manager.channel_state(self.index)
+    }
+
+    /// Wait until the state of the channel changes, and return the new state.
+    ///
+    /// Lets a task learn that a channel used by other tasks was closed, by us or by the peer, without
+    /// sending or receiving on it. Returns right away once the channel is disconnected. Only one task
+    /// should wait for state changes of a channel at a time.
+    pub async fn state_changed(&self) -> L2capChannelState {
+        let from = self.state();
+        poll_fn(|cx| self.manager.poll_state_changed(self.index, from, cx)).await
+    }
+
     #[cfg(feature = "channel-metrics")]
     /// Read metrics of the l2cap channel.
     pub fn metrics<F: FnOnce(&ChannelMetrics) -> R, R>(&self, f: F) -> R {
         self.manager.metrics(self.index, f)
     }
+
+    #[cfg(feature = "channel-metrics")]
+    /// Reset the metrics of the l2cap channel.
+    pub fn reset_metrics(&self) {
+        self.manager.reset_metrics(self.index)
+    }
 }
 
 impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
@@ -309,6 +932,74 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
             .await
     }
 
+    /// Send the provided buffer over this l2cap channel, waiting at most `timeout`.
+    ///
+    /// Returns [`Error::Timeout`] if the SDU could not be sent in time, for instance because the peer
+    /// stopped granting credits. Credits for the whole SDU are reserved before its first frame is sent, so
+    /// the timeout only interrupts an SDU midway if the controller runs out of buffers.
+    pub async fn send_timeout<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<(), BleHostError<T::Error>> {
+        with_timeout(timeout, self.send(stack, buf))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Start sending an SDU of `len` bytes that is written in chunks.
+    ///
+    /// Waits until the peer has granted the credits for all frames of the SDU. `len` must be equal to or
+    /// smaller than the MTU agreed for the channel. Returns [`Error::InsufficientSpace`] if a frame of the
+    /// MPS of the channel does not fit in a packet of the pool.
+    pub async fn sdu_writer<'a, T: Controller>(
+        &'a mut self,
+        stack: &'a Stack<'d, T, P>,
+        len: usize,
+    ) -> Result<SduWriter<'a, 'd, T, P>, BleHostError<T::Error>> {
+        let frame = P::allocate().ok_or(Error::OutOfMemory)?;
+        let (conn, mps, peer_cid, grant) = self.manager.reserve_sdu(self.index, len).await?;
+        Ok(SduWriter {
+            stack,
+            conn,
+            grant,
+            frame,
+            sar: SduSegmentation::new(peer_cid, mps as usize, len),
+            _writer: self,
+        })
+    }
+
+    /// Send an SDU made of the concatenation of several buffers over this l2cap channel.
+    ///
+    /// Useful when a header and a payload are held in different buffers. The total length must be equal
+    /// to or smaller than the MTU agreed for the channel.
+    pub async fn send_vectored<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        bufs: &[&[u8]],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        stack
+            .host
+            .channels
+            .send_vectored(self.index, bufs, p_buf.as_mut(), &stack.host)
+            .await
+    }
+
+    /// Send an SDU allocated from the packet pool over this l2cap channel.
+    ///
+    /// Unlike [`send`](Self::send), the SDU is framed inside its own packet instead of being copied into a
+    /// second one. This requires the packet to have room for 6 bytes more than the SDU payload; smaller
+    /// packets are copied as with `send`. The SDU must be equal to or smaller than the MTU agreed for the channel.
+    pub async fn send_sdu<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        sdu: Sdu<P::Packet>,
+    ) -> Result<(), BleHostError<T::Error>> {
+        stack.host.channels.send_sdu(self.index, sdu, &stack.host).await
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
     /// The buffer must be equal to or smaller than the MTU agreed for the channel.
@@ -320,11 +1011,21 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
-        stack
-            .host
-            .channels
-            .try_send(self.index, buf, p_buf.as_mut(), &stack.host)
+        stack.host.channels.try_send(self.index, buf, &stack.host)
+    }
+
+    /// Poll to send the provided buffer over this l2cap channel.
+    ///
+    /// Sends the buffer as one SDU if the credits and controller buffers for all of its frames are available,
+    /// and otherwise returns [`Poll::Pending`] and wakes the context once they may be. Allows driving the
+    /// channel from a custom executor or a hand-written future.
+    pub fn poll_send<T: Controller + blocking::Controller>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+    ) -> Poll<Result<(), BleHostError<T::Error>>> {
+        stack.host.channels.poll_send(self.index, buf, &stack.host, Some(cx))
     }
 
     /// Read metrics of the l2cap channel.
@@ -333,6 +1034,12 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
         self.manager.metrics(self.index, f)
     }
 
+    /// Reset the metrics of the l2cap channel.
+    #[cfg(feature = "channel-metrics")]
+    pub fn reset_metrics(&self) {
+        self.manager.reset_metrics(self.index)
+    }
+
     /// Create a channel reference for the l2cap channel.
     pub fn channel_ref(&mut self) -> L2capChannelRef<'d, P> {
         self.manager.inc_ref(self.index);
@@ -342,3 +1049,224 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
         }
     }
 }
+
+impl<'d, P: PacketPool> L2capListener<'d, P> {
+    /// Register a listener for the PSM, opening channels with the provided configuration.
+    ///
+    /// Returns [`Error::Busy`] if a listener is already registered for the PSM.
+    pub fn new<T: Controller>(stack: &'d Stack<'d, T, P>, psm: u16, config: L2capChannelConfig) -> Result<Self, Error> {
+        config.validate::<P>()?;
+        let manager = &stack.host.channels;
+        manager.listen(psm)?;
+        Ok(Self { psm, config, manager })
+    }
+
+    /// The PSM of this listener.
+    pub fn psm(&self) -> u16 {
+        self.psm
+    }
+
+    /// Await the next connection request for the PSM on any connection.
+    ///
+    /// Returns the connection the channel was opened on together with the channel.
+    pub async fn accept<T: Controller>(
+        &mut self,
+        stack: &'d Stack<'d, T, P>,
+    ) -> Result<(Connection<'d, P>, L2capChannel<'d, P>), BleHostError<T::Error>> {
+        let (handle, channel) = self
+            .manager
+            .accept_from(None, &[self.psm], &self.config, &stack.host)
+            .await?;
+        let connection = stack
+            .host
+            .connections
+            .get_connected_handle(handle)
+            .ok_or(Error::Disconnected)?;
+        Ok((connection, channel))
+    }
+}
+
+impl<T: Controller, P: PacketPool> SduWriter<'_, '_, T, P> {
+    /// Number of bytes of the SDU that remain to be written.
+    pub fn remaining(&self) -> usize {
+        self.sar.remaining()
+    }
+
+    /// Append data to the SDU, sending every frame that is filled.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the data exceeds the remaining length of the SDU.
+    pub async fn write(&mut self, mut data: &[u8]) -> Result<(), BleHostError<T::Error>> {
+        if data.len() > self.sar.remaining() {
+            return Err(Error::InsufficientSpace.into());
+        }
+        while !data.is_empty() {
+            let n = self.sar.write(self.frame.as_mut(), data);
+            data = &data[n..];
+            if self.sar.is_full() {
+                self.send_frame().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the last frame of the SDU.
+    ///
+    /// Returns [`Error::InvalidValue`] if less data than the announced length was written.
+    pub async fn finish(mut self) -> Result<(), BleHostError<T::Error>> {
+        if self.sar.remaining() > 0 {
+            return Err(Error::InvalidValue.into());
+        }
+        if self.sar.has_frame() {
+            self.send_frame().await?;
+        }
+        Ok(())
+    }
+
+    async fn send_frame(&mut self) -> Result<(), BleHostError<T::Error>> {
+        let len = self.sar.finish_frame(self.frame.as_mut());
+        self.stack
+            .host
+            .l2cap(self.conn, (len - 4) as u16, 1)
+            .await?
+            .send(&self.frame.as_ref()[..len])
+            .await?;
+        self.grant.confirm(1);
+        Ok(())
+    }
+}
+
+/// Wait until a receive on one of the channels would complete right away, and return its position.
+async fn poll_readable<'d, P: PacketPool, I>(channels: I) -> usize
+where
+    I: Iterator<Item = (&'d ChannelManager<'d, P>, ChannelIndex)> + Clone,
+{
+    poll_fn(|cx| {
+        for (idx, (manager, index)) in channels.clone().enumerate() {
+            if manager.poll_readable(index, cx).is_ready() {
+                return Poll::Ready(idx);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use super::*;
+    use crate::mock_controller::MockController;
+    use crate::pdu::Pdu;
+    use crate::poll::{Poller, WakeFlag};
+    use crate::prelude::DefaultPacketPool;
+    use crate::HostResources;
+
+    fn readers<'d>(
+        manager: &'d ChannelManager<'d, DefaultPacketPool>,
+    ) -> [L2capChannelReader<'d, DefaultPacketPool>; 2] {
+        [0, 1].map(|_| L2capChannelReader {
+            index: manager.alloc_connected(ConnHandle::new(1), 23, 23, 0),
+            manager,
+        })
+    }
+
+    #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
+    #[test]
+    fn receive_any_first_readable() {
+        static WOKEN: WakeFlag = WakeFlag::new();
+        let mut resources: HostResources<DefaultPacketPool, 1, 2> = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+        let mut readers = readers(&stack.host.channels);
+
+        let mut receive = pin!(Poller::new(
+            L2capChannelReader::receive_any(&stack, &mut readers),
+            &WOKEN
+        ));
+        assert!(receive.as_mut().poll().is_pending());
+        assert!(!receive.is_woken());
+
+        // An SDU on the second channel wakes the receive.
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[..5].copy_from_slice(&[3, 0, 1, 2, 3]);
+        stack.host.channels.dispatch(0x41, Pdu::new(packet, 5)).unwrap();
+        assert!(receive.is_woken());
+        let Poll::Ready((idx, Ok(sdu))) = receive.as_mut().poll() else {
+            panic!("expected an SDU");
+        };
+        assert_eq!(idx, 1);
+        assert_eq!(sdu.as_ref(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn receive_any_woken_on_disconnect() {
+        static WOKEN: WakeFlag = WakeFlag::new();
+        let mut resources: HostResources<DefaultPacketPool, 1, 2> = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+        let mut readers = readers(&stack.host.channels);
+        let index = readers[0].index;
+
+        let mut receive = pin!(Poller::new(
+            L2capChannelReader::receive_any(&stack, &mut readers),
+            &WOKEN
+        ));
+        assert!(receive.as_mut().poll().is_pending());
+
+        stack.host.channels.disconnect(index);
+        assert!(receive.is_woken());
+        assert!(matches!(
+            receive.as_mut().poll(),
+            Poll::Ready((0, Err(BleHostError::BleHost(Error::ChannelClosed))))
+        ));
+    }
+
+    #[test]
+    fn try_merge_mismatch_returns_halves() {
+        let mut resources: HostResources<DefaultPacketPool, 1, 2> = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+        let manager = &stack.host.channels;
+        let [(a_writer, a_reader), (b_writer, b_reader)] =
+            [0, 1].map(|_| L2capChannel::new(manager.alloc_connected(ConnHandle::new(1), 23, 23, 0), manager).split());
+        let (a, b) = (a_writer.index, b_writer.index);
+
+        // Neither channel is closed by a failed merge, and the halves can still be merged properly.
+        let Err((a_writer, b_reader)) = L2capChannel::try_merge(a_writer, b_reader) else {
+            panic!("expected halves of different channels to be handed back");
+        };
+        assert_eq!(manager.channel_state(a), L2capChannelState::Connected);
+        assert_eq!(manager.channel_state(b), L2capChannelState::Connected);
+
+        let Ok(channel_a) = L2capChannel::try_merge(a_writer, a_reader) else {
+            panic!("expected halves of the same channel to merge");
+        };
+        let Ok(channel_b) = L2capChannel::try_merge(b_writer, b_reader) else {
+            panic!("expected halves of the same channel to merge");
+        };
+        assert_eq!((channel_a.index, channel_b.index), (a, b));
+        assert_eq!(manager.channel_state(a), L2capChannelState::Connected);
+        assert_eq!(manager.channel_state(b), L2capChannelState::Connected);
+    }
+
+    #[test]
+    fn validate_channel_config() {
+        assert!(L2capChannelConfig::default().validate::<DefaultPacketPool>().is_ok());
+
+        let config = L2capChannelConfig {
+            mps: Some(DefaultPacketPool::MTU as u16),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate::<DefaultPacketPool>(),
+            Err(Error::InvalidConfiguration(_))
+        ));
+
+        let config = L2capChannelConfig {
+            mtu: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate::<DefaultPacketPool>(),
+            Err(Error::InvalidConfiguration(_))
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+//! Fixed L2CAP channels opened by the application.
+use bt_hci::controller::Controller;
+use bt_hci::param::ConnHandle;
+
+use crate::channel_manager::{ChannelIndex, ChannelManager};
+use crate::connection::Connection;
+use crate::pdu::Sdu;
+use crate::{BleHostError, Error, PacketPool, Stack};
+
+/// A fixed L2CAP channel of a connection, for protocols not implemented by the host.
+///
+/// Fixed channels carry basic frames without flow control or segmentation: every payload is one PDU,
+/// which must fit in a packet of the pool. PDUs received while the queue of the channel is full are
+/// dropped. The channels used by the host (signaling, ATT and SMP) cannot be opened.
+pub struct FixedChannel<'d, P: PacketPool> {
+    index: ChannelIndex,
+    conn: ConnHandle,
+    cid: u16,
+    manager: &'d ChannelManager<'d, P>,
+}
+
+impl<P: PacketPool> Drop for FixedChannel<'_, P> {
+    fn drop(&mut self) {
+        self.manager.close_fixed(self.index);
+    }
+}
+
+impl<'d, P: PacketPool> FixedChannel<'d, P> {
+    /// Open the fixed channel `cid` of a connection.
+    ///
+    /// Returns [`Error::InvalidChannelId`] for the channels used by the host and for dynamic channel ids, and
+    /// [`Error::Busy`] if the channel is already open on this connection.
+    pub fn open<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+        cid: u16,
+    ) -> Result<Self, Error> {
+        let conn = connection.handle();
+        let index = stack.host.channels.open_fixed(conn, cid)?;
+        Ok(Self {
+            index,
+            conn,
+            cid,
+            manager: &stack.host.channels,
+        })
+    }
+
+    /// The channel id.
+    pub fn cid(&self) -> u16 {
+        self.cid
+    }
+
+    /// The connection of the channel.
+    pub fn handle(&self) -> ConnHandle {
+        self.conn
+    }
+
+    /// Send a PDU on this channel.
+    pub async fn send<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        self.manager
+            .send_fixed(self.index, buf, p_buf.as_mut(), &stack.host)
+            .await
+    }
+
+    /// Receive the next PDU on this channel.
+    ///
+    /// Returns [`Error::Disconnected`] once the connection is closed.
+    pub async fn receive(&mut self) -> Result<Sdu<P::Packet>, Error> {
+        let pdu = self.manager.receive_fixed(self.index).await?;
+        Ok(Sdu::from_pdu(pdu))
+    }
+}
@@ -0,0 +1,202 @@
+//! Byte stream adapters implementing the `embedded-io-async` traits on top of L2CAP channels.
+use bt_hci::controller::Controller;
+use embedded_io::ErrorKind;
+
+use super::{L2capChannelReader, L2capChannelWriter};
+use crate::pdu::Sdu;
+use crate::{BleHostError, Error, PacketPool, Stack};
+
+impl<E: embedded_io::Error> embedded_io::Error for BleHostError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Controller(e) => e.kind(),
+            Self::BleHost(Error::Disconnected | Error::ChannelClosed) => ErrorKind::NotConnected,
+            Self::BleHost(Error::OutOfMemory) => ErrorKind::OutOfMemory,
+            Self::BleHost(Error::InvalidValue) => ErrorKind::InvalidInput,
+            Self::BleHost(_) => ErrorKind::Other,
+        }
+    }
+}
+
+/// Reads the SDUs received on an L2CAP channel as a byte stream.
+///
+/// SDUs are copied out as far as the buffer passed to `read` allows, the rest is kept for the next
+/// call. Reading returns 0 once the channel is closed.
+pub struct L2capStreamReader<'d, T: Controller, P: PacketPool> {
+    reader: L2capChannelReader<'d, P>,
+    stack: &'d Stack<'d, T, P>,
+    sdu: Option<Sdu<P::Packet>>,
+    pos: usize,
+}
+
+impl<'d, T: Controller, P: PacketPool> L2capStreamReader<'d, T, P> {
+    /// Create a stream reader for the read endpoint of a channel.
+    pub fn new(reader: L2capChannelReader<'d, P>, stack: &'d Stack<'d, T, P>) -> Self {
+        Self {
+            reader,
+            stack,
+            sdu: None,
+            pos: 0,
+        }
+    }
+
+    /// Return the read endpoint, dropping any data not read yet.
+    pub fn into_inner(self) -> L2capChannelReader<'d, P> {
+        self.reader
+    }
+}
+
+impl<T: Controller, P: PacketPool> embedded_io::ErrorType for L2capStreamReader<'_, T, P> {
+    type Error = BleHostError<T::Error>;
+}
+
+impl<T: Controller, P: PacketPool> embedded_io_async::Read for L2capStreamReader<'_, T, P> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(sdu) = &self.sdu {
+                let data = &sdu.as_ref()[self.pos..];
+                if !data.is_empty() {
+                    let n = data.len().min(buf.len());
+                    buf[..n].copy_from_slice(&data[..n]);
+                    self.pos += n;
+                    return Ok(n);
+                }
+            }
+            self.sdu = None;
+            match self.reader.receive_sdu(self.stack).await {
+                Ok(sdu) => {
+                    self.sdu = Some(sdu);
+                    self.pos = 0;
+                }
+                Err(BleHostError::BleHost(Error::ChannelClosed | Error::Disconnected)) => return Ok(0),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Writes a byte stream to an L2CAP channel.
+///
+/// Written data is buffered until it fills the MTU of the channel, and sent as an SDU by the next write or
+/// when the writer is flushed. Data still buffered when the writer is dropped is lost.
+pub struct L2capStreamWriter<'d, T: Controller, P: PacketPool> {
+    writer: L2capChannelWriter<'d, P>,
+    stack: &'d Stack<'d, T, P>,
+    buf: Option<P::Packet>,
+    len: usize,
+}
+
+impl<'d, T: Controller, P: PacketPool> L2capStreamWriter<'d, T, P> {
+    /// Create a stream writer for the write endpoint of a channel.
+    pub fn new(writer: L2capChannelWriter<'d, P>, stack: &'d Stack<'d, T, P>) -> Self {
+        Self {
+            writer,
+            stack,
+            buf: None,
+            len: 0,
+        }
+    }
+
+    /// Return the write endpoint, dropping any data not flushed yet.
+    pub fn into_inner(self) -> L2capChannelWriter<'d, P> {
+        self.writer
+    }
+
+    /// Send the buffered data, if any, as one SDU.
+    ///
+    /// The data is kept if sending fails, so that the next write or flush sends it again.
+    async fn send_buffered(&mut self) -> Result<(), BleHostError<T::Error>> {
+        if let Some(packet) = &self.buf {
+            if self.len > 0 {
+                self.writer.send(self.stack, &packet.as_ref()[..self.len]).await?;
+            }
+        }
+        self.buf = None;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl<T: Controller, P: PacketPool> embedded_io::ErrorType for L2capStreamWriter<'_, T, P> {
+    type Error = BleHostError<T::Error>;
+}
+
+impl<T: Controller, P: PacketPool> embedded_io_async::Write for L2capStreamWriter<'_, T, P> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let capacity = (self.writer.manager.mtu(self.writer.index) as usize).min(P::MTU);
+        if capacity == 0 {
+            return Err(Error::ChannelClosed.into());
+        }
+        // A full buffer is sent before taking more data, so that an error is only returned when none of
+        // the data was written.
+        if self.len >= capacity {
+            self.send_buffered().await?;
+        }
+        let mut packet = match self.buf.take() {
+            Some(packet) => packet,
+            None => P::allocate().ok_or(Error::OutOfMemory)?,
+        };
+        let n = (capacity - self.len).min(buf.len());
+        packet.as_mut()[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.buf = Some(packet);
+        self.len += n;
+        Ok(n)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.send_buffered().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+    use core::task::{Context, Waker};
+
+    use bt_hci::param::ConnHandle;
+    use embassy_futures::block_on;
+    use embedded_io_async::Write;
+
+    use super::*;
+    use crate::mock_controller::MockController;
+    use crate::prelude::DefaultPacketPool;
+    use crate::HostResources;
+
+    #[test]
+    fn write_error_keeps_buffered_data() {
+        let mut resources: HostResources<DefaultPacketPool, 1, 1> = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+        let index = stack.host.channels.alloc_connected(ConnHandle::new(1), 4, 23, 0);
+        let writer = L2capChannelWriter {
+            index,
+            manager: &stack.host.channels,
+        };
+        let mut stream = L2capStreamWriter::new(writer, &stack);
+
+        // Data is buffered up to the MTU of the channel.
+        assert_eq!(block_on(stream.write(&[1, 2, 3, 4, 5, 6])).unwrap(), 4);
+        assert_eq!(stream.len, 4);
+
+        // The next write first sends the full buffer, which waits for credits. Dropping it loses nothing.
+        {
+            let write = pin!(stream.write(&[5, 6]));
+            assert!(write.poll(&mut Context::from_waker(Waker::noop())).is_pending());
+        }
+        assert_eq!(stream.len, 4);
+
+        // A failed send returns the error without taking any of the data, and keeps the buffered SDU.
+        stack.host.channels.disconnect(index);
+        assert!(matches!(
+            block_on(stream.write(&[5, 6])),
+            Err(BleHostError::BleHost(Error::ChannelClosed))
+        ));
+        assert_eq!(stream.len, 4);
+        assert_eq!(&stream.buf.as_ref().unwrap().as_ref()[..4], &[1, 2, 3, 4]);
+    }
+}
@@ -150,3 +150,122 @@ impl<P> PacketReassembly<P> {
         }
     }
 }
+
+/// Segmentation of an SDU into K-frames, written into a frame buffer piece by piece.
+///
+/// The frame buffer holds the L2CAP headers of the frame followed by its payload. The first frame also
+/// carries the length of the SDU.
+pub(crate) struct SduSegmentation {
+    peer_cid: u16,
+    mps: usize,
+    total: usize,
+    remaining: usize,
+    // Length of the headers of the current frame.
+    header: usize,
+    // Payload written to the current frame.
+    len: usize,
+}
+
+impl SduSegmentation {
+    /// Segment an SDU of `total` bytes for a peer accepting frames of up to `mps` bytes.
+    pub fn new(peer_cid: u16, mps: usize, total: usize) -> Self {
+        Self {
+            peer_cid,
+            mps,
+            total,
+            remaining: total,
+            header: 6,
+            len: 0,
+        }
+    }
+
+    /// Number of bytes of the SDU that remain to be written.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Copy as much of the data into the current frame as fits, returning the number of bytes copied.
+    pub fn write(&mut self, frame: &mut [u8], data: &[u8]) -> usize {
+        let n = (self.mps + 4 - self.header - self.len)
+            .min(data.len())
+            .min(self.remaining);
+        let start = self.header + self.len;
+        frame[start..start + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        self.remaining -= n;
+        n
+    }
+
+    /// Returns `true` if the current frame is full and must be sent before writing more data.
+    pub fn is_full(&self) -> bool {
+        self.header + self.len == self.mps + 4
+    }
+
+    /// Returns `true` if the current frame must still be sent, including the first frame of an empty SDU.
+    pub fn has_frame(&self) -> bool {
+        self.len > 0 || self.header == 6
+    }
+
+    /// Write the headers of the current frame, returning the length of the frame, and start the next one.
+    pub fn finish_frame(&mut self, frame: &mut [u8]) -> usize {
+        let payload = self.header - 4 + self.len;
+        frame[..2].copy_from_slice(&(payload as u16).to_le_bytes());
+        frame[2..4].copy_from_slice(&self.peer_cid.to_le_bytes());
+        if self.header == 6 {
+            frame[4..6].copy_from_slice(&(self.total as u16).to_le_bytes());
+        }
+        self.header = 4;
+        self.len = 0;
+        4 + payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_sdu() {
+        let mut sar = SduSegmentation::new(0x40, 5, 8);
+        let mut frame = [0; 9];
+
+        assert_eq!(sar.write(&mut frame, &[1, 2]), 2);
+        assert!(!sar.is_full());
+        // The first frame has room for the MPS minus the SDU length.
+        assert_eq!(sar.write(&mut frame, &[3, 4, 5]), 1);
+        assert!(sar.is_full());
+        assert_eq!(sar.finish_frame(&mut frame), 9);
+        assert_eq!(frame, [5, 0, 0x40, 0, 8, 0, 1, 2, 3]);
+
+        assert_eq!(sar.write(&mut frame, &[4, 5, 6, 7, 8]), 5);
+        assert!(sar.is_full());
+        assert_eq!(sar.finish_frame(&mut frame), 9);
+        assert_eq!(frame, [5, 0, 0x40, 0, 4, 5, 6, 7, 8]);
+        assert_eq!(sar.remaining(), 0);
+        assert!(!sar.has_frame());
+    }
+
+    #[test]
+    fn segment_partial_frames() {
+        let mut sar = SduSegmentation::new(0x41, 23, 4);
+        let mut frame = [0; 27];
+
+        // Data beyond the length of the SDU is not taken.
+        assert_eq!(sar.write(&mut frame, &[1, 2, 3, 4, 5]), 4);
+        assert!(!sar.is_full());
+        assert!(sar.has_frame());
+        assert_eq!(sar.finish_frame(&mut frame), 10);
+        assert_eq!(&frame[..10], &[6, 0, 0x41, 0, 4, 0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn segment_empty_sdu() {
+        let mut sar = SduSegmentation::new(0x40, 23, 0);
+        let mut frame = [0; 27];
+
+        assert!(sar.has_frame());
+        assert_eq!(sar.finish_frame(&mut frame), 6);
+        assert_eq!(&frame[..6], &[2, 0, 0x40, 0, 0, 0]);
+        assert!(!sar.has_frame());
+    }
+}
@@ -5,13 +5,21 @@
 #![doc = include_str!(concat!("../", env!("CARGO_PKG_README")))]
 #![warn(missing_docs)]
 
+// The growable tables are also built for tests, so that they are covered without the `alloc` feature.
+#[cfg(any(feature = "alloc", test))]
+extern crate alloc;
+// Lets unit tests use the gatt macros, whose generated code refers to this crate by name.
+#[cfg(test)]
+extern crate self as trouble_host;
+
+use core::future::poll_fn;
 use core::mem::MaybeUninit;
 
 use advertise::AdvertisementDataError;
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::cmd::{AsyncCmd, SyncCmd};
-use bt_hci::param::{AddrKind, BdAddr};
-use bt_hci::FromHciBytesError;
+use bt_hci::param::{AddrKind, BdAddr, ConnHandle};
+use bt_hci::{FromHciBytes, FromHciBytesError};
 use embassy_time::Duration;
 #[cfg(feature = "security")]
 use heapless::Vec;
@@ -21,7 +29,9 @@ use crate::att::AttErrorCode;
 use crate::channel_manager::ChannelStorage;
 use crate::connection_manager::ConnectionStorage;
 #[cfg(feature = "security")]
-pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey};
+pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey, PairingBackoff};
+#[cfg(feature = "alloc")]
+use crate::table::Table;
 pub use crate::types::capabilities::IoCapabilities;
 
 /// Number of bonding information stored
@@ -32,6 +42,7 @@ mod fmt;
 #[cfg(not(any(feature = "central", feature = "peripheral")))]
 compile_error!("Must enable at least one of the `central` or `peripheral` features");
 
+mod activity;
 pub mod att;
 #[cfg(feature = "central")]
 pub mod central;
@@ -41,13 +52,18 @@ mod command;
 pub mod config;
 mod connection_manager;
 mod cursor;
-#[cfg(feature = "default-packet-pool")]
-mod packet_pool;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 mod pdu;
 #[cfg(feature = "peripheral")]
 pub mod peripheral;
+pub mod poll;
+mod radio_state;
 #[cfg(feature = "security")]
 mod security_manager;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod table;
 pub mod types;
 
 #[cfg(feature = "central")]
@@ -59,7 +75,11 @@ pub mod advertise;
 pub mod connection;
 #[cfg(feature = "gatt")]
 pub mod gap;
+pub mod iso;
 pub mod l2cap;
+pub mod packet_pool;
+#[cfg(feature = "scan")]
+pub mod periodic_sync;
 #[cfg(feature = "scan")]
 pub mod scan;
 
@@ -67,7 +87,9 @@ pub mod scan;
 pub(crate) mod mock_controller;
 
 pub(crate) mod host;
-use host::{AdvHandleState, BleHost, HostMetrics, Runner};
+pub use activity::{RadioActivity, RadioUsage, TRACKED_ADV_SETS};
+use host::{AdvHandleState, BleHost, ControllerInfo, HostMetrics, Runner};
+use radio_state::RestoredState;
 
 pub mod prelude {
     //! Convenience include of most commonly used types.
@@ -81,7 +103,10 @@ pub mod prelude {
     pub use trouble_host_macros::*;
 
     pub use super::att::AttErrorCode;
-    pub use super::{BleHostError, Controller, Error, Host, HostResources, Packet, PacketPool, Stack};
+    pub use super::{
+        BleHostError, Controller, Error, Host, HostResources, Packet, PacketPool, PacketPoolMetrics, Stack,
+    };
+    pub use super::{RadioActivity, RadioUsage};
     #[cfg(feature = "peripheral")]
     pub use crate::advertise::*;
     #[cfg(feature = "gatt")]
@@ -92,24 +117,36 @@ pub mod prelude {
     pub use crate::central::*;
     pub use crate::connection::*;
     #[cfg(feature = "gatt")]
+    pub use crate::eatt::{EattBearer, EATT_PSM};
+    #[cfg(feature = "gatt")]
     pub use crate::gap::*;
     #[cfg(feature = "gatt")]
     pub use crate::gatt::*;
-    pub use crate::host::{ControlRunner, EventHandler, HostMetrics, Runner, RxRunner, TxRunner};
+    pub use crate::host::{
+        ConnectionDecision, ConnectionRequest, ControlRunner, ControllerInfo, EventHandler, HciEventFilter,
+        HostMetrics, Runner, RxRunner, TxRunner,
+    };
     pub use crate::l2cap::*;
     #[cfg(feature = "default-packet-pool")]
     pub use crate::packet_pool::DefaultPacketPool;
+    #[cfg(feature = "alloc")]
+    pub use crate::packet_pool::{AllocPacket, AllocPool};
     pub use crate::pdu::Sdu;
+    #[cfg(feature = "scan")]
+    pub use crate::periodic_sync::*;
     #[cfg(feature = "peripheral")]
     pub use crate::peripheral::*;
+    pub use crate::poll::{Poller, WakeFlag};
+    pub use crate::radio_state::RestoredState;
     #[cfg(feature = "scan")]
     pub use crate::scan::*;
     #[cfg(feature = "security")]
-    pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey};
+    pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey, PairingBackoff};
+    pub use crate::types::appearance::{Appearance, AppearanceCategory};
     pub use crate::types::capabilities::IoCapabilities;
     #[cfg(feature = "gatt")]
     pub use crate::types::gatt_traits::{AsGatt, FixedGattValue, FromGatt};
-    pub use crate::{Address, Identity};
+    pub use crate::{Address, AddressType, Identity};
 }
 
 #[cfg(feature = "gatt")]
@@ -117,7 +154,11 @@ pub mod attribute;
 #[cfg(feature = "gatt")]
 mod attribute_server;
 #[cfg(feature = "gatt")]
+pub mod eatt;
+#[cfg(feature = "gatt")]
 pub mod gatt;
+#[cfg(feature = "gatt")]
+pub mod services;
 
 /// A BLE address.
 /// Every BLE device is identified by a unique *Bluetooth Device Address*, which is a 48-bit identifier similar to a MAC address. BLE addresses are categorized into two main types: *Public* and *Random*.
@@ -131,10 +172,13 @@ pub mod gatt;
 ///
 /// Random addresses enhance privacy by preventing device tracking.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Address {
     /// Address type.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::addr_kind"))]
     pub kind: AddrKind,
     /// Address value.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::bd_addr"))]
     pub addr: BdAddr,
 }
 
@@ -147,6 +191,104 @@ impl Address {
         }
     }
 
+    /// Create a new public address.
+    pub fn public(val: [u8; 6]) -> Self {
+        Self {
+            kind: AddrKind::PUBLIC,
+            addr: BdAddr::new(val),
+        }
+    }
+
+    /// Parse an address of the given kind in colon notation, most significant byte first (`C0:11:22:33:44:55`).
+    pub fn parse(kind: AddrKind, s: &str) -> Result<Self, Error> {
+        let mut val = [0; 6];
+        let mut parts = s.split(':');
+        for byte in val.iter_mut().rev() {
+            let part = parts.next().ok_or(Error::InvalidValue)?;
+            // `from_str_radix` also accepts a sign, so check for exactly two hex digits first.
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Error::InvalidValue);
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| Error::InvalidValue)?;
+        }
+        if parts.next().is_some() {
+            return Err(Error::InvalidValue);
+        }
+        Ok(Self {
+            kind,
+            addr: BdAddr::new(val),
+        })
+    }
+
+    /// Generate a new random static address.
+    ///
+    /// The two most significant bits are set, and the random part is neither all zeros nor all ones,
+    /// as required by the Core Specification (Vol 6, Part B, Section 1.3.2.1).
+    pub fn random_static<RNG: RngCore>(rng: &mut RNG) -> Self {
+        loop {
+            let mut val = [0; 6];
+            rng.fill_bytes(&mut val);
+            val[5] |= 0xC0;
+            let address = Self::random(val);
+            if address.is_random_static() {
+                return address;
+            }
+        }
+    }
+
+    /// Check whether this is a valid random static address.
+    pub fn is_random_static(&self) -> bool {
+        let a = self.addr.into_inner();
+        let random = &a[..5];
+        let top = a[5] & 0x3F;
+        let all_zeros = random.iter().all(|b| *b == 0) && top == 0;
+        let all_ones = random.iter().all(|b| *b == 0xFF) && top == 0x3F;
+        self.kind == AddrKind::RANDOM && a[5] & 0xC0 == 0xC0 && !all_zeros && !all_ones
+    }
+
+    /// Check whether this is a resolvable private address.
+    pub fn is_resolvable_private(&self) -> bool {
+        let a = self.addr.into_inner();
+        let prand = [a[3], a[4], a[5] & 0x3F];
+        let all_zeros = prand == [0, 0, 0];
+        let all_ones = prand == [0xFF, 0xFF, 0x3F];
+        self.kind == AddrKind::RANDOM && a[5] & 0xC0 == 0x40 && !all_zeros && !all_ones
+    }
+
+    /// Check whether this is a non-resolvable private address.
+    pub fn is_non_resolvable_private(&self) -> bool {
+        let a = self.addr.into_inner();
+        let random = &a[..5];
+        let top = a[5] & 0x3F;
+        let all_zeros = random.iter().all(|b| *b == 0) && top == 0;
+        let all_ones = random.iter().all(|b| *b == 0xFF) && top == 0x3F;
+        self.kind == AddrKind::RANDOM && a[5] & 0xC0 == 0x00 && !all_zeros && !all_ones
+    }
+
+    /// Classify this address, or `None` if it is a random address that breaks the rules of the
+    /// Core Specification (Vol 6, Part B, Section 1.3).
+    pub fn address_type(&self) -> Option<AddressType> {
+        if self.kind == AddrKind::PUBLIC {
+            Some(AddressType::Public)
+        } else if self.is_random_static() {
+            Some(AddressType::RandomStatic)
+        } else if self.is_resolvable_private() {
+            Some(AddressType::ResolvablePrivate)
+        } else if self.is_non_resolvable_private() {
+            Some(AddressType::NonResolvablePrivate)
+        } else {
+            None
+        }
+    }
+
+    /// Check whether this address is valid for its kind.
+    ///
+    /// Public addresses are always valid, random addresses must be static, resolvable private or
+    /// non-resolvable private.
+    pub fn is_valid(&self) -> bool {
+        self.address_type().is_some()
+    }
+
     /// To bytes
     pub fn to_bytes(&self) -> [u8; 7] {
         let mut bytes = [0; 7];
@@ -158,6 +300,15 @@ impl Address {
     }
 }
 
+impl core::str::FromStr for Address {
+    type Err = Error;
+
+    /// Parse a public address in colon notation. Use [`Address::parse`] for other address kinds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(AddrKind::PUBLIC, s)
+    }
+}
+
 impl core::fmt::Display for Address {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let a = self.addr.into_inner();
@@ -186,6 +337,20 @@ impl defmt::Format for Address {
     }
 }
 
+/// Classification of a BLE address.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressType {
+    /// Public address assigned by the IEEE.
+    Public,
+    /// Random static address.
+    RandomStatic,
+    /// Resolvable private address.
+    ResolvablePrivate,
+    /// Non-resolvable private address.
+    NonResolvablePrivate,
+}
+
 /// Identity of a peer device
 ///
 /// Sometimes we have to save both the address and the IRK.
@@ -193,8 +358,10 @@ impl defmt::Format for Address {
 /// In this case, the IRK exists but the used address is not RPA.
 /// Should `Address` be used instead?
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Identity {
     /// Random static or public address
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impl::bd_addr"))]
     pub bd_addr: BdAddr,
 
     /// Identity Resolving Key
@@ -351,6 +518,12 @@ pub enum Error {
     ///
     /// The limit can be modified using the `gatt-client-notification-max-subscribers-N` features.
     GattSubscriberLimitReached,
+    /// A GATT client notification queue overflowed and notifications were discarded.
+    NotificationOverflowed,
+    /// The security manager random number generator has not been seeded.
+    RandomGeneratorNotSeeded,
+    /// The resource or channel configuration is inconsistent.
+    InvalidConfiguration(&'static str),
     /// Other error.
     Other,
 }
@@ -419,6 +592,7 @@ pub trait Controller:
     bt_hci::controller::Controller
     + embedded_io::ErrorType
     + ControllerCmdSync<LeReadBufferSize>
+    + ControllerCmdSync<LeReadBufferSizeV2>
     + ControllerCmdSync<Disconnect>
     + ControllerCmdSync<SetEventMask>
     + ControllerCmdSync<SetEventMaskPage2>
@@ -427,6 +601,9 @@ pub trait Controller:
     + ControllerCmdSync<HostBufferSize>
     + ControllerCmdAsync<LeConnUpdate>
     + ControllerCmdSync<LeReadFilterAcceptListSize>
+    + ControllerCmdSync<LeReadLocalSupportedFeatures>
+    + ControllerCmdSync<LeReadSupportedStates>
+    + ControllerCmdSync<LeReadMaxAdvDataLength>
     + ControllerCmdSync<SetControllerToHostFlowControl>
     + ControllerCmdSync<Reset>
     + ControllerCmdSync<ReadRssi>
@@ -454,6 +631,7 @@ impl<
         C: bt_hci::controller::Controller
             + embedded_io::ErrorType
             + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeReadBufferSizeV2>
             + ControllerCmdSync<Disconnect>
             + ControllerCmdSync<SetEventMask>
             + ControllerCmdSync<SetEventMaskPage2>
@@ -462,6 +640,9 @@ impl<
             + ControllerCmdSync<HostBufferSize>
             + ControllerCmdAsync<LeConnUpdate>
             + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>
+            + ControllerCmdSync<LeReadSupportedStates>
+            + ControllerCmdSync<LeReadMaxAdvDataLength>
             + ControllerCmdSync<LeClearFilterAcceptList>
             + ControllerCmdSync<LeAddDeviceToFilterAcceptList>
             + ControllerCmdSync<SetControllerToHostFlowControl>
@@ -509,10 +690,44 @@ pub trait PacketPool: 'static {
     /// amount of bytes it has received.
     fn allocate() -> Option<Self::Packet>;
 
+    /// Allocate a new buffer with space for at least `len` bytes.
+    /// Return `None` when the allocation can't be fulfilled.
+    ///
+    /// Pools with several size classes can hand out a buffer smaller than `MTU`
+    /// for short packets. The default implementation allocates an `MTU` sized buffer.
+    fn allocate_sized(len: usize) -> Option<Self::Packet> {
+        Self::allocate()
+    }
+
     /// Capacity of this pool in the number of packets.
     fn capacity() -> usize;
+
+    /// Usage metrics of this pool, if the pool keeps track of them.
+    fn metrics() -> Option<PacketPoolMetrics> {
+        None
+    }
 }
 
+/// Usage metrics of a packet pool.
+///
+/// A pool that keeps running out of packets while the high-water mark equals the capacity is undersized,
+/// one whose free count does not recover when idle is leaking packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PacketPoolMetrics {
+    /// Number of packets in the pool.
+    pub capacity: usize,
+    /// Number of packets currently free.
+    pub free: usize,
+    /// Highest number of packets in use at the same time.
+    pub high_water: usize,
+    /// Number of allocations that failed because no packet was free.
+    pub alloc_failures: u32,
+}
+
+/// Smallest packet pool MTU that fits the minimum ATT MTU (23) and the L2CAP header (4).
+pub(crate) const MIN_PACKET_POOL_MTU: usize = 27;
+
 /// HostResources holds the resources used by the host.
 ///
 /// The l2cap packet pool is used by the host to handle inbound data, by allocating space for
@@ -535,7 +750,15 @@ impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: u
     HostResources<P, CONNS, CHANNELS, ADV_SETS>
 {
     /// Create a new instance of host resources.
+    ///
+    /// Fails to compile if the packet pool cannot hold a minimum sized ATT PDU.
     pub const fn new() -> Self {
+        const {
+            assert!(
+                P::MTU >= MIN_PACKET_POOL_MTU,
+                "packet pool MTU must be at least 27 bytes (minimum ATT MTU of 23 plus the 4 byte L2CAP header)"
+            );
+        }
         Self {
             connections: MaybeUninit::uninit(),
             channels: MaybeUninit::uninit(),
@@ -546,6 +769,11 @@ impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: u
 
 /// Create a new instance of the BLE host using the provided controller implementation and
 /// the resource configuration
+///
+/// All host state lives in the returned [`Stack`] and the provided resources, so several stacks can run
+/// side by side against different controllers. The only state shared between them is the packet pool,
+/// which is selected by type: use a distinct [`PacketPool`] implementation per stack if they must not
+/// compete for packets.
 pub fn new<
     'resources,
     C: Controller,
@@ -576,6 +804,38 @@ pub fn new<
 
     let advertise_handles = &mut *resources.advertise_handles.write([AdvHandleState::None; ADV_SETS]);
     let advertise_handles: &'static mut [AdvHandleState] = unsafe { transmute_slice(advertise_handles) };
+    let host: BleHost<'_, C, P> = BleHost::new(controller, connections.into(), channels.into(), advertise_handles);
+
+    Stack { host }
+}
+
+/// Maximum number of L2CAP channels, limited by the range of dynamically allocated channel identifiers.
+#[cfg(feature = "alloc")]
+const MAX_DYNAMIC_CHANNELS: usize = 64;
+
+/// Create a new instance of the BLE host with connection and channel tables allocated on the heap.
+///
+/// The tables start with `CONNS` connections and `CHANNELS` channels, like with [`new`], and grow when they
+/// run out of free slots, up to `max_connections` connections and `max_channels` channels. The limits are
+/// raised to the initial sizes if lower, and capped at 255 connections and 64 channels. Only the advertising
+/// handles are taken from `resources`.
+#[cfg(feature = "alloc")]
+pub fn new_dynamic<
+    'resources,
+    C: Controller,
+    P: PacketPool,
+    const CONNS: usize,
+    const CHANNELS: usize,
+    const ADV_SETS: usize,
+>(
+    controller: C,
+    resources: &'resources mut HostResources<P, CONNS, CHANNELS, ADV_SETS>,
+    max_connections: usize,
+    max_channels: usize,
+) -> Stack<'resources, C, P> {
+    let connections = Table::growable(CONNS, max_connections.min(u8::MAX as usize), ConnectionStorage::new);
+    let channels = Table::growable(CHANNELS, max_channels.min(MAX_DYNAMIC_CHANNELS), ChannelStorage::new);
+    let advertise_handles = &mut *resources.advertise_handles.write([AdvHandleState::None; ADV_SETS]);
     let host: BleHost<'_, C, P> = BleHost::new(controller, connections, channels, advertise_handles);
 
     Stack { host }
@@ -607,6 +867,31 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
         self.host.connections.security_manager.set_local_address(address);
         self
     }
+
+    /// Use a random static address, generating it on first boot.
+    ///
+    /// If `persisted` holds a valid random static address it is used as-is. Otherwise a new address is
+    /// generated from `rng` and handed to `persist`, so the application can store it (e.g. in flash) and
+    /// provide it as `persisted` on the next boot. The address is programmed into the controller when the
+    /// runner initializes it.
+    pub fn set_random_static_address<RNG: RngCore, F: FnOnce(&Address)>(
+        self,
+        persisted: Option<Address>,
+        rng: &mut RNG,
+        persist: F,
+    ) -> Self {
+        let address = match persisted {
+            Some(address) if address.is_random_static() => address,
+            _ => {
+                let address = Address::random_static(rng);
+                info!("[host] generated random static address {}", address);
+                persist(&address);
+                address
+            }
+        };
+        self.set_random_address(address)
+    }
+
     /// Set the random generator seed for random generator used by security manager
     pub fn set_random_generator_seed<RNG: RngCore + CryptoRng>(self, _random_generator: &mut RNG) -> Self {
         #[cfg(feature = "security")]
@@ -634,23 +919,56 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
         self
     }
 
+    /// Set the protection against repeated pairing attempts, or disable it with `None`.
+    ///
+    /// Disabled unless set, [`PairingBackoff::default`] follows the recommendation of the Core Specification.
+    #[cfg(feature = "security")]
+    pub fn set_pairing_backoff(self, backoff: Option<PairingBackoff>) -> Self {
+        self.host.connections.security_manager.set_pairing_backoff(backoff);
+        self
+    }
+
+    /// Enable the controller watchdog.
+    ///
+    /// When enabled, any HCI command that does not complete, or any outbound packet waiting for
+    /// controller credits longer than `timeout`, is treated as a hung controller. The host will then
+    /// reset the controller and re-establish its configuration, see [`Stack::reset_controller`].
+    pub fn set_controller_watchdog(mut self, timeout: Duration) -> Self {
+        self.host.watchdog_timeout.replace(timeout);
+        self
+    }
+
     /// Build the stack.
+    ///
+    /// Panics if the configuration is invalid, see [`Stack::try_build`] for a non-panicking variant.
     pub fn build(&'stack self) -> Host<'stack, C, P> {
+        match self.try_build() {
+            Ok(host) => host,
+            Err(Error::RandomGeneratorNotSeeded) => panic!(
+                "The security manager random number generator has not been seeded from a cryptographically secure random number generator"
+            ),
+            Err(e) => panic!("invalid stack configuration: {:?}", e),
+        }
+    }
+
+    /// Build the stack, returning an error instead of panicking if the configuration is invalid.
+    ///
+    /// Returns [`Error::RandomGeneratorNotSeeded`] if the `security` feature is enabled and
+    /// [`Stack::set_random_generator_seed`] has not been called.
+    pub fn try_build(&'stack self) -> Result<Host<'stack, C, P>, Error> {
         #[cfg(all(feature = "security", not(feature = "dev-disable-csprng-seed-requirement")))]
         {
             if !self.host.connections.security_manager.get_random_generator_seeded() {
-                panic!(
-                    "The security manager random number generator has not been seeded from a cryptographically secure random number generator"
-                )
+                return Err(Error::RandomGeneratorNotSeeded);
             }
         }
-        Host {
+        Ok(Host {
             #[cfg(feature = "central")]
             central: Central::new(self),
             #[cfg(feature = "peripheral")]
             peripheral: Peripheral::new(self),
             runner: Runner::new(self),
-        }
+        })
     }
 
     /// Run a HCI command and return the response.
@@ -671,11 +989,137 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
         self.host.async_command(cmd).await
     }
 
+    /// Send a complete SDU on an isochronous stream.
+    ///
+    /// The SDU is sent in a single HCI ISO data packet, so it must not be longer than
+    /// [`iso::ISO_MAX_PACKET_DATA_LEN`] or [`ControllerInfo::iso_max_len`]. Waits for one of the ISO
+    /// buffers of the controller to be free, and fails with [`Error::NotSupported`] if it has none. The timestamp, in
+    /// microseconds in the time base of the controller, tells the controller when the SDU should be
+    /// transmitted; without it the controller transmits the SDU on the next available event.
+    pub async fn send_iso_sdu(
+        &self,
+        handle: ConnHandle,
+        timestamp: Option<u32>,
+        sequence_number: u16,
+        sdu: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        if let Some(info) = self.host.controller_info() {
+            if sdu.len() > info.iso_max_len as usize {
+                return Err(Error::InsufficientSpace.into());
+            }
+        }
+        let mut buf = [0; iso::ISO_MAX_PACKET_LEN];
+        let len = iso::encode_sdu(&mut buf, handle, timestamp, sequence_number, sdu)?;
+        let packet = bt_hci::data::IsoPacket::from_hci_bytes_complete(&buf[..len]).map_err(Error::HciDecode)?;
+        self.host.iso_credits.acquire(handle).await?;
+        if let Err(e) = self.host.controller.write_iso_data(&packet).await {
+            self.host.iso_credits.release(handle, 1);
+            return Err(BleHostError::Controller(e));
+        }
+        Ok(())
+    }
+
+    /// Report the anchor point of a connection event, as provided by the controller.
+    ///
+    /// Controllers that report anchor points, typically through vendor events, make
+    /// [`Connection::event_timing`](connection::Connection::event_timing) exact instead of estimated by the host. Fails with
+    /// [`Error::NotFound`] until the connection interval is known.
+    pub fn set_connection_event_anchor(
+        &self,
+        handle: ConnHandle,
+        event_counter: u16,
+        anchor: embassy_time::Instant,
+    ) -> Result<(), Error> {
+        self.host.connections.update_event_timing(handle, |timing| {
+            let timing = timing.ok_or(Error::NotFound)?;
+            Ok(connection::ConnectionEventTiming {
+                event_counter,
+                anchor,
+                interval: timing.interval,
+                reported: true,
+            })
+        })
+    }
+
+    /// Request a reset of the controller.
+    ///
+    /// The control runner resets the controller and re-programs the random address, event masks, buffer
+    /// configuration and filter accept list. All connections are reported as disconnected, and any ongoing
+    /// connect procedure is stopped. Advertising, scanning and the resolving list are restored if a task waits
+    /// in [`Stack::radio_state_restored`], otherwise advertising and scanning are stopped.
+    pub fn reset_controller(&self) {
+        self.host.reset_state.request();
+    }
+
+    /// Wait until the controller has been reset and re-initialized.
+    ///
+    /// Completes for every recovery that happens after this is called, whether the reset was requested
+    /// by the application, the watchdog or a hardware error reported by the controller. Returns the
+    /// total number of recoveries so far.
+    pub async fn controller_recovered(&self) -> u32 {
+        let seen = self.host.reset_state.recoveries();
+        poll_fn(|cx| self.host.reset_state.poll_recovered(seen, cx)).await
+    }
+
+    /// Wait until the controller has been reset, then restore the radio state.
+    ///
+    /// While this is waiting, a recovery keeps active advertising and scanning. Once the controller is
+    /// re-initialized, this re-applies the resolving list, advertising and scanning, and reports which parts
+    /// of the configuration were restored. Advertising and scanning that cannot be restored are stopped, as
+    /// they are if no task is waiting here during the recovery. Only one task should wait here at a time.
+    ///
+    /// Advertising sets with more than 251 bytes of data, more than 2 extended advertising sets, and
+    /// filter accept lists of more than 16 entries are not restored.
+    pub async fn radio_state_restored(&self) -> RestoredState
+    where
+        C: ControllerCmdSync<LeAddDeviceToResolvingList>
+            + ControllerCmdSync<LeSetAddrResolutionEnable>
+            + ControllerCmdSync<LeSetExtAdvParams>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+            + ControllerCmdSync<LeSetScanParams>
+            + ControllerCmdSync<LeSetExtScanParams>,
+    {
+        let host = &self.host;
+        host.reset_state.set_restorer(true);
+        // Stop what was left for this task if it goes away before restoring it.
+        let _restorer = crate::host::OnDrop::new(|| {
+            if let Some(pending) = host.reset_state.set_restorer(false) {
+                host.stop_radio_activity(pending.advertising, pending.scanning);
+            }
+        });
+        self.controller_recovered().await;
+        host.restore_radio_state().await
+    }
+
+    /// Capabilities and limits of the controller.
+    ///
+    /// Returns `None` until the runner has initialized the controller.
+    pub fn controller_info(&self) -> Option<ControllerInfo> {
+        self.host.controller_info()
+    }
+
     /// Read current host metrics
     pub fn metrics<F: FnOnce(&HostMetrics) -> R, R>(&self, f: F) -> R {
         self.host.metrics(f)
     }
 
+    /// Estimated radio activity of the advertising sets and scanning.
+    ///
+    /// The controller does not report radio usage, so it is estimated from the time advertising and
+    /// scanning were enabled and their parameters. Use
+    /// [`Connection::radio_activity`](connection::Connection::radio_activity) for the connection events of
+    /// a link.
+    pub fn radio_usage(&self) -> RadioUsage {
+        self.host.activity.borrow().usage()
+    }
+
+    /// Clear the radio activity accumulated so far, for instance at the start of a measurement window.
+    pub fn reset_radio_usage(&self) {
+        self.host.activity.borrow_mut().reset();
+    }
+
     /// Log status information of the host
     pub fn log_status(&self, verbose: bool) {
         self.host.log_status(verbose);
@@ -710,3 +1154,60 @@ pub(crate) fn bt_hci_duration<const US: u32>(d: Duration) -> bt_hci::param::Dura
 pub(crate) fn bt_hci_ext_duration<const US: u16>(d: Duration) -> bt_hci::param::ExtDuration<US> {
     bt_hci::param::ExtDuration::from_micros(d.as_micros())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_static_address() {
+        let mut rng = rand::rngs::OsRng;
+        for _ in 0..16 {
+            let address = Address::random_static(&mut rng);
+            assert!(address.is_random_static());
+            assert_eq!(address.addr.into_inner()[5] & 0xC0, 0xC0);
+        }
+        assert!(!Address::random([0, 0, 0, 0, 0, 0xC0]).is_random_static());
+        assert!(!Address::random([0xFF; 6]).is_random_static());
+        assert!(!Address::random([1, 2, 3, 4, 5, 6]).is_random_static());
+    }
+
+    #[test]
+    fn parse_address() {
+        let address: Address = "C0:11:22:33:44:5f".parse().unwrap();
+        assert_eq!(address, Address::public([0x5F, 0x44, 0x33, 0x22, 0x11, 0xC0]));
+
+        let address = Address::parse(AddrKind::RANDOM, "C0:11:22:33:44:55").unwrap();
+        assert_eq!(address.kind, AddrKind::RANDOM);
+        let mut s: heapless::String<17> = heapless::String::new();
+        core::fmt::write(&mut s, format_args!("{}", address)).unwrap();
+        assert_eq!(s.as_str(), "C0:11:22:33:44:55");
+
+        assert!("C0:11:22:33:44".parse::<Address>().is_err());
+        assert!("C0:11:22:33:44:55:66".parse::<Address>().is_err());
+        assert!("C0:11:22:33:44:5".parse::<Address>().is_err());
+        assert!("C0:11:22:33:44:ZZ".parse::<Address>().is_err());
+        assert!("C0:11:22:33:44:+5".parse::<Address>().is_err());
+        assert!("C0:11:22:33:44:-5".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn classify_address() {
+        assert_eq!(Address::public([0; 6]).address_type(), Some(AddressType::Public));
+        assert_eq!(
+            Address::random([1, 2, 3, 4, 5, 0xC6]).address_type(),
+            Some(AddressType::RandomStatic)
+        );
+        assert_eq!(
+            Address::random([1, 2, 3, 4, 5, 0x46]).address_type(),
+            Some(AddressType::ResolvablePrivate)
+        );
+        assert_eq!(
+            Address::random([1, 2, 3, 4, 5, 0x06]).address_type(),
+            Some(AddressType::NonResolvablePrivate)
+        );
+        assert_eq!(Address::random([1, 2, 3, 4, 5, 0x86]).address_type(), None);
+        assert_eq!(Address::random([1, 2, 3, 0, 0, 0x40]).address_type(), None);
+        assert!(!Address::random([0; 6]).is_valid());
+    }
+}
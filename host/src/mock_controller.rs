@@ -1,14 +1,27 @@
+extern crate std;
+
+use core::cell::RefCell;
 use core::convert::Infallible;
 use core::future::Future;
+use std::vec::Vec;
 
 use bt_hci::cmd::{self, AsyncCmd, SyncCmd};
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
 
-pub struct MockController {}
+pub struct MockController {
+    acl_data: RefCell<Vec<Vec<u8>>>,
+}
 
 impl MockController {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            acl_data: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Take the data of the ACL packets written so far.
+    pub fn take_acl_data(&self) -> Vec<Vec<u8>> {
+        self.acl_data.take()
     }
 }
 
@@ -18,7 +31,8 @@ impl embedded_io::ErrorType for MockController {
 
 impl bt_hci::controller::blocking::Controller for MockController {
     fn write_acl_data(&self, packet: &bt_hci::data::AclPacket) -> Result<(), Self::Error> {
-        todo!()
+        self.acl_data.borrow_mut().push(packet.data().to_vec());
+        Ok(())
     }
 
     fn write_sync_data(&self, packet: &bt_hci::data::SyncPacket) -> Result<(), Self::Error> {
@@ -33,7 +47,8 @@ impl bt_hci::controller::blocking::Controller for MockController {
         &self,
         packet: &bt_hci::data::AclPacket,
     ) -> Result<(), bt_hci::controller::blocking::TryError<Self::Error>> {
-        todo!()
+        self.acl_data.borrow_mut().push(packet.data().to_vec());
+        Ok(())
     }
 
     fn try_write_sync_data(
@@ -64,7 +79,11 @@ impl bt_hci::controller::blocking::Controller for MockController {
 
 impl bt_hci::controller::Controller for MockController {
     fn write_acl_data(&self, packet: &bt_hci::data::AclPacket) -> impl Future<Output = Result<(), Self::Error>> {
-        async { todo!() }
+        let data = packet.data().to_vec();
+        async move {
+            self.acl_data.borrow_mut().push(data);
+            Ok(())
+        }
     }
 
     fn write_sync_data(&self, packet: &bt_hci::data::SyncPacket) -> impl Future<Output = Result<(), Self::Error>> {
@@ -1,11 +1,24 @@
 //! A packet pool for allocating and freeing packet buffers with quality of service policy.
+//!
+//! Besides the `DefaultPacketPool`, packet pools can be defined with [`static_packet_pool!`](crate::static_packet_pool),
+//! which allows placing the packets in a specific memory section. The `packet-pool-align-{4,8,16,32}` features align
+//! every packet buffer of static pools, so DMA based HCI transports can use them directly.
 use core::cell::RefCell;
 
-use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
+#[doc(hidden)]
+pub use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 
-use crate::{config, Packet, PacketPool};
+#[cfg(feature = "default-packet-pool")]
+use crate::config;
+use crate::{Packet, PacketPool, PacketPoolMetrics};
 
+#[repr(C)]
+#[cfg_attr(feature = "packet-pool-align-4", repr(align(4)))]
+#[cfg_attr(feature = "packet-pool-align-8", repr(align(8)))]
+#[cfg_attr(feature = "packet-pool-align-16", repr(align(16)))]
+#[cfg_attr(feature = "packet-pool-align-32", repr(align(32)))]
 struct PacketBuf<const MTU: usize> {
     buf: [u8; MTU],
     free: bool,
@@ -24,21 +37,40 @@ impl<const MTU: usize> PacketBuf<MTU> {
 
 struct State<const MTU: usize, const N: usize> {
     packets: [PacketBuf<MTU>; N],
+    used: usize,
+    high_water: usize,
+    alloc_failures: u32,
+    on_exhausted: Option<fn()>,
 }
 
 impl<const MTU: usize, const N: usize> State<MTU, N> {
     pub(crate) const fn new() -> Self {
         Self {
             packets: [PacketBuf::NEW; N],
+            used: 0,
+            high_water: 0,
+            alloc_failures: 0,
+            on_exhausted: None,
         }
     }
 
     fn alloc(&mut self) -> Option<PacketRef<MTU>> {
+        let p_ref = self.take();
+        if p_ref.is_none() {
+            self.alloc_failures = self.alloc_failures.saturating_add(1);
+        }
+        p_ref
+    }
+
+    /// Take a free packet, without counting a failure if there is none.
+    fn take(&mut self) -> Option<PacketRef<MTU>> {
         for (idx, packet) in self.packets.iter_mut().enumerate() {
             if packet.free {
                 // info!("[{}] alloc {}", id.0, idx);
                 packet.free = false;
                 packet.buf.iter_mut().for_each(|b| *b = 0);
+                self.used += 1;
+                self.high_water = self.high_water.max(self.used);
                 return Some(PacketRef {
                     idx,
                     buf: packet.buf.as_mut_ptr(),
@@ -51,11 +83,21 @@ impl<const MTU: usize, const N: usize> State<MTU, N> {
     fn free(&mut self, p_ref: &PacketRef<MTU>) {
         // info!("[{}] free {}", id.0, p_ref.idx);
         self.packets[p_ref.idx].free = true;
+        self.used -= 1;
     }
 
     fn available(&mut self) -> usize {
         self.packets.iter().filter(|p| p.free).count()
     }
+
+    fn metrics(&self) -> PacketPoolMetrics {
+        PacketPoolMetrics {
+            capacity: N,
+            free: N - self.used,
+            high_water: self.high_water,
+            alloc_failures: self.alloc_failures,
+        }
+    }
 }
 
 /// A packet pool holds a pool of packet buffers that can be dynamically allocated
@@ -71,18 +113,26 @@ impl<M: RawMutex, const MTU: usize, const N: usize> Default for StaticPacketPool
 }
 
 impl<M: RawMutex, const MTU: usize, const N: usize> StaticPacketPool<M, MTU, N> {
-    /// Create a new packet pool with the given QoS policy
-    const fn new() -> Self {
+    /// Create a new packet pool.
+    pub const fn new() -> Self {
         Self {
             state: Mutex::new(RefCell::new(State::new())),
         }
     }
 
     fn alloc(&self) -> Option<PacketRef<MTU>> {
-        self.state.lock(|state| {
+        let (p_ref, on_exhausted) = self.state.lock(|state| {
             let mut state = state.borrow_mut();
-            state.alloc()
-        })
+            let p_ref = state.alloc();
+            (p_ref, state.on_exhausted)
+        });
+        // Called outside of the lock, so the callback may inspect the pool.
+        if p_ref.is_none() {
+            if let Some(f) = on_exhausted {
+                f();
+            }
+        }
+        p_ref
     }
 
     fn free(&self, p_ref: &PacketRef<MTU>) {
@@ -98,6 +148,197 @@ impl<M: RawMutex, const MTU: usize, const N: usize> StaticPacketPool<M, MTU, N>
             state.available()
         })
     }
+
+    /// Allocate a packet from this pool.
+    pub fn allocate_packet(&'static self) -> Option<StaticPacket<MTU>> {
+        self.alloc().map(|p_ref| StaticPacket { p_ref, pool: self })
+    }
+
+    /// Usage metrics of this pool.
+    pub fn usage(&self) -> PacketPoolMetrics {
+        self.state.lock(|state| state.borrow().metrics())
+    }
+
+    /// Set a function called whenever an allocation fails because all packets are in use.
+    pub fn set_on_exhausted(&self, f: Option<fn()>) {
+        self.state.lock(|state| state.borrow_mut().on_exhausted = f);
+    }
+}
+
+#[cfg(any(test, feature = "default-packet-pool"))]
+/// Packets of `MTU` bytes together with a class of `SMALL_MTU` byte packets, which serves the
+/// allocations that fit in them first.
+struct SizeClassPool<M: RawMutex, const MTU: usize, const N: usize, const SMALL_MTU: usize, const SMALL_N: usize> {
+    state: Mutex<M, RefCell<SizeClassState<MTU, N, SMALL_MTU, SMALL_N>>>,
+}
+
+#[cfg(any(test, feature = "default-packet-pool"))]
+struct SizeClassState<const MTU: usize, const N: usize, const SMALL_MTU: usize, const SMALL_N: usize> {
+    large: State<MTU, N>,
+    small: State<SMALL_MTU, SMALL_N>,
+    /// Highest number of packets of both classes in use at the same time.
+    high_water: usize,
+}
+
+#[cfg(any(test, feature = "default-packet-pool"))]
+enum SizedPacketRef<const MTU: usize, const SMALL_MTU: usize> {
+    Large(PacketRef<MTU>),
+    Small(PacketRef<SMALL_MTU>),
+}
+
+#[cfg(any(test, feature = "default-packet-pool"))]
+impl<M: RawMutex, const MTU: usize, const N: usize, const SMALL_MTU: usize, const SMALL_N: usize>
+    SizeClassPool<M, MTU, N, SMALL_MTU, SMALL_N>
+{
+    const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(SizeClassState {
+                large: State::new(),
+                small: State::new(),
+                high_water: 0,
+            })),
+        }
+    }
+
+    /// Allocate a packet of at least `len` bytes.
+    fn alloc(&self, len: usize) -> Option<SizedPacketRef<MTU, SMALL_MTU>> {
+        let (p_ref, on_exhausted) = self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let state = &mut *state;
+            // Running out of small packets is not a failure, as a large packet is taken instead.
+            let small = if len <= SMALL_MTU { state.small.take() } else { None };
+            let p_ref = match small {
+                Some(p) => Some(SizedPacketRef::Small(p)),
+                None => state.large.alloc().map(SizedPacketRef::Large),
+            };
+            state.high_water = state.high_water.max(state.large.used + state.small.used);
+            (p_ref, state.large.on_exhausted)
+        });
+        // Called outside of the lock, so the callback may inspect the pool.
+        if p_ref.is_none() {
+            if let Some(f) = on_exhausted {
+                f();
+            }
+        }
+        p_ref
+    }
+
+    fn free(&self, p_ref: &SizedPacketRef<MTU, SMALL_MTU>) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            match p_ref {
+                SizedPacketRef::Large(p) => state.large.free(p),
+                SizedPacketRef::Small(p) => state.small.free(p),
+            }
+        });
+    }
+
+    /// Usage metrics of the packets of both classes.
+    fn usage(&self) -> PacketPoolMetrics {
+        self.state.lock(|state| {
+            let state = state.borrow();
+            let (large, small) = (state.large.metrics(), state.small.metrics());
+            PacketPoolMetrics {
+                capacity: large.capacity + small.capacity,
+                free: large.free + small.free,
+                high_water: state.high_water,
+                alloc_failures: large.alloc_failures,
+            }
+        })
+    }
+
+    fn set_on_exhausted(&self, f: Option<fn()>) {
+        self.state.lock(|state| state.borrow_mut().large.on_exhausted = f);
+    }
+}
+
+trait FreePacket<const MTU: usize> {
+    fn free_packet(&self, p_ref: &PacketRef<MTU>);
+}
+
+impl<M: RawMutex, const MTU: usize, const N: usize> FreePacket<MTU> for StaticPacketPool<M, MTU, N> {
+    fn free_packet(&self, p_ref: &PacketRef<MTU>) {
+        self.free(p_ref);
+    }
+}
+
+/// Type representing a packet allocated from a [`StaticPacketPool`].
+pub struct StaticPacket<const MTU: usize> {
+    p_ref: PacketRef<MTU>,
+    pool: &'static dyn FreePacket<MTU>,
+}
+
+impl<const MTU: usize> Packet for StaticPacket<MTU> {}
+
+impl<const MTU: usize> AsRef<[u8]> for StaticPacket<MTU> {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.p_ref.buf, MTU) }
+    }
+}
+
+impl<const MTU: usize> AsMut<[u8]> for StaticPacket<MTU> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.p_ref.buf, MTU) }
+    }
+}
+
+impl<const MTU: usize> Drop for StaticPacket<MTU> {
+    fn drop(&mut self) {
+        self.pool.free_packet(&self.p_ref);
+    }
+}
+
+/// Define a packet pool of `N` packets of `MTU` bytes, backed by a static [`StaticPacketPool`].
+///
+/// Attributes are applied to the static holding the packets, which allows placing them in
+/// a specific memory section, for instance one the DMA of the HCI transport can access:
+///
+/// ```ignore
+/// trouble_host::static_packet_pool!(
+///     #[link_section = ".ram2"]
+///     pub DmaPacketPool, 251, 8
+/// );
+///
+/// let resources: HostResources<DmaPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
+/// ```
+#[macro_export]
+macro_rules! static_packet_pool {
+    ($(#[$attr:meta])* $vis:vis $name:ident, $mtu:expr, $n:expr) => {
+        /// A packet pool defined with `static_packet_pool!`.
+        $vis struct $name;
+
+        const _: () = {
+            $(#[$attr])*
+            static POOL: $crate::packet_pool::StaticPacketPool<
+                $crate::packet_pool::CriticalSectionRawMutex,
+                { $mtu },
+                { $n },
+            > = $crate::packet_pool::StaticPacketPool::new();
+
+            impl $name {
+                /// Set a function called whenever an allocation fails because all packets are in use.
+                pub fn set_exhausted_handler(f: Option<fn()>) {
+                    POOL.set_on_exhausted(f);
+                }
+            }
+
+            impl $crate::PacketPool for $name {
+                type Packet = $crate::packet_pool::StaticPacket<{ $mtu }>;
+                const MTU: usize = $mtu;
+                fn capacity() -> usize {
+                    $n
+                }
+
+                fn allocate() -> Option<Self::Packet> {
+                    POOL.allocate_packet()
+                }
+
+                fn metrics() -> Option<$crate::PacketPoolMetrics> {
+                    Some(POOL.usage())
+                }
+            }
+        };
+    };
 }
 
 /// Represents a reference to a packet.
@@ -107,19 +348,39 @@ pub struct PacketRef<const MTU: usize> {
     buf: *mut u8,
 }
 
+#[cfg(feature = "default-packet-pool")]
 /// Global default packet pool.
+///
+/// Packets are `DEFAULT_PACKET_POOL_MTU` bytes, unless a small size class is configured with
+/// `DEFAULT_PACKET_POOL_SMALL_SIZE`: allocations of at most `DEFAULT_PACKET_POOL_SMALL_MTU` bytes
+/// through [`PacketPool::allocate_sized`] are then served from the small packets first.
 pub type DefaultPacketPool = StaticPacketPool<
     CriticalSectionRawMutex,
     { config::DEFAULT_PACKET_POOL_MTU },
     { config::DEFAULT_PACKET_POOL_SIZE },
 >;
 
-static DEFAULT_POOL: StaticPacketPool<
+#[cfg(feature = "default-packet-pool")]
+static DEFAULT_POOL: SizeClassPool<
     CriticalSectionRawMutex,
     { config::DEFAULT_PACKET_POOL_MTU },
     { config::DEFAULT_PACKET_POOL_SIZE },
-> = StaticPacketPool::new();
+    { config::DEFAULT_PACKET_POOL_SMALL_MTU },
+    { config::DEFAULT_PACKET_POOL_SMALL_SIZE },
+> = SizeClassPool::new();
 
+#[cfg(feature = "default-packet-pool")]
+impl DefaultPacketPool {
+    /// Set a function called whenever an allocation fails because all packets are in use.
+    ///
+    /// The function is called from the context of the allocation, which may be the host runner,
+    /// and should return quickly. Pass `None` to remove it.
+    pub fn set_exhausted_handler(f: Option<fn()>) {
+        DEFAULT_POOL.set_on_exhausted(f);
+    }
+}
+
+#[cfg(feature = "default-packet-pool")]
 impl PacketPool for DefaultPacketPool {
     type Packet = DefaultPacket;
     const MTU: usize = { config::DEFAULT_PACKET_POOL_MTU };
@@ -128,40 +389,116 @@ impl PacketPool for DefaultPacketPool {
     }
 
     fn allocate() -> Option<DefaultPacket> {
-        DEFAULT_POOL.alloc().map(|p| DefaultPacket {
-            p_ref: p,
-            pool: &DEFAULT_POOL,
-        })
+        Self::allocate_sized(Self::MTU)
+    }
+
+    fn allocate_sized(len: usize) -> Option<DefaultPacket> {
+        DEFAULT_POOL.alloc(len).map(|p_ref| DefaultPacket { p_ref })
+    }
+
+    /// Metrics of the packets of both size classes. Allocations that fall back to a large packet
+    /// because the small packets are all in use are not counted as failures.
+    fn metrics() -> Option<PacketPoolMetrics> {
+        Some(DEFAULT_POOL.usage())
     }
 }
 
+#[cfg(feature = "default-packet-pool")]
+type DefaultPacketRef = SizedPacketRef<{ config::DEFAULT_PACKET_POOL_MTU }, { config::DEFAULT_PACKET_POOL_SMALL_MTU }>;
+
+#[cfg(feature = "default-packet-pool")]
 /// Type representing the packet from the default packet pool.
 pub struct DefaultPacket {
-    p_ref: PacketRef<{ config::DEFAULT_PACKET_POOL_MTU }>,
-    pool: &'static DefaultPacketPool,
+    p_ref: DefaultPacketRef,
 }
 
+#[cfg(feature = "default-packet-pool")]
+impl DefaultPacket {
+    fn raw(&self) -> (*mut u8, usize) {
+        match &self.p_ref {
+            SizedPacketRef::Large(p) => (p.buf, config::DEFAULT_PACKET_POOL_MTU),
+            SizedPacketRef::Small(p) => (p.buf, config::DEFAULT_PACKET_POOL_SMALL_MTU),
+        }
+    }
+}
+
+#[cfg(feature = "default-packet-pool")]
 impl Packet for DefaultPacket {}
+
+#[cfg(feature = "default-packet-pool")]
 impl AsRef<[u8]> for DefaultPacket {
     fn as_ref(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self.p_ref.buf, config::DEFAULT_PACKET_POOL_MTU) }
+        let (buf, len) = self.raw();
+        unsafe { core::slice::from_raw_parts(buf, len) }
     }
 }
 
+#[cfg(feature = "default-packet-pool")]
 impl AsMut<[u8]> for DefaultPacket {
     fn as_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self.p_ref.buf, config::DEFAULT_PACKET_POOL_MTU) }
+        let (buf, len) = self.raw();
+        unsafe { core::slice::from_raw_parts_mut(buf, len) }
     }
 }
 
+#[cfg(feature = "default-packet-pool")]
 impl Drop for DefaultPacket {
     fn drop(&mut self) {
-        self.pool.free(&self.p_ref);
+        DEFAULT_POOL.free(&self.p_ref);
+    }
+}
+
+// Also built for tests, so that the pool is covered without the `alloc` feature.
+/// A packet pool allocating packets of `MTU` bytes on the heap.
+///
+/// Every packet is allocated when requested and freed when dropped, so the pool does not need to be sized
+/// up front. Useful for host tools, simulators and tests running on std targets. Like other heap
+/// allocations, a failure to allocate a packet aborts instead of returning `None`.
+#[cfg(any(feature = "alloc", test))]
+pub struct AllocPool<const MTU: usize>;
+
+#[cfg(any(feature = "alloc", test))]
+impl<const MTU: usize> PacketPool for AllocPool<MTU> {
+    type Packet = AllocPacket;
+    const MTU: usize = MTU;
+    fn capacity() -> usize {
+        usize::MAX
+    }
+
+    fn allocate() -> Option<AllocPacket> {
+        Some(AllocPacket {
+            buf: alloc::vec![0; MTU].into_boxed_slice(),
+        })
+    }
+}
+
+/// Type representing a packet allocated on the heap by an [`AllocPool`].
+#[cfg(any(feature = "alloc", test))]
+pub struct AllocPacket {
+    buf: alloc::boxed::Box<[u8]>,
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl Packet for AllocPacket {}
+
+#[cfg(any(feature = "alloc", test))]
+impl AsRef<[u8]> for AllocPacket {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl AsMut<[u8]> for AllocPacket {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 
     use super::*;
@@ -191,4 +528,110 @@ mod tests {
         let b2 = pool.alloc();
         assert!(b2.is_none());
     }
+
+    static EXHAUSTED: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn pool_metrics() {
+        let pool: StaticPacketPool<NoopRawMutex, 27, 2> = StaticPacketPool::new();
+        pool.set_on_exhausted(Some(|| {
+            EXHAUSTED.fetch_add(1, Ordering::Relaxed);
+        }));
+
+        let a1 = pool.alloc().unwrap();
+        let a2 = pool.alloc().unwrap();
+        assert!(pool.alloc().is_none());
+        assert_eq!(EXHAUSTED.load(Ordering::Relaxed), 1);
+
+        pool.free(&a1);
+        let m = pool.usage();
+        assert_eq!(m.capacity, 2);
+        assert_eq!(m.free, 1);
+        assert_eq!(m.high_water, 2);
+        assert_eq!(m.alloc_failures, 1);
+        pool.free(&a2);
+    }
+
+    #[cfg(feature = "default-packet-pool")]
+    #[test]
+    fn default_pool_size_classes() {
+        let small = DefaultPacketPool::allocate_sized(7).unwrap();
+        let expected = if config::DEFAULT_PACKET_POOL_SMALL_SIZE > 0 {
+            config::DEFAULT_PACKET_POOL_SMALL_MTU
+        } else {
+            config::DEFAULT_PACKET_POOL_MTU
+        };
+        assert_eq!(small.as_ref().len(), expected);
+
+        let large = DefaultPacketPool::allocate_sized(config::DEFAULT_PACKET_POOL_MTU).unwrap();
+        assert_eq!(large.as_ref().len(), config::DEFAULT_PACKET_POOL_MTU);
+    }
+
+    #[test]
+    fn size_class_routing() {
+        let pool: SizeClassPool<NoopRawMutex, 64, 2, 16, 2> = SizeClassPool::new();
+        let is_small = |p: &SizedPacketRef<64, 16>| matches!(p, SizedPacketRef::Small(_));
+
+        // Allocations that fit in a small packet are served from the small class first.
+        let s1 = pool.alloc(16).unwrap();
+        assert!(is_small(&s1));
+        let l1 = pool.alloc(17).unwrap();
+        assert!(!is_small(&l1));
+        let s2 = pool.alloc(1).unwrap();
+        assert!(is_small(&s2));
+
+        // Once the small class is exhausted they fall back to large packets, which is not a failure.
+        let l2 = pool.alloc(1).unwrap();
+        assert!(!is_small(&l2));
+        assert_eq!(
+            pool.usage(),
+            PacketPoolMetrics {
+                capacity: 4,
+                free: 0,
+                high_water: 4,
+                alloc_failures: 0,
+            }
+        );
+        assert!(pool.alloc(1).is_none());
+        assert_eq!(pool.usage().alloc_failures, 1);
+
+        // A freed small packet is taken again by the next short allocation.
+        pool.free(&s1);
+        let s3 = pool.alloc(8).unwrap();
+        assert!(is_small(&s3));
+        for p in [s2, s3, l1, l2] {
+            pool.free(&p);
+        }
+        let m = pool.usage();
+        assert_eq!((m.free, m.high_water), (4, 4));
+    }
+
+    crate::static_packet_pool!(TestPacketPool, 27, 2);
+
+    #[test]
+    fn static_packet_pool() {
+        let a1 = TestPacketPool::allocate().unwrap();
+        let _a2 = TestPacketPool::allocate().unwrap();
+        assert!(TestPacketPool::allocate().is_none());
+        assert_eq!(a1.as_ref().len(), 27);
+        assert_eq!(
+            a1.as_ref().as_ptr() as usize % core::mem::align_of::<PacketBuf<27>>(),
+            0
+        );
+        drop(a1);
+
+        let m = TestPacketPool::metrics().unwrap();
+        assert_eq!(m.free, 1);
+        assert_eq!(m.high_water, 2);
+        assert!(TestPacketPool::allocate().is_some());
+    }
+
+    #[test]
+    fn alloc_pool() {
+        let mut a = AllocPool::<64>::allocate().unwrap();
+        assert_eq!(a.as_ref().len(), 64);
+        a.as_mut()[0] = 1;
+        let b = AllocPool::<64>::allocate().unwrap();
+        assert_eq!(b.as_ref()[0], 0);
+    }
 }
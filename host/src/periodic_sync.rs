@@ -0,0 +1,385 @@
+//! Synchronization to periodic advertising trains.
+//!
+//! Several trains can be followed at the same time, up to the limit of the controller. Each sync is
+//! identified by its sync handle, which is included in every [`PeriodicSyncEvent`] delivered to
+//! [`EventHandler::on_periodic_sync_event`](crate::prelude::EventHandler::on_periodic_sync_event).
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use bt_hci::cmd::le::{
+    LePeriodicAdvCreateSync, LePeriodicAdvCreateSyncCancel, LePeriodicAdvCreateSyncOptions, LePeriodicAdvTerminateSync,
+};
+use bt_hci::controller::{Controller, ControllerCmdAsync, ControllerCmdSync};
+use bt_hci::param::{AddrKind, BdAddr, CteMask, PhyKind, Status, SyncHandle};
+use bt_hci::FromHciBytes;
+use embassy_futures::select::{select, Either};
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, Timer};
+
+use crate::cursor::ReadCursor;
+use crate::scan::AdvDataStatus;
+use crate::{bt_hci_duration, Address, BleHostError, Error, PacketPool, Stack};
+
+/// Parameters for synchronizing to a periodic advertising train.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeriodicSyncParams {
+    /// Address of the advertiser.
+    pub address: Address,
+    /// Advertising set identifier of the train, as reported in
+    /// [`ScanReport::adv_sid`](crate::scan::ScanReport::adv_sid).
+    pub adv_sid: u8,
+    /// Number of periodic advertising events that may be skipped after a successful receive.
+    pub skip: u16,
+    /// Time without a received packet after which the sync is lost.
+    pub sync_timeout: Duration,
+    /// How long to wait for the first packet of the train before giving up.
+    pub establish_timeout: Duration,
+}
+
+impl PeriodicSyncParams {
+    /// Create parameters to sync to a train, with no skipping and a 2 s sync timeout.
+    pub const fn new(address: Address, adv_sid: u8) -> Self {
+        Self {
+            address,
+            adv_sid,
+            skip: 0,
+            sync_timeout: Duration::from_secs(2),
+            establish_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Information about an established periodic advertising sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeriodicSyncInfo {
+    /// Sync handle identifying the reports of this train.
+    pub handle: u16,
+    /// Advertising set identifier of the train.
+    pub adv_sid: u8,
+    /// Address of the advertiser.
+    pub address: Address,
+    /// PHY of the train.
+    pub phy: PhyKind,
+    /// Interval of the train.
+    pub interval: Duration,
+    /// Clock accuracy of the advertiser, as defined by the Core Specification.
+    pub clock_accuracy: u8,
+}
+
+/// A periodic advertising report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeriodicReport<'a> {
+    /// Sync handle of the train the report belongs to.
+    pub handle: u16,
+    /// Transmit power reported by the advertiser.
+    pub tx_power: Option<i8>,
+    /// Received signal strength.
+    pub rssi: Option<i8>,
+    /// Whether the advertising data is complete.
+    pub data_status: AdvDataStatus,
+    /// Advertising data.
+    pub data: &'a [u8],
+}
+
+/// An event of a periodic advertising sync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PeriodicSyncEvent<'a> {
+    /// A sync was established, either for the first time or again after it was lost.
+    Established(PeriodicSyncInfo),
+    /// A report was received on a sync.
+    Report(PeriodicReport<'a>),
+    /// The sync with the given handle was lost. The handle is no longer valid.
+    Lost(u16),
+}
+
+impl<'a> PeriodicSyncEvent<'a> {
+    /// Parse the parameters of an LE Periodic Advertising Sync Established event.
+    ///
+    /// Returns the error reported by the controller if the sync failed.
+    pub(crate) fn established(params: &[u8]) -> Result<Result<PeriodicSyncInfo, bt_hci::param::Error>, Error> {
+        let mut r = ReadCursor::new(params);
+        let status = Status::from_hci_bytes_complete(&[r.read::<u8>()?]).map_err(|_| Error::InvalidValue)?;
+        let handle: u16 = r.read()?;
+        let adv_sid: u8 = r.read()?;
+        let kind = AddrKind::from_hci_bytes_complete(&[r.read::<u8>()?]).map_err(|_| Error::InvalidValue)?;
+        let addr = BdAddr::from_hci_bytes_complete(r.slice(6)?).map_err(|_| Error::InvalidValue)?;
+        let phy = PhyKind::from_hci_bytes_complete(&[r.read::<u8>()?]).map_err(|_| Error::InvalidValue)?;
+        let interval: u16 = r.read()?;
+        let clock_accuracy: u8 = r.read()?;
+        if let Err(e) = status.to_result() {
+            return Ok(Err(e));
+        }
+        Ok(Ok(PeriodicSyncInfo {
+            handle,
+            adv_sid,
+            address: Address { kind, addr },
+            phy,
+            interval: Duration::from_micros(interval as u64 * 1250),
+            clock_accuracy,
+        }))
+    }
+
+    /// Parse the parameters of an LE Periodic Advertising Report event.
+    pub(crate) fn report(params: &'a [u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(params);
+        let handle: u16 = r.read()?;
+        let tx_power = r.read::<u8>()? as i8;
+        let rssi = r.read::<u8>()? as i8;
+        let _cte_type: u8 = r.read()?;
+        let data_status = match r.read::<u8>()? {
+            0 => AdvDataStatus::Complete,
+            1 => AdvDataStatus::MoreToCome,
+            2 => AdvDataStatus::Truncated,
+            _ => return Err(Error::InvalidValue),
+        };
+        let len: u8 = r.read()?;
+        let data = r.slice(len as usize)?;
+        Ok(Self::Report(PeriodicReport {
+            handle,
+            tx_power: (tx_power != 127).then_some(tx_power),
+            rssi: (rssi != 127).then_some(rssi),
+            data_status,
+            data,
+        }))
+    }
+
+    /// Parse the parameters of an LE Periodic Advertising Sync Lost event.
+    pub(crate) fn lost(params: &[u8]) -> Result<Self, Error> {
+        let mut r = ReadCursor::new(params);
+        Ok(Self::Lost(r.read()?))
+    }
+}
+
+enum CreateState {
+    Idle,
+    Pending,
+    Done(Result<PeriodicSyncInfo, bt_hci::param::Error>),
+}
+
+struct Inner {
+    create: CreateState,
+    waker: WakerRegistration,
+}
+
+/// Host side state of periodic sync creation.
+///
+/// The controller only allows one sync to be pending at a time, so creations are serialized.
+pub(crate) struct PeriodicSyncState {
+    inner: RefCell<Inner>,
+}
+
+impl PeriodicSyncState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            inner: RefCell::new(Inner {
+                create: CreateState::Idle,
+                waker: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Wait until no other sync is being created and claim the creation slot.
+    async fn begin(&self) {
+        poll_fn(|cx| {
+            let mut inner = self.inner.borrow_mut();
+            inner.waker.register(cx.waker());
+            match inner.create {
+                CreateState::Idle => {
+                    inner.create = CreateState::Pending;
+                    Poll::Ready(())
+                }
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Wait for the result of the pending creation.
+    async fn wait(&self) -> Result<PeriodicSyncInfo, bt_hci::param::Error> {
+        poll_fn(|cx| {
+            let mut inner = self.inner.borrow_mut();
+            inner.waker.register(cx.waker());
+            match core::mem::replace(&mut inner.create, CreateState::Pending) {
+                CreateState::Done(result) => Poll::Ready(result),
+                state => {
+                    inner.create = state;
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Release the creation slot.
+    fn finish(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.create = CreateState::Idle;
+        inner.waker.wake();
+    }
+
+    /// Record the outcome of a sync establishment reported by the controller.
+    pub(crate) fn established(&self, result: Result<PeriodicSyncInfo, bt_hci::param::Error>) {
+        let mut inner = self.inner.borrow_mut();
+        if let CreateState::Pending = inner.create {
+            inner.create = CreateState::Done(result);
+            inner.waker.wake();
+        }
+    }
+}
+
+/// A sync to a periodic advertising train.
+///
+/// Reports, loss and re-establishment of the sync are delivered to the event handler of the host
+/// runner as [`PeriodicSyncEvent`]s carrying [`PeriodicSync::handle`]. Dropping the sync does not
+/// stop it, use [`PeriodicSync::terminate`].
+pub struct PeriodicSync<'d, C, P: PacketPool> {
+    stack: &'d Stack<'d, C, P>,
+    params: PeriodicSyncParams,
+    info: PeriodicSyncInfo,
+}
+
+impl<'d, C: Controller, P: PacketPool> PeriodicSync<'d, C, P> {
+    pub(crate) async fn create(
+        stack: &'d Stack<'d, C, P>,
+        params: &PeriodicSyncParams,
+    ) -> Result<Self, BleHostError<C::Error>>
+    where
+        C: ControllerCmdAsync<LePeriodicAdvCreateSync> + ControllerCmdSync<LePeriodicAdvCreateSyncCancel>,
+    {
+        let info = establish(stack, params).await?;
+        Ok(Self {
+            stack,
+            params: *params,
+            info,
+        })
+    }
+
+    /// The handle of the sync, included in the events of this train.
+    pub fn handle(&self) -> u16 {
+        self.info.handle
+    }
+
+    /// Information about the sync, as of when it was last established.
+    pub fn info(&self) -> &PeriodicSyncInfo {
+        &self.info
+    }
+
+    /// Establish the sync again after [`PeriodicSyncEvent::Lost`] was reported for it.
+    ///
+    /// The sync gets a new handle. Must not be called while the sync is still established.
+    pub async fn resync(&mut self) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdAsync<LePeriodicAdvCreateSync> + ControllerCmdSync<LePeriodicAdvCreateSyncCancel>,
+    {
+        self.info = establish(self.stack, &self.params).await?;
+        Ok(())
+    }
+
+    /// Stop following the train.
+    pub async fn terminate(self) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LePeriodicAdvTerminateSync>,
+    {
+        self.stack
+            .host
+            .command(LePeriodicAdvTerminateSync::new(SyncHandle::new(self.info.handle)))
+            .await
+    }
+}
+
+async fn establish<C, P>(
+    stack: &Stack<'_, C, P>,
+    params: &PeriodicSyncParams,
+) -> Result<PeriodicSyncInfo, BleHostError<C::Error>>
+where
+    C: Controller + ControllerCmdAsync<LePeriodicAdvCreateSync> + ControllerCmdSync<LePeriodicAdvCreateSyncCancel>,
+    P: PacketPool,
+{
+    let host = &stack.host;
+    let state = &host.periodic_sync;
+    state.begin().await;
+    let _release = crate::host::OnDrop::new(|| state.finish());
+
+    host.async_command(LePeriodicAdvCreateSync::new(
+        LePeriodicAdvCreateSyncOptions::new(),
+        params.adv_sid,
+        params.address.kind,
+        params.address.addr,
+        params.skip,
+        bt_hci_duration(params.sync_timeout),
+        CteMask::new(),
+    ))
+    .await?;
+
+    match select(state.wait(), Timer::after(params.establish_timeout)).await {
+        Either::First(result) => Ok(result?),
+        Either::Second(_) => {
+            host.command(LePeriodicAdvCreateSyncCancel::new()).await?;
+            // The controller reports the cancellation, or a sync established in the meantime.
+            match state.wait().await {
+                Ok(info) => Ok(info),
+                Err(_) => Err(Error::Timeout.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_established() {
+        let params = [
+            0x00, // status
+            0x01, 0x00, // sync handle
+            0x02, // adv sid
+            0x01, // random address
+            0x01, 0x02, 0x03, 0x04, 0x05, 0xc6, // address
+            0x02, // 2M phy
+            0x50, 0x00, // interval 100 ms
+            0x05, // clock accuracy
+        ];
+        let info = PeriodicSyncEvent::established(&params).unwrap().unwrap();
+        assert_eq!(info.handle, 1);
+        assert_eq!(info.adv_sid, 2);
+        assert_eq!(info.address.kind, AddrKind::RANDOM);
+        assert_eq!(info.interval, Duration::from_millis(100));
+        assert_eq!(info.clock_accuracy, 5);
+
+        let mut failed = params;
+        failed[0] = 0x44; // operation cancelled by host
+        assert!(PeriodicSyncEvent::established(&failed).unwrap().is_err());
+    }
+
+    #[test]
+    fn parse_report_and_lost() {
+        let params = [
+            0x03, 0x00, // sync handle
+            0x7f, // tx power not available
+            0xd8, // rssi -40
+            0xff, // no cte
+            0x01, // more to come
+            0x02, 0xaa, 0xbb, // data
+        ];
+        let PeriodicSyncEvent::Report(report) = PeriodicSyncEvent::report(&params).unwrap() else {
+            panic!("expected a report");
+        };
+        assert_eq!(report.handle, 3);
+        assert_eq!(report.tx_power, None);
+        assert_eq!(report.rssi, Some(-40));
+        assert_eq!(report.data_status, AdvDataStatus::MoreToCome);
+        assert_eq!(report.data, &[0xaa, 0xbb]);
+
+        assert_eq!(
+            PeriodicSyncEvent::lost(&[0x03, 0x00]).unwrap(),
+            PeriodicSyncEvent::Lost(3)
+        );
+        assert!(PeriodicSyncEvent::report(&params[..7]).is_err());
+    }
+}
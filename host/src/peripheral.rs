@@ -1,17 +1,26 @@
 //! Functionality for the BLE peripheral role.
+use core::future::Future;
 use core::task::Poll;
 
 use bt_hci::cmd::le::{
-    LeClearAdvSets, LeReadNumberOfSupportedAdvSets, LeSetAdvData, LeSetAdvEnable, LeSetAdvParams,
-    LeSetAdvSetRandomAddr, LeSetExtAdvData, LeSetExtAdvEnable, LeSetExtAdvParams, LeSetExtScanResponseData,
-    LeSetScanResponseData,
+    LeAddDeviceToFilterAcceptList, LeClearAdvSets, LeClearFilterAcceptList, LeReadNumberOfSupportedAdvSets,
+    LeSetAdvData, LeSetAdvEnable, LeSetAdvParams, LeSetAdvSetRandomAddr, LeSetExtAdvData, LeSetExtAdvEnable,
+    LeSetExtAdvParams, LeSetExtScanResponseData, LeSetScanResponseData,
 };
+#[cfg(feature = "security")]
+use bt_hci::cmd::le::{LeAddDeviceToResolvingList, LeClearResolvingList, LeSetAddrResolutionEnable};
 use bt_hci::controller::{Controller, ControllerCmdSync};
 use bt_hci::param::{AddrKind, AdvChannelMap, AdvHandle, AdvKind, AdvSet, BdAddr, LeConnRole, Operation};
 use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Timer};
 
-use crate::advertise::{Advertisement, AdvertisementParameters, AdvertisementSet, RawAdvertisement};
+use crate::advertise::{AdvRotationEntry, Advertisement, AdvertisementParameters, AdvertisementSet, RawAdvertisement};
 use crate::connection::Connection;
+#[cfg(feature = "security")]
+use crate::radio_state::SavedResolvingEntry;
+use crate::radio_state::{SavedAdvSet, SavedAdvertising, RESTORABLE_ADV_SETS};
+#[cfg(feature = "security")]
+use crate::security_manager::BondInformation;
 use crate::{bt_hci_duration, bt_hci_ext_duration, Address, BleHostError, Error, PacketPool, Stack};
 
 /// Type which implements the BLE peripheral role.
@@ -24,6 +33,20 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         Self { stack }
     }
 
+    /// Replace the controller filter accept list used by [`AdvertisementParameters::filter_policy`].
+    ///
+    /// The list is shared with scanning and connecting, and the controller does not allow it to be
+    /// changed while advertising with a filter policy that uses it.
+    pub async fn set_filter_accept_list(
+        &mut self,
+        filter_accept_list: &[(AddrKind, &BdAddr)],
+    ) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeClearFilterAcceptList> + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        self.stack.host.set_filter_accept_list(filter_accept_list).await
+    }
+
     /// Start advertising with the provided parameters and return a handle to accept connections.
     pub async fn advertise<'k>(
         &mut self,
@@ -54,6 +77,7 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
 
         let kind = match (data.props.connectable_adv(), data.props.scannable_adv()) {
             (true, true) => AdvKind::AdvInd,
+            (true, false) if data.props.high_duty_cycle_directed_connectable_adv() => AdvKind::AdvDirectIndHigh,
             (true, false) => AdvKind::AdvDirectIndLow,
             (false, true) => AdvKind::AdvScanInd,
             (false, false) => AdvKind::AdvNonconnInd,
@@ -98,6 +122,11 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         trace!("[host] enabling advertising");
         host.advertise_state.start(&advset[..]);
         host.command(LeSetAdvEnable::new(true)).await?;
+        host.activity
+            .borrow_mut()
+            .advertising_started(advset[0].adv_handle, params, &data);
+        host.radio_state.borrow_mut().advertising =
+            SavedAdvSet::new(params, &data, advset[0]).map(|set| SavedAdvertising::Legacy { kind, set });
         drop.defuse();
         Ok(Advertiser {
             stack: self.stack,
@@ -106,6 +135,65 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         })
     }
 
+    /// Start directed advertising to a bonded peer, addressed to its current resolvable private address.
+    ///
+    /// `identity` is the identity address of the peer, which must have been bonded with an identity
+    /// resolving key. The controller resolving list is replaced with that peer and address resolution
+    /// is enabled, so the controller targets the advertisements at the random address the peer is
+    /// currently using. High duty cycle advertising gives the fastest reconnection, but the
+    /// controller stops it after 1.28 s.
+    ///
+    /// Returns [`Error::NotFound`] if no peer is bonded with `identity`. An identity address is either
+    /// public or random static, so a random `identity` must be a random static address.
+    ///
+    /// The resolving list cannot be changed while advertising, scanning or connecting with address
+    /// resolution enabled; stop those first.
+    #[cfg(feature = "security")]
+    pub async fn advertise_to_bonded_peer(
+        &mut self,
+        params: &AdvertisementParameters,
+        identity: Address,
+        high_duty: bool,
+    ) -> Result<Advertiser<'d, C, P>, BleHostError<C::Error>>
+    where
+        C: for<'t> ControllerCmdSync<LeSetAdvData>
+            + ControllerCmdSync<LeSetAdvParams>
+            + for<'t> ControllerCmdSync<LeSetAdvEnable>
+            + for<'t> ControllerCmdSync<LeSetScanResponseData>
+            + ControllerCmdSync<LeClearResolvingList>
+            + ControllerCmdSync<LeAddDeviceToResolvingList>
+            + ControllerCmdSync<LeSetAddrResolutionEnable>,
+    {
+        let host = &self.stack.host;
+        let bond = bonded_identity(host.connections.security_manager.get_bond_information(), &identity)
+            .ok_or(Error::NotFound)?;
+        let irk = bond.identity.irk.ok_or(Error::InvalidValue)?;
+
+        host.command(LeSetAddrResolutionEnable::new(false)).await?;
+        host.command(LeClearResolvingList::new()).await?;
+        host.radio_state.borrow_mut().resolving_list = None;
+        host.command(LeAddDeviceToResolvingList::new(
+            identity.kind,
+            identity.addr,
+            irk.0.to_le_bytes(),
+            [0; 16],
+        ))
+        .await?;
+        host.command(LeSetAddrResolutionEnable::new(true)).await?;
+        host.radio_state.borrow_mut().resolving_list = Some(SavedResolvingEntry {
+            kind: identity.kind,
+            addr: identity.addr,
+            irk: irk.0.to_le_bytes(),
+        });
+
+        let data = if high_duty {
+            Advertisement::ConnectableNonscannableDirectedHighDuty { peer: identity }
+        } else {
+            Advertisement::ConnectableNonscannableDirected { peer: identity }
+        };
+        self.advertise(params, data).await
+    }
+
     /// Update the advertisment adv_data and/or scan_data. Does not change any
     /// other advertising parameters. If no advertising is active, this will not
     /// produce any observable effect. This is typically useful when
@@ -132,9 +220,57 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
             buf[..to_copy].copy_from_slice(&data.scan_data[..to_copy]);
             host.command(LeSetScanResponseData::new(to_copy as u8, buf)).await?;
         }
+        host.radio_state.borrow_mut().update_adv_data(AdvHandle::new(0), &data);
         Ok(())
     }
 
+    /// Broadcast several payloads in turn on the active advertising set.
+    ///
+    /// Each entry replaces both the advertising and the scan response data, an empty one clearing it,
+    /// and is broadcast for its number of advertising events before the data is switched to the next
+    /// one, wrapping around at the end of the list. Advertising must already have been started with
+    /// [`advertise`](Self::advertise) using the same parameters. Only returns if updating the data fails.
+    ///
+    /// Legacy advertising does not report its events, so each entry is kept for
+    /// [`AdvRotationEntry::duration`], an estimate that can be off by a few events when the
+    /// controller advertises faster than `interval_max`. Use
+    /// [`rotate_adv_data_ext`](Self::rotate_adv_data_ext) where the events must be exact.
+    pub async fn rotate_adv_data<'k>(
+        &mut self,
+        params: &AdvertisementParameters,
+        entries: &[AdvRotationEntry<'k>],
+    ) -> Result<(), BleHostError<C::Error>>
+    where
+        C: for<'t> ControllerCmdSync<LeSetAdvData> + for<'t> ControllerCmdSync<LeSetScanResponseData>,
+    {
+        if entries.is_empty() {
+            return Err(Error::InvalidValue.into());
+        }
+        if entries
+            .iter()
+            .any(|entry| !RawAdvertisement::from(entry.data).props.legacy_adv())
+        {
+            return Err(Error::ExtendedAdvertisingNotSupported.into());
+        }
+        let host = &self.stack.host;
+        Err(rotate(entries, |entry| async move {
+            let data: RawAdvertisement = entry.data.into();
+            let mut adv_data = [0; 31];
+            let adv_len = data.adv_data.len().min(adv_data.len());
+            adv_data[..adv_len].copy_from_slice(&data.adv_data[..adv_len]);
+            host.command(LeSetAdvData::new(adv_len as u8, adv_data)).await?;
+            let mut scan_data = [0; 31];
+            let scan_len = data.scan_data.len().min(scan_data.len());
+            scan_data[..scan_len].copy_from_slice(&data.scan_data[..scan_len]);
+            host.command(LeSetScanResponseData::new(scan_len as u8, scan_data))
+                .await?;
+            host.radio_state.borrow_mut().update_adv_data(AdvHandle::new(0), &data);
+            Timer::after(entry.duration(params)).await;
+            Ok::<_, BleHostError<C::Error>>(())
+        })
+        .await)
+    }
+
     /// Starts sending BLE advertisements according to the provided config.
     ///
     /// The handles are required to provide the storage while advertising, and
@@ -158,7 +294,9 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
             + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
             + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>,
     {
-        assert_eq!(sets.len(), handles.len());
+        if sets.len() != handles.len() {
+            return Err(Error::InvalidValue.into());
+        }
         let host = &self.stack.host;
         // Check host supports the required advertisement sets
         {
@@ -235,6 +373,24 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         trace!("[host] enabling extended advertising");
         host.advertise_state.start(handles);
         host.command(LeSetExtAdvEnable::new(true, handles)).await?;
+        {
+            let mut activity = host.activity.borrow_mut();
+            for (set, handle) in sets.iter().zip(handles.iter()) {
+                activity.advertising_started(handle.adv_handle, &set.params, &set.data.into());
+            }
+        }
+        host.radio_state.borrow_mut().advertising = if sets.len() <= RESTORABLE_ADV_SETS {
+            sets.iter()
+                .zip(handles.iter())
+                .map(|(set, handle)| {
+                    let data: RawAdvertisement<'k> = set.data.into();
+                    SavedAdvSet::new(&set.params, &data, *handle)
+                })
+                .collect::<Option<_>>()
+                .map(SavedAdvertising::Extended)
+        } else {
+            None
+        };
         drop.defuse();
         Ok(Advertiser {
             stack: self.stack,
@@ -256,7 +412,9 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
     where
         C: for<'t> ControllerCmdSync<LeSetExtAdvData<'t>> + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>,
     {
-        assert_eq!(sets.len(), handles.len());
+        if sets.len() != handles.len() {
+            return Err(Error::InvalidValue.into());
+        }
         let host = &self.stack.host;
         for (i, set) in sets.iter().enumerate() {
             let handle = handles[i].adv_handle;
@@ -279,10 +437,75 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
                 ))
                 .await?;
             }
+            host.radio_state.borrow_mut().update_adv_data(handle, &data);
         }
         Ok(())
     }
 
+    /// Broadcast several payloads in turn on a single active extended advertising set.
+    ///
+    /// Behaves like [`rotate_adv_data`](Self::rotate_adv_data) for the set identified by `handle`,
+    /// which must have been started with [`advertise_ext`](Self::advertise_ext) using `params`.
+    /// Rather than estimating the time on air, the set is enabled again for every entry with its
+    /// number of events as the limit, and the data is switched once the controller reports the set
+    /// as terminated. The set also terminates when a connection is made on it, in which case the
+    /// rotation moves on to the next entry.
+    pub async fn rotate_adv_data_ext<'k>(
+        &mut self,
+        handle: &AdvSet,
+        params: &AdvertisementParameters,
+        entries: &[AdvRotationEntry<'k>],
+    ) -> Result<(), BleHostError<C::Error>>
+    where
+        C: for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>,
+    {
+        if entries.is_empty() {
+            return Err(Error::InvalidValue.into());
+        }
+        let host = &self.stack.host;
+        let adv_handle = handle.adv_handle;
+        Err(rotate(entries, |entry| async move {
+            let data: RawAdvertisement<'k> = entry.data.into();
+            host.command(LeSetExtAdvData::new(
+                adv_handle,
+                Operation::Complete,
+                params.fragment,
+                data.adv_data,
+            ))
+            .await?;
+            host.command(LeSetExtScanResponseData::new(
+                adv_handle,
+                Operation::Complete,
+                params.fragment,
+                data.scan_data,
+            ))
+            .await?;
+            host.radio_state.borrow_mut().update_adv_data(adv_handle, &data);
+
+            // The controller counts at most 255 events per enable.
+            let mut remaining = entry.events;
+            while remaining > 0 {
+                let events = remaining.min(u8::MAX as u16) as u8;
+                let set = [AdvSet {
+                    adv_handle,
+                    duration: bt_hci_duration(Duration::from_micros(0)),
+                    max_ext_adv_events: events,
+                }];
+                host.advertise_state.restart(adv_handle);
+                host.command(LeSetExtAdvEnable::new(true, &set)).await?;
+                host.activity
+                    .borrow_mut()
+                    .advertising_started(adv_handle, params, &data);
+                host.advertise_state.wait_terminated(adv_handle).await;
+                remaining -= events as u16;
+            }
+            Ok::<_, BleHostError<C::Error>>(())
+        })
+        .await)
+    }
+
     /// Accept any pending available connection.
     ///
     /// Accepts the next pending connection if there are any.
@@ -300,6 +523,33 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
     }
 }
 
+/// Find the bond of the peer with the identity address `identity`.
+///
+/// Bonds record the identity address without its kind, which is checked against the address instead.
+#[cfg(feature = "security")]
+fn bonded_identity(bonds: impl IntoIterator<Item = BondInformation>, identity: &Address) -> Option<BondInformation> {
+    if identity.kind != AddrKind::PUBLIC && !identity.is_random_static() {
+        return None;
+    }
+    bonds.into_iter().find(|bond| bond.identity.bd_addr == identity.addr)
+}
+
+/// Show the entries one after the other, wrapping around at the end, until `show` fails.
+///
+/// `show` puts an entry on air and returns once it has been broadcast for its events.
+async fn rotate<'k, E, F>(entries: &[AdvRotationEntry<'k>], mut show: impl FnMut(AdvRotationEntry<'k>) -> F) -> E
+where
+    F: Future<Output = Result<(), E>>,
+{
+    loop {
+        for entry in entries {
+            if let Err(e) = show(*entry).await {
+                return e;
+            }
+        }
+    }
+}
+
 /// Handle to an active advertiser which can accept connections.
 pub struct Advertiser<'d, C, P: PacketPool> {
     stack: &'d Stack<'d, C, P>,
@@ -331,7 +581,86 @@ impl<C, P: PacketPool> Drop for Advertiser<'_, C, P> {
         if !self.done {
             self.stack.host.advertise_command_state.cancel(self.extended);
         } else {
+            // Advertising already stopped when a connection was made or the sets terminated.
+            self.stack.host.activity.borrow_mut().advertising_stopped(None);
             self.stack.host.advertise_command_state.canceled();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::*;
+
+    #[test]
+    fn rotate_adv_entries() {
+        let entries = [
+            AdvRotationEntry {
+                data: Advertisement::NonconnectableNonscannableUndirected { adv_data: &[1] },
+                events: 3,
+            },
+            AdvRotationEntry {
+                data: Advertisement::NonconnectableScannableUndirected {
+                    adv_data: &[2],
+                    scan_data: &[],
+                },
+                events: 300,
+            },
+        ];
+        let shown = RefCell::new(Vec::new());
+        let error = embassy_futures::block_on(rotate(&entries, |entry| {
+            let data: RawAdvertisement = entry.data.into();
+            shown.borrow_mut().push((data.adv_data[0], entry.events));
+            let result = if shown.borrow().len() < 5 {
+                Ok(())
+            } else {
+                Err("failed")
+            };
+            async move { result }
+        }));
+
+        // The entries wrap around until showing one fails.
+        assert_eq!(error, "failed");
+        assert_eq!(&shown.borrow()[..], &[(1, 3), (2, 300), (1, 3), (2, 300), (1, 3)]);
+    }
+
+    #[cfg(feature = "security")]
+    #[test]
+    fn bonded_identity_kind() {
+        use crate::prelude::{Identity, LongTermKey, SecurityLevel};
+
+        let bond = |addr: [u8; 6]| {
+            BondInformation::new(
+                Identity {
+                    bd_addr: BdAddr::new(addr),
+                    irk: None,
+                },
+                LongTermKey(1),
+                SecurityLevel::Encrypted,
+                true,
+            )
+        };
+        let public = [1, 2, 3, 4, 5, 0x06];
+        let random_static = [1, 2, 3, 4, 5, 0xc6];
+        let bonds = [bond(public), bond(random_static)];
+
+        // Both kinds of identity address are found.
+        let found = bonded_identity(bonds.clone(), &Address::public(public));
+        assert_eq!(unwrap!(found).identity.bd_addr, BdAddr::new(public));
+        let found = bonded_identity(bonds.clone(), &Address::random(random_static));
+        assert_eq!(unwrap!(found).identity.bd_addr, BdAddr::new(random_static));
+
+        // The kind must fit the address: a random identity is a random static address.
+        assert!(bonded_identity(bonds.clone(), &Address::random(public)).is_none());
+        let resolvable = Address {
+            kind: AddrKind::RESOLVABLE_PRIVATE_OR_PUBLIC,
+            addr: BdAddr::new(public),
+        };
+        assert!(bonded_identity(bonds.clone(), &resolvable).is_none());
+
+        // A peer without a bond is not found.
+        assert!(bonded_identity(bonds, &Address::public([9, 2, 3, 4, 5, 0x06])).is_none());
+    }
+}
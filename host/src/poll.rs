@@ -0,0 +1,164 @@
+//! Driving the host without an async executor.
+//!
+//! The [`Runner`](crate::prelude::Runner) and the operations of the stack are futures. On targets where an async
+//! executor is not available, for instance a bare-metal superloop or an RTIC task, a [`Poller`] advances such a
+//! future every time it is polled and returns immediately when it cannot make progress.
+//!
+//! ```rust,ignore
+//! static RUNNER_WOKEN: WakeFlag = WakeFlag::new();
+//!
+//! let mut runner = pin!(Poller::new(runner.run(), &RUNNER_WOKEN));
+//! loop {
+//!     if let Poll::Ready(result) = runner.as_mut().poll() {
+//!         break result;
+//!     }
+//!     // Application work.
+//!     if !runner.is_woken() {
+//!         cortex_m::asm::wfe();
+//!     }
+//! }
+//! ```
+//!
+//! Timers only wake a poller with the generic timer queue of `embassy-time`, as the timer queue integrated in
+//! `embassy-executor` requires wakers of that executor.
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Wake-up flag of a [`Poller`], set when its future was woken and should be polled again.
+///
+/// The flag is static, as the wakers given to the future may be kept by the stack after the poller is gone.
+pub struct WakeFlag(AtomicBool);
+
+impl WakeFlag {
+    /// Create a flag, initially set so that the future is polled at least once.
+    pub const fn new() -> Self {
+        Self(AtomicBool::new(true))
+    }
+
+    /// Returns `true` if the future was woken since it was last polled.
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+impl Default for WakeFlag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, noop);
+
+fn clone(flag: *const ()) -> RawWaker {
+    RawWaker::new(flag, &VTABLE)
+}
+
+fn wake(flag: *const ()) {
+    // Safety: the data pointer always comes from a `&'static WakeFlag`.
+    let flag = unsafe { &*(flag as *const WakeFlag) };
+    flag.0.store(true, Ordering::Release);
+}
+
+fn noop(_: *const ()) {}
+
+/// A future driven by calling [`poll`](Poller::poll) from a loop instead of an async executor.
+pub struct Poller<F: Future> {
+    future: F,
+    flag: &'static WakeFlag,
+}
+
+impl<F: Future> Poller<F> {
+    /// Create a poller for a future, which sets `flag` when the future is woken.
+    ///
+    /// Each poller should have its own flag, so that it is only polled again when its own future was woken.
+    pub fn new(future: F, flag: &'static WakeFlag) -> Self {
+        Self { future, flag }
+    }
+
+    /// Returns `true` if the future was woken since it was last polled.
+    ///
+    /// When it is not set, the application can put the core to sleep until the next interrupt instead of
+    /// polling again.
+    pub fn is_woken(&self) -> bool {
+        self.flag.is_set()
+    }
+
+    /// Advance the future as far as possible without blocking.
+    ///
+    /// Processes the pending controller events, expired timers and ready operations, and returns the output
+    /// once the future has completed. The future must not be polled again after that.
+    pub fn poll(self: Pin<&mut Self>) -> Poll<F::Output> {
+        self.flag.0.store(false, Ordering::Release);
+        // Safety: the vtable functions only access the static flag the data pointer refers to.
+        let waker = unsafe { Waker::from_raw(RawWaker::new(self.flag as *const WakeFlag as *const (), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        // Safety: the future is never moved out of the pinned poller.
+        let future = unsafe { self.map_unchecked_mut(|poller| &mut poller.future) };
+        future.poll(&mut cx)
+    }
+
+    /// Poll the future until it completes, spinning while it waits.
+    pub fn block_on(mut self: Pin<&mut Self>) -> F::Output {
+        loop {
+            if let Poll::Ready(output) = self.as_mut().poll() {
+                return output;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use super::*;
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = u8;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u8> {
+            if self.0 {
+                Poll::Ready(42)
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn poll_until_ready() {
+        static FLAG: WakeFlag = WakeFlag::new();
+        let mut poller = pin!(Poller::new(YieldOnce(false), &FLAG));
+        assert!(poller.is_woken());
+        assert_eq!(poller.as_mut().poll(), Poll::Pending);
+        assert!(poller.is_woken());
+        assert_eq!(poller.as_mut().poll(), Poll::Ready(42));
+    }
+
+    #[test]
+    fn block_on_async_block() {
+        static FLAG: WakeFlag = WakeFlag::new();
+        let poller = pin!(Poller::new(async { YieldOnce(false).await + 1 }, &FLAG));
+        assert_eq!(poller.block_on(), 43);
+    }
+
+    #[test]
+    fn wake_flag_per_poller() {
+        static WOKEN: WakeFlag = WakeFlag::new();
+        static IDLE: WakeFlag = WakeFlag::new();
+        let mut woken = pin!(Poller::new(YieldOnce(false), &WOKEN));
+        let mut idle = pin!(Poller::new(core::future::pending::<()>(), &IDLE));
+        assert_eq!(idle.as_mut().poll(), Poll::Pending);
+        assert!(!idle.is_woken());
+        assert_eq!(woken.as_mut().poll(), Poll::Pending);
+        // Waking one future does not mark the other poller as woken.
+        assert!(woken.is_woken());
+        assert!(!idle.is_woken());
+    }
+}
@@ -0,0 +1,274 @@
+//! Controller configuration remembered by the host.
+//!
+//! A controller reset clears the resolving list, the filter accept list, advertising sets and scanning. The
+//! host keeps a copy of what it programmed, so that it can be re-applied after a reset: the control runner
+//! restores the filter accept list, and the task waiting in
+//! [`Stack::radio_state_restored`](crate::Stack::radio_state_restored) restores the rest.
+use bt_hci::param::{AddrKind, AdvEventProps, AdvHandle, AdvKind, AdvSet, BdAddr};
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use crate::advertise::{AdvertisementParameters, RawAdvertisement};
+use crate::connection::{PhySet, ScanConfig};
+use crate::Address;
+
+/// Maximum number of extended advertising sets restored after a controller reset.
+pub(crate) const RESTORABLE_ADV_SETS: usize = 2;
+
+/// Maximum length of advertising or scan response data restored after a controller reset.
+const RESTORABLE_ADV_DATA_LEN: usize = 251;
+
+/// Configuration re-applied to the controller after a reset.
+///
+/// Each field is `true` if that part of the configuration was programmed again, and `false` if there
+/// was nothing to restore, restoring it failed, or no task was waiting to restore it. Advertising and scanning that could not be restored
+/// have been stopped, which ends the corresponding [`Advertiser`](crate::peripheral::Advertiser) or
+/// scan session like before.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RestoredState {
+    /// Number of controller recoveries so far.
+    pub recoveries: u32,
+    /// The random address was programmed again.
+    pub random_address: bool,
+    /// The resolving list was restored and address resolution enabled again.
+    pub resolving_list: bool,
+    /// The filter accept list was restored.
+    pub filter_accept_list: bool,
+    /// Advertising was enabled again.
+    pub advertising: bool,
+    /// Scanning was enabled again.
+    pub scanning: bool,
+}
+
+/// Advertising and scanning kept active across a controller reset, for the restorer to enable again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct PendingRestore {
+    pub(crate) advertising: bool,
+    pub(crate) scanning: bool,
+}
+
+/// An advertising set as last programmed by the host.
+#[derive(Clone)]
+pub(crate) struct SavedAdvSet {
+    pub(crate) params: AdvertisementParameters,
+    pub(crate) props: AdvEventProps,
+    pub(crate) peer: Address,
+    pub(crate) adv_data: Vec<u8, RESTORABLE_ADV_DATA_LEN>,
+    pub(crate) scan_data: Vec<u8, RESTORABLE_ADV_DATA_LEN>,
+    pub(crate) set: AdvSet,
+}
+
+impl SavedAdvSet {
+    /// Remember an advertising set, or `None` if its data is too long to be kept.
+    pub(crate) fn new(params: &AdvertisementParameters, data: &RawAdvertisement<'_>, set: AdvSet) -> Option<Self> {
+        Some(Self {
+            params: *params,
+            props: data.props,
+            peer: data.peer.unwrap_or(Address {
+                kind: AddrKind::PUBLIC,
+                addr: BdAddr::default(),
+            }),
+            adv_data: Vec::from_slice(data.adv_data).ok()?,
+            scan_data: Vec::from_slice(data.scan_data).ok()?,
+            set,
+        })
+    }
+
+    fn update_data(&mut self, data: &RawAdvertisement<'_>) -> Result<(), ()> {
+        if !data.adv_data.is_empty() {
+            self.adv_data = Vec::from_slice(data.adv_data).map_err(|_| ())?;
+        }
+        if !data.scan_data.is_empty() {
+            self.scan_data = Vec::from_slice(data.scan_data).map_err(|_| ())?;
+        }
+        Ok(())
+    }
+}
+
+/// Advertising as last started by the host.
+pub(crate) enum SavedAdvertising {
+    Legacy { kind: AdvKind, set: SavedAdvSet },
+    Extended(Vec<SavedAdvSet, RESTORABLE_ADV_SETS>),
+}
+
+/// Scanning as last started by the host.
+#[derive(Clone, Copy)]
+pub(crate) struct SavedScan {
+    pub(crate) extended: bool,
+    pub(crate) active: bool,
+    pub(crate) phys: PhySet,
+    pub(crate) interval: Duration,
+    pub(crate) window: Duration,
+    pub(crate) filtered: bool,
+    pub(crate) deadline: Option<Instant>,
+}
+
+impl SavedScan {
+    pub(crate) fn new(config: &ScanConfig<'_>, extended: bool, deadline: Option<Instant>) -> Self {
+        Self {
+            extended,
+            active: config.active,
+            phys: config.phys,
+            interval: config.interval,
+            window: config.window,
+            filtered: !config.filter_accept_list.is_empty(),
+            deadline,
+        }
+    }
+
+    /// Scan duration left at `now`, zero if scanning has no timeout, or `None` if it would have ended.
+    pub(crate) fn remaining(&self, now: Instant) -> Option<Duration> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => None,
+            Some(deadline) => Some(deadline - now),
+            None => Some(Duration::from_secs(0)),
+        }
+    }
+}
+
+/// A resolving list entry programmed by the host.
+#[derive(Clone, Copy)]
+pub(crate) struct SavedResolvingEntry {
+    pub(crate) kind: AddrKind,
+    pub(crate) addr: BdAddr,
+    pub(crate) irk: [u8; 16],
+}
+
+/// Controller configuration remembered by the host to restore it after a controller reset.
+///
+/// The filter accept list is tracked separately by the host, as it is also used for the connection policy.
+#[derive(Default)]
+pub(crate) struct RadioState {
+    pub(crate) resolving_list: Option<SavedResolvingEntry>,
+    pub(crate) advertising: Option<SavedAdvertising>,
+    pub(crate) scan: Option<SavedScan>,
+}
+
+impl RadioState {
+    /// The advertising to enable again, without the sets that terminated before the reset.
+    pub(crate) fn advertising_to_restore(
+        &self,
+        is_advertising: impl Fn(AdvHandle) -> bool,
+    ) -> Option<SavedAdvertising> {
+        match self.advertising.as_ref()? {
+            SavedAdvertising::Legacy { kind, set } if is_advertising(set.set.adv_handle) => {
+                Some(SavedAdvertising::Legacy {
+                    kind: *kind,
+                    set: set.clone(),
+                })
+            }
+            SavedAdvertising::Extended(sets) => {
+                let sets: Vec<SavedAdvSet, RESTORABLE_ADV_SETS> = sets
+                    .iter()
+                    .filter(|set| is_advertising(set.set.adv_handle))
+                    .cloned()
+                    .collect();
+                (!sets.is_empty()).then_some(SavedAdvertising::Extended(sets))
+            }
+            _ => None,
+        }
+    }
+
+    /// Record new data for an advertising set. Empty data leaves the current data unchanged.
+    pub(crate) fn update_adv_data(&mut self, handle: AdvHandle, data: &RawAdvertisement<'_>) {
+        let updated = match &mut self.advertising {
+            Some(SavedAdvertising::Legacy { set, .. }) if handle == set.set.adv_handle => set.update_data(data),
+            Some(SavedAdvertising::Extended(sets)) => match sets.iter_mut().find(|s| s.set.adv_handle == handle) {
+                Some(set) => set.update_data(data),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        };
+        if updated.is_err() {
+            warn!("[host] advertising data too long to be restored after a controller reset");
+            self.advertising = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_set(handle: u8) -> SavedAdvSet {
+        let set = AdvSet {
+            adv_handle: AdvHandle::new(handle),
+            duration: bt_hci::param::Duration::from_secs(0),
+            max_ext_adv_events: 0,
+        };
+        let data = RawAdvertisement {
+            adv_data: &[1, 2, 3],
+            scan_data: &[4],
+            ..Default::default()
+        };
+        SavedAdvSet::new(&AdvertisementParameters::default(), &data, set).unwrap()
+    }
+
+    #[test]
+    fn update_saved_adv_data() {
+        let saved = saved_set(1);
+        let mut state = RadioState {
+            advertising: Some(SavedAdvertising::Extended(Vec::from_slice(&[saved]).unwrap())),
+            ..Default::default()
+        };
+
+        let update = RawAdvertisement {
+            adv_data: &[5, 6],
+            scan_data: &[],
+            ..Default::default()
+        };
+        state.update_adv_data(AdvHandle::new(1), &update);
+        let Some(SavedAdvertising::Extended(sets)) = &state.advertising else {
+            panic!("advertising not saved");
+        };
+        assert_eq!(&sets[0].adv_data[..], &[5, 6]);
+        assert_eq!(&sets[0].scan_data[..], &[4]);
+
+        let too_long = [0; RESTORABLE_ADV_DATA_LEN + 1];
+        let update = RawAdvertisement {
+            adv_data: &too_long,
+            ..Default::default()
+        };
+        state.update_adv_data(AdvHandle::new(1), &update);
+        assert!(state.advertising.is_none());
+    }
+
+    #[test]
+    fn advertising_to_restore() {
+        let mut state = RadioState::default();
+        assert!(state.advertising_to_restore(|_| true).is_none());
+
+        state.advertising = Some(SavedAdvertising::Extended(
+            Vec::from_slice(&[saved_set(1), saved_set(2)]).unwrap(),
+        ));
+        // Sets that terminated before the reset are left out.
+        let Some(SavedAdvertising::Extended(sets)) = state.advertising_to_restore(|h| h == AdvHandle::new(2)) else {
+            panic!("advertising not restored");
+        };
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].set.adv_handle, AdvHandle::new(2));
+        assert!(state.advertising_to_restore(|_| false).is_none());
+
+        state.advertising = Some(SavedAdvertising::Legacy {
+            kind: AdvKind::AdvInd,
+            set: saved_set(0),
+        });
+        assert!(matches!(
+            state.advertising_to_restore(|_| true),
+            Some(SavedAdvertising::Legacy { .. })
+        ));
+        assert!(state.advertising_to_restore(|_| false).is_none());
+    }
+
+    #[test]
+    fn remaining_scan_time() {
+        let now = Instant::from_secs(10);
+        let mut scan = SavedScan::new(&ScanConfig::default(), false, None);
+        assert_eq!(scan.remaining(now), Some(Duration::from_secs(0)));
+        scan.deadline = Some(Instant::from_secs(15));
+        assert_eq!(scan.remaining(now), Some(Duration::from_secs(5)));
+        scan.deadline = Some(now);
+        assert_eq!(scan.remaining(now), None);
+    }
+}
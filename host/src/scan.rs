@@ -1,16 +1,276 @@
 //! Scan config.
+use core::future::Future;
+
 use bt_hci::cmd::le::{
-    LeAddDeviceToFilterAcceptList, LeClearFilterAcceptList, LeSetExtScanEnable, LeSetExtScanParams, LeSetScanEnable,
-    LeSetScanParams,
+    LeAddDeviceToFilterAcceptList, LeClearFilterAcceptList, LePeriodicAdvCreateSync, LePeriodicAdvCreateSyncCancel,
+    LeSetExtScanEnable, LeSetExtScanParams, LeSetScanEnable, LeSetScanParams,
 };
-use bt_hci::controller::{Controller, ControllerCmdSync};
-use bt_hci::param::{AddrKind, FilterDuplicates, ScanningPhy};
+use bt_hci::controller::{Controller, ControllerCmdAsync, ControllerCmdSync};
+use bt_hci::param::{AddrKind, BdAddr, FilterDuplicates, PhyKind, ScanningPhy};
 pub use bt_hci::param::{LeAdvReportsIter, LeExtAdvReportsIter};
-use embassy_time::Instant;
+use bt_hci::FromHciBytes;
+use embassy_time::{Duration, Instant, Timer};
 
 use crate::command::CommandState;
 use crate::connection::ScanConfig;
-use crate::{bt_hci_duration, BleHostError, Central, PacketPool};
+use crate::cursor::ReadCursor;
+use crate::periodic_sync::{PeriodicSync, PeriodicSyncParams};
+use crate::radio_state::SavedScan;
+use crate::{bt_hci_duration, Address, BleHostError, Central, Error, PacketPool};
+
+/// Completeness of the data in a [`ScanReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AdvDataStatus {
+    /// The advertising data is complete.
+    Complete,
+    /// The advertising data is incomplete, more data follows in another report.
+    MoreToCome,
+    /// The advertising data is incomplete and was truncated, no more data follows.
+    Truncated,
+}
+
+/// An advertising report with all the metadata reported by the controller.
+///
+/// Reports from legacy scanning are converted to the same structure, with the fields that only exist in
+/// extended advertising set to their "not available" values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanReport<'a> {
+    /// The advertising is connectable.
+    pub connectable: bool,
+    /// The advertising is scannable.
+    pub scannable: bool,
+    /// The advertising is directed.
+    pub directed: bool,
+    /// This report contains a scan response.
+    pub scan_response: bool,
+    /// The report was generated from legacy advertising PDUs.
+    pub legacy: bool,
+    /// Whether the advertising data is complete.
+    pub data_status: AdvDataStatus,
+    /// Address and address type of the advertiser, `None` for anonymous advertising.
+    pub address: Option<Address>,
+    /// Advertising set identifier, if provided by the advertiser.
+    pub adv_sid: Option<u8>,
+    /// PHY used on the primary advertising channels.
+    pub primary_phy: PhyKind,
+    /// PHY used on the secondary advertising channel, if any.
+    pub secondary_phy: Option<PhyKind>,
+    /// Transmit power reported by the advertiser.
+    pub tx_power: Option<i8>,
+    /// Received signal strength.
+    pub rssi: Option<i8>,
+    /// Interval of the periodic advertising train associated with this advertising, if any.
+    pub periodic_adv_interval: Option<Duration>,
+    /// Target address of directed advertising.
+    pub direct_address: Option<Address>,
+    /// Advertising or scan response data.
+    pub data: &'a [u8],
+}
+
+impl ScanReport<'_> {
+    /// Whether the advertiser did not include its address.
+    pub fn anonymous(&self) -> bool {
+        self.address.is_none()
+    }
+}
+
+/// Iterator over the [`ScanReport`]s in the parameters of an LE Advertising Report or
+/// LE Extended Advertising Report event.
+///
+/// Iteration stops at the first malformed report.
+#[derive(Debug, Clone)]
+pub struct ScanReports<'a> {
+    cursor: ReadCursor<'a>,
+    remaining: u8,
+    extended: bool,
+}
+
+impl<'a> ScanReports<'a> {
+    /// Iterate over the reports of an LE Advertising Report event.
+    pub fn legacy(params: &'a [u8]) -> Self {
+        Self::new(params, false)
+    }
+
+    /// Iterate over the reports of an LE Extended Advertising Report event.
+    pub fn extended(params: &'a [u8]) -> Self {
+        Self::new(params, true)
+    }
+
+    fn new(params: &'a [u8], extended: bool) -> Self {
+        let mut cursor = ReadCursor::new(params);
+        let remaining = cursor.read().unwrap_or(0);
+        Self {
+            cursor,
+            remaining,
+            extended,
+        }
+    }
+
+    fn read_address(&mut self, kind: u8) -> Result<Address, Error> {
+        let kind = AddrKind::from_hci_bytes_complete(&[kind]).map_err(|_| Error::InvalidValue)?;
+        let addr = BdAddr::from_hci_bytes_complete(self.cursor.slice(6)?).map_err(|_| Error::InvalidValue)?;
+        Ok(Address { kind, addr })
+    }
+
+    fn read_phy(&mut self) -> Result<Option<PhyKind>, Error> {
+        match self.cursor.read::<u8>()? {
+            0 => Ok(None),
+            phy => Ok(Some(
+                PhyKind::from_hci_bytes_complete(&[phy]).map_err(|_| Error::InvalidValue)?,
+            )),
+        }
+    }
+
+    fn read_legacy(&mut self) -> Result<ScanReport<'a>, Error> {
+        let kind: u8 = self.cursor.read()?;
+        let addr_kind: u8 = self.cursor.read()?;
+        let address = self.read_address(addr_kind)?;
+        let len: u8 = self.cursor.read()?;
+        let data = self.cursor.slice(len as usize)?;
+        let rssi = self.cursor.read::<u8>()? as i8;
+        // ADV_IND, ADV_DIRECT_IND, ADV_SCAN_IND, ADV_NONCONN_IND, SCAN_RSP
+        let (connectable, scannable, directed, scan_response) = match kind {
+            0x00 => (true, true, false, false),
+            0x01 => (true, false, true, false),
+            0x02 => (false, true, false, false),
+            0x03 => (false, false, false, false),
+            0x04 => (false, true, false, true),
+            _ => return Err(Error::InvalidValue),
+        };
+        Ok(ScanReport {
+            connectable,
+            scannable,
+            directed,
+            scan_response,
+            legacy: true,
+            data_status: AdvDataStatus::Complete,
+            address: Some(address),
+            adv_sid: None,
+            primary_phy: PhyKind::Le1M,
+            secondary_phy: None,
+            tx_power: None,
+            rssi: (rssi != 127).then_some(rssi),
+            periodic_adv_interval: None,
+            direct_address: None,
+            data,
+        })
+    }
+
+    fn read_extended(&mut self) -> Result<ScanReport<'a>, Error> {
+        let kind: u16 = self.cursor.read()?;
+        let addr_kind: u8 = self.cursor.read()?;
+        let address = self.read_address(addr_kind)?;
+        let primary_phy = self.read_phy()?.ok_or(Error::InvalidValue)?;
+        let secondary_phy = self.read_phy()?;
+        let adv_sid: u8 = self.cursor.read()?;
+        let tx_power = self.cursor.read::<u8>()? as i8;
+        let rssi = self.cursor.read::<u8>()? as i8;
+        let interval: u16 = self.cursor.read()?;
+        let direct_addr_kind: u8 = self.cursor.read()?;
+        let direct_address = self.read_address(direct_addr_kind)?;
+        let len: u8 = self.cursor.read()?;
+        let data = self.cursor.slice(len as usize)?;
+
+        let directed = kind & 0x04 != 0;
+        Ok(ScanReport {
+            connectable: kind & 0x01 != 0,
+            scannable: kind & 0x02 != 0,
+            directed,
+            scan_response: kind & 0x08 != 0,
+            legacy: kind & 0x10 != 0,
+            data_status: match (kind >> 5) & 0x03 {
+                0 => AdvDataStatus::Complete,
+                1 => AdvDataStatus::MoreToCome,
+                _ => AdvDataStatus::Truncated,
+            },
+            // 0xff is used for anonymous advertising
+            address: (addr_kind != 0xff).then_some(address),
+            adv_sid: (adv_sid != 0xff).then_some(adv_sid),
+            primary_phy,
+            secondary_phy,
+            tx_power: (tx_power != 127).then_some(tx_power),
+            rssi: (rssi != 127).then_some(rssi),
+            periodic_adv_interval: (interval != 0).then(|| Duration::from_micros(interval as u64 * 1250)),
+            direct_address: directed.then_some(direct_address),
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for ScanReports<'a> {
+    type Item = ScanReport<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let report = if self.extended {
+            self.read_extended()
+        } else {
+            self.read_legacy()
+        };
+        match report {
+            Ok(report) => {
+                self.remaining -= 1;
+                Some(report)
+            }
+            Err(_) => {
+                warn!("[scan] malformed advertising report");
+                self.remaining = 0;
+                None
+            }
+        }
+    }
+}
+
+/// Scan configuration that starts with a fast, high duty cycle phase and then falls back
+/// to a low duty cycle phase to save power.
+///
+/// The defaults follow the GAP recommendations (Core Specification Vol 3, Part C, Appendix A):
+/// a 60 ms interval with a 30 ms window for 30.72 s, then a 1.28 s interval with an 11.25 ms window.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PhasedScanConfig<'d> {
+    /// Scan configuration used for both phases. Its interval and window are used for the slow phase.
+    ///
+    /// The timeout is ignored, a phased scan runs until the session is dropped.
+    pub config: ScanConfig<'d>,
+    /// Scan interval during the fast phase.
+    pub fast_interval: Duration,
+    /// Scan window during the fast phase.
+    pub fast_window: Duration,
+    /// How long the fast phase lasts.
+    pub fast_duration: Duration,
+}
+
+impl Default for PhasedScanConfig<'_> {
+    fn default() -> Self {
+        Self {
+            config: ScanConfig {
+                interval: Duration::from_millis(1280),
+                window: Duration::from_micros(11250),
+                ..Default::default()
+            },
+            fast_interval: Duration::from_millis(60),
+            fast_window: Duration::from_millis(30),
+            fast_duration: Duration::from_millis(30720),
+        }
+    }
+}
+
+impl<'d> PhasedScanConfig<'d> {
+    fn phase(&self, fast: bool) -> ScanConfig<'d> {
+        let mut config = self.config.clone();
+        if fast {
+            config.interval = self.fast_interval;
+            config.window = self.fast_window;
+        }
+        config.timeout = Duration::from_ticks(0);
+        config
+    }
+}
 
 /// A scanner that wraps a central to provide additional functionality
 /// around BLE scanning.
@@ -32,6 +292,21 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
         self.central
     }
 
+    /// Synchronize to a periodic advertising train.
+    ///
+    /// Extended scanning must be running, for instance with [`scan_ext`](Self::scan_ext), for the controller
+    /// to find the train. Several syncs can be created one after the other and are maintained concurrently.
+    /// Returns [`Error::Timeout`] if the train was not found within [`PeriodicSyncParams::establish_timeout`].
+    pub async fn sync_periodic(
+        &mut self,
+        params: &PeriodicSyncParams,
+    ) -> Result<PeriodicSync<'d, C, P>, BleHostError<C::Error>>
+    where
+        C: ControllerCmdAsync<LePeriodicAdvCreateSync> + ControllerCmdSync<LePeriodicAdvCreateSyncCancel>,
+    {
+        PeriodicSync::create(self.central.stack, params).await
+    }
+
     /// Performs an extended BLE scan, return a report for discovering peripherals.
     ///
     /// Scan is stopped when a report is received. Call this method repeatedly to continue scanning.
@@ -42,7 +317,36 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             + ControllerCmdSync<LeClearFilterAcceptList>
             + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
-        let host = &self.central.stack.host;
+        self.start_scan_ext(config).await
+    }
+
+    /// Performs an extended BLE scan which starts with a fast phase and then falls back to a slow phase.
+    ///
+    /// The returned session must be driven with [`PhasedScanSession::run`] for the phase switch to happen.
+    pub async fn scan_ext_phased<'a>(
+        &'a mut self,
+        config: &'a PhasedScanConfig<'a>,
+    ) -> Result<PhasedScanSession<'a, 'd, C, P, true>, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeSetExtScanEnable>
+            + ControllerCmdSync<LeSetExtScanParams>
+            + ControllerCmdSync<LeClearFilterAcceptList>
+            + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let mut session = PhasedScanSession::new(self, config);
+        session.restart_fast().await?;
+        Ok(session)
+    }
+
+    async fn start_scan_ext(&mut self, config: &ScanConfig<'_>) -> Result<ScanSession<'d, true>, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeSetExtScanEnable>
+            + ControllerCmdSync<LeSetExtScanParams>
+            + ControllerCmdSync<LeClearFilterAcceptList>
+            + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let stack = self.central.stack;
+        let host = &stack.host;
         let drop = crate::host::OnDrop::new(|| {
             host.scan_command_state.cancel(true);
         });
@@ -55,7 +359,6 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             scan_window: bt_hci_duration(config.window),
         };
         let phy_params = crate::central::create_phy_params(scanning, config.phys);
-        let host = &self.central.stack.host;
         host.command(LeSetExtScanParams::new(
             host.address.map(|s| s.kind).unwrap_or(AddrKind::PUBLIC),
             if config.filter_accept_list.is_empty() {
@@ -74,14 +377,17 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             bt_hci::param::Duration::from_secs(0),
         ))
         .await?;
+        let deadline = if config.timeout.as_ticks() == 0 {
+            None
+        } else {
+            Some(Instant::now() + config.timeout)
+        };
+        host.radio_state.borrow_mut().scan = Some(SavedScan::new(config, true, deadline));
+        host.activity.borrow_mut().scan_started(config);
         drop.defuse();
         Ok(ScanSession {
-            command_state: &self.central.stack.host.scan_command_state,
-            deadline: if config.timeout.as_ticks() == 0 {
-                None
-            } else {
-                Some(Instant::now() + config.timeout)
-            },
+            command_state: &host.scan_command_state,
+            deadline,
             done: false,
         })
     }
@@ -96,7 +402,36 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             + ControllerCmdSync<LeClearFilterAcceptList>
             + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
-        let host = &self.central.stack.host;
+        self.start_scan(config).await
+    }
+
+    /// Performs a BLE scan which starts with a fast phase and then falls back to a slow phase.
+    ///
+    /// The returned session must be driven with [`PhasedScanSession::run`] for the phase switch to happen.
+    pub async fn scan_phased<'a>(
+        &'a mut self,
+        config: &'a PhasedScanConfig<'a>,
+    ) -> Result<PhasedScanSession<'a, 'd, C, P, false>, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeSetScanParams>
+            + ControllerCmdSync<LeSetScanEnable>
+            + ControllerCmdSync<LeClearFilterAcceptList>
+            + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let mut session = PhasedScanSession::new(self, config);
+        session.restart_fast().await?;
+        Ok(session)
+    }
+
+    async fn start_scan(&mut self, config: &ScanConfig<'_>) -> Result<ScanSession<'d, false>, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeSetScanParams>
+            + ControllerCmdSync<LeSetScanEnable>
+            + ControllerCmdSync<LeClearFilterAcceptList>
+            + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+    {
+        let stack = self.central.stack;
+        let host = &stack.host;
         let drop = crate::host::OnDrop::new(|| {
             host.scan_command_state.cancel(false);
         });
@@ -122,14 +457,17 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
         host.command(params).await?;
 
         host.command(LeSetScanEnable::new(true, true)).await?;
+        let deadline = if config.timeout.as_ticks() == 0 {
+            None
+        } else {
+            Some(Instant::now() + config.timeout.into())
+        };
+        host.radio_state.borrow_mut().scan = Some(SavedScan::new(config, false, deadline));
+        host.activity.borrow_mut().scan_started(config);
         drop.defuse();
         Ok(ScanSession {
-            command_state: &self.central.stack.host.scan_command_state,
-            deadline: if config.timeout.as_ticks() == 0 {
-                None
-            } else {
-                Some(Instant::now() + config.timeout.into())
-            },
+            command_state: &host.scan_command_state,
+            deadline,
             done: false,
         })
     }
@@ -142,8 +480,351 @@ pub struct ScanSession<'d, const EXTENDED: bool> {
     done: bool,
 }
 
+impl<const EXTENDED: bool> ScanSession<'_, EXTENDED> {
+    /// Stop scanning and wait until the controller has stopped.
+    async fn stop(self) {
+        let command_state = self.command_state;
+        drop(self);
+        command_state.wait_idle().await;
+    }
+}
+
 impl<const EXTENDED: bool> Drop for ScanSession<'_, EXTENDED> {
     fn drop(&mut self) {
         self.command_state.cancel(EXTENDED);
     }
 }
+
+/// Starts and stops the scans of the phases of a [`PhasedScanSession`].
+pub(crate) trait PhaseScanner<const EXTENDED: bool> {
+    type Session;
+    type Error;
+
+    /// Start scanning with the configuration of a phase.
+    fn start(&mut self, config: &ScanConfig<'_>) -> impl Future<Output = Result<Self::Session, Self::Error>>;
+    /// Stop scanning and wait until the controller has stopped.
+    fn stop(&mut self, session: Self::Session) -> impl Future<Output = ()>;
+}
+
+impl<'d, C: Controller, P: PacketPool> PhaseScanner<false> for Scanner<'d, C, P>
+where
+    C: ControllerCmdSync<LeSetScanParams>
+        + ControllerCmdSync<LeSetScanEnable>
+        + ControllerCmdSync<LeClearFilterAcceptList>
+        + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+{
+    type Session = ScanSession<'d, false>;
+    type Error = BleHostError<C::Error>;
+
+    async fn start(&mut self, config: &ScanConfig<'_>) -> Result<Self::Session, Self::Error> {
+        self.start_scan(config).await
+    }
+
+    async fn stop(&mut self, session: Self::Session) {
+        session.stop().await
+    }
+}
+
+impl<'d, C: Controller, P: PacketPool> PhaseScanner<true> for Scanner<'d, C, P>
+where
+    C: ControllerCmdSync<LeSetExtScanEnable>
+        + ControllerCmdSync<LeSetExtScanParams>
+        + ControllerCmdSync<LeClearFilterAcceptList>
+        + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+{
+    type Session = ScanSession<'d, true>;
+    type Error = BleHostError<C::Error>;
+
+    async fn start(&mut self, config: &ScanConfig<'_>) -> Result<Self::Session, Self::Error> {
+        self.start_scan_ext(config).await
+    }
+
+    async fn stop(&mut self, session: Self::Session) {
+        session.stop().await
+    }
+}
+
+/// Phase schedule of a phased scan, shared by legacy and extended scanning.
+struct ScanPhases<S> {
+    session: Option<S>,
+    fast_until: Option<Instant>,
+}
+
+impl<S> ScanPhases<S> {
+    const fn new() -> Self {
+        Self {
+            session: None,
+            fast_until: None,
+        }
+    }
+
+    async fn stop<const EXTENDED: bool, T: PhaseScanner<EXTENDED, Session = S>>(&mut self, scanner: &mut T) {
+        if let Some(session) = self.session.take() {
+            scanner.stop(session).await;
+        }
+    }
+
+    async fn restart_fast<const EXTENDED: bool, T: PhaseScanner<EXTENDED, Session = S>>(
+        &mut self,
+        scanner: &mut T,
+        config: &PhasedScanConfig<'_>,
+    ) -> Result<(), T::Error> {
+        self.stop(scanner).await;
+        self.session = Some(scanner.start(&config.phase(true)).await?);
+        self.fast_until = Some(Instant::now() + config.fast_duration);
+        Ok(())
+    }
+
+    async fn run<const EXTENDED: bool, T: PhaseScanner<EXTENDED, Session = S>>(
+        &mut self,
+        scanner: &mut T,
+        config: &PhasedScanConfig<'_>,
+    ) -> Result<(), T::Error> {
+        loop {
+            match (self.fast_until, &self.session) {
+                (Some(until), Some(_)) => {
+                    Timer::at(until).await;
+                    self.fast_until = None;
+                    self.stop(scanner).await;
+                }
+                (None, None) => {
+                    self.session = Some(scanner.start(&config.phase(false)).await?);
+                }
+                (Some(_), None) => self.restart_fast(scanner, config).await?,
+                (None, Some(_)) => core::future::pending().await,
+            }
+        }
+    }
+}
+
+/// Handle to an active scan which switches from a fast to a slow phase.
+///
+/// The fast phase is entered when the session is created and on [`restart_fast`](Self::restart_fast),
+/// for instance after a disconnect. Dropping the session stops scanning.
+pub struct PhasedScanSession<'a, 'd, C: Controller, P: PacketPool, const EXTENDED: bool> {
+    scanner: &'a mut Scanner<'d, C, P>,
+    config: &'a PhasedScanConfig<'a>,
+    phases: ScanPhases<ScanSession<'d, EXTENDED>>,
+}
+
+impl<'a, 'd, C: Controller, P: PacketPool, const EXTENDED: bool> PhasedScanSession<'a, 'd, C, P, EXTENDED> {
+    fn new(scanner: &'a mut Scanner<'d, C, P>, config: &'a PhasedScanConfig<'a>) -> Self {
+        Self {
+            scanner,
+            config,
+            phases: ScanPhases::new(),
+        }
+    }
+
+    /// Returns true while the fast phase is active.
+    pub fn is_fast(&self) -> bool {
+        self.phases.fast_until.is_some()
+    }
+}
+
+impl<'a, 'd, C: Controller, P: PacketPool> PhasedScanSession<'a, 'd, C, P, false>
+where
+    C: ControllerCmdSync<LeSetScanParams>
+        + ControllerCmdSync<LeSetScanEnable>
+        + ControllerCmdSync<LeClearFilterAcceptList>
+        + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+{
+    /// Restart the fast phase.
+    pub async fn restart_fast(&mut self) -> Result<(), BleHostError<C::Error>> {
+        self.phases
+            .restart_fast::<false, _>(&mut *self.scanner, self.config)
+            .await
+    }
+
+    /// Drive the scan schedule, switching to the slow phase when the fast phase is over.
+    ///
+    /// Never returns unless reconfiguring the scan fails. It is safe to cancel.
+    pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>> {
+        self.phases.run::<false, _>(&mut *self.scanner, self.config).await
+    }
+}
+
+impl<'a, 'd, C: Controller, P: PacketPool> PhasedScanSession<'a, 'd, C, P, true>
+where
+    C: ControllerCmdSync<LeSetExtScanEnable>
+        + ControllerCmdSync<LeSetExtScanParams>
+        + ControllerCmdSync<LeClearFilterAcceptList>
+        + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
+{
+    /// Restart the fast phase.
+    pub async fn restart_fast(&mut self) -> Result<(), BleHostError<C::Error>> {
+        self.phases
+            .restart_fast::<true, _>(&mut *self.scanner, self.config)
+            .await
+    }
+
+    /// Drive the scan schedule, switching to the slow phase when the fast phase is over.
+    ///
+    /// Never returns unless reconfiguring the scan fails. It is safe to cancel.
+    pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>> {
+        self.phases.run::<true, _>(&mut *self.scanner, self.config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_scan_report() {
+        let params = [
+            0x01, // num reports
+            0x04, // SCAN_RSP
+            0x01, // random
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, // address
+            0x03, 0x02, 0x01, 0x06, // data
+            0xc4, // rssi -60
+        ];
+        let mut reports = ScanReports::legacy(&params);
+        let report = unwrap!(reports.next());
+        assert!(reports.next().is_none());
+        assert!(report.legacy && report.scan_response && report.scannable);
+        assert!(!report.connectable && !report.anonymous());
+        assert_eq!(
+            unwrap!(report.address).addr,
+            BdAddr::new([0x11, 0x22, 0x33, 0x44, 0x55, 0x66])
+        );
+        assert_eq!(report.data, &[0x02, 0x01, 0x06]);
+        assert_eq!(report.rssi, Some(-60));
+        assert_eq!(report.primary_phy, PhyKind::Le1M);
+        assert_eq!(report.data_status, AdvDataStatus::Complete);
+    }
+
+    #[test]
+    fn extended_scan_report() {
+        let params = [
+            0x02, // num reports
+            0x25, 0x00, // connectable, directed, more to come
+            0x00, // public
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, // address
+            0x03, // coded primary phy
+            0x02, // 2M secondary phy
+            0x05, // sid
+            0xfc, // tx power -4
+            0xb0, // rssi -80
+            0x50, 0x00, // periodic interval 100ms
+            0x01, // direct address random
+            0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, // direct address
+            0x01, 0xaa, // data
+            0x40, 0x00, // truncated, anonymous
+            0xff, // anonymous
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // address
+            0x01, // 1M primary phy
+            0x00, // no secondary phy
+            0xff, // no sid
+            0x7f, // no tx power
+            0x7f, // no rssi
+            0x00, 0x00, // no periodic advertising
+            0x00, // direct address type
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // direct address
+            0x00, // no data
+        ];
+        let mut reports = ScanReports::extended(&params);
+
+        let report = unwrap!(reports.next());
+        assert!(report.connectable && report.directed && !report.legacy);
+        assert_eq!(report.data_status, AdvDataStatus::MoreToCome);
+        assert_eq!(report.primary_phy, PhyKind::LeCoded);
+        assert_eq!(report.secondary_phy, Some(PhyKind::Le2M));
+        assert_eq!(report.adv_sid, Some(5));
+        assert_eq!(report.tx_power, Some(-4));
+        assert_eq!(report.rssi, Some(-80));
+        assert_eq!(report.periodic_adv_interval, Some(Duration::from_millis(100)));
+        assert_eq!(
+            unwrap!(report.direct_address).addr,
+            BdAddr::new([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff])
+        );
+        assert_eq!(report.data, &[0xaa]);
+
+        let report = unwrap!(reports.next());
+        assert!(report.anonymous());
+        assert_eq!(report.data_status, AdvDataStatus::Truncated);
+        assert_eq!(report.secondary_phy, None);
+        assert_eq!(report.adv_sid, None);
+        assert_eq!(report.tx_power, None);
+        assert_eq!(report.rssi, None);
+        assert_eq!(report.periodic_adv_interval, None);
+        assert_eq!(report.direct_address, None);
+        assert!(reports.next().is_none());
+    }
+
+    #[test]
+    fn malformed_scan_report() {
+        let params = [0x02, 0x00, 0x00, 0x11, 0x22];
+        assert_eq!(ScanReports::legacy(&params).count(), 0);
+    }
+
+    #[test]
+    fn phased_scan_config() {
+        let config = PhasedScanConfig::default();
+        let fast = config.phase(true);
+        assert_eq!(fast.interval, Duration::from_millis(60));
+        assert_eq!(fast.window, Duration::from_millis(30));
+        let slow = config.phase(false);
+        assert_eq!(slow.interval, Duration::from_millis(1280));
+        assert_eq!(slow.window, Duration::from_micros(11250));
+        assert_eq!(slow.timeout.as_ticks(), 0);
+    }
+
+    #[derive(Default)]
+    struct PhaseRecorder {
+        /// Interval and start time of every scan started.
+        started: Vec<(Duration, Instant)>,
+        stopped: usize,
+    }
+
+    impl PhaseScanner<false> for PhaseRecorder {
+        type Session = ();
+        type Error = ();
+
+        async fn start(&mut self, config: &ScanConfig<'_>) -> Result<(), ()> {
+            self.started.push((config.interval, Instant::now()));
+            Ok(())
+        }
+
+        async fn stop(&mut self, _session: ()) {
+            self.stopped += 1;
+        }
+    }
+
+    #[test]
+    fn phased_scan_switch() {
+        let config = PhasedScanConfig {
+            fast_duration: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let mut scanner = PhaseRecorder::default();
+        let mut phases = ScanPhases::new();
+        embassy_futures::block_on(async {
+            unwrap!(phases.restart_fast::<false, _>(&mut scanner, &config).await);
+            embassy_futures::select::select(
+                phases.run::<false, _>(&mut scanner, &config),
+                Timer::after(Duration::from_millis(60)),
+            )
+            .await;
+        });
+
+        // The fast scan is stopped once the fast phase is over and replaced with a slow scan.
+        assert_eq!(scanner.stopped, 1);
+        assert_eq!(scanner.started.len(), 2);
+        let (fast_interval, fast_start) = scanner.started[0];
+        let (slow_interval, slow_start) = scanner.started[1];
+        assert_eq!(fast_interval, config.fast_interval);
+        assert_eq!(slow_interval, config.config.interval);
+        assert!(slow_start - fast_start >= config.fast_duration);
+        assert!(phases.fast_until.is_none());
+
+        // Restarting the fast phase replaces the slow scan.
+        embassy_futures::block_on(async {
+            unwrap!(phases.restart_fast::<false, _>(&mut scanner, &config).await);
+        });
+        assert_eq!(scanner.stopped, 2);
+        assert_eq!(scanner.started[2].0, config.fast_interval);
+        assert!(phases.fast_until.is_some());
+    }
+}
@@ -12,6 +12,7 @@ use crate::Address;
 
 /// LE Secure Connections Long Term Key.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[must_use]
 #[repr(transparent)]
 pub struct LongTermKey(pub u128);
@@ -56,6 +57,7 @@ impl defmt::Format for LongTermKey {
 
 /// Identity Resolving Key.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[must_use]
 #[repr(transparent)]
 pub struct IdentityResolvingKey(pub u128);
@@ -12,12 +12,12 @@ use core::ops::DerefMut;
 
 use bt_hci::event::le::{LeEventKind, LeEventPacket, LeLongTermKeyRequest};
 use bt_hci::event::{EncryptionChangeV1, EventKind, EventPacket};
-use bt_hci::param::{ConnHandle, EncryptionEnabledLevel, LeConnRole};
+use bt_hci::param::{BdAddr, ConnHandle, EncryptionEnabledLevel, LeConnRole};
 use bt_hci::FromHciBytes;
 pub use crypto::{IdentityResolvingKey, LongTermKey};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
-use embassy_time::{Instant, TimeoutError, WithTimeout};
+use embassy_time::{Duration, Instant, TimeoutError, WithTimeout};
 use heapless::Vec;
 use rand_chacha::ChaCha12Rng;
 use rand_core::SeedableRng;
@@ -47,6 +47,7 @@ pub(crate) enum SecurityEventData {
 
 /// Bond Information
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BondInformation {
     /// Long Term Key (LTK)
     pub ltk: LongTermKey,
@@ -59,6 +60,12 @@ pub struct BondInformation {
 }
 
 impl BondInformation {
+    /// Current version of the serialized bond information format.
+    pub const SERIALIZED_VERSION: u8 = 1;
+
+    /// Size in bytes of serialized bond information.
+    pub const SERIALIZED_SIZE: usize = 41;
+
     /// Create a BondInformation
     pub fn new(identity: Identity, ltk: LongTermKey, security_level: SecurityLevel, is_bonded: bool) -> Self {
         Self {
@@ -68,6 +75,79 @@ impl BondInformation {
             security_level,
         }
     }
+
+    /// Serialize the bond information into a fixed size, versioned byte layout.
+    ///
+    /// The layout is stable across releases and suited for persisting bonds to flash or transferring
+    /// them during provisioning. Being a fixed size array, it is also encoded as-is by postcard.
+    ///
+    /// | Offset | Size | Field                                          |
+    /// |--------|------|------------------------------------------------|
+    /// | 0      | 1    | Format version ([`Self::SERIALIZED_VERSION`])  |
+    /// | 1      | 16   | LTK, little endian                             |
+    /// | 17     | 6    | Peer identity address, little endian           |
+    /// | 23     | 1    | Flags: bit 0 = IRK present, bit 1 = bonded     |
+    /// | 24     | 16   | IRK, little endian (zero if not present)       |
+    /// | 40     | 1    | Security level                                 |
+    pub fn to_bytes(&self) -> [u8; Self::SERIALIZED_SIZE] {
+        let mut out = [0; Self::SERIALIZED_SIZE];
+        out[0] = Self::SERIALIZED_VERSION;
+        out[1..17].copy_from_slice(&self.ltk.to_le_bytes());
+        out[17..23].copy_from_slice(&self.identity.bd_addr.into_inner());
+        let mut flags = 0;
+        if let Some(irk) = self.identity.irk {
+            flags |= 0x01;
+            out[24..40].copy_from_slice(&irk.to_le_bytes());
+        }
+        if self.is_bonded {
+            flags |= 0x02;
+        }
+        out[23] = flags;
+        out[40] = match self.security_level {
+            SecurityLevel::NoEncryption => 0,
+            SecurityLevel::Encrypted => 1,
+            SecurityLevel::EncryptedAuthenticated => 2,
+        };
+        out
+    }
+
+    /// Deserialize bond information produced by [`Self::to_bytes`].
+    ///
+    /// Returns [`Error::InvalidValue`] if the data is not exactly [`Self::SERIALIZED_SIZE`] bytes, has an
+    /// unknown version or contains invalid field values, such as an IRK when the flags say there is none.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        if data.len() != Self::SERIALIZED_SIZE || data[0] != Self::SERIALIZED_VERSION {
+            return Err(Error::InvalidValue);
+        }
+        let flags = data[23];
+        if flags & !0x03 != 0 {
+            return Err(Error::InvalidValue);
+        }
+        if flags & 0x01 == 0 && data[24..40].iter().any(|b| *b != 0) {
+            return Err(Error::InvalidValue);
+        }
+        let security_level = match data[40] {
+            0 => SecurityLevel::NoEncryption,
+            1 => SecurityLevel::Encrypted,
+            2 => SecurityLevel::EncryptedAuthenticated,
+            _ => return Err(Error::InvalidValue),
+        };
+        let mut ltk = [0; 16];
+        ltk.copy_from_slice(&data[1..17]);
+        let mut bd_addr = [0; 6];
+        bd_addr.copy_from_slice(&data[17..23]);
+        let mut irk = [0; 16];
+        irk.copy_from_slice(&data[24..40]);
+        Ok(Self {
+            ltk: LongTermKey::from_le_bytes(ltk),
+            identity: Identity {
+                bd_addr: BdAddr::new(bd_addr),
+                irk: (flags & 0x01 != 0).then_some(IdentityResolvingKey::from_le_bytes(irk)),
+            },
+            is_bonded: flags & 0x02 != 0,
+            security_level,
+        })
+    }
 }
 
 impl core::fmt::Display for BondInformation {
@@ -83,14 +163,64 @@ impl defmt::Format for BondInformation {
     }
 }
 
+/// Protection against repeated pairing attempts.
+///
+/// After a pairing with a peer fails, further pairing attempts by that peer are rejected with
+/// [`Reason::RepeatedAttempts`] until a waiting interval has passed. The interval starts at `initial_delay`
+/// and doubles with each further failure, up to `max_delay`, as recommended by the Core Specification
+/// (Vol 3, Part H, Section 2.3.6). A successful pairing clears the failures of the peer.
+///
+/// Peers are recognized by their identity. A peer using resolvable private addresses is only recognized
+/// across address changes once its identity resolving key is known, that is if it is bonded.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairingBackoff {
+    /// Waiting interval after the first failure.
+    pub initial_delay: Duration,
+    /// Longest waiting interval.
+    pub max_delay: Duration,
+}
+
+impl Default for PairingBackoff {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(64),
+        }
+    }
+}
+
+impl PairingBackoff {
+    /// Waiting interval after the given number of failed attempts.
+    fn delay(&self, failures: u8) -> Duration {
+        let shift = u32::from(failures.saturating_sub(1)).min(31);
+        let ticks = self.initial_delay.as_ticks().saturating_mul(1 << shift);
+        Duration::from_ticks(ticks).min(self.max_delay)
+    }
+}
+
+/// Failed pairing attempts of a peer.
+#[derive(Clone, Copy)]
+struct PairingAttempts {
+    identity: Identity,
+    failures: u8,
+    retry_after: Instant,
+}
+
 /// Security manager data
 struct SecurityManagerData<const BOND_COUNT: usize> {
     /// Local device address
     local_address: Option<Address>,
     /// Current bonds with other devices
     bond: Vec<BondInformation, BOND_COUNT>,
+    /// Keys of pairings awaiting approval by the application, kept apart from the bonds
+    pending_bonds: Vec<BondInformation, BOND_COUNT>,
     /// Random generator seeded
     random_generator_seeded: bool,
+    /// Protection against repeated pairing attempts, if enabled
+    backoff: Option<PairingBackoff>,
+    /// Peers with recently failed pairing attempts
+    attempts: Vec<PairingAttempts, BOND_COUNT>,
 }
 
 impl<const BOND_COUNT: usize> SecurityManagerData<BOND_COUNT> {
@@ -99,7 +229,10 @@ impl<const BOND_COUNT: usize> SecurityManagerData<BOND_COUNT> {
         Self {
             local_address: None,
             bond: Vec::new(),
+            pending_bonds: Vec::new(),
             random_generator_seeded: false,
+            backoff: None,
+            attempts: Vec::new(),
         }
     }
 }
@@ -207,27 +340,21 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         self.state.borrow_mut().local_address = Some(address);
     }
 
+    /// Find the keys for a peer, preferring the keys of a pairing awaiting approval on the current link
     fn get_peer_bond_information(&self, identity: &Identity) -> Option<BondInformation> {
         trace!("[security manager] Find long term key for {:?}", identity);
-        self.state.borrow().bond.iter().find_map(|bond| {
-            if bond.identity.match_identity(identity) {
-                Some(bond.clone())
-            } else {
-                None
-            }
-        })
+        let state = self.state.borrow();
+        state
+            .pending_bonds
+            .iter()
+            .chain(state.bond.iter())
+            .find(|bond| bond.identity.match_identity(identity))
+            .cloned()
     }
 
     /// Get the long term key for peer
     pub(crate) fn get_peer_long_term_key(&self, identity: &Identity) -> Option<LongTermKey> {
-        trace!("[security manager] Find long term key for {:?}", identity);
-        self.state.borrow().bond.iter().find_map(|bond| {
-            if bond.identity.match_identity(identity) {
-                Some(bond.ltk)
-            } else {
-                None
-            }
-        })
+        self.get_peer_bond_information(identity).map(|bond| bond.ltk)
     }
 
     /// Has the random generator been seeded?
@@ -277,11 +404,181 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         }
     }
 
+    /// Keep the keys of a pairing only for the current connection until the bond is approved.
+    ///
+    /// The keys are held apart from the bonds, so an existing bond with the peer stays intact
+    /// if the new bond is never approved.
+    fn add_pending_bond(&self, bond_information: BondInformation) -> Result<(), Error> {
+        trace!(
+            "[security manager] Add pending bond for {:?}",
+            bond_information.identity
+        );
+        let mut state = self.state.borrow_mut();
+        let bond_information = BondInformation {
+            is_bonded: false,
+            ..bond_information
+        };
+        match state
+            .pending_bonds
+            .iter_mut()
+            .find(|bond| bond.identity.match_identity(&bond_information.identity))
+        {
+            Some(pending) => *pending = bond_information,
+            None => state
+                .pending_bonds
+                .push(bond_information)
+                .map_err(|_| Error::OutOfMemory)?,
+        }
+        Ok(())
+    }
+
+    /// Approve the pending bond of a peer, replacing any existing bond with it
+    pub(crate) fn approve_bond(&self, identity: &Identity) -> Result<BondInformation, Error> {
+        let pending = {
+            let mut state = self.state.borrow_mut();
+            let index = state
+                .pending_bonds
+                .iter()
+                .position(|bond| bond.identity.match_identity(identity))
+                .ok_or(Error::NotFound)?;
+            state.pending_bonds.swap_remove(index)
+        };
+        trace!("[security manager] Approve bond for {:?}", pending.identity);
+        let bond = BondInformation {
+            is_bonded: true,
+            ..pending.clone()
+        };
+        if let Err(e) = self.add_bond_information(bond.clone()) {
+            // Keep the keys pending so that the approval can be retried
+            let _ = self.state.borrow_mut().pending_bonds.push(pending);
+            return Err(e);
+        }
+        Ok(bond)
+    }
+
+    /// Whether an approved bond is stored for the peer
+    pub(crate) fn is_bonded(&self, identity: &Identity) -> bool {
+        self.bonded_identity(identity).is_some()
+    }
+
+    /// Identity of the approved bond stored for the peer.
+    ///
+    /// A peer using a resolvable private address is found with the IRK of its bond.
+    pub(crate) fn bonded_identity(&self, identity: &Identity) -> Option<Identity> {
+        self.state
+            .borrow()
+            .bond
+            .iter()
+            .find(|bond| bond.is_bonded && bond.identity.match_identity(identity))
+            .map(|bond| bond.identity)
+    }
+
     /// Get bonded devices
     pub(crate) fn get_bond_information(&self) -> Vec<BondInformation, BOND_COUNT> {
         Vec::from_slice(self.state.borrow().bond.as_slice()).unwrap()
     }
 
+    /// Set the protection against repeated pairing attempts, or disable it with `None`.
+    pub(crate) fn set_pairing_backoff(&self, backoff: Option<PairingBackoff>) {
+        let mut state = self.state.borrow_mut();
+        state.backoff = backoff;
+        if backoff.is_none() {
+            state.attempts.clear();
+        }
+    }
+
+    /// Record a failed pairing with a peer, starting or extending its waiting interval.
+    fn pairing_failed(&self, identity: Identity) {
+        let mut state = self.state.borrow_mut();
+        let Some(backoff) = state.backoff else {
+            return;
+        };
+        let index = match state.attempts.iter().position(|a| a.identity.match_identity(&identity)) {
+            Some(index) => index,
+            None => {
+                if state.attempts.is_full() {
+                    // Forget the peer whose waiting interval ends first
+                    if let Some((index, _)) = state.attempts.iter().enumerate().min_by_key(|(_, a)| a.retry_after) {
+                        state.attempts.swap_remove(index);
+                    }
+                }
+                let attempts = PairingAttempts {
+                    identity,
+                    failures: 0,
+                    retry_after: Instant::now(),
+                };
+                if state.attempts.push(attempts).is_err() {
+                    return;
+                }
+                state.attempts.len() - 1
+            }
+        };
+        let attempts = &mut state.attempts[index];
+        if identity.irk.is_some() {
+            // Keep the resolving key, to recognize the peer when it changes its address
+            attempts.identity = identity;
+        }
+        attempts.failures = attempts.failures.saturating_add(1);
+        attempts.retry_after = Instant::now() + backoff.delay(attempts.failures);
+        info!(
+            "[security manager] Pairing attempt {} failed, waiting {} ms before accepting another",
+            attempts.failures,
+            backoff.delay(attempts.failures).as_millis()
+        );
+    }
+
+    /// Clear the failed pairing attempts of a peer.
+    fn pairing_succeeded(&self, identity: &Identity) {
+        self.state
+            .borrow_mut()
+            .attempts
+            .retain(|a| !a.identity.match_identity(identity));
+    }
+
+    /// Number of failed attempts and time left in the waiting interval, if pairing with a peer is blocked.
+    fn pairing_blocked(&self, identity: &Identity) -> Option<(u8, Duration)> {
+        let state = self.state.borrow();
+        state.backoff?;
+        let now = Instant::now();
+        state
+            .attempts
+            .iter()
+            .find(|a| a.identity.match_identity(identity) && a.retry_after > now)
+            .map(|a| (a.failures, a.retry_after - now))
+    }
+
+    /// Reject a pairing started by the peer if it is in its waiting interval after a failed pairing.
+    fn reject_repeated_attempt<P: PacketPool>(
+        &self,
+        pdu: &Pdu<P::Packet>,
+        storage: &ConnectionStorage<P::Packet>,
+    ) -> Result<(), Error> {
+        let role = storage.role.ok_or(Error::InvalidValue)?;
+        let command = pdu.as_ref().first().map(|command| Command::try_from(*command));
+        let starts_pairing = match role {
+            LeConnRole::Peripheral => matches!(command, Some(Ok(Command::PairingRequest))),
+            LeConnRole::Central => matches!(command, Some(Ok(Command::SecurityRequest))),
+        };
+        if !starts_pairing || self.pairing_sm.borrow().is_some() {
+            return Ok(());
+        }
+        let peer_identity = storage.peer_identity.ok_or(Error::InvalidValue)?;
+        match self.pairing_blocked(&peer_identity) {
+            Some((failed_attempts, retry_after)) => {
+                warn!(
+                    "[security manager] Rejecting repeated pairing attempt, retry in {} ms",
+                    retry_after.as_millis()
+                );
+                let _ = storage.events.try_send(ConnectionEvent::PairingRejected {
+                    failed_attempts,
+                    retry_after,
+                });
+                Err(Error::Security(Reason::RepeatedAttempts))
+            }
+            None => Ok(()),
+        }
+    }
+
     fn handle_peripheral<P: PacketPool>(
         &self,
         pdu: Pdu<P::Packet>,
@@ -439,7 +736,9 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     ) -> Result<(), Error> {
         let role = storage.role.ok_or(Error::InvalidValue)?;
 
-        let result = if role == LeConnRole::Peripheral {
+        let result = if let Err(e) = self.reject_repeated_attempt::<P>(&pdu, storage) {
+            Err(e)
+        } else if role == LeConnRole::Peripheral {
             self.handle_peripheral(pdu, connections, storage)
         } else {
             self.handle_central(pdu, connections, storage)
@@ -552,10 +851,11 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     pub(crate) fn disconnect(&self, handle: ConnHandle, identity: Option<Identity>) -> Result<(), Error> {
         self.pairing_sm.replace(None);
         if let Some(identity) = identity {
-            self.state
-                .borrow_mut()
+            let mut state = self.state.borrow_mut();
+            state
                 .bond
-                .retain(|x| x.is_bonded || x.identity != identity);
+                .retain(|x| x.is_bonded || !x.identity.match_identity(&identity));
+            state.pending_bonds.retain(|x| !x.identity.match_identity(&identity));
         }
 
         Ok(())
@@ -698,7 +998,8 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         command: Command,
         connections: &ConnectionManager<P>,
     ) -> Result<TxPacket<P>, Error> {
-        let packet = P::allocate().ok_or(Error::OutOfMemory)?;
+        let packet = P::allocate_sized(TxPacket::<P>::HEADER_SIZE + usize::from(command.payload_size()))
+            .ok_or(Error::OutOfMemory)?;
         TxPacket::new(packet, command)
     }
 
@@ -764,7 +1065,11 @@ impl<'sm, 'cm, 'cm2, 'cs, const B: usize, P: PacketPool> PairingOps<P> for Pairi
             is_bonded,
             security_level,
         };
-        self.security_manager.add_bond_information(bond_info.clone())?;
+        if is_bonded && self.storage.bond_approval {
+            self.security_manager.add_pending_bond(bond_info.clone())?;
+        } else {
+            self.security_manager.add_bond_information(bond_info.clone())?;
+        }
         self.security_manager
             .try_send_event(SecurityEventData::EnableEncryption(self.conn_handle, bond_info.clone()))?;
         Ok(bond_info)
@@ -800,9 +1105,25 @@ impl<'sm, 'cm, 'cm2, 'cs, const B: usize, P: PacketPool> PairingOps<P> for Pairi
     }
 
     fn try_send_connection_event(&mut self, event: ConnectionEvent) -> Result<(), Error> {
+        let event = match event {
+            ConnectionEvent::PairingComplete {
+                security_level,
+                bond: Some(bond),
+            } if self.storage.bond_approval => ConnectionEvent::BondingRequested { security_level, bond },
+            event => event,
+        };
+        match event {
+            ConnectionEvent::PairingFailed(_) => self.security_manager.pairing_failed(self.peer_identity),
+            ConnectionEvent::PairingComplete { .. } | ConnectionEvent::BondingRequested { .. } => {
+                self.security_manager.pairing_succeeded(&self.peer_identity)
+            }
+            _ => {}
+        }
         let timer_changed = matches!(
             event,
-            ConnectionEvent::PairingComplete { .. } | ConnectionEvent::PairingFailed(_)
+            ConnectionEvent::PairingComplete { .. }
+                | ConnectionEvent::PairingFailed(_)
+                | ConnectionEvent::BondingRequested { .. }
         );
         self.storage.events.try_send(event).map_err(|_| Error::OutOfMemory)?;
         if timer_changed {
@@ -811,3 +1132,164 @@ impl<'sm, 'cm, 'cm2, 'cs, const B: usize, P: PacketPool> PairingOps<P> for Pairi
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bond_information_roundtrip() {
+        let bond = BondInformation::new(
+            Identity {
+                bd_addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+                irk: Some(IdentityResolvingKey::new(0x1234_5678_9abc_def0_0fed_cba9_8765_4321)),
+            },
+            LongTermKey::new(0xdead_beef_0000_1111_2222_3333_4444_5555),
+            SecurityLevel::EncryptedAuthenticated,
+            true,
+        );
+        let bytes = bond.to_bytes();
+        assert_eq!(bytes[0], BondInformation::SERIALIZED_VERSION);
+        assert_eq!(unwrap!(BondInformation::from_bytes(&bytes)), bond);
+
+        let mut unknown_version = bytes;
+        unknown_version[0] = 0xff;
+        assert!(BondInformation::from_bytes(&unknown_version).is_err());
+        assert!(BondInformation::from_bytes(&bytes[..10]).is_err());
+
+        let mut trailing = [0; BondInformation::SERIALIZED_SIZE + 1];
+        trailing[..BondInformation::SERIALIZED_SIZE].copy_from_slice(&bytes);
+        assert_eq!(BondInformation::from_bytes(&trailing), Err(Error::InvalidValue));
+
+        // The IRK must be zero when the flags say there is none.
+        let mut stray_irk = bytes;
+        stray_irk[23] &= !0x01;
+        assert_eq!(BondInformation::from_bytes(&stray_irk), Err(Error::InvalidValue));
+        let without_irk = BondInformation {
+            identity: Identity {
+                irk: None,
+                ..bond.identity
+            },
+            ..bond
+        };
+        let bytes = without_irk.to_bytes();
+        assert_eq!(&bytes[24..40], &[0; 16]);
+        assert_eq!(unwrap!(BondInformation::from_bytes(&bytes)), without_irk);
+    }
+
+    #[test]
+    fn pairing_backoff_delay() {
+        let backoff = PairingBackoff {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(2), Duration::from_secs(2));
+        assert_eq!(backoff.delay(4), Duration::from_secs(8));
+        assert_eq!(backoff.delay(5), Duration::from_secs(10));
+        assert_eq!(backoff.delay(u8::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn pairing_backoff_by_identity() {
+        let sm: SecurityManager<2> = SecurityManager::new();
+        let irk = IdentityResolvingKey::new(0x1234_5678_9abc_def0_0fed_cba9_8765_4321);
+        let identity = Identity {
+            bd_addr: BdAddr::new([1, 2, 3, 4, 5, 0xc6]),
+            irk: Some(irk),
+        };
+        let rpa = Identity {
+            bd_addr: BdAddr::new(irk.generate_resolvable_address(sm.rng.borrow_mut().deref_mut())),
+            irk: None,
+        };
+
+        // Disabled by default.
+        sm.pairing_failed(identity);
+        assert_eq!(sm.pairing_blocked(&identity), None);
+
+        sm.set_pairing_backoff(Some(PairingBackoff::default()));
+        sm.pairing_failed(identity);
+        assert!(matches!(sm.pairing_blocked(&identity), Some((1, _))));
+        // The peer is recognized when it uses a resolvable private address.
+        sm.pairing_failed(rpa);
+        assert!(matches!(sm.pairing_blocked(&rpa), Some((2, _))));
+        assert!(matches!(sm.pairing_blocked(&identity), Some((2, _))));
+
+        let other = Identity {
+            bd_addr: BdAddr::new([6, 5, 4, 3, 2, 0xc1]),
+            irk: None,
+        };
+        assert_eq!(sm.pairing_blocked(&other), None);
+
+        sm.pairing_succeeded(&rpa);
+        assert_eq!(sm.pairing_blocked(&identity), None);
+    }
+
+    fn bond(ltk: u128) -> BondInformation {
+        BondInformation::new(
+            Identity {
+                bd_addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+                irk: None,
+            },
+            LongTermKey::new(ltk),
+            SecurityLevel::Encrypted,
+            true,
+        )
+    }
+
+    #[test]
+    fn pending_bond_approved() {
+        let sm: SecurityManager<2> = SecurityManager::new();
+        let identity = bond(1).identity;
+        unwrap!(sm.add_pending_bond(bond(1)));
+        assert!(!sm.is_bonded(&identity));
+        assert!(sm.get_bond_information().is_empty());
+        // The keys are in use for the current link.
+        assert_eq!(sm.get_peer_long_term_key(&identity), Some(LongTermKey::new(1)));
+
+        assert_eq!(unwrap!(sm.approve_bond(&identity)), bond(1));
+        assert!(sm.is_bonded(&identity));
+        assert_eq!(sm.get_bond_information().as_slice(), &[bond(1)]);
+        assert!(matches!(sm.approve_bond(&identity), Err(Error::NotFound)));
+
+        // The bond survives the disconnection.
+        unwrap!(sm.disconnect(ConnHandle::new(0), Some(identity)));
+        assert!(sm.is_bonded(&identity));
+    }
+
+    #[test]
+    fn pending_bond_rejected() {
+        let sm: SecurityManager<2> = SecurityManager::new();
+        let identity = bond(1).identity;
+        unwrap!(sm.add_pending_bond(bond(1)));
+        unwrap!(sm.disconnect(ConnHandle::new(0), Some(identity)));
+        assert!(!sm.is_bonded(&identity));
+        assert!(sm.get_bond_information().is_empty());
+        assert_eq!(sm.get_peer_long_term_key(&identity), None);
+        assert!(matches!(sm.approve_bond(&identity), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn pending_bond_over_existing_bond() {
+        let sm: SecurityManager<2> = SecurityManager::new();
+        let identity = bond(1).identity;
+        unwrap!(sm.add_bond_information(bond(1)));
+
+        // Re-pairing keeps the existing bond until the new one is approved.
+        unwrap!(sm.add_pending_bond(bond(2)));
+        assert!(sm.is_bonded(&identity));
+        assert_eq!(sm.get_bond_information().as_slice(), &[bond(1)]);
+        assert_eq!(sm.get_peer_long_term_key(&identity), Some(LongTermKey::new(2)));
+
+        // Rejecting the new bond restores the old keys.
+        unwrap!(sm.disconnect(ConnHandle::new(0), Some(identity)));
+        assert_eq!(sm.get_bond_information().as_slice(), &[bond(1)]);
+        assert_eq!(sm.get_peer_long_term_key(&identity), Some(LongTermKey::new(1)));
+
+        // Approving the new bond replaces the old one.
+        unwrap!(sm.add_pending_bond(bond(2)));
+        unwrap!(sm.approve_bond(&identity));
+        assert_eq!(sm.get_bond_information().as_slice(), &[bond(2)]);
+        assert_eq!(sm.get_peer_long_term_key(&identity), Some(LongTermKey::new(2)));
+    }
+}
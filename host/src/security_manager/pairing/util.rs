@@ -87,7 +87,8 @@ pub fn choose_pairing_method(central: PairingFeatures, peripheral: PairingFeatur
 }
 
 pub fn prepare_packet<P: PacketPool>(command: Command) -> Result<TxPacket<P>, Error> {
-    let packet = P::allocate().ok_or(Error::OutOfMemory)?;
+    let packet = P::allocate_sized(TxPacket::<P>::HEADER_SIZE + usize::from(command.payload_size()))
+        .ok_or(Error::OutOfMemory)?;
     TxPacket::new(packet, command)
 }
 
@@ -698,6 +698,7 @@ impl defmt::Format for PairingFeatures {
 
 /// Security Mode 1 Levels
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SecurityMode1Level {
     /// No security (No authentication and no encryption)
     Level1,
@@ -711,6 +712,7 @@ pub enum SecurityMode1Level {
 
 /// Security Mode 2 Levels
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SecurityMode2Level {
     /// Unauthenticated pairing with data signing
     Level1,
@@ -720,6 +722,7 @@ pub enum SecurityMode2Level {
 
 /// Security Mode 3 Levels
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SecurityMode3Level {
     /// No security (no authentication and no encryption)
     Level1,
@@ -732,6 +735,7 @@ pub enum SecurityMode3Level {
 /// Security Levels
 //
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SecurityLevel {
     /// LE security mode 1
     Mode1(SecurityMode1Level),
@@ -0,0 +1,49 @@
+//! Helpers for serializing foreign types used in public structs.
+//!
+//! Types from `bt-hci` and `embassy-time` do not implement serde traits, so fields of those
+//! types are (de)serialized through these modules with `#[serde(with = "...")]`.
+
+/// Serialize a [`bt_hci::param::BdAddr`] as its 6 raw little-endian bytes.
+pub(crate) mod bd_addr {
+    use bt_hci::param::BdAddr;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(addr: &BdAddr, serializer: S) -> Result<S::Ok, S::Error> {
+        addr.into_inner().serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BdAddr, D::Error> {
+        <[u8; 6]>::deserialize(deserializer).map(BdAddr::new)
+    }
+}
+
+/// Serialize a [`bt_hci::param::AddrKind`] as its raw byte value.
+pub(crate) mod addr_kind {
+    use bt_hci::param::AddrKind;
+    use bt_hci::FromHciBytes;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(kind: &AddrKind, serializer: S) -> Result<S::Ok, S::Error> {
+        kind.into_inner().serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AddrKind, D::Error> {
+        let raw = u8::deserialize(deserializer)?;
+        AddrKind::from_hci_bytes_complete(&[raw]).map_err(|_| D::Error::custom("invalid address kind"))
+    }
+}
+
+/// Serialize an [`embassy_time::Duration`] as a number of microseconds.
+pub(crate) mod duration {
+    use embassy_time::Duration;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        duration.as_micros().serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_micros)
+    }
+}
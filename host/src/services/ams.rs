@@ -0,0 +1,327 @@
+//! ## Apple Media Service client
+//!
+//! The Apple Media Service (AMS) is exposed by iOS devices and lets an accessory show the
+//! now-playing media and control playback. The accessory registers for updates of entity
+//! attributes (player, queue and track information), sends remote commands such as play or
+//! next track, and reads attribute values that were truncated in an update.
+
+use bt_hci::controller::Controller;
+use heapless::Vec;
+
+use crate::attribute::Characteristic;
+use crate::gatt::{GattClient, NotificationListener};
+use crate::types::uuid::Uuid;
+use crate::{BleHostError, Error, PacketPool};
+
+/// UUID of the Apple Media Service.
+pub const AMS_SERVICE: Uuid = Uuid::parse("89d3502b-0f36-433a-8ef4-c502ad55f8dc");
+/// UUID of the Remote Command characteristic.
+pub const REMOTE_COMMAND: Uuid = Uuid::parse("9b3c81d8-57b1-4a8a-b8df-0e56f7ca51c2");
+/// UUID of the Entity Update characteristic.
+pub const ENTITY_UPDATE: Uuid = Uuid::parse("2f7cabce-808d-411f-9a0c-bb92ba96c102");
+/// UUID of the Entity Attribute characteristic.
+pub const ENTITY_ATTRIBUTE: Uuid = Uuid::parse("c6b2f38c-23ab-46d8-a6ab-a3a870bbd5d7");
+
+type RawValue = Vec<u8, 512>;
+
+/// Commands that can be sent to the media player.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum RemoteCommand {
+    Play = 0,
+    Pause = 1,
+    TogglePlayPause = 2,
+    NextTrack = 3,
+    PreviousTrack = 4,
+    VolumeUp = 5,
+    VolumeDown = 6,
+    AdvanceRepeatMode = 7,
+    AdvanceShuffleMode = 8,
+    SkipForward = 9,
+    SkipBackward = 10,
+    LikeTrack = 11,
+    DislikeTrack = 12,
+    BookmarkTrack = 13,
+}
+
+/// The set of remote commands currently supported by the media player, as notified on the
+/// Remote Command characteristic.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AvailableCommands(u16);
+
+impl AvailableCommands {
+    /// Parse a Remote Command notification.
+    pub fn parse(data: &[u8]) -> Self {
+        let mut mask = 0;
+        for id in data {
+            if *id < 16 {
+                mask |= 1 << id;
+            }
+        }
+        Self(mask)
+    }
+
+    /// Whether the command is currently available.
+    pub fn contains(&self, command: RemoteCommand) -> bool {
+        self.0 & (1 << command as u8) != 0
+    }
+}
+
+/// Entities exposed by the Apple Media Service.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntityId {
+    /// The currently active media player.
+    Player = 0,
+    /// The play queue of the active media player.
+    Queue = 1,
+    /// The currently loaded track.
+    Track = 2,
+}
+
+/// Attributes of the [`EntityId::Player`] entity.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PlayerAttribute {
+    /// Localized name of the application.
+    Name = 0,
+    /// Playback state, playback rate and elapsed time, separated by commas.
+    PlaybackInfo = 1,
+    /// Volume, from 0 to 1.
+    Volume = 2,
+}
+
+/// Attributes of the [`EntityId::Queue`] entity.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QueueAttribute {
+    /// Index of the current track in the queue.
+    Index = 0,
+    /// Number of tracks in the queue.
+    Count = 1,
+    /// Shuffle mode.
+    ShuffleMode = 2,
+    /// Repeat mode.
+    RepeatMode = 3,
+}
+
+/// Attributes of the [`EntityId::Track`] entity.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TrackAttribute {
+    /// Name of the artist.
+    Artist = 0,
+    /// Name of the album.
+    Album = 1,
+    /// Title of the track.
+    Title = 2,
+    /// Duration of the track in seconds.
+    Duration = 3,
+}
+
+/// An attribute of an AMS entity.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityAttribute {
+    /// Attribute of the player entity.
+    Player(PlayerAttribute),
+    /// Attribute of the queue entity.
+    Queue(QueueAttribute),
+    /// Attribute of the track entity.
+    Track(TrackAttribute),
+}
+
+impl EntityAttribute {
+    /// The entity this attribute belongs to.
+    pub fn entity(&self) -> EntityId {
+        match self {
+            Self::Player(_) => EntityId::Player,
+            Self::Queue(_) => EntityId::Queue,
+            Self::Track(_) => EntityId::Track,
+        }
+    }
+
+    /// The attribute ID within the entity.
+    pub fn attribute_id(&self) -> u8 {
+        match self {
+            Self::Player(a) => *a as u8,
+            Self::Queue(a) => *a as u8,
+            Self::Track(a) => *a as u8,
+        }
+    }
+
+    /// Create an attribute from its entity and attribute IDs.
+    pub fn from_ids(entity: u8, attribute: u8) -> Option<Self> {
+        Some(match (entity, attribute) {
+            (0, 0) => Self::Player(PlayerAttribute::Name),
+            (0, 1) => Self::Player(PlayerAttribute::PlaybackInfo),
+            (0, 2) => Self::Player(PlayerAttribute::Volume),
+            (1, 0) => Self::Queue(QueueAttribute::Index),
+            (1, 1) => Self::Queue(QueueAttribute::Count),
+            (1, 2) => Self::Queue(QueueAttribute::ShuffleMode),
+            (1, 3) => Self::Queue(QueueAttribute::RepeatMode),
+            (2, 0) => Self::Track(TrackAttribute::Artist),
+            (2, 1) => Self::Track(TrackAttribute::Album),
+            (2, 2) => Self::Track(TrackAttribute::Title),
+            (2, 3) => Self::Track(TrackAttribute::Duration),
+            _ => return None,
+        })
+    }
+}
+
+/// An Entity Update notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityUpdate<'a> {
+    /// The attribute that changed.
+    pub attribute: EntityAttribute,
+    /// The value was truncated to fit the notification, use [`AmsClient::read_entity_attribute`]
+    /// to read the complete value.
+    pub truncated: bool,
+    /// The new value, as a UTF-8 string.
+    pub value: &'a [u8],
+}
+
+impl<'a> EntityUpdate<'a> {
+    const TRUNCATED: u8 = 0x01;
+
+    /// Parse an Entity Update notification.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        match data {
+            [entity, attribute, flags, value @ ..] => Ok(Self {
+                attribute: EntityAttribute::from_ids(*entity, *attribute).ok_or(Error::InvalidValue)?,
+                truncated: flags & Self::TRUNCATED != 0,
+                value,
+            }),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// A client for the Apple Media Service of a connected iOS device.
+pub struct AmsClient<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    remote_command: Characteristic<RawValue>,
+    entity_update: Characteristic<RawValue>,
+    entity_attribute: Characteristic<RawValue>,
+}
+
+impl<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize>
+    AmsClient<'a, 'reference, C, P, MAX_SERVICES>
+{
+    /// Discover the Apple Media Service and its characteristics.
+    ///
+    /// Returns [`Error::NotFound`] if the peer does not expose the service.
+    pub async fn new(client: &'a GattClient<'reference, C, P, MAX_SERVICES>) -> Result<Self, BleHostError<C::Error>> {
+        let services = client.services_by_uuid(&AMS_SERVICE).await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        Ok(Self {
+            client,
+            remote_command: client.characteristic_by_uuid(service, &REMOTE_COMMAND).await?,
+            entity_update: client.characteristic_by_uuid(service, &ENTITY_UPDATE).await?,
+            entity_attribute: client.characteristic_by_uuid(service, &ENTITY_ATTRIBUTE).await?,
+        })
+    }
+
+    /// Subscribe to the list of available remote commands.
+    ///
+    /// Parse the notifications with [`AvailableCommands::parse`].
+    pub async fn subscribe_remote_commands(&self) -> Result<NotificationListener<'a, 512>, BleHostError<C::Error>> {
+        self.client.subscribe(&self.remote_command, false).await
+    }
+
+    /// Send a remote command to the media player.
+    pub async fn send_command(&self, command: RemoteCommand) -> Result<(), BleHostError<C::Error>> {
+        self.client
+            .write_characteristic(&self.remote_command, &[command as u8])
+            .await
+    }
+
+    /// Register for updates of the given attributes, which must all belong to the same entity.
+    ///
+    /// Registering again for an entity replaces the previous registration. Parse the notifications
+    /// with [`EntityUpdate::parse`].
+    pub async fn subscribe_entity_updates(
+        &self,
+        attributes: &[EntityAttribute],
+    ) -> Result<NotificationListener<'a, 512>, BleHostError<C::Error>> {
+        let entity = attributes.first().ok_or(Error::InvalidValue)?.entity();
+        let mut request: Vec<u8, 8> = Vec::new();
+        request.push(entity as u8).map_err(|_| Error::InsufficientSpace)?;
+        for attribute in attributes {
+            if attribute.entity() != entity {
+                return Err(Error::InvalidValue.into());
+            }
+            request
+                .push(attribute.attribute_id())
+                .map_err(|_| Error::InsufficientSpace)?;
+        }
+
+        let listener = self.client.subscribe(&self.entity_update, false).await?;
+        self.client.write_characteristic(&self.entity_update, &request).await?;
+        Ok(listener)
+    }
+
+    /// Read the complete value of an attribute, e.g. after a truncated [`EntityUpdate`].
+    ///
+    /// The attribute must be registered for updates. Returns the number of bytes copied into `dest`.
+    pub async fn read_entity_attribute(
+        &self,
+        attribute: EntityAttribute,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        self.client
+            .write_characteristic(
+                &self.entity_attribute,
+                &[attribute.entity() as u8, attribute.attribute_id()],
+            )
+            .await?;
+        self.client.read_characteristic_long(&self.entity_attribute, dest).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_uuids() {
+        assert_eq!(
+            AMS_SERVICE.as_raw(),
+            &[0xdc, 0xf8, 0x55, 0xad, 0x02, 0xc5, 0xf4, 0x8e, 0x3a, 0x43, 0x36, 0x0f, 0x2b, 0x50, 0xd3, 0x89]
+        );
+    }
+
+    #[test]
+    fn entity_update() {
+        let update = unwrap!(EntityUpdate::parse(&[0x02, 0x02, 0x01, b'S', b'o', b'n', b'g']));
+        assert_eq!(update.attribute, EntityAttribute::Track(TrackAttribute::Title));
+        assert!(update.truncated);
+        assert_eq!(update.value, b"Song");
+
+        let update = unwrap!(EntityUpdate::parse(&[0x00, 0x01, 0x00]));
+        assert_eq!(update.attribute, EntityAttribute::Player(PlayerAttribute::PlaybackInfo));
+        assert!(!update.truncated);
+        assert!(update.value.is_empty());
+
+        assert!(EntityUpdate::parse(&[0x00, 0x01]).is_err());
+        assert!(EntityUpdate::parse(&[0x03, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn available_commands() {
+        let commands = AvailableCommands::parse(&[0x00, 0x01, 0x03, 0x0d]);
+        assert!(commands.contains(RemoteCommand::Play));
+        assert!(commands.contains(RemoteCommand::Pause));
+        assert!(commands.contains(RemoteCommand::NextTrack));
+        assert!(commands.contains(RemoteCommand::BookmarkTrack));
+        assert!(!commands.contains(RemoteCommand::VolumeUp));
+    }
+}
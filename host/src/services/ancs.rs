@@ -0,0 +1,422 @@
+//! ## Apple Notification Center Service client
+//!
+//! The Apple Notification Center Service (ANCS) is exposed by iOS devices and lets an accessory
+//! show the notifications of the device. The accessory is told about added, modified and removed
+//! notifications on the Notification Source characteristic, requests their details through the
+//! Control Point, receives them on the Data Source characteristic, and can perform the positive or
+//! negative action of a notification.
+
+use bt_hci::controller::Controller;
+use heapless::Vec;
+
+use crate::attribute::Characteristic;
+use crate::gatt::{GattClient, NotificationListener};
+use crate::types::uuid::Uuid;
+use crate::{BleHostError, Error, PacketPool};
+
+/// UUID of the Apple Notification Center Service.
+pub const ANCS_SERVICE: Uuid = Uuid::parse("7905f431-b5ce-4e99-a40f-4b1e122d00d0");
+/// UUID of the Notification Source characteristic.
+pub const NOTIFICATION_SOURCE: Uuid = Uuid::parse("9fbf120d-6301-42d9-8c58-25e699a21dbd");
+/// UUID of the Control Point characteristic.
+pub const CONTROL_POINT: Uuid = Uuid::parse("69d1d8f3-45e1-49a8-9821-9bbdfdaad9d9");
+/// UUID of the Data Source characteristic.
+pub const DATA_SOURCE: Uuid = Uuid::parse("22eac6e9-24d6-4bb5-be44-b36ace7c7bfb");
+
+type RawValue = Vec<u8, 512>;
+
+const GET_NOTIFICATION_ATTRIBUTES: u8 = 0;
+const GET_APP_ATTRIBUTES: u8 = 1;
+const PERFORM_NOTIFICATION_ACTION: u8 = 2;
+
+/// What happened to a notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum EventId {
+    Added = 0,
+    Modified = 1,
+    Removed = 2,
+}
+
+/// Category of a notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum CategoryId {
+    Other = 0,
+    IncomingCall = 1,
+    MissedCall = 2,
+    Voicemail = 3,
+    Social = 4,
+    Schedule = 5,
+    Email = 6,
+    News = 7,
+    HealthAndFitness = 8,
+    BusinessAndFinance = 9,
+    Location = 10,
+    Entertainment = 11,
+}
+
+impl CategoryId {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::IncomingCall,
+            2 => Self::MissedCall,
+            3 => Self::Voicemail,
+            4 => Self::Social,
+            5 => Self::Schedule,
+            6 => Self::Email,
+            7 => Self::News,
+            8 => Self::HealthAndFitness,
+            9 => Self::BusinessAndFinance,
+            10 => Self::Location,
+            11 => Self::Entertainment,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Flags of a notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EventFlags(u8);
+
+impl EventFlags {
+    /// The notification is delivered without sound or vibration.
+    pub const SILENT: u8 = 0x01;
+    /// The notification is important.
+    pub const IMPORTANT: u8 = 0x02;
+    /// The notification existed before the accessory subscribed.
+    pub const PRE_EXISTING: u8 = 0x04;
+    /// The notification has a positive action.
+    pub const POSITIVE_ACTION: u8 = 0x08;
+    /// The notification has a negative action.
+    pub const NEGATIVE_ACTION: u8 = 0x10;
+
+    /// Whether all the given flags are set.
+    pub fn contains(&self, flags: u8) -> bool {
+        self.0 & flags == flags
+    }
+}
+
+/// A Notification Source notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationEvent {
+    /// What happened to the notification.
+    pub event: EventId,
+    /// Flags of the notification.
+    pub flags: EventFlags,
+    /// Category of the notification.
+    pub category: CategoryId,
+    /// Number of active notifications in the category.
+    pub category_count: u8,
+    /// Identifier of the notification, used to request its attributes or perform an action.
+    pub uid: u32,
+}
+
+impl NotificationEvent {
+    /// Parse a Notification Source notification.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        match data {
+            [event, flags, category, category_count, u0, u1, u2, u3, ..] => Ok(Self {
+                event: match event {
+                    0 => EventId::Added,
+                    1 => EventId::Modified,
+                    2 => EventId::Removed,
+                    _ => return Err(Error::InvalidValue),
+                },
+                flags: EventFlags(*flags),
+                category: CategoryId::from_u8(*category),
+                category_count: *category_count,
+                uid: u32::from_le_bytes([*u0, *u1, *u2, *u3]),
+            }),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+}
+
+/// Attributes of a notification that can be requested.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum NotificationAttribute {
+    AppIdentifier = 0,
+    Title = 1,
+    Subtitle = 2,
+    Message = 3,
+    MessageSize = 4,
+    Date = 5,
+    PositiveActionLabel = 6,
+    NegativeActionLabel = 7,
+}
+
+impl NotificationAttribute {
+    /// Whether the attribute is requested with a maximum length.
+    fn has_max_len(&self) -> bool {
+        matches!(self, Self::Title | Self::Subtitle | Self::Message)
+    }
+}
+
+/// Attributes of an app that can be requested.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum AppAttribute {
+    DisplayName = 0,
+}
+
+/// Action to perform on a notification.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum Action {
+    Positive = 0,
+    Negative = 1,
+}
+
+/// What a Data Source response describes.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataSubject<'a> {
+    /// The notification with the given identifier.
+    Notification(u32),
+    /// The app with the given identifier.
+    App(&'a [u8]),
+}
+
+/// A response to a Control Point command, received on the Data Source characteristic.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataResponse<'a> {
+    /// The notification or app the attributes belong to.
+    pub subject: DataSubject<'a>,
+    attributes: &'a [u8],
+}
+
+impl<'a> DataResponse<'a> {
+    /// Parse a response, as reassembled by [`AncsClient::get_notification_attributes`] or
+    /// [`AncsClient::get_app_attributes`].
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        match data {
+            [GET_NOTIFICATION_ATTRIBUTES, u0, u1, u2, u3, attributes @ ..] => Ok(Self {
+                subject: DataSubject::Notification(u32::from_le_bytes([*u0, *u1, *u2, *u3])),
+                attributes,
+            }),
+            [GET_APP_ATTRIBUTES, rest @ ..] => {
+                let end = rest.iter().position(|b| *b == 0).ok_or(Error::InvalidValue)?;
+                Ok(Self {
+                    subject: DataSubject::App(&rest[..end]),
+                    attributes: &rest[end + 1..],
+                })
+            }
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    /// The attributes in the response, as pairs of attribute ID and value.
+    ///
+    /// Iteration stops at an attribute that is not complete.
+    pub fn attributes(&self) -> impl Iterator<Item = (u8, &'a [u8])> + 'a {
+        let mut data = self.attributes;
+        core::iter::from_fn(move || match data {
+            [id, l0, l1, rest @ ..] => {
+                let len = u16::from_le_bytes([*l0, *l1]) as usize;
+                let value = rest.get(..len)?;
+                data = &rest[len..];
+                Some((*id, value))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// A client for the Apple Notification Center Service of a connected iOS device.
+pub struct AncsClient<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    notification_source: Characteristic<RawValue>,
+    control_point: Characteristic<RawValue>,
+    data_source: Characteristic<RawValue>,
+}
+
+impl<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize>
+    AncsClient<'a, 'reference, C, P, MAX_SERVICES>
+{
+    /// Discover the Apple Notification Center Service and its characteristics.
+    ///
+    /// Returns [`Error::NotFound`] if the peer does not expose the service.
+    pub async fn new(client: &'a GattClient<'reference, C, P, MAX_SERVICES>) -> Result<Self, BleHostError<C::Error>> {
+        let services = client.services_by_uuid(&ANCS_SERVICE).await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        Ok(Self {
+            client,
+            notification_source: client.characteristic_by_uuid(service, &NOTIFICATION_SOURCE).await?,
+            control_point: client.characteristic_by_uuid(service, &CONTROL_POINT).await?,
+            data_source: client.characteristic_by_uuid(service, &DATA_SOURCE).await?,
+        })
+    }
+
+    /// Subscribe to the Notification Source.
+    ///
+    /// Parse the notifications with [`NotificationEvent::parse`]. The device only sends them once the
+    /// connection is encrypted.
+    pub async fn subscribe_notifications(&self) -> Result<NotificationListener<'a, 512>, BleHostError<C::Error>> {
+        self.client.subscribe(&self.notification_source, false).await
+    }
+
+    /// Subscribe to the Data Source, which carries the responses to attribute requests.
+    pub async fn subscribe_data(&self) -> Result<NotificationListener<'a, 512>, BleHostError<C::Error>> {
+        self.client.subscribe(&self.data_source, false).await
+    }
+
+    /// Request attributes of a notification and wait for the response on the Data Source.
+    ///
+    /// `max_len` limits the length of the title, subtitle and message. The response, which may span
+    /// several Data Source notifications, is reassembled into `dest`; parse it with
+    /// [`DataResponse::parse`]. Returns the length of the response.
+    pub async fn get_notification_attributes(
+        &self,
+        data_source: &mut NotificationListener<'_, 512>,
+        uid: u32,
+        attributes: &[NotificationAttribute],
+        max_len: u16,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let mut request: Vec<u8, 32> = Vec::new();
+        request
+            .push(GET_NOTIFICATION_ATTRIBUTES)
+            .map_err(|_| Error::InsufficientSpace)?;
+        request
+            .extend_from_slice(&uid.to_le_bytes())
+            .map_err(|_| Error::InsufficientSpace)?;
+        for attribute in attributes {
+            request.push(*attribute as u8).map_err(|_| Error::InsufficientSpace)?;
+            if attribute.has_max_len() {
+                request
+                    .extend_from_slice(&max_len.to_le_bytes())
+                    .map_err(|_| Error::InsufficientSpace)?;
+            }
+        }
+
+        self.client.write_characteristic(&self.control_point, &request).await?;
+        Self::receive_response(data_source, attributes.len(), dest).await
+    }
+
+    /// Request attributes of the app with the given identifier, as found in the
+    /// [`NotificationAttribute::AppIdentifier`] attribute of a notification.
+    ///
+    /// The response is reassembled into `dest`; parse it with [`DataResponse::parse`]. Returns the
+    /// length of the response.
+    pub async fn get_app_attributes(
+        &self,
+        data_source: &mut NotificationListener<'_, 512>,
+        app_identifier: &[u8],
+        attributes: &[AppAttribute],
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let mut request: Vec<u8, 128> = Vec::new();
+        request.push(GET_APP_ATTRIBUTES).map_err(|_| Error::InsufficientSpace)?;
+        request
+            .extend_from_slice(app_identifier)
+            .map_err(|_| Error::InsufficientSpace)?;
+        request.push(0).map_err(|_| Error::InsufficientSpace)?;
+        for attribute in attributes {
+            request.push(*attribute as u8).map_err(|_| Error::InsufficientSpace)?;
+        }
+
+        self.client.write_characteristic(&self.control_point, &request).await?;
+        Self::receive_response(data_source, attributes.len(), dest).await
+    }
+
+    /// Perform the positive or negative action of a notification, e.g. answer or decline a call.
+    pub async fn perform_action(&self, uid: u32, action: Action) -> Result<(), BleHostError<C::Error>> {
+        let [u0, u1, u2, u3] = uid.to_le_bytes();
+        let request = [PERFORM_NOTIFICATION_ACTION, u0, u1, u2, u3, action as u8];
+        self.client.write_characteristic(&self.control_point, &request).await
+    }
+
+    async fn receive_response(
+        data_source: &mut NotificationListener<'_, 512>,
+        count: usize,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let mut len = 0;
+        loop {
+            let notification = data_source.next_checked().await?;
+            let data = notification.as_ref();
+            let end = len + data.len();
+            if end > dest.len() {
+                return Err(Error::InsufficientSpace.into());
+            }
+            dest[len..end].copy_from_slice(data);
+            len = end;
+            if let Ok(response) = DataResponse::parse(&dest[..len]) {
+                if response.attributes().count() >= count {
+                    return Ok(len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_uuids() {
+        assert_eq!(
+            ANCS_SERVICE.as_raw(),
+            &[0xd0, 0x00, 0x2d, 0x12, 0x1e, 0x4b, 0x0f, 0xa4, 0x99, 0x4e, 0xce, 0xb5, 0x31, 0xf4, 0x05, 0x79]
+        );
+    }
+
+    #[test]
+    fn notification_event() {
+        let data = [0x00, 0x0a, 0x01, 0x02, 0x78, 0x56, 0x34, 0x12];
+        let event = unwrap!(NotificationEvent::parse(&data));
+        assert_eq!(event.event, EventId::Added);
+        let flags = EventFlags::IMPORTANT | EventFlags::POSITIVE_ACTION;
+        assert!(event.flags.contains(flags));
+        assert!(!event.flags.contains(EventFlags::SILENT));
+        assert_eq!(event.category, CategoryId::IncomingCall);
+        assert_eq!(event.category_count, 2);
+        assert_eq!(event.uid, 0x1234_5678);
+
+        assert!(NotificationEvent::parse(&[0x03, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(NotificationEvent::parse(&[0x00, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn notification_attributes() {
+        let data = [
+            0x00, 0x78, 0x56, 0x34, 0x12, // command and notification
+            0x01, 0x02, 0x00, b'H', b'i', // title
+            0x03, 0x00, 0x00, // empty message
+            0x05, 0x04, 0x00, b'2', b'0', // truncated date
+        ];
+        let response = unwrap!(DataResponse::parse(&data));
+        assert_eq!(response.subject, DataSubject::Notification(0x1234_5678));
+        let mut attributes = response.attributes();
+        assert_eq!(attributes.next(), Some((1, &b"Hi"[..])));
+        assert_eq!(attributes.next(), Some((3, &b""[..])));
+        assert_eq!(attributes.next(), None);
+    }
+
+    #[test]
+    fn app_attributes() {
+        let data = [0x01, b'a', b'p', b'p', 0x00, 0x00, 0x03, 0x00, b'A', b'p', b'p'];
+        let response = unwrap!(DataResponse::parse(&data));
+        assert_eq!(response.subject, DataSubject::App(b"app"));
+        assert_eq!(response.attributes().next(), Some((0, &b"App"[..])));
+
+        assert!(DataResponse::parse(&[0x01, b'a', b'p', b'p']).is_err());
+        assert!(DataResponse::parse(&[0x02, 0x00]).is_err());
+    }
+}
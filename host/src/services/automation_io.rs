@@ -0,0 +1,323 @@
+//! ## Automation IO Service
+//!
+//! The Automation IO Service (AIOS) exposes the digital and analog inputs and outputs of a device,
+//! for example GPIO pins or sensor readings. Each Digital characteristic holds an array of 2-bit
+//! digital states, each Analog characteristic a single 16-bit value, and the optional Aggregate
+//! characteristic all digital values followed by all analog values so a client can read them at once.
+//!
+//! Every characteristic gets a Characteristic Presentation Format descriptor whose description
+//! field tells instances apart. Digital characteristics also get a Number of Digitals descriptor,
+//! and analog characteristics may get a Valid Range descriptor. Value and time triggers are not
+//! supported.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+
+pub use crate::attribute::PresentationFormat;
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service, ServiceBuilder};
+use crate::types::uuid::Uuid;
+use crate::Error;
+
+/// UUID of the Automation IO Service.
+pub const AUTOMATION_IO_SERVICE: Uuid = Uuid::new_short(0x1815);
+/// UUID of the Digital characteristic.
+pub const DIGITAL: Uuid = Uuid::new_short(0x2a56);
+/// UUID of the Analog characteristic.
+pub const ANALOG: Uuid = Uuid::new_short(0x2a58);
+/// UUID of the Aggregate characteristic.
+pub const AGGREGATE: Uuid = Uuid::new_short(0x2a5a);
+/// UUID of the Characteristic Presentation Format descriptor.
+pub const CHARACTERISTIC_PRESENTATION_FORMAT: Uuid = Uuid::new_short(0x2904);
+/// UUID of the Valid Range descriptor.
+pub const VALID_RANGE: Uuid = Uuid::new_short(0x2906);
+/// UUID of the Number of Digitals descriptor.
+pub const NUMBER_OF_DIGITALS: Uuid = Uuid::new_short(0x2909);
+
+/// State of a single digital signal.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DigitalState {
+    /// The signal is inactive.
+    Inactive = 0,
+    /// The signal is active.
+    Active = 1,
+    /// The signal is tri-stated.
+    Tristate = 2,
+    /// The state of the output is unknown.
+    Unknown = 3,
+}
+
+impl DigitalState {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => Self::Inactive,
+            1 => Self::Active,
+            2 => Self::Tristate,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Read the state of the digital signal at `index` in a Digital characteristic value.
+pub fn digital_state(value: &[u8], index: usize) -> Option<DigitalState> {
+    let byte = value.get(index / 4)?;
+    Some(DigitalState::from_bits(byte >> ((index % 4) * 2)))
+}
+
+/// Set the state of the digital signal at `index` in a Digital characteristic value.
+///
+/// Returns [`Error::InsufficientSpace`] if the value is too short to hold the signal.
+pub fn set_digital_state(value: &mut [u8], index: usize, state: DigitalState) -> Result<(), Error> {
+    let byte = value.get_mut(index / 4).ok_or(Error::InsufficientSpace)?;
+    let shift = (index % 4) * 2;
+    *byte = (*byte & !(0x03 << shift)) | ((state as u8) << shift);
+    Ok(())
+}
+
+/// Encode the value of the Aggregate characteristic from the values of all Digital characteristics
+/// and all Analog characteristics, in the order they were added to the service.
+pub fn encode_aggregate<const N: usize>(digitals: &[&[u8]], analogs: &[u16]) -> Result<Vec<u8, N>, Error> {
+    let mut out = Vec::new();
+    for digital in digitals {
+        out.extend_from_slice(digital).map_err(|_| Error::InsufficientSpace)?;
+    }
+    for analog in analogs {
+        out.extend_from_slice(&analog.to_le_bytes())
+            .map_err(|_| Error::InsufficientSpace)?;
+    }
+    Ok(out)
+}
+
+/// Attribute value storage for a Digital characteristic holding up to `4 * N` digital signals.
+pub struct DigitalStorage<const N: usize> {
+    value: [u8; N],
+    presentation: [u8; 7],
+    number_of_digitals: [u8; 1],
+}
+
+impl<const N: usize> Default for DigitalStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> DigitalStorage<N> {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            value: [0; N],
+            presentation: [0; 7],
+            number_of_digitals: [0; 1],
+        }
+    }
+}
+
+/// Attribute value storage for an Analog characteristic.
+pub struct AnalogStorage {
+    value: [u8; 2],
+    presentation: [u8; 7],
+    valid_range: [u8; 4],
+}
+
+impl Default for AnalogStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalogStorage {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            value: [0; 2],
+            presentation: [0; 7],
+            valid_range: [0; 4],
+        }
+    }
+}
+
+/// Attribute value storage for an Aggregate characteristic of up to `N` bytes.
+pub struct AggregateStorage<const N: usize> {
+    value: [u8; N],
+    presentation: [u8; 7],
+}
+
+impl<const N: usize> Default for AggregateStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AggregateStorage<N> {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            value: [0; N],
+            presentation: [0; 7],
+        }
+    }
+}
+
+/// Builder adding an Automation IO Service to an attribute table.
+///
+/// The description field of each Characteristic Presentation Format descriptor is set to the
+/// 1-based index of the instance among characteristics of the same kind.
+pub struct AutomationIoBuilder<'r, 'd, M: RawMutex, const MAX: usize> {
+    service: ServiceBuilder<'r, 'd, M, MAX>,
+    digitals: u16,
+    analogs: u16,
+}
+
+impl<'r, 'd, M: RawMutex, const MAX: usize> AutomationIoBuilder<'r, 'd, M, MAX> {
+    /// Start adding an Automation IO Service to the attribute table.
+    pub fn new(table: &'r mut AttributeTable<'d, M, MAX>) -> Self {
+        Self {
+            service: table.add_service(Service::new(AUTOMATION_IO_SERVICE)),
+            digitals: 0,
+            analogs: 0,
+        }
+    }
+
+    /// Add a Digital characteristic with `count` digital signals.
+    ///
+    /// `props` usually contains [`CharacteristicProp::Read`] for inputs, and
+    /// [`CharacteristicProp::Write`] or [`CharacteristicProp::WriteWithoutResponse`] for outputs.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the storage cannot hold `count` signals.
+    pub fn add_digital<const N: usize>(
+        &mut self,
+        props: &[CharacteristicProp],
+        count: u8,
+        storage: &'d mut DigitalStorage<N>,
+    ) -> Result<Characteristic<[u8; N]>, Error> {
+        if count as usize > N * 4 {
+            return Err(Error::InsufficientSpace);
+        }
+        self.digitals += 1;
+        let DigitalStorage {
+            value,
+            presentation,
+            number_of_digitals,
+        } = storage;
+        *presentation = PresentationFormat::new(
+            PresentationFormat::FORMAT_STRUCT,
+            0,
+            PresentationFormat::UNITLESS,
+            PresentationFormat::NAMESPACE_BLUETOOTH_SIG,
+            self.digitals,
+        )
+        .to_bytes();
+        *number_of_digitals = [count];
+
+        let initial = *value;
+        let mut characteristic = self.service.add_characteristic(DIGITAL, props, initial, value);
+        characteristic.add_descriptor_ro::<[u8; 7], _>(CHARACTERISTIC_PRESENTATION_FORMAT, presentation);
+        characteristic.add_descriptor_ro::<u8, _>(NUMBER_OF_DIGITALS, number_of_digitals);
+        Ok(characteristic.build())
+    }
+
+    /// Add an Analog characteristic.
+    ///
+    /// The format of `presentation` is set to [`PresentationFormat::FORMAT_UINT16`] and its
+    /// description to the index of the characteristic. If `valid_range` is given, a Valid Range
+    /// descriptor with the inclusive lower and upper bounds is added.
+    pub fn add_analog(
+        &mut self,
+        props: &[CharacteristicProp],
+        initial: u16,
+        presentation: PresentationFormat,
+        valid_range: Option<(u16, u16)>,
+        storage: &'d mut AnalogStorage,
+    ) -> Characteristic<u16> {
+        self.analogs += 1;
+        let AnalogStorage {
+            value,
+            presentation: presentation_store,
+            valid_range: valid_range_store,
+        } = storage;
+        *presentation_store = PresentationFormat::new(
+            PresentationFormat::FORMAT_UINT16,
+            presentation.exponent(),
+            presentation.unit(),
+            presentation.namespace(),
+            self.analogs,
+        )
+        .to_bytes();
+
+        let mut characteristic = self.service.add_characteristic(ANALOG, props, initial, value);
+        characteristic.add_descriptor_ro::<[u8; 7], _>(CHARACTERISTIC_PRESENTATION_FORMAT, presentation_store);
+        if let Some((lower, upper)) = valid_range {
+            valid_range_store[..2].copy_from_slice(&lower.to_le_bytes());
+            valid_range_store[2..].copy_from_slice(&upper.to_le_bytes());
+            characteristic.add_descriptor_ro::<[u8; 4], _>(VALID_RANGE, valid_range_store);
+        }
+        characteristic.build()
+    }
+
+    /// Add the Aggregate characteristic, whose value can be computed with [`encode_aggregate`].
+    pub fn add_aggregate<const N: usize>(
+        &mut self,
+        props: &[CharacteristicProp],
+        storage: &'d mut AggregateStorage<N>,
+    ) -> Characteristic<Vec<u8, N>> {
+        let AggregateStorage { value, presentation } = storage;
+        *presentation = PresentationFormat::new(
+            PresentationFormat::FORMAT_STRUCT,
+            0,
+            PresentationFormat::UNITLESS,
+            PresentationFormat::NAMESPACE_BLUETOOTH_SIG,
+            1,
+        )
+        .to_bytes();
+
+        let mut characteristic = self.service.add_characteristic(AGGREGATE, props, Vec::new(), value);
+        characteristic.add_descriptor_ro::<[u8; 7], _>(CHARACTERISTIC_PRESENTATION_FORMAT, presentation);
+        characteristic.build()
+    }
+
+    /// Finish construction of the service and return its handle.
+    pub fn build(self) -> u16 {
+        self.service.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digital_states() {
+        let mut value = [0u8; 2];
+        unwrap!(set_digital_state(&mut value, 0, DigitalState::Active));
+        unwrap!(set_digital_state(&mut value, 3, DigitalState::Unknown));
+        unwrap!(set_digital_state(&mut value, 5, DigitalState::Tristate));
+        assert_eq!(value, [0xc1, 0x08]);
+
+        unwrap!(set_digital_state(&mut value, 3, DigitalState::Inactive));
+        assert_eq!(value, [0x01, 0x08]);
+        assert_eq!(digital_state(&value, 0), Some(DigitalState::Active));
+        assert_eq!(digital_state(&value, 5), Some(DigitalState::Tristate));
+        assert_eq!(digital_state(&value, 8), None);
+        assert!(set_digital_state(&mut value, 8, DigitalState::Active).is_err());
+    }
+
+    #[test]
+    fn aggregate() {
+        let out: Vec<u8, 8> = unwrap!(encode_aggregate(&[&[0x01], &[0x40, 0x02]], &[0x1234]));
+        assert_eq!(out.as_slice(), &[0x01, 0x40, 0x02, 0x34, 0x12]);
+        assert!(encode_aggregate::<4>(&[&[0x01]], &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn presentation_format() {
+        let format = PresentationFormat::new(
+            PresentationFormat::FORMAT_UINT16,
+            -3,
+            0x2728,
+            PresentationFormat::NAMESPACE_BLUETOOTH_SIG,
+            2,
+        );
+        assert_eq!(format.to_bytes(), [0x06, 0xfd, 0x28, 0x27, 0x01, 0x02, 0x00]);
+    }
+}
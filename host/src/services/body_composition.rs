@@ -0,0 +1,361 @@
+//! ## Body Composition Service
+//!
+//! The Body Composition Service (BCS) exposes body composition measurements such as body fat
+//! percentage, muscle mass or body water mass. Measurements are delivered to the collector as
+//! indications on the Body Composition Measurement characteristic. A measurement with many fields
+//! may not fit in a single indication, in which case it is split over two indications with the
+//! Multiple Packet Measurement flag set.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+
+use super::weight_scale::MeasurementUnits;
+use super::DateTime;
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service};
+use crate::gatt::GattConnection;
+use crate::types::uuid::Uuid;
+use crate::{Error, PacketPool};
+
+/// UUID of the Body Composition Service.
+pub const BODY_COMPOSITION_SERVICE: Uuid = Uuid::new_short(0x181b);
+/// UUID of the Body Composition Measurement characteristic.
+pub const BODY_COMPOSITION_MEASUREMENT: Uuid = Uuid::new_short(0x2a9c);
+/// UUID of the Body Composition Feature characteristic.
+pub const BODY_COMPOSITION_FEATURE: Uuid = Uuid::new_short(0x2a9b);
+
+/// The number of attributes added by [`BodyCompositionService::build`]
+/// BODY_COMPOSITION_SERVICE:         1
+/// ├── BODY_COMPOSITION_MEASUREMENT: 3
+/// └── BODY_COMPOSITION_FEATURE:     2
+///                                 ---
+///                                 = 6
+pub const BODY_COMPOSITION_SERVICE_ATTRIBUTE_COUNT: usize = 6;
+
+/// Maximum length of an encoded Body Composition Measurement.
+pub const BODY_COMPOSITION_MEASUREMENT_MAX_LEN: usize = 30;
+
+/// Value of a field indicating that the measurement was unsuccessful.
+pub const BODY_COMPOSITION_MEASUREMENT_UNSUCCESSFUL: u16 = 0xffff;
+
+const FLAG_IMPERIAL: u16 = 1 << 0;
+const FLAG_TIME_STAMP_PRESENT: u16 = 1 << 1;
+const FLAG_USER_ID_PRESENT: u16 = 1 << 2;
+const FLAG_BASAL_METABOLISM_PRESENT: u16 = 1 << 3;
+const FLAG_MUSCLE_PERCENTAGE_PRESENT: u16 = 1 << 4;
+const FLAG_MUSCLE_MASS_PRESENT: u16 = 1 << 5;
+const FLAG_FAT_FREE_MASS_PRESENT: u16 = 1 << 6;
+const FLAG_SOFT_LEAN_MASS_PRESENT: u16 = 1 << 7;
+const FLAG_BODY_WATER_MASS_PRESENT: u16 = 1 << 8;
+const FLAG_IMPEDANCE_PRESENT: u16 = 1 << 9;
+const FLAG_WEIGHT_PRESENT: u16 = 1 << 10;
+const FLAG_HEIGHT_PRESENT: u16 = 1 << 11;
+const FLAG_MULTIPLE_PACKET: u16 = 1 << 12;
+
+/// Flags and body fat percentage, present in every packet.
+const HEADER_LEN: usize = 4;
+
+type Packet = Vec<u8, BODY_COMPOSITION_MEASUREMENT_MAX_LEN>;
+
+/// A body composition measurement.
+///
+/// Masses have a resolution of 0.005 kg or 0.01 lb and heights of 0.001 m or 0.1 inch,
+/// depending on the units.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BodyCompositionMeasurement {
+    /// Unit system of the masses and height.
+    pub units: MeasurementUnits,
+    /// Body fat percentage, with a resolution of 0.1 %.
+    pub body_fat_percentage: u16,
+    /// Time of the measurement.
+    pub time_stamp: Option<DateTime>,
+    /// Index of the user the measurement belongs to, `0xff` for an unknown user.
+    pub user_id: Option<u8>,
+    /// Basal metabolism in kilojoules.
+    pub basal_metabolism: Option<u16>,
+    /// Muscle percentage, with a resolution of 0.1 %.
+    pub muscle_percentage: Option<u16>,
+    /// Muscle mass.
+    pub muscle_mass: Option<u16>,
+    /// Fat free mass.
+    pub fat_free_mass: Option<u16>,
+    /// Soft lean mass.
+    pub soft_lean_mass: Option<u16>,
+    /// Body water mass.
+    pub body_water_mass: Option<u16>,
+    /// Impedance, with a resolution of 0.1 Ω.
+    pub impedance: Option<u16>,
+    /// Weight.
+    pub weight: Option<u16>,
+    /// Height.
+    pub height: Option<u16>,
+}
+
+impl BodyCompositionMeasurement {
+    /// Encode the measurement as one or two Body Composition Measurement characteristic values of
+    /// at most `max_len` bytes each.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the measurement does not fit in two values.
+    pub fn encode(&self, max_len: usize) -> Result<Vec<Packet, 2>, Error> {
+        let max_len = max_len.min(BODY_COMPOSITION_MEASUREMENT_MAX_LEN);
+        let mut fields: Vec<(u16, Vec<u8, 7>), 11> = Vec::new();
+        // There is one slot for each optional field, and no field is longer than a time stamp.
+        let mut push = |flag, bytes: &[u8]| {
+            let value = unwrap!(Vec::from_slice(bytes).ok());
+            unwrap!(fields.push((flag, value)).ok());
+        };
+        if let Some(time_stamp) = self.time_stamp {
+            push(FLAG_TIME_STAMP_PRESENT, &time_stamp.to_bytes());
+        }
+        if let Some(user_id) = self.user_id {
+            push(FLAG_USER_ID_PRESENT, &[user_id]);
+        }
+        for (flag, value) in [
+            (FLAG_BASAL_METABOLISM_PRESENT, self.basal_metabolism),
+            (FLAG_MUSCLE_PERCENTAGE_PRESENT, self.muscle_percentage),
+            (FLAG_MUSCLE_MASS_PRESENT, self.muscle_mass),
+            (FLAG_FAT_FREE_MASS_PRESENT, self.fat_free_mass),
+            (FLAG_SOFT_LEAN_MASS_PRESENT, self.soft_lean_mass),
+            (FLAG_BODY_WATER_MASS_PRESENT, self.body_water_mass),
+            (FLAG_IMPEDANCE_PRESENT, self.impedance),
+            (FLAG_WEIGHT_PRESENT, self.weight),
+            (FLAG_HEIGHT_PRESENT, self.height),
+        ] {
+            if let Some(value) = value {
+                push(flag, &value.to_le_bytes());
+            }
+        }
+
+        let mut flags = if self.units == MeasurementUnits::Imperial {
+            FLAG_IMPERIAL
+        } else {
+            0
+        };
+        let total = HEADER_LEN + fields.iter().map(|(_, v)| v.len()).sum::<usize>();
+        if total > max_len {
+            flags |= FLAG_MULTIPLE_PACKET;
+        }
+
+        let mut packets: Vec<Packet, 2> = Vec::new();
+        let mut packet = self.start_packet();
+        let mut packet_flags = flags;
+        for (flag, value) in fields.iter() {
+            if packet.len() + value.len() > max_len {
+                finish_packet(&mut packet, packet_flags);
+                packets.push(packet).map_err(|_| Error::InsufficientSpace)?;
+                packet = self.start_packet();
+                packet_flags = flags;
+                if packet.len() + value.len() > max_len {
+                    return Err(Error::InsufficientSpace);
+                }
+            }
+            packet_flags |= flag;
+            unwrap!(packet.extend_from_slice(value));
+        }
+        if packet.len() > max_len {
+            return Err(Error::InsufficientSpace);
+        }
+        finish_packet(&mut packet, packet_flags);
+        packets.push(packet).map_err(|_| Error::InsufficientSpace)?;
+        Ok(packets)
+    }
+
+    fn start_packet(&self) -> Packet {
+        let mut packet = Packet::new();
+        unwrap!(packet.extend_from_slice(&[0, 0]));
+        unwrap!(packet.extend_from_slice(&self.body_fat_percentage.to_le_bytes()));
+        packet
+    }
+}
+
+fn finish_packet(packet: &mut Packet, flags: u16) {
+    packet[..2].copy_from_slice(&flags.to_le_bytes());
+}
+
+/// Value of the Body Composition Feature characteristic.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BodyCompositionFeatures(pub u32);
+
+impl BodyCompositionFeatures {
+    /// Measurements include a time stamp.
+    pub const TIME_STAMP: Self = Self(1 << 0);
+    /// The device supports multiple users.
+    pub const MULTIPLE_USERS: Self = Self(1 << 1);
+    /// Measurements include the basal metabolism.
+    pub const BASAL_METABOLISM: Self = Self(1 << 2);
+    /// Measurements include the muscle percentage.
+    pub const MUSCLE_PERCENTAGE: Self = Self(1 << 3);
+    /// Measurements include the muscle mass.
+    pub const MUSCLE_MASS: Self = Self(1 << 4);
+    /// Measurements include the fat free mass.
+    pub const FAT_FREE_MASS: Self = Self(1 << 5);
+    /// Measurements include the soft lean mass.
+    pub const SOFT_LEAN_MASS: Self = Self(1 << 6);
+    /// Measurements include the body water mass.
+    pub const BODY_WATER_MASS: Self = Self(1 << 7);
+    /// Measurements include the impedance.
+    pub const IMPEDANCE: Self = Self(1 << 8);
+    /// Measurements include the weight.
+    pub const WEIGHT: Self = Self(1 << 9);
+    /// Measurements include the height.
+    pub const HEIGHT: Self = Self(1 << 10);
+
+    /// Combine two feature sets.
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Set the mass measurement resolution, from 1 (0.5 kg) to 7 (0.005 kg); 0 if unspecified.
+    pub const fn with_mass_resolution(self, resolution: u8) -> Self {
+        Self((self.0 & !(0xf << 11)) | (((resolution & 0xf) as u32) << 11))
+    }
+
+    /// Set the height measurement resolution, from 1 (0.01 m) to 3 (0.001 m); 0 if unspecified.
+    pub const fn with_height_resolution(self, resolution: u8) -> Self {
+        Self((self.0 & !(0x7 << 15)) | (((resolution & 0x7) as u32) << 15))
+    }
+}
+
+/// Attribute value storage for a [`BodyCompositionService`].
+pub struct BodyCompositionStorage {
+    measurement: [u8; BODY_COMPOSITION_MEASUREMENT_MAX_LEN],
+    feature: [u8; 4],
+}
+
+impl Default for BodyCompositionStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BodyCompositionStorage {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            measurement: [0; BODY_COMPOSITION_MEASUREMENT_MAX_LEN],
+            feature: [0; 4],
+        }
+    }
+}
+
+/// Handles of the Body Composition Service.
+pub struct BodyCompositionService {
+    /// Body Composition Measurement characteristic.
+    pub measurement: Characteristic<Packet>,
+    /// Body Composition Feature characteristic.
+    pub feature: Characteristic<u32>,
+}
+
+impl BodyCompositionService {
+    /// Add the Body Composition Service to the attribute table.
+    ///
+    /// The Body Composition Service is usually included by a Weight Scale Service.
+    pub fn build<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut BodyCompositionStorage,
+        features: BodyCompositionFeatures,
+    ) -> Self {
+        let BodyCompositionStorage { measurement, feature } = storage;
+        let mut service = table.add_service(Service::new(BODY_COMPOSITION_SERVICE));
+        let measurement = service
+            .add_characteristic(
+                BODY_COMPOSITION_MEASUREMENT,
+                &[CharacteristicProp::Indicate],
+                Vec::new(),
+                measurement,
+            )
+            .build();
+        let feature = service
+            .add_characteristic(
+                BODY_COMPOSITION_FEATURE,
+                &[CharacteristicProp::Read],
+                features.0,
+                feature,
+            )
+            .build();
+        service.build();
+
+        Self { measurement, feature }
+    }
+
+    /// Indicate a measurement to the client, split over two indications if it does not fit in the
+    /// connection's ATT MTU.
+    ///
+    /// Each indication waits for the client's confirmation, so the second part is only sent once the first
+    /// one was confirmed.
+    ///
+    /// If the client has not enabled indications, only the stored value is updated.
+    pub async fn indicate<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        measurement: &BodyCompositionMeasurement,
+    ) -> Result<(), Error> {
+        let max_len = connection.raw().att_mtu() as usize - 3;
+        for packet in measurement.encode(max_len)? {
+            connection.indicate_raw(self.measurement.handle, &packet).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_measurement() -> BodyCompositionMeasurement {
+        BodyCompositionMeasurement {
+            units: MeasurementUnits::Si,
+            body_fat_percentage: 250,
+            time_stamp: Some(DateTime {
+                year: 2024,
+                month: 1,
+                day: 2,
+                hours: 3,
+                minutes: 4,
+                seconds: 5,
+            }),
+            user_id: Some(2),
+            basal_metabolism: Some(7000),
+            muscle_percentage: Some(400),
+            muscle_mass: Some(6000),
+            fat_free_mass: Some(11000),
+            soft_lean_mass: Some(10000),
+            body_water_mass: Some(8000),
+            impedance: Some(5000),
+            weight: Some(14000),
+            height: Some(1800),
+        }
+    }
+
+    #[test]
+    fn encode_single_packet() {
+        let m = BodyCompositionMeasurement {
+            body_fat_percentage: 250,
+            weight: Some(14000),
+            ..Default::default()
+        };
+        let packets = unwrap!(m.encode(20));
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].as_slice(), &[0x00, 0x04, 0xfa, 0x00, 0xb0, 0x36]);
+
+        let packets = unwrap!(full_measurement().encode(BODY_COMPOSITION_MEASUREMENT_MAX_LEN));
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].len(), BODY_COMPOSITION_MEASUREMENT_MAX_LEN);
+        assert_eq!(&packets[0][..2], &[0xfe, 0x0f]);
+    }
+
+    #[test]
+    fn encode_multiple_packets() {
+        let packets = unwrap!(full_measurement().encode(20));
+        assert_eq!(packets.len(), 2);
+        // Time stamp, user ID and four 16-bit fields in the first packet.
+        assert_eq!(packets[0].len(), 20);
+        assert_eq!(&packets[0][..4], &[0x7e, 0x10, 0xfa, 0x00]);
+        // The remaining five fields in the second.
+        assert_eq!(packets[1].len(), 14);
+        assert_eq!(&packets[1][..4], &[0x80, 0x1f, 0xfa, 0x00]);
+
+        assert!(full_measurement().encode(10).is_err());
+    }
+}
@@ -0,0 +1,956 @@
+//! ## Glucose Service
+//!
+//! The Glucose Service (GLS) exposes glucose measurements stored by a sensor. Measurements are not
+//! pushed as they are taken: the collector asks for them through the Record Access Control Point
+//! (RACP), which reports stored records as notifications on the Glucose Measurement characteristic
+//! and finishes every procedure with an indication on the RACP itself.
+//!
+//! [`GlucoseService`] adds the service to an attribute table and runs the RACP procedures against a
+//! [`GlucoseRecords`] store. Procedures run next to the event loop, so that an Abort Operation written
+//! during a report is seen before the report ends:
+//!
+//! ```rust,ignore
+//! let requests: Channel<NoopRawMutex, Vec<u8, RACP_MAX_LEN>, 2> = Channel::new();
+//! let procedures = async {
+//!     loop {
+//!         let request = requests.receive().await;
+//!         glucose.process_racp(&conn, &request, &mut records).await?;
+//!     }
+//! };
+//! let events = async {
+//!     loop {
+//!         let GattConnectionEvent::Gatt { event } = conn.next().await else { continue };
+//!         match event {
+//!             GattEvent::Write(event) if event.handle() == glucose.racp.handle => {
+//!                 if let Err(code) = glucose.check_racp_write(&conn, event.data()) {
+//!                     event.reject(code)?.send().await;
+//!                 } else {
+//!                     let request = Vec::from_slice(event.data()).unwrap();
+//!                     event.accept()?.send().await;
+//!                     requests.send(request).await;
+//!                 }
+//!             }
+//!             event => event.accept()?.send().await,
+//!         }
+//!     }
+//! };
+//! select(procedures, events).await;
+//! ```
+//!
+//! The optional Glucose Measurement Context characteristic is not included.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+
+use super::DateTime;
+use crate::att::AttErrorCode;
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service};
+use crate::gatt::GattConnection;
+use crate::types::uuid::Uuid;
+use crate::{Error, PacketPool};
+
+/// UUID of the Glucose Service.
+pub const GLUCOSE_SERVICE: Uuid = Uuid::new_short(0x1808);
+/// UUID of the Glucose Measurement characteristic.
+pub const GLUCOSE_MEASUREMENT: Uuid = Uuid::new_short(0x2a18);
+/// UUID of the Glucose Feature characteristic.
+pub const GLUCOSE_FEATURE: Uuid = Uuid::new_short(0x2a51);
+/// UUID of the Record Access Control Point characteristic.
+pub const RECORD_ACCESS_CONTROL_POINT: Uuid = Uuid::new_short(0x2a52);
+
+/// The number of attributes added by [`GlucoseService::build`]
+/// GLUCOSE_SERVICE:                 1
+/// ├── GLUCOSE_MEASUREMENT:         3
+/// ├── GLUCOSE_FEATURE:             2
+/// └── RECORD_ACCESS_CONTROL_POINT: 3
+///                                ---
+///                                = 9
+pub const GLUCOSE_SERVICE_ATTRIBUTE_COUNT: usize = 9;
+
+/// Maximum length of an encoded Glucose Measurement.
+pub const MEASUREMENT_MAX_LEN: usize = 17;
+/// Maximum length of a value written to or indicated on the Record Access Control Point.
+pub const RACP_MAX_LEN: usize = 20;
+
+const MEASUREMENT_TIME_OFFSET_PRESENT: u8 = 0x01;
+const MEASUREMENT_CONCENTRATION_PRESENT: u8 = 0x02;
+const MEASUREMENT_UNIT_MOL_PER_LITER: u8 = 0x04;
+const MEASUREMENT_SENSOR_STATUS_PRESENT: u8 = 0x08;
+
+const RACP_REPORT_STORED_RECORDS: u8 = 0x01;
+const RACP_DELETE_STORED_RECORDS: u8 = 0x02;
+const RACP_ABORT_OPERATION: u8 = 0x03;
+const RACP_REPORT_NUMBER_OF_RECORDS: u8 = 0x04;
+const RACP_NUMBER_OF_RECORDS_RESPONSE: u8 = 0x05;
+const RACP_RESPONSE_CODE: u8 = 0x06;
+
+const RACP_OPERATOR_NULL: u8 = 0x00;
+const RACP_FILTER_SEQUENCE_NUMBER: u8 = 0x01;
+const RACP_FILTER_USER_FACING_TIME: u8 = 0x02;
+
+/// Encode a value as an IEEE-11073 16-bit SFLOAT, `mantissa * 10^exponent`.
+///
+/// The mantissa is truncated to 12 bits and the exponent to 4 bits.
+pub const fn sfloat(mantissa: i16, exponent: i8) -> u16 {
+    (((exponent as u16) & 0x000f) << 12) | ((mantissa as u16) & 0x0fff)
+}
+
+/// Unit of a glucose concentration.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcentrationUnit {
+    /// Kilograms per liter.
+    KgPerLiter,
+    /// Moles per liter.
+    MolPerLiter,
+}
+
+/// Glucose concentration of a measurement, together with the sample type and location.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlucoseConcentration {
+    /// Concentration as an SFLOAT, see [`sfloat`].
+    pub value: u16,
+    /// Unit of the concentration.
+    pub unit: ConcentrationUnit,
+    /// Sample type, for example `0x01` for capillary whole blood.
+    pub sample_type: u8,
+    /// Sample location, for example `0x01` for finger.
+    pub sample_location: u8,
+}
+
+/// A stored glucose measurement record.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlucoseMeasurement {
+    /// Sequence number of the record, assigned by [`GlucoseRecords::push`].
+    pub sequence_number: u16,
+    /// Time of the measurement.
+    pub base_time: DateTime,
+    /// Offset from the base time in minutes.
+    pub time_offset: Option<i16>,
+    /// Glucose concentration.
+    pub concentration: Option<GlucoseConcentration>,
+    /// Sensor status annunciation flags.
+    pub sensor_status: Option<u16>,
+}
+
+impl GlucoseMeasurement {
+    /// Create a measurement taken at the given time, without optional fields.
+    pub const fn new(base_time: DateTime) -> Self {
+        Self {
+            sequence_number: 0,
+            base_time,
+            time_offset: None,
+            concentration: None,
+            sensor_status: None,
+        }
+    }
+
+    /// Encode the measurement as a Glucose Measurement characteristic value.
+    pub fn encode(&self) -> Vec<u8, MEASUREMENT_MAX_LEN> {
+        let mut flags = 0;
+        let mut out: Vec<u8, MEASUREMENT_MAX_LEN> = Vec::new();
+        // The buffer is sized for a measurement with every optional field present.
+        let mut put = |bytes: &[u8]| unwrap!(out.extend_from_slice(bytes));
+        put(&[0]);
+        put(&self.sequence_number.to_le_bytes());
+        put(&self.base_time.to_bytes());
+        if let Some(offset) = self.time_offset {
+            flags |= MEASUREMENT_TIME_OFFSET_PRESENT;
+            put(&offset.to_le_bytes());
+        }
+        if let Some(concentration) = &self.concentration {
+            flags |= MEASUREMENT_CONCENTRATION_PRESENT;
+            if concentration.unit == ConcentrationUnit::MolPerLiter {
+                flags |= MEASUREMENT_UNIT_MOL_PER_LITER;
+            }
+            put(&concentration.value.to_le_bytes());
+            put(&[(concentration.sample_type & 0x0f) | (concentration.sample_location << 4)]);
+        }
+        if let Some(status) = self.sensor_status {
+            flags |= MEASUREMENT_SENSOR_STATUS_PRESENT;
+            put(&status.to_le_bytes());
+        }
+        out[0] = flags;
+        out
+    }
+}
+
+/// Operator and operand of a RACP request, selecting records by sequence number.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacpOperator {
+    /// All records.
+    All,
+    /// Records with a sequence number less than or equal to the operand.
+    LessOrEqual(u16),
+    /// Records with a sequence number greater than or equal to the operand.
+    GreaterOrEqual(u16),
+    /// Records with a sequence number within the inclusive range.
+    Range(u16, u16),
+    /// The oldest record.
+    First,
+    /// The most recent record.
+    Last,
+}
+
+/// A request written to the Record Access Control Point.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacpRequest {
+    /// Notify the selected records.
+    ReportStoredRecords(RacpOperator),
+    /// Delete the selected records.
+    DeleteStoredRecords(RacpOperator),
+    /// Abort the procedure in progress.
+    AbortOperation,
+    /// Indicate the number of selected records.
+    ReportNumberOfRecords(RacpOperator),
+}
+
+/// Response codes of the Record Access Control Point.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum RacpResponseCode {
+    Success = 0x01,
+    OpCodeNotSupported = 0x02,
+    InvalidOperator = 0x03,
+    OperatorNotSupported = 0x04,
+    InvalidOperand = 0x05,
+    NoRecordsFound = 0x06,
+    AbortUnsuccessful = 0x07,
+    ProcedureNotCompleted = 0x08,
+    OperandNotSupported = 0x09,
+}
+
+impl RacpRequest {
+    /// Parse a value written to the Record Access Control Point.
+    ///
+    /// On failure, returns the response code to indicate to the client.
+    pub fn parse(data: &[u8]) -> Result<Self, RacpResponseCode> {
+        let (&opcode, rest) = data.split_first().ok_or(RacpResponseCode::OpCodeNotSupported)?;
+        let (&operator, operand) = rest.split_first().ok_or(RacpResponseCode::InvalidOperator)?;
+        match opcode {
+            RACP_REPORT_STORED_RECORDS => Ok(Self::ReportStoredRecords(parse_operator(operator, operand)?)),
+            RACP_DELETE_STORED_RECORDS => Ok(Self::DeleteStoredRecords(parse_operator(operator, operand)?)),
+            RACP_REPORT_NUMBER_OF_RECORDS => Ok(Self::ReportNumberOfRecords(parse_operator(operator, operand)?)),
+            RACP_ABORT_OPERATION if operator != RACP_OPERATOR_NULL => Err(RacpResponseCode::InvalidOperator),
+            RACP_ABORT_OPERATION if !operand.is_empty() => Err(RacpResponseCode::InvalidOperand),
+            RACP_ABORT_OPERATION => Ok(Self::AbortOperation),
+            _ => Err(RacpResponseCode::OpCodeNotSupported),
+        }
+    }
+
+    fn opcode(&self) -> u8 {
+        match self {
+            Self::ReportStoredRecords(_) => RACP_REPORT_STORED_RECORDS,
+            Self::DeleteStoredRecords(_) => RACP_DELETE_STORED_RECORDS,
+            Self::AbortOperation => RACP_ABORT_OPERATION,
+            Self::ReportNumberOfRecords(_) => RACP_REPORT_NUMBER_OF_RECORDS,
+        }
+    }
+}
+
+fn parse_operator(operator: u8, operand: &[u8]) -> Result<RacpOperator, RacpResponseCode> {
+    let no_operand = |op| {
+        if operand.is_empty() {
+            Ok(op)
+        } else {
+            Err(RacpResponseCode::InvalidOperand)
+        }
+    };
+    match operator {
+        0x00 => Err(RacpResponseCode::InvalidOperator),
+        0x01 => no_operand(RacpOperator::All),
+        0x02 => sequence_operand(operand, 2).map(|v| RacpOperator::LessOrEqual(u16::from_le_bytes([v[0], v[1]]))),
+        0x03 => sequence_operand(operand, 2).map(|v| RacpOperator::GreaterOrEqual(u16::from_le_bytes([v[0], v[1]]))),
+        0x04 => {
+            let v = sequence_operand(operand, 4)?;
+            let min = u16::from_le_bytes([v[0], v[1]]);
+            let max = u16::from_le_bytes([v[2], v[3]]);
+            if min > max {
+                return Err(RacpResponseCode::InvalidOperand);
+            }
+            Ok(RacpOperator::Range(min, max))
+        }
+        0x05 => no_operand(RacpOperator::First),
+        0x06 => no_operand(RacpOperator::Last),
+        _ => Err(RacpResponseCode::OperatorNotSupported),
+    }
+}
+
+fn sequence_operand(operand: &[u8], len: usize) -> Result<&[u8], RacpResponseCode> {
+    match operand.split_first() {
+        Some((&RACP_FILTER_SEQUENCE_NUMBER, value)) if value.len() == len => Ok(value),
+        Some((&RACP_FILTER_USER_FACING_TIME, _)) => Err(RacpResponseCode::OperandNotSupported),
+        _ => Err(RacpResponseCode::InvalidOperand),
+    }
+}
+
+/// Encode a RACP Response Code indication for the request with the given opcode.
+pub fn racp_response(request_opcode: u8, code: RacpResponseCode) -> [u8; 4] {
+    [RACP_RESPONSE_CODE, RACP_OPERATOR_NULL, request_opcode, code as u8]
+}
+
+/// Encode a RACP Number of Stored Records Response indication.
+pub fn racp_number_response(count: u16) -> [u8; 4] {
+    let count = count.to_le_bytes();
+    [RACP_NUMBER_OF_RECORDS_RESPONSE, RACP_OPERATOR_NULL, count[0], count[1]]
+}
+
+/// Storage of up to `N` glucose measurement records, ordered from oldest to most recent.
+pub struct GlucoseRecords<const N: usize> {
+    records: Vec<GlucoseMeasurement, N>,
+    next_sequence_number: u16,
+}
+
+impl<const N: usize> Default for GlucoseRecords<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> GlucoseRecords<N> {
+    /// Create an empty record store.
+    pub const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+            next_sequence_number: 0,
+        }
+    }
+
+    /// Store a measurement, assigning it the next sequence number, which is returned.
+    ///
+    /// When the store is full the oldest record is dropped.
+    pub fn push(&mut self, mut measurement: GlucoseMeasurement) -> u16 {
+        if self.records.is_full() {
+            self.records.remove(0);
+        }
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number = self.next_sequence_number.wrapping_add(1);
+        measurement.sequence_number = sequence_number;
+        // A slot was freed above if the store was full.
+        unwrap!(self.records.push(measurement).ok());
+        sequence_number
+    }
+
+    /// Stored records, from oldest to most recent.
+    pub fn records(&self) -> &[GlucoseMeasurement] {
+        &self.records
+    }
+
+    /// Records selected by the operator.
+    pub fn select(&self, operator: RacpOperator) -> impl Iterator<Item = &GlucoseMeasurement> {
+        let len = self.records.len();
+        self.records
+            .iter()
+            .enumerate()
+            .filter(move |(i, r)| selects(operator, *i, len, r))
+            .map(|(_, r)| r)
+    }
+
+    /// Number of records selected by the operator.
+    pub fn count(&self, operator: RacpOperator) -> u16 {
+        self.select(operator).count() as u16
+    }
+
+    /// Delete the records selected by the operator, returning how many were deleted.
+    pub fn delete(&mut self, operator: RacpOperator) -> usize {
+        let len = self.records.len();
+        let mut index = 0;
+        let before = self.records.len();
+        self.records.retain(|r| {
+            let keep = !selects(operator, index, len, r);
+            index += 1;
+            keep
+        });
+        before - self.records.len()
+    }
+}
+
+fn selects(operator: RacpOperator, index: usize, len: usize, record: &GlucoseMeasurement) -> bool {
+    let seq = record.sequence_number;
+    match operator {
+        RacpOperator::All => true,
+        RacpOperator::LessOrEqual(max) => seq <= max,
+        RacpOperator::GreaterOrEqual(min) => seq >= min,
+        RacpOperator::Range(min, max) => (min..=max).contains(&seq),
+        RacpOperator::First => index == 0,
+        RacpOperator::Last => index + 1 == len,
+    }
+}
+
+/// Attribute value storage for a [`GlucoseService`].
+pub struct GlucoseStorage {
+    measurement: [u8; MEASUREMENT_MAX_LEN],
+    feature: [u8; 2],
+    racp: [u8; RACP_MAX_LEN],
+}
+
+impl Default for GlucoseStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlucoseStorage {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            measurement: [0; MEASUREMENT_MAX_LEN],
+            feature: [0; 2],
+            racp: [0; RACP_MAX_LEN],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RacpState {
+    Idle,
+    InProgress,
+    /// An abort was accepted during a report, which will respond to it. `processed` is set once the
+    /// abort itself went through [`GlucoseService::process_racp`].
+    Aborting {
+        processed: bool,
+    },
+    /// The report responded to an abort that has not gone through [`GlucoseService::process_racp`] yet.
+    AbortAnswered,
+}
+
+/// Ends the report in progress if its procedure is dropped before completing.
+struct ReportGuard<'a>(&'a Cell<RacpState>);
+
+impl Drop for ReportGuard<'_> {
+    fn drop(&mut self) {
+        if matches!(self.0.get(), RacpState::InProgress | RacpState::Aborting { .. }) {
+            self.0.set(RacpState::Idle);
+        }
+    }
+}
+
+/// Handles of the Glucose Service and the state of its Record Access Control Point.
+pub struct GlucoseService {
+    /// Glucose Measurement characteristic, used to notify reported records.
+    pub measurement: Characteristic<Vec<u8, MEASUREMENT_MAX_LEN>>,
+    /// Glucose Feature characteristic.
+    pub feature: Characteristic<u16>,
+    /// Record Access Control Point characteristic.
+    pub racp: Characteristic<Vec<u8, RACP_MAX_LEN>>,
+    state: Cell<RacpState>,
+}
+
+impl GlucoseService {
+    /// Add the Glucose Service to the attribute table.
+    ///
+    /// `features` is the value of the Glucose Feature characteristic.
+    pub fn build<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut GlucoseStorage,
+        features: u16,
+    ) -> Self {
+        let GlucoseStorage {
+            measurement,
+            feature,
+            racp,
+        } = storage;
+        let mut service = table.add_service(Service::new(GLUCOSE_SERVICE));
+        let measurement = service
+            .add_characteristic(
+                GLUCOSE_MEASUREMENT,
+                &[CharacteristicProp::Notify],
+                Vec::new(),
+                measurement,
+            )
+            .build();
+        let feature = service
+            .add_characteristic(GLUCOSE_FEATURE, &[CharacteristicProp::Read], features, feature)
+            .build();
+        let racp = service
+            .add_characteristic(
+                RECORD_ACCESS_CONTROL_POINT,
+                &[CharacteristicProp::Write, CharacteristicProp::Indicate],
+                Vec::new(),
+                racp,
+            )
+            .build();
+        service.build();
+
+        Self {
+            measurement,
+            feature,
+            racp,
+            state: Cell::new(RacpState::Idle),
+        }
+    }
+
+    /// Whether a RACP procedure is in progress.
+    pub fn racp_in_progress(&self) -> bool {
+        matches!(self.state.get(), RacpState::InProgress | RacpState::Aborting { .. })
+    }
+
+    /// Check a write to the Record Access Control Point before accepting it.
+    ///
+    /// Returns the ATT error to reject the write with if the client has not enabled indications on
+    /// the control point, or if a procedure is already in progress. An Abort Operation request is
+    /// accepted while a procedure is in progress and stops it before its next record.
+    pub fn check_racp_write<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        data: &[u8],
+    ) -> Result<(), AttErrorCode> {
+        if !connection.indications_enabled(self.racp.handle) {
+            return Err(AttErrorCode::CCCD_IMPROPERLY_CONFIGURED);
+        }
+        match self.state.get() {
+            RacpState::Idle => Ok(()),
+            // The abort answered by the last report was not processed, so it can no longer be told
+            // apart from this write.
+            RacpState::AbortAnswered => {
+                self.state.set(RacpState::Idle);
+                Ok(())
+            }
+            RacpState::InProgress if data.first() == Some(&RACP_ABORT_OPERATION) => {
+                self.state.set(RacpState::Aborting { processed: false });
+                Ok(())
+            }
+            _ => Err(AttErrorCode::PROCEDURE_ALREADY_IN_PROGRESS),
+        }
+    }
+
+    /// Run the procedure for a value written to the Record Access Control Point.
+    ///
+    /// Reported records are notified on the Glucose Measurement characteristic, and the procedure
+    /// is completed with an indication on the control point. The write should have been accepted
+    /// with [`check_racp_write`](Self::check_racp_write) first.
+    ///
+    /// An Abort Operation accepted during a report stops it before its next record. The report then
+    /// indicates the response to the abort instead of its own, and processing the abort itself, before
+    /// or after the report ends, does nothing. If this future is dropped before completing, the
+    /// procedure ends without a response.
+    pub async fn process_racp<P: PacketPool, const N: usize>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        data: &[u8],
+        records: &mut GlucoseRecords<N>,
+    ) -> Result<(), Error> {
+        let request = match RacpRequest::parse(data) {
+            Ok(request) => request,
+            Err(code) => {
+                let opcode = data.first().copied().unwrap_or(0);
+                return self.respond(connection, &racp_response(opcode, code)).await;
+            }
+        };
+
+        let response = match request {
+            RacpRequest::AbortOperation => match self.state.get() {
+                // The report being aborted responds, or already did.
+                RacpState::Aborting { .. } => {
+                    self.state.set(RacpState::Aborting { processed: true });
+                    return Ok(());
+                }
+                RacpState::AbortAnswered => {
+                    self.state.set(RacpState::Idle);
+                    return Ok(());
+                }
+                _ => racp_response(RACP_ABORT_OPERATION, RacpResponseCode::Success),
+            },
+            RacpRequest::ReportNumberOfRecords(operator) => racp_number_response(records.count(operator)),
+            RacpRequest::DeleteStoredRecords(operator) => {
+                let code = if records.delete(operator) > 0 {
+                    RacpResponseCode::Success
+                } else {
+                    RacpResponseCode::NoRecordsFound
+                };
+                racp_response(request.opcode(), code)
+            }
+            RacpRequest::ReportStoredRecords(operator) => {
+                self.state.set(RacpState::InProgress);
+                let guard = ReportGuard(&self.state);
+                let result = self.report(connection, operator, records).await;
+                // Either the report completed or it was aborted, and only that one is responded to.
+                let state = self.state.get();
+                self.state.set(match state {
+                    RacpState::Aborting { processed: false } => RacpState::AbortAnswered,
+                    _ => RacpState::Idle,
+                });
+                drop(guard);
+                match result? {
+                    _ if matches!(state, RacpState::Aborting { .. }) => {
+                        racp_response(RACP_ABORT_OPERATION, RacpResponseCode::Success)
+                    }
+                    0 => racp_response(request.opcode(), RacpResponseCode::NoRecordsFound),
+                    _ => racp_response(request.opcode(), RacpResponseCode::Success),
+                }
+            }
+        };
+        self.respond(connection, &response).await
+    }
+
+    async fn report<P: PacketPool, const N: usize>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        operator: RacpOperator,
+        records: &GlucoseRecords<N>,
+    ) -> Result<usize, Error> {
+        let mut reported = 0;
+        for record in records.select(operator) {
+            if matches!(self.state.get(), RacpState::Aborting { .. }) {
+                break;
+            }
+            connection.notify_raw(self.measurement.handle, &record.encode()).await?;
+            reported += 1;
+        }
+        Ok(reported)
+    }
+
+    async fn respond<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &[u8]) -> Result<(), Error> {
+        connection.indicate_raw(self.racp.handle, value).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::att::{ATT_HANDLE_VALUE_CMF, ATT_HANDLE_VALUE_IND, ATT_HANDLE_VALUE_NTF, ATT_WRITE_REQ};
+    use crate::attribute_server::AttributeServer;
+    use crate::connection_manager::tests::{setup, ADDR_1};
+    use crate::connection_manager::ConnectionManager;
+    use crate::gatt::{GattConnectionEvent, GattEvent};
+    use crate::pdu::Pdu;
+    use crate::prelude::DefaultPacketPool;
+
+    type Manager = ConnectionManager<'static, DefaultPacketPool>;
+    type Gatt = GattConnection<'static, 'static, DefaultPacketPool>;
+
+    const HANDLE: u16 = 0;
+
+    fn post(mgr: &Manager, data: &[u8]) {
+        let mut packet = unwrap!(DefaultPacketPool::allocate());
+        packet.as_mut()[..data.len()].copy_from_slice(data);
+        unwrap!(mgr.post_gatt(ConnHandle::new(HANDLE), Pdu::new(packet, data.len())));
+    }
+
+    /// PDUs queued for the client, without their L2CAP header.
+    fn sent(mgr: &Manager) -> std::vec::Vec<std::vec::Vec<u8>> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut sent = std::vec::Vec::new();
+        while let Poll::Ready((_, pdu)) = pin!(mgr.outbound()).poll(&mut cx) {
+            sent.push(pdu.as_ref()[4..].to_vec());
+        }
+        sent
+    }
+
+    fn value_pdu(opcode: u8, handle: u16, value: &[u8]) -> std::vec::Vec<u8> {
+        let [lo, hi] = handle.to_le_bytes();
+        [&[opcode, lo, hi], value].concat()
+    }
+
+    /// Serve the Glucose Service to a client that enabled measurement notifications and RACP indications.
+    fn connected() -> (&'static Manager, &'static GlucoseService, Gatt) {
+        let mgr = setup();
+        let mut table: AttributeTable<'static, NoopRawMutex, 16> = AttributeTable::new();
+        let glucose = GlucoseService::build(&mut table, Box::leak(Box::new(GlucoseStorage::new())), 0);
+        let glucose = Box::leak(Box::new(glucose));
+        let server: &'static AttributeServer<'static, NoopRawMutex, DefaultPacketPool, 16, 2, 1> =
+            Box::leak(Box::new(AttributeServer::new(table)));
+        unwrap!(mgr.connect(
+            ConnHandle::new(HANDLE),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(connection) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let gatt = unwrap!(connection.with_attribute_server(server));
+        for (characteristic, value) in [(glucose.measurement.cccd_handle, 1u8), (glucose.racp.cccd_handle, 2)] {
+            let [lo, hi] = unwrap!(characteristic).to_le_bytes();
+            post(mgr, &[ATT_WRITE_REQ, lo, hi, value, 0]);
+            block_on(async {
+                let GattConnectionEvent::Gatt { event } = gatt.next().await else {
+                    panic!("expected a GATT event");
+                };
+                unwrap!(event.accept()).send().await;
+            });
+        }
+        sent(mgr);
+        (mgr, glucose, gatt)
+    }
+
+    /// Confirm the outstanding indication as the client would.
+    fn confirm(mgr: &Manager, gatt: &Gatt) {
+        post(mgr, &[ATT_HANDLE_VALUE_CMF]);
+        assert!(matches!(
+            block_on(gatt.next()),
+            GattConnectionEvent::Gatt {
+                event: GattEvent::Other(_)
+            }
+        ));
+    }
+
+    fn measurement(minutes: u8) -> GlucoseMeasurement {
+        GlucoseMeasurement::new(DateTime {
+            year: 2024,
+            month: 3,
+            day: 14,
+            hours: 9,
+            minutes,
+            seconds: 0,
+        })
+    }
+
+    #[test]
+    fn encode_measurement() {
+        let mut m = measurement(30);
+        m.sequence_number = 0x0102;
+        assert_eq!(m.encode().as_slice(), &[0x00, 0x02, 0x01, 0xe8, 0x07, 3, 14, 9, 30, 0]);
+
+        m.time_offset = Some(-5);
+        m.concentration = Some(GlucoseConcentration {
+            value: sfloat(55, -4),
+            unit: ConcentrationUnit::MolPerLiter,
+            sample_type: 0x01,
+            sample_location: 0x01,
+        });
+        m.sensor_status = Some(0x0004);
+        let encoded = m.encode();
+        assert_eq!(encoded.len(), MEASUREMENT_MAX_LEN);
+        assert_eq!(encoded[0], 0x0f);
+        assert_eq!(&encoded[10..], &[0xfb, 0xff, 0x37, 0xc0, 0x11, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn parse_racp_requests() {
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x01]),
+            Ok(RacpRequest::ReportStoredRecords(RacpOperator::All))
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x04, 0x03, 0x01, 0x05, 0x00]),
+            Ok(RacpRequest::ReportNumberOfRecords(RacpOperator::GreaterOrEqual(5)))
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x02, 0x04, 0x01, 0x01, 0x00, 0x03, 0x00]),
+            Ok(RacpRequest::DeleteStoredRecords(RacpOperator::Range(1, 3)))
+        );
+        assert_eq!(RacpRequest::parse(&[0x03, 0x00]), Ok(RacpRequest::AbortOperation));
+
+        assert_eq!(RacpRequest::parse(&[]), Err(RacpResponseCode::OpCodeNotSupported));
+        assert_eq!(
+            RacpRequest::parse(&[0x07, 0x01]),
+            Err(RacpResponseCode::OpCodeNotSupported)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x00]),
+            Err(RacpResponseCode::InvalidOperator)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x07]),
+            Err(RacpResponseCode::OperatorNotSupported)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x03, 0x01]),
+            Err(RacpResponseCode::InvalidOperator)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x01, 0x01]),
+            Err(RacpResponseCode::InvalidOperand)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x02, 0x01, 0x05]),
+            Err(RacpResponseCode::InvalidOperand)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x04, 0x01, 0x03, 0x00, 0x01, 0x00]),
+            Err(RacpResponseCode::InvalidOperand)
+        );
+        assert_eq!(
+            RacpRequest::parse(&[0x01, 0x02, 0x02, 0, 0, 0, 0, 0, 0, 0]),
+            Err(RacpResponseCode::OperandNotSupported)
+        );
+    }
+
+    #[test]
+    fn record_store() {
+        let mut records: GlucoseRecords<4> = GlucoseRecords::new();
+        for minute in 0..6 {
+            records.push(measurement(minute));
+        }
+        let seqs =
+            |records: &GlucoseRecords<4>, op| records.select(op).map(|r| r.sequence_number).collect::<Vec<u16, 4>>();
+
+        // The two oldest records were dropped.
+        assert_eq!(seqs(&records, RacpOperator::All).as_slice(), &[2, 3, 4, 5]);
+        assert_eq!(seqs(&records, RacpOperator::First).as_slice(), &[2]);
+        assert_eq!(seqs(&records, RacpOperator::Last).as_slice(), &[5]);
+        assert_eq!(seqs(&records, RacpOperator::LessOrEqual(3)).as_slice(), &[2, 3]);
+        assert_eq!(seqs(&records, RacpOperator::Range(3, 4)).as_slice(), &[3, 4]);
+        assert_eq!(records.count(RacpOperator::GreaterOrEqual(4)), 2);
+
+        assert_eq!(records.delete(RacpOperator::First), 1);
+        assert_eq!(records.delete(RacpOperator::Range(10, 20)), 0);
+        assert_eq!(seqs(&records, RacpOperator::All).as_slice(), &[3, 4, 5]);
+        assert_eq!(records.delete(RacpOperator::All), 3);
+        assert_eq!(records.count(RacpOperator::All), 0);
+    }
+
+    #[test]
+    fn racp_report_all() {
+        let (mgr, glucose, gatt) = connected();
+        let mut records: GlucoseRecords<4> = GlucoseRecords::new();
+        for minute in 0..3 {
+            records.push(measurement(minute));
+        }
+        let mut cx = Context::from_waker(Waker::noop());
+
+        assert_eq!(glucose.check_racp_write(&gatt, &[0x01, 0x01]), Ok(()));
+        let mut procedure = pin!(glucose.process_racp(&gatt, &[0x01, 0x01], &mut records));
+        assert!(procedure.as_mut().poll(&mut cx).is_pending());
+        assert!(glucose.racp_in_progress());
+
+        // Every record is notified, then the procedure completes with an indication.
+        let sent = sent(mgr);
+        assert_eq!(sent.len(), 4);
+        for (seq, pdu) in sent[..3].iter().enumerate() {
+            assert_eq!(
+                &pdu[..3],
+                &value_pdu(ATT_HANDLE_VALUE_NTF, glucose.measurement.handle, &[])[..]
+            );
+            assert_eq!(&pdu[4..6], &(seq as u16).to_le_bytes());
+        }
+        assert_eq!(
+            sent[3],
+            value_pdu(ATT_HANDLE_VALUE_IND, glucose.racp.handle, &[0x06, 0x00, 0x01, 0x01])
+        );
+        confirm(mgr, &gatt);
+        assert_eq!(procedure.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(!glucose.racp_in_progress());
+    }
+
+    #[test]
+    fn racp_abort_report() {
+        // More records than fit in the transmit queue, so that the report waits in the middle.
+        const RECORDS: usize = crate::config::L2CAP_TX_QUEUE_SIZE + 2;
+        let (mgr, glucose, gatt) = connected();
+        let mut records: GlucoseRecords<RECORDS> = GlucoseRecords::new();
+        for minute in 0..RECORDS {
+            records.push(measurement(minute as u8));
+        }
+        let mut cx = Context::from_waker(Waker::noop());
+        let abort_success = value_pdu(ATT_HANDLE_VALUE_IND, glucose.racp.handle, &[0x06, 0x00, 0x03, 0x01]);
+
+        // The abort is processed while the report runs, and the report responds to it.
+        {
+            let mut report = pin!(glucose.process_racp(&gatt, &[0x01, 0x01], &mut records));
+            assert!(report.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(
+                glucose.check_racp_write(&gatt, &[0x04, 0x01]),
+                Err(AttErrorCode::PROCEDURE_ALREADY_IN_PROGRESS)
+            );
+            assert_eq!(glucose.check_racp_write(&gatt, &[0x03, 0x00]), Ok(()));
+            let mut none: GlucoseRecords<1> = GlucoseRecords::new();
+            let mut abort = pin!(glucose.process_racp(&gatt, &[0x03, 0x00], &mut none));
+            assert_eq!(abort.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+            let mut pdus = sent(mgr);
+            assert!(report.as_mut().poll(&mut cx).is_pending());
+            pdus.extend(sent(mgr));
+            // The record waiting for the queue is the last one sent.
+            let (last, notified) = unwrap!(pdus.split_last());
+            assert_eq!(notified.len(), RECORDS - 1);
+            assert!(notified.iter().all(|pdu| pdu[0] == ATT_HANDLE_VALUE_NTF));
+            assert_eq!(*last, abort_success);
+            confirm(mgr, &gatt);
+            assert_eq!(report.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert!(!glucose.racp_in_progress());
+
+        // The report responds to an abort processed only after it ended, which then sends nothing.
+        {
+            let mut report = pin!(glucose.process_racp(&gatt, &[0x01, 0x01], &mut records));
+            assert!(report.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(glucose.check_racp_write(&gatt, &[0x03, 0x00]), Ok(()));
+            sent(mgr);
+            assert!(report.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(sent(mgr).last(), Some(&abort_success));
+            confirm(mgr, &gatt);
+            assert_eq!(report.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        let mut abort = pin!(glucose.process_racp(&gatt, &[0x03, 0x00], &mut records));
+        assert_eq!(abort.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(sent(mgr).is_empty());
+        assert!(!glucose.racp_in_progress());
+    }
+
+    #[test]
+    fn racp_report_dropped() {
+        const RECORDS: usize = crate::config::L2CAP_TX_QUEUE_SIZE + 2;
+        let (mgr, glucose, gatt) = connected();
+        let mut records: GlucoseRecords<RECORDS> = GlucoseRecords::new();
+        for minute in 0..RECORDS {
+            records.push(measurement(minute as u8));
+        }
+        let mut cx = Context::from_waker(Waker::noop());
+        {
+            let mut report = pin!(glucose.process_racp(&gatt, &[0x01, 0x01], &mut records));
+            assert!(report.as_mut().poll(&mut cx).is_pending());
+            assert!(glucose.racp_in_progress());
+        }
+
+        // A dropped report no longer holds the control point.
+        assert!(!glucose.racp_in_progress());
+        assert_eq!(glucose.check_racp_write(&gatt, &[0x01, 0x01]), Ok(()));
+    }
+
+    #[test]
+    fn racp_delete_records() {
+        let (mgr, glucose, gatt) = connected();
+        let mut records: GlucoseRecords<4> = GlucoseRecords::new();
+        for minute in 0..3 {
+            records.push(measurement(minute));
+        }
+        let mut cx = Context::from_waker(Waker::noop());
+
+        for code in [RacpResponseCode::Success, RacpResponseCode::NoRecordsFound] {
+            assert_eq!(glucose.check_racp_write(&gatt, &[0x02, 0x01]), Ok(()));
+            let mut procedure = pin!(glucose.process_racp(&gatt, &[0x02, 0x01], &mut records));
+            assert!(procedure.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(
+                sent(mgr),
+                [value_pdu(
+                    ATT_HANDLE_VALUE_IND,
+                    glucose.racp.handle,
+                    &[0x06, 0x00, 0x02, code as u8]
+                )]
+            );
+            confirm(mgr, &gatt);
+            assert_eq!(procedure.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        }
+        assert_eq!(records.count(RacpOperator::All), 0);
+    }
+
+    #[test]
+    fn racp_responses() {
+        assert_eq!(
+            racp_response(RACP_REPORT_STORED_RECORDS, RacpResponseCode::NoRecordsFound),
+            [0x06, 0x00, 0x01, 0x06]
+        );
+        assert_eq!(racp_number_response(0x0102), [0x05, 0x00, 0x02, 0x01]);
+    }
+}
@@ -0,0 +1,401 @@
+//! ## Mesh GATT bearer
+//!
+//! Bluetooth Mesh devices that cannot use the advertising bearer exchange mesh messages over GATT
+//! through one of two services: the Mesh Provisioning Service, exposed by unprovisioned devices,
+//! and the Mesh Proxy Service, exposed by provisioned proxy nodes. Both have a Data In
+//! characteristic written by the client with Write Without Response and a Data Out characteristic
+//! notified by the server.
+//!
+//! Mesh messages are carried in Proxy PDUs, which are segmented to fit in the ATT MTU.
+//! [`ProxyPduReassembler`] reassembles the PDUs written to Data In, and
+//! [`MeshGattService::send`] segments PDUs notified on Data Out, so a mesh stack only deals with
+//! complete messages:
+//!
+//! ```rust,ignore
+//! GattEvent::Write(event) if event.handle() == proxy.data_in.handle => {
+//!     let pdu = reassembler.push(event.data());
+//!     event.accept()?.send().await;
+//!     if let Some((message_type, message)) = pdu? {
+//!         mesh.receive(message_type, message);
+//!     }
+//! }
+//! ```
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service};
+use crate::gatt::GattConnection;
+use crate::types::uuid::Uuid;
+use crate::{Error, PacketPool};
+
+/// UUID of the Mesh Provisioning Service.
+pub const MESH_PROVISIONING_SERVICE: Uuid = Uuid::new_short(0x1827);
+/// UUID of the Mesh Provisioning Data In characteristic.
+pub const MESH_PROVISIONING_DATA_IN: Uuid = Uuid::new_short(0x2adb);
+/// UUID of the Mesh Provisioning Data Out characteristic.
+pub const MESH_PROVISIONING_DATA_OUT: Uuid = Uuid::new_short(0x2adc);
+/// UUID of the Mesh Proxy Service.
+pub const MESH_PROXY_SERVICE: Uuid = Uuid::new_short(0x1828);
+/// UUID of the Mesh Proxy Data In characteristic.
+pub const MESH_PROXY_DATA_IN: Uuid = Uuid::new_short(0x2add);
+/// UUID of the Mesh Proxy Data Out characteristic.
+pub const MESH_PROXY_DATA_OUT: Uuid = Uuid::new_short(0x2ade);
+
+/// The number of attributes added by [`MeshGattService::provisioning`] or [`MeshGattService::proxy`]
+/// MESH_SERVICE:     1
+/// ├── DATA_IN:      2
+/// └── DATA_OUT:     3
+///                 ---
+///                 = 6
+pub const MESH_GATT_SERVICE_ATTRIBUTE_COUNT: usize = 6;
+
+const SAR_COMPLETE: u8 = 0b00;
+const SAR_FIRST: u8 = 0b01;
+const SAR_CONTINUATION: u8 = 0b10;
+const SAR_LAST: u8 = 0b11;
+
+/// Type of the message carried in a Proxy PDU.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ProxyMessageType {
+    /// Network PDU.
+    NetworkPdu = 0x00,
+    /// Mesh beacon.
+    MeshBeacon = 0x01,
+    /// Proxy configuration message.
+    ProxyConfiguration = 0x02,
+    /// Provisioning PDU.
+    ProvisioningPdu = 0x03,
+}
+
+impl TryFrom<u8> for ProxyMessageType {
+    type Error = ProxyPduError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(Self::NetworkPdu),
+            0x01 => Ok(Self::MeshBeacon),
+            0x02 => Ok(Self::ProxyConfiguration),
+            0x03 => Ok(Self::ProvisioningPdu),
+            _ => Err(ProxyPduError::UnknownMessageType),
+        }
+    }
+}
+
+/// Errors when reassembling Proxy PDUs.
+///
+/// The Mesh Profile requires the proxy connection to be closed when any of these happen.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyPduError {
+    /// The PDU was empty.
+    Empty,
+    /// The message type is reserved.
+    UnknownMessageType,
+    /// A continuation or last segment was received without a first segment, or a first or
+    /// complete PDU was received while a message was being reassembled.
+    UnexpectedSegment,
+    /// A segment had a different message type than the first segment.
+    MessageTypeMismatch,
+    /// The reassembled message does not fit in the buffer.
+    MessageTooLong,
+}
+
+fn header(sar: u8, message_type: ProxyMessageType) -> u8 {
+    (sar << 6) | message_type as u8
+}
+
+/// Reassembles Proxy PDUs into mesh messages of up to `N` bytes.
+///
+/// A message that is not completed within 20 seconds must be discarded by calling
+/// [`reset`](Self::reset), and the connection closed.
+pub struct ProxyPduReassembler<const N: usize> {
+    buffer: Vec<u8, N>,
+    message_type: Option<ProxyMessageType>,
+}
+
+impl<const N: usize> Default for ProxyPduReassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ProxyPduReassembler<N> {
+    /// Create an idle reassembler.
+    pub const fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            message_type: None,
+        }
+    }
+
+    /// Whether a segmented message is being reassembled.
+    pub fn in_progress(&self) -> bool {
+        self.message_type.is_some()
+    }
+
+    /// Discard any partially reassembled message.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.message_type = None;
+    }
+
+    /// Process a Proxy PDU written to the Data In characteristic.
+    ///
+    /// Returns the message type and message once the last segment is received. On error, the
+    /// partially reassembled message is discarded.
+    pub fn push(&mut self, pdu: &[u8]) -> Result<Option<(ProxyMessageType, &[u8])>, ProxyPduError> {
+        let result = self.process(pdu);
+        if result.is_err() {
+            self.reset();
+        }
+        if result? {
+            Ok(Some((unwrap!(self.message_type.take()), &self.buffer)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn process(&mut self, pdu: &[u8]) -> Result<bool, ProxyPduError> {
+        let (&header, data) = pdu.split_first().ok_or(ProxyPduError::Empty)?;
+        let message_type = ProxyMessageType::try_from(header & 0x3f)?;
+        let sar = header >> 6;
+        match (sar, self.message_type) {
+            (SAR_COMPLETE | SAR_FIRST, Some(_)) | (SAR_CONTINUATION | SAR_LAST, None) => {
+                return Err(ProxyPduError::UnexpectedSegment);
+            }
+            (SAR_COMPLETE | SAR_FIRST, None) => self.buffer.clear(),
+            (_, Some(current)) if current != message_type => return Err(ProxyPduError::MessageTypeMismatch),
+            _ => {}
+        }
+        self.buffer
+            .extend_from_slice(data)
+            .map_err(|_| ProxyPduError::MessageTooLong)?;
+        self.message_type = Some(message_type);
+        Ok(sar == SAR_COMPLETE || sar == SAR_LAST)
+    }
+}
+
+/// Iterator over the Proxy PDUs of a segmented message, see [`segments`].
+pub struct ProxyPduSegments<'a> {
+    message_type: ProxyMessageType,
+    data: &'a [u8],
+    max_data: usize,
+    first: bool,
+}
+
+/// Segment a message into Proxy PDUs of at most `max_len` bytes, including the header.
+///
+/// Each item is the PDU header followed by the segment data.
+pub fn segments(message_type: ProxyMessageType, message: &[u8], max_len: usize) -> ProxyPduSegments<'_> {
+    ProxyPduSegments {
+        message_type,
+        data: message,
+        max_data: max_len.saturating_sub(1).max(1),
+        first: true,
+    }
+}
+
+impl<'a> Iterator for ProxyPduSegments<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() && !self.first {
+            return None;
+        }
+        let len = self.data.len().min(self.max_data);
+        let (segment, rest) = self.data.split_at(len);
+        let sar = match (self.first, rest.is_empty()) {
+            (true, true) => SAR_COMPLETE,
+            (true, false) => SAR_FIRST,
+            (false, false) => SAR_CONTINUATION,
+            (false, true) => SAR_LAST,
+        };
+        self.first = false;
+        self.data = rest;
+        Some((header(sar, self.message_type), segment))
+    }
+}
+
+/// Attribute value storage for a [`MeshGattService`] with Data In and Data Out values of up to `N`
+/// bytes.
+pub struct MeshGattStorage<const N: usize> {
+    data_in: [u8; N],
+    data_out: [u8; N],
+}
+
+impl<const N: usize> Default for MeshGattStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MeshGattStorage<N> {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            data_in: [0; N],
+            data_out: [0; N],
+        }
+    }
+}
+
+/// Handles of a Mesh Provisioning or Mesh Proxy Service.
+///
+/// `N` bounds the size of a single Proxy PDU, and should be at least the negotiated ATT MTU minus 3.
+pub struct MeshGattService<const N: usize> {
+    /// Data In characteristic, written by the client.
+    pub data_in: Characteristic<Vec<u8, N>>,
+    /// Data Out characteristic, notified to the client.
+    pub data_out: Characteristic<Vec<u8, N>>,
+}
+
+impl<const N: usize> MeshGattService<N> {
+    /// Add the Mesh Provisioning Service to the attribute table.
+    pub fn provisioning<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut MeshGattStorage<N>,
+    ) -> Self {
+        Self::build(
+            table,
+            storage,
+            MESH_PROVISIONING_SERVICE,
+            MESH_PROVISIONING_DATA_IN,
+            MESH_PROVISIONING_DATA_OUT,
+        )
+    }
+
+    /// Add the Mesh Proxy Service to the attribute table.
+    pub fn proxy<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut MeshGattStorage<N>,
+    ) -> Self {
+        Self::build(
+            table,
+            storage,
+            MESH_PROXY_SERVICE,
+            MESH_PROXY_DATA_IN,
+            MESH_PROXY_DATA_OUT,
+        )
+    }
+
+    fn build<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut MeshGattStorage<N>,
+        service: Uuid,
+        data_in: Uuid,
+        data_out: Uuid,
+    ) -> Self {
+        let MeshGattStorage {
+            data_in: data_in_store,
+            data_out: data_out_store,
+        } = storage;
+        let mut service = table.add_service(Service::new(service));
+        let data_in = service
+            .add_characteristic(
+                data_in,
+                &[CharacteristicProp::WriteWithoutResponse],
+                Vec::new(),
+                data_in_store,
+            )
+            .build();
+        let data_out = service
+            .add_characteristic(data_out, &[CharacteristicProp::Notify], Vec::new(), data_out_store)
+            .build();
+        service.build();
+
+        Self { data_in, data_out }
+    }
+
+    /// Segment a message to fit the connection's ATT MTU and notify it on the Data Out characteristic.
+    ///
+    /// If the client has not enabled notifications, only the stored value is updated.
+    pub async fn send<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        message_type: ProxyMessageType,
+        message: &[u8],
+    ) -> Result<(), Error> {
+        let max_len = (connection.raw().att_mtu() as usize - 3).min(N);
+        for (header, segment) in segments(message_type, message, max_len) {
+            let mut pdu: Vec<u8, N> = Vec::new();
+            pdu.push(header).map_err(|_| Error::InsufficientSpace)?;
+            pdu.extend_from_slice(segment).map_err(|_| Error::InsufficientSpace)?;
+            connection.notify_raw(self.data_out.handle, &pdu).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassemble_complete() {
+        let mut reassembler: ProxyPduReassembler<32> = ProxyPduReassembler::new();
+        let (message_type, message) = unwrap!(unwrap!(reassembler.push(&[0x03, 0x00, 0x05])));
+        assert_eq!(message_type, ProxyMessageType::ProvisioningPdu);
+        assert_eq!(message, &[0x00, 0x05]);
+        assert!(!reassembler.in_progress());
+    }
+
+    #[test]
+    fn reassemble_segments() {
+        let mut reassembler: ProxyPduReassembler<32> = ProxyPduReassembler::new();
+        assert_eq!(reassembler.push(&[0x40, 1, 2]), Ok(None));
+        assert!(reassembler.in_progress());
+        assert_eq!(reassembler.push(&[0x80, 3, 4]), Ok(None));
+        let (message_type, message) = unwrap!(unwrap!(reassembler.push(&[0xc0, 5])));
+        assert_eq!(message_type, ProxyMessageType::NetworkPdu);
+        assert_eq!(message, &[1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn reassemble_errors() {
+        let mut reassembler: ProxyPduReassembler<4> = ProxyPduReassembler::new();
+        assert_eq!(reassembler.push(&[]), Err(ProxyPduError::Empty));
+        assert_eq!(reassembler.push(&[0x04]), Err(ProxyPduError::UnknownMessageType));
+        assert_eq!(reassembler.push(&[0x80, 1]), Err(ProxyPduError::UnexpectedSegment));
+
+        assert_eq!(reassembler.push(&[0x41, 1]), Ok(None));
+        assert_eq!(reassembler.push(&[0x01, 1]), Err(ProxyPduError::UnexpectedSegment));
+        assert!(!reassembler.in_progress());
+
+        assert_eq!(reassembler.push(&[0x41, 1]), Ok(None));
+        assert_eq!(reassembler.push(&[0xc2, 1]), Err(ProxyPduError::MessageTypeMismatch));
+
+        assert_eq!(reassembler.push(&[0x40, 1, 2, 3]), Ok(None));
+        assert_eq!(reassembler.push(&[0xc0, 4, 5]), Err(ProxyPduError::MessageTooLong));
+        assert!(!reassembler.in_progress());
+    }
+
+    #[test]
+    fn segment_message() {
+        let mut it = segments(ProxyMessageType::ProxyConfiguration, &[1, 2, 3, 4, 5], 3);
+        assert_eq!(it.next(), Some((0x42, &[1, 2][..])));
+        assert_eq!(it.next(), Some((0x82, &[3, 4][..])));
+        assert_eq!(it.next(), Some((0xc2, &[5][..])));
+        assert_eq!(it.next(), None);
+
+        let mut it = segments(ProxyMessageType::NetworkPdu, &[1, 2], 20);
+        assert_eq!(it.next(), Some((0x00, &[1, 2][..])));
+        assert_eq!(it.next(), None);
+
+        let mut reassembler: ProxyPduReassembler<8> = ProxyPduReassembler::new();
+        let mut result = None;
+        for (header, segment) in segments(ProxyMessageType::MeshBeacon, &[9, 8, 7, 6, 5], 2) {
+            let mut pdu: Vec<u8, 2> = Vec::new();
+            unwrap!(pdu.push(header).ok());
+            unwrap!(pdu.extend_from_slice(segment).ok());
+            if let Some((message_type, message)) = unwrap!(reassembler.push(&pdu)) {
+                result = Some((message_type, unwrap!(Vec::<u8, 8>::from_slice(message).ok())));
+            }
+        }
+        let (message_type, message) = unwrap!(result);
+        assert_eq!(message_type, ProxyMessageType::MeshBeacon);
+        assert_eq!(message.as_slice(), &[9, 8, 7, 6, 5]);
+    }
+}
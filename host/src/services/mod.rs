@@ -0,0 +1,37 @@
+//! Helpers for well-known GATT services and profiles.
+
+pub mod ams;
+pub mod ancs;
+pub mod automation_io;
+pub mod body_composition;
+pub mod glucose;
+pub mod mesh;
+pub mod weight_scale;
+
+/// Date and time, as defined by the Date Time characteristic and used in measurement time stamps.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(missing_docs)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl DateTime {
+    pub(crate) fn to_bytes(self) -> [u8; 7] {
+        let year = self.year.to_le_bytes();
+        [
+            year[0],
+            year[1],
+            self.month,
+            self.day,
+            self.hours,
+            self.minutes,
+            self.seconds,
+        ]
+    }
+}
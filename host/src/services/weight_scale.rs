@@ -0,0 +1,254 @@
+//! ## Weight Scale Service
+//!
+//! The Weight Scale Service (WSS) exposes weight measurements, optionally with a time stamp, the
+//! user they belong to, and the body mass index and height of that user. Measurements are
+//! delivered to the collector as indications on the Weight Measurement characteristic.
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+
+use super::DateTime;
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, Service};
+use crate::gatt::GattConnection;
+use crate::types::uuid::Uuid;
+use crate::{Error, PacketPool};
+
+/// UUID of the Weight Scale Service.
+pub const WEIGHT_SCALE_SERVICE: Uuid = Uuid::new_short(0x181d);
+/// UUID of the Weight Measurement characteristic.
+pub const WEIGHT_MEASUREMENT: Uuid = Uuid::new_short(0x2a9d);
+/// UUID of the Weight Scale Feature characteristic.
+pub const WEIGHT_SCALE_FEATURE: Uuid = Uuid::new_short(0x2a9e);
+
+/// The number of attributes added by [`WeightScaleService::build`]
+/// WEIGHT_SCALE_SERVICE:     1
+/// ├── WEIGHT_MEASUREMENT:   3
+/// └── WEIGHT_SCALE_FEATURE: 2
+///                         ---
+///                         = 6
+pub const WEIGHT_SCALE_SERVICE_ATTRIBUTE_COUNT: usize = 6;
+
+/// Maximum length of an encoded Weight Measurement.
+pub const WEIGHT_MEASUREMENT_MAX_LEN: usize = 15;
+
+/// Weight value indicating that the measurement was unsuccessful.
+pub const WEIGHT_MEASUREMENT_UNSUCCESSFUL: u16 = 0xffff;
+
+const FLAG_IMPERIAL: u8 = 0x01;
+const FLAG_TIME_STAMP_PRESENT: u8 = 0x02;
+const FLAG_USER_ID_PRESENT: u8 = 0x04;
+const FLAG_BMI_AND_HEIGHT_PRESENT: u8 = 0x08;
+
+/// Unit system of a measurement.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeasurementUnits {
+    /// Kilograms and meters.
+    #[default]
+    Si,
+    /// Pounds and inches.
+    Imperial,
+}
+
+/// Body mass index and height of the user a weight measurement belongs to.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BmiAndHeight {
+    /// Body mass index, with a resolution of 0.1 kg/m².
+    pub bmi: u16,
+    /// Height, with a resolution of 0.001 m or 0.1 inch depending on the units.
+    pub height: u16,
+}
+
+/// A weight measurement.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeightMeasurement {
+    /// Unit system of the weight and height.
+    pub units: MeasurementUnits,
+    /// Weight, with a resolution of 0.005 kg or 0.01 lb depending on the units.
+    ///
+    /// Use [`WEIGHT_MEASUREMENT_UNSUCCESSFUL`] if the measurement failed.
+    pub weight: u16,
+    /// Time of the measurement.
+    pub time_stamp: Option<DateTime>,
+    /// Index of the user the measurement belongs to, `0xff` for an unknown user.
+    pub user_id: Option<u8>,
+    /// Body mass index and height of the user.
+    pub bmi_and_height: Option<BmiAndHeight>,
+}
+
+impl WeightMeasurement {
+    /// Create a measurement with the given weight, without optional fields.
+    pub const fn new(units: MeasurementUnits, weight: u16) -> Self {
+        Self {
+            units,
+            weight,
+            time_stamp: None,
+            user_id: None,
+            bmi_and_height: None,
+        }
+    }
+
+    /// Encode the measurement as a Weight Measurement characteristic value.
+    pub fn encode(&self) -> Vec<u8, WEIGHT_MEASUREMENT_MAX_LEN> {
+        let mut flags = 0;
+        let mut out: Vec<u8, WEIGHT_MEASUREMENT_MAX_LEN> = Vec::new();
+        // The buffer is sized for a measurement with every optional field present.
+        let mut put = |bytes: &[u8]| unwrap!(out.extend_from_slice(bytes));
+        put(&[0]);
+        put(&self.weight.to_le_bytes());
+        if self.units == MeasurementUnits::Imperial {
+            flags |= FLAG_IMPERIAL;
+        }
+        if let Some(time_stamp) = self.time_stamp {
+            flags |= FLAG_TIME_STAMP_PRESENT;
+            put(&time_stamp.to_bytes());
+        }
+        if let Some(user_id) = self.user_id {
+            flags |= FLAG_USER_ID_PRESENT;
+            put(&[user_id]);
+        }
+        if let Some(BmiAndHeight { bmi, height }) = self.bmi_and_height {
+            flags |= FLAG_BMI_AND_HEIGHT_PRESENT;
+            put(&bmi.to_le_bytes());
+            put(&height.to_le_bytes());
+        }
+        out[0] = flags;
+        out
+    }
+}
+
+/// Value of the Weight Scale Feature characteristic.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WeightScaleFeatures(pub u32);
+
+impl WeightScaleFeatures {
+    /// Measurements include a time stamp.
+    pub const TIME_STAMP: Self = Self(1 << 0);
+    /// The scale supports multiple users.
+    pub const MULTIPLE_USERS: Self = Self(1 << 1);
+    /// Measurements include the body mass index and height.
+    pub const BMI: Self = Self(1 << 2);
+
+    /// Combine two feature sets.
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Set the weight measurement resolution, from 1 (0.5 kg) to 7 (0.005 kg); 0 if unspecified.
+    pub const fn with_weight_resolution(self, resolution: u8) -> Self {
+        Self((self.0 & !(0xf << 3)) | (((resolution & 0xf) as u32) << 3))
+    }
+
+    /// Set the height measurement resolution, from 1 (0.01 m) to 3 (0.001 m); 0 if unspecified.
+    pub const fn with_height_resolution(self, resolution: u8) -> Self {
+        Self((self.0 & !(0x7 << 7)) | (((resolution & 0x7) as u32) << 7))
+    }
+}
+
+/// Attribute value storage for a [`WeightScaleService`].
+pub struct WeightScaleStorage {
+    measurement: [u8; WEIGHT_MEASUREMENT_MAX_LEN],
+    feature: [u8; 4],
+}
+
+impl Default for WeightScaleStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeightScaleStorage {
+    /// Create zeroed storage.
+    pub const fn new() -> Self {
+        Self {
+            measurement: [0; WEIGHT_MEASUREMENT_MAX_LEN],
+            feature: [0; 4],
+        }
+    }
+}
+
+/// Handles of the Weight Scale Service.
+pub struct WeightScaleService {
+    /// Weight Measurement characteristic.
+    pub measurement: Characteristic<Vec<u8, WEIGHT_MEASUREMENT_MAX_LEN>>,
+    /// Weight Scale Feature characteristic.
+    pub feature: Characteristic<u32>,
+}
+
+impl WeightScaleService {
+    /// Add the Weight Scale Service to the attribute table.
+    pub fn build<'d, M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'d, M, MAX>,
+        storage: &'d mut WeightScaleStorage,
+        features: WeightScaleFeatures,
+    ) -> Self {
+        let WeightScaleStorage { measurement, feature } = storage;
+        let mut service = table.add_service(Service::new(WEIGHT_SCALE_SERVICE));
+        let measurement = service
+            .add_characteristic(
+                WEIGHT_MEASUREMENT,
+                &[CharacteristicProp::Indicate],
+                Vec::new(),
+                measurement,
+            )
+            .build();
+        let feature = service
+            .add_characteristic(WEIGHT_SCALE_FEATURE, &[CharacteristicProp::Read], features.0, feature)
+            .build();
+        service.build();
+
+        Self { measurement, feature }
+    }
+
+    /// Indicate a measurement to the client.
+    ///
+    /// If the client has not enabled indications, only the stored value is updated.
+    pub async fn indicate<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        measurement: &WeightMeasurement,
+    ) -> Result<(), Error> {
+        connection
+            .indicate_raw(self.measurement.handle, &measurement.encode())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_measurement() {
+        let m = WeightMeasurement::new(MeasurementUnits::Si, 14_000);
+        assert_eq!(m.encode().as_slice(), &[0x00, 0xb0, 0x36]);
+
+        let mut m = WeightMeasurement::new(MeasurementUnits::Imperial, 15_432);
+        m.time_stamp = Some(DateTime {
+            year: 2024,
+            month: 1,
+            day: 2,
+            hours: 3,
+            minutes: 4,
+            seconds: 5,
+        });
+        m.user_id = Some(1);
+        m.bmi_and_height = Some(BmiAndHeight { bmi: 235, height: 700 });
+        assert_eq!(
+            m.encode().as_slice(),
+            &[0x0f, 0x48, 0x3c, 0xe8, 0x07, 1, 2, 3, 4, 5, 0x01, 0xeb, 0x00, 0xbc, 0x02]
+        );
+    }
+
+    #[test]
+    fn features() {
+        let features = WeightScaleFeatures::TIME_STAMP
+            .with(WeightScaleFeatures::BMI)
+            .with_weight_resolution(7)
+            .with_height_resolution(3);
+        assert_eq!(features.0, 0x0000_01bd);
+    }
+}
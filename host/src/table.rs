@@ -0,0 +1,104 @@
+//! Storage for the connection and channel tables.
+//!
+//! By default the tables are the fixed size slices provided by [`HostResources`](crate::HostResources). With the
+//! `alloc` feature, the tables can also live on the heap and grow on demand up to a limit set at runtime.
+use core::ops::{Deref, DerefMut};
+
+#[cfg(any(feature = "alloc", test))]
+use alloc::vec::Vec;
+
+/// A table of connection or channel slots.
+pub(crate) enum Table<'d, T> {
+    /// A table of fixed size.
+    Fixed(&'d mut [T]),
+    /// A heap allocated table that can grow up to `max` entries.
+    #[cfg(any(feature = "alloc", test))]
+    Growable { entries: Vec<T>, max: usize },
+}
+
+impl<'d, T> Table<'d, T> {
+    /// Create a heap allocated table with `initial` entries that can grow up to `max` entries.
+    #[cfg(any(feature = "alloc", test))]
+    pub(crate) fn growable(initial: usize, max: usize, new: impl Fn() -> T) -> Self {
+        let max = max.max(initial);
+        let mut entries = Vec::with_capacity(initial);
+        entries.resize_with(initial, new);
+        Self::Growable { entries, max }
+    }
+
+    /// Maximum number of entries the table can hold.
+    pub(crate) fn max_len(&self) -> usize {
+        match self {
+            Self::Fixed(entries) => entries.len(),
+            #[cfg(any(feature = "alloc", test))]
+            Self::Growable { max, .. } => *max,
+        }
+    }
+
+    /// Add a new entry to the table, returning its index, or `None` if the table cannot grow.
+    pub(crate) fn grow(&mut self, new: impl FnOnce() -> T) -> Option<usize> {
+        match self {
+            Self::Fixed(_) => None,
+            #[cfg(any(feature = "alloc", test))]
+            Self::Growable { entries, max } => {
+                if entries.len() >= *max {
+                    return None;
+                }
+                entries.push(new());
+                Some(entries.len() - 1)
+            }
+        }
+    }
+}
+
+impl<'d, T> From<&'d mut [T]> for Table<'d, T> {
+    fn from(entries: &'d mut [T]) -> Self {
+        Self::Fixed(entries)
+    }
+}
+
+impl<T> Deref for Table<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Self::Fixed(entries) => entries,
+            #[cfg(any(feature = "alloc", test))]
+            Self::Growable { entries, .. } => entries,
+        }
+    }
+}
+
+impl<T> DerefMut for Table<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match self {
+            Self::Fixed(entries) => entries,
+            #[cfg(any(feature = "alloc", test))]
+            Self::Growable { entries, .. } => entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grow_up_to_max() {
+        let mut table = Table::growable(1, 3, || 0u8);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.max_len(), 3);
+        assert_eq!(table.grow(|| 1), Some(1));
+        assert_eq!(table.grow(|| 2), Some(2));
+        assert_eq!(table.grow(|| 3), None);
+        assert_eq!(&table[..], &[0, 1, 2]);
+    }
+
+    #[test]
+    fn fixed_does_not_grow() {
+        let mut entries = [0u8; 2];
+        let mut table = Table::from(&mut entries[..]);
+        assert_eq!(table.grow(|| 1), None);
+        assert_eq!(table.len(), 2);
+    }
+}
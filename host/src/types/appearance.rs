@@ -0,0 +1,234 @@
+//! Typed GAP Appearance values.
+//!
+//! An appearance is a 16-bit value made of a 10-bit category and a 6-bit subcategory, as listed in
+//! `assigned_numbers/core/appearance_values.yaml` from <https://www.bluetooth.com/specifications/assigned-numbers/>.
+//! The same value is exposed through the GAP Appearance characteristic and the Appearance AD type.
+
+use bt_hci::uuid::BluetoothUuid16;
+
+macro_rules! appearance_categories {
+    ($($(#[$meta:meta])* $variant:ident = $value:literal => $generic:ident,)*) => {
+        /// Appearance categories (the upper 10 bits of an [`Appearance`]).
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        #[allow(missing_docs)]
+        pub enum AppearanceCategory {
+            $($(#[$meta])* $variant = $value,)*
+        }
+
+        impl AppearanceCategory {
+            /// Look up a category from its assigned number.
+            pub const fn from_u16(value: u16) -> Option<Self> {
+                match value {
+                    $($value => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl Appearance {
+            $(
+                #[doc = concat!("Generic appearance of the [`AppearanceCategory::", stringify!($variant), "`] category.")]
+                pub const $generic: Appearance = Appearance::new(AppearanceCategory::$variant, 0);
+            )*
+        }
+    };
+}
+
+appearance_categories! {
+    Unknown = 0x000 => UNKNOWN,
+    Phone = 0x001 => GENERIC_PHONE,
+    Computer = 0x002 => GENERIC_COMPUTER,
+    Watch = 0x003 => GENERIC_WATCH,
+    Clock = 0x004 => GENERIC_CLOCK,
+    Display = 0x005 => GENERIC_DISPLAY,
+    RemoteControl = 0x006 => GENERIC_REMOTE_CONTROL,
+    EyeGlasses = 0x007 => GENERIC_EYE_GLASSES,
+    Tag = 0x008 => GENERIC_TAG,
+    Keyring = 0x009 => GENERIC_KEYRING,
+    MediaPlayer = 0x00A => GENERIC_MEDIA_PLAYER,
+    BarcodeScanner = 0x00B => GENERIC_BARCODE_SCANNER,
+    Thermometer = 0x00C => GENERIC_THERMOMETER,
+    HeartRateSensor = 0x00D => GENERIC_HEART_RATE_SENSOR,
+    BloodPressure = 0x00E => GENERIC_BLOOD_PRESSURE,
+    HumanInterfaceDevice = 0x00F => GENERIC_HUMAN_INTERFACE_DEVICE,
+    GlucoseMeter = 0x010 => GENERIC_GLUCOSE_METER,
+    RunningWalkingSensor = 0x011 => GENERIC_RUNNING_WALKING_SENSOR,
+    Cycling = 0x012 => GENERIC_CYCLING,
+    ControlDevice = 0x013 => GENERIC_CONTROL_DEVICE,
+    NetworkDevice = 0x014 => GENERIC_NETWORK_DEVICE,
+    Sensor = 0x015 => GENERIC_SENSOR,
+    LightFixtures = 0x016 => GENERIC_LIGHT_FIXTURES,
+    Fan = 0x017 => GENERIC_FAN,
+    Hvac = 0x018 => GENERIC_HVAC,
+    AirConditioning = 0x019 => GENERIC_AIR_CONDITIONING,
+    Humidifier = 0x01A => GENERIC_HUMIDIFIER,
+    Heating = 0x01B => GENERIC_HEATING,
+    AccessControl = 0x01C => GENERIC_ACCESS_CONTROL,
+    MotorizedDevice = 0x01D => GENERIC_MOTORIZED_DEVICE,
+    PowerDevice = 0x01E => GENERIC_POWER_DEVICE,
+    LightSource = 0x01F => GENERIC_LIGHT_SOURCE,
+    WindowCovering = 0x020 => GENERIC_WINDOW_COVERING,
+    AudioSink = 0x021 => GENERIC_AUDIO_SINK,
+    AudioSource = 0x022 => GENERIC_AUDIO_SOURCE,
+    MotorizedVehicle = 0x023 => GENERIC_MOTORIZED_VEHICLE,
+    DomesticAppliance = 0x024 => GENERIC_DOMESTIC_APPLIANCE,
+    WearableAudioDevice = 0x025 => GENERIC_WEARABLE_AUDIO_DEVICE,
+    Aircraft = 0x026 => GENERIC_AIRCRAFT,
+    AvEquipment = 0x027 => GENERIC_AV_EQUIPMENT,
+    DisplayEquipment = 0x028 => GENERIC_DISPLAY_EQUIPMENT,
+    HearingAid = 0x029 => GENERIC_HEARING_AID,
+    Gaming = 0x02A => GENERIC_GAMING,
+    Signage = 0x02B => GENERIC_SIGNAGE,
+    PulseOximeter = 0x031 => GENERIC_PULSE_OXIMETER,
+    WeightScale = 0x032 => GENERIC_WEIGHT_SCALE,
+    PersonalMobilityDevice = 0x033 => GENERIC_PERSONAL_MOBILITY_DEVICE,
+    ContinuousGlucoseMonitor = 0x034 => GENERIC_CONTINUOUS_GLUCOSE_MONITOR,
+    InsulinPump = 0x035 => GENERIC_INSULIN_PUMP,
+    MedicationDelivery = 0x036 => GENERIC_MEDICATION_DELIVERY,
+    Spirometer = 0x037 => GENERIC_SPIROMETER,
+    OutdoorSportsActivity = 0x051 => GENERIC_OUTDOOR_SPORTS_ACTIVITY,
+}
+
+/// External appearance of a device, as exposed by the GAP Appearance characteristic and the
+/// Appearance AD type.
+///
+/// Values that are not covered by the associated constants can be built from a category and
+/// subcategory with [`Appearance::new`], or converted from the `bt_hci` appearance constants.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Appearance(u16);
+
+impl Appearance {
+    /// Sports watch.
+    pub const SPORTS_WATCH: Appearance = Appearance::new(AppearanceCategory::Watch, 0x01);
+    /// Smartwatch.
+    pub const SMARTWATCH: Appearance = Appearance::new(AppearanceCategory::Watch, 0x02);
+    /// Ear thermometer.
+    pub const EAR_THERMOMETER: Appearance = Appearance::new(AppearanceCategory::Thermometer, 0x01);
+    /// Heart rate belt.
+    pub const HEART_RATE_BELT: Appearance = Appearance::new(AppearanceCategory::HeartRateSensor, 0x01);
+    /// Keyboard.
+    pub const KEYBOARD: Appearance = Appearance::new(AppearanceCategory::HumanInterfaceDevice, 0x01);
+    /// Mouse.
+    pub const MOUSE: Appearance = Appearance::new(AppearanceCategory::HumanInterfaceDevice, 0x02);
+    /// Joystick.
+    pub const JOYSTICK: Appearance = Appearance::new(AppearanceCategory::HumanInterfaceDevice, 0x03);
+    /// Gamepad.
+    pub const GAMEPAD: Appearance = Appearance::new(AppearanceCategory::HumanInterfaceDevice, 0x04);
+    /// In-shoe running/walking sensor.
+    pub const IN_SHOE_RUNNING_WALKING_SENSOR: Appearance =
+        Appearance::new(AppearanceCategory::RunningWalkingSensor, 0x01);
+    /// Cycling computer.
+    pub const CYCLING_COMPUTER: Appearance = Appearance::new(AppearanceCategory::Cycling, 0x01);
+    /// Cycling speed sensor.
+    pub const CYCLING_SPEED_SENSOR: Appearance = Appearance::new(AppearanceCategory::Cycling, 0x02);
+    /// Cycling cadence sensor.
+    pub const CYCLING_CADENCE_SENSOR: Appearance = Appearance::new(AppearanceCategory::Cycling, 0x03);
+    /// Cycling power sensor.
+    pub const CYCLING_POWER_SENSOR: Appearance = Appearance::new(AppearanceCategory::Cycling, 0x04);
+    /// Cycling speed and cadence sensor.
+    pub const CYCLING_SPEED_AND_CADENCE_SENSOR: Appearance = Appearance::new(AppearanceCategory::Cycling, 0x05);
+
+    /// Create an appearance from a category and a subcategory.
+    ///
+    /// Only the lower 6 bits of `subcategory` are used.
+    pub const fn new(category: AppearanceCategory, subcategory: u8) -> Self {
+        Self(((category as u16) << 6) | (subcategory as u16 & 0x3f))
+    }
+
+    /// Create an appearance from its raw 16-bit value.
+    pub const fn from_u16(value: u16) -> Self {
+        Self(value)
+    }
+
+    /// The raw 16-bit value.
+    pub const fn to_u16(self) -> u16 {
+        self.0
+    }
+
+    /// The category, if it is one known to this crate.
+    pub const fn category(self) -> Option<AppearanceCategory> {
+        AppearanceCategory::from_u16(self.0 >> 6)
+    }
+
+    /// The subcategory, where 0 means generic.
+    pub const fn subcategory(self) -> u8 {
+        (self.0 & 0x3f) as u8
+    }
+
+    /// Little endian encoding as used over the air.
+    pub const fn to_le_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self::UNKNOWN
+    }
+}
+
+impl From<AppearanceCategory> for Appearance {
+    fn from(category: AppearanceCategory) -> Self {
+        Self::new(category, 0)
+    }
+}
+
+impl From<u16> for Appearance {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Appearance> for u16 {
+    fn from(value: Appearance) -> Self {
+        value.0
+    }
+}
+
+impl From<BluetoothUuid16> for Appearance {
+    fn from(value: BluetoothUuid16) -> Self {
+        Self(u16::from_le_bytes(value.into()))
+    }
+}
+
+impl From<Appearance> for BluetoothUuid16 {
+    fn from(value: Appearance) -> Self {
+        BluetoothUuid16::new(value.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn category_and_subcategory() {
+        assert_eq!(Appearance::UNKNOWN.to_u16(), 0x0000);
+        assert_eq!(Appearance::GENERIC_POWER_DEVICE.to_u16(), 0x0780);
+        assert_eq!(Appearance::HEART_RATE_BELT.to_u16(), 0x0341);
+        assert_eq!(Appearance::KEYBOARD.to_u16(), 0x03c1);
+
+        let appearance = Appearance::from_u16(0x0485);
+        assert_eq!(appearance.category(), Some(AppearanceCategory::Cycling));
+        assert_eq!(appearance.subcategory(), 5);
+        assert_eq!(appearance, Appearance::CYCLING_SPEED_AND_CADENCE_SENSOR);
+
+        assert_eq!(Appearance::from_u16(0xffc0).category(), None);
+    }
+
+    #[test]
+    fn bt_hci_constants() {
+        let appearance = Appearance::from(bt_hci::uuid::appearance::power_device::GENERIC_POWER_DEVICE);
+        assert_eq!(appearance, Appearance::GENERIC_POWER_DEVICE);
+        assert_eq!(
+            BluetoothUuid16::from(Appearance::GENERIC_SENSOR),
+            bt_hci::uuid::appearance::sensor::GENERIC_SENSOR
+        );
+    }
+}
@@ -3,14 +3,19 @@ use core::{mem, slice};
 use bt_hci::uuid::BluetoothUuid16;
 use heapless::{String, Vec};
 
+use crate::types::appearance::Appearance;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Error type to signify an issue when converting from GATT bytes to a concrete type
+#[non_exhaustive]
 pub enum FromGattError {
     /// Byte array's length did not match what was expected for the converted type
     InvalidLength,
     /// Attempt to encode as string failed due to an invalid character representation in the byte array
     InvalidCharacter,
+    /// Byte array did not hold any of the values of the converted type
+    InvalidValue,
 }
 
 /// Trait to allow conversion of a fixed size type to and from a byte slice
@@ -63,7 +68,7 @@ impl<T: FixedGattValue> AsGatt for T {
     }
 }
 
-trait Primitive: Copy {}
+pub(crate) trait Primitive: Copy {}
 impl Primitive for u8 {}
 impl Primitive for u16 {}
 impl Primitive for u32 {}
@@ -75,6 +80,35 @@ impl Primitive for i64 {}
 impl Primitive for f32 {}
 impl Primitive for f64 {}
 impl Primitive for BluetoothUuid16 {} // ok as this is just a NewType(u16)
+impl Primitive for Appearance {} // ok as this is just a NewType(u16)
+
+/// Numbers with an explicit little-endian encoding, as used by descriptors holding several of them.
+pub(crate) trait LeBytes: Primitive {
+    /// Write the number into the first `size_of::<Self>()` bytes of `out`.
+    fn write_le(self, out: &mut [u8]);
+    /// Read the number from exactly `size_of::<Self>()` bytes.
+    fn read_le(data: &[u8]) -> Self;
+}
+
+macro_rules! impl_le_bytes {
+    ($($ty:ty),*) => {
+        $(
+            impl LeBytes for $ty {
+                fn write_le(self, out: &mut [u8]) {
+                    out[..mem::size_of::<Self>()].copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(data: &[u8]) -> Self {
+                    let mut bytes = [0; mem::size_of::<Self>()];
+                    bytes.copy_from_slice(data);
+                    Self::from_le_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_le_bytes!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
 
 impl<T: Primitive> FixedGattValue for T {
     const SIZE: usize = mem::size_of::<Self>();
@@ -156,7 +190,7 @@ impl<const N: usize> AsGatt for [u8; N] {
 
 impl<const N: usize> FromGatt for String<N> {
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        String::from_utf8(unwrap!(Vec::from_slice(data).map_err(|_| FromGattError::InvalidLength)))
+        String::from_utf8(Vec::from_slice(data).map_err(|_| FromGattError::InvalidLength)?)
             .map_err(|_| FromGattError::InvalidCharacter)
     }
 }
@@ -202,3 +236,17 @@ impl FromGatt for crate::types::uuid::Uuid {
         Self::try_from(data).map_err(|_| FromGattError::InvalidLength)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_from_gatt_too_long() {
+        assert_eq!(
+            <String<4> as FromGatt>::from_gatt(b"too long"),
+            Err(FromGattError::InvalidLength)
+        );
+        assert_eq!(<String<4> as FromGatt>::from_gatt(b"ok").map(|s| s.len()), Ok(2));
+    }
+}
@@ -32,7 +32,8 @@ pub struct L2capSignalHeader {
 
 unsafe impl FixedSizeValue for L2capSignalHeader {
     fn is_valid(data: &[u8]) -> bool {
-        true
+        // The code is read in place as an enum, so reject values without a variant.
+        L2capSignalCode::try_from(data[0]).is_ok()
     }
 }
 
@@ -54,6 +55,7 @@ pub trait L2capSignal: WriteHci + FixedSizeValue + defmt::Format {
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
 pub enum L2capSignalCode {
     CommandRejectRes = 0x01,
     ConnectionReq = 0x02,
@@ -130,7 +132,7 @@ impl L2capSignal for LeCreditConnReq {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u16)]
 pub enum LeCreditConnResultCode {
     Success = 0x0000,
@@ -282,3 +284,122 @@ impl L2capSignal for ConnParamUpdateRes {
         L2capSignalCode::ConnParamUpdateRes
     }
 }
+
+/// Maximum number of channels in one credit based connection request.
+pub(crate) const L2CAP_ECFC_MAX_CHANNELS: usize = 5;
+
+/// Credit based connection request, opening up to five channels in enhanced credit based flow control
+/// mode. The fixed part is followed by the source CID of each channel.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CreditConnReq {
+    pub spsm: u16,
+    pub mtu: u16,
+    pub mps: u16,
+    pub credits: u16,
+}
+
+unsafe impl FixedSizeValue for CreditConnReq {
+    fn is_valid(data: &[u8]) -> bool {
+        true
+    }
+}
+
+impl L2capSignal for CreditConnReq {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReq
+    }
+}
+
+/// Credit based connection response. The fixed part is followed by the destination CID of each requested
+/// channel, or 0 for channels that were refused.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CreditConnRes {
+    pub mtu: u16,
+    pub mps: u16,
+    pub credits: u16,
+    pub result: u16,
+}
+
+unsafe impl FixedSizeValue for CreditConnRes {
+    fn is_valid(data: &[u8]) -> bool {
+        true
+    }
+}
+
+impl L2capSignal for CreditConnRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnRes
+    }
+}
+
+/// Smallest MTU and MPS allowed on a channel in enhanced credit based flow control mode.
+pub(crate) const L2CAP_ECFC_MIN_MTU: u16 = 64;
+
+/// Credit based reconfigure request, changing the MTU and MPS the sender can receive on its channels.
+/// The fixed part is followed by the destination CID of each channel.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CreditConnReconfigReq {
+    pub mtu: u16,
+    pub mps: u16,
+}
+
+unsafe impl FixedSizeValue for CreditConnReconfigReq {
+    fn is_valid(data: &[u8]) -> bool {
+        true
+    }
+}
+
+impl L2capSignal for CreditConnReconfigReq {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReconfigReq
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum CreditConnReconfigResult {
+    Success = 0x0000,
+    MtuReductionNotAllowed = 0x0001,
+    MpsReductionNotAllowed = 0x0002,
+    InvalidDcid = 0x0003,
+    UnacceptableParameters = 0x0004,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CreditConnReconfigRes {
+    pub result: u16,
+}
+
+unsafe impl FixedSizeValue for CreditConnReconfigRes {
+    fn is_valid(data: &[u8]) -> bool {
+        true
+    }
+}
+
+impl L2capSignal for CreditConnReconfigRes {
+    fn code() -> L2capSignalCode {
+        L2capSignalCode::CreditConnReconfigRes
+    }
+}
+
+/// Parse the list of channel identifiers following a credit based signal.
+pub(crate) fn parse_cids(data: &[u8]) -> Result<heapless::Vec<u16, L2CAP_ECFC_MAX_CHANNELS>, Error> {
+    if data.len() % 2 != 0 {
+        return Err(Error::InvalidValue);
+    }
+    let mut cids = heapless::Vec::new();
+    for cid in data.chunks_exact(2) {
+        cids.push(u16::from_le_bytes([cid[0], cid[1]]))
+            .map_err(|_| Error::InvalidValue)?;
+    }
+    Ok(cids)
+}
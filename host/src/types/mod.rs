@@ -1,5 +1,6 @@
 //! Common types.
 
+pub mod appearance;
 /// Traits for conversion between types and their GATT representations
 pub mod gatt_traits;
 pub(crate) mod l2cap;
@@ -7,6 +7,7 @@ use crate::codec::{Decode, Encode, Error, Type};
 /// A 16-bit or 128-bit UUID.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Uuid {
     /// 16-bit UUID
     Uuid16([u8; 2]),
@@ -79,10 +80,68 @@ impl Uuid {
     }
 
     /// Get the 16-bit UUID value.
+    ///
+    /// Panics if this is a 128-bit UUID, see [`Uuid::try_as_short`] for a non-panicking variant.
     pub fn as_short(&self) -> u16 {
+        match self.try_as_short() {
+            Some(value) => value,
+            None => panic!("wrong type"),
+        }
+    }
+
+    /// Get the 16-bit UUID value, or `None` if this is a 128-bit UUID.
+    pub fn try_as_short(&self) -> Option<u16> {
         match self {
-            Uuid::Uuid16(data) => u16::from_le_bytes([data[0], data[1]]),
-            _ => panic!("wrong type"),
+            Uuid::Uuid16(data) => Some(u16::from_le_bytes([data[0], data[1]])),
+            _ => None,
+        }
+    }
+
+    /// Parse a 128-bit UUID from its string form.
+    ///
+    /// Accepts the hyphenated form (`"0000180f-0000-1000-8000-00805f9b34fb"`) or 32 hex digits
+    /// without hyphens. Returns `None` if the string is not a valid UUID.
+    pub const fn try_parse(s: &str) -> Option<Self> {
+        let s = s.as_bytes();
+        let hyphenated = match s.len() {
+            36 => true,
+            32 => false,
+            _ => return None,
+        };
+        let mut out = [0u8; 16];
+        let mut i = 0;
+        let mut n = 0;
+        while i < s.len() {
+            if hyphenated && (i == 8 || i == 13 || i == 18 || i == 23) {
+                if s[i] != b'-' {
+                    return None;
+                }
+                i += 1;
+                continue;
+            }
+            let (Some(hi), Some(lo)) = (hex_value(s[i]), hex_value(s[i + 1])) else {
+                return None;
+            };
+            // Strings are big endian, while UUIDs are stored in little endian byte order.
+            out[15 - n] = (hi << 4) | lo;
+            n += 1;
+            i += 2;
+        }
+        Some(Self::Uuid128(out))
+    }
+
+    /// Parse a 128-bit UUID from its string form, see [`Uuid::try_parse`].
+    ///
+    /// Panics if the string is not a valid UUID, which fails compilation when used in a const context:
+    ///
+    /// ```
+    /// # use trouble_host::types::uuid::Uuid;
+    /// const MY_SERVICE: Uuid = Uuid::parse("a1b2c3d4-0000-1000-8000-00805f9b34fb");
+    /// ```
+    pub const fn parse(s: &str) -> Self {
+        match Self::try_parse(s) {
+            Some(uuid) => uuid,
+            None => panic!("invalid UUID string"),
         }
     }
 
@@ -95,6 +154,56 @@ impl Uuid {
     }
 }
 
+const fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// The Bluetooth Base UUID, `00000000-0000-1000-8000-00805F9B34FB`, that 16-bit UUIDs are shortened from.
+#[cfg(any(test, feature = "uuid"))]
+const BLUETOOTH_BASE_UUID: u128 = 0x00000000_0000_1000_8000_00805F9B34FB;
+
+#[cfg(any(test, feature = "uuid"))]
+impl Uuid {
+    /// Create a UUID from its 128-bit value, using the 16-bit form if it is derived from the Bluetooth Base UUID.
+    fn from_u128_shortened(val: u128) -> Self {
+        let short = (val >> 96) as u32;
+        if val & !(0xFFFF_u128 << 96) == BLUETOOTH_BASE_UUID && short <= 0xFFFF {
+            Uuid::new_short(short as u16)
+        } else {
+            Uuid::from(val)
+        }
+    }
+
+    /// Get the 128-bit value of the UUID, expanding 16-bit UUIDs using the Bluetooth Base UUID.
+    fn to_u128(&self) -> u128 {
+        match self {
+            Uuid::Uuid16(bytes) => BLUETOOTH_BASE_UUID | ((u16::from_le_bytes(*bytes) as u128) << 96),
+            Uuid::Uuid128(bytes) => u128::from_le_bytes(*bytes),
+        }
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for Uuid {
+    /// Convert from a `uuid::Uuid`, using the 16-bit form if it is derived from the Bluetooth Base UUID.
+    fn from(uuid: uuid::Uuid) -> Self {
+        Uuid::from_u128_shortened(uuid.as_u128())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<Uuid> for uuid::Uuid {
+    /// Convert to a `uuid::Uuid`, expanding 16-bit UUIDs using the Bluetooth Base UUID.
+    fn from(uuid: Uuid) -> Self {
+        uuid::Uuid::from_u128(uuid.to_u128())
+    }
+}
+
 impl TryFrom<&[u8]> for Uuid {
     type Error = crate::Error;
 
@@ -143,3 +252,54 @@ impl Encode for Uuid {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uuid_string() {
+        const HYPHENATED: Uuid = Uuid::parse("0000180f-0000-1000-8000-00805f9b34fb");
+        let plain = Uuid::parse("0000180F00001000800000805F9B34FB");
+        assert_eq!(HYPHENATED, plain);
+        assert_eq!(HYPHENATED, Uuid::from(0x0000180f_0000_1000_8000_00805f9b34fb_u128));
+
+        assert!(Uuid::try_parse("0000180f-0000-1000-8000-00805f9b34f").is_none());
+        assert!(Uuid::try_parse("0000180f+0000-1000-8000-00805f9b34fb").is_none());
+        assert!(Uuid::try_parse("0000180g-0000-1000-8000-00805f9b34fb").is_none());
+    }
+
+    #[test]
+    fn bluetooth_base_uuid() {
+        // 16-bit UUIDs are expanded with the Bluetooth Base UUID, and shortened back.
+        let battery = Uuid::new_short(0x180f);
+        let expanded = 0x0000180f_0000_1000_8000_00805f9b34fb_u128;
+        assert_eq!(battery.to_u128(), expanded);
+        assert_eq!(Uuid::from_u128_shortened(expanded), battery);
+
+        // Values outside the 16-bit range of the Base UUID, or not derived from it, stay 128-bit.
+        let long = 0x1234180f_0000_1000_8000_00805f9b34fb_u128;
+        assert_eq!(Uuid::from_u128_shortened(long), Uuid::from(long));
+        let custom = 0x0000180f_0000_1000_8000_00805f9b34fc_u128;
+        assert_eq!(Uuid::from_u128_shortened(custom), Uuid::from(custom));
+        assert_eq!(Uuid::from(custom).to_u128(), custom);
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid_crate_conversions() {
+        let battery = uuid::Uuid::parse_str("0000180f-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(Uuid::from(battery), Uuid::new_short(0x180f));
+        assert_eq!(uuid::Uuid::from(Uuid::new_short(0x180f)), battery);
+
+        let custom = uuid::Uuid::parse_str("a1b2c3d4-0000-1000-8000-00805f9b34fb").unwrap();
+        assert_eq!(Uuid::from(custom), Uuid::parse("a1b2c3d4-0000-1000-8000-00805f9b34fb"));
+        assert_eq!(uuid::Uuid::from(Uuid::from(custom)), custom);
+    }
+
+    #[test]
+    fn try_as_short() {
+        assert_eq!(Uuid::new_short(0x180f).try_as_short(), Some(0x180f));
+        assert_eq!(Uuid::parse("0000180f-0000-1000-8000-00805f9b34fb").try_as_short(), None);
+    }
+}
@@ -2,6 +2,7 @@
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use trouble_host::prelude::*;
+use trouble_host::types::gatt_traits::FromGattError;
 
 #[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b0")]
 struct CustomService {
@@ -17,7 +18,8 @@ struct CustomService {
 #[tokio::test]
 async fn gatt_service_derive() {
     let mut table: AttributeTable<NoopRawMutex, 10> = AttributeTable::new();
-    let service = CustomService::new(&mut table);
+    let mut storage = CustomServiceStorage::new();
+    let service = CustomService::new(&mut table, &mut storage);
 
     // Check all fields of service have been generated and are accessible
     let _handle = service.handle;
@@ -26,3 +28,251 @@ async fn gatt_service_derive() {
     let _characteristic_long_uuid = service.long_uuid;
     let _notify = service.notify;
 }
+
+#[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b1")]
+struct HandlerService {
+    #[characteristic(uuid = "2a37", read, on_read = |_| 7)]
+    closure: u8,
+    #[characteristic(uuid = "2a38", write, on_write = Self::written)]
+    method: u16,
+    #[characteristic(uuid = "2a39", read, write_without_response, on_read = Self::read, on_write = |_, _| {})]
+    both: [u8; 2],
+}
+
+impl HandlerService {
+    fn written(&self, _value: &u16) {}
+
+    fn read(&self) -> [u8; 2] {
+        [1, 2]
+    }
+}
+
+#[gatt_server]
+struct HandlerServer {
+    handlers: HandlerService,
+}
+
+#[tokio::test]
+async fn gatt_service_handlers() {
+    let mut storage = HandlerServerStorage::new();
+    let server = HandlerServer::new_default("handlers", &mut storage).unwrap();
+
+    // Handlers are run through the generated dispatch method
+    let _dispatch = HandlerServer::dispatch;
+    assert_eq!(server.get(&server.handlers.both).unwrap(), [0, 0]);
+}
+
+#[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b0")]
+struct ReliableWriteService {
+    #[characteristic(uuid = "2a3a", read, write, reliable_write)]
+    reliable: u8,
+    #[characteristic(uuid = "2a3b", read, write)]
+    plain: u8,
+}
+
+#[gatt_server]
+struct ReliableWriteServer {
+    reliable: ReliableWriteService,
+}
+
+#[test]
+fn gatt_service_extended_properties() {
+    let mut storage = ReliableWriteServerStorage::new();
+    let server = ReliableWriteServer::new_default("reliable", &mut storage).unwrap();
+    let reliable = &server.reliable.reliable;
+    assert!(reliable.props.contains(CharacteristicProp::Extended));
+    assert!(reliable.extended_props.unwrap().reliable_write());
+    assert!(!reliable.extended_props.unwrap().writable_auxiliaries());
+    assert!(!server.reliable.plain.props.contains(CharacteristicProp::Extended));
+    assert!(server.reliable.plain.extended_props.is_none());
+}
+
+#[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b2")]
+struct DescriptorService {
+    #[characteristic(uuid = "2a6e", read, notify)]
+    #[user_description("Temperature")]
+    #[presentation_format(format = PresentationFormat::FORMAT_SINT16, exponent = -2, unit = 0x272f)]
+    #[valid_range(lower = -4000, upper = 8500)]
+    temperature: i16,
+    #[characteristic(uuid = "2aae", read)]
+    #[presentation_format(format = PresentationFormat::FORMAT_SINT32, exponent = -7, unit = 0x2763)]
+    #[presentation_format(format = PresentationFormat::FORMAT_SINT32, exponent = -7, unit = 0x2763, description = 1)]
+    #[aggregate_format]
+    position: [u8; 8],
+}
+
+#[gatt_server]
+struct DescriptorServer {
+    descriptors: DescriptorService,
+}
+
+#[test]
+fn gatt_service_standard_descriptors() {
+    let mut storage = DescriptorServerStorage::new();
+    let server = DescriptorServer::new_default("descriptors", &mut storage).unwrap();
+    let service = &server.descriptors;
+
+    let _user_description = service.temperature_user_description;
+    let format = server.get(&service.temperature_presentation_format).unwrap();
+    assert_eq!(
+        format,
+        PresentationFormat::new(
+            PresentationFormat::FORMAT_SINT16,
+            -2,
+            0x272f,
+            PresentationFormat::NAMESPACE_BLUETOOTH_SIG,
+            0,
+        )
+    );
+    let range = server.get(&service.temperature_valid_range).unwrap();
+    assert_eq!(range, ValidRange::new(-4000, 8500));
+    assert_eq!((range.lower(), range.upper()), (-4000, 8500));
+
+    let first = service.position_presentation_format_0.handle();
+    let second = service.position_presentation_format_1.handle();
+    let [a, b] = first.to_le_bytes();
+    let [c, d] = second.to_le_bytes();
+    assert_eq!(server.get(&service.position_aggregate_format).unwrap(), [a, b, c, d]);
+}
+
+#[derive(FixedGattValue, Debug, Clone, Copy, Default, PartialEq)]
+#[repr(u8)]
+enum Mode {
+    #[default]
+    Off = 0,
+    On = 1,
+    Auto = 5,
+}
+
+#[derive(FixedGattValue, Debug, Clone, Copy, Default, PartialEq)]
+#[repr(C, packed)]
+struct Setpoint {
+    mode: Mode,
+    temperature: i16,
+}
+
+#[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b3")]
+struct ThermostatService {
+    #[characteristic(uuid = "2a3a", read, write)]
+    mode: Mode,
+    #[characteristic(uuid = "2a3b", read, write)]
+    setpoint: Setpoint,
+}
+
+#[gatt_server]
+struct ThermostatServer {
+    thermostat: ThermostatService,
+}
+
+#[test]
+fn gatt_value_derive() {
+    assert_eq!(<Mode as FixedGattValue>::from_gatt(&[5]), Ok(Mode::Auto));
+    assert_eq!(
+        <Mode as FixedGattValue>::from_gatt(&[2]),
+        Err(FromGattError::InvalidValue)
+    );
+    let setpoint = Setpoint {
+        mode: Mode::On,
+        temperature: -2,
+    };
+    assert_eq!(FixedGattValue::as_gatt(&setpoint), &[0x01, 0xfe, 0xff]);
+    assert_eq!(
+        <Setpoint as FixedGattValue>::from_gatt(&[0x01, 0xfe]),
+        Err(FromGattError::InvalidLength)
+    );
+
+    let mut storage = ThermostatServerStorage::new();
+    let server = ThermostatServer::new_default("thermostat", &mut storage).unwrap();
+    server.set(&server.thermostat.setpoint, &setpoint).unwrap();
+    assert_eq!(server.get(&server.thermostat.setpoint).unwrap(), setpoint);
+    assert_eq!(server.get(&server.thermostat.mode).unwrap(), Mode::Off);
+}
+
+#[test]
+fn gatt_server_instances() {
+    // Each instance of a server keeps its values in its own storage.
+    let mut first_storage = ThermostatServerStorage::new();
+    let mut second_storage = ThermostatServerStorage::new();
+    let first = ThermostatServer::new_default("first", &mut first_storage).unwrap();
+    let second = ThermostatServer::new_default("second", &mut second_storage).unwrap();
+
+    first.set(&first.thermostat.mode, &Mode::On).unwrap();
+    second.set(&second.thermostat.mode, &Mode::Auto).unwrap();
+    assert_eq!(first.get(&first.thermostat.mode).unwrap(), Mode::On);
+    assert_eq!(second.get(&second.thermostat.mode).unwrap(), Mode::Auto);
+}
+
+#[gatt_service(uuid = service::BATTERY, secondary)]
+struct IncludedBatteryService {
+    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, value = 100)]
+    level: u8,
+}
+
+#[gatt_service(uuid = "1812")]
+struct IncludingService {
+    #[characteristic(uuid = "2a4d", read)]
+    report: u8,
+}
+
+#[gatt_server]
+struct IncludeServer {
+    bas: IncludedBatteryService,
+    #[include(bas)]
+    hid: IncludingService,
+}
+
+#[test]
+fn gatt_service_includes() {
+    let mut storage = IncludeServerStorage::new();
+    let server = IncludeServer::new_default("include", &mut storage).unwrap();
+    // The include definition comes right after the including service declaration.
+    assert_eq!(server.hid.report.handle, server.hid.handle() + 3);
+    assert_eq!(server.get(&server.bas.level).unwrap(), 100);
+}
+
+#[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b4", handle = 0x0100)]
+struct FixedHandleService {
+    #[characteristic(uuid = "2a3c", read)]
+    first: u8,
+    #[characteristic(uuid = "2a3d", read, handle = 0x0110)]
+    second: u8,
+}
+
+#[gatt_server]
+struct FixedHandleServer {
+    fixed: FixedHandleService,
+}
+
+#[test]
+fn gatt_service_fixed_handles() {
+    let mut storage = FixedHandleServerStorage::new();
+    let server = FixedHandleServer::new_default("fixed", &mut storage).unwrap();
+    assert_eq!(server.fixed.handle(), 0x0100);
+    assert_eq!(server.fixed.first.handle, 0x0102);
+    assert_eq!(server.fixed.second.handle, 0x0111);
+}
+
+#[gatt_server(service_changed)]
+struct ServiceChangedServer {
+    bas: IncludedBatteryService,
+}
+
+#[test]
+fn gatt_server_service_changed() {
+    // Without the argument, the GATT service is empty and the first service follows it.
+    let mut storage = FixedHandleServerStorage::new();
+    let server = FixedHandleServer::new_default("fixed", &mut storage).unwrap();
+    assert!(server
+        .table()
+        .find_characteristic_by_value_handle::<[u8; 4]>(8)
+        .is_err());
+
+    let mut storage = ServiceChangedServerStorage::new();
+    let server = ServiceChangedServer::new_default("changed", &mut storage).unwrap();
+    let service_changed = server
+        .table()
+        .find_characteristic_by_value_handle::<[u8; 4]>(8)
+        .unwrap();
+    assert_eq!(service_changed.cccd_handle, Some(9));
+    assert_eq!(server.bas.handle(), 10);
+}